@@ -6,9 +6,7 @@
 //!
 //! Run with: `cargo run --example benchmark --release`
 
-use dataflow_rs::engine::utils::set_nested_value;
 use dataflow_rs::{Engine, Message, Workflow};
-use datavalue::OwnedDataValue;
 use futures::future::join_all;
 use serde_json::json;
 use std::sync::Arc;
@@ -207,12 +205,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let handle = tokio::spawn(async move {
             let msg_start = Instant::now();
 
-            let mut message = Message::from_value(&data);
-            set_nested_value(
-                &mut message.context,
-                "metadata",
-                OwnedDataValue::from(&json!({ "iteration": i })),
-            );
+            let mut message = Message::builder()
+                .payload_json(&data)
+                .metadata_json(&json!({ "iteration": i }))
+                .build();
 
             engine.process_message(&mut message).await.unwrap();
 
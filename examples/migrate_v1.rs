@@ -0,0 +1,77 @@
+//! # v1 Migration Tool
+//!
+//! Upgrades a directory of v1 workflow files to the current schema with
+//! `Workflow::migrate_from_v1`, printing every change:
+//!
+//! ```text
+//! cargo run --example migrate_v1 -- rules/           # report only
+//! cargo run --example migrate_v1 -- rules/ --write   # rewrite the files
+//! ```
+//!
+//! Files that need no change are left untouched. Changes marked `REVIEW`
+//! must be finished by hand. Exits with status 1 when a file fails to
+//! migrate.
+
+use dataflow_rs::Workflow;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+fn json_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            json_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let write = args.iter().any(|arg| arg == "--write");
+    let Some(dir) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        eprintln!("usage: migrate_v1 <dir> [--write]");
+        process::exit(2);
+    };
+
+    let mut files = Vec::new();
+    json_files(Path::new(dir), &mut files)?;
+    files.sort();
+
+    let mut failed = 0;
+    for path in &files {
+        let json = fs::read_to_string(path)?;
+        let report = match Workflow::migrate_from_v1(&json) {
+            Ok((_, report)) => report,
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                failed += 1;
+                continue;
+            }
+        };
+        if report.is_unchanged() {
+            continue;
+        }
+        println!("{}", path.display());
+        for change in &report.changes {
+            let mark = if change.needs_review {
+                "REVIEW"
+            } else {
+                "  done"
+            };
+            println!("  {mark} {} {}", change.json_pointer, change.message);
+        }
+        if write {
+            let migrated = serde_json::to_string_pretty(&report.document)?;
+            fs::write(path, migrated + "\n")?;
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("{failed} file(s) failed to migrate");
+        process::exit(1);
+    }
+    Ok(())
+}
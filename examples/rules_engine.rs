@@ -10,19 +10,17 @@
 //! Run with: `cargo run --example rules_engine`
 
 use dataflow_rs::engine::message::Message;
-use dataflow_rs::engine::utils::set_nested_value;
 use dataflow_rs::{Rule, RulesEngine};
-use datavalue::OwnedDataValue;
 use serde_json::json;
-use std::sync::Arc;
 
 /// Helper to create a message with data already in the data context.
 /// In production, you'd typically use a `parse_json` task as the first action
 /// to move payload into the data context.
-fn message_with_data(data: serde_json::Value) -> Message {
-    let mut message = Message::new(Arc::new(OwnedDataValue::from(&json!({}))));
-    set_nested_value(&mut message.context, "data", OwnedDataValue::from(&data));
-    message
+fn message_with_data(data: serde_json::Value) -> dataflow_rs::Result<Message> {
+    Message::builder()
+        .payload_json(&json!({}))
+        .data_json(&data)
+        .try_build()
 }
 
 #[tokio::main]
@@ -192,7 +190,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "name": "Alice",
             "is_vip": true
         }
-    }));
+    }))?;
 
     engine.process_message(&mut message).await?;
 
@@ -219,7 +217,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "name": "Bob",
             "is_vip": false
         }
-    }));
+    }))?;
 
     engine.process_message(&mut message).await?;
 
@@ -244,7 +242,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             "name": "Charlie",
             "is_vip": false
         }
-    }));
+    }))?;
 
     engine.process_message(&mut message).await?;
 
@@ -0,0 +1,190 @@
+//! # Admin API
+//!
+//! A ready-made [axum](https://docs.rs/axum) router for operating an
+//! embedded engine over HTTP. Requires the `admin-api` feature. Nest it
+//! under a prefix of the service's own router, behind whatever
+//! authentication the service uses:
+//!
+//! ```no_run
+//! # use dataflow_rs::{Engine, SharedEngine};
+//! # fn demo(engine: Engine) {
+//! let shared = SharedEngine::new(engine);
+//! let app: axum::Router =
+//!     axum::Router::new().nest("/admin", dataflow_rs::admin::router(shared.clone()));
+//! // Process messages with `shared.current()`.
+//! # }
+//! ```
+//!
+//! | Endpoint | Description |
+//! |----------|-------------|
+//! | `GET /health` | `200` with `{"status": "ok", "in_flight": n}`, or `503` with `"draining"` |
+//! | `GET /workflows` | Every workflow, disabled ones included, with `enabled` |
+//! | `GET /workflows/{id}` | The workflow's [description](crate::WorkflowDescription) |
+//! | `POST /workflows/{id}/disable` | Take the workflow out of the engine |
+//! | `POST /workflows/{id}/enable` | Put a disabled workflow back |
+//! | `GET /lint` | The engine's [lint warnings](crate::LintWarning) |
+//! | `GET /metrics` | [`EngineStats`](crate::EngineStats); `404` without in-memory metrics |
+//! | `POST /dry-run` | [Dry-run](crate::Engine::dry_run) `{"payload", "data", "metadata"}` and return the [`DryRunReport`](crate::DryRunReport) |
+//!
+//! Enabling and disabling go through [`SharedEngine`], so the change
+//! applies to messages started afterwards. Failures answer with
+//! `{"error": "..."}`: `404` for an unknown workflow, `422` when the
+//! rebuilt engine doesn't compile or a dry-run message is malformed.
+//!
+//! The dry run goes through [`Engine::dry_run`](crate::Engine::dry_run):
+//! a fresh message is processed and returned rather than stored, and
+//! tasks that may have side effects (`http_call`, `publish_kafka`, custom
+//! handlers, …) are held back and listed instead of run.
+
+use crate::engine::message::Message;
+use crate::engine::shared::SharedEngine;
+use crate::engine::workflow::Workflow;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+type Reply = (StatusCode, Json<Value>);
+
+/// Router serving the admin endpoints for `engine`.
+pub fn router(engine: SharedEngine) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/workflows", get(list_workflows))
+        .route("/workflows/{id}", get(get_workflow))
+        .route("/workflows/{id}/disable", post(disable_workflow))
+        .route("/workflows/{id}/enable", post(enable_workflow))
+        .route("/lint", get(lint))
+        .route("/metrics", get(metrics))
+        .route("/dry-run", post(dry_run))
+        .with_state(engine)
+}
+
+async fn health(State(shared): State<SharedEngine>) -> Reply {
+    let engine = shared.current();
+    if engine.is_draining() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"status": "draining", "in_flight": engine.in_flight()})),
+        );
+    }
+    ok(json!({"status": "ok", "in_flight": engine.in_flight()}))
+}
+
+async fn list_workflows(State(shared): State<SharedEngine>) -> Reply {
+    let engine = shared.current();
+    let enabled = engine.workflows().iter().map(|w| summary(w, true));
+    let disabled = shared
+        .disabled_ids()
+        .into_iter()
+        .filter_map(|id| shared.disabled_workflow(&id))
+        .map(|w| summary(&w, false))
+        .collect::<Vec<_>>();
+    ok(Value::Array(enabled.chain(disabled).collect()))
+}
+
+async fn get_workflow(State(shared): State<SharedEngine>, Path(id): Path<String>) -> Reply {
+    let engine = shared.current();
+    let (workflow, enabled) = match engine.workflow_by_id(&id) {
+        Some(workflow) => (workflow.clone(), true),
+        None => match shared.disabled_workflow(&id) {
+            Some(workflow) => (workflow, false),
+            None => return error(StatusCode::NOT_FOUND, format!("No workflow '{id}'")),
+        },
+    };
+    ok(json!({"enabled": enabled, "description": workflow.describe()}))
+}
+
+async fn disable_workflow(State(shared): State<SharedEngine>, Path(id): Path<String>) -> Reply {
+    if shared.current().workflow_by_id(&id).is_none() {
+        return error(StatusCode::NOT_FOUND, format!("No enabled workflow '{id}'"));
+    }
+    match shared.disable(&id) {
+        Ok(()) => ok(json!({"id": id, "enabled": false})),
+        Err(e) => error(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+    }
+}
+
+async fn enable_workflow(State(shared): State<SharedEngine>, Path(id): Path<String>) -> Reply {
+    if shared.disabled_workflow(&id).is_none() {
+        return error(
+            StatusCode::NOT_FOUND,
+            format!("No disabled workflow '{id}'"),
+        );
+    }
+    match shared.enable(&id) {
+        Ok(()) => ok(json!({"id": id, "enabled": true})),
+        Err(e) => error(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+    }
+}
+
+async fn lint(State(shared): State<SharedEngine>) -> Reply {
+    ok(json!(shared.current().lint_warnings()))
+}
+
+async fn metrics(State(shared): State<SharedEngine>) -> Reply {
+    match shared.current().stats() {
+        Some(stats) => ok(json!(stats)),
+        None => error(
+            StatusCode::NOT_FOUND,
+            "The engine has no in-memory metrics".to_string(),
+        ),
+    }
+}
+
+/// Body of `POST /dry-run`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DryRunRequest {
+    #[serde(default)]
+    payload: Option<Value>,
+    #[serde(default)]
+    data: Option<Value>,
+    #[serde(default)]
+    metadata: Option<Value>,
+}
+
+async fn dry_run(State(shared): State<SharedEngine>, Json(request): Json<DryRunRequest>) -> Reply {
+    let engine = shared.current();
+    let mut builder = engine
+        .message()
+        .payload_json(&request.payload.unwrap_or_else(|| json!({})));
+    if let Some(data) = &request.data {
+        builder = builder.data_json(data);
+    }
+    if let Some(metadata) = &request.metadata {
+        builder = builder.metadata_json(metadata);
+    }
+    let message: Message = match builder.try_build() {
+        Ok(message) => message,
+        Err(e) => return error(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+    };
+    let report = engine.dry_run(&message).await;
+    match serde_json::to_value(&report) {
+        Ok(body) => ok(body),
+        Err(e) => error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+    }
+}
+
+fn summary(workflow: &Workflow, enabled: bool) -> Value {
+    json!({
+        "id": workflow.id,
+        "name": workflow.name,
+        "priority": workflow.priority,
+        "channel": workflow.channel,
+        "version": workflow.version,
+        "status": workflow.status,
+        "tasks": workflow.tasks.len(),
+        "enabled": enabled,
+    })
+}
+
+fn ok(body: Value) -> Reply {
+    (StatusCode::OK, Json(body))
+}
+
+fn error(status: StatusCode, message: String) -> Reply {
+    (status, Json(json!({"error": message})))
+}
@@ -0,0 +1,391 @@
+//! # Workflow DSL
+//!
+//! Typed builders for declaring workflows in Rust code — in tests, or in
+//! services that embed a few fixed workflows — instead of JSON inside
+//! string literals. They produce the same [`Workflow`] and [`Task`] values
+//! as [`Workflow::from_json`]:
+//!
+//! ```
+//! use dataflow_rs::Workflow;
+//! use dataflow_rs::dsl::{function, map, validate};
+//! use serde_json::json;
+//!
+//! let workflow = Workflow::builder("orders", "Orders")
+//!     .priority(10)
+//!     .condition(json!({"==": [{"var": "metadata.type"}, "order"]}))
+//!     .task(
+//!         map("normalize", "Normalize")
+//!             .set("data.total", json!({"*": [{"var": "data.qty"}, {"var": "data.price"}]}))
+//!             .set("data.currency", json!("EUR")),
+//!     )
+//!     .task(
+//!         validate("check", "Check")
+//!             .required(["data.id", "data.total"])
+//!             .rule(json!({">": [{"var": "data.total"}, 0]}), "Total must be positive"),
+//!     )
+//!     .task(
+//!         function("notify", "Notify", "notify", json!({"channel": "orders"}))
+//!             .condition(json!({">": [{"var": "data.total"}, 1000]}))
+//!             .continue_on_error(true),
+//!     )
+//!     .build()?;
+//! assert_eq!(workflow.tasks.len(), 3);
+//! # Ok::<(), dataflow_rs::DataflowError>(())
+//! ```
+//!
+//! The shape of a workflow is checked by the compiler: a mapping can only
+//! be added to a `map` task, a rule only to a `validate` task. Function
+//! inputs are parsed, and the workflow checked as by
+//! [`Workflow::validate`], when [`WorkflowBuilder::build`] runs; JSONLogic
+//! is compiled when the engine is built, as for workflows loaded from
+//! JSON. Any other function, built-in or custom, is declared with
+//! [`function`] and its JSON input.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::functions::FunctionConfig;
+use crate::engine::task::Task;
+use crate::engine::workflow::Workflow;
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+/// Anything [`WorkflowBuilder::task`] accepts: the DSL's task builders and
+/// ready-made [`Task`]s.
+pub trait IntoTask {
+    /// Build the task, parsing its function input.
+    fn into_task(self) -> Result<Task>;
+}
+
+impl IntoTask for Task {
+    fn into_task(self) -> Result<Task> {
+        Ok(self)
+    }
+}
+
+/// Fields every task has.
+#[derive(Debug, Clone)]
+struct TaskHeader {
+    id: String,
+    name: String,
+    description: Option<String>,
+    condition: Option<Value>,
+    continue_on_error: bool,
+}
+
+impl TaskHeader {
+    fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            description: None,
+            condition: None,
+            continue_on_error: false,
+        }
+    }
+
+    fn into_task(self, function: &str, input: Value) -> Result<Task> {
+        let config: FunctionConfig =
+            serde_json::from_value(json!({"name": function, "input": input}))
+                .map_err(|e| DataflowError::Validation(format!("Task {}: {}", self.id, e)))?;
+        let mut task = Task::action(&self.id, &self.name, config);
+        task.description = self.description;
+        if let Some(condition) = self.condition {
+            task.condition = condition;
+        }
+        task.continue_on_error = self.continue_on_error;
+        Ok(task)
+    }
+}
+
+/// Setters shared by every task builder.
+macro_rules! task_setters {
+    () => {
+        /// Human-readable description.
+        pub fn description(mut self, description: &str) -> Self {
+            self.header.description = Some(description.to_string());
+            self
+        }
+
+        /// JSONLogic the task runs under. Default: always.
+        pub fn condition(mut self, condition: Value) -> Self {
+            self.header.condition = Some(condition);
+            self
+        }
+
+        /// Record the task's errors and carry on with the workflow.
+        pub fn continue_on_error(mut self, on: bool) -> Self {
+            self.header.continue_on_error = on;
+            self
+        }
+    };
+}
+
+/// A `map` task. See [`map`].
+#[derive(Debug, Clone)]
+pub struct MapTask {
+    header: TaskHeader,
+    mappings: Vec<Value>,
+}
+
+/// Start a `map` task; add mappings with [`MapTask::set`].
+pub fn map(id: &str, name: &str) -> MapTask {
+    MapTask {
+        header: TaskHeader::new(id, name),
+        mappings: Vec::new(),
+    }
+}
+
+impl MapTask {
+    task_setters!();
+
+    /// Write the result of `logic` to `path`. Mappings run in the order
+    /// they are added.
+    pub fn set(mut self, path: &str, logic: Value) -> Self {
+        self.mappings.push(json!({"path": path, "logic": logic}));
+        self
+    }
+}
+
+impl IntoTask for MapTask {
+    fn into_task(self) -> Result<Task> {
+        self.header
+            .into_task("map", json!({"mappings": self.mappings}))
+    }
+}
+
+/// A `validation` task. See [`validate`].
+#[derive(Debug, Clone)]
+pub struct ValidateTask {
+    header: TaskHeader,
+    rules: Vec<Value>,
+    rules_ref: Vec<String>,
+}
+
+/// Start a `validation` task; add rules with [`ValidateTask::rule`] and
+/// [`ValidateTask::required`].
+pub fn validate(id: &str, name: &str) -> ValidateTask {
+    ValidateTask {
+        header: TaskHeader::new(id, name),
+        rules: Vec::new(),
+        rules_ref: Vec::new(),
+    }
+}
+
+impl ValidateTask {
+    task_setters!();
+
+    /// Fail with `message` unless `logic` is `true`.
+    pub fn rule(mut self, logic: Value, message: &str) -> Self {
+        self.rules.push(json!({"logic": logic, "message": message}));
+        self
+    }
+
+    /// Fail unless every path in `paths` is present and non-empty.
+    pub fn required<I, S>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let paths: Vec<String> = paths.into_iter().map(Into::into).collect();
+        self.rules.push(json!({"type": "required", "paths": paths}));
+        self
+    }
+
+    /// Also check the rules of the registered rule pack `name`.
+    pub fn rules_ref(mut self, name: &str) -> Self {
+        self.rules_ref.push(name.to_string());
+        self
+    }
+}
+
+impl IntoTask for ValidateTask {
+    fn into_task(self) -> Result<Task> {
+        let mut input = json!({"rules": self.rules});
+        if !self.rules_ref.is_empty() {
+            input["rules_ref"] = json!(self.rules_ref);
+        }
+        self.header.into_task("validation", input)
+    }
+}
+
+/// A task running any function with a JSON input. See [`function`].
+#[derive(Debug, Clone)]
+pub struct FunctionTask {
+    header: TaskHeader,
+    function: String,
+    input: Value,
+    output: Option<String>,
+}
+
+/// A task running the built-in or custom function `function` with `input`,
+/// as `{"name": function, "input": input}` would in JSON.
+pub fn function(id: &str, name: &str, function: &str, input: Value) -> FunctionTask {
+    FunctionTask {
+        header: TaskHeader::new(id, name),
+        function: function.to_string(),
+        input,
+        output: None,
+    }
+}
+
+impl FunctionTask {
+    task_setters!();
+
+    /// Path the handler's output is written to.
+    pub fn output(mut self, path: &str) -> Self {
+        self.output = Some(path.to_string());
+        self
+    }
+}
+
+impl IntoTask for FunctionTask {
+    fn into_task(self) -> Result<Task> {
+        let mut task = self.header.into_task(&self.function, self.input)?;
+        task.output = self.output;
+        Ok(task)
+    }
+}
+
+/// Builds a [`Workflow`]. Created by [`Workflow::builder`].
+#[must_use = "WorkflowBuilder must be `.build()` to produce a Workflow"]
+pub struct WorkflowBuilder {
+    workflow: Workflow,
+    tasks: Vec<Result<Task>>,
+}
+
+impl Workflow {
+    /// Declare a workflow in code. See the [`dsl`](crate::dsl) module.
+    pub fn builder(id: &str, name: &str) -> WorkflowBuilder {
+        let mut workflow = Workflow::new();
+        workflow.id = id.to_string();
+        workflow.id_arc = Arc::from(id);
+        workflow.name = name.to_string();
+        WorkflowBuilder {
+            workflow,
+            tasks: Vec::new(),
+        }
+    }
+}
+
+impl WorkflowBuilder {
+    /// Set the priority; lower runs first. Default: 0.
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.workflow.priority = priority;
+        self
+    }
+
+    /// Human-readable description.
+    pub fn description(mut self, description: &str) -> Self {
+        self.workflow.description = Some(description.to_string());
+        self
+    }
+
+    /// JSONLogic the workflow runs under. Default: always.
+    pub fn condition(mut self, condition: Value) -> Self {
+        self.workflow.condition = condition;
+        self
+    }
+
+    /// Channel the workflow is routed on. Default: `default`.
+    pub fn channel(mut self, channel: &str) -> Self {
+        self.workflow.channel = channel.to_string();
+        self
+    }
+
+    /// Workflows that must run before this one.
+    pub fn depends_on<I, S>(mut self, ids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.workflow
+            .depends_on
+            .extend(ids.into_iter().map(Into::into));
+        self
+    }
+
+    /// Record task errors and carry on with later tasks.
+    pub fn continue_on_error(mut self, on: bool) -> Self {
+        self.workflow.continue_on_error = on;
+        self
+    }
+
+    /// Append a task.
+    pub fn task(mut self, task: impl IntoTask) -> Self {
+        self.tasks.push(task.into_task());
+        self
+    }
+
+    /// Append several tasks of one kind, e.g. a `Vec<Task>`.
+    pub fn tasks<I>(mut self, tasks: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: IntoTask,
+    {
+        self.tasks
+            .extend(tasks.into_iter().map(IntoTask::into_task));
+        self
+    }
+
+    /// The workflow. Fails on the first task whose function input doesn't
+    /// parse, or when [`Workflow::validate`] rejects the workflow.
+    pub fn build(self) -> Result<Workflow> {
+        let mut workflow = self.workflow;
+        workflow.tasks = self.tasks.into_iter().collect::<Result<_>>()?;
+        workflow.validate()?;
+        Ok(workflow)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_the_same_workflow_as_json() {
+        let built = Workflow::builder("w", "W")
+            .priority(3)
+            .task(map("m", "M").set("data.x", json!(1)))
+            .task(
+                validate("v", "V")
+                    .required(["data.x"])
+                    .condition(json!({"var": "data.check"})),
+            )
+            .build()
+            .unwrap();
+        let parsed = Workflow::from_json(
+            &json!({"id": "w", "name": "W", "priority": 3, "tasks": [
+                {"id": "m", "name": "M", "function": {"name": "map", "input": {
+                    "mappings": [{"path": "data.x", "logic": 1}]
+                }}},
+                {"id": "v", "name": "V", "condition": {"var": "data.check"},
+                 "function": {"name": "validation", "input": {
+                    "rules": [{"type": "required", "paths": ["data.x"]}]
+                }}}
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            built.to_normalized_json().unwrap(),
+            parsed.to_normalized_json().unwrap()
+        );
+    }
+
+    #[test]
+    fn build_reports_bad_inputs_and_invalid_workflows() {
+        let err = Workflow::builder("w", "W")
+            .task(function("s", "S", "sample", json!({"rate": "often"})))
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("Task s"), "{err}");
+
+        assert!(Workflow::builder("w", "W").build().is_err());
+        assert!(
+            Workflow::builder("w", "W")
+                .task(map("m", "M"))
+                .task(map("m", "M"))
+                .build()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! # Alerts
+//!
+//! Operational alerts raised from within workflows. An `alert` task whose
+//! condition passes builds an [`Alert`] — severity, code, rendered message
+//! and selected fields — and hands it to the [`AlertSink`] configured with
+//! [`EngineBuilder::with_alert_sink`](crate::EngineBuilder::with_alert_sink):
+//!
+//! ```json
+//! {"id": "high_risk", "name": "High risk",
+//!  "condition": {">=": [{"var": "data.risk.score"}, 80]},
+//!  "function": {"name": "alert", "input": {
+//!      "severity": "critical",
+//!      "code": "HIGH_RISK",
+//!      "message": {"cat": ["Risk score ", {"var": "data.risk.score"}]},
+//!      "fields": {"customer": {"var": "data.customer.id"}}
+//!  }}}
+//! ```
+//!
+//! Without a sink, alerts are logged under the `dataflow::alert` target
+//! instead: `info` for [`AlertSeverity::Info`], `warn` for
+//! [`AlertSeverity::Warning`] and `error` for [`AlertSeverity::Critical`].
+//! If the sink returns `Err`, the task fails like any other task error.
+
+use crate::engine::error::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use datavalue::OwnedDataValue;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// How urgent an alert is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertSeverity {
+    Info,
+    #[default]
+    Warning,
+    Critical,
+}
+
+/// One alert raised by an `alert` task.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alert {
+    pub severity: AlertSeverity,
+    /// Stable identifier of the alert, e.g. `HIGH_RISK`.
+    pub code: String,
+    /// The task's `message` expression, rendered as text.
+    pub message: String,
+    /// The task's `fields` expressions, evaluated.
+    pub fields: BTreeMap<String, OwnedDataValue>,
+    pub message_id: String,
+    pub workflow_id: String,
+    pub task_id: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Destination for alerts: a pager, a chat webhook, a metrics counter, …
+///
+/// `send` is awaited on the processing path, so slow deliveries should be
+/// queued rather than performed inline.
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn send(&self, alert: Alert) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: AlertSink + ?Sized> AlertSink for Arc<T> {
+    async fn send(&self, alert: Alert) -> Result<()> {
+        (**self).send(alert).await
+    }
+}
+
+/// `AlertSink` that keeps alerts in memory. Handy for tests.
+#[derive(Debug, Default)]
+pub struct InMemoryAlertSink {
+    alerts: Mutex<Vec<Alert>>,
+}
+
+impl InMemoryAlertSink {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of every alert sent so far, in order.
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.alerts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl AlertSink for InMemoryAlertSink {
+    async fn send(&self, alert: Alert) -> Result<()> {
+        self.alerts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(alert);
+        Ok(())
+    }
+}
@@ -0,0 +1,514 @@
+//! # Audit Integrity
+//!
+//! Optional digests that make the audit trail usable as evidence. All
+//! hashes are SHA-256 in lowercase hex.
+//!
+//! ## Input Hashes
+//!
+//! With [`EngineOptions::audit_input_hashes`](crate::EngineOptions::audit_input_hashes)
+//! on, every audit entry records two digests:
+//!
+//! - `input_hash` — the task's function config, as serialized by
+//!   [`FunctionConfig`]'s `Serialize` (typed, so defaults are explicit).
+//!   It changes whenever the configuration that produced the entry changes.
+//! - `data_hash` — the slice of the message context the task read, taken
+//!   just before it ran: an object from each context path its logic reads
+//!   (`var`, `missing`, `missing_some`) to the value found there, or `null`.
+//!   Tasks whose reads can't be derived from their logic (custom handlers,
+//!   parse, publish, aggregate validations, …) hash all of `data` instead.
+//!
+//! JSON is hashed in a canonical form: object keys sorted, no whitespace.
+//! Recomputing a digest from an archived config and input snapshot with
+//! [`sha256_hex`] and [`canonical_json`] proves which configuration and
+//! inputs produced an output, without storing copies of either.
+//!
+//! ## Chaining and Signing
+//!
+//! With [`EngineOptions::audit_chain`](crate::EngineOptions::audit_chain)
+//! on, every entry records in `hash` the digest of the previous entry's
+//! `hash` followed by the entry's own canonical JSON (without `hash`). The
+//! first entry chains from the message's [`chain_seed`], a digest of its id
+//! and payload, so a trail can't be moved onto another message. Editing,
+//! reordering or removing an entry breaks every later link, which
+//! [`verify_chain`] detects.
+//!
+//! With [`EngineOptions::audit_signing_key`](crate::EngineOptions::audit_signing_key)
+//! set (it implies chaining), the engine also signs the chain when
+//! processing finishes: an HMAC-SHA256 of the message id and the last
+//! entry's `hash` (the chain seed when there are no entries), keyed by
+//! the named secret from the engine's
+//! [`SecretsResolver`](crate::SecretsResolver), stored as
+//! [`Message::audit_signature`]. Only the key holder can produce a valid
+//! signature, so truncating the trail or rebuilding the whole chain is
+//! detected too. [`Engine::verify_audit`](crate::Engine::verify_audit)
+//! checks both.
+//!
+//! ## Replay
+//!
+//! [`Message::replay_audit`] checks that the recorded changes account for
+//! the message's `data`: starting from the message as it arrived, it
+//! applies every change under `data` in order, checking that each finds
+//! its `old_value` in place (an absent value reads as `null`), then
+//! compares the result with the final `data`. The payload must be
+//! unchanged too. `metadata` and `temp_data` carry engine bookkeeping and
+//! scratch values that are not all recorded, so they are not checked, and
+//! a message processed without change capture only replays when its
+//! `data` never changed. The first divergence is returned as an
+//! [`AuditMismatch`].
+//!
+//! ## Reprocessing
+//!
+//! [`Engine::reprocess_from_audit`](crate::Engine::reprocess_from_audit)
+//! uses the same changes to rebuild a message as it stood just before one
+//! of its tasks ran, then runs it again from that task with the current
+//! engine — after fixing a faulty mapping, say, without repeating the
+//! side effects of the tasks before it. The changes of the earlier entries
+//! are applied to the message as it arrived, under `data` checked as in
+//! a replay and elsewhere as recorded, and those entries become the
+//! rebuilt message's audit trail. Reconstruction is only as complete as
+//! the trail: it needs change capture, and values a task wrote without
+//! recording a change (such as engine bookkeeping in `metadata`) are
+//! missing.
+//!
+//! ## Redelivery
+//!
+//! With [`EngineOptions::skip_audited_tasks`](crate::EngineOptions::skip_audited_tasks)
+//! on, a task is skipped when the message's audit trail already records it
+//! completing: an entry for its workflow and task id with a status below
+//! 500. A message redelivered after a crash, carrying the trail of its
+//! first attempt, then runs only the tasks that didn't complete, so a
+//! `publish_kafka` or `http_call` that went through isn't repeated. Tasks
+//! that failed (status 500) run again. A skipped task is treated like one
+//! whose condition is false: nothing is recorded and its condition is not
+//! evaluated. The message must carry the context the completed tasks left
+//! behind, as the trail alone doesn't restore it.
+
+use crate::engine::functions::FunctionConfig;
+use crate::engine::lint::logic_sites;
+use crate::engine::logic_text::var_paths;
+use crate::engine::message::{AuditTrail, Message};
+use crate::engine::task::Task;
+use crate::engine::utils::{get_nested_value, set_nested_value};
+use crate::engine::workflow::Workflow;
+use datavalue::OwnedDataValue;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::sync::Arc;
+
+/// Context path hashed for tasks whose reads are unknown.
+const FALLBACK_PATH: &str = "data";
+
+/// Hashing inputs of one task, computed once at engine construction.
+#[derive(Debug)]
+pub struct AuditInputs {
+    config_hash: Arc<str>,
+    read_paths: Vec<String>,
+}
+
+/// The two digests recorded on an audit entry.
+#[derive(Debug, Clone)]
+pub(crate) struct AuditHashes {
+    pub(crate) input: Arc<str>,
+    pub(crate) data: String,
+}
+
+impl AuditInputs {
+    /// Hash the task's config and collect the paths it reads.
+    pub(crate) fn for_task(workflow: &Workflow, task: &Task) -> Self {
+        let config = serde_json::to_value(&task.function).unwrap_or(Value::Null);
+        Self {
+            config_hash: Arc::from(sha256_hex(canonical_json(&config).as_bytes())),
+            read_paths: read_paths(workflow, task),
+        }
+    }
+
+    /// Digests for a run of the task against `context`.
+    pub(crate) fn hashes(&self, context: &OwnedDataValue) -> AuditHashes {
+        let slice: serde_json::Map<String, Value> = self
+            .read_paths
+            .iter()
+            .map(|path| {
+                let value = get_nested_value(context, path)
+                    .and_then(|value| serde_json::to_value(value).ok())
+                    .unwrap_or(Value::Null);
+                (path.clone(), value)
+            })
+            .collect();
+        AuditHashes {
+            input: Arc::clone(&self.config_hash),
+            data: sha256_hex(canonical_json(&Value::Object(slice)).as_bytes()),
+        }
+    }
+}
+
+/// Context paths `task` reads, or just `data` when they can't be derived.
+fn read_paths(workflow: &Workflow, task: &Task) -> Vec<String> {
+    let derivable = match &task.function {
+        FunctionConfig::Validation { input, .. } => input.aggregates.is_empty(),
+        FunctionConfig::Map { .. }
+        | FunctionConfig::Filter { .. }
+        | FunctionConfig::Switch { .. }
+        | FunctionConfig::Log { .. }
+        | FunctionConfig::HttpCall { .. }
+        | FunctionConfig::Enrich { .. }
+        | FunctionConfig::PublishKafka { .. } => true,
+        _ => false,
+    };
+    let mut paths = BTreeSet::new();
+    if derivable {
+        for site in logic_sites(workflow) {
+            if site.task_id == Some(task.id.as_str()) {
+                var_paths(site.logic, &mut paths);
+            }
+        }
+    } else {
+        paths.insert(FALLBACK_PATH.to_string());
+    }
+    paths.into_iter().collect()
+}
+
+/// Where `message`'s chain starts: the digest of its id, a newline and
+/// the digest of its canonical payload.
+pub fn chain_seed(message: &Message) -> String {
+    let payload = sha256_hex(canonical_json(&Value::from(message.payload())).as_bytes());
+    sha256_hex(format!("{}\n{}", message.id(), payload).as_bytes())
+}
+
+/// The chained `hash` of `entry` following an entry whose hash is
+/// `previous` (the [`chain_seed`] for the first entry).
+pub fn entry_hash(previous: &str, entry: &AuditTrail) -> String {
+    let mut value = serde_json::to_value(entry).unwrap_or(Value::Null);
+    if let Value::Object(map) = &mut value {
+        map.remove("hash");
+    }
+    let mut input = previous.to_string();
+    input.push_str(&canonical_json(&value));
+    sha256_hex(input.as_bytes())
+}
+
+/// Append `entry` to `message`'s trail, linking it to the last entry.
+pub(crate) fn push_chained(message: &mut Message, mut entry: AuditTrail) {
+    let hash = match message.audit_trail.last() {
+        Some(last) => entry_hash(last.hash.as_deref().unwrap_or_default(), &entry),
+        None => entry_hash(&chain_seed(message), &entry),
+    };
+    entry.hash = Some(hash);
+    message.audit_trail.push(entry);
+}
+
+/// Whether `trail` records task `task_id` of workflow `workflow_id`
+/// completing, i.e. with a status below 500.
+pub(crate) fn completed(trail: &[AuditTrail], workflow_id: &str, task_id: &str) -> bool {
+    trail.iter().any(|entry| {
+        entry.status < 500 && &*entry.workflow_id == workflow_id && &*entry.task_id == task_id
+    })
+}
+
+/// Whether every entry of `message`'s trail carries a `hash` and each
+/// links to the one before, the first to the message's [`chain_seed`].
+pub fn verify_chain(message: &Message) -> bool {
+    let mut previous = chain_seed(message);
+    for entry in &message.audit_trail {
+        match &entry.hash {
+            Some(hash) if *hash == entry_hash(&previous, entry) => previous.clone_from(hash),
+            _ => return false,
+        }
+    }
+    true
+}
+
+fn mac(key: &[u8], message: &Message) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    let head = match message.audit_trail.last() {
+        Some(last) => last.hash.clone().unwrap_or_default(),
+        None => chain_seed(message),
+    };
+    mac.update(message.id().as_bytes());
+    mac.update(b"\n");
+    mac.update(head.as_bytes());
+    mac
+}
+
+/// The signature of `message`'s chained trail under `key`.
+pub fn sign(key: &[u8], message: &Message) -> String {
+    to_hex(&mac(key, message).finalize().into_bytes())
+}
+
+/// Whether `message`'s trail is intact and its signature was made with
+/// `key`. The signature comparison is constant-time.
+pub fn verify_signature(key: &[u8], message: &Message) -> bool {
+    let Some(signature) = message.audit_signature().and_then(from_hex) else {
+        return false;
+    };
+    verify_chain(message) && mac(key, message).verify_slice(&signature).is_ok()
+}
+
+/// Where replaying a message's audit trail diverged from the message.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditMismatch {
+    /// Index of the audit entry whose change did not find its `old_value`;
+    /// `None` when the replayed result differs from the final message.
+    pub entry: Option<usize>,
+    /// Context path of the divergence, or `payload`.
+    pub path: String,
+    /// Value the audit trail implies.
+    pub expected: OwnedDataValue,
+    /// Value actually found.
+    pub actual: OwnedDataValue,
+}
+
+impl fmt::Display for AuditMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expected = serde_json::to_string(&self.expected).unwrap_or_default();
+        let actual = serde_json::to_string(&self.actual).unwrap_or_default();
+        match self.entry {
+            Some(entry) => write!(
+                f,
+                "audit entry {} expects {} at {}, found {}",
+                entry, expected, self.path, actual
+            ),
+            None => write!(
+                f,
+                "replayed audit trail gives {} at {}, message has {}",
+                expected, self.path, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AuditMismatch {}
+
+/// Replay `message`'s changes to `data` over `original`. See
+/// [Replay](self#replay).
+pub(crate) fn replay(original: &Message, message: &Message) -> Result<(), AuditMismatch> {
+    if original.payload() != message.payload() {
+        return Err(AuditMismatch {
+            entry: None,
+            path: "payload".to_string(),
+            expected: original.payload().clone(),
+            actual: message.payload().clone(),
+        });
+    }
+    let context = apply_entries(original, message.audit_trail(), false)?;
+    match first_difference("data".to_string(), &context["data"], message.data()) {
+        Some((path, expected, actual)) => Err(AuditMismatch {
+            entry: None,
+            path,
+            expected: expected.clone(),
+            actual: actual.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// `original` as it stood just before the task of `entries[upto]` ran:
+/// the changes of the entries before it applied, and those entries as its
+/// audit trail. See [Reprocessing](self#reprocessing).
+pub(crate) fn rewind(
+    original: &Message,
+    entries: &[AuditTrail],
+    upto: usize,
+) -> Result<Message, AuditMismatch> {
+    let entries = &entries[..upto];
+    let mut message = original.clone();
+    message.context = apply_entries(original, entries, true)?;
+    message.audit_trail = entries.to_vec();
+    Ok(message)
+}
+
+/// `original`'s context with the changes of `entries` applied in order,
+/// checking that each change to `data` finds its `old_value`. Changes
+/// outside `data` are applied, unchecked, only with `all_sections`.
+fn apply_entries(
+    original: &Message,
+    entries: &[AuditTrail],
+    all_sections: bool,
+) -> Result<OwnedDataValue, AuditMismatch> {
+    let mut context = original.context.clone();
+    for (idx, entry) in entries.iter().enumerate() {
+        for change in &entry.changes {
+            let path = &*change.path;
+            if path != "data" && !path.starts_with("data.") {
+                if all_sections {
+                    apply_change(&mut context, path, change.new_value.clone());
+                }
+                continue;
+            }
+            let current = get_nested_value(&context, path).unwrap_or(&OwnedDataValue::Null);
+            if *current != change.old_value {
+                return Err(AuditMismatch {
+                    entry: Some(idx),
+                    path: path.to_string(),
+                    expected: change.old_value.clone(),
+                    actual: current.clone(),
+                });
+            }
+            apply_change(&mut context, path, change.new_value.clone());
+        }
+    }
+    Ok(context)
+}
+
+/// Write `value` at `path` as the engine did: a write to `data` itself
+/// merges an object into the existing one, like `map` does.
+fn apply_change(context: &mut OwnedDataValue, path: &str, value: OwnedDataValue) {
+    if path == "data"
+        && let OwnedDataValue::Object(root) = context
+        && let Some((_, OwnedDataValue::Object(existing))) =
+            root.iter_mut().find(|(k, _)| k == "data")
+        && let OwnedDataValue::Object(pairs) = value
+    {
+        for (key, item) in pairs {
+            match existing.iter_mut().find(|(k, _)| *k == key) {
+                Some(slot) => slot.1 = item,
+                None => existing.push((key, item)),
+            }
+        }
+        return;
+    }
+    set_nested_value(context, path, value);
+}
+
+/// The first path under `path` where `expected` and `actual` differ, with
+/// the values found there. Object keys compare regardless of order.
+fn first_difference<'a>(
+    path: String,
+    expected: &'a OwnedDataValue,
+    actual: &'a OwnedDataValue,
+) -> Option<(String, &'a OwnedDataValue, &'a OwnedDataValue)> {
+    const ABSENT: &OwnedDataValue = &OwnedDataValue::Null;
+    match (expected, actual) {
+        (OwnedDataValue::Object(a), OwnedDataValue::Object(b)) => {
+            let keys = a.iter().map(|(k, _)| k).chain(b.iter().map(|(k, _)| k));
+            keys.into_iter().find_map(|key| {
+                let lookup = |pairs: &'a [(String, OwnedDataValue)]| {
+                    pairs
+                        .iter()
+                        .find(|(k, _)| k == key)
+                        .map_or(ABSENT, |(_, v)| v)
+                };
+                first_difference(format!("{path}.{key}"), lookup(a), lookup(b))
+            })
+        }
+        (OwnedDataValue::Array(a), OwnedDataValue::Array(b)) if a.len() == b.len() => a
+            .iter()
+            .zip(b)
+            .enumerate()
+            .find_map(|(idx, (a, b))| first_difference(format!("{path}.{idx}"), a, b)),
+        _ if expected == actual => None,
+        _ => Some((path, expected, actual)),
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+/// `value` as compact JSON with object keys sorted at every level.
+pub fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (idx, (key, item)) in entries.into_iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workflow(function: Value) -> Workflow {
+        Workflow::from_json(
+            &json!({"id": "wf", "name": "WF", "tasks": [
+                {"id": "t", "name": "T", "condition": {"var": "metadata.kind"}, "function": function}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_at_every_level() {
+        assert_eq!(
+            canonical_json(&json!({"b": [{"z": 1, "a": null}], "a": "x"})),
+            r#"{"a":"x","b":[{"a":null,"z":1}]}"#
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn hashes_read_paths_or_all_data() {
+        let map = workflow(json!({"name": "map", "input": {"mappings": [
+            {"path": "data.total", "logic": {"+": [{"var": "data.a"}, 1]}}
+        ]}}));
+        let inputs = AuditInputs::for_task(&map, &map.tasks[0]);
+        assert_eq!(inputs.read_paths, ["data.a", "metadata.kind"]);
+
+        let context = |a: i64, unrelated: i64| {
+            OwnedDataValue::from(json!({"data": {"a": a, "b": unrelated}, "metadata": {}}))
+        };
+        let first = inputs.hashes(&context(1, 1));
+        assert_eq!(first.data, inputs.hashes(&context(1, 2)).data);
+        assert_ne!(first.data, inputs.hashes(&context(2, 1)).data);
+
+        let custom = workflow(json!({"name": "notify", "input": {"to": "ops"}}));
+        let inputs = AuditInputs::for_task(&custom, &custom.tasks[0]);
+        assert_eq!(inputs.read_paths, ["data"]);
+        assert_ne!(inputs.config_hash, first.input);
+    }
+}
@@ -0,0 +1,91 @@
+//! # Bulkheads
+//!
+//! Caps how many calls of one function handler run at once, so a slow
+//! handler cannot tie up every worker while messages needing other
+//! functions queue behind it. Bulkheads are configured per function name
+//! through [`EngineBuilder::with_bulkhead`](crate::EngineBuilder::with_bulkhead)
+//! or the `bulkheads` map of [`EngineOptions`](crate::EngineOptions), giving
+//! each its maximum concurrent executions:
+//!
+//! ```json
+//! {"bulkheads": {"enrich": 8}}
+//! ```
+//!
+//! A call holds a slot for the whole of `AsyncFunctionHandler::execute`.
+//! When every slot is taken it waits for one to free up; that wait counts
+//! against the task's `timeout_ms`. Slots are shared with engines produced
+//! by `with_new_workflows`.
+
+use crate::engine::error::{DataflowError, Result};
+use log::debug;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// The bulkheads of an engine, keyed by function name. Functions without
+/// one run without limit.
+#[derive(Debug, Default)]
+pub(crate) struct Bulkheads {
+    slots: HashMap<String, Arc<Semaphore>>,
+}
+
+impl Bulkheads {
+    /// Bulkheads allowing `limits[name]` concurrent calls of `name`. Fails
+    /// on a zero limit.
+    pub(crate) fn new(limits: &BTreeMap<String, usize>) -> Result<Self> {
+        let mut slots = HashMap::with_capacity(limits.len());
+        for (name, &limit) in limits {
+            if limit == 0 {
+                return Err(DataflowError::Workflow(format!(
+                    "Bulkhead for '{}' needs at least 1 concurrent execution",
+                    name
+                )));
+            }
+            slots.insert(name.clone(), Arc::new(Semaphore::new(limit)));
+        }
+        Ok(Self { slots })
+    }
+
+    /// Wait for a slot to call `name`; it is released when the returned
+    /// permit drops. `None` when `name` has no bulkhead.
+    pub(crate) async fn enter(&self, name: &str) -> Option<SemaphorePermit<'_>> {
+        let slots = self.slots.get(name)?;
+        if slots.available_permits() == 0 {
+            debug!("Bulkhead for {} is full, waiting for a slot", name);
+        }
+        // The semaphore is never closed.
+        slots.acquire().await.ok()
+    }
+
+    /// Free slots of the bulkhead around `name`, `None` without one.
+    pub(crate) fn available(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).map(|slots| slots.available_permits())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn slots_are_held_until_the_permit_drops() {
+        let bulkheads = Bulkheads::new(&BTreeMap::from([("enrich".to_string(), 2)])).unwrap();
+        let first = bulkheads.enter("enrich").await.unwrap();
+        let _second = bulkheads.enter("enrich").await.unwrap();
+        assert_eq!(bulkheads.available("enrich"), Some(0));
+
+        let mut third = Box::pin(bulkheads.enter("enrich"));
+        assert!(futures::poll!(&mut third).is_pending());
+        drop(first);
+        assert!(third.await.is_some());
+
+        assert!(bulkheads.enter("other").await.is_none());
+        assert_eq!(bulkheads.available("other"), None);
+    }
+
+    #[test]
+    fn rejects_a_zero_limit() {
+        let limits = BTreeMap::from([("enrich".to_string(), 0)]);
+        assert!(Bulkheads::new(&limits).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+//! # Capabilities
+//!
+//! Coarse permissions a function needs: [`Capability::Network`],
+//! [`Capability::Filesystem`] and [`Capability::Scripting`]. An engine
+//! built with an allowed set
+//! ([`EngineBuilder::with_allowed_capabilities`](crate::EngineBuilder::with_allowed_capabilities),
+//! or `allowed_capabilities` in [`EngineOptions`](crate::EngineOptions))
+//! refuses at construction any workflow with a task, `foreach` bodies
+//! included, whose function needs a capability outside it. An empty set
+//! guarantees no workflow reaches a function that does I/O:
+//!
+//! ```json
+//! {"allowed_capabilities": []}
+//! ```
+//!
+//! `http_call`, `enrich` and `publish_kafka` need `network`; the other
+//! built-ins need nothing. Custom handlers declare what they use by
+//! overriding
+//! [`AsyncFunctionHandler::capabilities`](crate::AsyncFunctionHandler::capabilities);
+//! a handler that doesn't is trusted to need nothing. Without an allowed
+//! set every capability is allowed.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::functions::{BoxedFunctionHandler, FunctionConfig};
+use crate::engine::task::Task;
+use crate::engine::workflow::Workflow;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+/// A permission a function may need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    /// Calls other services: HTTP, databases, message brokers.
+    Network,
+    /// Reads or writes files.
+    Filesystem,
+    /// Runs user-supplied scripts or code.
+    Scripting,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Network => "network",
+            Capability::Filesystem => "filesystem",
+            Capability::Scripting => "scripting",
+        })
+    }
+}
+
+/// Fail on the first task whose function needs a capability outside
+/// `allowed`.
+pub(crate) fn check_capabilities(
+    workflows: &[Workflow],
+    handlers: &HashMap<String, BoxedFunctionHandler>,
+    allowed: &BTreeSet<Capability>,
+) -> Result<()> {
+    workflows
+        .iter()
+        .try_for_each(|workflow| check_tasks(&workflow.id, &workflow.tasks, handlers, allowed))
+}
+
+fn check_tasks(
+    workflow_id: &str,
+    tasks: &[Task],
+    handlers: &HashMap<String, BoxedFunctionHandler>,
+    allowed: &BTreeSet<Capability>,
+) -> Result<()> {
+    for task in tasks {
+        let required: &[Capability] = match &task.function {
+            FunctionConfig::HttpCall { .. }
+            | FunctionConfig::Enrich { .. }
+            | FunctionConfig::PublishKafka { .. } => &[Capability::Network],
+            FunctionConfig::Custom { name, .. } => handlers
+                .get(name)
+                .map_or(&[], |handler| handler.dyn_capabilities()),
+            FunctionConfig::Foreach { input, .. } => {
+                check_tasks(workflow_id, &input.tasks, handlers, allowed)?;
+                &[]
+            }
+            _ => &[],
+        };
+        if let Some(missing) = required.iter().find(|c| !allowed.contains(c)) {
+            let allowed: Vec<String> = allowed.iter().map(Capability::to_string).collect();
+            return Err(DataflowError::Workflow(format!(
+                "Task {} in workflow {} uses '{}', which needs the {} capability (allowed: {})",
+                task.id,
+                workflow_id,
+                task.function.function_name(),
+                missing,
+                if allowed.is_empty() {
+                    "none".to_string()
+                } else {
+                    allowed.join(", ")
+                }
+            )));
+        }
+    }
+    Ok(())
+}
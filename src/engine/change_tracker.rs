@@ -0,0 +1,167 @@
+//! # Change Tracking
+//!
+//! Build audit-trail [`Change`] entries by comparing a value before and
+//! after an edit, instead of writing them by hand. Handlers that mutate the
+//! message in bulk — through [`TaskContext::message_mut`], a library that
+//! rewrites a whole tree — snapshot the part they touch, make their edits,
+//! and record the difference:
+//!
+//! ```rust,no_run
+//! # use dataflow_rs::{ChangeTracker, TaskContext};
+//! # fn edit(ctx: &mut TaskContext<'_>) {
+//! let before = ctx.message().context.clone();
+//! // ... edit ctx.message_mut().context in place ...
+//! let changes = ChangeTracker::diff_at("", &before, &ctx.message().context);
+//! ctx.record_changes(changes);
+//! # }
+//! ```
+//!
+//! Each difference is reported at the deepest path a `Change` can replay
+//! it from: an edited or added field on its own, an object that lost keys
+//! or has keys containing `.` or starting with `#`, or an array that
+//! changed length, whole. Replaying the changes over `before` with
+//! [`set_nested_value`](crate::engine::utils::set_nested_value) yields
+//! `after`, so they roll back and replay like the engine's own. Key order
+//! is ignored.
+//!
+//! [`TaskContext::message_mut`]: crate::TaskContext::message_mut
+
+use crate::engine::message::Change;
+use crate::engine::shadow::diff_values;
+use datavalue::OwnedDataValue;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Computes [`Change`]s between two values. See the
+/// [module docs](self).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChangeTracker;
+
+impl ChangeTracker {
+    /// Changes turning `before` into `after`, with paths relative to them;
+    /// replacing the value outright is a change at the empty path.
+    pub fn diff(before: &Value, after: &Value) -> Vec<Change> {
+        Self::diff_at(
+            "",
+            &OwnedDataValue::from(before),
+            &OwnedDataValue::from(after),
+        )
+    }
+
+    /// Changes turning `before` into `after`, the values at context path
+    /// `path` (`"data"`, or `""` for the whole context), with paths rooted
+    /// there — ready for
+    /// [`TaskContext::record_changes`](crate::TaskContext::record_changes).
+    pub fn diff_at(path: &str, before: &OwnedDataValue, after: &OwnedDataValue) -> Vec<Change> {
+        let mut changes = Vec::new();
+        collect(path, before, after, &mut changes);
+        changes
+    }
+}
+
+/// Whether `key` can be addressed as a single dot-path segment.
+fn plain_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains('.') && !key.starts_with('#')
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn collect(path: &str, before: &OwnedDataValue, after: &OwnedDataValue, out: &mut Vec<Change>) {
+    match (before, after) {
+        (OwnedDataValue::Object(old), OwnedDataValue::Object(new))
+            if old.iter().all(|(key, _)| new.iter().any(|(k, _)| k == key))
+                && new.iter().all(|(key, _)| plain_key(key)) =>
+        {
+            for (key, value) in new {
+                let child = join(path, key);
+                match old.iter().find(|(k, _)| k == key) {
+                    Some((_, previous)) => collect(&child, previous, value, out),
+                    None => out.push(change(&child, OwnedDataValue::Null, value)),
+                }
+            }
+        }
+        (OwnedDataValue::Array(old), OwnedDataValue::Array(new)) if old.len() == new.len() => {
+            for (i, (previous, value)) in old.iter().zip(new).enumerate() {
+                collect(&join(path, &i.to_string()), previous, value, out);
+            }
+        }
+        _ if diff_values(before, after).is_empty() => {}
+        _ => out.push(change(path, before.clone(), after)),
+    }
+}
+
+fn change(path: &str, old_value: OwnedDataValue, new_value: &OwnedDataValue) -> Change {
+    Change {
+        path: Arc::from(path),
+        old_value,
+        new_value: new_value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::utils::set_nested_value;
+    use serde_json::json;
+
+    fn paths(changes: &[Change]) -> Vec<(&str, Value)> {
+        changes
+            .iter()
+            .map(|c| (&*c.path, Value::from(&c.new_value)))
+            .collect()
+    }
+
+    #[test]
+    fn reports_each_difference_at_the_deepest_replayable_path() {
+        let before = json!({"user": {"email": "A@B.io", "a.b": 1}, "tags": ["x"], "n": [1, 2], "same": {"k": 1}});
+        let after = json!({"user": {"email": "a@b.io", "a.b": 2}, "tags": ["x", "y"], "n": [1, 3], "same": {"k": 1}, "new": true});
+        let changes = ChangeTracker::diff(&before, &after);
+
+        assert_eq!(
+            paths(&changes),
+            [
+                ("n.1", json!(3)),
+                ("new", json!(true)),
+                ("tags", json!(["x", "y"])),
+                // `a.b` is not addressable as a path segment.
+                ("user", json!({"email": "a@b.io", "a.b": 2})),
+            ]
+        );
+        assert_eq!(Value::from(&changes[0].old_value), json!(2));
+        assert_eq!(changes[1].old_value, OwnedDataValue::Null);
+
+        let mut replayed = OwnedDataValue::from(&json!({"data": before}));
+        for change in ChangeTracker::diff_at(
+            "data",
+            &OwnedDataValue::from(&before),
+            &OwnedDataValue::from(&after),
+        ) {
+            assert!(change.path.starts_with("data."));
+            set_nested_value(&mut replayed, &change.path, change.new_value);
+        }
+        assert_eq!(Value::from(&replayed["data"]), after);
+    }
+
+    #[test]
+    fn removed_keys_and_root_replacements_are_reported_whole() {
+        let changes = ChangeTracker::diff(&json!({"a": {"b": 1, "c": 2}}), &json!({"a": {"b": 1}}));
+        assert_eq!(paths(&changes), [("a", json!({"b": 1}))]);
+
+        let changes = ChangeTracker::diff(&json!({"a": 1}), &json!([1]));
+        assert_eq!(paths(&changes), [("", json!([1]))]);
+
+        assert!(
+            ChangeTracker::diff(
+                &json!({"a": [1, {"b": null}]}),
+                &json!({"a": [1, {"b": null}]})
+            )
+            .is_empty()
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! # Checkpoints
+//!
+//! Saves how far a message got, so processing interrupted by a crash can
+//! continue where it stopped instead of starting over and repeating side
+//! effects. With a [`CheckpointStore`] configured
+//! ([`EngineBuilder::with_checkpoint_store`](crate::EngineBuilder::with_checkpoint_store)),
+//! `process_message` and `process_message_for_channel` save a
+//! [`Checkpoint`] after each async task they complete (`http_call`,
+//! `enrich`, `publish_kafka`, custom handlers) and after each workflow they
+//! complete. Synchronous built-ins only transform
+//! the message and are simply run again. The checkpoint is removed once the
+//! message is processed without error; a message that fails keeps its last
+//! one.
+//!
+//! After a restart, load the message's checkpoint and hand it to
+//! [`Engine::resume_message`](crate::Engine::resume_message). The message
+//! is restored from the checkpoint's snapshot and processing continues with
+//! the task after the checkpointed one, without re-evaluating that
+//! workflow's condition or idempotency key, then with the workflows after
+//! it — on the checkpoint's channel only, when it has one. `before_message`
+//! hooks are not run again.
+//!
+//! A failed save is logged and processing goes on; resuming then repeats
+//! the work done since the previous checkpoint.
+
+use crate::engine::error::Result;
+use crate::engine::message::Message;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// How far a message got.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Workflow the message was in.
+    pub workflow_id: String,
+    /// Last task completed in that workflow. `None` once the whole
+    /// workflow completed.
+    pub task_id: Option<String>,
+    /// Channel the message was routed through by
+    /// [`Engine::process_message_for_channel`](crate::Engine::process_message_for_channel);
+    /// `None` for every workflow.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// The message as it stood at this point.
+    pub message: Message,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Checkpoint {
+    /// Id of the checkpointed message.
+    pub fn message_id(&self) -> &str {
+        self.message.id()
+    }
+}
+
+/// Durable home of checkpoints, one per message. Implementations should
+/// persist the checkpoint before returning `Ok`.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// Store `checkpoint`, replacing the earlier one of its message.
+    async fn save(&self, checkpoint: Checkpoint) -> Result<()>;
+
+    /// The checkpoint of message `message_id`, if any.
+    async fn load(&self, message_id: &str) -> Result<Option<Checkpoint>>;
+
+    /// Forget the checkpoint of `message_id`. Removing an absent one is not
+    /// an error.
+    async fn remove(&self, message_id: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: CheckpointStore + ?Sized> CheckpointStore for Arc<T> {
+    async fn save(&self, checkpoint: Checkpoint) -> Result<()> {
+        (**self).save(checkpoint).await
+    }
+
+    async fn load(&self, message_id: &str) -> Result<Option<Checkpoint>> {
+        (**self).load(message_id).await
+    }
+
+    async fn remove(&self, message_id: &str) -> Result<()> {
+        (**self).remove(message_id).await
+    }
+}
+
+/// `CheckpointStore` that keeps checkpoints in memory. Not durable; handy
+/// for tests.
+#[derive(Debug, Default)]
+pub struct InMemoryCheckpointStore {
+    checkpoints: Mutex<HashMap<String, Checkpoint>>,
+}
+
+impl InMemoryCheckpointStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Messages with a checkpoint.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no message has a checkpoint.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Checkpoint>> {
+        self.checkpoints.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for InMemoryCheckpointStore {
+    async fn save(&self, checkpoint: Checkpoint) -> Result<()> {
+        self.lock()
+            .insert(checkpoint.message_id().to_string(), checkpoint);
+        Ok(())
+    }
+
+    async fn load(&self, message_id: &str) -> Result<Option<Checkpoint>> {
+        Ok(self.lock().get(message_id).cloned())
+    }
+
+    async fn remove(&self, message_id: &str) -> Result<()> {
+        self.lock().remove(message_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn keeps_the_latest_checkpoint_per_message() {
+        let store = InMemoryCheckpointStore::new();
+        let message = Message::builder().id("m1").build();
+        for task_id in ["fetch", "notify"] {
+            store
+                .save(Checkpoint {
+                    workflow_id: "orders".to_string(),
+                    task_id: Some(task_id.to_string()),
+                    channel: None,
+                    message: message.clone(),
+                    created_at: Utc::now(),
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.len(), 1);
+        let loaded = store.load("m1").await.unwrap().unwrap();
+        assert_eq!(loaded.task_id.as_deref(), Some("notify"));
+
+        store.remove("m1").await.unwrap();
+        assert!(store.is_empty());
+        assert!(store.load("m1").await.unwrap().is_none());
+    }
+}
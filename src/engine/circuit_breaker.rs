@@ -0,0 +1,307 @@
+//! # Circuit Breakers
+//!
+//! Stops calling a function handler whose downstream dependency keeps
+//! failing, so messages fail fast instead of each waiting out a timeout.
+//! Breakers are configured per function name (`http_call`, `enrich`,
+//! `publish_kafka` or a custom handler) through
+//! [`EngineBuilder::with_circuit_breaker`](crate::EngineBuilder::with_circuit_breaker)
+//! or the `circuit_breakers` map of [`EngineOptions`](crate::EngineOptions):
+//!
+//! ```json
+//! {"circuit_breakers": {
+//!     "http_call": {"failure_threshold": 5, "open_ms": 30000, "half_open_probes": 1}
+//! }}
+//! ```
+//!
+//! - **Closed**: calls go through. `failure_threshold` consecutive
+//!   [`retryable`](crate::DataflowError::retryable) failures (5xx, timeouts,
+//!   I/O errors) open the circuit; any other result resets the count.
+//! - **Open**: calls fail at once with the retryable
+//!   [`DataflowError::CircuitOpen`] until `open_ms` has passed.
+//! - **Half-open**: up to `half_open_probes` calls are let through. Once
+//!   that many succeed the circuit closes; a failing probe reopens it.
+//!
+//! Breaker state is shared by every task calling the function, and carries
+//! over to engines produced by `with_new_workflows`.
+
+use crate::engine::error::{DataflowError, Result};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Settings of one function's circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive retryable failures that open the circuit. At least 1.
+    pub failure_threshold: u32,
+    /// How long the circuit stays open before probing, in milliseconds.
+    pub open_ms: u64,
+    /// Calls let through while half-open; that many successes close the
+    /// circuit. Default: 1.
+    #[serde(default = "default_half_open_probes")]
+    pub half_open_probes: u32,
+}
+
+fn default_half_open_probes() -> u32 {
+    1
+}
+
+impl CircuitBreakerConfig {
+    /// Open after `failure_threshold` consecutive failures, for `open_ms`.
+    pub fn new(failure_threshold: u32, open_ms: u64) -> Self {
+        Self {
+            failure_threshold,
+            open_ms,
+            half_open_probes: default_half_open_probes(),
+        }
+    }
+
+    /// Let `probes` calls through while half-open.
+    pub fn with_half_open_probes(mut self, probes: u32) -> Self {
+        self.half_open_probes = probes;
+        self
+    }
+}
+
+/// State of a circuit, as reported by [`Engine::circuit_state`](crate::Engine::circuit_state).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// Mutable state behind one breaker.
+#[derive(Debug)]
+enum Circuit {
+    Closed { failures: u32 },
+    Open { until: DateTime<Utc> },
+    HalfOpen { in_flight: u32, successes: u32 },
+}
+
+/// One function's breaker.
+#[derive(Debug)]
+struct CircuitBreaker {
+    name: String,
+    config: CircuitBreakerConfig,
+    circuit: Mutex<Circuit>,
+}
+
+/// The breakers of an engine, keyed by function name. Functions without a
+/// breaker are always called.
+#[derive(Debug, Default)]
+pub(crate) struct CircuitBreakers {
+    breakers: HashMap<String, CircuitBreaker>,
+}
+
+impl CircuitBreakers {
+    /// Breakers for `configs`, all closed. Fails on a zero
+    /// `failure_threshold` or `half_open_probes`.
+    pub(crate) fn new(configs: &BTreeMap<String, CircuitBreakerConfig>) -> Result<Self> {
+        let mut breakers = HashMap::with_capacity(configs.len());
+        for (name, config) in configs {
+            if config.failure_threshold == 0 || config.half_open_probes == 0 {
+                return Err(DataflowError::Workflow(format!(
+                    "Circuit breaker for '{}' needs a failure_threshold and half_open_probes of at least 1",
+                    name
+                )));
+            }
+            breakers.insert(
+                name.clone(),
+                CircuitBreaker {
+                    name: name.clone(),
+                    config: *config,
+                    circuit: Mutex::new(Circuit::Closed { failures: 0 }),
+                },
+            );
+        }
+        Ok(Self { breakers })
+    }
+
+    /// Ask to call the function `name`. `Err(CircuitOpen)` when its circuit
+    /// refuses the call; otherwise a permit to report the result through.
+    pub(crate) fn admit(&self, name: &str) -> Result<Permit<'_>> {
+        let Some(breaker) = self.breakers.get(name) else {
+            return Ok(Permit {
+                breaker: None,
+                probe: false,
+            });
+        };
+        let now = Utc::now();
+        let mut circuit = breaker.lock();
+        if let Circuit::Open { until } = *circuit
+            && now >= until
+        {
+            info!("Circuit for {} is half-open", breaker.name);
+            *circuit = Circuit::HalfOpen {
+                in_flight: 0,
+                successes: 0,
+            };
+        }
+        match &mut *circuit {
+            Circuit::Closed { .. } => Ok(Permit {
+                breaker: Some(breaker),
+                probe: false,
+            }),
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if *in_flight + *successes < breaker.config.half_open_probes => {
+                *in_flight += 1;
+                Ok(Permit {
+                    breaker: Some(breaker),
+                    probe: true,
+                })
+            }
+            _ => Err(DataflowError::CircuitOpen(format!(
+                "function {} is failing fast",
+                breaker.name
+            ))),
+        }
+    }
+
+    /// Current state of the circuit of `name`, `None` without a breaker.
+    pub(crate) fn state(&self, name: &str) -> Option<CircuitState> {
+        let breaker = self.breakers.get(name)?;
+        Some(match *breaker.lock() {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { until } if Utc::now() < until => CircuitState::Open,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => CircuitState::HalfOpen,
+        })
+    }
+}
+
+impl CircuitBreaker {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Circuit> {
+        self.circuit.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open(&self, circuit: &mut Circuit) {
+        warn!(
+            "Circuit for {} opened for {}ms",
+            self.name, self.config.open_ms
+        );
+        let open_for = chrono::Duration::milliseconds(self.config.open_ms as i64);
+        *circuit = Circuit::Open {
+            until: Utc::now() + open_for,
+        };
+    }
+}
+
+/// Leave to make one call, returned by [`CircuitBreakers::admit`]. A permit
+/// dropped without [`record`](Self::record) — the call was abandoned —
+/// frees its probe slot without counting either way.
+pub(crate) struct Permit<'a> {
+    breaker: Option<&'a CircuitBreaker>,
+    probe: bool,
+}
+
+impl Permit<'_> {
+    /// Count the call's result against its circuit.
+    pub(crate) fn record<T>(mut self, result: &Result<T>) {
+        let Some(breaker) = self.breaker.take() else {
+            return;
+        };
+        let failed = matches!(result, Err(e) if e.retryable());
+        let mut circuit = breaker.lock();
+        match &mut *circuit {
+            Circuit::Closed { failures } if failed => {
+                *failures += 1;
+                if *failures >= breaker.config.failure_threshold {
+                    breaker.open(&mut circuit);
+                }
+            }
+            Circuit::Closed { failures } => *failures = 0,
+            Circuit::HalfOpen { .. } if self.probe && failed => breaker.open(&mut circuit),
+            Circuit::HalfOpen {
+                in_flight,
+                successes,
+            } if self.probe => {
+                *in_flight = in_flight.saturating_sub(1);
+                *successes += 1;
+                if *successes >= breaker.config.half_open_probes {
+                    info!("Circuit for {} closed", breaker.name);
+                    *circuit = Circuit::Closed { failures: 0 };
+                }
+            }
+            // A call admitted before the circuit changed state.
+            _ => {}
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Some(breaker) = self.breaker
+            && self.probe
+            && let Circuit::HalfOpen { in_flight, .. } = &mut *breaker.lock()
+        {
+            *in_flight = in_flight.saturating_sub(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breakers(config: CircuitBreakerConfig) -> CircuitBreakers {
+        CircuitBreakers::new(&BTreeMap::from([("api".to_string(), config)])).unwrap()
+    }
+
+    fn call(breakers: &CircuitBreakers, result: Result<()>) -> Result<()> {
+        breakers.admit("api")?.record(&result);
+        Ok(())
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures_and_closes_after_probes() {
+        let breakers = breakers(CircuitBreakerConfig::new(2, 0).with_half_open_probes(2));
+        let unavailable = || Err(DataflowError::http(503, "unavailable"));
+
+        // Non-retryable errors and successes reset the streak.
+        call(&breakers, unavailable()).unwrap();
+        call(&breakers, Err(DataflowError::Validation("bad".into()))).unwrap();
+        call(&breakers, unavailable()).unwrap();
+        assert_eq!(breakers.state("api"), Some(CircuitState::Closed));
+        call(&breakers, unavailable()).unwrap();
+
+        // `open_ms` is 0, so the next call probes; a failing probe reopens.
+        assert_eq!(breakers.state("api"), Some(CircuitState::HalfOpen));
+        call(&breakers, unavailable()).unwrap();
+
+        // Two probes at once, then no more until they report.
+        let first = breakers.admit("api").unwrap();
+        let second = breakers.admit("api").unwrap();
+        assert!(matches!(
+            breakers.admit("api"),
+            Err(DataflowError::CircuitOpen(_))
+        ));
+        first.record(&Ok(()));
+        drop(second);
+        call(&breakers, Ok(())).unwrap();
+        assert_eq!(breakers.state("api"), Some(CircuitState::Closed));
+        assert_eq!(breakers.state("other"), None);
+    }
+
+    #[test]
+    fn open_circuit_fails_fast() {
+        let breakers = breakers(CircuitBreakerConfig::new(1, 60_000));
+        call(&breakers, Err(DataflowError::Timeout("slow".into()))).unwrap();
+        assert_eq!(breakers.state("api"), Some(CircuitState::Open));
+        let err = call(&breakers, Ok(())).unwrap_err();
+        assert!(matches!(err, DataflowError::CircuitOpen(_)));
+        assert!(err.retryable());
+        assert!(breakers.admit("other").is_ok());
+    }
+
+    #[test]
+    fn rejects_zero_thresholds() {
+        let configs = BTreeMap::from([("api".to_string(), CircuitBreakerConfig::new(0, 100))]);
+        assert!(CircuitBreakers::new(&configs).is_err());
+    }
+}
@@ -7,20 +7,60 @@
 //! `Arc` and is `Send + Sync` so the entire stack is safe to share across
 //! Tokio worker threads.
 
-use crate::engine::error::{DataflowError, Result};
+use crate::engine::audit::AuditInputs;
+use crate::engine::complexity::{self, ComplexityThresholds, ExpressionComplexity};
+use crate::engine::construction::WorkflowReport;
+use crate::engine::context_roots::check_readable_roots;
+use crate::engine::error::{DataflowError, Result, invalid, locate};
+use crate::engine::fragment::{Fragments, resolve_includes};
+use crate::engine::functions::alert::AlertConfig;
 use crate::engine::functions::integration::{EnrichConfig, HttpCallConfig, PublishKafkaConfig};
+use crate::engine::functions::join::JoinConfig;
+use crate::engine::functions::reject::RejectConfig;
+use crate::engine::functions::score::ScoreConfig;
+use crate::engine::functions::state::{StateGetConfig, StateSetConfig};
+use crate::engine::functions::switch::{self, SwitchConfig};
 use crate::engine::functions::{FilterConfig, LogConfig, MapConfig, ValidationConfig};
-use crate::engine::{FunctionConfig, Workflow};
+use crate::engine::interpolation::{InterpolationConfig, Placeholders, interpolate_tasks};
+use crate::engine::lint::LintWarning;
+use crate::engine::metrics::elapsed_ms;
+use crate::engine::rule_pack::{RulePacks, resolve_rule_refs};
+use crate::engine::secrets::SecretsResolver;
+use crate::engine::structure;
+use crate::engine::unknown_vars;
+use crate::engine::workflow::{Annotations, PriorityTieBreak};
+use crate::engine::{FunctionConfig, Task, Workflow};
+use chrono::Utc;
 use datalogic_rs::{Engine, Logic};
-use log::debug;
-use serde_json::Value;
-use std::sync::Arc;
+use datavalue::OwnedDataValue;
+use log::{debug, warn};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
 /// Compiles JSONLogic expressions and stamps them onto workflow/task/config
 /// structs as `Option<Arc<Logic>>` slots.
 pub struct LogicCompiler {
     /// Shared datalogic Engine used both for compilation and (later) evaluation.
     engine: Arc<Engine>,
+    /// Ordering applied to workflows that share a `priority`.
+    tie_break: PriorityTieBreak,
+    /// Packs available to `rules_ref` in validation configs.
+    rule_packs: RulePacks,
+    /// Fragments available to `include` entries in task lists.
+    fragments: Fragments,
+    /// Variables and strictness for placeholders in task inputs.
+    interpolation: InterpolationConfig,
+    /// Source of `${secret:NAME}` placeholders.
+    secrets: Option<Arc<dyn SecretsResolver>>,
+    /// Secret values spliced into task inputs so far.
+    resolved_secrets: Mutex<Vec<String>>,
+    /// Limits above which [`Self::lint`] reports an expression.
+    complexity: ComplexityThresholds,
+    /// Precompute per-task audit hashing inputs.
+    audit_input_hashes: bool,
+    /// Context roots each listed workflow may read.
+    readable_roots: BTreeMap<String, BTreeSet<String>>,
 }
 
 impl Default for LogicCompiler {
@@ -33,11 +73,91 @@ impl LogicCompiler {
     /// Create a new LogicCompiler with a fresh datalogic `Engine` configured for
     /// templating mode (preserves object structure in JSONLogic operations).
     pub fn new() -> Self {
+        Self::from_datalogic(Arc::new(Engine::builder().with_templating(true).build()))
+    }
+
+    /// Create a LogicCompiler that compiles with an existing datalogic
+    /// `Engine`, e.g. one built from [`EngineOptions`](crate::EngineOptions).
+    pub fn from_datalogic(engine: Arc<Engine>) -> Self {
         Self {
-            engine: Arc::new(Engine::builder().with_templating(true).build()),
+            engine,
+            tie_break: PriorityTieBreak::default(),
+            rule_packs: RulePacks::default(),
+            fragments: Fragments::default(),
+            interpolation: InterpolationConfig::default(),
+            secrets: None,
+            resolved_secrets: Mutex::default(),
+            complexity: ComplexityThresholds::default(),
+            audit_input_hashes: false,
+            readable_roots: BTreeMap::new(),
         }
     }
 
+    /// Choose how workflows with equal `priority` are ordered by
+    /// [`Self::compile_workflows`]. Defaults to
+    /// [`PriorityTieBreak::DeclarationOrder`].
+    pub fn with_priority_tie_break(mut self, tie_break: PriorityTieBreak) -> Self {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Rule packs used to resolve `rules_ref` in validation configs.
+    pub(crate) fn with_rule_packs(mut self, rule_packs: RulePacks) -> Self {
+        self.rule_packs = rule_packs;
+        self
+    }
+
+    /// Fragments used to resolve `include` entries in task lists.
+    pub(crate) fn with_fragments(mut self, fragments: Fragments) -> Self {
+        self.fragments = fragments;
+        self
+    }
+
+    /// Variables used to fill placeholders in task inputs.
+    pub(crate) fn with_interpolation(mut self, interpolation: InterpolationConfig) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Resolver for `${secret:NAME}` placeholders in task inputs.
+    pub(crate) fn with_secrets(mut self, secrets: Option<Arc<dyn SecretsResolver>>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// The secret values spliced into task inputs since the last call.
+    pub(crate) fn take_resolved_secrets(&self) -> Vec<String> {
+        std::mem::take(
+            &mut *self
+                .resolved_secrets
+                .lock()
+                .unwrap_or_else(|e| e.into_inner()),
+        )
+    }
+
+    /// Limits used by [`Self::lint`] to flag overly complex expressions.
+    /// Defaults to [`ComplexityThresholds::default`].
+    pub fn with_complexity_thresholds(mut self, thresholds: ComplexityThresholds) -> Self {
+        self.complexity = thresholds;
+        self
+    }
+
+    /// Populate each task's audit hashing inputs (see [`crate::engine::audit`]).
+    pub(crate) fn with_audit_input_hashes(mut self, on: bool) -> Self {
+        self.audit_input_hashes = on;
+        self
+    }
+
+    /// Restrict the context roots the listed workflows may read (see
+    /// [`context_roots`](crate::engine::context_roots)).
+    pub(crate) fn with_readable_roots(
+        mut self,
+        readable_roots: BTreeMap<String, BTreeSet<String>>,
+    ) -> Self {
+        self.readable_roots = readable_roots;
+        self
+    }
+
     /// Get the Engine instance
     pub fn engine(&self) -> Arc<Engine> {
         Arc::clone(&self.engine)
@@ -52,47 +172,210 @@ impl LogicCompiler {
     /// Returns `Err` on the first validation or compilation failure — engine
     /// construction is fail-loud so misconfigured workflows can't silently
     /// disappear at runtime.
+    ///
+    /// Ties on `priority` are broken according to the compiler's
+    /// [`PriorityTieBreak`]; the sort is stable, so the result is fully
+    /// deterministic for a given input. `depends_on` then moves each
+    /// workflow after its dependencies; an unknown dependency or a cycle is
+    /// an error.
     pub fn compile_workflows(&self, workflows: Vec<Workflow>) -> Result<Vec<Workflow>> {
+        self.compile_workflows_reported(workflows, &mut Vec::new())
+    }
+
+    /// [`compile_workflows`](Self::compile_workflows), appending a report
+    /// of each successfully compiled workflow to `reports`, in declaration
+    /// order.
+    pub(crate) fn compile_workflows_reported(
+        &self,
+        workflows: Vec<Workflow>,
+        reports: &mut Vec<WorkflowReport>,
+    ) -> Result<Vec<Workflow>> {
         let mut compiled_workflows = Vec::with_capacity(workflows.len());
+        let mut workflow_ids = HashSet::new();
 
-        for mut workflow in workflows {
-            workflow.validate()?;
+        for workflow in workflows {
+            let id = workflow.id.clone();
+            let workflow = self
+                .compile_workflow(workflow, &mut workflow_ids, reports)
+                .map_err(|e| locate(e, |at| at.in_workflow(&id)))?;
+            compiled_workflows.push(workflow);
+        }
+        // A misspelt id would leave the intended workflow unrestricted.
+        if let Some(id) = self
+            .readable_roots
+            .keys()
+            .find(|id| !workflow_ids.contains(*id))
+        {
+            return Err(invalid(format!(
+                "readable_roots names workflow '{id}', which is not loaded"
+            ))
+            .into());
+        }
 
-            // Populate the cached Arc<str> ids so audit emission can refcount-bump
-            // rather than reallocate per AuditTrail entry.
-            workflow.id_arc = Arc::from(workflow.id.as_str());
-            for task in &mut workflow.tasks {
-                task.id_arc = Arc::from(task.id.as_str());
-            }
+        // Sort by priority once at construction time
+        sort_workflows(&mut compiled_workflows, self.tie_break);
+        order_by_dependencies(compiled_workflows)
+    }
 
-            // Compile the workflow condition (required — defaults to `true`).
-            let label = format!("workflow {} condition", workflow.id);
-            workflow.compiled_condition = Some(self.compile(&workflow.condition, &label)?);
-            debug!("Workflow {} condition compiled", workflow.id);
+    /// Validate and compile one workflow, appending its report to
+    /// `reports`.
+    fn compile_workflow(
+        &self,
+        mut workflow: Workflow,
+        workflow_ids: &mut HashSet<String>,
+        reports: &mut Vec<WorkflowReport>,
+    ) -> Result<Workflow> {
+        let started = Utc::now();
+        resolve_includes(&mut workflow.tasks, &self.fragments, &workflow.id)?;
+        // Workflows taken from a built engine are interpolated already;
+        // a second pass would expand their `$${` escapes.
+        if workflow.compiled_condition.is_none() {
+            let mut placeholders = Placeholders::new(&self.interpolation, self.secrets.as_deref());
+            interpolate_tasks(&mut workflow.tasks, &mut placeholders, &workflow.id)?;
+            self.resolved_secrets
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .append(&mut placeholders.resolved_secrets);
+        }
+        workflow.validate()?;
+        if !workflow_ids.insert(workflow.id.clone()) {
+            return Err(invalid(format!("Duplicate workflow ID '{}'", workflow.id))
+                .within("/id")
+                .into());
+        }
 
-            // Compile task conditions and function-specific logic.
-            self.compile_workflow_tasks(&mut workflow)?;
+        // Populate the cached Arc<str> ids so audit emission can refcount-bump
+        // rather than reallocate per AuditTrail entry.
+        workflow.id_arc = Arc::from(workflow.id.as_str());
 
-            compiled_workflows.push(workflow);
+        // Compile the workflow condition (required — defaults to `true`).
+        let label = format!("workflow {} condition", workflow.id);
+        workflow.compiled_condition = Some(self.compile(&workflow.condition, &label)?);
+        debug!("Workflow {} condition compiled", workflow.id);
+
+        if let Some(variant) = &mut workflow.variant
+            && let Some(key) = &variant.key
+        {
+            let label = format!("workflow {} variant key", workflow.id);
+            variant.compiled_key = Some(self.compile(key, &label)?);
         }
 
-        // Sort by priority once at construction time
-        compiled_workflows.sort_by_key(|w| w.priority);
-        Ok(compiled_workflows)
+        if let Some(key) = &workflow.idempotency_key {
+            let label = format!("workflow {} idempotency key", workflow.id);
+            workflow.compiled_idempotency_key = Some(self.compile(key, &label)?);
+        }
+
+        compile_annotations(&mut workflow);
+        workflow.compiled_locale = workflow
+            .locale
+            .as_ref()
+            .map(|locale| Arc::new(locale.to_metadata()));
+
+        // Compile task conditions and function-specific logic.
+        self.compile_tasks(&mut workflow.tasks, &workflow.id)?;
+        if let Some(allowed) = self.readable_roots.get(&workflow.id) {
+            check_readable_roots(&workflow, allowed)?;
+        }
+
+        if self.audit_input_hashes {
+            let inputs: Vec<_> = workflow
+                .tasks
+                .iter()
+                .map(|task| Arc::new(AuditInputs::for_task(&workflow, task)))
+                .collect();
+            for (task, inputs) in workflow.tasks.iter_mut().zip(inputs) {
+                task.compiled_audit_inputs = Some(inputs);
+            }
+        }
+
+        reports.push(WorkflowReport {
+            workflow_id: workflow.id.clone(),
+            tasks: workflow.tasks.len(),
+            expressions: complexity::measure_workflow(&workflow).len(),
+            compile_ms: elapsed_ms(started),
+        });
+        Ok(workflow)
     }
 
-    /// Compile task conditions and function logic for a workflow
-    fn compile_workflow_tasks(&self, workflow: &mut Workflow) -> Result<()> {
-        for task in &mut workflow.tasks {
-            let label = format!("task {} condition (workflow {})", task.id, workflow.id);
-            task.compiled_condition = Some(self.compile(&task.condition, &label)?);
+    /// Measure every JSONLogic expression in `workflows`: conditions,
+    /// variant keys, mappings, validation rules and function logic. Pass
+    /// compiled workflows to see `rules_ref` rules merged in.
+    pub fn complexity_report(&self, workflows: &[Workflow]) -> Vec<ExpressionComplexity> {
+        workflows
+            .iter()
+            .flat_map(complexity::measure_workflow)
+            .collect()
+    }
+
+    /// Lint `workflows`, logging each warning at `warn` level. Reports
+    /// expressions over the configured [`ComplexityThresholds`], likely
+    /// misspelt `var` paths and the [structural](crate::engine::structure)
+    /// findings: dead conditions, empty workflows, reused task ids and
+    /// ambiguous priority ties.
+    pub fn lint(&self, workflows: &[Workflow]) -> Vec<LintWarning> {
+        let mut warnings: Vec<LintWarning> = workflows
+            .iter()
+            .flat_map(|workflow| {
+                let mut warnings = structure::lint_workflow(workflow);
+                warnings.extend(complexity::lint_workflow(workflow, &self.complexity));
+                warnings.extend(unknown_vars::lint_workflow(workflow));
+                warnings
+            })
+            .collect();
+        warnings.extend(structure::lint_priorities(workflows, self.tie_break));
+        for warning in &warnings {
+            match &warning.task_id {
+                Some(task_id) => warn!(
+                    "Lint {} in task {} (workflow {}): {}",
+                    warning.code, task_id, warning.workflow_id, warning.message
+                ),
+                None => warn!(
+                    "Lint {} in workflow {}: {}",
+                    warning.code, warning.workflow_id, warning.message
+                ),
+            }
+        }
+        warnings
+    }
 
-            // Compile function-specific logic (map transformations, validation rules, …)
-            self.compile_function_logic(&mut task.function, &task.id, &workflow.id)?;
+    /// Compile task conditions and function logic for a workflow's tasks,
+    /// or a `foreach` body. Tasks a `switch` branches to or jumps over get
+    /// its checks folded into their compiled condition.
+    fn compile_tasks(&self, tasks: &mut [Task], workflow_id: &str) -> Result<()> {
+        let gates = switch::branch_gates(workflow_id, tasks)?;
+        for (idx, (task, gates)) in tasks.iter_mut().zip(gates).enumerate() {
+            self.compile_task(task, gates, workflow_id).map_err(|e| {
+                locate(e, |at| {
+                    at.in_task(&task.id).within(&format!("/tasks/{idx}"))
+                })
+            })?;
         }
         Ok(())
     }
 
+    /// Compile one task's condition, with the `switch` `gates` folded in,
+    /// and its function logic.
+    fn compile_task(
+        &self,
+        task: &mut Task,
+        mut gates: Vec<Value>,
+        workflow_id: &str,
+    ) -> Result<()> {
+        // Cached so audit emission can refcount-bump the id.
+        task.id_arc = Arc::from(task.id.as_str());
+        let label = format!("task {} condition (workflow {})", task.id, workflow_id);
+        let condition = if gates.is_empty() {
+            self.compile(&task.condition, &label)?
+        } else {
+            gates.push(task.condition.clone());
+            self.compile(&json!({"and": gates}), &label)?
+        };
+        task.compiled_condition = Some(condition);
+
+        // Compile function-specific logic (map transformations, validation rules, …)
+        self.compile_function_logic(&mut task.function, &task.id, workflow_id)
+    }
+
     /// Compile function-specific logic based on function type
     fn compile_function_logic(
         &self,
@@ -122,6 +405,30 @@ impl LogicCompiler {
             FunctionConfig::PublishKafka { input, .. } => {
                 self.compile_publish_kafka_logic(input, task_id, workflow_id)
             }
+            FunctionConfig::Switch { input, .. } => {
+                self.compile_switch_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::Reject { input, .. } => {
+                self.compile_reject_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::Score { input, .. } => {
+                self.compile_score_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::Join { input, .. } => {
+                self.compile_join_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::StateGet { input, .. } => {
+                self.compile_state_get_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::StateSet { input, .. } => {
+                self.compile_state_set_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::Alert { input, .. } => {
+                self.compile_alert_logic(input, task_id, workflow_id)
+            }
+            FunctionConfig::Foreach { input, .. } => self
+                .compile_tasks(&mut input.tasks, workflow_id)
+                .map_err(|e| locate(e, |at| at.within("/function/input"))),
             // Custom and other functions don't need pre-compilation
             _ => Ok(()),
         }
@@ -169,6 +476,7 @@ impl LogicCompiler {
         task_id: &str,
         workflow_id: &str,
     ) -> Result<()> {
+        resolve_rule_refs(config, &self.rule_packs, task_id, workflow_id)?;
         for (idx, rule) in config.rules.iter_mut().enumerate() {
             let label = format!(
                 "validation rule {} for task {} in workflow {}",
@@ -222,6 +530,151 @@ impl LogicCompiler {
         Ok(())
     }
 
+    /// Compile switch case conditions and fix where the selection is recorded
+    fn compile_switch_logic(
+        &self,
+        config: &mut SwitchConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        for (idx, case) in config.cases.iter_mut().enumerate() {
+            let label = format!(
+                "switch case {} for task {} in workflow {}",
+                idx, task_id, workflow_id
+            );
+            case.compiled_when = Some(self.compile(&case.when, &label)?);
+        }
+        config.selection_path = switch::selection_path(workflow_id, task_id);
+        Ok(())
+    }
+
+    /// Compile reject code and reason expressions
+    fn compile_reject_logic(
+        &self,
+        config: &mut RejectConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        let label = |field: &str| {
+            format!(
+                "reject {} for task {} in workflow {}",
+                field, task_id, workflow_id
+            )
+        };
+        config.compiled_code = Some(self.compile(&config.code, &label("code"))?);
+        config.compiled_reason = Some(self.compile(&config.reason, &label("reason"))?);
+        Ok(())
+    }
+
+    /// Compile score rules, rejecting duplicate rule ids
+    fn compile_score_logic(
+        &self,
+        config: &mut ScoreConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        let mut seen = HashSet::new();
+        for rule in &mut config.rules {
+            if !seen.insert(rule.id.as_str()) {
+                return Err(DataflowError::Workflow(format!(
+                    "Score task '{}' in workflow '{}' has duplicate rule id '{}'",
+                    task_id, workflow_id, rule.id
+                )));
+            }
+            let label = format!(
+                "score rule {} for task {} in workflow {}",
+                rule.id, task_id, workflow_id
+            );
+            rule.compiled_logic = Some(self.compile(&rule.logic, &label)?);
+        }
+        Ok(())
+    }
+
+    /// Compile the join correlation key and check its completion conditions
+    fn compile_join_logic(
+        &self,
+        config: &mut JoinConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        if config.count.is_none() && config.timeout_ms.is_none() {
+            return Err(DataflowError::Workflow(format!(
+                "Join task '{}' in workflow '{}' must set 'count', 'timeout_ms' or both",
+                task_id, workflow_id
+            )));
+        }
+        if config.count == Some(0) {
+            return Err(DataflowError::Workflow(format!(
+                "Join task '{}' in workflow '{}': 'count' must be at least 1",
+                task_id, workflow_id
+            )));
+        }
+        let label = format!("join key for task {} in workflow {}", task_id, workflow_id);
+        config.compiled_key = Some(self.compile(&config.key, &label)?);
+        config.group_prefix = format!("join:{workflow_id}:{task_id}");
+        config.status_path = format!("metadata.joins.{workflow_id}.{task_id}");
+        Ok(())
+    }
+
+    /// Compile the state_get key
+    fn compile_state_get_logic(
+        &self,
+        config: &mut StateGetConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        let label = format!(
+            "state_get key for task {} in workflow {}",
+            task_id, workflow_id
+        );
+        config.compiled_key = Some(self.compile(&config.key, &label)?);
+        Ok(())
+    }
+
+    /// Compile the state_set key and value
+    fn compile_state_set_logic(
+        &self,
+        config: &mut StateSetConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        let label = |field: &str| {
+            format!(
+                "state_set {} for task {} in workflow {}",
+                field, task_id, workflow_id
+            )
+        };
+        config.compiled_key = Some(self.compile(&config.key, &label("key"))?);
+        config.compiled_value = Some(self.compile(&config.value, &label("value"))?);
+        Ok(())
+    }
+
+    /// Compile the alert message and fields, and record where the alert
+    /// is raised from
+    fn compile_alert_logic(
+        &self,
+        config: &mut AlertConfig,
+        task_id: &str,
+        workflow_id: &str,
+    ) -> Result<()> {
+        let label = |field: &str| {
+            format!(
+                "alert {} for task {} in workflow {}",
+                field, task_id, workflow_id
+            )
+        };
+        config.compiled_message = Some(self.compile(&config.message, &label("message"))?);
+        let mut compiled_fields = Vec::with_capacity(config.fields.len());
+        for (key, logic) in &config.fields {
+            let compiled = self.compile(logic, &label(&format!("field '{key}'")))?;
+            compiled_fields.push((key.clone(), compiled));
+        }
+        config.compiled_fields = compiled_fields;
+        config.workflow_id = workflow_id.to_string();
+        config.task_id = task_id.to_string();
+        Ok(())
+    }
+
     /// Compile http_call JSONLogic expressions (path_logic, body_logic)
     fn compile_http_call_logic(
         &self,
@@ -287,3 +740,228 @@ impl LogicCompiler {
         Ok(())
     }
 }
+
+/// Sort workflows by ascending `priority`, breaking ties per `tie_break`.
+/// `sort_by` is stable, so `DeclarationOrder` falls out of leaving equal
+/// elements where they are.
+fn sort_workflows(workflows: &mut [Workflow], tie_break: PriorityTieBreak) {
+    match tie_break {
+        PriorityTieBreak::DeclarationOrder => workflows.sort_by_key(|w| w.priority),
+        PriorityTieBreak::Id => {
+            workflows.sort_by(|a, b| a.priority.cmp(&b.priority).then_with(|| a.id.cmp(&b.id)))
+        }
+    }
+}
+
+/// Reorder priority-sorted `workflows` so each runs after every workflow
+/// named in its `depends_on`. Among the workflows whose dependencies have
+/// all been placed, the earliest in priority order goes next, so unrelated
+/// workflows keep their priority order.
+fn order_by_dependencies(workflows: Vec<Workflow>) -> Result<Vec<Workflow>> {
+    if workflows.iter().all(|w| w.depends_on.is_empty()) {
+        return Ok(workflows);
+    }
+
+    // Workflows not yet placed, per id.
+    let mut unplaced: HashMap<String, usize> = HashMap::new();
+    for workflow in &workflows {
+        *unplaced.entry(workflow.id.clone()).or_default() += 1;
+    }
+    for workflow in &workflows {
+        if let Some(dep) = workflow
+            .depends_on
+            .iter()
+            .find(|dep| !unplaced.contains_key(*dep))
+        {
+            return Err(invalid(format!(
+                "Workflow '{}' depends on unknown workflow '{}'",
+                workflow.id, dep
+            ))
+            .in_workflow(&workflow.id)
+            .within("/depends_on")
+            .into());
+        }
+    }
+
+    let total = workflows.len();
+    let mut waiting: Vec<Option<Workflow>> = workflows.into_iter().map(Some).collect();
+    let mut ordered = Vec::with_capacity(total);
+    while ordered.len() < total {
+        let ready = waiting.iter().position(|slot| {
+            slot.as_ref()
+                .is_some_and(|w| w.depends_on.iter().all(|dep| unplaced[dep] == 0))
+        });
+        let Some(idx) = ready else {
+            let blocked: Vec<&str> = waiting.iter().flatten().map(|w| w.id.as_str()).collect();
+            return Err(invalid(format!(
+                "Workflow dependency cycle among: {}",
+                blocked.join(", ")
+            ))
+            .into());
+        };
+        let workflow = waiting[idx].take().expect("ready slot is occupied");
+        if let Some(count) = unplaced.get_mut(&workflow.id) {
+            *count -= 1;
+        }
+        ordered.push(workflow);
+    }
+    Ok(ordered)
+}
+
+/// Populate the `compiled_annotations` objects of a workflow and its tasks.
+/// Tasks without annotations of their own share the workflow's `Arc`.
+fn compile_annotations(workflow: &mut Workflow) {
+    fn to_object(annotations: &Annotations) -> OwnedDataValue {
+        OwnedDataValue::Object(
+            annotations
+                .iter()
+                .map(|(k, v)| (k.clone(), OwnedDataValue::from(v)))
+                .collect(),
+        )
+    }
+
+    workflow.compiled_annotations =
+        (!workflow.annotations.is_empty()).then(|| Arc::new(to_object(&workflow.annotations)));
+    for task in &mut workflow.tasks {
+        task.compiled_annotations = if task.annotations.is_empty() {
+            workflow.compiled_annotations.clone()
+        } else {
+            let mut merged = workflow.annotations.clone();
+            merged.extend(task.annotations.clone());
+            Some(Arc::new(to_object(&merged)))
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow(id: &str, priority: u32) -> Workflow {
+        Workflow::from_json(&format!(
+            r#"{{"id": "{id}", "name": "{id}", "priority": {priority},
+                "tasks": [{{"id": "t", "name": "t",
+                    "function": {{"name": "map", "input": {{"mappings": []}}}}}}]}}"#
+        ))
+        .unwrap()
+    }
+
+    fn ids(workflows: &[Workflow]) -> Vec<&str> {
+        workflows.iter().map(|w| w.id.as_str()).collect()
+    }
+
+    #[test]
+    fn equal_priorities_keep_declaration_order_by_default() {
+        let compiled = LogicCompiler::new()
+            .compile_workflows(vec![
+                workflow("c", 1),
+                workflow("a", 1),
+                workflow("z", 0),
+                workflow("b", 1),
+            ])
+            .unwrap();
+        assert_eq!(ids(&compiled), ["z", "c", "a", "b"]);
+    }
+
+    #[test]
+    fn equal_priorities_sorted_by_id_when_requested() {
+        let compiled = LogicCompiler::new()
+            .with_priority_tie_break(PriorityTieBreak::Id)
+            .compile_workflows(vec![
+                workflow("c", 1),
+                workflow("a", 1),
+                workflow("z", 0),
+                workflow("b", 1),
+            ])
+            .unwrap();
+        assert_eq!(ids(&compiled), ["z", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn depends_on_overrides_priority_and_rejects_cycles() {
+        let depends = |id: &str, priority: u32, deps: &[&str]| {
+            let mut w = workflow(id, priority);
+            w.depends_on = deps.iter().map(|d| d.to_string()).collect();
+            w
+        };
+        let compiled = LogicCompiler::new()
+            .compile_workflows(vec![
+                depends("enrich", 0, &["parse"]),
+                workflow("audit", 1),
+                depends("parse", 5, &[]),
+                depends("route", 0, &["enrich", "audit"]),
+            ])
+            .unwrap();
+        assert_eq!(ids(&compiled), ["audit", "parse", "enrich", "route"]);
+
+        let err = LogicCompiler::new()
+            .compile_workflows(vec![depends("a", 0, &["missing"])])
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown workflow 'missing'"));
+
+        let err = LogicCompiler::new()
+            .compile_workflows(vec![
+                depends("a", 0, &["b"]),
+                depends("b", 0, &["a"]),
+                workflow("c", 0),
+            ])
+            .unwrap_err();
+        assert!(err.to_string().contains("cycle among: a, b"));
+    }
+
+    #[test]
+    fn rejects_duplicate_workflow_and_task_ids() {
+        let err = LogicCompiler::new()
+            .compile_workflows(vec![workflow("a", 0), workflow("b", 0), workflow("a", 1)])
+            .unwrap_err();
+        assert!(err.to_string().contains("Duplicate workflow ID 'a'"));
+
+        let map = r#"{"name": "map", "input": {"mappings": []}}"#;
+        let nested = Workflow::from_json(&format!(
+            r#"{{"id": "wf", "name": "WF", "tasks": [{{"id": "each", "name": "each",
+                "function": {{"name": "foreach", "input": {{"path": "data.items", "tasks": [
+                    {{"id": "t", "name": "t", "function": {map}}},
+                    {{"id": "t", "name": "t", "function": {map}}}
+                ]}}}}}}]}}"#
+        ))
+        .unwrap();
+        let err = LogicCompiler::new()
+            .compile_workflows(vec![nested])
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Duplicate task ID 't' in workflow 'wf'")
+        );
+    }
+
+    #[test]
+    fn reports_complexity_and_lints_expressions_over_thresholds() {
+        let workflow = Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [{"id": "t", "name": "t",
+                "condition": {"==": [{"var": "metadata.type"}, "order"]},
+                "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.total", "logic": {"+": [
+                        {"var": "data.a"}, {"var": "data.b"}, {"var": "data.c"}
+                    ]}}
+                ]}}}]}"#,
+        )
+        .unwrap();
+        let compiler = LogicCompiler::new().with_complexity_thresholds(ComplexityThresholds {
+            max_var_refs: Some(2),
+            ..ComplexityThresholds::default()
+        });
+        let compiled = compiler.compile_workflows(vec![workflow]).unwrap();
+
+        let report = compiler.complexity_report(&compiled);
+        let locations: Vec<&str> = report.iter().map(|e| e.location.as_str()).collect();
+        assert_eq!(locations, ["condition", "condition", "mapping data.total"]);
+        assert_eq!(report[2].task_id.as_deref(), Some("t"));
+        assert_eq!(report[2].complexity.var_refs, 3);
+
+        let lints = compiler.lint(&compiled);
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].code, "complex_expression");
+        assert_eq!(lints[0].task_id.as_deref(), Some("t"));
+        assert!(lints[0].message.contains("3 var references (max 2)"));
+    }
+}
@@ -0,0 +1,202 @@
+//! # Logic Complexity
+//!
+//! Size metrics for JSONLogic expressions, used to spot expressions that
+//! should be split into several mappings or moved into a custom function.
+//!
+//! [`LogicCompiler::complexity_report`](crate::engine::compiler::LogicCompiler::complexity_report)
+//! measures every expression of a workflow set. While an engine is built,
+//! expressions over the configured [`ComplexityThresholds`] are reported as
+//! `complex_expression` [lint warnings](crate::LintWarning).
+
+use crate::engine::lint::{LintWarning, logic_sites};
+use crate::engine::workflow::Workflow;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Size of one JSONLogic expression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LogicComplexity {
+    /// Operators and literals in the expression.
+    pub nodes: usize,
+    /// Deepest operator nesting; a bare literal is 0, `{"var": "x"}` is 1.
+    pub depth: usize,
+    /// `var` and `val` lookups.
+    pub var_refs: usize,
+}
+
+impl LogicComplexity {
+    /// Measure `logic`.
+    pub fn measure(logic: &Value) -> Self {
+        match logic {
+            Value::Array(items) => {
+                items
+                    .iter()
+                    .map(Self::measure)
+                    .fold(Self::default(), |acc, item| Self {
+                        nodes: acc.nodes + item.nodes,
+                        depth: acc.depth.max(item.depth),
+                        var_refs: acc.var_refs + item.var_refs,
+                    })
+            }
+            Value::Object(map) if map.len() == 1 => {
+                let (op, args) = map.iter().next().expect("one entry");
+                let inner = Self::measure(args);
+                Self {
+                    nodes: 1 + inner.nodes,
+                    depth: 1 + inner.depth,
+                    var_refs: inner.var_refs + usize::from(op == "var" || op == "val"),
+                }
+            }
+            // Literal object (templating mode): the object itself plus its values.
+            Value::Object(map) => {
+                let inner = Self::measure(&Value::Array(map.values().cloned().collect()));
+                Self {
+                    nodes: 1 + inner.nodes,
+                    ..inner
+                }
+            }
+            _ => Self {
+                nodes: 1,
+                depth: 0,
+                var_refs: 0,
+            },
+        }
+    }
+}
+
+/// Limits above which an expression is reported. `None` disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComplexityThresholds {
+    pub max_nodes: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub max_var_refs: Option<usize>,
+}
+
+impl Default for ComplexityThresholds {
+    fn default() -> Self {
+        Self {
+            max_nodes: Some(100),
+            max_depth: Some(12),
+            max_var_refs: Some(30),
+        }
+    }
+}
+
+impl ComplexityThresholds {
+    /// No limits: nothing is ever reported.
+    pub fn unlimited() -> Self {
+        Self {
+            max_nodes: None,
+            max_depth: None,
+            max_var_refs: None,
+        }
+    }
+
+    /// Descriptions of the limits `complexity` exceeds, e.g.
+    /// `"140 nodes (max 100)"`. Empty when within every limit.
+    pub fn violations(&self, complexity: &LogicComplexity) -> Vec<String> {
+        [
+            (complexity.nodes, self.max_nodes, "nodes"),
+            (complexity.depth, self.max_depth, "levels deep"),
+            (complexity.var_refs, self.max_var_refs, "var references"),
+        ]
+        .into_iter()
+        .filter_map(|(value, max, what)| {
+            max.filter(|max| value > *max)
+                .map(|max| format!("{value} {what} (max {max})"))
+        })
+        .collect()
+    }
+}
+
+/// Complexity of one expression in a workflow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExpressionComplexity {
+    pub workflow_id: String,
+    /// `None` for workflow-level expressions (condition, variant key).
+    pub task_id: Option<String>,
+    /// Where the expression sits, e.g. `condition` or `mapping data.total`.
+    pub location: String,
+    pub complexity: LogicComplexity,
+}
+
+/// Measure every expression of `workflow`.
+pub(crate) fn measure_workflow(workflow: &Workflow) -> Vec<ExpressionComplexity> {
+    logic_sites(workflow)
+        .into_iter()
+        .map(|site| ExpressionComplexity {
+            workflow_id: workflow.id.clone(),
+            task_id: site.task_id.map(str::to_string),
+            location: site.location,
+            complexity: LogicComplexity::measure(site.logic),
+        })
+        .collect()
+}
+
+/// `complex_expression` lints for the expressions of `workflow` over
+/// `thresholds`.
+pub(crate) fn lint_workflow(
+    workflow: &Workflow,
+    thresholds: &ComplexityThresholds,
+) -> Vec<LintWarning> {
+    measure_workflow(workflow)
+        .into_iter()
+        .filter_map(|expression| {
+            let violations = thresholds.violations(&expression.complexity);
+            (!violations.is_empty()).then(|| LintWarning {
+                code: "complex_expression".to_string(),
+                workflow_id: expression.workflow_id,
+                task_id: expression.task_id,
+                message: format!(
+                    "{} is too complex: {}; consider splitting it or moving it into a custom function",
+                    expression.location,
+                    violations.join(", ")
+                ),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn measures_nodes_depth_and_var_refs() {
+        let logic = json!({"and": [
+            {">": [{"var": "data.amount"}, 100]},
+            {"in": [{"var": "data.country"}, ["US", "CA"]]}
+        ]});
+        assert_eq!(
+            LogicComplexity::measure(&logic),
+            LogicComplexity {
+                nodes: 10,
+                depth: 3,
+                var_refs: 2
+            }
+        );
+        assert_eq!(LogicComplexity::measure(&json!(true)).depth, 0);
+    }
+
+    #[test]
+    fn reports_only_exceeded_limits() {
+        let thresholds = ComplexityThresholds {
+            max_nodes: Some(5),
+            max_depth: None,
+            max_var_refs: Some(2),
+        };
+        let complexity = LogicComplexity {
+            nodes: 8,
+            depth: 40,
+            var_refs: 2,
+        };
+        assert_eq!(thresholds.violations(&complexity), ["8 nodes (max 5)"]);
+        assert!(
+            ComplexityThresholds::unlimited()
+                .violations(&complexity)
+                .is_empty()
+        );
+    }
+}
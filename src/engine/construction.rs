@@ -0,0 +1,122 @@
+//! # Construction Report
+//!
+//! What [`Engine::try_new`](crate::Engine::try_new) and
+//! [`EngineBuilder::build_with_report`](crate::EngineBuilder::build_with_report)
+//! found while building an engine: how many JSONLogic expressions each
+//! workflow compiled and how long that took, the lint warnings, and the
+//! workflows and tasks that can never run. When construction fails, the
+//! [`EngineBuildError`] carries the report up to the point of failure.
+
+use crate::engine::error::DataflowError;
+use crate::engine::lint::LintWarning;
+use crate::engine::workflow::{Workflow, WorkflowStatus};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Summary of an engine's construction.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConstructionReport {
+    /// Compiled workflows, in execution order.
+    pub workflows: Vec<WorkflowReport>,
+    /// Lint findings, as also returned by `Engine::lint_warnings`.
+    pub warnings: Vec<LintWarning>,
+    /// Workflows and tasks that compiled but can never run.
+    pub skipped: Vec<SkippedItem>,
+    /// Time spent compiling workflows, in milliseconds.
+    pub compile_ms: f64,
+}
+
+/// Compilation figures for one workflow.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkflowReport {
+    pub workflow_id: String,
+    /// Tasks at the top level of the workflow.
+    pub tasks: usize,
+    /// JSONLogic expressions compiled: conditions, variant keys, mappings,
+    /// rules and function logic.
+    pub expressions: usize,
+    /// Time spent validating and compiling the workflow, in milliseconds.
+    pub compile_ms: f64,
+}
+
+/// A workflow or task that will never run, and why.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedItem {
+    pub workflow_id: String,
+    /// `None` when the whole workflow is skipped.
+    pub task_id: Option<String>,
+    pub reason: String,
+}
+
+impl ConstructionReport {
+    /// Total JSONLogic expressions compiled across all workflows.
+    pub fn expression_count(&self) -> usize {
+        self.workflows.iter().map(|w| w.expressions).sum()
+    }
+
+    /// Report of the workflows compiled so far, in declaration order.
+    pub(crate) fn compiled(workflows: Vec<WorkflowReport>) -> Self {
+        Self {
+            compile_ms: workflows.iter().map(|w| w.compile_ms).sum(),
+            workflows,
+            ..Default::default()
+        }
+    }
+
+    /// Complete the report of a successful construction: put the workflows
+    /// in execution order and add the warnings and skipped items.
+    pub(crate) fn finish(&mut self, workflows: &[Workflow], warnings: &[LintWarning]) {
+        let mut compiled: HashMap<String, WorkflowReport> = self
+            .workflows
+            .drain(..)
+            .map(|report| (report.workflow_id.clone(), report))
+            .collect();
+        self.workflows = workflows
+            .iter()
+            .filter_map(|workflow| compiled.remove(&workflow.id))
+            .collect();
+        self.warnings = warnings.to_vec();
+        self.skipped = workflows.iter().flat_map(skipped_items).collect();
+    }
+}
+
+/// Items of `workflow` that can never run: the whole workflow when it is
+/// not active or its condition is literally `false`, else each such task.
+fn skipped_items(workflow: &Workflow) -> Vec<SkippedItem> {
+    let skip = |task_id: Option<&str>, reason: String| SkippedItem {
+        workflow_id: workflow.id.clone(),
+        task_id: task_id.map(str::to_string),
+        reason,
+    };
+    if workflow.status != WorkflowStatus::Active {
+        let status = serde_json::to_value(&workflow.status).unwrap_or(Value::Null);
+        let status = status.as_str().unwrap_or("inactive");
+        return vec![skip(None, format!("workflow status is {status}"))];
+    }
+    if workflow.condition == Value::Bool(false) {
+        return vec![skip(None, "condition is always false".to_string())];
+    }
+    workflow
+        .tasks
+        .iter()
+        .filter(|task| task.condition == Value::Bool(false))
+        .map(|task| skip(Some(&task.id), "condition is always false".to_string()))
+        .collect()
+}
+
+/// Engine construction failed. `report` covers what was compiled before
+/// the failure.
+#[derive(Debug, Clone, Error)]
+#[error("{error}")]
+pub struct EngineBuildError {
+    pub error: DataflowError,
+    pub report: ConstructionReport,
+}
+
+impl From<EngineBuildError> for DataflowError {
+    fn from(err: EngineBuildError) -> Self {
+        err.error
+    }
+}
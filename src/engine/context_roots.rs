@@ -0,0 +1,240 @@
+//! # Readable Context Roots
+//!
+//! Least privilege between workflows authored by different teams: an engine
+//! can restrict which context roots (`data`, `metadata`, `temp_data`, …)
+//! each workflow may read, per workflow id, through
+//! [`EngineBuilder::with_readable_roots`](crate::EngineBuilder::with_readable_roots)
+//! or `readable_roots` in [`EngineOptions`](crate::EngineOptions):
+//!
+//! ```json
+//! {"readable_roots": {"sepa_routing": ["data", "metadata"]}}
+//! ```
+//!
+//! The check runs at compile time over every JSONLogic expression of the
+//! workflow — conditions, variant key, mappings, rules (including those of
+//! referenced rule packs) and function logic, `foreach` bodies included. A
+//! `var`, `val`, `exists`, `missing` or `missing_some` reference whose
+//! first path segment is not an allowed root fails construction. So does
+//! a read the check can't verify: an empty path (the whole context), a
+//! computed path (`{"var": {"cat": [...]}}`), or a `val` that climbs out
+//! of a `map`/`filter`/… element scope. Paths inside per-element logic are
+//! relative to the element and are not restricted.
+//!
+//! Workflows without an entry are unrestricted; an entry naming a workflow
+//! the engine doesn't load fails construction. Writes and the paths
+//! functions read by configuration (such as a parse `source`) are not
+//! covered.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::lint::logic_sites;
+use crate::engine::workflow::Workflow;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// A context read found in logic.
+enum Read {
+    /// A literal path, e.g. `data.amount`.
+    Path(String),
+    /// A read whose root can't be known before evaluation.
+    Unverifiable(&'static str),
+}
+
+/// Fail on the first expression in `workflow` that reads a root outside
+/// `allowed`, or reads in a way the check can't verify.
+pub(crate) fn check_readable_roots(workflow: &Workflow, allowed: &BTreeSet<String>) -> Result<()> {
+    for site in logic_sites(workflow) {
+        let mut found = Vec::new();
+        reads(site.logic, false, &mut found);
+        let denied = found.iter().find_map(|read| match read {
+            Read::Path(path) => {
+                let root = path.split('.').next().unwrap_or_default();
+                (!allowed.contains(root)).then(|| format!("'{path}'"))
+            }
+            Read::Unverifiable(what) => Some(what.to_string()),
+        });
+        if let Some(read) = denied {
+            let location = match site.task_id {
+                Some(task_id) => format!("task {} {}", task_id, site.location),
+                None => site.location,
+            };
+            let allowed: Vec<&str> = allowed.iter().map(String::as_str).collect();
+            return Err(DataflowError::Workflow(format!(
+                "Workflow {} reads {} in {}, but may only read: {}",
+                workflow.id,
+                read,
+                location,
+                if allowed.is_empty() {
+                    "nothing".to_string()
+                } else {
+                    allowed.join(", ")
+                }
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Collect the context reads of `logic` into `out`. `scoped` is set inside
+/// the per-element logic of iterating operators, where paths are relative
+/// to the element.
+fn reads(logic: &Value, scoped: bool, out: &mut Vec<Read>) {
+    match logic {
+        Value::Array(items) => items.iter().for_each(|item| reads(item, scoped, out)),
+        Value::Object(map) if map.len() == 1 => {
+            let (op, args) = map.iter().next().expect("one entry");
+            let args: &[Value] = match args {
+                Value::Array(items) => items,
+                single => std::slice::from_ref(single),
+            };
+            match op.as_str() {
+                "var" => {
+                    if !scoped {
+                        out.push(var_read(args.first()));
+                    }
+                    args.iter().for_each(|arg| reads(arg, scoped, out));
+                }
+                "val" | "exists" => {
+                    if let Some(read) = segments_read(args, scoped) {
+                        out.push(read);
+                    }
+                    args.iter().for_each(|arg| reads(arg, scoped, out));
+                }
+                "missing" => args.iter().for_each(|arg| missing_reads(arg, scoped, out)),
+                "missing_some" => {
+                    args.iter()
+                        .skip(1)
+                        .for_each(|arg| missing_reads(arg, scoped, out));
+                }
+                "map" | "filter" | "reduce" | "all" | "some" | "none" => {
+                    for (idx, arg) in args.iter().enumerate() {
+                        reads(arg, scoped || idx == 1, out);
+                    }
+                }
+                _ => args.iter().for_each(|arg| reads(arg, scoped, out)),
+            }
+        }
+        Value::Object(map) => map.values().for_each(|value| reads(value, scoped, out)),
+        _ => {}
+    }
+}
+
+/// The read of a `var` whose path argument is `path`.
+fn var_read(path: Option<&Value>) -> Read {
+    match path {
+        None | Some(Value::Null) => Read::Unverifiable("the whole context"),
+        Some(Value::String(path)) if path.is_empty() => Read::Unverifiable("the whole context"),
+        Some(Value::String(path)) => Read::Path(path.clone()),
+        Some(Value::Number(n)) => Read::Path(n.to_string()),
+        Some(_) => Read::Unverifiable("a computed path"),
+    }
+}
+
+/// The read of a `val` or `exists` with path segments `segments`. Only the
+/// first segment picks the root, so later ones may be computed.
+fn segments_read(segments: &[Value], scoped: bool) -> Option<Read> {
+    match segments.first() {
+        // `[[level], …]` reads an enclosing scope; `index` and `key` of
+        // the current element are bookkeeping, not context.
+        Some(Value::Array(_))
+            if scoped
+                && segments.len() == 2
+                && matches!(segments[1].as_str(), Some("index" | "key")) =>
+        {
+            None
+        }
+        Some(Value::Array(_)) => Some(Read::Unverifiable("a scope-level path")),
+        _ if scoped => None,
+        None => Some(Read::Unverifiable("the whole context")),
+        Some(Value::String(root)) if root.is_empty() => {
+            Some(Read::Unverifiable("the whole context"))
+        }
+        Some(Value::String(_)) => {
+            let path: Vec<String> = segments
+                .iter()
+                .map(|segment| match segment {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                })
+                .collect();
+            Some(Read::Path(path.join(".")))
+        }
+        Some(_) => Some(Read::Unverifiable("a computed path")),
+    }
+}
+
+/// The reads of a `missing` argument: literal paths, or logic computing
+/// them.
+fn missing_reads(arg: &Value, scoped: bool, out: &mut Vec<Read>) {
+    match arg {
+        Value::String(_) if !scoped => out.push(var_read(Some(arg))),
+        Value::String(_) => {}
+        Value::Array(items) => items
+            .iter()
+            .for_each(|item| missing_reads(item, scoped, out)),
+        other => {
+            if !scoped {
+                out.push(Read::Unverifiable("a computed path"));
+            }
+            reads(other, scoped, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn denied(logic: Value) -> Option<String> {
+        let mut found = Vec::new();
+        reads(&logic, false, &mut found);
+        let allowed = ["data"];
+        found.into_iter().find_map(|read| match read {
+            Read::Path(path) if allowed.contains(&path.split('.').next().unwrap()) => None,
+            Read::Path(path) => Some(path),
+            Read::Unverifiable(what) => Some(what.to_string()),
+        })
+    }
+
+    #[test]
+    fn literal_paths_under_allowed_roots_pass() {
+        assert_eq!(denied(json!({"var": "data.a"})), None);
+        assert_eq!(denied(json!({"val": ["data", {"var": "data.k"}]})), None);
+        assert_eq!(denied(json!({"exists": ["data", "a"]})), None);
+        assert_eq!(denied(json!({"missing": ["data.a", "data.b"]})), None);
+        assert_eq!(
+            denied(json!({"map": [{"var": "data.items"}, {"var": "sku"}]})),
+            None
+        );
+        assert_eq!(
+            denied(json!({"map": [{"var": "data.items"}, {"val": [[1], "index"]}]})),
+            None
+        );
+    }
+
+    #[test]
+    fn other_roots_and_unverifiable_reads_are_denied() {
+        assert_eq!(
+            denied(json!({"val": ["temp_data", "x"]})).as_deref(),
+            Some("temp_data.x")
+        );
+        assert_eq!(
+            denied(json!({"exists": "metadata"})).as_deref(),
+            Some("metadata")
+        );
+        for whole in [json!({"var": ""}), json!({"var": []}), json!({"val": []})] {
+            assert_eq!(denied(whole).as_deref(), Some("the whole context"));
+        }
+        for computed in [
+            json!({"var": {"cat": ["temp", "_data.x"]}}),
+            json!({"val": [{"cat": ["temp", "_data"]}, "x"]}),
+            json!({"missing": {"merge": ["temp_data.x"]}}),
+        ] {
+            assert_eq!(denied(computed).as_deref(), Some("a computed path"));
+        }
+        assert_eq!(
+            denied(json!({"map": [{"var": "data.items"}, {"val": [[1], "temp_data"]}]})).as_deref(),
+            Some("a scope-level path")
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! # Coverage Reporting
+//!
+//! Runs a corpus of sample messages through the engine and counts, per
+//! workflow task, how often each validation rule was evaluated and failed
+//! and how often each map mapping was executed and actually wrote a value.
+//! Rules that never fail and mappings that never write are candidates for
+//! dead configuration or missing test data.
+//!
+//! Produced by [`Engine::coverage`](crate::Engine::coverage). Coverage runs
+//! use the tracing path and are meant for test suites and CI, not the hot
+//! path. Samples run as in a [dry run](crate::engine::dry_run): tasks that
+//! may have side effects are held back rather than run, and count as not
+//! executed.
+
+use crate::engine::dry_run::HeldBackTask;
+use crate::engine::executor::eval_to_owned;
+use crate::engine::functions::FunctionConfig;
+use crate::engine::trace::{ExecutionTrace, StepResult};
+use crate::engine::workflow::Workflow;
+use datalogic_rs::Engine as DatalogicEngine;
+use datavalue::OwnedDataValue;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Hit counts for one validation rule.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RuleCoverage {
+    /// Position in the task's rule list (aggregate rules follow the logic
+    /// rules).
+    pub index: usize,
+    /// The rule's error message, or its aggregate expression.
+    pub message: String,
+    /// Times the rule was evaluated (its task executed).
+    pub evaluated: usize,
+    /// Times the rule failed.
+    pub failed: usize,
+}
+
+/// Hit counts for one map mapping.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MappingCoverage {
+    /// Position in the task's mapping list.
+    pub index: usize,
+    /// Target path.
+    pub path: String,
+    /// Times the mapping ran (its task executed).
+    pub executed: usize,
+    /// Times the mapping produced a non-null value and wrote it.
+    pub written: usize,
+}
+
+/// Hit counts for one task.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TaskCoverage {
+    pub task_id: String,
+    /// Function name (`map`, `validation`, a custom handler name, …).
+    pub function: String,
+    /// Times the task ran.
+    pub executed: usize,
+    /// Times the task's condition skipped it.
+    pub skipped: usize,
+    /// Times the task was held back because it may have side effects.
+    pub held_back: usize,
+    /// Per-rule counts (validation tasks only).
+    pub rules: Vec<RuleCoverage>,
+    /// Per-mapping counts (map tasks only).
+    pub mappings: Vec<MappingCoverage>,
+}
+
+/// Hit counts for one workflow.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WorkflowCoverage {
+    pub workflow_id: String,
+    /// Messages whose run entered this workflow.
+    pub matched: usize,
+    /// Messages whose run skipped this workflow on its condition.
+    pub skipped: usize,
+    pub tasks: Vec<TaskCoverage>,
+}
+
+/// Coverage of a message corpus over the engine's workflows, in engine
+/// (priority) order.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CoverageReport {
+    /// Number of sample messages processed.
+    pub messages: usize,
+    /// Number of samples whose run ended in an error.
+    pub failed_messages: usize,
+    pub workflows: Vec<WorkflowCoverage>,
+}
+
+impl CoverageReport {
+    /// Empty report shaped after `workflows`.
+    pub(crate) fn new(workflows: &[Workflow]) -> Self {
+        let workflows = workflows
+            .iter()
+            .map(|workflow| WorkflowCoverage {
+                workflow_id: workflow.id.clone(),
+                tasks: workflow
+                    .tasks
+                    .iter()
+                    .map(|task| {
+                        let mut coverage = TaskCoverage {
+                            task_id: task.id.clone(),
+                            function: task.function.function_name().to_string(),
+                            ..Default::default()
+                        };
+                        match &task.function {
+                            FunctionConfig::Validation { input, .. } => {
+                                let logic = input.rules.iter().map(|r| r.message.clone());
+                                let aggregates = input
+                                    .aggregates
+                                    .iter()
+                                    .map(|a| a.message.clone().unwrap_or_else(|| a.expression()));
+                                coverage.rules = logic
+                                    .chain(aggregates)
+                                    .enumerate()
+                                    .map(|(index, message)| RuleCoverage {
+                                        index,
+                                        message,
+                                        ..Default::default()
+                                    })
+                                    .collect();
+                            }
+                            FunctionConfig::Map { input, .. } => {
+                                coverage.mappings = input
+                                    .mappings
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(index, m)| MappingCoverage {
+                                        index,
+                                        path: m.path.clone(),
+                                        ..Default::default()
+                                    })
+                                    .collect();
+                            }
+                            _ => {}
+                        }
+                        coverage
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect();
+        Self {
+            workflows,
+            ..Default::default()
+        }
+    }
+
+    /// Fold one message's trace, and the tasks its run held back, into the
+    /// counts.
+    pub(crate) fn record(
+        &mut self,
+        workflows: &[Workflow],
+        trace: &ExecutionTrace,
+        held_back: &[HeldBackTask],
+        failed: bool,
+        engine: &DatalogicEngine,
+    ) {
+        self.messages += 1;
+        if failed {
+            self.failed_messages += 1;
+        }
+
+        let mut entered = HashSet::new();
+        for step in &trace.steps {
+            let Some(wf_idx) = workflows.iter().position(|w| w.id == step.workflow_id) else {
+                continue;
+            };
+            let workflow = &workflows[wf_idx];
+            let wf_cov = &mut self.workflows[wf_idx];
+
+            let Some(task_id) = &step.task_id else {
+                wf_cov.skipped += 1;
+                continue;
+            };
+            entered.insert(wf_idx);
+            let Some(t_idx) = workflow.tasks.iter().position(|t| &t.id == task_id) else {
+                continue;
+            };
+            let task_cov = &mut wf_cov.tasks[t_idx];
+            if step.result == StepResult::Skipped {
+                let was_held_back = held_back
+                    .iter()
+                    .any(|h| h.workflow_id == workflow.id && &h.task_id == task_id);
+                if was_held_back {
+                    task_cov.held_back += 1;
+                } else {
+                    task_cov.skipped += 1;
+                }
+                continue;
+            }
+            task_cov.executed += 1;
+
+            match &workflow.tasks[t_idx].function {
+                FunctionConfig::Validation { input, .. } => {
+                    let Some(message) = &step.message else {
+                        continue;
+                    };
+                    let logic_results = input.rules.iter().map(|rule| match &rule.compiled_logic {
+                        Some(logic) => !matches!(
+                            eval_to_owned(engine, logic, &message.context),
+                            Ok(OwnedDataValue::Bool(true))
+                        ),
+                        None => true,
+                    });
+                    let aggregate_results = input
+                        .aggregates
+                        .iter()
+                        .map(|a| a.check(&message.context).is_some());
+                    for (cov, did_fail) in task_cov
+                        .rules
+                        .iter_mut()
+                        .zip(logic_results.chain(aggregate_results))
+                    {
+                        cov.evaluated += 1;
+                        if did_fail {
+                            cov.failed += 1;
+                        }
+                    }
+                }
+                FunctionConfig::Map { input, .. } => {
+                    let Some(contexts) = &step.mapping_contexts else {
+                        continue;
+                    };
+                    for ((cov, mapping), ctx) in task_cov
+                        .mappings
+                        .iter_mut()
+                        .zip(&input.mappings)
+                        .zip(contexts)
+                    {
+                        cov.executed += 1;
+                        let wrote = mapping.compiled_logic.as_ref().is_some_and(|logic| {
+                            matches!(
+                                eval_to_owned(engine, logic, &OwnedDataValue::from(ctx)),
+                                Ok(ref v) if !matches!(v, OwnedDataValue::Null)
+                            )
+                        });
+                        if wrote {
+                            cov.written += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for wf_idx in entered {
+            self.workflows[wf_idx].matched += 1;
+        }
+    }
+
+    /// Validation rules that never failed: `(workflow_id, task_id, rule)`.
+    pub fn dead_rules(&self) -> impl Iterator<Item = (&str, &str, &RuleCoverage)> {
+        self.workflows.iter().flat_map(|w| {
+            w.tasks.iter().flat_map(move |t| {
+                t.rules
+                    .iter()
+                    .filter(|r| r.failed == 0)
+                    .map(move |r| (w.workflow_id.as_str(), t.task_id.as_str(), r))
+            })
+        })
+    }
+
+    /// Mappings that never wrote a value: `(workflow_id, task_id, mapping)`.
+    pub fn unexercised_mappings(&self) -> impl Iterator<Item = (&str, &str, &MappingCoverage)> {
+        self.workflows.iter().flat_map(|w| {
+            w.tasks.iter().flat_map(move |t| {
+                t.mappings
+                    .iter()
+                    .filter(|m| m.written == 0)
+                    .map(move |m| (w.workflow_id.as_str(), t.task_id.as_str(), m))
+            })
+        })
+    }
+
+    /// Tasks that never ran: `(workflow_id, task)`.
+    pub fn unexecuted_tasks(&self) -> impl Iterator<Item = (&str, &TaskCoverage)> {
+        self.workflows.iter().flat_map(|w| {
+            w.tasks
+                .iter()
+                .filter(|t| t.executed == 0)
+                .map(move |t| (w.workflow_id.as_str(), t))
+        })
+    }
+}
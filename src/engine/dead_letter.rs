@@ -0,0 +1,319 @@
+//! # Dead Letters
+//!
+//! Hands messages the engine gives up on to a [`DeadLetterHandler`]
+//! instead of leaving the caller with nothing but an `Err`. A message is
+//! dead-lettered when processing fails with an error retrying won't fix
+//! (one that is not [`DataflowError::retryable`]), or — with an error
+//! budget set — when it completes with more errors than the budget allows
+//! (e.g. through `continue_on_error` tasks).
+//!
+//! The handler receives the message and its [`ErrorInfo`] list, and
+//! `process_message` returns the non-retryable
+//! [`DataflowError::DeadLettered`]. Cancelled, draining and quarantined
+//! messages are left alone. If the handler fails, the original result is
+//! returned unchanged.
+//!
+//! Two handlers ship with the engine: [`InMemoryDeadLetterQueue`] and
+//! [`FileDeadLetterQueue`], which appends one JSON record per line.
+
+use crate::engine::error::{DataflowError, ErrorInfo, Result};
+use crate::engine::message::Message;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// When messages are dead-lettered besides non-retryable failures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeadLetterPolicy {
+    /// Dead-letter a message that completes with more than this many
+    /// errors. Defaults to none: only failures are dead-lettered.
+    pub error_budget: Option<usize>,
+}
+
+/// Why a message was dead-lettered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterReason {
+    /// Processing failed with a non-retryable error.
+    Failed,
+    /// Processing completed with more errors than the error budget.
+    ErrorBudget,
+}
+
+/// A message the engine gave up on, as handed to a [`DeadLetterHandler`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The message as it stood when processing ended, including its audit
+    /// trail.
+    pub message: Message,
+    /// Errors recorded on the message.
+    pub errors: Vec<ErrorInfo>,
+    pub reason: DeadLetterReason,
+    /// Error processing ended with; `None` for [`DeadLetterReason::ErrorBudget`].
+    pub error: Option<DataflowError>,
+    /// When the message was dead-lettered.
+    pub dead_lettered_at: DateTime<Utc>,
+}
+
+/// Destination for dead letters: a DLQ topic, a database table, a file.
+///
+/// If `handle` returns `Err`, the message is not considered dead-lettered
+/// and the engine returns the original processing result.
+#[async_trait]
+pub trait DeadLetterHandler: Send + Sync {
+    async fn handle(&self, letter: DeadLetter) -> Result<()>;
+}
+
+/// `DeadLetterHandler` that keeps letters in memory. Handy for tests and for
+/// services that expose dead letters through their own API.
+#[derive(Debug, Default)]
+pub struct InMemoryDeadLetterQueue {
+    letters: Mutex<Vec<DeadLetter>>,
+}
+
+impl InMemoryDeadLetterQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of every letter received so far.
+    pub fn letters(&self) -> Vec<DeadLetter> {
+        self.letters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Remove and return every letter received so far.
+    pub fn drain(&self) -> Vec<DeadLetter> {
+        std::mem::take(&mut *self.letters.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+#[async_trait]
+impl DeadLetterHandler for InMemoryDeadLetterQueue {
+    async fn handle(&self, letter: DeadLetter) -> Result<()> {
+        self.letters
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(letter);
+        Ok(())
+    }
+}
+
+/// `DeadLetterHandler` that appends each letter to a file as one line of
+/// JSON (NDJSON). The file is created on first use.
+#[derive(Debug)]
+pub struct FileDeadLetterQueue {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl FileDeadLetterQueue {
+    /// Queue backed by the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every letter in the file, oldest first. A missing file reads as
+    /// empty.
+    pub fn read_all(&self) -> Result<Vec<DeadLetter>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(DataflowError::from_io(e)),
+        };
+        BufReader::new(file)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| {
+                let line = line.map_err(DataflowError::from_io)?;
+                serde_json::from_str(&line).map_err(DataflowError::from_serde)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl DeadLetterHandler for FileDeadLetterQueue {
+    async fn handle(&self, letter: DeadLetter) -> Result<()> {
+        let mut line = serde_json::to_string(&letter).map_err(DataflowError::from_serde)?;
+        line.push('\n');
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(DataflowError::from_io)
+    }
+}
+
+#[async_trait]
+impl<T: DeadLetterHandler + ?Sized> DeadLetterHandler for Arc<T> {
+    async fn handle(&self, letter: DeadLetter) -> Result<()> {
+        (**self).handle(letter).await
+    }
+}
+
+/// Engine-internal dead-letter configuration: policy and handler. Shared by
+/// `Arc` with engines produced by `Engine::with_new_workflows`.
+pub(crate) struct DeadLetters {
+    policy: DeadLetterPolicy,
+    handler: Box<dyn DeadLetterHandler>,
+}
+
+impl DeadLetters {
+    pub(crate) fn new(policy: DeadLetterPolicy, handler: Box<dyn DeadLetterHandler>) -> Self {
+        Self { policy, handler }
+    }
+
+    /// Hand the message to the handler when `result` calls for it. Returns
+    /// the result the caller should see: unchanged, or
+    /// `Err(DeadLettered)` once the message has been handed off.
+    pub(crate) async fn observe(&self, message: &Message, result: Result<()>) -> Result<()> {
+        let (reason, error) = match &result {
+            Ok(()) => match self.policy.error_budget {
+                Some(budget) if message.errors().len() > budget => {
+                    (DeadLetterReason::ErrorBudget, None)
+                }
+                _ => return result,
+            },
+            Err(
+                DataflowError::Cancelled(_)
+                | DataflowError::Draining(_)
+                | DataflowError::Quarantined(_),
+            ) => return result,
+            Err(e) if e.retryable() => return result,
+            Err(e) => (DeadLetterReason::Failed, Some(e.clone())),
+        };
+
+        let summary = match &error {
+            Some(e) => e.to_string(),
+            None => format!(
+                "{} errors exceed the budget of {}",
+                message.errors().len(),
+                self.policy.error_budget.unwrap_or_default()
+            ),
+        };
+        warn!("Dead-lettering message {}: {}", message.id(), summary);
+        let letter = DeadLetter {
+            message: message.clone(),
+            errors: message.errors().to_vec(),
+            reason,
+            error,
+            dead_lettered_at: Utc::now(),
+        };
+        match self.handler.handle(letter).await {
+            Ok(()) => Err(DataflowError::DeadLettered(format!(
+                "message {}: {}",
+                message.id(),
+                summary
+            ))),
+            Err(handler_err) => {
+                error!(
+                    "Dead-letter handler rejected message {}: {}",
+                    message.id(),
+                    handler_err
+                );
+                result
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_errors(count: usize) -> Message {
+        let mut msg = Message::builder().id("m1").build();
+        for _ in 0..count {
+            msg.add_error(ErrorInfo::builder("TASK_ERROR", "boom").build());
+        }
+        msg
+    }
+
+    #[tokio::test]
+    async fn dead_letters_fatal_failures_and_blown_budgets() {
+        let queue = Arc::new(InMemoryDeadLetterQueue::new());
+        let dead_letters = DeadLetters::new(
+            DeadLetterPolicy {
+                error_budget: Some(1),
+            },
+            Box::new(Arc::clone(&queue)),
+        );
+
+        // Within budget, retryable and cancelled results pass through.
+        assert!(dead_letters.observe(&with_errors(1), Ok(())).await.is_ok());
+        let timeout = Err(DataflowError::Timeout("slow".to_string()));
+        let result = dead_letters.observe(&with_errors(1), timeout).await;
+        assert!(matches!(result, Err(DataflowError::Timeout(_))));
+        let cancelled = Err(DataflowError::Cancelled("stop".to_string()));
+        let result = dead_letters.observe(&with_errors(0), cancelled).await;
+        assert!(matches!(result, Err(DataflowError::Cancelled(_))));
+        assert!(queue.letters().is_empty());
+
+        let fatal = Err(DataflowError::Validation("bad".to_string()));
+        let result = dead_letters.observe(&with_errors(1), fatal).await;
+        assert!(matches!(result, Err(DataflowError::DeadLettered(_))));
+        let result = dead_letters.observe(&with_errors(2), Ok(())).await;
+        assert!(matches!(result, Err(DataflowError::DeadLettered(_))));
+
+        let letters = queue.drain();
+        assert_eq!(letters.len(), 2);
+        assert_eq!(letters[0].reason, DeadLetterReason::Failed);
+        assert!(matches!(
+            letters[0].error,
+            Some(DataflowError::Validation(_))
+        ));
+        assert_eq!(letters[1].reason, DeadLetterReason::ErrorBudget);
+        assert_eq!(letters[1].errors.len(), 2);
+        assert!(letters[1].error.is_none());
+    }
+
+    #[tokio::test]
+    async fn file_queue_appends_ndjson() {
+        let path =
+            std::env::temp_dir().join(format!("dataflow-dlq-{}.ndjson", uuid::Uuid::now_v7()));
+        let queue = FileDeadLetterQueue::new(&path);
+        assert!(queue.read_all().unwrap().is_empty());
+
+        for id in ["a", "b"] {
+            let letter = DeadLetter {
+                message: Message::builder().id(id).build(),
+                errors: Vec::new(),
+                reason: DeadLetterReason::Failed,
+                error: Some(DataflowError::Task("boom".to_string())),
+                dead_lettered_at: Utc::now(),
+            };
+            queue.handle(letter).await.unwrap();
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        let ids: Vec<String> = queue
+            .read_all()
+            .unwrap()
+            .into_iter()
+            .map(|letter| letter.message.id().to_string())
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
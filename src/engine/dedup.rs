@@ -0,0 +1,205 @@
+//! # Deduplication
+//!
+//! Runs a workflow at most once per idempotency key within a time window,
+//! so a message delivered twice does not, say, charge a card twice. A
+//! workflow opts in with an `idempotency_key` JSONLogic expression and,
+//! optionally, `idempotency_ttl_ms` (default 24 hours):
+//!
+//! ```json
+//! {"id": "charge", "idempotency_key": {"var": "data.payment_id"},
+//!  "idempotency_ttl_ms": 3600000, "tasks": [...]}
+//! ```
+//!
+//! Once its condition matches, the key is evaluated against the message.
+//! The engine's [`DeduplicationStore`], configured with
+//! [`EngineBuilder::with_deduplication_store`](crate::EngineBuilder::with_deduplication_store),
+//! records it under `<workflow_id>:<key>`. A key already recorded and not
+//! expired skips the workflow and adds an audit entry with task id
+//! [`DEDUP_TASK_ID`] and status [`DEDUP_STATUS`]. A workflow that fails
+//! releases its key so a redelivery can try again. A `null` key is not
+//! deduplicated.
+//!
+//! Building an engine with an `idempotency_key` and no store fails.
+//! [`InMemoryDeduplicationStore`] keeps a bounded number of keys in memory,
+//! evicting the least recently used; share a durable implementation
+//! between instances to deduplicate across them.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::workflow::Workflow;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Task id of the audit entry recording a skipped duplicate.
+pub const DEDUP_TASK_ID: &str = "idempotency_key";
+
+/// Audit status of a skipped duplicate (HTTP 208, Already Reported).
+pub const DEDUP_STATUS: usize = 208;
+
+/// How long a key is remembered when a workflow sets no
+/// `idempotency_ttl_ms`.
+pub const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Remembers the idempotency keys seen recently.
+#[async_trait]
+pub trait DeduplicationStore: Send + Sync {
+    /// Record `key` for `ttl`. Returns `true` when it was already recorded
+    /// and not expired, in which case its expiry is left alone. Must be
+    /// atomic: of concurrent calls with one key, only one returns `false`.
+    async fn check_and_record(&self, key: &str, ttl: Duration) -> Result<bool>;
+
+    /// Forget `key`. Forgetting an absent key is not an error.
+    async fn release(&self, key: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: DeduplicationStore + ?Sized> DeduplicationStore for Arc<T> {
+    async fn check_and_record(&self, key: &str, ttl: Duration) -> Result<bool> {
+        (**self).check_and_record(key, ttl).await
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        (**self).release(key).await
+    }
+}
+
+/// `DeduplicationStore` holding up to `capacity` keys in memory. Recording
+/// a key beyond that evicts the least recently used one. Not durable.
+#[derive(Debug)]
+pub struct InMemoryDeduplicationStore {
+    capacity: usize,
+    keys: Mutex<Lru>,
+}
+
+/// Keys with their expiry and last use, and the keys by last use.
+#[derive(Debug, Default)]
+struct Lru {
+    entries: HashMap<String, (DateTime<Utc>, u64)>,
+    by_use: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.get_mut(key) {
+            self.by_use.remove(used);
+            *used = self.clock;
+            self.by_use.insert(self.clock, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.by_use.remove(&used);
+        }
+    }
+}
+
+impl InMemoryDeduplicationStore {
+    /// A store remembering at most `capacity` keys (at least one).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            keys: Mutex::default(),
+        }
+    }
+
+    /// Keys currently remembered, expired ones included until evicted.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether no key is remembered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        self.keys.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl DeduplicationStore for InMemoryDeduplicationStore {
+    async fn check_and_record(&self, key: &str, ttl: Duration) -> Result<bool> {
+        let now = Utc::now();
+        let mut lru = self.lock();
+        let seen = lru
+            .entries
+            .get(key)
+            .is_some_and(|(expires, _)| *expires > now);
+        if seen {
+            lru.touch(key);
+            return Ok(true);
+        }
+        lru.remove(key);
+        while lru.entries.len() >= self.capacity {
+            let Some((_, oldest)) = lru.by_use.pop_first() else {
+                break;
+            };
+            lru.entries.remove(&oldest);
+        }
+        lru.entries.insert(key.to_string(), (now + ttl, 0));
+        lru.touch(key);
+        Ok(false)
+    }
+
+    async fn release(&self, key: &str) -> Result<()> {
+        self.lock().remove(key);
+        Ok(())
+    }
+}
+
+/// Store key of idempotency key `key` of workflow `workflow_id`.
+pub(crate) fn dedup_key(workflow_id: &str, key: &str) -> String {
+    format!("{workflow_id}:{key}")
+}
+
+/// Fail when a workflow declares an `idempotency_key` but the engine has no
+/// store to check it against.
+pub(crate) fn validate_idempotency(workflows: &[Workflow], configured: bool) -> Result<()> {
+    match workflows.iter().find(|w| w.idempotency_key.is_some()) {
+        Some(workflow) if !configured => Err(DataflowError::Workflow(format!(
+            "Workflow {} sets idempotency_key, but the engine has no deduplication store",
+            workflow.id
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn remembers_keys_until_they_expire_or_are_released() {
+        let store = InMemoryDeduplicationStore::new(10);
+        let hour = Duration::from_secs(3600);
+        assert!(!store.check_and_record("a", hour).await.unwrap());
+        assert!(store.check_and_record("a", hour).await.unwrap());
+
+        store.release("a").await.unwrap();
+        assert!(!store.check_and_record("a", hour).await.unwrap());
+
+        assert!(!store.check_and_record("b", Duration::ZERO).await.unwrap());
+        assert!(!store.check_and_record("b", hour).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_key() {
+        let store = InMemoryDeduplicationStore::new(2);
+        let hour = Duration::from_secs(3600);
+        store.check_and_record("a", hour).await.unwrap();
+        store.check_and_record("b", hour).await.unwrap();
+        // Seeing "a" again makes "b" the least recently used.
+        assert!(store.check_and_record("a", hour).await.unwrap());
+        store.check_and_record("c", hour).await.unwrap();
+
+        assert_eq!(store.len(), 2);
+        assert!(store.check_and_record("a", hour).await.unwrap());
+        assert!(!store.check_and_record("b", hour).await.unwrap());
+    }
+}
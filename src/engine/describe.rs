@@ -0,0 +1,424 @@
+//! # Workflow Descriptions
+//!
+//! A structured, serializable model of a workflow for UIs and generated
+//! documentation: each task's function, its condition rendered as infix
+//! text (broken over lines when long), a line per mapping or rule, and the context paths the task reads
+//! and writes.
+//!
+//! Produced by [`Workflow::describe`]. The model is built from the typed
+//! task configs the compiler consumes, so describing the workflows of a
+//! built engine ([`Engine::workflows`](crate::Engine::workflows)) also
+//! reflects construction-time expansion such as merged rule packs.
+//!
+//! Read and write paths are static: a path read by logic that never runs,
+//! or written by a mapping that evaluates to `null`, is still listed.
+//! Custom functions are opaque, so only their `output` path is known.
+
+use crate::engine::functions::FunctionConfig;
+use crate::engine::functions::filter::RejectAction;
+use crate::engine::logic_text::{render_condition, render_logic, var_paths};
+use crate::engine::task::Task;
+use crate::engine::workflow::{Annotations, Workflow, WorkflowStatus};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// Description of one workflow.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkflowDescription {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub priority: u32,
+    pub channel: String,
+    pub status: WorkflowStatus,
+    pub tags: Vec<String>,
+    pub annotations: Annotations,
+    /// The workflow condition as infix text (see
+    /// [`render_logic_pretty`](crate::render_logic_pretty)); `None` when it
+    /// always runs.
+    pub condition: Option<String>,
+    pub tasks: Vec<TaskDescription>,
+    /// Every path read by the condition or any task, sorted.
+    pub reads: Vec<String>,
+    /// Every path written by any task, sorted.
+    pub writes: Vec<String>,
+}
+
+/// Description of one task.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskDescription {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    /// Function name (`map`, `validation`, a custom handler name, …).
+    pub function: String,
+    /// The task condition as infix text, like
+    /// [`WorkflowDescription::condition`]; `None` when it always runs.
+    pub condition: Option<String>,
+    pub continue_on_error: bool,
+    /// One line per mapping, rule or other operation, e.g.
+    /// `data.total = data.net + data.tax`.
+    pub steps: Vec<String>,
+    /// Paths read by the condition and the function, sorted.
+    pub reads: Vec<String>,
+    /// Paths written by the function, sorted.
+    pub writes: Vec<String>,
+}
+
+impl Workflow {
+    /// Describe this workflow's structure. See [`WorkflowDescription`].
+    pub fn describe(&self) -> WorkflowDescription {
+        let tasks: Vec<TaskDescription> = self.tasks.iter().map(describe_task).collect();
+
+        let mut reads = BTreeSet::new();
+        var_paths(&self.condition, &mut reads);
+        let mut writes = BTreeSet::new();
+        for task in &tasks {
+            reads.extend(task.reads.iter().cloned());
+            writes.extend(task.writes.iter().cloned());
+        }
+
+        WorkflowDescription {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            priority: self.priority,
+            channel: self.channel.clone(),
+            status: self.status.clone(),
+            tags: self.tags.clone(),
+            annotations: self.annotations.clone(),
+            condition: render_condition(&self.condition),
+            tasks,
+            reads: reads.into_iter().collect(),
+            writes: writes.into_iter().collect(),
+        }
+    }
+}
+
+fn describe_task(task: &Task) -> TaskDescription {
+    let mut steps = Vec::new();
+    let mut reads = BTreeSet::new();
+    let mut writes = BTreeSet::new();
+    var_paths(&task.condition, &mut reads);
+
+    let function = task.function.function_name();
+    match &task.function {
+        FunctionConfig::Map { input, .. } => {
+            for mapping in &input.mappings {
+                steps.push(format!(
+                    "{} = {}",
+                    mapping.path,
+                    render_logic(&mapping.logic)
+                ));
+                var_paths(&mapping.logic, &mut reads);
+                writes.insert(mapping.path.clone());
+            }
+        }
+        FunctionConfig::Validation { input, .. } => {
+            for rule in &input.rules {
+                steps.push(format!(
+                    "require {}: {}",
+                    render_logic(&rule.logic),
+                    rule.message
+                ));
+                var_paths(&rule.logic, &mut reads);
+            }
+            for aggregate in &input.aggregates {
+                let expression = aggregate.expression();
+                steps.push(match &aggregate.message {
+                    Some(message) => format!("require aggregate {expression}: {message}"),
+                    None => format!("require aggregate {expression}"),
+                });
+                reads.insert(aggregate.array.clone());
+            }
+        }
+        FunctionConfig::Filter { input, .. } => {
+            let otherwise = match input.on_reject {
+                RejectAction::Halt => "halt the workflow",
+                RejectAction::Skip => "skip this task",
+            };
+            steps.push(format!(
+                "continue if {}, otherwise {otherwise}",
+                render_logic(&input.condition)
+            ));
+            var_paths(&input.condition, &mut reads);
+        }
+        FunctionConfig::Switch { input, .. } => {
+            for (idx, case) in input.cases.iter().enumerate() {
+                let label = case.name.clone().unwrap_or_else(|| idx.to_string());
+                let target = match &case.goto {
+                    Some(goto) => format!("go to {goto}"),
+                    None => format!("run {}", case.tasks.join(", ")),
+                };
+                steps.push(format!(
+                    "case {label}: if {}, {target}",
+                    render_logic(&case.when)
+                ));
+                var_paths(&case.when, &mut reads);
+            }
+            if let Some(default) = &input.default {
+                steps.push(match &default.goto {
+                    Some(goto) => format!("otherwise go to {goto}"),
+                    None => format!("otherwise run {}", default.tasks.join(", ")),
+                });
+            }
+            if !input.selection_path.is_empty() {
+                writes.insert(input.selection_path.clone());
+            }
+        }
+        FunctionConfig::Reject { input, .. } => {
+            steps.push(format!(
+                "reject {}: {}",
+                render_logic(&input.code),
+                render_logic(&input.reason)
+            ));
+            var_paths(&input.code, &mut reads);
+            var_paths(&input.reason, &mut reads);
+            reads.extend(input.references.values().cloned());
+            writes.insert(input.target.clone());
+            writes.insert("metadata.rejected".to_string());
+        }
+        FunctionConfig::Score { input, .. } => {
+            for rule in &input.rules {
+                steps.push(format!(
+                    "score {:+} ({}) when {}",
+                    rule.weight,
+                    rule.id,
+                    render_logic(&rule.logic)
+                ));
+                var_paths(&rule.logic, &mut reads);
+            }
+            writes.insert(input.target.clone());
+            writes.insert(input.triggered_target.clone());
+        }
+        FunctionConfig::MapErrors { input, .. } => {
+            steps.push(format!(
+                "{} = map_errors({} mappings)",
+                input.target,
+                input.mappings.len()
+            ));
+            writes.insert(input.target.clone());
+        }
+        FunctionConfig::Join { input, .. } => {
+            let until = match (input.count, input.timeout_ms) {
+                (Some(count), Some(ms)) => format!("{count} messages or {ms} ms"),
+                (Some(count), None) => format!("{count} messages"),
+                (None, Some(ms)) => format!("{ms} ms"),
+                (None, None) => String::new(),
+            };
+            steps.push(format!(
+                "join {} by {} until {until} into {}",
+                input.source,
+                render_logic(&input.key),
+                input.target
+            ));
+            var_paths(&input.key, &mut reads);
+            reads.insert(input.source.clone());
+            writes.insert(input.target.clone());
+            if !input.status_path.is_empty() {
+                writes.insert(input.status_path.clone());
+            }
+        }
+        FunctionConfig::StateGet { input, .. } => {
+            steps.push(format!(
+                "read state {} into {}",
+                render_logic(&input.key),
+                input.target
+            ));
+            var_paths(&input.key, &mut reads);
+            writes.insert(input.target.clone());
+        }
+        FunctionConfig::StateSet { input, .. } => {
+            steps.push(format!(
+                "store {} as state {}",
+                render_logic(&input.value),
+                render_logic(&input.key)
+            ));
+            var_paths(&input.key, &mut reads);
+            var_paths(&input.value, &mut reads);
+        }
+        FunctionConfig::Alert { input, .. } => {
+            steps.push(format!(
+                "raise {:?} alert {}: {}",
+                input.severity,
+                input.code,
+                render_logic(&input.message)
+            ));
+            var_paths(&input.message, &mut reads);
+            for logic in input.fields.values() {
+                var_paths(logic, &mut reads);
+            }
+        }
+        FunctionConfig::Include { input, .. } => {
+            steps.push(format!("include fragment {}", input.fragment));
+        }
+        FunctionConfig::Foreach { input, .. } => {
+            steps.push(format!("for each {} in {}:", input.item, input.path));
+            for nested in input.tasks.iter().map(describe_task) {
+                let condition = nested
+                    .condition
+                    .map(|condition| format!(" if {condition}"))
+                    .unwrap_or_default();
+                steps.push(format!("  {} ({}){condition}", nested.id, nested.function));
+                for step in nested.steps {
+                    steps.push(format!("    {step}"));
+                }
+                reads.extend(nested.reads);
+                writes.extend(nested.writes);
+            }
+            reads.insert(input.path.clone());
+            writes.insert(input.target.clone().unwrap_or_else(|| input.path.clone()));
+        }
+        FunctionConfig::Log { input, .. } => {
+            steps.push(format!("log {}", render_logic(&input.message)));
+            var_paths(&input.message, &mut reads);
+            for logic in input.fields.values() {
+                var_paths(logic, &mut reads);
+            }
+        }
+        FunctionConfig::ParseJson { input, .. } | FunctionConfig::ParseXml { input, .. } => {
+            let target = format!("data.{}", input.target);
+            steps.push(format!("{target} = {function}({})", input.source));
+            reads.insert(input.source.clone());
+            writes.insert(target);
+        }
+        FunctionConfig::PublishJson { input, .. } | FunctionConfig::PublishXml { input, .. } => {
+            let source = if input.source.starts_with("data.") {
+                input.source.clone()
+            } else {
+                format!("data.{}", input.source)
+            };
+            let target = format!("data.{}", input.target);
+            steps.push(format!("{target} = {function}({source})"));
+            reads.insert(source);
+            writes.insert(target);
+        }
+        FunctionConfig::Project { input, .. } => {
+            reads.insert(input.path.clone());
+            writes.insert(input.path.clone());
+        }
+        FunctionConfig::Flatten { input, .. } | FunctionConfig::Unflatten { input, .. } => {
+            let target = input.target.as_ref().unwrap_or(&input.path);
+            steps.push(format!("{target} = {function}({})", input.path));
+            reads.insert(input.path.clone());
+            writes.insert(target.clone());
+        }
+        FunctionConfig::RenameKeys { input, .. } => {
+            reads.insert(input.path.clone());
+            writes.insert(input.path.clone());
+        }
+        FunctionConfig::Sample { input, .. } => {
+            steps.push(format!("{} = sample({})", input.target, input.source));
+            reads.insert(input.source.clone());
+            writes.insert(input.target.clone());
+        }
+        FunctionConfig::HttpCall { input, .. } => {
+            for logic in [&input.path_logic, &input.body_logic].into_iter().flatten() {
+                var_paths(logic, &mut reads);
+            }
+        }
+        FunctionConfig::Enrich { input, .. } => {
+            if let Some(logic) = &input.path_logic {
+                var_paths(logic, &mut reads);
+            }
+            writes.insert(input.merge_path.clone());
+        }
+        FunctionConfig::PublishKafka { input, .. } => {
+            for logic in [&input.key_logic, &input.value_logic].into_iter().flatten() {
+                var_paths(logic, &mut reads);
+            }
+        }
+        FunctionConfig::Custom { .. } => {}
+    }
+    if let Some(output) = &task.output {
+        writes.insert(output.clone());
+    }
+
+    TaskDescription {
+        id: task.id.clone(),
+        name: task.name.clone(),
+        description: task.description.clone(),
+        function: function.to_string(),
+        condition: render_condition(&task.condition),
+        continue_on_error: task.continue_on_error,
+        steps,
+        reads: reads.into_iter().collect(),
+        writes: writes.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn describes_conditions_steps_and_paths() {
+        let workflow = Workflow::from_json(
+            r#"{"id": "orders", "name": "Orders",
+                "condition": {"==": [{"var": "metadata.type"}, "order"]},
+                "tasks": [
+                    {"id": "parse", "name": "Parse",
+                     "function": {"name": "parse_json", "input": {"source": "payload", "target": "order"}}},
+                    {"id": "total", "name": "Total",
+                     "condition": {">": [{"var": "data.order.qty"}, 0]},
+                     "function": {"name": "map", "input": {"mappings": [
+                        {"path": "data.total", "logic": {"*": [{"var": "data.order.qty"}, {"var": "data.order.price"}]}}
+                     ]}}},
+                    {"id": "check", "name": "Check",
+                     "function": {"name": "validation", "input": {"rules": [
+                        {"logic": {">=": [{"var": "data.total"}, 0]}, "message": "negative total"}
+                     ]}}},
+                    {"id": "notify", "name": "Notify", "output": "temp_data.notified",
+                     "function": {"name": "notify", "input": {}}}
+                ]}"#,
+        )
+        .unwrap();
+        let description = workflow.describe();
+
+        assert_eq!(
+            description.condition.as_deref(),
+            Some(r#"metadata.type == "order""#)
+        );
+        assert_eq!(
+            description.tasks[0].steps,
+            ["data.order = parse_json(payload)"]
+        );
+        assert!(description.tasks[0].condition.is_none());
+
+        let total = &description.tasks[1];
+        assert_eq!(total.condition.as_deref(), Some("data.order.qty > 0"));
+        assert_eq!(
+            total.steps,
+            ["data.total = data.order.qty * data.order.price"]
+        );
+        assert_eq!(total.reads, ["data.order.price", "data.order.qty"]);
+        assert_eq!(total.writes, ["data.total"]);
+
+        assert_eq!(
+            description.tasks[2].steps,
+            ["require data.total >= 0: negative total"]
+        );
+        assert_eq!(description.tasks[3].function, "notify");
+        assert_eq!(description.tasks[3].writes, ["temp_data.notified"]);
+
+        assert_eq!(
+            description.reads,
+            [
+                "data.order.price",
+                "data.order.qty",
+                "data.total",
+                "metadata.type",
+                "payload"
+            ]
+        );
+        assert_eq!(
+            description.writes,
+            ["data.order", "data.total", "temp_data.notified"]
+        );
+        assert_eq!(
+            serde_json::to_value(&description).unwrap()["tasks"][1]["function"],
+            json!("map")
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! # Routing Diagnostics
+//!
+//! Per-message record of which workflows the engine considered, what each
+//! workflow condition evaluated to, and why a workflow was skipped. Lets
+//! operators answer "why didn't rule X fire for this message?" without
+//! reconstructing it from debug logs.
+//!
+//! Produced by
+//! [`Engine::process_message_with_diagnostics`](crate::Engine::process_message_with_diagnostics).
+
+use crate::engine::error::DataflowError;
+use crate::engine::logic_text;
+use crate::engine::workflow::Workflow;
+use datavalue::OwnedDataValue;
+use serde::Serialize;
+
+/// Why a workflow did not run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The condition evaluated to something other than `true`.
+    ConditionFalse,
+    /// The condition failed to evaluate; failures count as "not met".
+    ConditionError { error: String },
+    /// Another member of the workflow's variant group was selected.
+    VariantNotSelected { group: String, selected: String },
+}
+
+/// What happened to one workflow for one message.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "decision", rename_all = "snake_case")]
+pub enum WorkflowDecision {
+    /// The condition matched and the tasks ran. `errors` counts the errors
+    /// the workflow added to `message.errors` (non-zero only with
+    /// `continue_on_error`).
+    Executed { errors: usize },
+    /// The condition matched and the workflow stopped the engine with an
+    /// error.
+    Failed { error: String },
+    /// The workflow was not run.
+    Skipped(SkipReason),
+    /// An earlier workflow stopped the engine before this one was reached.
+    NotReached,
+}
+
+/// Routing decision for one workflow, in engine (priority) order.
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkflowDiagnostic {
+    pub workflow_id: String,
+    pub priority: u32,
+    /// The workflow condition as infix text (see
+    /// [`render_logic_pretty`](crate::render_logic_pretty)); `None` when it
+    /// always runs.
+    pub condition: Option<String>,
+    /// Value the workflow condition evaluated to, or `None` when it was not
+    /// evaluated (variant not selected, not reached) or failed.
+    pub condition_value: Option<OwnedDataValue>,
+    pub decision: WorkflowDecision,
+}
+
+impl WorkflowDiagnostic {
+    /// Entry for `workflow` before the engine reached it.
+    pub(crate) fn not_reached(workflow: &Workflow) -> Self {
+        Self {
+            workflow_id: workflow.id.clone(),
+            priority: workflow.priority,
+            condition: logic_text::render_condition(&workflow.condition),
+            condition_value: None,
+            decision: WorkflowDecision::NotReached,
+        }
+    }
+}
+
+/// Routing diagnostics for one message.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RoutingDiagnostics {
+    /// Every workflow the engine considered, in evaluation order.
+    pub workflows: Vec<WorkflowDiagnostic>,
+    /// Error returned by processing, if the engine stopped early. Same
+    /// value `process_message` would have returned.
+    pub error: Option<DataflowError>,
+}
+
+impl RoutingDiagnostics {
+    /// Diagnostic entry for `workflow_id`, if it was considered.
+    pub fn workflow(&self, workflow_id: &str) -> Option<&WorkflowDiagnostic> {
+        self.workflows.iter().find(|w| w.workflow_id == workflow_id)
+    }
+
+    /// Ids of the workflows whose tasks ran (including failed ones).
+    pub fn executed(&self) -> impl Iterator<Item = &str> {
+        self.workflows
+            .iter()
+            .filter(|w| {
+                matches!(
+                    w.decision,
+                    WorkflowDecision::Executed { .. } | WorkflowDecision::Failed { .. }
+                )
+            })
+            .map(|w| w.workflow_id.as_str())
+    }
+
+    /// Skipped workflows with their reasons.
+    pub fn skipped(&self) -> impl Iterator<Item = (&str, &SkipReason)> {
+        self.workflows.iter().filter_map(|w| match &w.decision {
+            WorkflowDecision::Skipped(reason) => Some((w.workflow_id.as_str(), reason)),
+            _ => None,
+        })
+    }
+}
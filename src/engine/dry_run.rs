@@ -0,0 +1,119 @@
+//! # Dry Runs
+//!
+//! [`Engine::dry_run`](crate::Engine::dry_run) previews what the engine
+//! would do with a message — which conditions pass, what every mapping
+//! writes, which rules fail — without touching the message or anything
+//! outside the engine, so workflow edits can be tried against production
+//! samples.
+//!
+//! The run works on a copy of the message with change capture on. Only
+//! built-ins that transform the message run: `map`, `validation`, the
+//! parse and publish functions, `filter`, `log`, `project`, `flatten`,
+//! `unflatten`, `rename_keys`, `sample`, `switch`, `reject`, `score`,
+//! `map_errors`, and `foreach` loops made of these. Every other task whose condition passes —
+//! custom handlers, `http_call`, `enrich`, `publish_kafka`, `state_get`,
+//! `state_set`, `join`, `alert` — is held back and listed in
+//! [`DryRunReport::held_back`]; later tasks see the message without its
+//! output. Idempotency keys are not claimed, rate limits are not applied
+//! and no metrics are recorded. Processing hooks still run.
+
+use crate::engine::error::{DataflowError, ErrorInfo};
+use crate::engine::functions::FunctionConfig;
+use crate::engine::message::{AuditTrail, Message};
+use serde::Serialize;
+
+/// A task a dry run did not run because it may have side effects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeldBackTask {
+    pub workflow_id: String,
+    pub task_id: String,
+    /// Name of the task's function, e.g. `http_call`.
+    pub function: String,
+}
+
+/// What processing a message would do. Returned by
+/// [`Engine::dry_run`](crate::Engine::dry_run).
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunReport {
+    /// The message as processing would leave it.
+    pub message: Message,
+    /// One entry per task that ran, in order, with the changes it made.
+    pub changes: Vec<AuditTrail>,
+    /// Tasks that would have run but were held back.
+    pub held_back: Vec<HeldBackTask>,
+    /// The error that would have stopped processing, if any. Errors of
+    /// workflows that continue on error are in the message's errors.
+    pub error: Option<DataflowError>,
+}
+
+impl DryRunReport {
+    /// Build the report from the processed copy; `audit_start` is the
+    /// length of its audit trail before the run.
+    pub(crate) fn new(
+        mut message: Message,
+        audit_start: usize,
+        error: Option<DataflowError>,
+    ) -> Self {
+        let held_back = message.held_back.take().unwrap_or_default();
+        Self {
+            changes: message.audit_trail[audit_start..].to_vec(),
+            message,
+            held_back,
+            error,
+        }
+    }
+
+    /// Errors the run recorded on the message.
+    pub fn errors(&self) -> &[ErrorInfo] {
+        self.message.errors()
+    }
+
+    /// Whether processing would succeed without recording an error.
+    pub fn is_clean(&self) -> bool {
+        self.error.is_none() && self.message.errors().is_empty()
+    }
+}
+
+/// Whether a task running `function` runs in a dry run.
+pub(crate) fn runs_in_dry_run(function: &FunctionConfig) -> bool {
+    match function {
+        FunctionConfig::Foreach { input, .. } => input
+            .tasks
+            .iter()
+            .all(|task| runs_in_dry_run(&task.function)),
+        function => function.is_sync_builtin(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn function(value: serde_json::Value) -> FunctionConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn only_message_transformations_run() {
+        assert!(runs_in_dry_run(&function(
+            json!({"name": "map", "input": {"mappings": []}})
+        )));
+        assert!(!runs_in_dry_run(&function(
+            json!({"name": "notify", "input": {}})
+        )));
+        let foreach = |body: serde_json::Value| {
+            function(
+                json!({"name": "foreach", "input": {"path": "data.items", "tasks": [
+                    {"id": "t", "name": "T", "function": body}
+                ]}}),
+            )
+        };
+        assert!(runs_in_dry_run(&foreach(
+            json!({"name": "map", "input": {"mappings": []}})
+        )));
+        assert!(!runs_in_dry_run(&foreach(
+            json!({"name": "notify", "input": {}})
+        )));
+    }
+}
@@ -0,0 +1,209 @@
+//! # Embedded Workflow Tests
+//!
+//! Test cases shipped inside a workflow definition, so mapping authors can
+//! pin down a workflow's behaviour without writing Rust. Each case gives
+//! the message to run and what to expect afterwards:
+//!
+//! ```json
+//! {"id": "totals", "tasks": [...], "tests": [
+//!     {"name": "adds tax",
+//!      "payload": {"amount": 100},
+//!      "expect": {"data": {"total": 110}}},
+//!     {"name": "rejects negative amounts",
+//!      "data": {"amount": -1},
+//!      "expect": {"errors": ["VALIDATION_ERROR"]}}
+//! ]}
+//! ```
+//!
+//! [`Engine::run_workflow_tests`](crate::Engine::run_workflow_tests) runs
+//! every case through its own workflow only, condition included, and
+//! returns a [`WorkflowTestReport`]. Expected `data` and `temp_data` are
+//! matched as subsets: every key given must be present with an equal value
+//! (numbers compare by value, arrays element by element), and other keys
+//! are ignored. Each code in `errors` must be among the message's error
+//! codes; with no `errors`, the run must produce none.
+
+use crate::engine::error::Result;
+use crate::engine::message::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One test case of a workflow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkflowTestCase {
+    pub name: String,
+    /// Message payload. Default: `{}`.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub payload: Value,
+    /// Initial `data`. Default: `{}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    /// Initial `metadata`. Default: `{}`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    #[serde(default)]
+    pub expect: WorkflowTestExpectations,
+}
+
+/// What a test case expects after its run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkflowTestExpectations {
+    /// Subset of `data` that must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    /// Subset of `temp_data` that must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_data: Option<Value>,
+    /// Error codes that must be recorded. Empty: no errors allowed.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// Outcome of one test case.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkflowTestResult {
+    pub workflow_id: String,
+    pub test: String,
+    /// Why the case failed, one entry per unmet expectation. Empty when it
+    /// passed.
+    pub failures: Vec<String>,
+}
+
+impl WorkflowTestResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Outcome of every test case of an engine's workflows, in engine order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WorkflowTestReport {
+    pub results: Vec<WorkflowTestResult>,
+}
+
+impl WorkflowTestReport {
+    /// Whether every case passed.
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(WorkflowTestResult::passed)
+    }
+
+    /// The cases that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &WorkflowTestResult> {
+        self.results.iter().filter(|result| !result.passed())
+    }
+}
+
+impl WorkflowTestCase {
+    /// The message this case runs. Fails when `data` or `metadata` is not
+    /// an object.
+    pub(crate) fn message(&self) -> Result<Message> {
+        let payload = match &self.payload {
+            Value::Null => Value::Object(Default::default()),
+            payload => payload.clone(),
+        };
+        let mut builder = Message::builder().payload_json(&payload);
+        if let Some(data) = &self.data {
+            builder = builder.data_json(data);
+        }
+        if let Some(metadata) = &self.metadata {
+            builder = builder.metadata_json(metadata);
+        }
+        builder.try_build()
+    }
+
+    /// Unmet expectations of this case after `message` ran.
+    pub(crate) fn check(&self, message: &Message) -> Vec<String> {
+        let mut failures = Vec::new();
+        for (section, expected, actual) in [
+            ("data", &self.expect.data, message.data()),
+            ("temp_data", &self.expect.temp_data, message.temp_data()),
+        ] {
+            if let Some(expected) = expected {
+                let actual = serde_json::to_value(actual).unwrap_or(Value::Null);
+                compare(section, expected, &actual, &mut failures);
+            }
+        }
+        let codes: Vec<&str> = message.errors().iter().map(|e| e.code.as_str()).collect();
+        if self.expect.errors.is_empty() {
+            if !codes.is_empty() {
+                failures.push(format!("expected no errors, got {}", codes.join(", ")));
+            }
+        } else {
+            for code in &self.expect.errors {
+                if !codes.contains(&code.as_str()) {
+                    failures.push(format!(
+                        "expected error {}, got {}",
+                        code,
+                        if codes.is_empty() {
+                            "none".to_string()
+                        } else {
+                            codes.join(", ")
+                        }
+                    ));
+                }
+            }
+        }
+        failures
+    }
+}
+
+/// Record in `failures` where `actual` at `path` doesn't match `expected`.
+fn compare(path: &str, expected: &Value, actual: &Value, failures: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, expected) in expected {
+                let path = format!("{path}.{key}");
+                match actual.get(key) {
+                    Some(actual) => compare(&path, expected, actual, failures),
+                    None => failures.push(format!("{path}: expected {expected}, but it is absent")),
+                }
+            }
+        }
+        (Value::Array(expected_items), Value::Array(actual_items))
+            if expected_items.len() == actual_items.len() =>
+        {
+            for (idx, (expected, actual)) in expected_items.iter().zip(actual_items).enumerate() {
+                compare(&format!("{path}.{idx}"), expected, actual, failures);
+            }
+        }
+        (Value::Number(a), Value::Number(b)) if a.as_f64() == b.as_f64() => {}
+        _ if expected == actual => {}
+        _ => failures.push(format!("{path}: expected {expected}, got {actual}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn expectations_match_subsets_of_data_and_error_codes() {
+        let case: WorkflowTestCase = serde_json::from_value(json!({
+            "name": "totals",
+            "data": {"total": 110.0, "items": [1, 2], "extra": true},
+            "expect": {"data": {"total": 110, "items": [1, 3], "tax": 10}, "errors": ["X"]}
+        }))
+        .unwrap();
+        let message = case.message().unwrap();
+        assert_eq!(
+            case.check(&message),
+            vec![
+                "data.items.1: expected 3, got 2",
+                "data.tax: expected 10, but it is absent",
+                "expected error X, got none",
+            ]
+        );
+
+        let passing = WorkflowTestCase {
+            expect: WorkflowTestExpectations {
+                data: Some(json!({"total": 110, "items": [1, 2]})),
+                ..Default::default()
+            },
+            ..case
+        };
+        assert!(passing.check(&message).is_empty());
+    }
+}
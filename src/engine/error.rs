@@ -1,5 +1,7 @@
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 /// Main error type for the dataflow engine
@@ -50,6 +52,31 @@ pub enum DataflowError {
     #[error("Timeout error: {0}")]
     Timeout(String),
 
+    /// Processing stopped because the message's cancellation token fired
+    #[error("Cancelled: {0}")]
+    Cancelled(String),
+
+    /// The engine is draining and no longer accepts messages
+    #[error("Engine draining: {0}")]
+    Draining(String),
+
+    /// Message diverted to the quarantine sink after repeated failures
+    #[error("Message quarantined: {0}")]
+    Quarantined(String),
+
+    /// Message handed to the dead-letter handler
+    #[error("Message dead-lettered: {0}")]
+    DeadLettered(String),
+
+    /// Call refused because the function's circuit breaker is open
+    #[error("Circuit open: {0}")]
+    CircuitOpen(String),
+
+    /// A workflow, manifest or other configuration failed to load or
+    /// validate, with where the problem is
+    #[error("Configuration error: {0}")]
+    Config(Box<ConfigError>),
+
     /// Any other errors
     #[error("Unknown error: {0}")]
     Unknown(String),
@@ -82,6 +109,14 @@ impl DataflowError {
         DataflowError::Deserialization(err.to_string())
     }
 
+    /// The [`ConfigError`] of a configuration error.
+    pub fn config(&self) -> Option<&ConfigError> {
+        match self {
+            DataflowError::Config(err) => Some(err),
+            _ => None,
+        }
+    }
+
     /// Determines if this error is retryable (worth retrying)
     ///
     /// Retryable errors are typically transient infrastructure failures that might succeed on retry.
@@ -97,6 +132,10 @@ impl DataflowError {
             }
             DataflowError::Timeout(_) => true,
             DataflowError::Io(_) => true,
+            // The message was never touched; another instance can take it
+            DataflowError::Draining(_) => true,
+            // The handler was not called; the dependency may recover
+            DataflowError::CircuitOpen(_) => true,
             DataflowError::FunctionExecution { source, .. } => {
                 // Inherit retryability from the source error if present
                 source.as_ref().map(|e| e.retryable()).unwrap_or(false)
@@ -109,6 +148,10 @@ impl DataflowError {
             DataflowError::Workflow(_) => false,
             DataflowError::Task(_) => false,
             DataflowError::FunctionNotFound(_) => false,
+            DataflowError::Cancelled(_) => false,
+            DataflowError::Quarantined(_) => false,
+            DataflowError::DeadLettered(_) => false,
+            DataflowError::Config(_) => false,
             DataflowError::Unknown(_) => false,
         }
     }
@@ -117,6 +160,165 @@ impl DataflowError {
 /// Type alias for Result with DataflowError
 pub type Result<T> = std::result::Result<T, DataflowError>;
 
+/// A configuration problem and where it is: the file, workflow and task,
+/// and a JSON pointer ([RFC 6901]) into the workflow document, e.g.
+/// `/tasks/3/function/input`. Each location is `None` when unknown or not
+/// applicable. Pointers address tasks after `include` entries have been
+/// expanded when the error comes from engine construction.
+///
+/// [RFC 6901]: https://www.rfc-editor.org/rfc/rfc6901
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigError {
+    pub file: Option<PathBuf>,
+    pub workflow_id: Option<String>,
+    pub task_id: Option<String>,
+    pub json_pointer: Option<String>,
+    pub kind: ConfigErrorKind,
+}
+
+/// What is wrong with a configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "message", rename_all = "snake_case")]
+pub enum ConfigErrorKind {
+    /// The file or directory could not be read.
+    Io(String),
+    /// The document is not valid JSON.
+    Syntax(String),
+    /// The JSON doesn't have the expected shape: a missing field, a value
+    /// of the wrong type.
+    Parse(String),
+    /// Keys rejected by strict parsing.
+    UnknownKeys(String),
+    /// The configuration parsed but is not valid, e.g. a duplicate id.
+    Invalid(String),
+    /// A JSONLogic expression failed to compile.
+    Logic(String),
+}
+
+impl ConfigErrorKind {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Io(message)
+            | Self::Syntax(message)
+            | Self::Parse(message)
+            | Self::UnknownKeys(message)
+            | Self::Invalid(message)
+            | Self::Logic(message) => message,
+        }
+    }
+}
+
+impl ConfigError {
+    /// Error of `kind` with no location yet.
+    pub fn new(kind: ConfigErrorKind) -> Self {
+        Self {
+            file: None,
+            workflow_id: None,
+            task_id: None,
+            json_pointer: None,
+            kind,
+        }
+    }
+
+    /// Set the file, unless already known.
+    pub fn in_file(mut self, path: &Path) -> Self {
+        self.file.get_or_insert_with(|| path.to_path_buf());
+        self
+    }
+
+    /// Set the workflow, unless already known or `id` is empty.
+    pub fn in_workflow(mut self, id: &str) -> Self {
+        if self.workflow_id.is_none() && !id.is_empty() {
+            self.workflow_id = Some(id.to_string());
+        }
+        self
+    }
+
+    /// Set the task, unless already known or `id` is empty.
+    pub fn in_task(mut self, id: &str) -> Self {
+        if self.task_id.is_none() && !id.is_empty() {
+            self.task_id = Some(id.to_string());
+        }
+        self
+    }
+
+    /// Prefix the pointer with `pointer`, for an error located inside the
+    /// value found there.
+    pub fn within(mut self, pointer: &str) -> Self {
+        let rest = self.json_pointer.take().unwrap_or_default();
+        self.json_pointer = Some(format!("{pointer}{rest}"));
+        self
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kind.message())?;
+        let mut at = Vec::new();
+        if let Some(file) = &self.file {
+            at.push(format!("file {}", file.display()));
+        }
+        if let Some(id) = &self.workflow_id {
+            at.push(format!("workflow '{id}'"));
+        }
+        if let Some(id) = &self.task_id {
+            at.push(format!("task '{id}'"));
+        }
+        if let Some(pointer) = self.json_pointer.as_deref().filter(|p| !p.is_empty()) {
+            at.push(format!("at {pointer}"));
+        }
+        if !at.is_empty() {
+            write!(f, " ({})", at.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ConfigError> for DataflowError {
+    fn from(err: ConfigError) -> Self {
+        DataflowError::Config(Box::new(err))
+    }
+}
+
+/// Turn an error raised while loading or validating configuration into a
+/// [`DataflowError::Config`] and apply `at` to its location. Errors that
+/// are not about configuration, such as `FunctionNotFound`, pass through.
+pub(crate) fn locate(
+    err: DataflowError,
+    at: impl FnOnce(ConfigError) -> ConfigError,
+) -> DataflowError {
+    let config = match err {
+        DataflowError::Config(config) => *config,
+        DataflowError::Io(message) => ConfigError::new(ConfigErrorKind::Io(message)),
+        DataflowError::Deserialization(message) => {
+            ConfigError::new(ConfigErrorKind::Parse(message))
+        }
+        DataflowError::Workflow(message) | DataflowError::Validation(message) => {
+            ConfigError::new(ConfigErrorKind::Invalid(message))
+        }
+        DataflowError::LogicEvaluation(message) => {
+            ConfigError::new(ConfigErrorKind::Logic(message))
+        }
+        other => return other,
+    };
+    at(config).into()
+}
+
+/// A [`ConfigErrorKind::Syntax`] or [`ConfigErrorKind::Parse`] error for
+/// a document that failed to parse with `err`.
+pub(crate) fn parse_failure(err: serde_json::Error) -> ConfigError {
+    if err.is_syntax() || err.is_eof() {
+        ConfigError::new(ConfigErrorKind::Syntax(err.to_string()))
+    } else {
+        ConfigError::new(ConfigErrorKind::Parse(err.to_string()))
+    }
+}
+
+/// A [`ConfigErrorKind::Invalid`] error with no location yet.
+pub(crate) fn invalid(message: impl Into<String>) -> ConfigError {
+    ConfigError::new(ConfigErrorKind::Invalid(message.into()))
+}
+
 /// Structured error information for error tracking in messages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ErrorInfo {
@@ -165,6 +367,12 @@ impl ErrorInfo {
                 DataflowError::Timeout(_) => "TIMEOUT_ERROR".to_string(),
                 DataflowError::Io(_) => "IO_ERROR".to_string(),
                 DataflowError::Deserialization(_) => "DESERIALIZATION_ERROR".to_string(),
+                DataflowError::Cancelled(_) => "CANCELLED".to_string(),
+                DataflowError::Draining(_) => "DRAINING".to_string(),
+                DataflowError::Quarantined(_) => "QUARANTINED".to_string(),
+                DataflowError::DeadLettered(_) => "DEAD_LETTERED".to_string(),
+                DataflowError::CircuitOpen(_) => "CIRCUIT_OPEN".to_string(),
+                DataflowError::Config(_) => "CONFIG_ERROR".to_string(),
                 DataflowError::Unknown(_) => "UNKNOWN_ERROR".to_string(),
             },
             message: error.to_string(),
@@ -347,6 +555,8 @@ mod tests {
             .retryable()
         );
         assert!(DataflowError::Timeout("Connection timeout".to_string()).retryable());
+        assert!(DataflowError::Draining("shutting down".to_string()).retryable());
+        assert!(DataflowError::CircuitOpen("api".to_string()).retryable());
         assert!(DataflowError::Io("Network error".to_string()).retryable());
     }
 
@@ -385,6 +595,9 @@ mod tests {
         assert!(!DataflowError::LogicEvaluation("Invalid logic".to_string()).retryable());
         assert!(!DataflowError::Deserialization("Invalid JSON".to_string()).retryable());
         assert!(!DataflowError::Workflow("Invalid workflow".to_string()).retryable());
+        assert!(!DataflowError::Quarantined("poison".to_string()).retryable());
+        assert!(!DataflowError::DeadLettered("poison".to_string()).retryable());
+        assert!(!DataflowError::Cancelled("shutdown".to_string()).retryable());
         assert!(!DataflowError::Unknown("Unknown error".to_string()).retryable());
     }
 
@@ -467,6 +680,20 @@ mod tests {
                 DataflowError::Deserialization("test".to_string()),
                 "DESERIALIZATION_ERROR",
             ),
+            (DataflowError::Cancelled("test".to_string()), "CANCELLED"),
+            (DataflowError::Draining("test".to_string()), "DRAINING"),
+            (
+                DataflowError::Quarantined("test".to_string()),
+                "QUARANTINED",
+            ),
+            (
+                DataflowError::DeadLettered("test".to_string()),
+                "DEAD_LETTERED",
+            ),
+            (
+                DataflowError::CircuitOpen("test".to_string()),
+                "CIRCUIT_OPEN",
+            ),
             (DataflowError::Unknown("test".to_string()), "UNKNOWN_ERROR"),
         ];
 
@@ -555,6 +782,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn config_errors_display_their_location() {
+        let err = locate(
+            DataflowError::Workflow("Duplicate task ID 'c'".into()),
+            |at| {
+                at.in_task("c")
+                    .within("/tasks/1")
+                    .within("/function/input")
+                    .in_workflow("orders")
+                    .in_task("outer")
+            },
+        );
+        let config = err.config().unwrap();
+        assert_eq!(config.task_id.as_deref(), Some("c"));
+        assert_eq!(
+            config.json_pointer.as_deref(),
+            Some("/function/input/tasks/1")
+        );
+        assert_eq!(
+            err.to_string(),
+            "Configuration error: Duplicate task ID 'c' (workflow 'orders', task 'c', at /function/input/tasks/1)"
+        );
+        assert!(!err.retryable());
+
+        let other = locate(DataflowError::FunctionNotFound("f".into()), |at| at);
+        assert!(matches!(other, DataflowError::FunctionNotFound(_)));
+    }
+
     #[test]
     fn test_error_conversions() {
         // Test from_serde (we can't easily create a real serde error, but we can test the conversion works)
@@ -0,0 +1,189 @@
+//! # Execution Plans
+//!
+//! [`Engine::explain`](crate::Engine::explain) answers "which workflows and
+//! tasks would run for this message?" without running anything: it
+//! evaluates every workflow condition, in engine order, and the task
+//! conditions of each workflow that would run, and reports the results.
+//!
+//! Nothing is executed, so every condition sees the message as given.
+//! A task condition that reads a value an earlier task writes is evaluated
+//! without it; use [`Engine::dry_run`](crate::Engine::dry_run) when the
+//! answer depends on earlier tasks. Variant groups are drawn as
+//! processing would draw them, and workflow annotations and locale are
+//! visible to the conditions.
+
+use crate::engine::diagnostics::SkipReason;
+use crate::engine::executor::eval_to_owned;
+use crate::engine::logic_text::render_condition;
+use crate::engine::message::Message;
+use crate::engine::task::Task;
+use crate::engine::workflow::Workflow;
+use datalogic_rs::{Engine as DatalogicEngine, Logic};
+use datavalue::OwnedDataValue;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Whether one task would run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskPlan {
+    pub task_id: String,
+    /// Name of the task's function, e.g. `map`.
+    pub function: String,
+    /// The task condition as infix text; `None` when it always runs.
+    pub condition: Option<String>,
+    /// Value the condition evaluated to; `None` when it always runs or
+    /// failed to evaluate.
+    pub condition_value: Option<OwnedDataValue>,
+    /// Why the task would not run; `None` when it would.
+    pub skipped: Option<SkipReason>,
+}
+
+impl TaskPlan {
+    /// Whether the task would run.
+    pub fn runs(&self) -> bool {
+        self.skipped.is_none()
+    }
+}
+
+/// Whether one workflow would run, and its tasks' plans if so.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkflowPlan {
+    pub workflow_id: String,
+    pub priority: u32,
+    /// The workflow condition as infix text; `None` when it always runs.
+    pub condition: Option<String>,
+    /// Value the condition evaluated to; `None` when it always runs, was
+    /// not evaluated (variant not selected) or failed to evaluate.
+    pub condition_value: Option<OwnedDataValue>,
+    /// Why the workflow would not run; `None` when it would.
+    pub skipped: Option<SkipReason>,
+    /// One entry per task, in order; empty when the workflow would not run.
+    pub tasks: Vec<TaskPlan>,
+}
+
+impl WorkflowPlan {
+    /// Whether the workflow would run.
+    pub fn runs(&self) -> bool {
+        self.skipped.is_none()
+    }
+}
+
+/// Which workflows and tasks would run for a message. Returned by
+/// [`Engine::explain`](crate::Engine::explain).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ExecutionPlan {
+    /// Every workflow of the engine, in execution order.
+    pub workflows: Vec<WorkflowPlan>,
+}
+
+impl ExecutionPlan {
+    /// Plan entry for `workflow_id`.
+    pub fn workflow(&self, workflow_id: &str) -> Option<&WorkflowPlan> {
+        self.workflows.iter().find(|w| w.workflow_id == workflow_id)
+    }
+
+    /// `(workflow_id, task_id)` of every task that would run, in order.
+    pub fn tasks_to_run(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.workflows.iter().flat_map(|workflow| {
+            workflow
+                .tasks
+                .iter()
+                .filter(|task| task.runs())
+                .map(|task| (workflow.workflow_id.as_str(), task.task_id.as_str()))
+        })
+    }
+}
+
+/// Plan for a workflow outside its variant group's draw.
+pub(crate) fn variant_not_selected(workflow: &Workflow, selected: String) -> WorkflowPlan {
+    WorkflowPlan {
+        workflow_id: workflow.id.clone(),
+        priority: workflow.priority,
+        condition: render_condition(&workflow.condition),
+        condition_value: None,
+        skipped: Some(SkipReason::VariantNotSelected {
+            group: workflow
+                .variant
+                .as_ref()
+                .map(|v| v.group.clone())
+                .unwrap_or_default(),
+            selected,
+        }),
+        tasks: Vec::new(),
+    }
+}
+
+/// Plan `workflow` against `message`, whose context already carries the
+/// workflow's annotations and locale.
+pub(crate) fn plan_workflow(
+    engine: &DatalogicEngine,
+    workflow: &Workflow,
+    message: &Message,
+) -> WorkflowPlan {
+    let (condition_value, skipped) = evaluate(
+        engine,
+        &workflow.condition,
+        workflow.compiled_condition.as_ref(),
+        message,
+    );
+    let tasks = match skipped {
+        Some(_) => Vec::new(),
+        None => workflow
+            .tasks
+            .iter()
+            .map(|task| plan_task(engine, task, message))
+            .collect(),
+    };
+    WorkflowPlan {
+        workflow_id: workflow.id.clone(),
+        priority: workflow.priority,
+        condition: render_condition(&workflow.condition),
+        condition_value,
+        skipped,
+        tasks,
+    }
+}
+
+fn plan_task(engine: &DatalogicEngine, task: &Task, message: &Message) -> TaskPlan {
+    let (condition_value, skipped) = evaluate(
+        engine,
+        &task.condition,
+        task.compiled_condition.as_ref(),
+        message,
+    );
+    TaskPlan {
+        task_id: task.id.clone(),
+        function: task.function.function_name().to_string(),
+        condition: render_condition(&task.condition),
+        condition_value,
+        skipped,
+    }
+}
+
+/// Evaluate a condition the way processing does: only `true` runs, and
+/// an evaluation error skips.
+fn evaluate(
+    engine: &DatalogicEngine,
+    condition: &Value,
+    compiled: Option<&Arc<Logic>>,
+    message: &Message,
+) -> (Option<OwnedDataValue>, Option<SkipReason>) {
+    let Some(compiled) = compiled else {
+        return (None, None);
+    };
+    match eval_to_owned(engine, compiled, &message.context) {
+        Ok(value) => {
+            let skipped =
+                (value != OwnedDataValue::Bool(true)).then_some(SkipReason::ConditionFalse);
+            let value = (*condition != Value::Bool(true)).then_some(value);
+            (value, skipped)
+        }
+        Err(e) => (
+            None,
+            Some(SkipReason::ConditionError {
+                error: e.to_string(),
+            }),
+        ),
+    }
+}
@@ -0,0 +1,171 @@
+//! # Expression Cache
+//!
+//! JSONLogic evaluated ad hoc — through [`Engine::evaluate`](crate::Engine::evaluate),
+//! or by custom functions through [`TaskContext::evaluate`](crate::TaskContext::evaluate)
+//! — is compiled once and kept in a bounded least-recently-used cache, so
+//! editor previews and dynamic expressions don't recompile on every call.
+//!
+//! Entries are keyed by the SHA-256 of the expression's canonical JSON, so
+//! the same expression written with different key order or whitespace
+//! shares an entry. The capacity is
+//! [`EngineOptions::expression_cache_size`](crate::EngineOptions::expression_cache_size)
+//! (256 by default; 0 turns caching off). The cache belongs to the engine
+//! and carries over to engines produced by `with_new_workflows`. Workflow
+//! expressions are compiled at construction and never go through it.
+
+use crate::engine::audit::{canonical_json, sha256_hex};
+use crate::engine::error::{DataflowError, Result};
+use datalogic_rs::{Engine as DatalogicEngine, Logic};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Default [`EngineOptions::expression_cache_size`](crate::EngineOptions::expression_cache_size).
+pub const DEFAULT_EXPRESSION_CACHE_SIZE: usize = 256;
+
+/// Bounded LRU cache of compiled ad-hoc expressions.
+pub(crate) struct ExpressionCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Compiled expression and the tick it was last used at, by content
+    /// hash.
+    map: HashMap<String, (Arc<Logic>, u64)>,
+    tick: u64,
+}
+
+/// Counters of an engine's expression cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExpressionCacheStats {
+    /// Lookups answered from the cache.
+    pub hits: u64,
+    /// Lookups that compiled the expression.
+    pub misses: u64,
+    /// Expressions currently cached.
+    pub size: usize,
+    /// Most expressions kept.
+    pub capacity: usize,
+}
+
+impl ExpressionCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// The compiled form of `logic`, compiling it with `engine` on a miss.
+    pub(crate) fn get_or_compile(
+        &self,
+        logic: &Value,
+        engine: &DatalogicEngine,
+    ) -> Result<Arc<Logic>> {
+        if self.capacity == 0 {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return compile(logic, engine);
+        }
+        let key = sha256_hex(canonical_json(logic).as_bytes());
+        {
+            let mut entries = self.entries();
+            entries.tick += 1;
+            let tick = entries.tick;
+            if let Some((compiled, used)) = entries.map.get_mut(&key) {
+                *used = tick;
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Arc::clone(compiled));
+            }
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        // Compile outside the lock; a concurrent miss on the same
+        // expression compiles it twice and keeps one.
+        let compiled = compile(logic, engine)?;
+        let mut entries = self.entries();
+        if entries.map.len() >= self.capacity
+            && !entries.map.contains_key(&key)
+            && let Some(oldest) = entries
+                .map
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(key, _)| key.clone())
+        {
+            entries.map.remove(&oldest);
+        }
+        entries.tick += 1;
+        let tick = entries.tick;
+        entries.map.insert(key, (Arc::clone(&compiled), tick));
+        Ok(compiled)
+    }
+
+    pub(crate) fn stats(&self) -> ExpressionCacheStats {
+        ExpressionCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: self.entries().map.len(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn compile(logic: &Value, engine: &DatalogicEngine) -> Result<Arc<Logic>> {
+    engine
+        .compile_arc(logic)
+        .map_err(|e| DataflowError::LogicEvaluation(format!("ad-hoc expression: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reuses_entries_and_evicts_the_least_recently_used() {
+        let engine = DatalogicEngine::new();
+        let cache = ExpressionCache::new(2);
+        let a = json!({"+": [1, 2]});
+        let b = json!({"var": "x"});
+
+        let first = cache.get_or_compile(&a, &engine).unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &cache.get_or_compile(&a, &engine).unwrap()
+        ));
+        cache.get_or_compile(&b, &engine).unwrap();
+        cache.get_or_compile(&a, &engine).unwrap();
+        // `b` is now the least recently used and makes room for `c`.
+        cache.get_or_compile(&json!({"var": "c"}), &engine).unwrap();
+        assert!(Arc::ptr_eq(
+            &first,
+            &cache.get_or_compile(&a, &engine).unwrap()
+        ));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.size), (3, 3, 2));
+        cache.get_or_compile(&b, &engine).unwrap();
+        assert_eq!(cache.stats().misses, 4);
+    }
+
+    #[test]
+    fn capacity_zero_compiles_every_time() {
+        let engine = DatalogicEngine::new();
+        let cache = ExpressionCache::new(0);
+        let logic = json!({"==": [1, 1]});
+        cache.get_or_compile(&logic, &engine).unwrap();
+        cache.get_or_compile(&logic, &engine).unwrap();
+        assert_eq!(cache.stats().misses, 2);
+        assert_eq!(cache.stats().size, 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! # Message extensions
+//!
+//! A typed, message-scoped scratch store keyed by Rust type. Custom handlers
+//! use it to hand non-JSON intermediate state (parsed structs, lookup
+//! results, decoded documents) to later tasks of the same message without
+//! serializing through `temp_data`.
+//!
+//! Extensions are in-memory only: they are never serialized, never visible to
+//! JSONLogic, and never recorded in the audit trail.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Object-safe `Any + Clone` so `Extensions` (and therefore `Message`) can
+/// stay `Clone`.
+trait AnyClone: Any + Send + Sync {
+    fn clone_box(&self) -> Box<dyn AnyClone>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + Send + Sync + 'static> AnyClone for T {
+    fn clone_box(&self) -> Box<dyn AnyClone> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Clone for Box<dyn AnyClone> {
+    fn clone(&self) -> Self {
+        (**self).clone_box()
+    }
+}
+
+/// Type-keyed map holding at most one value per type.
+///
+/// The backing map does not allocate until the first insert, so messages
+/// that never touch extensions pay nothing beyond an empty `HashMap`.
+///
+/// ```
+/// use dataflow_rs::Extensions;
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// struct Parsed { amount: u64 }
+///
+/// let mut ext = Extensions::new();
+/// ext.insert(Parsed { amount: 42 });
+/// assert_eq!(ext.get::<Parsed>(), Some(&Parsed { amount: 42 }));
+/// ```
+#[derive(Clone, Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn AnyClone>>,
+}
+
+impl Extensions {
+    /// Create an empty store. Does not allocate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a value, returning the previous value of the same type if any.
+    pub fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|boxed| boxed.into_any().downcast::<T>().ok())
+            .map(|boxed| *boxed)
+    }
+
+    /// Borrow the value of type `T`, if present.
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|boxed| (**boxed).as_any().downcast_ref())
+    }
+
+    /// Mutably borrow the value of type `T`, if present.
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|boxed| (**boxed).as_any_mut().downcast_mut())
+    }
+
+    /// Return the value of type `T`, inserting the result of `f` first if
+    /// absent.
+    pub fn get_or_insert_with<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        f: impl FnOnce() -> T,
+    ) -> &mut T {
+        let boxed = self
+            .map
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(f()));
+        (**boxed)
+            .as_any_mut()
+            .downcast_mut()
+            .expect("extension stored under mismatched TypeId")
+    }
+
+    /// Remove and return the value of type `T`, if present.
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|boxed| boxed.into_any().downcast::<T>().ok())
+            .map(|boxed| *boxed)
+    }
+
+    /// Whether a value of type `T` is present.
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Number of stored values.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Drop every stored value.
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Parsed {
+        amount: u64,
+    }
+
+    #[test]
+    fn insert_get_remove_round_trip() {
+        let mut ext = Extensions::new();
+        assert!(ext.is_empty());
+        assert_eq!(ext.insert(Parsed { amount: 1 }), None);
+        assert_eq!(ext.insert(7u32), None);
+        assert_eq!(ext.len(), 2);
+
+        assert_eq!(ext.insert(Parsed { amount: 2 }), Some(Parsed { amount: 1 }));
+        ext.get_mut::<Parsed>().unwrap().amount += 1;
+        assert_eq!(ext.get::<Parsed>(), Some(&Parsed { amount: 3 }));
+
+        assert_eq!(ext.remove::<u32>(), Some(7));
+        assert!(!ext.contains::<u32>());
+        assert_eq!(ext.len(), 1);
+    }
+
+    #[test]
+    fn get_or_insert_with_only_initializes_once() {
+        let mut ext = Extensions::new();
+        ext.get_or_insert_with(Vec::<u8>::new).push(1);
+        ext.get_or_insert_with(|| vec![9u8]).push(2);
+        assert_eq!(ext.get::<Vec<u8>>(), Some(&vec![1, 2]));
+    }
+
+    #[test]
+    fn clone_is_deep() {
+        let mut ext = Extensions::new();
+        ext.insert(Parsed { amount: 1 });
+        let mut copy = ext.clone();
+        copy.get_mut::<Parsed>().unwrap().amount = 99;
+        assert_eq!(ext.get::<Parsed>(), Some(&Parsed { amount: 1 }));
+    }
+}
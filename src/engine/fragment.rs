@@ -0,0 +1,338 @@
+//! # Task Fragments
+//!
+//! Reusable lists of tasks — a standard parse-and-validate prelude, say —
+//! shared by many workflows instead of copied into each. A workflow's task
+//! list (or a `foreach` body) names a fragment with an `include` entry:
+//!
+//! ```json
+//! "tasks": [
+//!     {"include": "common_validation"},
+//!     {"include": "fragments/audit_tail.json"},
+//!     {"id": "route", "name": "Route", "function": {"name": "map", "input": {...}}}
+//! ]
+//! ```
+//!
+//! A reference is either the id of a fragment registered on the engine
+//! ([`EngineBuilder::with_fragment`](crate::EngineBuilder::with_fragment)) or
+//! the path of a fragment file ending in `.json`. Paths in workflow and
+//! fragment files read with `from_file`, `load_dir` or a manifest are
+//! relative to that file's directory; other paths are relative to the
+//! working directory.
+//!
+//! A fragment file lists its tasks, which may include other fragments:
+//!
+//! ```json
+//! {
+//!     "id": "common_validation",
+//!     "description": "Parse the payload and check the header",
+//!     "tasks": [
+//!         {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {...}}},
+//!         {"id": "check_header", "name": "Check header", "function": {"name": "validation", "input": {...}}}
+//!     ]
+//! }
+//! ```
+//!
+//! Includes are replaced by the fragment's tasks when the engine is built,
+//! before the workflows are validated and compiled, so the engine's
+//! workflows never contain them. Included task ids must still be unique
+//! within the task list they land in: including one fragment twice in a
+//! workflow fails as a duplicate task id. An include cycle is an error.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::functions::FunctionConfig;
+use crate::engine::manifest::in_file;
+use crate::engine::task::Task;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Registered fragments, keyed by id. Shared by the engines produced from
+/// one builder (including hot reloads).
+pub(crate) type Fragments = Arc<HashMap<String, Arc<TaskFragment>>>;
+
+/// A named, reusable list of tasks.
+#[derive(Debug, Clone)]
+pub struct TaskFragment {
+    /// Id used in `include`.
+    pub id: String,
+    /// Human-readable description.
+    pub description: Option<String>,
+    /// The tasks, which may include other fragments.
+    pub tasks: Vec<Task>,
+}
+
+/// Configuration of the `include` placeholder task an `include` entry
+/// parses to. It is replaced by the fragment's tasks at engine
+/// construction and never runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IncludeConfig {
+    /// Fragment id or `.json` file path.
+    pub fragment: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFragment {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(deserialize_with = "deserialize_tasks")]
+    tasks: Vec<Task>,
+}
+
+impl TaskFragment {
+    /// Parse a fragment from a JSON string.
+    pub fn from_json(json_str: &str) -> Result<Self> {
+        Self::parse(json_str, None)
+    }
+
+    /// Load a fragment from a JSON file. Without an `id`, the fragment is
+    /// named after the file (`common_validation.json` is
+    /// `common_validation`).
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let json_str =
+            fs::read_to_string(path).map_err(|e| in_file(path, DataflowError::from_io(e)))?;
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy());
+        let mut fragment = Self::parse(&json_str, stem.as_deref()).map_err(|e| in_file(path, e))?;
+        if let Some(dir) = path.parent() {
+            anchor_includes(&mut fragment.tasks, dir);
+        }
+        Ok(fragment)
+    }
+
+    /// Load every `*.json` file in `dir` as a fragment, sorted by file name.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(DataflowError::from_io)? {
+            let path = entry.map_err(DataflowError::from_io)?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.into_iter().map(Self::from_file).collect()
+    }
+
+    fn parse(json_str: &str, default_id: Option<&str>) -> Result<Self> {
+        let raw: RawFragment = serde_json::from_str(json_str).map_err(DataflowError::from_serde)?;
+        let id = raw
+            .id
+            .or_else(|| default_id.map(str::to_string))
+            .unwrap_or_default();
+        if id.is_empty() {
+            return Err(DataflowError::Validation(
+                "Task fragment id cannot be empty".to_string(),
+            ));
+        }
+        if raw.tasks.is_empty() {
+            return Err(DataflowError::Validation(format!(
+                "Task fragment '{id}' has no tasks"
+            )));
+        }
+        Ok(Self {
+            id,
+            description: raw.description,
+            tasks: raw.tasks,
+        })
+    }
+}
+
+/// Deserialize a task list, turning `{"include": "<ref>"}` entries into
+/// `include` placeholder tasks.
+pub(crate) fn deserialize_tasks<'de, D>(deserializer: D) -> std::result::Result<Vec<Task>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+
+    let entries = Vec::<Value>::deserialize(deserializer)?;
+    entries
+        .into_iter()
+        .map(|entry| {
+            let entry = match entry.get("include") {
+                Some(Value::String(reference))
+                    if entry.as_object().is_some_and(|o| o.len() == 1) =>
+                {
+                    json!({
+                        "id": reference,
+                        "name": reference,
+                        "function": {"name": "include", "input": {"fragment": reference}},
+                    })
+                }
+                Some(_) => {
+                    return Err(D::Error::custom(
+                        "an include entry must be {\"include\": \"<fragment id or path>\"}",
+                    ));
+                }
+                None => entry,
+            };
+            serde_json::from_value(entry).map_err(|err| {
+                let raw = err.to_string();
+                let trimmed = raw
+                    .rsplit_once(" at line ")
+                    .map(|(head, _)| head)
+                    .unwrap_or(&raw);
+                D::Error::custom(trimmed)
+            })
+        })
+        .collect()
+}
+
+/// Make relative fragment file paths in `tasks` relative to `dir`, the
+/// directory of the file the tasks were read from.
+pub(crate) fn anchor_includes(tasks: &mut [Task], dir: &Path) {
+    for task in tasks {
+        match &mut task.function {
+            FunctionConfig::Include { input, .. }
+                if is_path(&input.fragment) && Path::new(&input.fragment).is_relative() =>
+            {
+                input.fragment = dir.join(&input.fragment).to_string_lossy().into_owned();
+            }
+            FunctionConfig::Foreach { input, .. } => anchor_includes(&mut input.tasks, dir),
+            _ => {}
+        }
+    }
+}
+
+/// Replace every `include` task in `tasks`, `foreach` bodies included,
+/// with the tasks of the fragment it names.
+pub(crate) fn resolve_includes(
+    tasks: &mut Vec<Task>,
+    fragments: &HashMap<String, Arc<TaskFragment>>,
+    workflow_id: &str,
+) -> Result<()> {
+    resolve(tasks, fragments, workflow_id, &mut Vec::new())
+}
+
+fn resolve(
+    tasks: &mut Vec<Task>,
+    fragments: &HashMap<String, Arc<TaskFragment>>,
+    workflow_id: &str,
+    stack: &mut Vec<String>,
+) -> Result<()> {
+    let mut resolved = Vec::with_capacity(tasks.len());
+    for mut task in std::mem::take(tasks) {
+        match &mut task.function {
+            FunctionConfig::Include { input, .. } => {
+                let reference = input.fragment.clone();
+                if stack.contains(&reference) {
+                    return Err(DataflowError::Workflow(format!(
+                        "Fragment '{}' includes itself in workflow {} ({} -> {})",
+                        reference,
+                        workflow_id,
+                        stack.join(" -> "),
+                        reference
+                    )));
+                }
+                let mut included = lookup(&reference, fragments, workflow_id)?.tasks.clone();
+                stack.push(reference);
+                resolve(&mut included, fragments, workflow_id, stack)?;
+                stack.pop();
+                resolved.append(&mut included);
+            }
+            FunctionConfig::Foreach { input, .. } => {
+                resolve(&mut input.tasks, fragments, workflow_id, stack)?;
+                resolved.push(task);
+            }
+            _ => resolved.push(task),
+        }
+    }
+    *tasks = resolved;
+    Ok(())
+}
+
+fn lookup(
+    reference: &str,
+    fragments: &HashMap<String, Arc<TaskFragment>>,
+    workflow_id: &str,
+) -> Result<Arc<TaskFragment>> {
+    if let Some(fragment) = fragments.get(reference) {
+        return Ok(Arc::clone(fragment));
+    }
+    if is_path(reference) {
+        return TaskFragment::from_file(reference)
+            .map(Arc::new)
+            .map_err(|e| {
+                DataflowError::Workflow(format!(
+                    "Cannot include '{}' in workflow {}: {}",
+                    reference, workflow_id, e
+                ))
+            });
+    }
+    Err(DataflowError::Workflow(format!(
+        "Unknown fragment '{}' included in workflow {}",
+        reference, workflow_id
+    )))
+}
+
+fn is_path(reference: &str) -> bool {
+    reference.ends_with(".json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(json: Value) -> TaskFragment {
+        TaskFragment::from_json(&json.to_string()).unwrap()
+    }
+
+    fn task(id: &str) -> Value {
+        json!({"id": id, "name": id, "function": {"name": "log", "input": {"message": id}}})
+    }
+
+    fn ids(tasks: &[Task]) -> Vec<&str> {
+        tasks.iter().map(|t| t.id.as_str()).collect()
+    }
+
+    fn registry(fragments: Vec<TaskFragment>) -> HashMap<String, Arc<TaskFragment>> {
+        fragments
+            .into_iter()
+            .map(|f| (f.id.clone(), Arc::new(f)))
+            .collect()
+    }
+
+    #[test]
+    fn rejects_unnamed_or_empty_fragments() {
+        assert!(TaskFragment::from_json(&json!({"tasks": [task("a")]}).to_string()).is_err());
+        assert!(TaskFragment::from_json(r#"{"id": "x", "tasks": []}"#).is_err());
+        assert!(TaskFragment::from_json(r#"{"id": "x", "tasks": [{"include": 1}]}"#).is_err());
+    }
+
+    #[test]
+    fn inlines_nested_includes_in_order() {
+        let fragments = registry(vec![
+            fragment(json!({"id": "inner", "tasks": [task("b"), task("c")]})),
+            fragment(json!({"id": "outer", "tasks": [task("a"), {"include": "inner"}]})),
+        ]);
+        let mut tasks = fragment(json!({
+            "id": "w",
+            "tasks": [{"include": "outer"}, task("d")]
+        }))
+        .tasks;
+
+        resolve_includes(&mut tasks, &fragments, "w").unwrap();
+        assert_eq!(ids(&tasks), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn rejects_unknown_and_cyclic_includes() {
+        let mut tasks = fragment(json!({"id": "w", "tasks": [{"include": "missing"}]})).tasks;
+        let err = resolve_includes(&mut tasks, &HashMap::new(), "w").unwrap_err();
+        assert!(err.to_string().contains("Unknown fragment 'missing'"));
+
+        let fragments = registry(vec![
+            fragment(json!({"id": "a", "tasks": [{"include": "b"}]})),
+            fragment(json!({"id": "b", "tasks": [{"include": "a"}]})),
+        ]);
+        let mut tasks = fragment(json!({"id": "w", "tasks": [{"include": "a"}]})).tasks;
+        let err = resolve_includes(&mut tasks, &fragments, "w").unwrap_err();
+        assert!(err.to_string().contains("a -> b -> a"), "{err}");
+    }
+}
@@ -0,0 +1,191 @@
+//! # Alert Function Module
+//!
+//! Raises an operational [`Alert`] when the task runs. Put the threshold in
+//! the task's `condition`:
+//!
+//! ```json
+//! {"id": "high_risk", "name": "High risk",
+//!  "condition": {">=": [{"var": "data.risk.score"}, 80]},
+//!  "function": {"name": "alert", "input": {
+//!      "severity": "critical",
+//!      "code": "HIGH_RISK",
+//!      "message": {"cat": ["Risk score ", {"var": "data.risk.score"}]},
+//!      "fields": {"customer": {"var": "data.customer.id"}}
+//!  }}}
+//! ```
+//!
+//! `message` and every `fields` entry are pre-compiled JSONLogic. The
+//! message is rendered as text; a string result is used as is. The alert
+//! goes to the engine's [`AlertSink`], or is logged when none is
+//! configured — see [`alert`](crate::engine::alert). The message is not
+//! changed.
+
+use crate::engine::alert::{Alert, AlertSeverity, AlertSink};
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::executor::{ArenaContext, with_arena};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use chrono::Utc;
+use datalogic_rs::{Engine, Logic};
+use datavalue::OwnedDataValue;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Configuration for the `alert` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    /// How urgent the alert is (default `warning`).
+    #[serde(default)]
+    pub severity: AlertSeverity,
+
+    /// Stable identifier of the alert, e.g. `HIGH_RISK`.
+    pub code: String,
+
+    /// JSONLogic producing the alert text.
+    pub message: Value,
+
+    /// Fields to attach: each value is a JSONLogic expression.
+    #[serde(default)]
+    pub fields: BTreeMap<String, Value>,
+
+    /// Pre-compiled `message`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_message: Option<Arc<Logic>>,
+
+    /// Pre-compiled `fields`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_fields: Vec<(String, Arc<Logic>)>,
+
+    /// Workflow the alert is raised from, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub workflow_id: String,
+
+    /// Task the alert is raised from, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub task_id: String,
+}
+
+impl AlertConfig {
+    /// Build the alert and send it to `sink`, or log it without one.
+    pub(crate) async fn execute(
+        &self,
+        message: &mut Message,
+        sink: Option<&dyn AlertSink>,
+        engine: &Engine,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let alert = self.build(message, engine)?;
+        match sink {
+            Some(sink) => sink.send(alert).await?,
+            None => log_alert(&alert),
+        }
+        Ok((TaskOutcome::Success, Vec::new()))
+    }
+
+    /// Evaluate the message and fields against `message`'s context.
+    fn build(&self, message: &Message, engine: &Engine) -> Result<Alert> {
+        let compiled_message = self
+            .compiled_message
+            .as_ref()
+            .ok_or_else(|| DataflowError::Validation("alert: message is not compiled".into()))?;
+        let (text, fields) = with_arena(|arena| {
+            let arena_ctx = ArenaContext::from_owned(&message.context, arena);
+            let evaluate = |compiled: &Logic, label: &str| {
+                engine
+                    .evaluate(compiled, arena_ctx.as_data_value(), arena)
+                    .map(|value| value.to_owned())
+                    .map_err(|e| DataflowError::LogicEvaluation(format!("alert {label}: {e}")))
+            };
+            let text = match evaluate(compiled_message, "message")? {
+                OwnedDataValue::String(text) => text,
+                other => other.to_string(),
+            };
+            let fields = self
+                .compiled_fields
+                .iter()
+                .map(|(key, compiled)| Ok((key.clone(), evaluate(compiled, key)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?;
+            Ok::<_, DataflowError>((text, fields))
+        })?;
+        Ok(Alert {
+            severity: self.severity,
+            code: self.code.clone(),
+            message: text,
+            fields,
+            message_id: message.id().to_string(),
+            workflow_id: self.workflow_id.clone(),
+            task_id: self.task_id.clone(),
+            timestamp: Utc::now(),
+        })
+    }
+}
+
+/// Log `alert` at the level matching its severity.
+fn log_alert(alert: &Alert) {
+    let fields = serde_json::to_string(&alert.fields).unwrap_or_default();
+    let (code, text, workflow) = (&alert.code, &alert.message, &alert.workflow_id);
+    match alert.severity {
+        AlertSeverity::Info => {
+            info!(target: "dataflow::alert", "{code} in {workflow}: {text} {fields}")
+        }
+        AlertSeverity::Warning => {
+            warn!(target: "dataflow::alert", "{code} in {workflow}: {text} {fields}")
+        }
+        AlertSeverity::Critical => {
+            error!(target: "dataflow::alert", "{code} in {workflow}: {text} {fields}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::alert::InMemoryAlertSink;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn sends_the_rendered_alert_to_the_sink() {
+        let engine = Engine::builder().build();
+        let mut config: AlertConfig = serde_json::from_value(json!({
+            "severity": "critical",
+            "code": "HIGH_RISK",
+            "message": {"cat": ["Risk score ", {"var": "data.score"}]},
+            "fields": {"customer": {"var": "data.customer"}, "score": {"var": "data.score"}}
+        }))
+        .unwrap();
+        config.compiled_message = Some(engine.compile_arc(&config.message).unwrap());
+        config.compiled_fields = config
+            .fields
+            .iter()
+            .map(|(key, logic)| (key.clone(), engine.compile_arc(logic).unwrap()))
+            .collect();
+        config.workflow_id = "screen".to_string();
+        config.task_id = "high_risk".to_string();
+
+        let mut message = Message::from_value(&json!({}));
+        message.context = OwnedDataValue::from(&json!({
+            "data": {"score": 91, "customer": "C-7"}, "metadata": {}, "temp_data": {}
+        }));
+        let sink = InMemoryAlertSink::new();
+        let (outcome, changes) = config
+            .execute(&mut message, Some(&sink), &engine)
+            .await
+            .unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert!(changes.is_empty());
+
+        let alerts = sink.alerts();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].severity, AlertSeverity::Critical);
+        assert_eq!(alerts[0].message, "Risk score 91");
+        assert_eq!(
+            serde_json::to_value(&alerts[0].fields).unwrap(),
+            json!({"customer": "C-7", "score": 91})
+        );
+        assert_eq!(alerts[0].workflow_id, "screen");
+        assert_eq!(alerts[0].message_id, message.id());
+    }
+}
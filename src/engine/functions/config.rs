@@ -1,19 +1,33 @@
 use crate::engine::error::Result;
 use crate::engine::executor::ArenaContext;
+use crate::engine::fragment::IncludeConfig;
+use crate::engine::functions::alert::AlertConfig;
 use crate::engine::functions::filter::FilterConfig;
+use crate::engine::functions::flatten::{FlattenConfig, execute_flatten, execute_unflatten};
+use crate::engine::functions::foreach::ForeachConfig;
 use crate::engine::functions::integration::{EnrichConfig, HttpCallConfig, PublishKafkaConfig};
+use crate::engine::functions::join::JoinConfig;
 use crate::engine::functions::log::LogConfig;
 use crate::engine::functions::map::MapConfig;
+use crate::engine::functions::map_errors::{MapErrorsConfig, execute_map_errors};
 use crate::engine::functions::parse::{
     ParseConfig, execute_parse_json_in_arena, execute_parse_xml,
 };
+use crate::engine::functions::project::{ProjectConfig, execute_project};
 use crate::engine::functions::publish::{PublishConfig, execute_publish_json, execute_publish_xml};
+use crate::engine::functions::reject::RejectConfig;
+use crate::engine::functions::rename_keys::{RenameKeysConfig, execute_rename_keys};
+use crate::engine::functions::sample::{SampleConfig, execute_sample};
+use crate::engine::functions::score::ScoreConfig;
+use crate::engine::functions::state::{StateGetConfig, StateSetConfig};
+use crate::engine::functions::switch::SwitchConfig;
 use crate::engine::functions::validation::ValidationConfig;
 use crate::engine::message::{Change, Message};
 use crate::engine::task_outcome::TaskOutcome;
 use datalogic_rs::Engine;
 use serde::de::DeserializeOwned;
-use serde::{Deserialize, Deserializer};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 use std::any::Any;
 use std::sync::Arc;
@@ -86,6 +100,68 @@ pub enum FunctionConfig {
         name: LogName,
         input: LogConfig,
     },
+    Project {
+        name: ProjectName,
+        input: ProjectConfig,
+    },
+    Flatten {
+        name: FlattenName,
+        input: FlattenConfig,
+    },
+    Unflatten {
+        name: UnflattenName,
+        input: FlattenConfig,
+    },
+    RenameKeys {
+        name: RenameKeysName,
+        input: RenameKeysConfig,
+    },
+    Sample {
+        name: SampleName,
+        input: SampleConfig,
+    },
+    Switch {
+        name: SwitchName,
+        input: SwitchConfig,
+    },
+    Foreach {
+        name: ForeachName,
+        input: ForeachConfig,
+    },
+    Reject {
+        name: RejectName,
+        input: RejectConfig,
+    },
+    Score {
+        name: ScoreName,
+        input: ScoreConfig,
+    },
+    MapErrors {
+        name: MapErrorsName,
+        input: MapErrorsConfig,
+    },
+    Join {
+        name: JoinName,
+        input: JoinConfig,
+    },
+    StateGet {
+        name: StateGetName,
+        input: StateGetConfig,
+    },
+    StateSet {
+        name: StateSetName,
+        input: StateSetConfig,
+    },
+    Alert {
+        name: AlertName,
+        input: AlertConfig,
+    },
+    /// Placeholder for an `include` entry; replaced by the fragment's tasks
+    /// when the engine is built. See [`crate::engine::fragment`].
+    Include {
+        name: IncludeName,
+        input: IncludeConfig,
+    },
     HttpCall {
         name: HttpCallName,
         input: HttpCallConfig,
@@ -161,6 +237,96 @@ pub enum LogName {
     Log,
 }
 
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectName {
+    Project,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlattenName {
+    Flatten,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnflattenName {
+    Unflatten,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RenameKeysName {
+    RenameKeys,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleName {
+    Sample,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchName {
+    Switch,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ForeachName {
+    Foreach,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectName {
+    Reject,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreName {
+    Score,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MapErrorsName {
+    MapErrors,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinName {
+    Join,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateGetName {
+    StateGet,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StateSetName {
+    StateSet,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertName {
+    Alert,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IncludeName {
+    Include,
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum HttpCallName {
@@ -193,6 +359,21 @@ pub(crate) const BUILTIN_FUNCTION_NAMES: &[&str] = &[
     "publish_xml",
     "filter",
     "log",
+    "project",
+    "flatten",
+    "unflatten",
+    "rename_keys",
+    "sample",
+    "switch",
+    "foreach",
+    "reject",
+    "score",
+    "map_errors",
+    "join",
+    "state_get",
+    "state_set",
+    "alert",
+    "include",
     "http_call",
     "enrich",
     "publish_kafka",
@@ -272,6 +453,66 @@ impl<'de> Deserialize<'de> for FunctionConfig {
                 name: LogName::Log,
                 input: parse_function_input("log", input)?,
             },
+            "project" => FunctionConfig::Project {
+                name: ProjectName::Project,
+                input: parse_function_input("project", input)?,
+            },
+            "flatten" => FunctionConfig::Flatten {
+                name: FlattenName::Flatten,
+                input: parse_function_input("flatten", input)?,
+            },
+            "unflatten" => FunctionConfig::Unflatten {
+                name: UnflattenName::Unflatten,
+                input: parse_function_input("unflatten", input)?,
+            },
+            "rename_keys" => FunctionConfig::RenameKeys {
+                name: RenameKeysName::RenameKeys,
+                input: parse_function_input("rename_keys", input)?,
+            },
+            "sample" => FunctionConfig::Sample {
+                name: SampleName::Sample,
+                input: parse_function_input("sample", input)?,
+            },
+            "switch" => FunctionConfig::Switch {
+                name: SwitchName::Switch,
+                input: parse_function_input("switch", input)?,
+            },
+            "foreach" => FunctionConfig::Foreach {
+                name: ForeachName::Foreach,
+                input: parse_function_input("foreach", input)?,
+            },
+            "reject" => FunctionConfig::Reject {
+                name: RejectName::Reject,
+                input: parse_function_input("reject", input)?,
+            },
+            "score" => FunctionConfig::Score {
+                name: ScoreName::Score,
+                input: parse_function_input("score", input)?,
+            },
+            "map_errors" => FunctionConfig::MapErrors {
+                name: MapErrorsName::MapErrors,
+                input: parse_function_input("map_errors", input)?,
+            },
+            "join" => FunctionConfig::Join {
+                name: JoinName::Join,
+                input: parse_function_input("join", input)?,
+            },
+            "state_get" => FunctionConfig::StateGet {
+                name: StateGetName::StateGet,
+                input: parse_function_input("state_get", input)?,
+            },
+            "state_set" => FunctionConfig::StateSet {
+                name: StateSetName::StateSet,
+                input: parse_function_input("state_set", input)?,
+            },
+            "alert" => FunctionConfig::Alert {
+                name: AlertName::Alert,
+                input: parse_function_input("alert", input)?,
+            },
+            "include" => FunctionConfig::Include {
+                name: IncludeName::Include,
+                input: parse_function_input("include", input)?,
+            },
             "http_call" => FunctionConfig::HttpCall {
                 name: HttpCallName::HttpCall,
                 input: parse_function_input("http_call", input)?,
@@ -293,6 +534,57 @@ impl<'de> Deserialize<'de> for FunctionConfig {
     }
 }
 
+/// Serializes as `{"name": …, "input": …}`, the shape it is read from.
+/// Built-in inputs are written from their typed config, so defaults appear
+/// explicitly; engine-compiled state is left out.
+impl Serialize for FunctionConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        fn entry<S: Serializer, T: Serialize + ?Sized>(
+            serializer: S,
+            name: &str,
+            input: &T,
+        ) -> std::result::Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("FunctionConfig", 2)?;
+            state.serialize_field("name", name)?;
+            state.serialize_field("input", input)?;
+            state.end()
+        }
+
+        let name = self.function_name();
+        match self {
+            FunctionConfig::Map { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Validation { input, .. } => entry(serializer, name, input),
+            FunctionConfig::ParseJson { input, .. } | FunctionConfig::ParseXml { input, .. } => {
+                entry(serializer, name, input)
+            }
+            FunctionConfig::PublishJson { input, .. }
+            | FunctionConfig::PublishXml { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Filter { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Log { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Project { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Flatten { input, .. } | FunctionConfig::Unflatten { input, .. } => {
+                entry(serializer, name, input)
+            }
+            FunctionConfig::RenameKeys { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Sample { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Switch { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Foreach { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Reject { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Score { input, .. } => entry(serializer, name, input),
+            FunctionConfig::MapErrors { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Join { input, .. } => entry(serializer, name, input),
+            FunctionConfig::StateGet { input, .. } => entry(serializer, name, input),
+            FunctionConfig::StateSet { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Alert { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Include { input, .. } => entry(serializer, name, input),
+            FunctionConfig::HttpCall { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Enrich { input, .. } => entry(serializer, name, input),
+            FunctionConfig::PublishKafka { input, .. } => entry(serializer, name, input),
+            FunctionConfig::Custom { input, .. } => entry(serializer, name, input),
+        }
+    }
+}
+
 impl FunctionConfig {
     /// Get the function name for this configuration
     pub fn function_name(&self) -> &str {
@@ -305,6 +597,21 @@ impl FunctionConfig {
             FunctionConfig::PublishXml { .. } => "publish_xml",
             FunctionConfig::Filter { .. } => "filter",
             FunctionConfig::Log { .. } => "log",
+            FunctionConfig::Project { .. } => "project",
+            FunctionConfig::Flatten { .. } => "flatten",
+            FunctionConfig::Unflatten { .. } => "unflatten",
+            FunctionConfig::RenameKeys { .. } => "rename_keys",
+            FunctionConfig::Sample { .. } => "sample",
+            FunctionConfig::Switch { .. } => "switch",
+            FunctionConfig::Foreach { .. } => "foreach",
+            FunctionConfig::Reject { .. } => "reject",
+            FunctionConfig::Score { .. } => "score",
+            FunctionConfig::MapErrors { .. } => "map_errors",
+            FunctionConfig::Join { .. } => "join",
+            FunctionConfig::StateGet { .. } => "state_get",
+            FunctionConfig::StateSet { .. } => "state_set",
+            FunctionConfig::Alert { .. } => "alert",
+            FunctionConfig::Include { .. } => "include",
             FunctionConfig::HttpCall { .. } => "http_call",
             FunctionConfig::Enrich { .. } => "enrich",
             FunctionConfig::PublishKafka { .. } => "publish_kafka",
@@ -329,6 +636,15 @@ impl FunctionConfig {
                 | FunctionConfig::PublishXml { .. }
                 | FunctionConfig::Filter { .. }
                 | FunctionConfig::Log { .. }
+                | FunctionConfig::Project { .. }
+                | FunctionConfig::Flatten { .. }
+                | FunctionConfig::Unflatten { .. }
+                | FunctionConfig::RenameKeys { .. }
+                | FunctionConfig::Sample { .. }
+                | FunctionConfig::Switch { .. }
+                | FunctionConfig::Reject { .. }
+                | FunctionConfig::Score { .. }
+                | FunctionConfig::MapErrors { .. }
         )
     }
 
@@ -401,7 +717,70 @@ impl FunctionConfig {
             FunctionConfig::Log { input, .. } => {
                 Some(input.execute_in_arena(message, arena_ctx, engine))
             }
-            FunctionConfig::HttpCall { .. }
+            FunctionConfig::Project { input, .. } => Some(match execute_project(message, input) {
+                Ok(r) => {
+                    arena_ctx.refresh_for_path(&message.context, &input.path);
+                    Ok(r)
+                }
+                Err(e) => Err(e),
+            }),
+            FunctionConfig::Flatten { input, .. } => Some(match execute_flatten(message, input) {
+                Ok(r) => {
+                    arena_ctx.refresh_for_path(&message.context, input.target_path());
+                    Ok(r)
+                }
+                Err(e) => Err(e),
+            }),
+            FunctionConfig::Unflatten { input, .. } => {
+                Some(match execute_unflatten(message, input) {
+                    Ok(r) => {
+                        arena_ctx.refresh_for_path(&message.context, input.target_path());
+                        Ok(r)
+                    }
+                    Err(e) => Err(e),
+                })
+            }
+            FunctionConfig::RenameKeys { input, .. } => {
+                Some(match execute_rename_keys(message, input) {
+                    Ok(r) => {
+                        arena_ctx.refresh_for_path(&message.context, &input.path);
+                        Ok(r)
+                    }
+                    Err(e) => Err(e),
+                })
+            }
+            FunctionConfig::Sample { input, .. } => Some(match execute_sample(message, input) {
+                Ok(r) => {
+                    arena_ctx.refresh_for_path(&message.context, &input.target);
+                    Ok(r)
+                }
+                Err(e) => Err(e),
+            }),
+            FunctionConfig::Switch { input, .. } => {
+                Some(input.execute_in_arena(message, arena_ctx, engine))
+            }
+            FunctionConfig::Reject { input, .. } => {
+                Some(input.execute_in_arena(message, arena_ctx, engine))
+            }
+            FunctionConfig::Score { input, .. } => {
+                Some(input.execute_in_arena(message, arena_ctx, engine))
+            }
+            FunctionConfig::MapErrors { input, .. } => {
+                Some(match execute_map_errors(message, input) {
+                    Ok(r) => {
+                        arena_ctx.refresh_for_path(&message.context, &input.target);
+                        Ok(r)
+                    }
+                    Err(e) => Err(e),
+                })
+            }
+            FunctionConfig::Foreach { .. }
+            | FunctionConfig::Join { .. }
+            | FunctionConfig::StateGet { .. }
+            | FunctionConfig::StateSet { .. }
+            | FunctionConfig::Alert { .. }
+            | FunctionConfig::Include { .. }
+            | FunctionConfig::HttpCall { .. }
             | FunctionConfig::Enrich { .. }
             | FunctionConfig::PublishKafka { .. }
             | FunctionConfig::Custom { .. } => None,
@@ -546,4 +925,32 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn serializes_typed_input_with_explicit_defaults_and_reparses() {
+        let cfg = parse(json!({
+            "name": "validation",
+            "input": {"rules": [
+                {"type": "required", "paths": ["data.id"]},
+                {"type": "aggregate", "array": "data.items", "op": "count", "expected": 2}
+            ]}
+        }))
+        .unwrap();
+        let value = serde_json::to_value(&cfg).unwrap();
+        assert_eq!(value["name"], "validate");
+        let rules = value["input"]["rules"].as_array().unwrap();
+        assert_eq!(rules[0]["code"], "REQUIRED_FIELD_MISSING");
+        assert_eq!(rules[1]["comparison"], "==");
+        assert_eq!(rules[1]["tolerance"], 1e-9);
+        assert_eq!(
+            serde_json::to_value(parse(value.clone()).unwrap()).unwrap(),
+            value
+        );
+
+        let flatten = serde_json::to_value(parse(json!({"name": "flatten", "input": {}})).unwrap());
+        assert_eq!(
+            flatten.unwrap()["input"],
+            json!({"path": "data", "target": null, "separator": ".", "arrays": "index"})
+        );
+    }
 }
@@ -5,12 +5,12 @@ use crate::engine::task_outcome::TaskOutcome;
 use datalogic_rs::{Engine, Logic};
 use datavalue::DataValue;
 use log::{debug, info};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
 /// What to do when the filter condition evaluates to false
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum RejectAction {
     /// Halt the entire workflow — no further tasks in this workflow execute
@@ -21,7 +21,7 @@ pub enum RejectAction {
 }
 
 /// Configuration for the filter/gate function
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FilterConfig {
     /// JSONLogic condition to evaluate against the message context.
     /// If true, the message passes through. If false, the on_reject action is taken.
@@ -0,0 +1,356 @@
+//! # Flatten Function Module
+//!
+//! `flatten` turns a nested object into a single-level map with dotted keys
+//! (`{"a": {"b": 1}}` → `{"a.b": 1}`); `unflatten` reverses it. Tabular
+//! destinations (CSV writers, SQL staging tables) usually want the flat
+//! form. Both share [`FlattenConfig`].
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{get_nested_value, replace_with_change};
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// How arrays are treated by `flatten` / `unflatten`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArrayMode {
+    /// Flatten array elements by position (`items.0.sku`); `unflatten`
+    /// turns numeric segments back into arrays.
+    #[default]
+    Index,
+    /// Keep arrays as leaf values; `unflatten` treats numeric segments as
+    /// ordinary object keys.
+    Keep,
+}
+
+/// Configuration shared by the `flatten` and `unflatten` functions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlattenConfig {
+    /// Context path of the object to convert. Defaults to `data`.
+    #[serde(default = "default_path")]
+    pub path: String,
+
+    /// Context path receiving the result. Defaults to `path` (in place).
+    #[serde(default)]
+    pub target: Option<String>,
+
+    /// Key separator. Defaults to `"."`; must not be empty.
+    #[serde(
+        default = "default_separator",
+        deserialize_with = "deserialize_separator"
+    )]
+    pub separator: String,
+
+    /// Array handling. Defaults to [`ArrayMode::Index`].
+    #[serde(default)]
+    pub arrays: ArrayMode,
+}
+
+fn default_path() -> String {
+    "data".to_string()
+}
+
+fn default_separator() -> String {
+    ".".to_string()
+}
+
+fn deserialize_separator<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let separator = String::deserialize(deserializer)?;
+    if separator.is_empty() {
+        return Err(serde::de::Error::custom("'separator' must not be empty"));
+    }
+    Ok(separator)
+}
+
+impl FlattenConfig {
+    /// Where the result is written: `target`, or `path` when unset.
+    pub fn target_path(&self) -> &str {
+        self.target.as_deref().unwrap_or(&self.path)
+    }
+
+    /// Flatten `value` into a single-level object.
+    pub fn flatten_value(&self, value: &OwnedDataValue) -> OwnedDataValue {
+        let mut out = Vec::new();
+        let mut prefix = String::new();
+        self.flatten_into(value, &mut prefix, &mut out);
+        OwnedDataValue::Object(out)
+    }
+
+    fn flatten_into(
+        &self,
+        value: &OwnedDataValue,
+        prefix: &mut String,
+        out: &mut Vec<(String, OwnedDataValue)>,
+    ) {
+        let children: Vec<(String, &OwnedDataValue)> = match value {
+            OwnedDataValue::Object(pairs) if !pairs.is_empty() => {
+                pairs.iter().map(|(k, v)| (k.clone(), v)).collect()
+            }
+            OwnedDataValue::Array(items)
+                if !items.is_empty() && self.arrays == ArrayMode::Index =>
+            {
+                items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| (i.to_string(), v))
+                    .collect()
+            }
+            // Scalars, empty containers, and kept arrays are leaves.
+            _ => {
+                out.push((prefix.clone(), value.clone()));
+                return;
+            }
+        };
+
+        let base_len = prefix.len();
+        for (key, child) in children {
+            if base_len > 0 {
+                prefix.push_str(&self.separator);
+            }
+            prefix.push_str(&key);
+            self.flatten_into(child, prefix, out);
+            prefix.truncate(base_len);
+        }
+    }
+
+    /// Rebuild a nested value from a single-level object with separated
+    /// keys. Returns an error if `value` is not an object, two keys
+    /// conflict (`"a": 1` alongside `"a.b": 2`), or an array index is not
+    /// below the number of keys — no flattened array has more elements
+    /// than that, and padding up to an arbitrary index from message data
+    /// could exhaust memory.
+    pub fn unflatten_value(&self, value: &OwnedDataValue) -> Result<OwnedDataValue> {
+        let OwnedDataValue::Object(pairs) = value else {
+            return Err(DataflowError::Validation(
+                "Unflatten: source must be an object".to_string(),
+            ));
+        };
+        let mut root = OwnedDataValue::Object(Vec::new());
+        for (key, leaf) in pairs {
+            let parts: Vec<&str> = key.split(self.separator.as_str()).collect();
+            match self.insert(&mut root, &parts, leaf.clone(), pairs.len()) {
+                Insert::Done => {}
+                Insert::Conflict => {
+                    return Err(DataflowError::Validation(format!(
+                        "Unflatten: key '{}' conflicts with another key",
+                        key
+                    )));
+                }
+                Insert::IndexTooLarge(idx) => {
+                    return Err(DataflowError::Validation(format!(
+                        "Unflatten: index {} in key '{}' exceeds the {} keys of the source",
+                        idx,
+                        key,
+                        pairs.len()
+                    )));
+                }
+            }
+        }
+        Ok(root)
+    }
+
+    /// Insert `leaf` at `parts` under `node`, padding arrays up to indices
+    /// below `max_len`.
+    fn insert(
+        &self,
+        node: &mut OwnedDataValue,
+        parts: &[&str],
+        leaf: OwnedDataValue,
+        max_len: usize,
+    ) -> Insert {
+        let Some((head, rest)) = parts.split_first() else {
+            return Insert::Conflict;
+        };
+        let as_index = match self.arrays {
+            ArrayMode::Index => head.parse::<usize>().ok(),
+            ArrayMode::Keep => None,
+        };
+        let next_is_index = self.arrays == ArrayMode::Index
+            && rest.first().is_some_and(|p| p.parse::<usize>().is_ok());
+        let empty_child = || {
+            if next_is_index {
+                OwnedDataValue::Array(Vec::new())
+            } else {
+                OwnedDataValue::Object(Vec::new())
+            }
+        };
+
+        let slot = match (node, as_index) {
+            (OwnedDataValue::Array(_), Some(idx)) if idx >= max_len => {
+                return Insert::IndexTooLarge(idx);
+            }
+            (OwnedDataValue::Array(items), Some(idx)) => {
+                while items.len() <= idx {
+                    items.push(OwnedDataValue::Null);
+                }
+                if rest.is_empty() {
+                    items[idx] = leaf;
+                    return Insert::Done;
+                }
+                if matches!(items[idx], OwnedDataValue::Null) {
+                    items[idx] = empty_child();
+                }
+                &mut items[idx]
+            }
+            (OwnedDataValue::Object(pairs), _) => {
+                let idx = match pairs.iter().position(|(k, _)| k == head) {
+                    Some(idx) => idx,
+                    None if rest.is_empty() => {
+                        pairs.push((head.to_string(), leaf));
+                        return Insert::Done;
+                    }
+                    None => {
+                        pairs.push((head.to_string(), empty_child()));
+                        pairs.len() - 1
+                    }
+                };
+                if rest.is_empty() {
+                    return Insert::Conflict;
+                }
+                &mut pairs[idx].1
+            }
+            _ => return Insert::Conflict,
+        };
+        self.insert(slot, rest, leaf, max_len)
+    }
+}
+
+/// Result of [`FlattenConfig::insert`].
+enum Insert {
+    Done,
+    /// The key's shape clashes with an earlier key.
+    Conflict,
+    /// An array index at or beyond the number of source keys.
+    IndexTooLarge(usize),
+}
+
+/// Execute `flatten`: write the flattened form of `config.path` to the
+/// target path.
+pub fn execute_flatten(
+    message: &mut Message,
+    config: &FlattenConfig,
+) -> Result<(TaskOutcome, Vec<Change>)> {
+    debug!("Flatten: '{}' -> '{}'", config.path, config.target_path());
+    let source = get_nested_value(&message.context, &config.path).ok_or_else(|| {
+        DataflowError::Validation(format!("Flatten: path '{}' not found", config.path))
+    })?;
+    let flat = config.flatten_value(source);
+    let changes = replace_with_change(message, config.target_path(), flat);
+    Ok((TaskOutcome::Success, changes))
+}
+
+/// Execute `unflatten`: write the nested form of `config.path` to the
+/// target path.
+pub fn execute_unflatten(
+    message: &mut Message,
+    config: &FlattenConfig,
+) -> Result<(TaskOutcome, Vec<Change>)> {
+    debug!("Unflatten: '{}' -> '{}'", config.path, config.target_path());
+    let source = get_nested_value(&message.context, &config.path).ok_or_else(|| {
+        DataflowError::Validation(format!("Unflatten: path '{}' not found", config.path))
+    })?;
+    let nested = config.unflatten_value(source)?;
+    let changes = replace_with_change(message, config.target_path(), nested);
+    Ok((TaskOutcome::Success, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn config(input: Value) -> FlattenConfig {
+        serde_json::from_value(input).unwrap()
+    }
+
+    fn dv(v: Value) -> OwnedDataValue {
+        OwnedDataValue::from(&v)
+    }
+
+    fn js(v: &OwnedDataValue) -> Value {
+        serde_json::to_value(v).unwrap()
+    }
+
+    #[test]
+    fn flatten_indexes_arrays_by_default() {
+        let cfg = config(json!({}));
+        let flat = cfg.flatten_value(&dv(json!({
+            "a": {"b": 1, "c": [10, {"d": true}]},
+            "e": {},
+            "f": null
+        })));
+        assert_eq!(
+            js(&flat),
+            json!({"a.b": 1, "a.c.0": 10, "a.c.1.d": true, "e": {}, "f": null})
+        );
+    }
+
+    #[test]
+    fn flatten_keep_mode_and_custom_separator() {
+        let cfg = config(json!({"separator": "__", "arrays": "keep"}));
+        let flat = cfg.flatten_value(&dv(json!({"a": {"b": [1, 2]}})));
+        assert_eq!(js(&flat), json!({"a__b": [1, 2]}));
+    }
+
+    #[test]
+    fn unflatten_round_trips_flatten() {
+        let cfg = config(json!({}));
+        let nested = json!({"a": {"b": 1, "c": [10, {"d": true}]}, "e": "x"});
+        let flat = cfg.flatten_value(&dv(nested.clone()));
+        assert_eq!(js(&cfg.unflatten_value(&flat).unwrap()), nested);
+    }
+
+    #[test]
+    fn unflatten_keep_mode_uses_object_keys_for_numbers() {
+        let cfg = config(json!({"arrays": "keep"}));
+        let nested = cfg.unflatten_value(&dv(json!({"a.0": 1}))).unwrap();
+        assert_eq!(js(&nested), json!({"a": {"0": 1}}));
+    }
+
+    #[test]
+    fn unflatten_rejects_conflicting_keys() {
+        let cfg = config(json!({}));
+        assert!(cfg.unflatten_value(&dv(json!({"a": 1, "a.b": 2}))).is_err());
+        assert!(cfg.unflatten_value(&dv(json!([1]))).is_err());
+    }
+
+    #[test]
+    fn unflatten_rejects_indices_beyond_the_key_count() {
+        let cfg = config(json!({}));
+        let err = cfg
+            .unflatten_value(&dv(json!({"a.99999999999": 1})))
+            .unwrap_err();
+        assert!(err.to_string().contains("index 99999999999"));
+
+        // Lexicographic key order ("a.10" before "a.2") still round-trips.
+        let items: Vec<Value> = (0..12).map(Value::from).collect();
+        let flat = cfg.flatten_value(&dv(json!({"a": items})));
+        let sorted: serde_json::Map<String, Value> = serde_json::from_value(js(&flat)).unwrap();
+        let nested = cfg.unflatten_value(&dv(Value::Object(sorted))).unwrap();
+        assert_eq!(js(&nested), json!({"a": items}));
+    }
+
+    #[test]
+    fn empty_separator_is_rejected() {
+        assert!(serde_json::from_value::<FlattenConfig>(json!({"separator": ""})).is_err());
+    }
+
+    #[test]
+    fn execute_flatten_writes_to_target() {
+        let mut message = Message::from_value(&json!({}));
+        replace_with_change(&mut message, "data.row", dv(json!({"a": {"b": 1}})));
+        let cfg = config(json!({"path": "data.row", "target": "data.flat"}));
+
+        let (outcome, changes) = execute_flatten(&mut message, &cfg).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(js(&message.data()["flat"]), json!({"a.b": 1}));
+        assert_eq!(js(&message.data()["row"]), json!({"a": {"b": 1}}));
+    }
+}
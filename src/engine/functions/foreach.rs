@@ -0,0 +1,191 @@
+//! # Foreach Function Module
+//!
+//! Runs a nested list of tasks once per element of an array, in place of a
+//! JSONLogic `map` expression that would have to build every element in
+//! one go — and could not call custom functions:
+//!
+//! ```json
+//! {"name": "foreach", "input": {
+//!     "path": "data.orders",
+//!     "tasks": [
+//!         {"id": "price", "name": "Price", "function": {"name": "map", "input": {"mappings": [
+//!             {"path": "temp_data.item.total",
+//!              "logic": {"*": [{"var": "temp_data.item.qty"}, {"var": "temp_data.item.price"}]}}
+//!         ]}}}
+//!     ]
+//! }}
+//! ```
+//!
+//! Each element is bound at `item` (default `temp_data.item`) and, when
+//! `index` is set, its position at that path. The nested tasks — any
+//! function, custom ones included, each with its own `condition` — read
+//! and rewrite the element there. Once every element has run, the
+//! elements as left at `item` are written back over `path`, or to `target`
+//! when one is set, and the previous `item`/`index` values are restored.
+//!
+//! A nested task that halts or returns a status of 500 or more ends the
+//! loop: nothing is written back and its outcome becomes the foreach's.
+//! Errors abort the loop the same way. A missing or `null` array is treated
+//! as empty.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::executor::{ArenaContext, evaluate_condition_in_arena, with_arena};
+use crate::engine::fragment::deserialize_tasks;
+use crate::engine::message::{Change, Message};
+use crate::engine::task::Task;
+use crate::engine::task_executor::TaskExecutor;
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{
+    get_nested_value, get_nested_value_cloned, replace_with_change, set_nested_value,
+};
+use datalogic_rs::Engine;
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Configuration for the `foreach` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ForeachConfig {
+    /// Path of the array to iterate, e.g. `data.orders`.
+    pub path: String,
+
+    /// Path each element is bound at while the nested tasks run.
+    #[serde(default = "default_item")]
+    pub item: String,
+
+    /// Path the element's position is bound at, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+
+    /// Path the processed elements are written to. Defaults to `path`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    /// Tasks run per element, in order.
+    #[serde(deserialize_with = "deserialize_tasks")]
+    pub tasks: Vec<Task>,
+}
+
+fn default_item() -> String {
+    "temp_data.item".to_string()
+}
+
+impl ForeachConfig {
+    /// Run the nested tasks over every element and write the results.
+    pub(crate) async fn execute(
+        &self,
+        message: &mut Message,
+        executor: &TaskExecutor,
+        engine: &Engine,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let items = match get_nested_value(&message.context, &self.path) {
+            Some(OwnedDataValue::Array(items)) => items.clone(),
+            None | Some(OwnedDataValue::Null) => Vec::new(),
+            Some(_) => {
+                return Err(DataflowError::Validation(format!(
+                    "foreach: '{}' is not an array",
+                    self.path
+                )));
+            }
+        };
+        debug!("Foreach: {} elements at {}", items.len(), self.path);
+
+        let bindings: Vec<&str> = std::iter::once(self.item.as_str())
+            .chain(self.index.as_deref())
+            .collect();
+        let saved: Vec<Option<OwnedDataValue>> = bindings
+            .iter()
+            .map(|path| get_nested_value_cloned(&message.context, path))
+            .collect();
+
+        let run = self.run(items, message, executor, engine).await;
+        for (path, previous) in bindings.iter().zip(saved) {
+            restore(&mut message.context, path, previous);
+        }
+
+        let (outcome, results, mut changes) = run?;
+        changes.retain(|change| !bindings.iter().any(|path| within(&change.path, path)));
+        if outcome == TaskOutcome::Success {
+            let target = self.target.as_deref().unwrap_or(&self.path);
+            changes.extend(replace_with_change(
+                message,
+                target,
+                OwnedDataValue::Array(results),
+            ));
+        }
+        Ok((outcome, changes))
+    }
+
+    /// The loop itself; bindings are left for `execute` to restore.
+    async fn run(
+        &self,
+        items: Vec<OwnedDataValue>,
+        message: &mut Message,
+        executor: &TaskExecutor,
+        engine: &Engine,
+    ) -> Result<(TaskOutcome, Vec<OwnedDataValue>, Vec<Change>)> {
+        let mut results = Vec::with_capacity(items.len());
+        let mut changes = Vec::new();
+        for (idx, item) in items.into_iter().enumerate() {
+            set_nested_value(&mut message.context, &self.item, item);
+            if let Some(index) = &self.index {
+                set_nested_value(
+                    &mut message.context,
+                    index,
+                    OwnedDataValue::from(&Value::from(idx)),
+                );
+            }
+
+            for task in &self.tasks {
+                let runs = with_arena(|arena| {
+                    let arena_ctx = ArenaContext::from_owned(&message.context, arena);
+                    evaluate_condition_in_arena(
+                        engine,
+                        task.compiled_condition.as_ref(),
+                        arena_ctx.as_data_value(),
+                        arena,
+                    )
+                })?;
+                if !runs {
+                    continue;
+                }
+                let (outcome, task_changes) = executor.execute_nested(task, message).await?;
+                changes.extend(task_changes);
+                match outcome {
+                    TaskOutcome::Halt => return Ok((outcome, results, changes)),
+                    TaskOutcome::Status(code) if code >= 500 => {
+                        return Ok((outcome, results, changes));
+                    }
+                    _ => {}
+                }
+            }
+
+            results.push(
+                get_nested_value_cloned(&message.context, &self.item)
+                    .unwrap_or(OwnedDataValue::Null),
+            );
+        }
+        Ok((TaskOutcome::Success, results, changes))
+    }
+}
+
+/// Whether `path` is `binding` or lies under it.
+fn within(path: &str, binding: &str) -> bool {
+    path.strip_prefix(binding)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+}
+
+/// Put `previous` back at `path`, removing the key when it was unset.
+fn restore(context: &mut OwnedDataValue, path: &str, previous: Option<OwnedDataValue>) {
+    if let Some(value) = previous {
+        set_nested_value(context, path, value);
+        return;
+    }
+    let (parent_path, key) = path.rsplit_once('.').unwrap_or(("", path));
+    if let Some(OwnedDataValue::Object(mut pairs)) = get_nested_value_cloned(context, parent_path) {
+        pairs.retain(|(k, _)| k != key);
+        set_nested_value(context, parent_path, OwnedDataValue::Object(pairs));
+    }
+}
@@ -0,0 +1,375 @@
+//! # GeoIP Function Module
+//!
+//! Country and autonomous-system data for IP addresses, read from MaxMind
+//! DB (`.mmdb`) files loaded into memory, so fraud-screening workflows can
+//! route on where a request came from without calling a lookup service per
+//! message. Requires the `geoip` feature.
+//!
+//! Register a [`GeoIpFunction`] with the databases to consult:
+//!
+//! ```no_run
+//! # fn demo() -> dataflow_rs::Result<()> {
+//! use dataflow_rs::{Engine, GeoIpFunction};
+//!
+//! let geoip = GeoIpFunction::open("GeoLite2-Country.mmdb")?
+//!     .with_database("GeoLite2-ASN.mmdb")?;
+//! let builder = Engine::builder().register("geoip", geoip);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! and call it from a task:
+//!
+//! ```json
+//! {"name": "geoip", "input": {"ip": "data.client_ip", "target": "data.geo"}}
+//! ```
+//!
+//! `target` receives a [`GeoIpRecord`] merged from every database that
+//! knows the address — e.g. `{"country": "DE", "continent": "EU", "asn":
+//! 3320, "as_org": "Deutsche Telekom AG"}` — or `null` when none does or
+//! the `ip` field is missing or null. An `ip` value that isn't an IP
+//! address fails the task. IPv6 addresses are not looked up in IPv4-only
+//! databases.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::functions::AsyncFunctionHandler;
+use crate::engine::task_context::TaskContext;
+use crate::engine::task_outcome::TaskOutcome;
+use async_trait::async_trait;
+use datavalue::OwnedDataValue;
+use maxminddb::{LookupResult, PathElement, Reader};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Configuration for the `geoip` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeoIpConfig {
+    /// Dot-path of the IP address, e.g. `data.client_ip`.
+    pub ip: String,
+    /// Dot-path the lookup result is written to.
+    pub target: String,
+}
+
+/// What the databases know about an address. Fields no database provides
+/// are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoIpRecord {
+    /// ISO 3166-1 code of the country the address is located in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// Two-letter continent code, e.g. `EU`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub continent: Option<String>,
+    /// ISO 3166-1 code of the country the network is registered in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registered_country: Option<String>,
+    /// Autonomous system number.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// Organization the autonomous system belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_org: Option<String>,
+}
+
+impl GeoIpRecord {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Fill fields still unset from one database's result.
+    fn merge(&mut self, result: &LookupResult<'_, Vec<u8>>) -> Result<()> {
+        fn field<'a, T: Deserialize<'a>>(
+            result: &LookupResult<'a, Vec<u8>>,
+            slot: &mut Option<T>,
+            path: &[PathElement<'_>],
+        ) -> Result<()> {
+            if slot.is_none() {
+                *slot = result.decode_path(path).map_err(lookup_error)?;
+            }
+            Ok(())
+        }
+
+        use PathElement::Key;
+        field(
+            result,
+            &mut self.country,
+            &[Key("country"), Key("iso_code")],
+        )?;
+        field(
+            result,
+            &mut self.continent,
+            &[Key("continent"), Key("code")],
+        )?;
+        field(
+            result,
+            &mut self.registered_country,
+            &[Key("registered_country"), Key("iso_code")],
+        )?;
+        field(result, &mut self.asn, &[Key("autonomous_system_number")])?;
+        field(
+            result,
+            &mut self.as_org,
+            &[Key("autonomous_system_organization")],
+        )
+    }
+}
+
+/// Handler for the `geoip` function, holding its databases in memory.
+pub struct GeoIpFunction {
+    databases: Vec<Reader<Vec<u8>>>,
+}
+
+impl GeoIpFunction {
+    /// Load the MaxMind DB file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new().with_database(path)
+    }
+
+    /// Use the MaxMind DB file held in `bytes`.
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::new().with_database_bytes(bytes)
+    }
+
+    /// Also consult the MaxMind DB file at `path`. Databases are consulted
+    /// in the order they were added; the first one to provide a field wins.
+    pub fn with_database(self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| {
+            DataflowError::Io(format!(
+                "cannot read GeoIP database {}: {e}",
+                path.display()
+            ))
+        })?;
+        self.with_database_bytes(bytes)
+    }
+
+    /// Also consult the MaxMind DB file held in `bytes`.
+    pub fn with_database_bytes(mut self, bytes: Vec<u8>) -> Result<Self> {
+        let reader = Reader::from_source(bytes)
+            .map_err(|e| DataflowError::Io(format!("invalid GeoIP database: {e}")))?;
+        self.databases.push(reader);
+        Ok(self)
+    }
+
+    fn new() -> Self {
+        Self {
+            databases: Vec::new(),
+        }
+    }
+
+    /// Look `ip` up in every database; `None` when none knows it.
+    pub fn lookup(&self, ip: IpAddr) -> Result<Option<GeoIpRecord>> {
+        let mut record = GeoIpRecord::default();
+        for database in &self.databases {
+            if ip.is_ipv6() && database.metadata().ip_version == 4 {
+                continue;
+            }
+            let result = database.lookup(ip).map_err(lookup_error)?;
+            if result.has_data() {
+                record.merge(&result)?;
+            }
+        }
+        Ok((!record.is_empty()).then_some(record))
+    }
+}
+
+fn lookup_error(e: maxminddb::MaxMindDbError) -> DataflowError {
+    DataflowError::Task(format!("GeoIP lookup failed: {e}"))
+}
+
+#[async_trait]
+impl AsyncFunctionHandler for GeoIpFunction {
+    type Input = GeoIpConfig;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, input: &GeoIpConfig) -> Result<TaskOutcome> {
+        let record = match ctx.get(&input.ip) {
+            None | Some(OwnedDataValue::Null) => None,
+            Some(OwnedDataValue::String(text)) => {
+                let ip = text.trim().parse::<IpAddr>().map_err(|_| {
+                    DataflowError::Validation(format!(
+                        "geoip: '{text}' at {} is not an IP address",
+                        input.ip
+                    ))
+                })?;
+                self.lookup(ip)?
+            }
+            Some(_) => {
+                return Err(DataflowError::Validation(format!(
+                    "geoip: {} is not a string",
+                    input.ip
+                )));
+            }
+        };
+        let value = serde_json::to_value(record).map_err(DataflowError::from_serde)?;
+        ctx.set_json(&input.target, &value);
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MaxMind DB data-section encoding of the few types the tests need.
+    fn string(s: &str) -> Vec<u8> {
+        let mut out = match s.len() {
+            len @ 0..29 => vec![(2 << 5) | len as u8],
+            len => vec![(2 << 5) | 29, (len - 29) as u8],
+        };
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    fn uint(kind: u8, n: u64) -> Vec<u8> {
+        let bytes: Vec<u8> = n
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        let mut out = match kind {
+            5 | 6 => vec![(kind << 5) | bytes.len() as u8],
+            _ => vec![bytes.len() as u8, kind - 7],
+        };
+        out.extend(bytes);
+        out
+    }
+
+    fn map(entries: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut out = vec![(7 << 5) | entries.len() as u8];
+        for (key, value) in entries {
+            out.extend(string(key));
+            out.extend(value);
+        }
+        out
+    }
+
+    /// An IPv4 database mapping `first_octet.0.0.0/8` to `record`.
+    fn database(first_octet: u8, record: Vec<u8>) -> Vec<u8> {
+        const NODES: u32 = 8;
+        let mut out = Vec::new();
+        for depth in 0..NODES {
+            let bit = (first_octet >> (7 - depth)) & 1;
+            let next = if depth + 1 < NODES {
+                depth + 1
+            } else {
+                NODES + 16
+            };
+            let (left, right) = if bit == 0 {
+                (next, NODES)
+            } else {
+                (NODES, next)
+            };
+            out.extend(&left.to_be_bytes()[1..]);
+            out.extend(&right.to_be_bytes()[1..]);
+        }
+        out.extend([0; 16]);
+        out.extend(record);
+        out.extend(b"\xAB\xCD\xEFMaxMind.com");
+        out.extend(map(&[
+            ("binary_format_major_version", uint(5, 2)),
+            ("binary_format_minor_version", uint(5, 0)),
+            ("build_epoch", uint(9, 1_700_000_000)),
+            ("database_type", string("Test")),
+            ("description", map(&[])),
+            ("ip_version", uint(5, 4)),
+            ("languages", vec![0, 11 - 7]),
+            ("node_count", uint(6, NODES as u64)),
+            ("record_size", uint(5, 24)),
+        ]));
+        out
+    }
+
+    fn geoip() -> GeoIpFunction {
+        let country = database(
+            81,
+            map(&[
+                ("continent", map(&[("code", string("EU"))])),
+                ("country", map(&[("iso_code", string("DE"))])),
+            ]),
+        );
+        let asn = database(
+            81,
+            map(&[
+                ("autonomous_system_number", uint(6, 3320)),
+                (
+                    "autonomous_system_organization",
+                    string("Deutsche Telekom AG"),
+                ),
+            ]),
+        );
+        GeoIpFunction::from_bytes(country)
+            .unwrap()
+            .with_database_bytes(asn)
+            .unwrap()
+    }
+
+    #[test]
+    fn merges_what_every_database_knows() {
+        let record = geoip().lookup("81.2.69.160".parse().unwrap()).unwrap();
+        assert_eq!(
+            record,
+            Some(GeoIpRecord {
+                country: Some("DE".to_string()),
+                continent: Some("EU".to_string()),
+                registered_country: None,
+                asn: Some(3320),
+                as_org: Some("Deutsche Telekom AG".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_addresses_have_no_record() {
+        let geoip = geoip();
+        assert_eq!(geoip.lookup("10.0.0.1".parse().unwrap()).unwrap(), None);
+        assert_eq!(geoip.lookup("2001:db8::1".parse().unwrap()).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn writes_the_record_to_the_target() {
+        use crate::engine::Engine;
+        use crate::engine::message::Message;
+        use crate::engine::workflow::Workflow;
+        use serde_json::json;
+
+        let workflow = Workflow::from_json(
+            r#"{"id": "screen", "name": "Screen", "tasks": [{"id": "geo", "name": "Geo",
+                "function": {"name": "geoip", "input": {"ip": "data.ip", "target": "data.geo"}}}]}"#,
+        )
+        .unwrap();
+        let engine = Engine::builder()
+            .register("geoip", geoip())
+            .with_workflow(workflow)
+            .build()
+            .unwrap();
+
+        let mut message = Message::builder()
+            .data_json(&json!({"ip": "81.2.69.160"}))
+            .build();
+        engine.process_message(&mut message).await.unwrap();
+        let geo = message.data().get("geo").map(serde_json::Value::from);
+        assert_eq!(geo.unwrap()["country"], json!("DE"));
+
+        let mut message = Message::builder()
+            .data_json(&json!({"ip": "10.0.0.1"}))
+            .build();
+        engine.process_message(&mut message).await.unwrap();
+        assert_eq!(
+            message.data().get("geo").map(serde_json::Value::from),
+            Some(json!(null))
+        );
+
+        let mut message = Message::builder().data_json(&json!({"ip": "nope"})).build();
+        assert!(engine.process_message(&mut message).await.is_err());
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_databases() {
+        let err = GeoIpFunction::from_bytes(b"not a database".to_vec())
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("invalid GeoIP database"), "{err}");
+    }
+}
@@ -1,5 +1,5 @@
 use datalogic_rs::Logic;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -8,7 +8,7 @@ use std::sync::Arc;
 ///
 /// The actual HTTP implementation is provided by the service layer via AsyncFunctionHandler.
 /// This struct provides typed config validation and pre-compilation of JSONLogic expressions.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HttpCallConfig {
     /// Named connector reference (resolved by service layer)
     pub connector: String,
@@ -55,7 +55,7 @@ pub struct HttpCallConfig {
 }
 
 /// HTTP methods supported by http_call
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum HttpMethod {
     #[default]
@@ -77,7 +77,7 @@ fn default_timeout() -> u64 {
 /// Configuration for the enrich integration function.
 ///
 /// Enrichment calls an external service and merges the response into the message context.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnrichConfig {
     /// Named connector reference
     pub connector: String,
@@ -111,7 +111,7 @@ pub struct EnrichConfig {
 }
 
 /// What to do when enrichment fails
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum EnrichErrorAction {
     /// Fail the task (default)
@@ -124,7 +124,7 @@ pub enum EnrichErrorAction {
 /// Configuration for the publish_kafka integration function.
 ///
 /// The actual Kafka producer is provided by the service layer via AsyncFunctionHandler.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublishKafkaConfig {
     /// Named connector reference
     pub connector: String,
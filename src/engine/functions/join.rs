@@ -0,0 +1,410 @@
+//! # Join Function Module
+//!
+//! Buffers messages that share a correlation key and lets the message that
+//! completes the group continue as the combined message — request/response
+//! pairing, or reassembling a batch that arrived in pieces:
+//!
+//! ```json
+//! {"name": "join", "input": {
+//!     "key": {"var": "data.batch_id"},
+//!     "count": 3,
+//!     "timeout_ms": 60000,
+//!     "source": "data.item",
+//!     "target": "data.items"
+//! }}
+//! ```
+//!
+//! `key` is pre-compiled JSONLogic; a `null` key is an error. Each message
+//! adds the value at `source` (default `data`) to its key's group, which is
+//! kept in the engine's [`StateStore`](crate::StateStore) under
+//! `join:<workflow_id>:<task_id>:<key>` — or, when none is configured, in
+//! a store private to the engine. The group is complete once it holds
+//! `count` messages, or when a message arrives after `timeout_ms` has
+//! passed since the group was opened. At least one of the two is required.
+//!
+//! - Until then the task halts the workflow for the buffered message.
+//! - The completing message gets every part, in arrival order, written to
+//!   `target` (default `data.parts`). Its `metadata.parent_id` lists the
+//!   ids of the messages buffered before it and `metadata.root_id` is the
+//!   first one's root. The group is then removed.
+//!
+//! Either way `metadata.joins.<workflow_id>.<task_id>` records `key`,
+//! `received` (the group size so far) and `complete`. A message redelivered
+//! while its group is open replaces its earlier part rather than adding a
+//! second one.
+//!
+//! The timeout is checked when a message arrives, so a group whose last
+//! message never comes relies on
+//! [`Engine::expire_joins`](crate::Engine::expire_joins), which completes
+//! every group past its timeout and returns the combined messages. Left
+//! alone, a timed group expires from the store an hour after its timeout
+//! and a count-only group an hour after its last message, and their parts
+//! are dropped.
+//!
+//! Updates to a group are serialised within the engine; engines in
+//! separate processes sharing one store are not coordinated.
+//!
+//! Open groups are listed by
+//! [`Engine::pending_instances`](crate::Engine::pending_instances); a group
+//! that will never complete can be finished with
+//! [`Engine::force_complete`](crate::Engine::force_complete) or dropped
+//! with [`Engine::force_expire`](crate::Engine::force_expire).
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::executor::{ArenaContext, with_arena};
+use crate::engine::instances::{PendingInstance, WaitReason};
+use crate::engine::message::{Change, Message, PARENT_ID, ROOT_ID};
+use crate::engine::state::{InMemoryStateStore, StateStore};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{get_nested_value, replace_with_change};
+use chrono::{DateTime, Utc};
+use datalogic_rs::{Engine, Logic};
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// How long a group outlives its timeout — or, without one, its last
+/// message — in the store when nothing completes it.
+const ABANDONED_GROUP_GRACE: Duration = Duration::from_secs(60 * 60);
+
+/// Configuration for the `join` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct JoinConfig {
+    /// JSONLogic producing the correlation key.
+    pub key: Value,
+
+    /// Complete the group once it holds this many messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+
+    /// Complete the group on the first message arriving this long after
+    /// the group was opened.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+
+    /// Path of the value each message contributes.
+    #[serde(default = "default_source")]
+    pub source: String,
+
+    /// Path the collected values are written to.
+    #[serde(default = "default_target")]
+    pub target: String,
+
+    /// Pre-compiled `key`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_key: Option<Arc<Logic>>,
+
+    /// State store key prefix of this task's groups, populated by
+    /// `LogicCompiler`.
+    #[serde(skip)]
+    pub group_prefix: String,
+
+    /// Context path the join status is written to, populated by
+    /// `LogicCompiler`.
+    #[serde(skip)]
+    pub status_path: String,
+}
+
+fn default_source() -> String {
+    "data".to_string()
+}
+
+fn default_target() -> String {
+    "data.parts".to_string()
+}
+
+/// The open groups of every `join` task of an engine, shared with engines
+/// derived by `with_new_workflows`.
+pub(crate) struct JoinState {
+    store: Arc<dyn StateStore>,
+    lock: Mutex<()>,
+}
+
+impl JoinState {
+    /// Groups kept in `store`, or in memory when there is none.
+    pub(crate) fn new(store: Option<Arc<dyn StateStore>>) -> Self {
+        Self {
+            store: store.unwrap_or_else(|| Arc::new(InMemoryStateStore::new())),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// The store groups are kept in — the engine's state store.
+    pub(crate) fn store(&self) -> &Arc<dyn StateStore> {
+        &self.store
+    }
+
+    /// Remove group `store_key`, serialised with the updates to groups.
+    pub(crate) async fn discard(&self, store_key: &str) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        self.store.delete(store_key).await
+    }
+}
+
+impl Default for JoinState {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// The group stored under `store_key`, if open.
+async fn load_group(joins: &JoinState, store_key: &str) -> Result<Option<Group>> {
+    joins
+        .store
+        .get(store_key)
+        .await?
+        .map(|value| {
+            serde_json::from_value(value).map_err(|e| {
+                DataflowError::Validation(format!("join: corrupt group '{store_key}': {e}"))
+            })
+        })
+        .transpose()
+}
+
+/// A group as kept in the state store.
+#[derive(Debug, Serialize, Deserialize)]
+struct Group {
+    opened_at: DateTime<Utc>,
+    parts: Vec<Part>,
+}
+
+/// One message's contribution to a group.
+#[derive(Debug, Serialize, Deserialize)]
+struct Part {
+    message_id: String,
+    root_id: String,
+    value: Value,
+}
+
+impl JoinConfig {
+    /// Add the message to its group and, once the group is complete, write
+    /// the combined parts to it.
+    pub(crate) async fn execute(
+        &self,
+        message: &mut Message,
+        joins: &JoinState,
+        engine: &Engine,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let key = self.correlation_key(message, engine)?;
+        let store_key = format!("{}:{}", self.group_prefix, key);
+        let part = Part {
+            message_id: message.id().to_string(),
+            root_id: message.root_id().to_string(),
+            value: get_nested_value(&message.context, &self.source)
+                .map(|value| serde_json::to_value(value).unwrap_or(Value::Null))
+                .unwrap_or(Value::Null),
+        };
+
+        let _guard = joins.lock.lock().await;
+        let now = Utc::now();
+        let mut group = load_group(joins, &store_key).await?.unwrap_or(Group {
+            opened_at: now,
+            parts: Vec::new(),
+        });
+        match group
+            .parts
+            .iter_mut()
+            .find(|p| p.message_id == part.message_id)
+        {
+            Some(existing) => *existing = part,
+            None => group.parts.push(part),
+        }
+
+        let timeout = self.timeout_ms.map(Duration::from_millis);
+        let complete = self.count.is_some_and(|count| group.parts.len() >= count)
+            || timeout.is_some_and(|timeout| now >= group.opened_at + timeout);
+        let received = group.parts.len();
+        debug!(
+            "Join {}: {} message(s), complete: {}",
+            store_key, received, complete
+        );
+
+        let mut changes = Vec::new();
+        if complete {
+            joins.store.delete(&store_key).await?;
+            changes.extend(self.combine(message, group));
+        } else {
+            let ttl = match timeout {
+                Some(timeout) => {
+                    let elapsed = (now - group.opened_at).to_std().unwrap_or_default();
+                    timeout.saturating_sub(elapsed) + ABANDONED_GROUP_GRACE
+                }
+                None => ABANDONED_GROUP_GRACE,
+            };
+            let value = serde_json::to_value(&group).unwrap_or(Value::Null);
+            joins.store.set(&store_key, value, Some(ttl)).await?;
+        }
+
+        let status = serde_json::json!({"key": key, "received": received, "complete": complete});
+        changes.extend(replace_with_change(
+            message,
+            &self.status_path,
+            OwnedDataValue::from(&status),
+        ));
+        let outcome = if complete {
+            TaskOutcome::Success
+        } else {
+            TaskOutcome::Halt
+        };
+        Ok((outcome, changes))
+    }
+
+    /// This task's open groups, one instance per correlation key.
+    pub(crate) async fn pending(
+        &self,
+        joins: &JoinState,
+        workflow_id: &str,
+        task_id: &str,
+    ) -> Result<Vec<PendingInstance>> {
+        let prefix = format!("{}:", self.group_prefix);
+        let mut pending = Vec::new();
+        for store_key in joins.store.keys_with_prefix(&prefix).await? {
+            let Some(group) = load_group(joins, &store_key).await? else {
+                continue;
+            };
+            let timeout = self.timeout_ms.map(Duration::from_millis);
+            pending.push(PendingInstance {
+                workflow_id: workflow_id.to_string(),
+                task_id: task_id.to_string(),
+                key: store_key[prefix.len()..].to_string(),
+                opened_at: Some(group.opened_at),
+                reason: WaitReason::Join {
+                    message_ids: group.parts.into_iter().map(|p| p.message_id).collect(),
+                    count: self.count,
+                    timeout_at: timeout.map(|timeout| group.opened_at + timeout),
+                },
+            });
+        }
+        Ok(pending)
+    }
+
+    /// Complete group `key` with the parts it holds, writing them to
+    /// `message` as the message completing it would get them. Fails when
+    /// the group is not open.
+    pub(crate) async fn force_complete(
+        &self,
+        message: &mut Message,
+        joins: &JoinState,
+        key: &str,
+    ) -> Result<Vec<Change>> {
+        self.complete_if(message, joins, key, |_| true)
+            .await?
+            .ok_or_else(|| {
+                DataflowError::Validation(format!(
+                    "join: no open group '{}:{}'",
+                    self.group_prefix, key
+                ))
+            })
+    }
+
+    /// Complete group `key` as [`force_complete`](Self::force_complete)
+    /// does if its timeout passed by `now`. `None` when the group is gone,
+    /// still within its timeout, or the task has no timeout.
+    pub(crate) async fn expire(
+        &self,
+        message: &mut Message,
+        joins: &JoinState,
+        key: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Option<Vec<Change>>> {
+        let Some(timeout) = self.timeout_ms.map(Duration::from_millis) else {
+            return Ok(None);
+        };
+        self.complete_if(message, joins, key, |group| {
+            now >= group.opened_at + timeout
+        })
+        .await
+    }
+
+    /// Remove group `key` and write its parts to `message` when it is open
+    /// and `due` holds for it.
+    async fn complete_if(
+        &self,
+        message: &mut Message,
+        joins: &JoinState,
+        key: &str,
+        due: impl FnOnce(&Group) -> bool,
+    ) -> Result<Option<Vec<Change>>> {
+        let store_key = format!("{}:{}", self.group_prefix, key);
+        let _guard = joins.lock.lock().await;
+        let Some(group) = load_group(joins, &store_key).await?.filter(|g| due(g)) else {
+            return Ok(None);
+        };
+        joins.store.delete(&store_key).await?;
+        debug!(
+            "Join {}: completed early with {} message(s)",
+            store_key,
+            group.parts.len()
+        );
+        let received = group.parts.len();
+        let mut changes = self.combine(message, group);
+        let status = serde_json::json!({"key": key, "received": received, "complete": true});
+        changes.extend(replace_with_change(
+            message,
+            &self.status_path,
+            OwnedDataValue::from(&status),
+        ));
+        Ok(Some(changes))
+    }
+
+    /// Evaluate `key` to the string groups are stored under.
+    fn correlation_key(&self, message: &Message, engine: &Engine) -> Result<String> {
+        let compiled = self
+            .compiled_key
+            .as_ref()
+            .ok_or_else(|| DataflowError::Validation("join: key is not compiled".to_string()))?;
+        let key = with_arena(|arena| {
+            let arena_ctx = ArenaContext::from_owned(&message.context, arena);
+            engine
+                .evaluate(compiled, arena_ctx.as_data_value(), arena)
+                .map(|value| value.to_owned())
+                .map_err(|e| DataflowError::LogicEvaluation(format!("join key: {e}")))
+        })?;
+        match key {
+            OwnedDataValue::Null => Err(DataflowError::Validation(
+                "join: correlation key is null".to_string(),
+            )),
+            OwnedDataValue::String(key) => Ok(key),
+            other => Ok(serde_json::to_string(&other).unwrap_or_default()),
+        }
+    }
+
+    /// Write the parts of a complete group and its lineage to `message`.
+    fn combine(&self, message: &mut Message, group: Group) -> Vec<Change> {
+        let own_id = message.id().to_string();
+        let root_id = group.parts.first().map(|p| p.root_id.clone());
+        let parent_ids: Vec<OwnedDataValue> = group
+            .parts
+            .iter()
+            .filter(|p| p.message_id != own_id)
+            .map(|p| OwnedDataValue::String(p.message_id.clone()))
+            .collect();
+        let values = group
+            .parts
+            .iter()
+            .map(|p| OwnedDataValue::from(&p.value))
+            .collect();
+
+        let mut changes = replace_with_change(message, &self.target, OwnedDataValue::Array(values));
+        if !parent_ids.is_empty()
+            && let Some(root_id) = root_id
+        {
+            changes.extend(replace_with_change(
+                message,
+                &format!("metadata.{PARENT_ID}"),
+                OwnedDataValue::Array(parent_ids),
+            ));
+            changes.extend(replace_with_change(
+                message,
+                &format!("metadata.{ROOT_ID}"),
+                OwnedDataValue::String(root_id),
+            ));
+        }
+        changes
+    }
+}
@@ -5,13 +5,13 @@ use crate::engine::task_outcome::TaskOutcome;
 use datalogic_rs::{Engine, Logic};
 use datavalue::DataValue;
 use log::{debug, error, info, trace, warn};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 /// Log levels supported by the log function
-#[derive(Debug, Clone, Default, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
     Trace,
@@ -25,7 +25,7 @@ pub enum LogLevel {
 /// Configuration for the log function.
 ///
 /// The message and field expressions are pre-compiled at startup.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogConfig {
     /// Log level to emit at
     #[serde(default)]
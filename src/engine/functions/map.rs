@@ -21,19 +21,19 @@ use crate::engine::utils::{get_nested_value_parts, set_nested_value_parts};
 use datalogic_rs::{Engine, Logic};
 use datavalue::OwnedDataValue;
 use log::{debug, error};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
 /// Configuration for the map function containing a list of mappings.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MapConfig {
     /// List of mappings to execute in order.
     pub mappings: Vec<MapMapping>,
 }
 
 /// A single mapping that transforms and assigns data.
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct MapMapping {
     /// Target path where the result will be stored (e.g., "data.user.name").
     /// Supports dot notation for nested paths and `#` prefix for numeric field names.
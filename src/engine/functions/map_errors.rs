@@ -0,0 +1,250 @@
+//! # Map Errors Function Module
+//!
+//! Translates the errors collected on a message into the list a partner or
+//! scheme expects, through a mapping table:
+//!
+//! ```json
+//! {"name": "map_errors", "input": {
+//!     "mappings": [
+//!         {"from": "VALIDATION_ERROR", "task_id": "check_account", "code": "AC01", "message": "Incorrect account number"},
+//!         {"from": "VALIDATION_ERROR", "code": "FF01", "message": "Invalid file format"},
+//!         {"from": "HTTP_ERROR", "code": "MS03", "severity": "fatal"}
+//!     ],
+//!     "default": {"code": "NARR", "message": "Unspecified error"},
+//!     "target": "data.response.errors"
+//! }}
+//! ```
+//!
+//! Each error in `message.errors` is matched against the mappings in order:
+//! a mapping applies when `from` equals the error's code and, if given,
+//! `task_id` equals the task the error came from (failed tasks record
+//! their id; validation errors carry none). The first match supplies
+//! the external `code`, `severity` (default `error`) and `message` (default
+//! the error's own). An error no mapping matches takes `default` when set,
+//! and otherwise passes through with its own code and message. Every error
+//! produces one entry, in order, written to `target` (default
+//! `data.errors`):
+//!
+//! ```json
+//! [{"code": "AC01", "severity": "error", "message": "Incorrect account number", "path": "data.account"}]
+//! ```
+//!
+//! `path` is included when the error has one. `message.errors` itself is
+//! left as it is, so the audit trail and error handling keep the internal
+//! codes.
+
+use crate::engine::error::{ErrorInfo, Result};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::replace_with_change;
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for the `map_errors` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MapErrorsConfig {
+    /// Mappings, tried in order.
+    pub mappings: Vec<ErrorMapping>,
+
+    /// Applied to errors no mapping matches; without it they pass through.
+    #[serde(default)]
+    pub default: Option<ErrorTranslation>,
+
+    /// Path the external error list is written to.
+    #[serde(default = "default_target")]
+    pub target: String,
+}
+
+/// One row of a [`MapErrorsConfig`] table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorMapping {
+    /// Internal error code matched, e.g. `VALIDATION_ERROR`.
+    pub from: String,
+
+    /// Only match errors raised by this task.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+
+    /// External code, e.g. an ISO 20022 reason code.
+    pub code: String,
+
+    #[serde(default)]
+    pub severity: ErrorSeverity,
+
+    /// External text; the error's own message when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// The external form given to errors no [`ErrorMapping`] matches.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorTranslation {
+    pub code: String,
+
+    #[serde(default)]
+    pub severity: ErrorSeverity,
+
+    /// External text; the error's own message when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Severity of an externally reported error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorSeverity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+    Fatal,
+}
+
+impl ErrorSeverity {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+            Self::Fatal => "fatal",
+        }
+    }
+}
+
+fn default_target() -> String {
+    "data.errors".to_string()
+}
+
+impl MapErrorsConfig {
+    /// The external `(code, severity, message)` of `error`.
+    fn translate<'a>(&'a self, error: &'a ErrorInfo) -> (&'a str, ErrorSeverity, &'a str) {
+        let mapped = self.mappings.iter().find(|mapping| {
+            mapping.from == error.code
+                && mapping
+                    .task_id
+                    .as_ref()
+                    .is_none_or(|task| error.task_id.as_ref() == Some(task))
+        });
+        match (mapped, &self.default) {
+            (Some(m), _) => (
+                &m.code,
+                m.severity,
+                m.message.as_deref().unwrap_or(&error.message),
+            ),
+            (None, Some(d)) => (
+                &d.code,
+                d.severity,
+                d.message.as_deref().unwrap_or(&error.message),
+            ),
+            (None, None) => (&error.code, ErrorSeverity::Error, &error.message),
+        }
+    }
+
+    fn entry(&self, error: &ErrorInfo) -> OwnedDataValue {
+        let (code, severity, text) = self.translate(error);
+        let mut entry = vec![
+            ("code".to_string(), OwnedDataValue::String(code.to_string())),
+            (
+                "severity".to_string(),
+                OwnedDataValue::String(severity.as_str().to_string()),
+            ),
+            (
+                "message".to_string(),
+                OwnedDataValue::String(text.to_string()),
+            ),
+        ];
+        if let Some(path) = &error.path {
+            entry.push(("path".to_string(), OwnedDataValue::String(path.clone())));
+        }
+        OwnedDataValue::Object(entry)
+    }
+}
+
+/// Execute `map_errors`: write the external form of `message.errors` to
+/// `config.target`.
+pub fn execute_map_errors(
+    message: &mut Message,
+    config: &MapErrorsConfig,
+) -> Result<(TaskOutcome, Vec<Change>)> {
+    debug!(
+        "MapErrors: {} error(s) -> '{}'",
+        message.errors.len(),
+        config.target
+    );
+    let entries = message
+        .errors
+        .iter()
+        .map(|error| config.entry(error))
+        .collect();
+    let changes = replace_with_change(message, &config.target, OwnedDataValue::Array(entries));
+    Ok((TaskOutcome::Success, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn maps_errors_through_the_table_in_order() {
+        let config: MapErrorsConfig = serde_json::from_value(json!({
+            "mappings": [
+                {"from": "VALIDATION_ERROR", "task_id": "check_account", "code": "AC01", "message": "Incorrect account number"},
+                {"from": "VALIDATION_ERROR", "code": "FF01"},
+                {"from": "HTTP_ERROR", "code": "MS03", "severity": "fatal"}
+            ],
+            "target": "data.response.errors"
+        }))
+        .unwrap();
+        let mut message = Message::from_value(&json!({}));
+        message.add_error(
+            ErrorInfo::builder("VALIDATION_ERROR", "account 12 unknown")
+                .task_id("check_account")
+                .path("data.account")
+                .build(),
+        );
+        message.add_error(
+            ErrorInfo::builder("VALIDATION_ERROR", "amount missing")
+                .task_id("check_amount")
+                .build(),
+        );
+        message.add_error(ErrorInfo::builder("HTTP_ERROR", "503 from ledger").build());
+        message.add_error(ErrorInfo::builder("TASK_ERROR", "boom").build());
+
+        let (outcome, changes) = execute_map_errors(&mut message, &config).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            Value::from(&message.context)["data"]["response"]["errors"],
+            json!([
+                {"code": "AC01", "severity": "error", "message": "Incorrect account number", "path": "data.account"},
+                {"code": "FF01", "severity": "error", "message": "amount missing"},
+                {"code": "MS03", "severity": "fatal", "message": "503 from ledger"},
+                {"code": "TASK_ERROR", "severity": "error", "message": "boom"}
+            ])
+        );
+        assert_eq!(message.errors[0].code, "VALIDATION_ERROR");
+    }
+
+    #[test]
+    fn default_covers_unmapped_errors() {
+        let config: MapErrorsConfig = serde_json::from_value(json!({
+            "mappings": [],
+            "default": {"code": "NARR", "severity": "warning", "message": "Unspecified error"}
+        }))
+        .unwrap();
+        let mut message = Message::from_value(&json!({}));
+        message.add_error(ErrorInfo::builder("TASK_ERROR", "boom").build());
+
+        let (outcome, _) = execute_map_errors(&mut message, &config).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(
+            Value::from(&message.context)["data"]["errors"],
+            json!([{"code": "NARR", "severity": "warning", "message": "Unspecified error"}])
+        );
+    }
+}
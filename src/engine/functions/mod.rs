@@ -1,3 +1,4 @@
+use crate::engine::capability::Capability;
 use crate::engine::error::{DataflowError, Result};
 use crate::engine::task_context::TaskContext;
 use crate::engine::task_outcome::TaskOutcome;
@@ -10,7 +11,10 @@ pub mod config;
 pub use config::{CompiledCustomInput, FunctionConfig};
 
 pub mod validation;
-pub use validation::{ValidationConfig, ValidationRule};
+pub use validation::{
+    AggregateComparison, AggregateExpected, AggregateOp, AggregateRule, RequiredRule,
+    ValidationConfig, ValidationRule,
+};
 
 pub mod map;
 pub use map::{MapConfig, MapMapping};
@@ -27,9 +31,53 @@ pub use filter::{FilterConfig, RejectAction};
 pub mod log;
 pub use log::{LogConfig, LogLevel};
 
+pub mod project;
+pub use project::ProjectConfig;
+
+pub mod flatten;
+pub use flatten::{ArrayMode, FlattenConfig};
+
+pub mod rename_keys;
+pub use rename_keys::{KeyCase, RenameKeysConfig};
+
+pub mod sample;
+pub use sample::SampleConfig;
+
+pub mod foreach;
+pub use foreach::ForeachConfig;
+
+pub mod reject;
+pub use reject::RejectConfig;
+
+pub mod score;
+pub use score::{ScoreConfig, ScoreRule};
+
+pub mod map_errors;
+pub use map_errors::{ErrorMapping, ErrorSeverity, ErrorTranslation, MapErrorsConfig};
+
+pub mod join;
+pub use join::JoinConfig;
+
+pub mod state;
+pub use state::{StateGetConfig, StateSetConfig};
+
+pub mod alert;
+pub use alert::AlertConfig;
+
+pub mod switch;
+pub use switch::{SwitchBranch, SwitchCase, SwitchConfig};
+
+pub mod transformer;
+pub use transformer::{MessageTransformer, Transformer};
+
 pub mod integration;
 pub use integration::{EnrichConfig, HttpCallConfig, PublishKafkaConfig};
 
+#[cfg(feature = "geoip")]
+pub mod geoip;
+#[cfg(feature = "geoip")]
+pub use geoip::{GeoIpConfig, GeoIpFunction, GeoIpRecord};
+
 /// Async interface for task functions that operate on messages.
 ///
 /// Implement this trait for custom processing logic. The trait associates a
@@ -108,6 +156,13 @@ pub trait AsyncFunctionHandler: Send + Sync + 'static {
     /// pushed via its `set` family; the workflow executor folds them into
     /// the audit trail when this method returns.
     async fn execute(&self, ctx: &mut TaskContext<'_>, input: &Self::Input) -> Result<TaskOutcome>;
+
+    /// Capabilities the handler uses, checked against the engine's allowed
+    /// set at construction (see [`capability`](crate::engine::capability)).
+    /// Default: none.
+    fn capabilities(&self) -> &'static [Capability] {
+        &[]
+    }
 }
 
 /// Object-safe sibling of [`AsyncFunctionHandler`]. Engine-internal — users
@@ -130,6 +185,11 @@ pub trait DynAsyncFunctionHandler: Send + Sync + 'static {
         ctx: &mut TaskContext<'_>,
         input: &(dyn Any + Send + Sync),
     ) -> Result<TaskOutcome>;
+
+    /// The handler's [`AsyncFunctionHandler::capabilities`].
+    fn dyn_capabilities(&self) -> &'static [Capability] {
+        &[]
+    }
 }
 
 #[async_trait]
@@ -152,6 +212,10 @@ impl<F: AsyncFunctionHandler> DynAsyncFunctionHandler for F {
         })?;
         AsyncFunctionHandler::execute(self, ctx, typed).await
     }
+
+    fn dyn_capabilities(&self) -> &'static [Capability] {
+        AsyncFunctionHandler::capabilities(self)
+    }
 }
 
 /// Boxed handler stored in the engine's function registry. Users construct
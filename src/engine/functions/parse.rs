@@ -9,6 +9,16 @@
 //! - `"payload.<path>"` — a nested field of the payload
 //! - `"data.<path>"` — a nested field of the existing data context
 //! - `"<path>"` — anything else is resolved against the full context
+//!
+//! ## Streaming extraction
+//!
+//! When `extract` lists dot-paths, a string source is scanned with an
+//! event-based parser and only the listed subtrees are materialized; every
+//! other subtree is skipped without allocating. This keeps multi-hundred-MB
+//! batch files within memory limits when a workflow only needs a handful of
+//! fields. The result keeps the document shape: `extract: ["header.id"]`
+//! stores `{"header": {"id": ...}}` under `data.{target}`. Paths that don't
+//! occur in the document are left out.
 
 use crate::engine::error::{DataflowError, Result};
 use crate::engine::executor::ArenaContext;
@@ -17,18 +27,24 @@ use crate::engine::task_outcome::TaskOutcome;
 use crate::engine::utils::{get_nested_value, set_nested_value};
 use datavalue::OwnedDataValue;
 use log::debug;
-use serde::Deserialize;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
 /// Configuration for parse functions.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ParseConfig {
     /// Source path to read from.
     pub source: String,
 
     /// Target field name in `data` (stored at `data.{target}`).
     pub target: String,
+
+    /// Dot-paths to extract. When set, string sources are stream-parsed and
+    /// only these subtrees are kept (see the module docs).
+    #[serde(default)]
+    pub extract: Option<Vec<String>>,
 }
 
 impl ParseConfig {
@@ -49,27 +65,79 @@ impl ParseConfig {
             })?
             .to_string();
 
-        Ok(ParseConfig { source, target })
+        let extract = match input.get("extract") {
+            None | Some(Value::Null) => None,
+            Some(Value::Array(paths)) => Some(
+                paths
+                    .iter()
+                    .map(|p| {
+                        p.as_str().map(str::to_string).ok_or_else(|| {
+                            DataflowError::Validation(
+                                "'extract' in parse config must be an array of strings".to_string(),
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Some(_) => {
+                return Err(DataflowError::Validation(
+                    "'extract' in parse config must be an array of strings".to_string(),
+                ));
+            }
+        };
+
+        Ok(ParseConfig {
+            source,
+            target,
+            extract,
+        })
     }
 
-    /// Extract the source value as an owned `OwnedDataValue`.
-    fn extract_source(&self, message: &Message) -> OwnedDataValue {
+    /// Borrow the source value, if present.
+    fn source_ref<'m>(&self, message: &'m Message) -> Option<&'m OwnedDataValue> {
         if self.source == "payload" {
-            (*message.payload).clone()
+            Some(&message.payload)
         } else if let Some(path) = self.source.strip_prefix("payload.") {
             get_nested_value(&message.payload, path)
-                .cloned()
-                .unwrap_or(OwnedDataValue::Null)
         } else if let Some(path) = self.source.strip_prefix("data.") {
             get_nested_value(message.data(), path)
-                .cloned()
-                .unwrap_or(OwnedDataValue::Null)
         } else {
             get_nested_value(&message.context, &self.source)
-                .cloned()
-                .unwrap_or(OwnedDataValue::Null)
         }
     }
+
+    /// Extract the source value as an owned `OwnedDataValue`.
+    fn extract_source(&self, message: &Message) -> OwnedDataValue {
+        self.source_ref(message)
+            .cloned()
+            .unwrap_or(OwnedDataValue::Null)
+    }
+}
+
+/// Store the extracted data under `data.{target}`, recording a change when
+/// audit capture is on.
+fn store_extracted(
+    message: &mut Message,
+    config: &ParseConfig,
+    value: OwnedDataValue,
+) -> (TaskOutcome, Vec<Change>) {
+    let target_path = format!("data.{}", config.target);
+    if !message.capture_changes {
+        set_nested_value(&mut message.context, &target_path, value);
+        return (TaskOutcome::Success, Vec::new());
+    }
+    let old_value = get_nested_value(&message.context, &target_path)
+        .cloned()
+        .unwrap_or(OwnedDataValue::Null);
+    set_nested_value(&mut message.context, &target_path, value.clone());
+    (
+        TaskOutcome::Success,
+        vec![Change {
+            path: Arc::from(target_path),
+            old_value,
+            new_value: value,
+        }],
+    )
 }
 
 /// Execute `parse_json`: read the source value and store it under `data.{target}`.
@@ -84,6 +152,15 @@ pub fn execute_parse_json(
         config.source, config.target
     );
 
+    if let Some(paths) = &config.extract {
+        let extracted = match config.source_ref(message) {
+            Some(OwnedDataValue::String(s)) => stream_extract_json(s, paths)?,
+            Some(value) => select_paths(value, paths),
+            None => OwnedDataValue::Object(Vec::new()),
+        };
+        return Ok(store_extracted(message, config, extracted));
+    }
+
     let target_path = format!("data.{}", config.target);
 
     // Hot path: source == "payload" and not a JSON-string payload. The
@@ -187,10 +264,8 @@ pub fn execute_parse_xml(
         config.source, config.target
     );
 
-    let source_data = config.extract_source(message);
-
-    let xml_string = match &source_data {
-        OwnedDataValue::String(s) => s.clone(),
+    let xml_string = match config.source_ref(message) {
+        Some(OwnedDataValue::String(s)) => s,
         _ => {
             return Err(DataflowError::Validation(format!(
                 "ParseXml: Source '{}' is not a string",
@@ -199,7 +274,12 @@ pub fn execute_parse_xml(
         }
     };
 
-    let parsed_json = xml_to_json(&xml_string)?;
+    if let Some(paths) = &config.extract {
+        let extracted = stream_extract_xml(xml_string, paths)?;
+        return Ok(store_extracted(message, config, extracted));
+    }
+
+    let parsed_json = xml_to_json(xml_string)?;
     let parsed_owned = OwnedDataValue::from(&parsed_json);
 
     let target_path = format!("data.{}", config.target);
@@ -234,6 +314,278 @@ fn xml_to_json(xml: &str) -> Result<Value> {
     Ok(parsed)
 }
 
+/// Copy the listed paths out of an already-structured value.
+fn select_paths(value: &OwnedDataValue, paths: &[String]) -> OwnedDataValue {
+    let mut out = OwnedDataValue::Object(Vec::new());
+    for path in paths {
+        if let Some(found) = get_nested_value(value, path) {
+            set_nested_value(&mut out, path, found.clone());
+        }
+    }
+    out
+}
+
+/// Scan a JSON document and materialize only the subtrees at `paths`.
+fn stream_extract_json(json: &str, paths: &[String]) -> Result<OwnedDataValue> {
+    let targets: Vec<Vec<&str>> = paths.iter().map(|p| p.split('.').collect()).collect();
+    let mut found: Vec<Option<OwnedDataValue>> = vec![None; targets.len()];
+    let mut de = serde_json::Deserializer::from_str(json);
+    Select {
+        targets: &targets,
+        active: (0..targets.len()).collect(),
+        depth: 0,
+        found: &mut found,
+    }
+    .deserialize(&mut de)
+    .and_then(|()| de.end())
+    .map_err(|e| DataflowError::Validation(format!("Failed to parse JSON: {}", e)))?;
+
+    let mut out = OwnedDataValue::Object(Vec::new());
+    for (path, value) in paths.iter().zip(found) {
+        if let Some(value) = value {
+            set_nested_value(&mut out, path, value);
+        }
+    }
+    Ok(out)
+}
+
+/// Deserialize seed positioned `depth` segments into the document. `active`
+/// holds the targets whose first `depth` segments match the current path.
+struct Select<'a, 'b> {
+    targets: &'a [Vec<&'a str>],
+    active: Vec<usize>,
+    depth: usize,
+    found: &'b mut Vec<Option<OwnedDataValue>>,
+}
+
+impl Select<'_, '_> {
+    /// Seed for the child at `segment`, or `None` when no target goes there.
+    fn child(&mut self, segment: &str) -> Option<Select<'_, '_>> {
+        let active: Vec<usize> = self
+            .active
+            .iter()
+            .copied()
+            .filter(|&i| self.targets[i][self.depth] == segment)
+            .collect();
+        if active.is_empty() {
+            return None;
+        }
+        Some(Select {
+            targets: self.targets,
+            active,
+            depth: self.depth + 1,
+            found: self.found,
+        })
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Select<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), D::Error> {
+        if !self
+            .active
+            .iter()
+            .any(|&i| self.targets[i].len() == self.depth)
+        {
+            return deserializer.deserialize_any(self);
+        }
+        // A target ends here: materialize this subtree and serve any deeper
+        // targets from it.
+        let value = OwnedDataValue::deserialize(deserializer)?;
+        for &i in &self.active {
+            let rest = self.targets[i][self.depth..].join(".");
+            let hit = if rest.is_empty() {
+                Some(&value)
+            } else {
+                get_nested_value(&value, &rest)
+            };
+            self.found[i] = hit.cloned();
+        }
+        Ok(())
+    }
+}
+
+impl<'de> Visitor<'de> for Select<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> std::result::Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match self.child(&key) {
+                Some(seed) => map.next_value_seed(seed)?,
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> std::result::Result<(), A::Error> {
+        let mut index = 0usize;
+        loop {
+            let more = match self.child(&index.to_string()) {
+                Some(seed) => seq.next_element_seed(seed)?.is_some(),
+                None => seq.next_element::<IgnoredAny>()?.is_some(),
+            };
+            if !more {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    // Scalars where a container was expected: the path doesn't exist.
+    fn visit_bool<E>(self, _: bool) -> std::result::Result<(), E> {
+        Ok(())
+    }
+    fn visit_i64<E>(self, _: i64) -> std::result::Result<(), E> {
+        Ok(())
+    }
+    fn visit_u64<E>(self, _: u64) -> std::result::Result<(), E> {
+        Ok(())
+    }
+    fn visit_f64<E>(self, _: f64) -> std::result::Result<(), E> {
+        Ok(())
+    }
+    fn visit_str<E>(self, _: &str) -> std::result::Result<(), E> {
+        Ok(())
+    }
+    fn visit_unit<E>(self) -> std::result::Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Scan an XML document and materialize only the elements at `paths`.
+/// Paths are element names below the root (the root is dropped, as in the
+/// full parse), prefixed elements by their local name (`item` for
+/// `ns:item`), as the full parse keys them; repeated matches become an
+/// array.
+fn stream_extract_xml(xml: &str, paths: &[String]) -> Result<OwnedDataValue> {
+    use quick_xml::Reader;
+    use quick_xml::events::Event;
+
+    let xml_err =
+        |e: quick_xml::Error| DataflowError::Validation(format!("Failed to parse XML: {}", e));
+    let targets: Vec<Vec<&str>> = paths.iter().map(|p| p.split('.').collect()).collect();
+    let mut found: Vec<Vec<OwnedDataValue>> = vec![Vec::new(); targets.len()];
+    let mut reader = Reader::from_str(xml);
+    let mut in_root = false;
+    // Local names of the elements below the root: the full parse keys
+    // `ns:item` as `item`.
+    let mut stack: Vec<String> = Vec::new();
+
+    loop {
+        match reader.read_event().map_err(xml_err)? {
+            Event::Start(_) if !in_root => in_root = true,
+            Event::Start(start) => {
+                stack.push(local_name(&start));
+                let exact: Vec<usize> = (0..targets.len())
+                    .filter(|&i| {
+                        targets[i].len() == stack.len() && starts_with(&targets[i], &stack)
+                    })
+                    .collect();
+                let deeper: Vec<usize> = (0..targets.len())
+                    .filter(|&i| targets[i].len() > stack.len() && starts_with(&targets[i], &stack))
+                    .collect();
+                if exact.is_empty() && !deeper.is_empty() {
+                    continue;
+                }
+                // Nothing below is wanted except this element itself: jump to
+                // its end tag without building events for the subtree.
+                let span = reader.read_to_end(start.name()).map_err(xml_err)?;
+                let depth = stack.len();
+                let name = stack.pop().unwrap_or_default();
+                if exact.is_empty() {
+                    continue;
+                }
+                let value = xml_element_value(
+                    &name,
+                    &format!(
+                        "<{}>{}</{}>",
+                        String::from_utf8_lossy(&start),
+                        &xml[span.start as usize..span.end as usize],
+                        String::from_utf8_lossy(start.name().as_ref()),
+                    ),
+                )?;
+                // Deeper targets are inside the element: serve them from it.
+                for i in deeper {
+                    if let Some(hit) = get_nested_value(&value, &targets[i][depth..].join(".")) {
+                        found[i].push(hit.clone());
+                    }
+                }
+                for i in exact {
+                    found[i].push(value.clone());
+                }
+            }
+            Event::Empty(start) if in_root => {
+                let name = local_name(&start);
+                let mut value = None;
+                for (i, target) in targets.iter().enumerate() {
+                    if target.len() == stack.len() + 1
+                        && starts_with(target, &stack)
+                        && target[stack.len()] == name
+                    {
+                        let value = match &value {
+                            Some(v) => v,
+                            None => value.insert(xml_element_value(
+                                &name,
+                                &format!("<{}/>", String::from_utf8_lossy(&start)),
+                            )?),
+                        };
+                        found[i].push(value.clone());
+                    }
+                }
+            }
+            // The root's end tag.
+            Event::End(_) if stack.is_empty() => break,
+            Event::End(_) => {
+                stack.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut out = OwnedDataValue::Object(Vec::new());
+    for (path, mut values) in paths.iter().zip(found) {
+        let value = match values.len() {
+            0 => continue,
+            1 => values.remove(0),
+            _ => OwnedDataValue::Array(values),
+        };
+        set_nested_value(&mut out, path, value);
+    }
+    Ok(out)
+}
+
+/// Name of `start` without its namespace prefix.
+fn local_name(start: &quick_xml::events::BytesStart) -> String {
+    String::from_utf8_lossy(start.local_name().as_ref()).into_owned()
+}
+
+/// Whether `path` begins with the element names in `stack`.
+fn starts_with(path: &[&str], stack: &[String]) -> bool {
+    path.len() >= stack.len() && path.iter().zip(stack).all(|(a, b)| a == b)
+}
+
+/// Convert one extracted element exactly as the full parse converts it as a
+/// child: parse it under a throwaway root and take the `name` field.
+fn xml_element_value(name: &str, element: &str) -> Result<OwnedDataValue> {
+    let value = xml_to_json(&format!("<root>{}</root>", element))?;
+    Ok(value
+        .get(name)
+        .map(OwnedDataValue::from)
+        .unwrap_or(OwnedDataValue::Null))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -269,6 +621,7 @@ mod tests {
         let config = ParseConfig {
             source: "payload".to_string(),
             target: "input".to_string(),
+            extract: None,
         };
 
         let result = execute_parse_json(&mut message, &config);
@@ -291,6 +644,7 @@ mod tests {
         let config = ParseConfig {
             source: "payload.body.user".to_string(),
             target: "user_data".to_string(),
+            extract: None,
         };
 
         let result = execute_parse_json(&mut message, &config);
@@ -313,6 +667,7 @@ mod tests {
         let config = ParseConfig {
             source: "data.existing".to_string(),
             target: "copied".to_string(),
+            extract: None,
         };
 
         let result = execute_parse_json(&mut message, &config);
@@ -329,6 +684,7 @@ mod tests {
         let config = ParseConfig {
             source: "payload".to_string(),
             target: "parsed".to_string(),
+            extract: None,
         };
 
         let result = execute_parse_xml(&mut message, &config);
@@ -349,6 +705,7 @@ mod tests {
         let config = ParseConfig {
             source: "payload".to_string(),
             target: "parsed".to_string(),
+            extract: None,
         };
 
         assert!(execute_parse_xml(&mut message, &config).is_err());
@@ -392,6 +749,7 @@ mod tests {
         let config = ParseConfig {
             source: "payload".to_string(),
             target: "input".to_string(),
+            extract: None,
         };
 
         let result = execute_parse_json(&mut message, &config);
@@ -403,4 +761,137 @@ mod tests {
         assert_eq!(message.data()["input"]["name"], dv(json!("John")));
         assert_eq!(message.data()["input"]["age"], dv(json!(30)));
     }
+
+    fn extract_config(source: &str, paths: &[&str]) -> ParseConfig {
+        ParseConfig {
+            source: source.to_string(),
+            target: "picked".to_string(),
+            extract: Some(paths.iter().map(|p| p.to_string()).collect()),
+        }
+    }
+
+    #[test]
+    fn test_parse_config_extract() {
+        let config = ParseConfig::from_json(
+            &json!({"source": "payload", "target": "t", "extract": ["a.b", "c"]}),
+        )
+        .unwrap();
+        assert_eq!(
+            config.extract,
+            Some(vec!["a.b".to_string(), "c".to_string()])
+        );
+        assert!(
+            ParseConfig::from_json(&json!({"source": "payload", "target": "t", "extract": "a"}))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_stream_extract_json_keeps_only_selected_paths() {
+        let payload = Value::String(
+            r#"{"header": {"id": "B-1", "skip": [1, 2, 3]},
+                "records": [{"amount": 5, "blob": "x"}, {"amount": 7, "blob": "y"}],
+                "trailer": {"count": 2, "nested": {"deep": true}}}"#
+                .to_string(),
+        );
+        let mut message = Message::from_value(&payload);
+        let config = extract_config(
+            "payload",
+            &["header.id", "records.1.amount", "trailer", "missing.x"],
+        );
+
+        let (outcome, _) = execute_parse_json(&mut message, &config).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+
+        assert_eq!(
+            message.data()["picked"],
+            dv(json!({
+                "header": {"id": "B-1"},
+                "records": [null, {"amount": 7}],
+                "trailer": {"count": 2, "nested": {"deep": true}}
+            }))
+        );
+    }
+
+    #[test]
+    fn test_stream_extract_json_overlapping_paths_and_errors() {
+        let doc = r#"{"a": {"b": {"c": 1, "d": 2}}}"#;
+        let out = stream_extract_json(doc, &["a.b".to_string(), "a.b.d".to_string()]).unwrap();
+        assert_eq!(out, dv(json!({"a": {"b": {"c": 1, "d": 2}}})));
+
+        assert!(stream_extract_json(r#"{"a": [1, 2"#, &["a".to_string()]).is_err());
+        assert!(stream_extract_json(r#"{"a": 1} trailing"#, &["a".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_extract_from_structured_source() {
+        let payload = json!({"user": {"name": "Ann", "ssn": "secret"}});
+        let mut message = Message::from_value(&payload);
+        let config = extract_config("payload", &["user.name"]);
+
+        let (outcome, _) = execute_parse_json(&mut message, &config).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+
+        assert_eq!(
+            message.data()["picked"],
+            dv(json!({"user": {"name": "Ann"}}))
+        );
+    }
+
+    #[test]
+    fn test_stream_extract_xml() {
+        let xml = json!(
+            r#"<batch><header><id>B-1</id><note>ignored</note></header><item sku="a"><qty>1</qty></item><item sku="b"><qty>2</qty></item><flag/></batch>"#
+        );
+        let mut message = Message::from_value(&xml);
+        let config = extract_config("payload", &["header.id", "item", "flag", "missing"]);
+
+        let (_, changes) = execute_parse_xml(&mut message, &config).unwrap();
+        assert_eq!(changes.len(), 1);
+
+        let picked = &message.data()["picked"];
+        let full = OwnedDataValue::from(&xml_to_json(xml.as_str().unwrap()).unwrap());
+        assert_eq!(picked["header"]["id"], full["header"]["id"]);
+        assert!(picked["header"].get("note").is_none());
+        let items = picked["item"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1], full["item"]);
+        assert_eq!(items[0]["@sku"], dv(json!("a")));
+        assert!(picked.get("flag").is_some());
+        assert!(picked.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_stream_extract_xml_overlapping_paths() {
+        let doc = "<doc><a><b><c>1</c><d>2</d></b></a></doc>";
+        let full = OwnedDataValue::from(&xml_to_json(doc).unwrap());
+        for paths in [["a.b", "a.b.d"], ["a.b.d", "a.b"]] {
+            let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+            let out = stream_extract_xml(doc, &paths).unwrap();
+            assert_eq!(out, select_paths(&full, &paths));
+            assert_eq!(out["a"]["b"]["d"], full["a"]["b"]["d"]);
+        }
+    }
+
+    #[test]
+    fn test_stream_extract_xml_prefixed_elements() {
+        let doc = r#"<doc xmlns:x="urn:x"><x:a><x:b>1</x:b></x:a><x:flag/><c>2</c></doc>"#;
+        let full = OwnedDataValue::from(&xml_to_json(doc).unwrap());
+        for paths in [&["a.b", "flag", "c"][..], &["a"], &["x:a.x:b"]] {
+            let paths: Vec<String> = paths.iter().map(|p| p.to_string()).collect();
+            assert_eq!(
+                stream_extract_xml(doc, &paths).unwrap(),
+                select_paths(&full, &paths),
+                "{paths:?}"
+            );
+        }
+        let out = stream_extract_xml(doc, &["a.b".to_string()]).unwrap();
+        assert_eq!(out["a"]["b"], full["a"]["b"]);
+        assert!(out["a"]["b"].get("$text").is_some());
+    }
+
+    #[test]
+    fn test_stream_extract_xml_invalid() {
+        assert!(stream_extract_xml("<a><b>1</c></a>", &["b".to_string()]).is_err());
+    }
 }
@@ -0,0 +1,263 @@
+//! # Project Function Module
+//!
+//! Keeps only an allow-listed set of fields in a context subtree and drops
+//! everything else. Used to strip internal working fields before publishing
+//! and to enforce output contracts. The allow-list is either a list of
+//! dot-paths (`fields`) or a JSON-Schema-style `schema` whose `properties` /
+//! `items` describe the permitted shape; both are compiled into one selector
+//! tree when the workflow is loaded.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{get_nested_value, replace_with_change};
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Path segment that matches every object key and every array element.
+const WILDCARD: &str = "*";
+
+/// Configuration for the `project` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawProjectConfig")]
+pub struct ProjectConfig {
+    /// Context path of the subtree to project. Defaults to `data`.
+    pub path: String,
+
+    /// Allowed dot-paths, relative to `path`. `*` matches any key or array
+    /// element (`items.*.sku`). A path keeps its whole subtree.
+    pub fields: Option<Vec<String>>,
+
+    /// JSON-Schema-style shape: `properties` lists allowed object keys,
+    /// `items` describes array elements, and a schema with neither keeps
+    /// the value as-is.
+    pub schema: Option<Value>,
+
+    /// Selector compiled from `fields` or `schema`.
+    #[serde(skip)]
+    selector: Selector,
+}
+
+#[derive(Deserialize)]
+struct RawProjectConfig {
+    #[serde(default = "default_path")]
+    path: String,
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    #[serde(default)]
+    schema: Option<Value>,
+}
+
+fn default_path() -> String {
+    "data".to_string()
+}
+
+impl TryFrom<RawProjectConfig> for ProjectConfig {
+    type Error = String;
+
+    fn try_from(raw: RawProjectConfig) -> std::result::Result<Self, Self::Error> {
+        let selector = match (&raw.fields, &raw.schema) {
+            (Some(fields), None) => Selector::from_fields(fields),
+            (None, Some(schema)) => Selector::from_schema(schema),
+            _ => return Err("exactly one of 'fields' or 'schema' is required".to_string()),
+        };
+        Ok(Self {
+            path: raw.path,
+            fields: raw.fields,
+            schema: raw.schema,
+            selector,
+        })
+    }
+}
+
+/// Tree of allowed keys. `keep` marks a leaf whose whole subtree survives.
+#[derive(Debug, Clone, Default)]
+struct Selector {
+    keep: bool,
+    children: Vec<(String, Selector)>,
+}
+
+impl Selector {
+    fn from_fields(fields: &[String]) -> Self {
+        let mut root = Selector::default();
+        for field in fields {
+            let mut node = &mut root;
+            for part in field.split('.') {
+                node = node.child_mut(part);
+            }
+            node.keep = true;
+        }
+        root
+    }
+
+    fn from_schema(schema: &Value) -> Self {
+        let mut node = Selector::default();
+        let properties = schema.get("properties").and_then(Value::as_object);
+        let items = schema.get("items");
+        if properties.is_none() && items.is_none() {
+            node.keep = true;
+            return node;
+        }
+        if let Some(properties) = properties {
+            for (key, sub) in properties {
+                node.children
+                    .push((key.clone(), Selector::from_schema(sub)));
+            }
+        }
+        if let Some(items) = items {
+            node.children
+                .push((WILDCARD.to_string(), Selector::from_schema(items)));
+        }
+        node
+    }
+
+    fn child_mut(&mut self, key: &str) -> &mut Selector {
+        let idx = match self.children.iter().position(|(k, _)| k == key) {
+            Some(idx) => idx,
+            None => {
+                self.children.push((key.to_string(), Selector::default()));
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[idx].1
+    }
+
+    /// Exact-key match first, then wildcard.
+    fn child(&self, key: &str) -> Option<&Selector> {
+        self.children
+            .iter()
+            .find(|(k, _)| k == key)
+            .or_else(|| self.children.iter().find(|(k, _)| k == WILDCARD))
+            .map(|(_, s)| s)
+    }
+
+    /// Project `value`; `None` when nothing under it is allowed.
+    fn apply(&self, value: &OwnedDataValue) -> Option<OwnedDataValue> {
+        if self.keep {
+            return Some(value.clone());
+        }
+        match value {
+            OwnedDataValue::Object(pairs) => Some(OwnedDataValue::Object(
+                pairs
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.clone(), self.child(k)?.apply(v)?)))
+                    .collect(),
+            )),
+            OwnedDataValue::Array(items) => Some(OwnedDataValue::Array(
+                items
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, v)| self.child(&i.to_string())?.apply(v))
+                    .collect(),
+            )),
+            // A scalar where the selector expects a container doesn't match
+            // the contract; drop it.
+            _ => None,
+        }
+    }
+}
+
+impl ProjectConfig {
+    /// Project `value` through the compiled selector. Values that match
+    /// nothing become an empty container of the same kind (or `null`).
+    pub fn project_value(&self, value: &OwnedDataValue) -> OwnedDataValue {
+        self.selector.apply(value).unwrap_or(OwnedDataValue::Null)
+    }
+}
+
+/// Execute `project`: replace the subtree at `config.path` with its
+/// projection.
+pub fn execute_project(
+    message: &mut Message,
+    config: &ProjectConfig,
+) -> Result<(TaskOutcome, Vec<Change>)> {
+    debug!("Project: projecting '{}'", config.path);
+
+    let source = get_nested_value(&message.context, &config.path).ok_or_else(|| {
+        DataflowError::Validation(format!("Project: path '{}' not found", config.path))
+    })?;
+    let projected = config.project_value(source);
+
+    let changes = replace_with_change(message, &config.path, projected);
+    Ok((TaskOutcome::Success, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::utils::set_nested_value;
+    use serde_json::json;
+
+    fn config(input: Value) -> ProjectConfig {
+        serde_json::from_value(input).unwrap()
+    }
+
+    fn message_with_data(data: Value) -> Message {
+        let mut m = Message::from_value(&json!({}));
+        set_nested_value(&mut m.context, "data", OwnedDataValue::from(&data));
+        m
+    }
+
+    #[test]
+    fn fields_keep_only_listed_paths() {
+        let mut message = message_with_data(json!({
+            "id": 7,
+            "internal": {"trace": "x"},
+            "customer": {"name": "Ann", "ssn": "secret"},
+            "items": [{"sku": "A", "cost": 1}, {"sku": "B", "cost": 2}]
+        }));
+        let cfg = config(json!({"fields": ["id", "customer.name", "items.*.sku"]}));
+
+        let (outcome, changes) = execute_project(&mut message, &cfg).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            serde_json::to_value(message.data()).unwrap(),
+            json!({
+                "id": 7,
+                "customer": {"name": "Ann"},
+                "items": [{"sku": "A"}, {"sku": "B"}]
+            })
+        );
+    }
+
+    #[test]
+    fn schema_properties_and_items_drive_projection() {
+        let mut message = message_with_data(json!({
+            "out": {"total": 3, "debug": true, "lines": [{"qty": 1, "tmp": 0}]}
+        }));
+        let cfg = config(json!({
+            "path": "data.out",
+            "schema": {
+                "type": "object",
+                "properties": {
+                    "total": {"type": "number"},
+                    "lines": {"type": "array", "items": {"properties": {"qty": {}}}}
+                }
+            }
+        }));
+
+        assert!(execute_project(&mut message, &cfg).is_ok());
+        assert_eq!(
+            serde_json::to_value(&message.data()["out"]).unwrap(),
+            json!({"total": 3, "lines": [{"qty": 1}]})
+        );
+    }
+
+    #[test]
+    fn requires_exactly_one_of_fields_or_schema() {
+        assert!(serde_json::from_value::<ProjectConfig>(json!({})).is_err());
+        assert!(
+            serde_json::from_value::<ProjectConfig>(json!({"fields": [], "schema": {}})).is_err()
+        );
+    }
+
+    #[test]
+    fn missing_path_is_an_error() {
+        let mut message = message_with_data(json!({}));
+        let cfg = config(json!({"path": "data.nope", "fields": ["a"]}));
+        assert!(execute_project(&mut message, &cfg).is_err());
+    }
+}
@@ -11,12 +11,12 @@ use crate::engine::task_outcome::TaskOutcome;
 use crate::engine::utils::{get_nested_value, set_nested_value};
 use datavalue::OwnedDataValue;
 use log::debug;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
 /// Configuration for publish functions.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PublishConfig {
     /// Source field path inside `data` to serialize.
     pub source: String,
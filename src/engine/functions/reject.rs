@@ -0,0 +1,175 @@
+//! # Reject Function Module
+//!
+//! Builds the rejection (NACK) record payment workflows otherwise assemble
+//! by hand, and flags the message as rejected:
+//!
+//! ```json
+//! {"name": "reject", "input": {
+//!     "code": "AC01",
+//!     "reason": {"cat": ["Unknown account ", {"var": "data.creditor.account"}]},
+//!     "references": {"message_id": "data.header.msg_id", "end_to_end_id": "data.tx.e2e_id"},
+//!     "include_errors": true
+//! }}
+//! ```
+//!
+//! writes to `target` (default `data.rejection`):
+//!
+//! ```json
+//! {"code": "AC01", "reason": "Unknown account 123",
+//!  "references": {"end_to_end_id": "E2E-9", "message_id": "MSG-1"},
+//!  "errors": [{"code": "VALIDATION_ERROR", "message": "…", "task_id": "check"}],
+//!  "rejected_at": "2024-05-01T12:00:00+00:00"}
+//! ```
+//!
+//! and sets `metadata.rejected` to `true`
+//! ([`Message::is_rejected`](crate::Message::is_rejected)). `code` and
+//! `reason` are pre-compiled JSONLogic, so literals and computed values
+//! both work. A reference whose path is missing is `null`. With
+//! `halt: true` the workflow stops after the rejection is recorded.
+
+use crate::engine::error::{DataflowError, ErrorInfo, Result};
+use crate::engine::executor::{ArenaContext, with_arena};
+use crate::engine::message::{Change, Message, REJECTED};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{get_nested_value, replace_with_change};
+use chrono::Utc;
+use datalogic_rs::{Engine, Logic};
+use datavalue::OwnedDataValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Configuration for the `reject` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RejectConfig {
+    /// JSONLogic producing the rejection code, e.g. `"AC01"`.
+    pub code: Value,
+
+    /// JSONLogic producing the human-readable reason.
+    pub reason: Value,
+
+    /// Original references to echo back: output key to context path.
+    #[serde(default)]
+    pub references: BTreeMap<String, String>,
+
+    /// Path the rejection is written to.
+    #[serde(default = "default_target")]
+    pub target: String,
+
+    /// Copy the errors collected so far into the rejection.
+    #[serde(default)]
+    pub include_errors: bool,
+
+    /// Stop the workflow after rejecting.
+    #[serde(default)]
+    pub halt: bool,
+
+    /// Pre-compiled `code`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_code: Option<Arc<Logic>>,
+
+    /// Pre-compiled `reason`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_reason: Option<Arc<Logic>>,
+}
+
+fn default_target() -> String {
+    "data.rejection".to_string()
+}
+
+impl RejectConfig {
+    /// Execute the reject function, opening a fresh thread-local arena scope.
+    pub fn execute(
+        &self,
+        message: &mut Message,
+        engine: &Arc<Engine>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        with_arena(|arena| {
+            let mut arena_ctx = ArenaContext::from_owned(&message.context, arena);
+            self.execute_in_arena(message, &mut arena_ctx, engine)
+        })
+    }
+
+    /// Build the rejection against the cached arena context.
+    pub(crate) fn execute_in_arena(
+        &self,
+        message: &mut Message,
+        arena_ctx: &mut ArenaContext<'_>,
+        engine: &Arc<Engine>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let evaluate = |compiled: Option<&Arc<Logic>>, field: &str| -> Result<OwnedDataValue> {
+            let compiled = compiled.ok_or_else(|| {
+                DataflowError::Validation(format!("reject: {field} is not compiled"))
+            })?;
+            engine
+                .evaluate(compiled, arena_ctx.as_data_value(), arena_ctx.arena())
+                .map(|value| value.to_owned())
+                .map_err(|e| DataflowError::LogicEvaluation(format!("reject {field}: {e}")))
+        };
+        let code = evaluate(self.compiled_code.as_ref(), "code")?;
+        let reason = evaluate(self.compiled_reason.as_ref(), "reason")?;
+
+        let references = self
+            .references
+            .iter()
+            .map(|(key, path)| {
+                let value = get_nested_value(&message.context, path)
+                    .cloned()
+                    .unwrap_or(OwnedDataValue::Null);
+                (key.clone(), value)
+            })
+            .collect();
+        let mut rejection = vec![
+            ("code".to_string(), code),
+            ("reason".to_string(), reason),
+            ("references".to_string(), OwnedDataValue::Object(references)),
+        ];
+        if self.include_errors {
+            let errors = message.errors.iter().map(error_entry).collect();
+            rejection.push(("errors".to_string(), OwnedDataValue::Array(errors)));
+        }
+        rejection.push((
+            "rejected_at".to_string(),
+            OwnedDataValue::String(Utc::now().to_rfc3339()),
+        ));
+
+        let mut changes =
+            replace_with_change(message, &self.target, OwnedDataValue::Object(rejection));
+        changes.extend(replace_with_change(
+            message,
+            &format!("metadata.{REJECTED}"),
+            OwnedDataValue::Bool(true),
+        ));
+        arena_ctx.refresh_for_path(&message.context, &self.target);
+        arena_ctx.refresh_for_path(&message.context, "metadata");
+
+        let outcome = if self.halt {
+            TaskOutcome::Halt
+        } else {
+            TaskOutcome::Success
+        };
+        Ok((outcome, changes))
+    }
+}
+
+/// An error as listed in a rejection.
+fn error_entry(error: &ErrorInfo) -> OwnedDataValue {
+    let mut entry = vec![
+        (
+            "code".to_string(),
+            OwnedDataValue::String(error.code.clone()),
+        ),
+        (
+            "message".to_string(),
+            OwnedDataValue::String(error.message.clone()),
+        ),
+    ];
+    for (key, value) in [("path", &error.path), ("task_id", &error.task_id)] {
+        if let Some(value) = value {
+            entry.push((key.to_string(), OwnedDataValue::String(value.clone())));
+        }
+    }
+    OwnedDataValue::Object(entry)
+}
@@ -0,0 +1,270 @@
+//! # Rename Keys Function Module
+//!
+//! Converts object keys in a context subtree between `camelCase`,
+//! `snake_case`, and `PascalCase`, recursing through nested objects and
+//! arrays. Used when bridging JSON APIs that follow different naming
+//! conventions.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{get_nested_value, replace_with_change};
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Target key convention.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyCase {
+    #[serde(rename = "camelCase")]
+    Camel,
+    #[serde(rename = "snake_case")]
+    Snake,
+    #[serde(rename = "PascalCase")]
+    Pascal,
+}
+
+/// Configuration for the `rename_keys` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameKeysConfig {
+    /// Context path of the subtree whose keys are converted. Defaults to
+    /// `data`.
+    #[serde(default = "default_path")]
+    pub path: String,
+
+    /// Convention to convert keys to.
+    pub case: KeyCase,
+
+    /// Keys left exactly as they are (matched against the original key).
+    /// Their values are still walked.
+    #[serde(default)]
+    pub exceptions: Vec<String>,
+
+    /// When `false`, only the top-level keys of `path` are converted.
+    /// Defaults to `true`.
+    #[serde(default = "default_recursive")]
+    pub recursive: bool,
+}
+
+fn default_path() -> String {
+    "data".to_string()
+}
+
+fn default_recursive() -> bool {
+    true
+}
+
+impl RenameKeysConfig {
+    /// Return `value` with its keys converted. Fails when two keys of one
+    /// object convert to the same key (`firstName` and `first_name` to
+    /// snake case), naming both.
+    pub fn rename_value(&self, value: &OwnedDataValue) -> Result<OwnedDataValue> {
+        self.rename(value, true)
+    }
+
+    fn rename(&self, value: &OwnedDataValue, top: bool) -> Result<OwnedDataValue> {
+        let descend = top || self.recursive;
+        Ok(match value {
+            OwnedDataValue::Object(pairs) if descend => {
+                let mut renamed: Vec<(String, OwnedDataValue)> = Vec::with_capacity(pairs.len());
+                for (idx, (k, v)) in pairs.iter().enumerate() {
+                    let key = if self.exceptions.iter().any(|e| e == k) {
+                        k.clone()
+                    } else {
+                        convert_key(k, self.case)
+                    };
+                    if let Some(other) = renamed.iter().position(|(seen, _)| *seen == key) {
+                        return Err(DataflowError::Validation(format!(
+                            "RenameKeys: keys '{}' and '{}' both become '{}'",
+                            pairs[other].0, pairs[idx].0, key
+                        )));
+                    }
+                    renamed.push((key, self.rename(v, false)?));
+                }
+                OwnedDataValue::Object(renamed)
+            }
+            // Arrays don't count as a level: objects inside a top-level
+            // array are still converted when `recursive` is off.
+            OwnedDataValue::Array(items) if descend => OwnedDataValue::Array(
+                items
+                    .iter()
+                    .map(|v| self.rename(v, top))
+                    .collect::<Result<_>>()?,
+            ),
+            _ => value.clone(),
+        })
+    }
+}
+
+/// Split `key` into lowercase words on `_`, `-`, spaces, and case
+/// boundaries (`userID` → `user`, `id`; `HTTPServer` → `http`, `server`).
+fn split_words(key: &str) -> Vec<String> {
+    let chars: Vec<char> = key.chars().collect();
+    let mut words = Vec::new();
+    let mut current = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' || c == '-' || c == ' ' {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !current.is_empty() {
+            let prev = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|n| n.is_lowercase());
+            if prev.is_lowercase()
+                || prev.is_ascii_digit()
+                || (prev.is_uppercase() && next_is_lower)
+            {
+                words.push(std::mem::take(&mut current));
+            }
+        }
+        current.extend(c.to_lowercase());
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Convert a single key to `case`. Keys with no word characters are
+/// returned unchanged.
+pub fn convert_key(key: &str, case: KeyCase) -> String {
+    let words = split_words(key);
+    if words.is_empty() {
+        return key.to_string();
+    }
+    match case {
+        KeyCase::Snake => words.join("_"),
+        KeyCase::Pascal => words.iter().map(|w| capitalize(w)).collect(),
+        KeyCase::Camel => {
+            let mut out = words[0].clone();
+            for word in &words[1..] {
+                out.push_str(&capitalize(word));
+            }
+            out
+        }
+    }
+}
+
+/// Execute `rename_keys`: replace the subtree at `config.path` with a copy
+/// whose keys follow `config.case`.
+pub fn execute_rename_keys(
+    message: &mut Message,
+    config: &RenameKeysConfig,
+) -> Result<(TaskOutcome, Vec<Change>)> {
+    debug!(
+        "RenameKeys: converting '{}' to {:?}",
+        config.path, config.case
+    );
+    let source = get_nested_value(&message.context, &config.path).ok_or_else(|| {
+        DataflowError::Validation(format!("RenameKeys: path '{}' not found", config.path))
+    })?;
+    let renamed = config.rename_value(source)?;
+    let changes = replace_with_change(message, &config.path, renamed);
+    Ok((TaskOutcome::Success, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn config(input: Value) -> RenameKeysConfig {
+        serde_json::from_value(input).unwrap()
+    }
+
+    fn rename(cfg: &RenameKeysConfig, v: Value) -> Value {
+        serde_json::to_value(cfg.rename_value(&OwnedDataValue::from(&v)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn converts_between_conventions() {
+        assert_eq!(convert_key("firstName", KeyCase::Snake), "first_name");
+        assert_eq!(convert_key("first_name", KeyCase::Camel), "firstName");
+        assert_eq!(convert_key("first_name", KeyCase::Pascal), "FirstName");
+        assert_eq!(convert_key("HTTPServer", KeyCase::Snake), "http_server");
+        assert_eq!(convert_key("userID", KeyCase::Snake), "user_id");
+        assert_eq!(convert_key("address2Line", KeyCase::Snake), "address2_line");
+        assert_eq!(convert_key("_", KeyCase::Camel), "_");
+    }
+
+    #[test]
+    fn recurses_through_objects_and_arrays_with_exceptions() {
+        let cfg = config(json!({"case": "snake_case", "exceptions": ["keepMe"]}));
+        assert_eq!(
+            rename(
+                &cfg,
+                json!({"orderId": 1, "lineItems": [{"unitPrice": 2}], "keepMe": {"innerKey": 3}})
+            ),
+            json!({"order_id": 1, "line_items": [{"unit_price": 2}], "keepMe": {"inner_key": 3}})
+        );
+    }
+
+    #[test]
+    fn non_recursive_converts_top_level_only() {
+        let cfg = config(json!({"case": "camelCase", "recursive": false}));
+        assert_eq!(
+            rename(&cfg, json!({"outer_key": {"inner_key": 1}})),
+            json!({"outerKey": {"inner_key": 1}})
+        );
+    }
+
+    #[test]
+    fn colliding_keys_are_rejected() {
+        let cfg = config(json!({"case": "snake_case"}));
+        let nested = OwnedDataValue::from(&json!({"user": {"firstName": "a", "first_name": "b"}}));
+        let err = cfg.rename_value(&nested).unwrap_err().to_string();
+        assert!(
+            err.contains("'firstName' and 'first_name' both become 'first_name'"),
+            "{err}"
+        );
+
+        // A key kept by an exception still collides with a converted one.
+        let cfg = config(json!({"case": "snake_case", "exceptions": ["first_name"]}));
+        assert!(
+            cfg.rename_value(&OwnedDataValue::from(
+                &json!({"first_name": 1, "firstName": 2})
+            ))
+            .is_err()
+        );
+        let mut message = Message::from_value(&json!({}));
+        replace_with_change(
+            &mut message,
+            "data",
+            OwnedDataValue::from(&json!({"a_b": 1, "aB": 2})),
+        );
+        let cfg = config(json!({"case": "camelCase"}));
+        assert!(execute_rename_keys(&mut message, &cfg).is_err());
+        assert_eq!(
+            serde_json::to_value(message.data()).unwrap(),
+            json!({"a_b": 1, "aB": 2})
+        );
+    }
+
+    #[test]
+    fn execute_records_one_change() {
+        let mut message = Message::from_value(&json!({}));
+        replace_with_change(
+            &mut message,
+            "data",
+            OwnedDataValue::from(&json!({"userName": "a"})),
+        );
+        let cfg = config(json!({"case": "PascalCase"}));
+        let (outcome, changes) = execute_rename_keys(&mut message, &cfg).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            serde_json::to_value(message.data()).unwrap(),
+            json!({"UserName": "a"})
+        );
+    }
+}
@@ -0,0 +1,201 @@
+//! # Sample Function Module
+//!
+//! Copies a compact summary of a context subtree to a target path: a subset
+//! of fields, a deterministic fraction of array elements, or both. Used to
+//! attach lightweight payload summaries to metrics or dead-letter entries
+//! without shipping whole documents.
+//!
+//! Array sampling is a fixed stride (`percent: 10` keeps elements 0, 10,
+//! 20, …), so the same input always yields the same summary.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::{get_nested_value, replace_with_change, set_nested_value};
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+/// Configuration for the `sample` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "RawSampleConfig")]
+pub struct SampleConfig {
+    /// Context path of the subtree to summarize. Defaults to `data`.
+    pub source: String,
+
+    /// Context path the summary is written to.
+    pub target: String,
+
+    /// Dot-paths to copy, relative to `source` (or to each element when
+    /// `source` is an array). All fields are kept when absent.
+    pub fields: Option<Vec<String>>,
+
+    /// Share of array elements to keep, 0–100. All elements are kept when
+    /// absent.
+    pub percent: Option<u32>,
+
+    /// Upper bound on the number of array elements kept.
+    pub max_items: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RawSampleConfig {
+    #[serde(default = "default_source")]
+    source: String,
+    target: String,
+    #[serde(default)]
+    fields: Option<Vec<String>>,
+    #[serde(default)]
+    percent: Option<u32>,
+    #[serde(default)]
+    max_items: Option<usize>,
+}
+
+fn default_source() -> String {
+    "data".to_string()
+}
+
+impl TryFrom<RawSampleConfig> for SampleConfig {
+    type Error = String;
+
+    fn try_from(raw: RawSampleConfig) -> std::result::Result<Self, Self::Error> {
+        if raw.fields.is_none() && raw.percent.is_none() && raw.max_items.is_none() {
+            return Err("at least one of 'fields', 'percent' or 'max_items' is required".into());
+        }
+        if raw.percent.is_some_and(|p| p > 100) {
+            return Err("'percent' must be between 0 and 100".to_string());
+        }
+        Ok(Self {
+            source: raw.source,
+            target: raw.target,
+            fields: raw.fields,
+            percent: raw.percent,
+            max_items: raw.max_items,
+        })
+    }
+}
+
+impl SampleConfig {
+    /// Build the summary of `value`.
+    pub fn sample_value(&self, value: &OwnedDataValue) -> OwnedDataValue {
+        match value {
+            OwnedDataValue::Array(items) => {
+                let percent = self.percent.unwrap_or(100) as usize;
+                let kept = items
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| (i * percent) % 100 < percent)
+                    .take(self.max_items.unwrap_or(usize::MAX))
+                    .map(|(_, item)| self.select(item))
+                    .collect();
+                OwnedDataValue::Array(kept)
+            }
+            _ => self.select(value),
+        }
+    }
+
+    /// Copy the configured fields out of `value`, keeping their paths.
+    fn select(&self, value: &OwnedDataValue) -> OwnedDataValue {
+        let Some(fields) = &self.fields else {
+            return value.clone();
+        };
+        let mut out = OwnedDataValue::Object(Vec::new());
+        for field in fields {
+            if let Some(found) = get_nested_value(value, field) {
+                set_nested_value(&mut out, field, found.clone());
+            }
+        }
+        out
+    }
+}
+
+/// Execute `sample`: write the summary of `config.source` to
+/// `config.target`.
+pub fn execute_sample(
+    message: &mut Message,
+    config: &SampleConfig,
+) -> Result<(TaskOutcome, Vec<Change>)> {
+    debug!("Sample: '{}' -> '{}'", config.source, config.target);
+    let source = get_nested_value(&message.context, &config.source).ok_or_else(|| {
+        DataflowError::Validation(format!("Sample: path '{}' not found", config.source))
+    })?;
+    let summary = config.sample_value(source);
+    let changes = replace_with_change(message, &config.target, summary);
+    Ok((TaskOutcome::Success, changes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    fn config(input: Value) -> std::result::Result<SampleConfig, serde_json::Error> {
+        serde_json::from_value(input)
+    }
+
+    fn sample(cfg: &SampleConfig, v: Value) -> Value {
+        serde_json::to_value(cfg.sample_value(&OwnedDataValue::from(&v))).unwrap()
+    }
+
+    #[test]
+    fn copies_selected_fields_keeping_shape() {
+        let cfg =
+            config(json!({"target": "data.s", "fields": ["id", "customer.name", "nope"]})).unwrap();
+        assert_eq!(
+            sample(
+                &cfg,
+                json!({"id": 1, "customer": {"name": "Ann", "email": "a@x"}, "blob": "..."})
+            ),
+            json!({"id": 1, "customer": {"name": "Ann"}})
+        );
+    }
+
+    #[test]
+    fn samples_arrays_by_stride_and_cap() {
+        let items: Vec<Value> = (0..10).map(|i| json!({"n": i, "pad": "x"})).collect();
+        let cfg = config(json!({"target": "data.s", "percent": 30, "fields": ["n"]})).unwrap();
+        // Every element whose index*30 wraps past a multiple of 100.
+        assert_eq!(
+            sample(&cfg, Value::Array(items.clone())),
+            json!([{"n": 0}, {"n": 4}, {"n": 7}])
+        );
+
+        let cfg = config(json!({"target": "data.s", "max_items": 2})).unwrap();
+        assert_eq!(
+            sample(&cfg, Value::Array(items)),
+            json!([{"n": 0, "pad": "x"}, {"n": 1, "pad": "x"}])
+        );
+    }
+
+    #[test]
+    fn rejects_empty_or_out_of_range_configs() {
+        assert!(config(json!({"target": "data.s"})).is_err());
+        assert!(config(json!({"target": "data.s", "percent": 101})).is_err());
+        assert!(config(json!({"fields": ["a"]})).is_err());
+    }
+
+    #[test]
+    fn execute_writes_summary_to_target() {
+        let mut message = Message::from_value(&json!({}));
+        replace_with_change(
+            &mut message,
+            "data",
+            OwnedDataValue::from(&json!({"order": {"id": 7, "lines": [1, 2, 3]}})),
+        );
+        let cfg = config(json!({
+            "source": "data.order", "target": "metadata.sample", "fields": ["id"]
+        }))
+        .unwrap();
+        let (outcome, changes) = execute_sample(&mut message, &cfg).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            serde_json::to_value(message.metadata()).unwrap()["sample"],
+            json!({"id": 7})
+        );
+
+        let missing =
+            config(json!({"source": "data.nope", "target": "data.s", "max_items": 1})).unwrap();
+        assert!(execute_sample(&mut message, &missing).is_err());
+    }
+}
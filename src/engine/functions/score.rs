@@ -0,0 +1,182 @@
+//! # Score Function Module
+//!
+//! Weighted-rule scorecards, the usual shape of a fraud or risk screening
+//! step:
+//!
+//! ```json
+//! {"name": "score", "input": {
+//!     "rules": [
+//!         {"id": "high_amount", "weight": 40, "logic": {">": [{"var": "data.amount"}, 10000]}},
+//!         {"id": "new_account", "weight": 25, "logic": {"<": [{"var": "data.account_age_days"}, 30]}},
+//!         {"id": "trusted_device", "weight": -20, "logic": {"var": "data.device.trusted"}}
+//!     ],
+//!     "target": "data.risk.score",
+//!     "triggered_target": "data.risk.triggered"
+//! }}
+//! ```
+//!
+//! Every rule's `logic` is pre-compiled JSONLogic; a rule triggers when it
+//! evaluates to `true`. The weights of the triggered rules are summed into
+//! `target` (default `data.score`) and their ids, in rule order, are
+//! written to `triggered_target` (default `data.triggered_rules`). Weights
+//! may be fractional or negative. Later tasks route on the result with
+//! ordinary conditions, e.g. `{">=": [{"var": "data.risk.score"}, 50]}`.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::executor::{ArenaContext, with_arena};
+use crate::engine::message::{Change, Message};
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::replace_with_change;
+use datalogic_rs::{Engine, Logic};
+use datavalue::{DataValue, OwnedDataValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Configuration for the `score` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreConfig {
+    /// Weighted rules, evaluated in order.
+    pub rules: Vec<ScoreRule>,
+
+    /// Path the summed weight is written to.
+    #[serde(default = "default_target")]
+    pub target: String,
+
+    /// Path the ids of the triggered rules are written to.
+    #[serde(default = "default_triggered_target")]
+    pub triggered_target: String,
+}
+
+/// One weighted predicate of a [`ScoreConfig`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScoreRule {
+    /// Identifier listed in `triggered_target` when the rule triggers.
+    pub id: String,
+
+    /// Added to the score when the rule triggers.
+    pub weight: f64,
+
+    /// JSONLogic predicate; the rule triggers when it yields `true`.
+    pub logic: Value,
+
+    /// Pre-compiled `logic`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_logic: Option<Arc<Logic>>,
+}
+
+fn default_target() -> String {
+    "data.score".to_string()
+}
+
+fn default_triggered_target() -> String {
+    "data.triggered_rules".to_string()
+}
+
+impl ScoreConfig {
+    /// Execute the score function, opening a fresh thread-local arena scope.
+    pub fn execute(
+        &self,
+        message: &mut Message,
+        engine: &Arc<Engine>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        with_arena(|arena| {
+            let mut arena_ctx = ArenaContext::from_owned(&message.context, arena);
+            self.execute_in_arena(message, &mut arena_ctx, engine)
+        })
+    }
+
+    /// Score the message against the cached arena context.
+    pub(crate) fn execute_in_arena(
+        &self,
+        message: &mut Message,
+        arena_ctx: &mut ArenaContext<'_>,
+        engine: &Arc<Engine>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let mut score = 0.0;
+        let mut triggered = Vec::new();
+        for rule in &self.rules {
+            let compiled = rule.compiled_logic.as_ref().ok_or_else(|| {
+                DataflowError::Validation(format!("score rule {} is not compiled", rule.id))
+            })?;
+            let value = engine
+                .evaluate(compiled, arena_ctx.as_data_value(), arena_ctx.arena())
+                .map_err(|e| {
+                    DataflowError::LogicEvaluation(format!("score rule {}: {e}", rule.id))
+                })?;
+            if matches!(value, DataValue::Bool(true)) {
+                score += rule.weight;
+                triggered.push(OwnedDataValue::String(rule.id.clone()));
+            }
+        }
+
+        let mut changes = replace_with_change(message, &self.target, OwnedDataValue::from(score));
+        changes.extend(replace_with_change(
+            message,
+            &self.triggered_target,
+            OwnedDataValue::Array(triggered),
+        ));
+        arena_ctx.refresh_for_path(&message.context, &self.target);
+        arena_ctx.refresh_for_path(&message.context, &self.triggered_target);
+        Ok((TaskOutcome::Success, changes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(input: Value, engine: &Engine) -> ScoreConfig {
+        let mut config: ScoreConfig = serde_json::from_value(input).unwrap();
+        for rule in &mut config.rules {
+            rule.compiled_logic = Some(engine.compile_arc(&rule.logic).unwrap());
+        }
+        config
+    }
+
+    #[test]
+    fn sums_the_weights_of_triggered_rules() {
+        let engine = Arc::new(Engine::builder().build());
+        let config = config(
+            json!({"rules": [
+                {"id": "high_amount", "weight": 40, "logic": {">": [{"var": "data.amount"}, 10000]}},
+                {"id": "new_account", "weight": 25.5, "logic": {"<": [{"var": "data.age"}, 30]}},
+                {"id": "trusted", "weight": -20, "logic": {"var": "data.trusted"}},
+                {"id": "truthy_only", "weight": 100, "logic": {"var": "data.amount"}}
+            ], "target": "data.risk.score"}),
+            &engine,
+        );
+        let mut message = Message::from_value(&json!({}));
+        message.context = OwnedDataValue::from(&json!({
+            "data": {"amount": 25000, "age": 3, "trusted": true},
+            "metadata": {}, "temp_data": {}
+        }));
+
+        let (outcome, changes) = config.execute(&mut message, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        assert_eq!(changes.len(), 2);
+        let data = Value::from(&message.context)["data"].clone();
+        assert_eq!(data["risk"]["score"], json!(45.5));
+        assert_eq!(
+            data["triggered_rules"],
+            json!(["high_amount", "new_account", "trusted"])
+        );
+    }
+
+    #[test]
+    fn nothing_triggered_scores_zero() {
+        let engine = Arc::new(Engine::builder().build());
+        let config = config(
+            json!({"rules": [{"id": "r", "weight": 10, "logic": {"var": "data.flag"}}]}),
+            &engine,
+        );
+        let mut message = Message::from_value(&json!({}));
+        let (outcome, _) = config.execute(&mut message, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+        let data = Value::from(&message.context)["data"].clone();
+        assert_eq!(data, json!({"score": 0, "triggered_rules": []}));
+    }
+}
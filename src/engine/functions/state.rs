@@ -0,0 +1,161 @@
+//! # State Functions Module
+//!
+//! Read and write the engine's [`StateStore`](crate::StateStore) from a
+//! workflow — cross-message counters, caches, the last value seen for a
+//! customer:
+//!
+//! ```json
+//! {"name": "state_get", "input": {
+//!     "key": {"cat": ["orders:", {"var": "data.customer_id"}]},
+//!     "target": "data.order_count",
+//!     "default": 0
+//! }}
+//! {"name": "state_set", "input": {
+//!     "key": {"cat": ["orders:", {"var": "data.customer_id"}]},
+//!     "value": {"+": [{"var": "data.order_count"}, 1]},
+//!     "ttl_ms": 86400000
+//! }}
+//! ```
+//!
+//! `key` is pre-compiled JSONLogic evaluated against the message; strings
+//! are used as is and other values as their JSON text, while a `null` key
+//! is an error. `state_get` writes the stored value, or `default` (default
+//! `null`) when the key is unset or expired, to `target`. `state_set`
+//! stores the result of the `value` expression, expiring after `ttl_ms`
+//! when given.
+//!
+//! Without a configured store the engine keeps state in memory, private to
+//! it and to engines derived by `with_new_workflows`. Keys starting with
+//! `retry:` and `join:` are used by the engine itself. A read followed by a
+//! write is not atomic: concurrent messages incrementing one counter can
+//! lose updates.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::executor::{ArenaContext, with_arena};
+use crate::engine::message::{Change, Message};
+use crate::engine::state::StateStore;
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::replace_with_change;
+use datalogic_rs::{Engine, Logic};
+use datavalue::OwnedDataValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Configuration for the `state_get` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateGetConfig {
+    /// JSONLogic producing the key to read.
+    pub key: Value,
+
+    /// Path the value is written to.
+    pub target: String,
+
+    /// Value written when the key is unset or expired.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub default: Value,
+
+    /// Pre-compiled `key`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_key: Option<Arc<Logic>>,
+}
+
+/// Configuration for the `state_set` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateSetConfig {
+    /// JSONLogic producing the key to write.
+    pub key: Value,
+
+    /// JSONLogic producing the value to store.
+    pub value: Value,
+
+    /// Expire the entry after this long.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_ms: Option<u64>,
+
+    /// Pre-compiled `key`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_key: Option<Arc<Logic>>,
+
+    /// Pre-compiled `value`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_value: Option<Arc<Logic>>,
+}
+
+impl StateGetConfig {
+    /// Read the key from `store` into `target`.
+    pub(crate) async fn execute(
+        &self,
+        message: &mut Message,
+        store: &dyn StateStore,
+        engine: &Engine,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let key = state_key(self.compiled_key.as_ref(), message, engine, "state_get")?;
+        let value = store
+            .get(&key)
+            .await?
+            .unwrap_or_else(|| self.default.clone());
+        let changes = replace_with_change(message, &self.target, OwnedDataValue::from(&value));
+        Ok((TaskOutcome::Success, changes))
+    }
+}
+
+impl StateSetConfig {
+    /// Store the evaluated value under the key.
+    pub(crate) async fn execute(
+        &self,
+        message: &mut Message,
+        store: &dyn StateStore,
+        engine: &Engine,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let key = state_key(self.compiled_key.as_ref(), message, engine, "state_set")?;
+        let value = evaluate(
+            self.compiled_value.as_ref(),
+            message,
+            engine,
+            "state_set value",
+        )?;
+        let value = serde_json::to_value(&value).unwrap_or(Value::Null);
+        store
+            .set(&key, value, self.ttl_ms.map(Duration::from_millis))
+            .await?;
+        Ok((TaskOutcome::Success, Vec::new()))
+    }
+}
+
+/// Evaluate `compiled` against `message`'s context.
+fn evaluate(
+    compiled: Option<&Arc<Logic>>,
+    message: &Message,
+    engine: &Engine,
+    label: &str,
+) -> Result<OwnedDataValue> {
+    let compiled =
+        compiled.ok_or_else(|| DataflowError::Validation(format!("{label} is not compiled")))?;
+    with_arena(|arena| {
+        let arena_ctx = ArenaContext::from_owned(&message.context, arena);
+        engine
+            .evaluate(compiled, arena_ctx.as_data_value(), arena)
+            .map(|value| value.to_owned())
+            .map_err(|e| DataflowError::LogicEvaluation(format!("{label}: {e}")))
+    })
+}
+
+/// Evaluate a `key` expression to the string the entry is stored under.
+fn state_key(
+    compiled: Option<&Arc<Logic>>,
+    message: &Message,
+    engine: &Engine,
+    function: &str,
+) -> Result<String> {
+    match evaluate(compiled, message, engine, &format!("{function} key"))? {
+        OwnedDataValue::Null => Err(DataflowError::Validation(format!(
+            "{function}: key is null"
+        ))),
+        OwnedDataValue::String(key) => Ok(key),
+        other => Ok(serde_json::to_string(&other).unwrap_or_default()),
+    }
+}
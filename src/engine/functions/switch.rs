@@ -0,0 +1,317 @@
+//! # Switch Function Module
+//!
+//! Picks one of several branches with a single task instead of repeating a
+//! near-identical condition on every task of every branch. Cases are tried
+//! in order and the first whose `when` is true is selected, else `default`:
+//!
+//! ```json
+//! {"name": "switch", "input": {
+//!     "cases": [
+//!         {"name": "domestic", "when": {"==": [{"var": "data.country"}, "US"]},
+//!          "tasks": ["ach_format", "ach_send"]},
+//!         {"name": "blocked", "when": {"var": "data.sanctioned"}, "goto": "reject"}
+//!     ],
+//!     "default": {"tasks": ["swift_format", "swift_send"]}
+//! }}
+//! ```
+//!
+//! A case either names the tasks of its branch (`tasks`) or jumps ahead to
+//! a task (`goto`). Both refer to tasks later in the same workflow:
+//!
+//! - A task listed by any branch of a switch runs only when that switch
+//!   selected one of the branches listing it. It is skipped when the
+//!   switch selected another branch, matched nothing or did not run.
+//! - `goto` skips every task between the switch and the target.
+//!
+//! The selected case (its `name`, else its position; `"default"`; or
+//! `null` when nothing matched) is written to
+//! `metadata.branches.<workflow_id>.<task_id>`, so later conditions can
+//! read it too. The compiler pre-compiles each `when` and folds the branch
+//! checks into the compiled conditions of the affected tasks; unknown task
+//! ids, backwards references and cases without exactly one of `tasks` and
+//! `goto` fail engine construction.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::executor::{ArenaContext, evaluate_condition_in_arena, with_arena};
+use crate::engine::functions::FunctionConfig;
+use crate::engine::message::{Change, Message};
+use crate::engine::task::Task;
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::replace_with_change;
+use datalogic_rs::{Engine, Logic};
+use datavalue::OwnedDataValue;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Label recorded when no case matched and `default` was taken.
+const DEFAULT_LABEL: &str = "default";
+
+/// Configuration for the `switch` function.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchConfig {
+    /// Cases, tried in order.
+    pub cases: Vec<SwitchCase>,
+
+    /// Branch taken when no case matches. Without one, nothing is selected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<SwitchBranch>,
+
+    /// Context path the selection is written to, populated by
+    /// `LogicCompiler`.
+    #[serde(skip)]
+    pub selection_path: String,
+}
+
+/// One case of a [`SwitchConfig`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchCase {
+    /// Label recorded when this case is selected. Defaults to its position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// JSONLogic condition selecting this case.
+    pub when: Value,
+
+    /// Ids of the tasks in this case's branch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<String>,
+
+    /// Id of the task to jump to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goto: Option<String>,
+
+    /// Pre-compiled `when`, populated by `LogicCompiler`.
+    #[serde(skip)]
+    pub compiled_when: Option<Arc<Logic>>,
+}
+
+/// The `default` branch of a [`SwitchConfig`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchBranch {
+    /// Ids of the tasks in the branch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<String>,
+
+    /// Id of the task to jump to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub goto: Option<String>,
+}
+
+impl SwitchCase {
+    fn label(&self, idx: usize) -> Value {
+        match &self.name {
+            Some(name) => Value::String(name.clone()),
+            None => Value::from(idx),
+        }
+    }
+}
+
+impl SwitchConfig {
+    /// Every branch as `(label, tasks, goto)`, `default` last.
+    fn branches(&self) -> impl Iterator<Item = (Value, &[String], Option<&str>)> {
+        let cases = self
+            .cases
+            .iter()
+            .enumerate()
+            .map(|(idx, case)| (case.label(idx), case.tasks.as_slice(), case.goto.as_deref()));
+        let default = self.default.iter().map(|branch| {
+            (
+                Value::from(DEFAULT_LABEL),
+                branch.tasks.as_slice(),
+                branch.goto.as_deref(),
+            )
+        });
+        cases.chain(default)
+    }
+
+    /// Execute the switch, opening a fresh thread-local arena scope.
+    pub fn execute(
+        &self,
+        message: &mut Message,
+        engine: &Arc<Engine>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        with_arena(|arena| {
+            let mut arena_ctx = ArenaContext::from_owned(&message.context, arena);
+            self.execute_in_arena(message, &mut arena_ctx, engine)
+        })
+    }
+
+    /// Select a case against the cached arena context and record it.
+    pub(crate) fn execute_in_arena(
+        &self,
+        message: &mut Message,
+        arena_ctx: &mut ArenaContext<'_>,
+        engine: &Arc<Engine>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let mut selected = None;
+        for (idx, case) in self.cases.iter().enumerate() {
+            let matched = evaluate_condition_in_arena(
+                engine,
+                case.compiled_when.as_ref(),
+                arena_ctx.as_data_value(),
+                arena_ctx.arena(),
+            )?;
+            if matched {
+                selected = Some(case.label(idx));
+                break;
+            }
+        }
+        let selected = selected
+            .or_else(|| self.default.as_ref().map(|_| Value::from(DEFAULT_LABEL)))
+            .unwrap_or(Value::Null);
+        debug!("Switch: selected {}", selected);
+
+        let changes = replace_with_change(
+            message,
+            &self.selection_path,
+            OwnedDataValue::from(&selected),
+        );
+        arena_ctx.refresh_for_path(&message.context, "metadata");
+        Ok((TaskOutcome::Success, changes))
+    }
+}
+
+/// Context path a switch task records its selection at.
+pub(crate) fn selection_path(workflow_id: &str, task_id: &str) -> String {
+    format!("metadata.branches.{workflow_id}.{task_id}")
+}
+
+/// For each of `tasks` (a workflow's, or a `foreach` body), the checks
+/// its `switch` tasks add to its condition: membership in the selected
+/// branch for branch tasks, and "not jumped over" for tasks a `goto` skips.
+pub(crate) fn branch_gates(workflow_id: &str, tasks: &[Task]) -> Result<Vec<Vec<Value>>> {
+    let positions: HashMap<&str, usize> = tasks
+        .iter()
+        .enumerate()
+        .map(|(idx, task)| (task.id.as_str(), idx))
+        .collect();
+    let mut gates = vec![Vec::new(); tasks.len()];
+
+    for (at, task) in tasks.iter().enumerate() {
+        let FunctionConfig::Switch { input, .. } = &task.function else {
+            continue;
+        };
+        let target = |id: &str| -> Result<usize> {
+            match positions.get(id) {
+                Some(&idx) if idx > at => Ok(idx),
+                Some(_) => Err(DataflowError::Workflow(format!(
+                    "Switch task '{}' in workflow '{}' refers to task '{}', which does not come after it",
+                    task.id, workflow_id, id
+                ))),
+                None => Err(DataflowError::Workflow(format!(
+                    "Switch task '{}' in workflow '{}' refers to unknown task '{}'",
+                    task.id, workflow_id, id
+                ))),
+            }
+        };
+
+        let mut members: BTreeMap<usize, Vec<Value>> = BTreeMap::new();
+        let mut jumped: BTreeMap<usize, Vec<Value>> = BTreeMap::new();
+        for (label, members_of, goto) in input.branches() {
+            match (members_of.is_empty(), goto) {
+                (false, None) => {
+                    for id in members_of {
+                        members.entry(target(id)?).or_default().push(label.clone());
+                    }
+                }
+                (true, Some(id)) => {
+                    for skipped in at + 1..target(id)? {
+                        jumped.entry(skipped).or_default().push(label.clone());
+                    }
+                }
+                _ => {
+                    return Err(DataflowError::Workflow(format!(
+                        "Switch task '{}' in workflow '{}': branch {} must set exactly one of 'tasks' and 'goto'",
+                        task.id, workflow_id, label
+                    )));
+                }
+            }
+        }
+
+        let selection = json!({"var": selection_path(workflow_id, &task.id)});
+        for (idx, labels) in members {
+            gates[idx].push(json!({"in": [selection, labels]}));
+        }
+        for (idx, labels) in jumped {
+            gates[idx].push(json!({"!": {"in": [selection, labels]}}));
+        }
+    }
+    Ok(gates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::workflow::Workflow;
+
+    fn workflow(switch: Value) -> Workflow {
+        let task = |id: &str| json!({"id": id, "name": id, "function": {"name": "map", "input": {"mappings": []}}});
+        Workflow::from_json(
+            &json!({"id": "wf", "name": "WF", "tasks": [
+                task("before"),
+                {"id": "route", "name": "Route", "function": {"name": "switch", "input": switch}},
+                task("a"),
+                task("b"),
+                task("end")
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn gates_branch_members_and_jumped_tasks() {
+        let gates = branch_gates(
+            "wf",
+            &workflow(json!({
+                "cases": [
+                    {"name": "first", "when": true, "tasks": ["a", "end"]},
+                    {"when": false, "goto": "end"}
+                ],
+                "default": {"tasks": ["a"]}
+            }))
+            .tasks,
+        )
+        .unwrap();
+        let selection = json!({"var": "metadata.branches.wf.route"});
+
+        assert!(gates[0].is_empty() && gates[1].is_empty());
+        assert_eq!(
+            gates[2],
+            [
+                json!({"in": [selection, ["first", "default"]]}),
+                json!({"!": {"in": [selection, [1]]}})
+            ]
+        );
+        assert_eq!(gates[3], [json!({"!": {"in": [selection, [1]]}})]);
+        assert_eq!(gates[4], [json!({"in": [selection, ["first"]]})]);
+    }
+
+    #[test]
+    fn rejects_unknown_backward_and_ambiguous_targets() {
+        let error = |switch: Value| {
+            branch_gates("wf", &workflow(switch).tasks)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert!(
+            error(json!({"cases": [{"when": true, "tasks": ["nope"]}]}))
+                .contains("unknown task 'nope'")
+        );
+        assert!(
+            error(json!({"cases": [{"when": true, "goto": "before"}]}))
+                .contains("does not come after it")
+        );
+        assert!(
+            error(json!({"cases": [{"when": true, "tasks": ["a"], "goto": "end"}]}))
+                .contains("exactly one of 'tasks' and 'goto'")
+        );
+    }
+}
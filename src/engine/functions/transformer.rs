@@ -0,0 +1,188 @@
+//! # Message Transformers
+//!
+//! Mount plain Rust transformation code as a task function. A
+//! [`MessageTransformer`] edits the message's `data` as a
+//! `serde_json::Value` — no [`TaskContext`], no typed input, no `Change`
+//! bookkeeping — and the [`Transformer`] adapter turns it into an
+//! [`AsyncFunctionHandler`]:
+//!
+//! ```rust,no_run
+//! use dataflow_rs::{Engine, Result, Transformer};
+//! use serde_json::Value;
+//!
+//! // Existing code, untouched.
+//! fn normalize(data: &mut Value) -> Result<()> {
+//!     if let Some(email) = data["email"].as_str() {
+//!         data["email"] = Value::from(email.trim().to_lowercase());
+//!     }
+//!     Ok(())
+//! }
+//!
+//! # fn demo() -> Result<()> {
+//! let engine = Engine::builder()
+//!     .register("normalize", Transformer::new(normalize))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The adapter compares `data` before and after the call with
+//! [`ChangeTracker`] and records each difference as a `Change` on the
+//! audit trail: an edited field on its own, an object that lost keys or an
+//! array that changed length whole. A
+//! transformer returning `Err` leaves `data` untouched. The task's
+//! `input`, if any, is ignored.
+
+use crate::engine::change_tracker::ChangeTracker;
+use crate::engine::error::Result;
+use crate::engine::functions::AsyncFunctionHandler;
+use crate::engine::task_context::TaskContext;
+use crate::engine::task_outcome::TaskOutcome;
+use async_trait::async_trait;
+use datavalue::OwnedDataValue;
+use serde_json::Value;
+
+/// Synchronous transformation of a message's `data`. Implemented for any
+/// `Fn(&mut Value) -> Result<()>`; mount one with [`Transformer`].
+pub trait MessageTransformer: Send + Sync + 'static {
+    fn transform(&self, data: &mut Value) -> Result<()>;
+}
+
+impl<F> MessageTransformer for F
+where
+    F: Fn(&mut Value) -> Result<()> + Send + Sync + 'static,
+{
+    fn transform(&self, data: &mut Value) -> Result<()> {
+        self(data)
+    }
+}
+
+/// Adapter running a [`MessageTransformer`] as a task function. See the
+/// [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Transformer<T>(T);
+
+impl<T: MessageTransformer> Transformer<T> {
+    pub fn new(transformer: T) -> Self {
+        Self(transformer)
+    }
+
+    /// The wrapped transformer.
+    pub fn inner(&self) -> &T {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<T: MessageTransformer> AsyncFunctionHandler for Transformer<T> {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        let before = ctx.data().clone();
+        let mut data = Value::from(&before);
+        self.0.transform(&mut data)?;
+        let after = OwnedDataValue::from(&data);
+
+        for change in ChangeTracker::diff_at("data", &before, &after) {
+            ctx.set(&change.path, change.new_value);
+        }
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::message::Message;
+    use datalogic_rs::Engine as DatalogicEngine;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    async fn run(
+        data: Value,
+        transformer: impl MessageTransformer,
+    ) -> (Result<TaskOutcome>, Value, Vec<(String, Value)>) {
+        let datalogic = Arc::new(DatalogicEngine::builder().build());
+        let mut message = Message::builder().build();
+        message.context = OwnedDataValue::from(&json!({"data": data}));
+        let mut ctx = TaskContext::new(&mut message, &datalogic);
+        let result = Transformer::new(transformer)
+            .execute(&mut ctx, &Value::Null)
+            .await;
+        let changes = ctx
+            .into_changes()
+            .into_iter()
+            .map(|c| (c.path.to_string(), Value::from(&c.new_value)))
+            .collect();
+        (result, Value::from(message.data()), changes)
+    }
+
+    #[tokio::test]
+    async fn records_the_differences_as_changes() {
+        let (result, data, changes) = run(
+            json!({"user": {"email": " A@B.io ", "name": "Ann"}, "tags": ["x", "y"], "old": 1}),
+            |data: &mut Value| {
+                data["user"]["email"] = json!("a@b.io");
+                data["user"]["verified"] = json!(true);
+                data["tags"][1] = json!("z");
+                data.as_object_mut().unwrap().remove("old");
+                Ok(())
+            },
+        )
+        .await;
+
+        assert!(matches!(result, Ok(TaskOutcome::Success)));
+        assert_eq!(
+            data,
+            json!({"user": {"email": "a@b.io", "name": "Ann", "verified": true}, "tags": ["x", "z"]})
+        );
+        // Removing `old` can't be expressed below `data`, so `data` is
+        // written whole.
+        assert_eq!(changes, [("data".to_string(), data)]);
+    }
+
+    #[tokio::test]
+    async fn records_edits_at_the_deepest_path() {
+        let (_, _, changes) = run(
+            json!({"user": {"email": "A@B.io", "a.b": 1}, "same": {"x.y": true, "z": 1}, "tags": ["x"]}),
+            |data: &mut Value| {
+                data["user"]["email"] = json!("a@b.io");
+                data["user"]["a.b"] = json!(2);
+                data["same"]["x.y"] = json!(true);
+                data["tags"][0] = json!("y");
+                data["tags"].as_array_mut().unwrap().push(json!("z"));
+                data["added"] = json!({"n": 1});
+                Ok(())
+            },
+        )
+        .await;
+
+        assert_eq!(
+            changes,
+            [
+                ("data.added".to_string(), json!({"n": 1})),
+                ("data.tags".to_string(), json!(["y", "z"])),
+                // `a.b` is not addressable as a path segment.
+                (
+                    "data.user".to_string(),
+                    json!({"email": "a@b.io", "a.b": 2})
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_transform_leaves_data_untouched() {
+        let (result, data, changes) = run(json!({"n": 1}), |data: &mut Value| {
+            data["n"] = json!(2);
+            Err(crate::engine::error::DataflowError::Validation(
+                "bad".into(),
+            ))
+        })
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(data, json!({"n": 1}));
+        assert!(changes.is_empty());
+    }
+}
@@ -10,6 +10,10 @@
 //! - Custom error messages for each rule
 //! - Non-destructive: validation is read-only and doesn't modify message data
 //! - Errors are collected in the message's error list
+//! - `required` shorthand for presence checks, optionally guarded by a
+//!   condition
+//! - `aggregate` rules for cross-array consistency (sum / count / min / max /
+//!   avg of an array field against a control value, with tolerance)
 //!
 //! ## Example Usage
 //!
@@ -25,6 +29,11 @@
 //!             {
 //!                 "logic": {">": [{"var": "data.age"}, 0]},
 //!                 "message": "Age must be positive"
+//!             },
+//!             {
+//!                 "type": "required",
+//!                 "paths": ["data.company.name", "data.company.vat_id"],
+//!                 "condition": {"==": [{"var": "data.account_type"}, "business"]}
 //!             }
 //!         ]
 //!     }
@@ -35,29 +44,468 @@ use crate::engine::error::{DataflowError, ErrorInfo, Result};
 use crate::engine::executor::{ArenaContext, with_arena};
 use crate::engine::message::{Change, Message};
 use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::utils::get_nested_value;
 use datalogic_rs::{Engine, Logic};
-use datavalue::DataValue;
+use datavalue::{DataValue, OwnedDataValue};
 use log::{debug, error};
-use serde::Deserialize;
-use serde_json::Value;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Value, json};
 use std::sync::Arc;
 
+/// Error code recorded for paths that fail a `required` rule.
+pub const REQUIRED_FIELD_MISSING: &str = "REQUIRED_FIELD_MISSING";
+
+/// Error code recorded when an `aggregate` rule's computed value does not
+/// match its expected value.
+pub const AGGREGATE_MISMATCH: &str = "AGGREGATE_MISMATCH";
+
+/// Error code recorded when an `aggregate` rule cannot be computed (missing
+/// array, non-numeric element, empty array for `min`/`max`, missing or
+/// non-numeric expected value).
+pub const AGGREGATE_INVALID: &str = "AGGREGATE_INVALID";
+
 /// Configuration for the validation function containing a list of rules.
 ///
 /// Each rule specifies a JSONLogic condition that must evaluate to `true`
 /// for the validation to pass. If a rule evaluates to anything other than
 /// `true`, its error message is added to the message's error list.
+///
+/// `{"type": "required", ...}` entries are expanded into one rule per path
+/// at parse time (see [`RequiredRule`]).
+///
+/// `rules_ref` names one or more [`RulePack`](crate::RulePack)s registered on
+/// the engine. Their rules are prepended to `rules` when the engine is
+/// built; an unknown name fails construction.
 #[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "RawValidationConfig")]
 pub struct ValidationConfig {
     /// List of validation rules to evaluate.
     pub rules: Vec<ValidationRule>,
+
+    /// `{"type": "aggregate", ...}` entries, evaluated after `rules`.
+    pub aggregates: Vec<AggregateRule>,
+
+    /// Rule packs still to be merged in. Emptied by engine construction.
+    pub rules_ref: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RawValidationConfig {
+    #[serde(default)]
+    rules: Option<Vec<RawRule>>,
+    #[serde(default)]
+    rules_ref: Option<OneOrMany>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<OneOrMany> for Vec<String> {
+    fn from(value: OneOrMany) -> Self {
+        match value {
+            OneOrMany::One(name) => vec![name],
+            OneOrMany::Many(names) => names,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawRule {
+    Required(RequiredRule),
+    Aggregate(AggregateRule),
+    Logic(ValidationRule),
+}
+
+/// Serializes in the input format: aggregates follow the plain rules in
+/// `rules`, and `required` shorthands appear expanded.
+impl Serialize for ValidationConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(untagged)]
+        enum Rule<'a> {
+            Logic(&'a ValidationRule),
+            Aggregate(&'a AggregateRule),
+        }
+
+        let mut state = serializer.serialize_struct("ValidationConfig", 2)?;
+        let rules: Vec<Rule<'_>> = self
+            .rules
+            .iter()
+            .map(Rule::Logic)
+            .chain(self.aggregates.iter().map(Rule::Aggregate))
+            .collect();
+        state.serialize_field("rules", &rules)?;
+        state.serialize_field("rules_ref", &self.rules_ref)?;
+        state.end()
+    }
+}
+
+impl TryFrom<RawValidationConfig> for ValidationConfig {
+    type Error = String;
+
+    fn try_from(raw: RawValidationConfig) -> std::result::Result<Self, Self::Error> {
+        if raw.rules.is_none() && raw.rules_ref.is_none() {
+            return Err("validation requires 'rules' or 'rules_ref'".to_string());
+        }
+        let raw_rules = raw.rules.unwrap_or_default();
+        let mut rules = Vec::with_capacity(raw_rules.len());
+        let mut aggregates = Vec::new();
+        for rule in raw_rules {
+            match rule {
+                RawRule::Required(required) => rules.extend(required.expand()),
+                RawRule::Aggregate(aggregate) => aggregates.push(aggregate),
+                RawRule::Logic(rule) => rules.push(rule),
+            }
+        }
+        Ok(Self {
+            rules,
+            aggregates,
+            rules_ref: raw.rules_ref.map(Vec::from).unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RequiredTag {
+    Required,
+}
+
+/// Presence-check shorthand: every path in `paths` must exist and be
+/// non-empty (not `null`, `""`, `[]` or `{}`; `0` and `false` count as
+/// present). When `condition` is set, the check only applies if it
+/// evaluates truthy.
+///
+/// ```json
+/// {
+///     "type": "required",
+///     "paths": ["data.email", "data.name"],
+///     "condition": {"var": "data.is_registered"},
+///     "message": "{path} is required"
+/// }
+/// ```
+///
+/// Each path expands to its own [`ValidationRule`] with code
+/// [`REQUIRED_FIELD_MISSING`] and the path recorded on the error.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequiredRule {
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    tag: RequiredTag,
+
+    /// Context paths that must be present.
+    pub paths: Vec<String>,
+
+    /// Optional JSONLogic guard.
+    #[serde(default)]
+    pub condition: Option<Value>,
+
+    /// Error message; `{path}` is replaced with the failing path. Defaults
+    /// to `"{path} is required"`.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl RequiredRule {
+    /// Expand into one standard rule per path.
+    pub fn expand(self) -> Vec<ValidationRule> {
+        let template = self.message.as_deref().unwrap_or("{path} is required");
+        self.paths
+            .iter()
+            .map(|path| {
+                let var = json!({"var": path});
+                let present = json!({"or": [
+                    {"!!": [var]},
+                    {"===": [var, 0]},
+                    {"===": [var, false]}
+                ]});
+                let logic = match &self.condition {
+                    Some(condition) => json!({"if": [condition, present, true]}),
+                    None => present,
+                };
+                ValidationRule {
+                    logic,
+                    message: template.replace("{path}", path),
+                    code: Some(REQUIRED_FIELD_MISSING.to_string()),
+                    path: Some(path.clone()),
+                    compiled_logic: None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AggregateTag {
+    Aggregate,
+}
+
+/// Aggregate computed over an array by an [`AggregateRule`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateOp {
+    Sum,
+    Count,
+    Min,
+    Max,
+    Avg,
+}
+
+impl AggregateOp {
+    fn name(self) -> &'static str {
+        match self {
+            AggregateOp::Sum => "sum",
+            AggregateOp::Count => "count",
+            AggregateOp::Min => "min",
+            AggregateOp::Max => "max",
+            AggregateOp::Avg => "avg",
+        }
+    }
+}
+
+/// Comparison between the computed aggregate and the expected value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AggregateComparison {
+    #[default]
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "<=")]
+    Lte,
+    #[serde(rename = ">=")]
+    Gte,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = ">")]
+    Gt,
+}
+
+impl AggregateComparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            AggregateComparison::Eq => "==",
+            AggregateComparison::Lte => "<=",
+            AggregateComparison::Gte => ">=",
+            AggregateComparison::Lt => "<",
+            AggregateComparison::Gt => ">",
+        }
+    }
+}
+
+/// Expected value of an [`AggregateRule`]: a context path or a literal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AggregateExpected {
+    Literal(f64),
+    Path(String),
+}
+
+/// Cross-array consistency check: an aggregate of `field` over the elements
+/// of `array` must relate to `expected` (e.g. the sum of
+/// `transactions[*].amount` equals `header.control_sum`).
+///
+/// ```json
+/// {
+///     "type": "aggregate",
+///     "array": "data.transactions",
+///     "field": "amount",
+///     "op": "sum",
+///     "expected": "data.header.control_sum",
+///     "tolerance": 0.005
+/// }
+/// ```
+///
+/// Numeric strings (`"100.50"`) are accepted for element values and the
+/// expected value. A mismatch records [`AGGREGATE_MISMATCH`] with the
+/// computed and expected values in the message and the aggregated
+/// `array.*.field` expression as the error path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregateRule {
+    #[serde(rename = "type")]
+    #[allow(dead_code)]
+    tag: AggregateTag,
+
+    /// Context path of the array to aggregate.
+    pub array: String,
+
+    /// Dot-path of the value inside each element. Required for every op
+    /// except `count`; when absent, elements themselves are aggregated.
+    #[serde(default)]
+    pub field: Option<String>,
+
+    /// Aggregate to compute.
+    pub op: AggregateOp,
+
+    /// Expected value: a context path or a number.
+    pub expected: AggregateExpected,
+
+    /// Comparison applied as `aggregate <comparison> expected`. Defaults to
+    /// `==`.
+    #[serde(default)]
+    pub comparison: AggregateComparison,
+
+    /// Absolute tolerance for `==`. Defaults to `1e-9` to absorb float
+    /// rounding.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+
+    /// Relative tolerance for `==`, as a fraction of `|expected|`. The
+    /// larger of the two tolerances applies.
+    #[serde(default)]
+    pub relative_tolerance: f64,
+
+    /// Error message. `{actual}` and `{expected}` are substituted.
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+fn default_tolerance() -> f64 {
+    1e-9
+}
+
+fn numeric(value: &OwnedDataValue) -> Option<f64> {
+    match value {
+        OwnedDataValue::String(s) => s.trim().parse().ok(),
+        other => other.as_f64(),
+    }
+}
+
+impl AggregateRule {
+    /// Human-readable aggregate expression, used as the error path.
+    pub(crate) fn expression(&self) -> String {
+        match &self.field {
+            Some(field) => format!("{}.*.{}", self.array, field),
+            None => format!("{}.*", self.array),
+        }
+    }
+
+    /// Compute the aggregate, or `(message, path)` describing why it can't
+    /// be computed.
+    fn compute(&self, context: &OwnedDataValue) -> std::result::Result<f64, (String, String)> {
+        let items = match get_nested_value(context, &self.array) {
+            Some(OwnedDataValue::Array(items)) => items,
+            _ => {
+                return Err((
+                    format!("'{}' is not an array", self.array),
+                    self.array.clone(),
+                ));
+            }
+        };
+        if self.op == AggregateOp::Count {
+            return Ok(items.len() as f64);
+        }
+
+        let mut values = Vec::with_capacity(items.len());
+        for (idx, item) in items.iter().enumerate() {
+            let value = match &self.field {
+                Some(field) => get_nested_value(item, field),
+                None => Some(item),
+            };
+            match value.and_then(numeric) {
+                Some(n) => values.push(n),
+                None => {
+                    let path = match &self.field {
+                        Some(field) => format!("{}.{}.{}", self.array, idx, field),
+                        None => format!("{}.{}", self.array, idx),
+                    };
+                    return Err((format!("'{}' is not numeric", path), path));
+                }
+            }
+        }
+
+        if values.is_empty() && matches!(self.op, AggregateOp::Min | AggregateOp::Max) {
+            return Err((
+                format!(
+                    "'{}' is empty, so its {} is undefined",
+                    self.array,
+                    self.op.name()
+                ),
+                self.array.clone(),
+            ));
+        }
+
+        Ok(match self.op {
+            AggregateOp::Sum => values.iter().sum(),
+            AggregateOp::Avg if values.is_empty() => 0.0,
+            AggregateOp::Avg => values.iter().sum::<f64>() / values.len() as f64,
+            AggregateOp::Min => values.iter().copied().fold(f64::INFINITY, f64::min),
+            AggregateOp::Max => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            AggregateOp::Count => unreachable!(),
+        })
+    }
+
+    /// Evaluate the rule against `context`; `Some(error)` on failure.
+    pub fn check(&self, context: &OwnedDataValue) -> Option<ErrorInfo> {
+        let actual = match self.compute(context) {
+            Ok(actual) => actual,
+            Err((message, path)) => {
+                return Some(ErrorInfo::simple_ref(
+                    AGGREGATE_INVALID,
+                    &message,
+                    Some(&path),
+                ));
+            }
+        };
+        let (expected, expected_label) = match &self.expected {
+            AggregateExpected::Literal(n) => (*n, n.to_string()),
+            AggregateExpected::Path(path) => {
+                match get_nested_value(context, path).and_then(numeric) {
+                    Some(n) => (n, format!("{} ({})", n, path)),
+                    None => {
+                        return Some(ErrorInfo::simple_ref(
+                            AGGREGATE_INVALID,
+                            &format!("expected value '{}' is missing or not numeric", path),
+                            Some(path),
+                        ));
+                    }
+                }
+            }
+        };
+
+        let tolerance = self.tolerance.max(self.relative_tolerance * expected.abs());
+        let ok = match self.comparison {
+            AggregateComparison::Eq => (actual - expected).abs() <= tolerance,
+            AggregateComparison::Lte => actual <= expected,
+            AggregateComparison::Gte => actual >= expected,
+            AggregateComparison::Lt => actual < expected,
+            AggregateComparison::Gt => actual > expected,
+        };
+        if ok {
+            return None;
+        }
+
+        let message = match &self.message {
+            Some(template) => template
+                .replace("{actual}", &actual.to_string())
+                .replace("{expected}", &expected.to_string()),
+            None => format!(
+                "{} of {} is {}, expected {} {}",
+                self.op.name(),
+                self.expression(),
+                actual,
+                self.comparison.symbol(),
+                expected_label
+            ),
+        };
+        Some(ErrorInfo::simple_ref(
+            AGGREGATE_MISMATCH,
+            &message,
+            Some(&self.expression()),
+        ))
+    }
 }
 
 /// A single validation rule with a condition and error message.
 ///
 /// The rule's logic is evaluated against the message context. If it does not
 /// return exactly `true`, the validation fails and the error message is recorded.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ValidationRule {
     /// JSONLogic expression that must evaluate to `true` for validation to pass.
     /// Any other result (false, null, etc.) is considered a validation failure.
@@ -67,6 +515,14 @@ pub struct ValidationRule {
     /// Defaults to "Validation failed" if not specified.
     pub message: String,
 
+    /// Error code recorded on failure. Defaults to `VALIDATION_ERROR`.
+    #[serde(default)]
+    pub code: Option<String>,
+
+    /// Context path recorded on the error, if any.
+    #[serde(default)]
+    pub path: Option<String>,
+
     /// Pre-compiled JSONLogic, populated by `LogicCompiler`. `None` is
     /// recorded as a `COMPILATION_ERROR` at execute time.
     #[serde(skip)]
@@ -84,18 +540,55 @@ impl ValidationConfig {
     /// - The "rules" field is missing
     /// - The "rules" field is not an array
     /// - Any rule is missing the "logic" field
+    /// - A `required` or `aggregate` rule is malformed
+    /// - `rules_ref` is neither a string nor an array of strings
     pub fn from_json(input: &Value) -> Result<Self> {
-        let rules = input.get("rules").ok_or_else(|| {
-            DataflowError::Validation("Missing 'rules' array in input".to_string())
-        })?;
+        let rules_ref: Vec<String> = match input.get("rules_ref") {
+            None => Vec::new(),
+            Some(value) => serde_json::from_value::<OneOrMany>(value.clone())
+                .map_err(|_| {
+                    DataflowError::Validation(
+                        "'rules_ref' must be a string or an array of strings".to_string(),
+                    )
+                })?
+                .into(),
+        };
+
+        let empty = Value::Array(Vec::new());
+        let rules = match input.get("rules") {
+            Some(rules) => rules,
+            None if input.get("rules_ref").is_some() => &empty,
+            None => {
+                return Err(DataflowError::Validation(
+                    "Missing 'rules' array in input".to_string(),
+                ));
+            }
+        };
 
         let rules_arr = rules
             .as_array()
             .ok_or_else(|| DataflowError::Validation("'rules' must be an array".to_string()))?;
 
         let mut parsed_rules = Vec::new();
+        let mut aggregates = Vec::new();
 
         for rule in rules_arr {
+            if rule.get("type").and_then(Value::as_str) == Some("required") {
+                let required: RequiredRule = serde_json::from_value(rule.clone()).map_err(|e| {
+                    DataflowError::Validation(format!("Invalid 'required' rule: {}", e))
+                })?;
+                parsed_rules.extend(required.expand());
+                continue;
+            }
+            if rule.get("type").and_then(Value::as_str) == Some("aggregate") {
+                let aggregate: AggregateRule =
+                    serde_json::from_value(rule.clone()).map_err(|e| {
+                        DataflowError::Validation(format!("Invalid 'aggregate' rule: {}", e))
+                    })?;
+                aggregates.push(aggregate);
+                continue;
+            }
+
             let logic = rule
                 .get("logic")
                 .ok_or_else(|| DataflowError::Validation("Missing 'logic' in rule".to_string()))?
@@ -110,12 +603,16 @@ impl ValidationConfig {
             parsed_rules.push(ValidationRule {
                 logic,
                 message,
+                code: rule.get("code").and_then(Value::as_str).map(str::to_string),
+                path: rule.get("path").and_then(Value::as_str).map(str::to_string),
                 compiled_logic: None,
             });
         }
 
         Ok(ValidationConfig {
             rules: parsed_rules,
+            aggregates,
+            rules_ref,
         })
     }
 
@@ -199,9 +696,9 @@ impl ValidationConfig {
                     if !matches!(value, DataValue::Bool(true)) {
                         debug!("Validation failed for rule {}: {}", idx, rule.message);
                         validation_errors.push(ErrorInfo::simple_ref(
-                            "VALIDATION_ERROR",
+                            rule.code.as_deref().unwrap_or("VALIDATION_ERROR"),
                             &rule.message,
-                            None,
+                            rule.path.as_deref(),
                         ));
                     } else {
                         debug!("Validation passed for rule {}", idx);
@@ -218,6 +715,18 @@ impl ValidationConfig {
             }
         }
 
+        for (idx, aggregate) in self.aggregates.iter().enumerate() {
+            debug!(
+                "Processing aggregate rule {}: {}",
+                idx,
+                aggregate.expression()
+            );
+            if let Some(err) = aggregate.check(&message.context) {
+                debug!("Aggregate rule {} failed: {}", idx, err.message);
+                validation_errors.push(err);
+            }
+        }
+
         if !validation_errors.is_empty() {
             message.errors.extend(validation_errors);
             Ok((TaskOutcome::Status(400), changes))
@@ -333,14 +842,20 @@ mod tests {
                 ValidationRule {
                     logic: json!({"!!": [{"var": "data.email"}]}),
                     message: "Email is required".to_string(),
+                    code: None,
+                    path: None,
                     compiled_logic: None,
                 },
                 ValidationRule {
                     logic: json!({">": [{"var": "data.age"}, 18]}),
                     message: "Must be over 18".to_string(),
+                    code: None,
+                    path: None,
                     compiled_logic: None,
                 },
             ],
+            aggregates: Vec::new(),
+            rules_ref: Vec::new(),
         };
         compile_rules(&engine, &mut config);
 
@@ -364,14 +879,20 @@ mod tests {
                 ValidationRule {
                     logic: json!({"!!": [{"var": "data.email"}]}),
                     message: "Email is required".to_string(),
+                    code: None,
+                    path: None,
                     compiled_logic: None,
                 },
                 ValidationRule {
                     logic: json!({">": [{"var": "data.age"}, 18]}),
                     message: "Must be over 18".to_string(),
+                    code: None,
+                    path: None,
                     compiled_logic: None,
                 },
             ],
+            aggregates: Vec::new(),
+            rules_ref: Vec::new(),
         };
         compile_rules(&engine, &mut config);
 
@@ -399,8 +920,12 @@ mod tests {
             rules: vec![ValidationRule {
                 logic: json!(true),
                 message: "Test".to_string(),
+                code: None,
+                path: None,
                 compiled_logic: None,
             }],
+            aggregates: Vec::new(),
+            rules_ref: Vec::new(),
         };
 
         let result = config.execute(&mut message, &engine);
@@ -411,4 +936,196 @@ mod tests {
         assert!(!message.errors.is_empty());
         assert!(message.errors[0].code == "COMPILATION_ERROR");
     }
+
+    #[test]
+    fn test_required_rule_expands_per_path() {
+        let input = json!({
+            "rules": [
+                {"type": "required", "paths": ["data.a", "data.b"]},
+                {"logic": true, "message": "ok"}
+            ]
+        });
+        let from_serde: ValidationConfig = serde_json::from_value(input.clone()).unwrap();
+        let from_json = ValidationConfig::from_json(&input).unwrap();
+        for config in [from_serde, from_json] {
+            assert_eq!(config.rules.len(), 3);
+            assert_eq!(config.rules[1].message, "data.b is required");
+            assert_eq!(
+                config.rules[1].code.as_deref(),
+                Some(REQUIRED_FIELD_MISSING)
+            );
+            assert_eq!(config.rules[1].path.as_deref(), Some("data.b"));
+        }
+    }
+
+    #[test]
+    fn test_required_rule_presence_semantics() {
+        let engine = Arc::new(Engine::builder().with_templating(true).build());
+        let mut message = message_with_data(json!({
+            "zero": 0, "no": false, "text": "x",
+            "empty": "", "list": [], "obj": {}, "nothing": null
+        }));
+        let mut config: ValidationConfig = serde_json::from_value(json!({
+            "rules": [{
+                "type": "required",
+                "paths": [
+                    "data.zero", "data.no", "data.text",
+                    "data.empty", "data.list", "data.obj", "data.nothing", "data.absent"
+                ]
+            }]
+        }))
+        .unwrap();
+        compile_rules(&engine, &mut config);
+
+        let (outcome, _) = config.execute(&mut message, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Status(400));
+        let failed: Vec<_> = message
+            .errors
+            .iter()
+            .map(|e| (e.code.as_str(), e.path.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            failed,
+            vec![
+                (REQUIRED_FIELD_MISSING, "data.empty"),
+                (REQUIRED_FIELD_MISSING, "data.list"),
+                (REQUIRED_FIELD_MISSING, "data.obj"),
+                (REQUIRED_FIELD_MISSING, "data.nothing"),
+                (REQUIRED_FIELD_MISSING, "data.absent"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_rules_check_control_values() {
+        let engine = Arc::new(Engine::builder().with_templating(true).build());
+        let input = json!({
+            "rules": [
+                {
+                    "type": "aggregate",
+                    "array": "data.transactions",
+                    "field": "amount",
+                    "op": "sum",
+                    "expected": "data.header.control_sum"
+                },
+                {
+                    "type": "aggregate",
+                    "array": "data.transactions",
+                    "op": "count",
+                    "expected": "data.header.nb_of_txs"
+                },
+                {
+                    "type": "aggregate",
+                    "array": "data.transactions",
+                    "field": "amount",
+                    "op": "max",
+                    "comparison": "<=",
+                    "expected": 100
+                }
+            ]
+        });
+        let config: ValidationConfig = serde_json::from_value(input.clone()).unwrap();
+        assert_eq!(config.aggregates.len(), 3);
+        assert_eq!(
+            ValidationConfig::from_json(&input)
+                .unwrap()
+                .aggregates
+                .len(),
+            3
+        );
+
+        let mut ok = message_with_data(json!({
+            "header": {"control_sum": "60.3", "nb_of_txs": 3},
+            "transactions": [{"amount": 10.1}, {"amount": "20.1"}, {"amount": 30.1}]
+        }));
+        let (outcome, _) = config.execute(&mut ok, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+
+        let mut bad = message_with_data(json!({
+            "header": {"control_sum": 60, "nb_of_txs": 2},
+            "transactions": [{"amount": 10}, {"amount": 20}, {"amount": 150}]
+        }));
+        let (outcome, _) = config.execute(&mut bad, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Status(400));
+        assert_eq!(bad.errors.len(), 3);
+        assert!(bad.errors.iter().all(|e| e.code == AGGREGATE_MISMATCH));
+        assert_eq!(
+            bad.errors[0].path.as_deref(),
+            Some("data.transactions.*.amount")
+        );
+        assert_eq!(
+            bad.errors[0].message,
+            "sum of data.transactions.*.amount is 180, expected == 60 (data.header.control_sum)"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_rule_tolerance_and_invalid_data() {
+        let rule: AggregateRule = serde_json::from_value(json!({
+            "type": "aggregate",
+            "array": "data.items",
+            "field": "v",
+            "op": "avg",
+            "expected": 10,
+            "relative_tolerance": 0.1
+        }))
+        .unwrap();
+        let ctx = |items: serde_json::Value| dv(json!({"data": {"items": items}}));
+
+        assert!(rule.check(&ctx(json!([{"v": 9}, {"v": 10.5}]))).is_none());
+        assert!(rule.check(&ctx(json!([{"v": 8}, {"v": 9}]))).is_some());
+
+        let err = rule.check(&ctx(json!([{"v": 1}, {"v": "x"}]))).unwrap();
+        assert_eq!(err.code, AGGREGATE_INVALID);
+        assert_eq!(err.path.as_deref(), Some("data.items.1.v"));
+
+        let err = rule.check(&dv(json!({"data": {}}))).unwrap();
+        assert_eq!(err.code, AGGREGATE_INVALID);
+    }
+
+    #[test]
+    fn test_aggregate_rule_min_max_of_empty_array_is_invalid() {
+        for op in ["min", "max"] {
+            let rule: AggregateRule = serde_json::from_value(json!({
+                "type": "aggregate",
+                "array": "data.items",
+                "field": "v",
+                "op": op,
+                "expected": 0,
+                "comparison": "<="
+            }))
+            .unwrap();
+            let err = rule.check(&dv(json!({"data": {"items": []}}))).unwrap();
+            assert_eq!(err.code, AGGREGATE_INVALID, "{op}");
+            assert_eq!(err.path.as_deref(), Some("data.items"));
+            assert!(!err.message.contains("inf"), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn test_required_rule_condition_guards_check() {
+        let engine = Arc::new(Engine::builder().with_templating(true).build());
+        let mut config: ValidationConfig = serde_json::from_value(json!({
+            "rules": [{
+                "type": "required",
+                "paths": ["data.company"],
+                "condition": {"==": [{"var": "data.kind"}, "business"]},
+                "message": "{path} is required for business accounts"
+            }]
+        }))
+        .unwrap();
+        compile_rules(&engine, &mut config);
+
+        let mut personal = message_with_data(json!({"kind": "personal"}));
+        let (outcome, _) = config.execute(&mut personal, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Success);
+
+        let mut business = message_with_data(json!({"kind": "business"}));
+        let (outcome, _) = config.execute(&mut business, &engine).unwrap();
+        assert_eq!(outcome, TaskOutcome::Status(400));
+        assert_eq!(
+            business.errors[0].message,
+            "data.company is required for business accounts"
+        );
+    }
 }
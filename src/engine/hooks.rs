@@ -0,0 +1,224 @@
+//! # Hooks
+//!
+//! ## Message Hooks
+//!
+//! Engine-level callbacks run around every `process_message` /
+//! `process_message_for_channel` call, for cross-cutting concerns that
+//! shouldn't be modelled as workflows: injecting auth context into
+//! metadata, rejecting messages up front, scrubbing sensitive fields before
+//! the message leaves the engine.
+//!
+//! Hooks run in registration order. `before_message` runs after the
+//! engine's processing metadata is stamped and before any workflow; an
+//! `Err` from it vetoes the message — no workflows run and the error is
+//! returned. `after_message` runs once processing (or a veto) is done and
+//! sees the outcome.
+//!
+//! Errors from either hook are recorded in `message.errors` with code
+//! [`HOOK_ERROR`].
+//!
+//! ## Processing Hooks
+//!
+//! [`ProcessingHook`]s run around every executed workflow and task, on
+//! every entry point, for behaviour such as per-tenant auth checks, timing
+//! or shipping audit records. They are synchronous and see the message
+//! read-only: sync tasks run back to back against a shared evaluation
+//! context, so a hook must not block or mutate it. Hand slow work (network
+//! calls) off to a channel or background task.
+//!
+//! An `Err` from `before_workflow` or `before_task` fails that workflow or
+//! task exactly as if it had failed itself: it is recorded in
+//! `message.errors` and `continue_on_error` applies. Skipped workflows and
+//! tasks (condition not met) get no callbacks.
+
+use crate::engine::error::{DataflowError, ErrorInfo, Result};
+use crate::engine::message::{Change, Message};
+use crate::engine::task::Task;
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::workflow::Workflow;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Error code recorded when a hook fails or vetoes a message.
+pub const HOOK_ERROR: &str = "HOOK_ERROR";
+
+/// Callbacks run around each processed message. Both methods default to
+/// no-ops.
+#[async_trait]
+pub trait MessageHook: Send + Sync {
+    /// Called before any workflow runs. May mutate the message. Returning
+    /// `Err` vetoes processing.
+    async fn before_message(&self, message: &mut Message) -> Result<()> {
+        let _ = message;
+        Ok(())
+    }
+
+    /// Called after processing, with its result (the veto error if a
+    /// `before_message` hook rejected the message). May mutate the message.
+    /// Returning `Err` fails an otherwise successful call.
+    async fn after_message(&self, message: &mut Message, result: &Result<()>) -> Result<()> {
+        let _ = (message, result);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: MessageHook + ?Sized> MessageHook for Arc<T> {
+    async fn before_message(&self, message: &mut Message) -> Result<()> {
+        (**self).before_message(message).await
+    }
+
+    async fn after_message(&self, message: &mut Message, result: &Result<()>) -> Result<()> {
+        (**self).after_message(message, result).await
+    }
+}
+
+/// Callbacks run around each executed workflow and task. Every method
+/// defaults to a no-op. See the [module docs](self).
+pub trait ProcessingHook: Send + Sync {
+    /// Called once the workflow's condition has passed, before its first
+    /// task. Returning `Err` fails the workflow without running any task.
+    fn before_workflow(&self, workflow: &Workflow, message: &Message) -> Result<()> {
+        let _ = (workflow, message);
+        Ok(())
+    }
+
+    /// Called after the workflow's tasks, with the result.
+    fn after_workflow(&self, workflow: &Workflow, message: &Message, result: &Result<()>) {
+        let _ = (workflow, message, result);
+    }
+
+    /// Called once the task's condition has passed. Returning `Err` fails
+    /// the task without running it.
+    fn before_task(&self, workflow: &Workflow, task: &Task, message: &Message) -> Result<()> {
+        let _ = (workflow, task, message);
+        Ok(())
+    }
+
+    /// Called after the task ran (or was vetoed), with its outcome and the
+    /// changes it made, before the outcome is applied to the audit trail.
+    fn after_task(
+        &self,
+        workflow: &Workflow,
+        task: &Task,
+        message: &Message,
+        result: &Result<(TaskOutcome, Vec<Change>)>,
+    ) {
+        let _ = (workflow, task, message, result);
+    }
+
+    /// Called when a task (`task` is `Some`) or a workflow (`None`) fails
+    /// with an error, after `after_task` / `after_workflow`. Error statuses
+    /// returned as `Ok` outcomes are not errors here.
+    fn on_error(
+        &self,
+        workflow: &Workflow,
+        task: Option<&Task>,
+        message: &Message,
+        error: &DataflowError,
+    ) {
+        let _ = (workflow, task, message, error);
+    }
+}
+
+impl<T: ProcessingHook + ?Sized> ProcessingHook for Arc<T> {
+    fn before_workflow(&self, workflow: &Workflow, message: &Message) -> Result<()> {
+        (**self).before_workflow(workflow, message)
+    }
+
+    fn after_workflow(&self, workflow: &Workflow, message: &Message, result: &Result<()>) {
+        (**self).after_workflow(workflow, message, result)
+    }
+
+    fn before_task(&self, workflow: &Workflow, task: &Task, message: &Message) -> Result<()> {
+        (**self).before_task(workflow, task, message)
+    }
+
+    fn after_task(
+        &self,
+        workflow: &Workflow,
+        task: &Task,
+        message: &Message,
+        result: &Result<(TaskOutcome, Vec<Change>)>,
+    ) {
+        (**self).after_task(workflow, task, message, result)
+    }
+
+    fn on_error(
+        &self,
+        workflow: &Workflow,
+        task: Option<&Task>,
+        message: &Message,
+        error: &DataflowError,
+    ) {
+        (**self).on_error(workflow, task, message, error)
+    }
+}
+
+/// Registered processing hooks, shared by the engines produced from one
+/// builder (including hot reloads).
+pub(crate) type ProcessingHooks = Arc<Vec<Arc<dyn ProcessingHook>>>;
+
+/// Adapter for [`EngineBuilder::before_message`](crate::EngineBuilder::before_message).
+pub(crate) struct BeforeFn<F>(pub(crate) F);
+
+#[async_trait]
+impl<F> MessageHook for BeforeFn<F>
+where
+    F: Fn(&mut Message) -> Result<()> + Send + Sync,
+{
+    async fn before_message(&self, message: &mut Message) -> Result<()> {
+        (self.0)(message)
+    }
+}
+
+/// Adapter for [`EngineBuilder::after_message`](crate::EngineBuilder::after_message).
+pub(crate) struct AfterFn<F>(pub(crate) F);
+
+#[async_trait]
+impl<F> MessageHook for AfterFn<F>
+where
+    F: Fn(&mut Message, &Result<()>) -> Result<()> + Send + Sync,
+{
+    async fn after_message(&self, message: &mut Message, result: &Result<()>) -> Result<()> {
+        (self.0)(message, result)
+    }
+}
+
+/// Run every `before_message` hook; stop at the first veto.
+pub(crate) async fn run_before(
+    hooks: &[Arc<dyn MessageHook>],
+    message: &mut Message,
+) -> Result<()> {
+    for hook in hooks {
+        if let Err(e) = hook.before_message(message).await {
+            record(message, &e, "rejected");
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Run every `after_message` hook. The first hook error replaces an `Ok`
+/// result; later hooks still run.
+pub(crate) async fn run_after(
+    hooks: &[Arc<dyn MessageHook>],
+    message: &mut Message,
+    mut result: Result<()>,
+) -> Result<()> {
+    for hook in hooks {
+        if let Err(e) = hook.after_message(message, &result).await {
+            record(message, &e, "failed");
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+fn record(message: &mut Message, error: &DataflowError, what: &str) {
+    message
+        .errors
+        .push(ErrorInfo::builder(HOOK_ERROR, format!("Message hook {}: {}", what, error)).build());
+}
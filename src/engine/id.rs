@@ -0,0 +1,135 @@
+//! # Message IDs
+//!
+//! [`Engine::message`](crate::Engine::message) starts a [`MessageBuilder`]
+//! whose id comes from the engine's [`IdGenerator`], set with
+//! [`EngineBuilder::with_id_generator`](crate::EngineBuilder::with_id_generator).
+//! An id passed to [`MessageBuilder::id`] still wins, for ids supplied by
+//! an upstream system.
+//!
+//! - [`UuidV7`] (default): time-ordered UUIDs, the same ids
+//!   [`Message::new`](crate::Message::new) assigns.
+//! - [`Snowflake`]: 63-bit ids of milliseconds since an epoch, a worker id
+//!   and a per-millisecond sequence, as zero-padded decimal so they sort
+//!   as strings too.
+//! - Any `Fn() -> String + Send + Sync` closure, e.g. one drawing from an
+//!   external id service.
+
+use crate::engine::message::MessageBuilder;
+use chrono::Utc;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Source of ids for new messages.
+pub trait IdGenerator: Send + Sync {
+    /// A new, unique id.
+    fn next_id(&self) -> String;
+}
+
+impl<F> IdGenerator for F
+where
+    F: Fn() -> String + Send + Sync,
+{
+    fn next_id(&self) -> String {
+        self()
+    }
+}
+
+/// UUID v7 ids: a millisecond timestamp in the high bits and a random tail.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidV7;
+
+impl IdGenerator for UuidV7 {
+    fn next_id(&self) -> String {
+        Uuid::now_v7().to_string()
+    }
+}
+
+/// Snowflake ids: 41 bits of milliseconds since `epoch_ms`, 10 bits of
+/// worker id and a 12-bit sequence. Ids from one generator strictly
+/// increase; generators with distinct worker ids never collide. When the
+/// 4096 ids of a millisecond run out, the generator waits for the next one.
+#[derive(Debug)]
+pub struct Snowflake {
+    worker_id: u64,
+    epoch_ms: i64,
+    /// Last millisecond an id was issued in, and the sequence used.
+    state: Mutex<(i64, u64)>,
+}
+
+const WORKER_BITS: u32 = 10;
+const SEQUENCE_BITS: u32 = 12;
+const MAX_SEQUENCE: u64 = (1 << SEQUENCE_BITS) - 1;
+
+impl Snowflake {
+    /// Highest accepted worker id.
+    pub const MAX_WORKER_ID: u16 = (1 << WORKER_BITS) - 1;
+    /// Default epoch: 2024-01-01T00:00:00Z.
+    pub const DEFAULT_EPOCH_MS: i64 = 1_704_067_200_000;
+
+    /// Generator for `worker_id` (masked to 10 bits) with the default epoch.
+    pub fn new(worker_id: u16) -> Self {
+        Self::with_epoch(worker_id, Self::DEFAULT_EPOCH_MS)
+    }
+
+    /// Generator counting milliseconds from `epoch_ms` (Unix time).
+    pub fn with_epoch(worker_id: u16, epoch_ms: i64) -> Self {
+        Self {
+            worker_id: u64::from(worker_id & Self::MAX_WORKER_ID),
+            epoch_ms,
+            state: Mutex::new((-1, 0)),
+        }
+    }
+
+    /// The next id as a number.
+    pub fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (last_ms, sequence) = *state;
+        // A clock that steps back keeps issuing from the last millisecond.
+        let mut now = (Utc::now().timestamp_millis() - self.epoch_ms).max(last_ms);
+        let sequence = if now == last_ms {
+            if sequence == MAX_SEQUENCE {
+                while now <= last_ms {
+                    std::hint::spin_loop();
+                    now = Utc::now().timestamp_millis() - self.epoch_ms;
+                }
+                0
+            } else {
+                sequence + 1
+            }
+        } else {
+            0
+        };
+        *state = (now, sequence);
+        (now.max(0) as u64) << (WORKER_BITS + SEQUENCE_BITS)
+            | self.worker_id << SEQUENCE_BITS
+            | sequence
+    }
+}
+
+impl IdGenerator for Snowflake {
+    fn next_id(&self) -> String {
+        format!("{:019}", self.next_u64())
+    }
+}
+
+/// Builder for a message whose id comes from `generator`.
+pub(crate) fn message_builder(generator: &dyn IdGenerator) -> MessageBuilder {
+    MessageBuilder::new().id(generator.next_id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snowflake_ids_increase_and_carry_the_worker() {
+        let generator = Snowflake::new(7);
+        let ids: Vec<u64> = (0..10_000).map(|_| generator.next_u64()).collect();
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ids.iter().all(|id| (id >> SEQUENCE_BITS) & 0x3ff == 7));
+
+        let text = generator.next_id();
+        assert_eq!(text.len(), 19);
+        assert!(text > format!("{:019}", ids[9_999]));
+    }
+}
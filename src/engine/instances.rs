@@ -0,0 +1,122 @@
+//! # Pending Instances
+//!
+//! Work the engine holds between messages, waiting for something to
+//! happen:
+//!
+//! - an open `join` group, waiting for the rest of its messages or its
+//!   timeout (see the [`join`](crate::engine::functions::join) module);
+//! - a persisted retry, waiting for the message to be redelivered after
+//!   its backoff (see the [`state`](crate::engine::state) module).
+//!
+//! [`Engine::pending_instances`](crate::Engine::pending_instances) lists
+//! them with their correlation key, age and what they are waiting for.
+//! When one is stuck — the last part of a batch was lost, the message
+//! will never be redelivered —
+//! [`Engine::force_complete`](crate::Engine::force_complete) finishes a
+//! join group with the parts it has, and
+//! [`Engine::force_expire`](crate::Engine::force_expire) drops the
+//! instance.
+//!
+//! Listing reads the keys of the engine's state store, so it needs a store
+//! implementing [`StateStore::keys_with_prefix`].
+
+use crate::engine::error::Result;
+use crate::engine::state::{RetryState, StateStore, retry_state_key};
+use crate::engine::workflow::Workflow;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+
+/// What a pending instance is waiting for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum WaitReason {
+    /// A `join` group waiting for more messages.
+    Join {
+        /// Messages in the group so far, in arrival order.
+        message_ids: Vec<String>,
+        /// Group size that completes it, when set.
+        count: Option<usize>,
+        /// When the next arriving message completes the group, when a
+        /// timeout is set.
+        timeout_at: Option<DateTime<Utc>>,
+    },
+    /// A task waiting for its message to be redelivered for the next retry.
+    Retry {
+        /// Retries made so far.
+        retries: u32,
+        /// When the next attempt is due.
+        next_attempt_at: DateTime<Utc>,
+    },
+}
+
+/// One instance the engine is holding. Returned by
+/// [`Engine::pending_instances`](crate::Engine::pending_instances).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingInstance {
+    pub workflow_id: String,
+    pub task_id: String,
+    /// Correlation key of a join group; message id of a retry.
+    pub key: String,
+    /// When the instance started waiting; `None` when not recorded
+    /// (retries).
+    pub opened_at: Option<DateTime<Utc>>,
+    pub reason: WaitReason,
+}
+
+impl PendingInstance {
+    /// How long the instance has been waiting, when known.
+    pub fn age(&self) -> Option<TimeDelta> {
+        self.opened_at.map(|at| Utc::now() - at)
+    }
+
+    /// State store key the instance is kept under.
+    pub(crate) fn store_key(&self) -> String {
+        match self.reason {
+            WaitReason::Join { .. } => {
+                format!("join:{}:{}:{}", self.workflow_id, self.task_id, self.key)
+            }
+            WaitReason::Retry { .. } => {
+                retry_state_key(&self.key, &self.workflow_id, &self.task_id)
+            }
+        }
+    }
+}
+
+/// Persisted retries of the tasks of `workflows`, by message.
+pub(crate) async fn retries(
+    store: &dyn StateStore,
+    workflows: &[Workflow],
+) -> Result<Vec<PendingInstance>> {
+    let mut pending = Vec::new();
+    for key in store.keys_with_prefix("retry:").await? {
+        let Some(state) = store
+            .get(&key)
+            .await?
+            .and_then(|value| serde_json::from_value::<RetryState>(value).ok())
+        else {
+            continue;
+        };
+        // Message ids may contain ':', so match the known task suffixes.
+        let owner = workflows.iter().find_map(|workflow| {
+            workflow.tasks.iter().find_map(|task| {
+                let suffix = format!(":{}:{}", workflow.id, task.id);
+                key.strip_prefix("retry:")?
+                    .strip_suffix(&suffix)
+                    .map(|message_id| (workflow, task, message_id))
+            })
+        });
+        if let Some((workflow, task, message_id)) = owner {
+            pending.push(PendingInstance {
+                workflow_id: workflow.id.clone(),
+                task_id: task.id.clone(),
+                key: message_id.to_string(),
+                opened_at: None,
+                reason: WaitReason::Retry {
+                    retries: state.retries,
+                    next_attempt_at: state.next_attempt_at,
+                },
+            });
+        }
+    }
+    Ok(pending)
+}
@@ -0,0 +1,290 @@
+//! # Config Interpolation
+//!
+//! Placeholders in task inputs are filled in when the engine is built, so
+//! one workflow artifact serves every environment:
+//!
+//! ```json
+//! {"name": "http_call", "input": {
+//!     "connector": "payments_${DEPLOY_ENV}",
+//!     "path": "${vars.api_base}/orders",
+//!     "timeout_ms": "${vars.http_timeout_ms}"
+//! }}
+//! ```
+//!
+//! - `${vars.<path>}` reads the engine's variables
+//!   ([`InterpolationConfig::vars`]); the path may descend into objects.
+//! - `${NAME}` reads environment variable `NAME`.
+//! - `${secret:NAME}` asks the engine's
+//!   [`SecretsResolver`] for secret `NAME` (see [`secrets`](crate::engine::secrets)).
+//! - `$${` writes a literal `${`.
+//!
+//! A string that is exactly one `vars` placeholder takes the variable's
+//! value with its JSON type, so numbers and booleans stay numbers and
+//! booleans. Anywhere else values are spliced in as text; environment
+//! variables and secrets are always text.
+//!
+//! Every string in a task's function input is interpolated, JSONLogic
+//! included, after [fragments](crate::engine::fragment) are inlined and
+//! before workflows are validated. Conditions are not interpolated. An
+//! unresolved placeholder is left as written with a warning, or fails
+//! engine construction when [`InterpolationConfig::strict`] is set; an
+//! unresolved secret always fails it.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::functions::FunctionConfig;
+use crate::engine::secrets::SecretsResolver;
+use crate::engine::task::Task;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Variables and checking for placeholders in task inputs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InterpolationConfig {
+    /// Values for `${vars.<name>}` placeholders.
+    pub vars: BTreeMap<String, Value>,
+    /// Fail engine construction on an unresolved placeholder instead of
+    /// leaving it in place. Default: `false`.
+    pub strict: bool,
+}
+
+impl InterpolationConfig {
+    /// The value `${vars.<path>}` resolves to.
+    fn var(&self, path: &str) -> Option<&Value> {
+        let mut parts = path.split('.');
+        let mut value = self.vars.get(parts.next()?)?;
+        for part in parts {
+            value = match value {
+                Value::Object(map) => map.get(part)?,
+                Value::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(value)
+    }
+}
+
+/// What placeholders resolve against, and the secret values they resolved
+/// to.
+pub(crate) struct Placeholders<'a> {
+    config: &'a InterpolationConfig,
+    secrets: Option<&'a dyn SecretsResolver>,
+    /// Secret values spliced in so far.
+    pub(crate) resolved_secrets: Vec<String>,
+}
+
+impl<'a> Placeholders<'a> {
+    pub(crate) fn new(
+        config: &'a InterpolationConfig,
+        secrets: Option<&'a dyn SecretsResolver>,
+    ) -> Self {
+        Self {
+            config,
+            secrets,
+            resolved_secrets: Vec::new(),
+        }
+    }
+
+    /// The value of placeholder `name`; `None` when it is unresolved. A
+    /// secret that can't be resolved is an error.
+    fn lookup(&mut self, name: &str) -> std::result::Result<Option<Value>, String> {
+        if let Some(secret) = name.strip_prefix("secret:") {
+            let resolver = self
+                .secrets
+                .ok_or_else(|| format!("'${{{name}}}' needs a secrets resolver"))?;
+            let value = resolver
+                .resolve(secret)
+                .map_err(|e| format!("cannot resolve '${{{name}}}': {e}"))?;
+            self.resolved_secrets.push(value.clone());
+            return Ok(Some(Value::String(value)));
+        }
+        Ok(match name.strip_prefix("vars.") {
+            Some(path) => self.config.var(path).cloned(),
+            None => std::env::var(name).ok().map(Value::String),
+        })
+    }
+}
+
+/// Interpolate the function inputs of `tasks`, `foreach` bodies included.
+pub(crate) fn interpolate_tasks(
+    tasks: &mut [Task],
+    placeholders: &mut Placeholders<'_>,
+    workflow_id: &str,
+) -> Result<()> {
+    for task in tasks {
+        let context = |message: String| {
+            DataflowError::Workflow(format!(
+                "Task {} in workflow {}: {}",
+                task.id, workflow_id, message
+            ))
+        };
+        if let FunctionConfig::Custom { input, .. } = &mut task.function {
+            interpolate_value(input, placeholders).map_err(context)?;
+            continue;
+        }
+        let mut function =
+            serde_json::to_value(&task.function).map_err(DataflowError::from_serde)?;
+        if !has_placeholder(&function) {
+            continue;
+        }
+        interpolate_value(&mut function, placeholders).map_err(context)?;
+        task.function = serde_json::from_value(function)
+            .map_err(|e| context(format!("interpolated input is invalid: {e}")))?;
+    }
+    Ok(())
+}
+
+fn has_placeholder(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.contains("${"),
+        Value::Array(items) => items.iter().any(has_placeholder),
+        Value::Object(map) => map.values().any(has_placeholder),
+        _ => false,
+    }
+}
+
+fn interpolate_value(
+    value: &mut Value,
+    placeholders: &mut Placeholders<'_>,
+) -> std::result::Result<(), String> {
+    match value {
+        Value::String(s) if s.contains("${") => *value = interpolate_str(s, placeholders)?,
+        Value::Array(items) => {
+            for item in items {
+                interpolate_value(item, placeholders)?;
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                interpolate_value(item, placeholders)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Interpolate one string. A string that is a single `vars` placeholder
+/// yields the variable's value as is.
+fn interpolate_str(
+    s: &str,
+    placeholders: &mut Placeholders<'_>,
+) -> std::result::Result<Value, String> {
+    if let Some(name) = s.strip_prefix("${").and_then(|rest| rest.strip_suffix('}'))
+        && name.starts_with("vars.")
+        && !name.contains(['$', '{', '}'])
+        && let Some(value) = placeholders.config.var(&name["vars.".len()..])
+    {
+        return Ok(value.clone());
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(after) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = after;
+        } else if let Some(after) = tail.strip_prefix("${")
+            && let Some(end) = after.find('}')
+        {
+            let name = &after[..end];
+            match placeholders.lookup(name)? {
+                Some(Value::String(text)) => out.push_str(&text),
+                Some(value) => out.push_str(&value.to_string()),
+                None if placeholders.config.strict => {
+                    return Err(format!("unresolved placeholder '${{{name}}}'"));
+                }
+                None => {
+                    warn!("Unresolved placeholder '${{{name}}}' left in place");
+                    out.push_str(&tail[..end + 3]);
+                }
+            }
+            rest = &after[end + 1..];
+        } else {
+            out.push('$');
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(Value::String(out))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(strict: bool) -> InterpolationConfig {
+        InterpolationConfig {
+            vars: BTreeMap::from([
+                ("base".to_string(), json!("https://api.test")),
+                ("limits".to_string(), json!({"max": 500, "on": true})),
+            ]),
+            strict,
+        }
+    }
+
+    fn run(s: &str, strict: bool) -> std::result::Result<Value, String> {
+        interpolate_str(s, &mut Placeholders::new(&config(strict), None))
+    }
+
+    #[test]
+    fn splices_vars_and_env_into_text() {
+        assert_eq!(
+            run("${vars.base}/orders", true),
+            Ok(json!("https://api.test/orders"))
+        );
+        assert_eq!(run("max=${vars.limits.max}", true), Ok(json!("max=500")));
+        assert_eq!(
+            run("pkg ${CARGO_PKG_NAME}", true),
+            Ok(json!("pkg dataflow-rs"))
+        );
+        assert_eq!(
+            run("$${vars.base} costs $5", true),
+            Ok(json!("${vars.base} costs $5"))
+        );
+    }
+
+    #[test]
+    fn whole_string_vars_keep_their_type() {
+        assert_eq!(run("${vars.limits.max}", true), Ok(json!(500)));
+        assert_eq!(
+            run("${vars.limits}", true),
+            Ok(json!({"max": 500, "on": true}))
+        );
+    }
+
+    #[test]
+    fn unresolved_placeholders_fail_only_when_strict() {
+        assert_eq!(
+            run("a ${vars.missing} b", false),
+            Ok(json!("a ${vars.missing} b"))
+        );
+        assert_eq!(run("${vars.missing}", false), Ok(json!("${vars.missing}")));
+        let err = run("${DATAFLOW_UNSET_VARIABLE_FOR_TESTS}", true).unwrap_err();
+        assert!(err.contains("DATAFLOW_UNSET_VARIABLE_FOR_TESTS"));
+    }
+
+    #[test]
+    fn secrets_come_from_the_resolver_and_are_recorded() {
+        use crate::engine::secrets::EnvSecretsResolver;
+
+        let config = config(false);
+        let resolver = EnvSecretsResolver::with_prefix("CARGO_PKG_");
+        let mut placeholders = Placeholders::new(&config, Some(&resolver));
+        assert_eq!(
+            interpolate_str("Bearer ${secret:NAME}", &mut placeholders),
+            Ok(json!("Bearer dataflow-rs"))
+        );
+        assert_eq!(placeholders.resolved_secrets, vec!["dataflow-rs"]);
+        assert!(interpolate_str("${secret:UNSET_FOR_TESTS}", &mut placeholders).is_err());
+
+        let mut without = Placeholders::new(&config, None);
+        let err = interpolate_str("${secret:NAME}", &mut without).unwrap_err();
+        assert!(err.contains("needs a secrets resolver"), "{err}");
+    }
+}
@@ -0,0 +1,163 @@
+//! # Processing Journal
+//!
+//! Optional durable log of task execution events. When a [`Journal`] is
+//! configured ([`EngineBuilder::with_journal`](crate::EngineBuilder::with_journal)),
+//! the engine records a `Started` entry before each side-effecting task runs
+//! and a `Completed` or `Failed` entry after it returns. After a crash,
+//! replaying the journal tells exactly which side effects finished, which
+//! never began, and which were in flight ([`unfinished`]) — without
+//! persisting whole messages.
+//!
+//! Journaled tasks are the ones that can reach outside the process: the
+//! async built-ins (`http_call`, `enrich`, `publish_kafka`) and custom
+//! handlers. Synchronous built-ins (`map`, `validation`, …) only transform
+//! the message and are not journaled.
+//!
+//! A failed `Started` write fails the task before it runs, so no side effect
+//! happens without a journal record. A failed `Completed`/`Failed` write is
+//! logged and ignored; the task then shows up as unfinished, which is the
+//! safe reading for reconciliation.
+
+use crate::engine::error::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Lifecycle point of a journaled task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalStatus {
+    /// The task is about to run.
+    Started,
+    /// The task returned successfully.
+    Completed,
+    /// The task returned an error or a 5xx status.
+    Failed,
+}
+
+/// One journal event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub message_id: String,
+    pub workflow_id: String,
+    pub task_id: String,
+    pub status: JournalStatus,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Durable destination for journal entries. Implementations should persist
+/// the entry before returning `Ok` (append to a file and fsync, insert into
+/// a table, …).
+#[async_trait]
+pub trait Journal: Send + Sync {
+    async fn record(&self, entry: JournalEntry) -> Result<()>;
+}
+
+/// `Journal` that keeps entries in memory. Not durable; handy for tests and
+/// for inspecting what a run did.
+#[derive(Debug, Default)]
+pub struct InMemoryJournal {
+    entries: Mutex<Vec<JournalEntry>>,
+}
+
+impl InMemoryJournal {
+    /// Create an empty journal.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of every entry recorded so far, in order.
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Journal for InMemoryJournal {
+    async fn record(&self, entry: JournalEntry) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(entry);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Journal + ?Sized> Journal for Arc<T> {
+    async fn record(&self, entry: JournalEntry) -> Result<()> {
+        (**self).record(entry).await
+    }
+}
+
+/// `Started` entries with no later `Completed`/`Failed` entry for the same
+/// message, workflow and task: side effects that may or may not have
+/// happened. Input must be in recording order.
+pub fn unfinished(entries: &[JournalEntry]) -> Vec<JournalEntry> {
+    let mut open: HashMap<(&str, &str, &str), &JournalEntry> = HashMap::new();
+    let mut order = Vec::new();
+    for entry in entries {
+        let key = (
+            entry.message_id.as_str(),
+            entry.workflow_id.as_str(),
+            entry.task_id.as_str(),
+        );
+        match entry.status {
+            JournalStatus::Started => {
+                if open.insert(key, entry).is_none() {
+                    order.push(key);
+                }
+            }
+            JournalStatus::Completed | JournalStatus::Failed => {
+                open.remove(&key);
+            }
+        }
+    }
+    order
+        .into_iter()
+        .filter_map(|key| open.remove(&key).cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(message_id: &str, task_id: &str, status: JournalStatus) -> JournalEntry {
+        JournalEntry {
+            message_id: message_id.to_string(),
+            workflow_id: "wf".to_string(),
+            task_id: task_id.to_string(),
+            status,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn unfinished_reports_started_without_outcome() {
+        let entries = vec![
+            entry("m1", "a", JournalStatus::Started),
+            entry("m1", "a", JournalStatus::Completed),
+            entry("m1", "b", JournalStatus::Started),
+            entry("m2", "a", JournalStatus::Started),
+            entry("m2", "a", JournalStatus::Failed),
+            entry("m2", "a", JournalStatus::Started),
+        ];
+        let open: Vec<_> = unfinished(&entries)
+            .into_iter()
+            .map(|e| (e.message_id, e.task_id))
+            .collect();
+        assert_eq!(
+            open,
+            vec![
+                ("m1".to_string(), "b".to_string()),
+                ("m2".to_string(), "a".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! # Engine Lifecycle
+//!
+//! In-flight tracking behind [`Engine::drain`](crate::Engine::drain). Every
+//! `process_message*` call holds an [`InFlightGuard`] for its duration;
+//! once draining starts, new calls are refused and `drain` resolves when
+//! the last guard is dropped.
+
+use crate::engine::error::{DataflowError, Result};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::sync::Notify;
+
+/// In-flight counter and draining flag shared by one engine.
+#[derive(Debug, Default)]
+pub(crate) struct Lifecycle {
+    in_flight: AtomicUsize,
+    draining: AtomicBool,
+    idle: Notify,
+}
+
+impl Lifecycle {
+    /// Register a message as in flight, or refuse it when draining.
+    pub(crate) fn enter(&self) -> Result<InFlightGuard<'_>> {
+        // Count first, then check: a `drain` that starts between the two
+        // still sees this message and waits for it.
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlightGuard { lifecycle: self };
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(DataflowError::Draining(
+                "engine is draining and no longer accepts messages".to_string(),
+            ));
+        }
+        Ok(guard)
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Refuse new messages and wait until none are in flight.
+    pub(crate) async fn drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        loop {
+            // Register for the wake-up before checking the count so a guard
+            // dropped in between is not missed.
+            let idle = self.idle.notified();
+            if self.in_flight() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+/// Marks one message as in flight until dropped.
+pub(crate) struct InFlightGuard<'a> {
+    lifecycle: &'a Lifecycle,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.lifecycle.in_flight.fetch_sub(1, Ordering::SeqCst) == 1
+            && self.lifecycle.is_draining()
+        {
+            self.lifecycle.idle.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_refuses_new_work_and_waits_for_in_flight() {
+        let lifecycle = Lifecycle::default();
+        let guard = lifecycle.enter().unwrap();
+        assert_eq!(lifecycle.in_flight(), 1);
+
+        let drain = lifecycle.drain();
+        tokio::pin!(drain);
+        assert!(futures::poll!(drain.as_mut()).is_pending());
+        assert!(lifecycle.is_draining());
+        assert!(matches!(lifecycle.enter(), Err(DataflowError::Draining(_))));
+        assert_eq!(lifecycle.in_flight(), 1);
+
+        drop(guard);
+        drain.await;
+        assert_eq!(lifecycle.in_flight(), 0);
+    }
+}
@@ -0,0 +1,210 @@
+//! # Workflow Lints
+//!
+//! Non-fatal findings about workflow definitions, collected while an engine
+//! is built. Unlike compile errors, a lint never stops construction: each
+//! warning is logged and kept on the engine for
+//! [`Engine::lint_warnings`](crate::Engine::lint_warnings), so CI gates can
+//! fail on them while production keeps running.
+
+use crate::engine::functions::FunctionConfig;
+use crate::engine::task::Task;
+use crate::engine::workflow::Workflow;
+use serde::Serialize;
+use serde_json::Value;
+
+/// One lint finding.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintWarning {
+    /// Stable identifier of the check, e.g. `complex_expression`.
+    pub code: String,
+    pub workflow_id: String,
+    /// The task the finding is about; `None` for workflow-level findings.
+    pub task_id: Option<String>,
+    pub message: String,
+}
+
+/// A JSONLogic expression inside a workflow and where it lives.
+pub(crate) struct LogicSite<'a> {
+    pub task_id: Option<&'a str>,
+    /// Human-readable location within the workflow or task, e.g.
+    /// `mapping data.total`.
+    pub location: String,
+    /// JSON path of the expression within the workflow, e.g.
+    /// `tasks[0].function.input.mappings[1].logic`.
+    pub path: String,
+    pub logic: &'a Value,
+}
+
+/// Every JSONLogic expression in `workflow`, in evaluation order.
+pub(crate) fn logic_sites(workflow: &Workflow) -> Vec<LogicSite<'_>> {
+    let mut sites = vec![LogicSite {
+        task_id: None,
+        location: "condition".to_string(),
+        path: "condition".to_string(),
+        logic: &workflow.condition,
+    }];
+    if let Some(key) = workflow.variant.as_ref().and_then(|v| v.key.as_ref()) {
+        sites.push(LogicSite {
+            task_id: None,
+            location: "variant key".to_string(),
+            path: "variant.key".to_string(),
+            logic: key,
+        });
+    }
+    if let Some(key) = &workflow.idempotency_key {
+        sites.push(LogicSite {
+            task_id: None,
+            location: "idempotency key".to_string(),
+            path: "idempotency_key".to_string(),
+            logic: key,
+        });
+    }
+    for (idx, task) in workflow.tasks.iter().enumerate() {
+        sites_at(&task.id, "", &format!("tasks[{idx}]."), task, &mut sites);
+    }
+    sites
+}
+
+/// Sites of `task`, attributed to `task_id`; `prefix` locates tasks nested
+/// in a `foreach` body.
+pub(crate) fn task_sites<'a>(
+    task_id: &'a str,
+    prefix: &str,
+    task: &'a Task,
+    sites: &mut Vec<LogicSite<'a>>,
+) {
+    sites_at(task_id, prefix, "", task, sites);
+}
+
+/// [`task_sites`] with paths starting with `path`, the task's own JSON
+/// path plus a trailing dot.
+fn sites_at<'a>(
+    task_id: &'a str,
+    prefix: &str,
+    path: &str,
+    task: &'a Task,
+    sites: &mut Vec<LogicSite<'a>>,
+) {
+    let mut push = |location: String, field: String, logic| {
+        sites.push(LogicSite {
+            task_id: Some(task_id),
+            location: format!("{prefix}{location}"),
+            path: format!("{path}{field}"),
+            logic,
+        })
+    };
+    let at = |field: &str| format!("function.input.{field}");
+    push(
+        "condition".to_string(),
+        "condition".to_string(),
+        &task.condition,
+    );
+    match &task.function {
+        FunctionConfig::Map { input, .. } => {
+            for (idx, mapping) in input.mappings.iter().enumerate() {
+                push(
+                    format!("mapping {}", mapping.path),
+                    at(&format!("mappings[{idx}].logic")),
+                    &mapping.logic,
+                );
+            }
+        }
+        FunctionConfig::Validation { input, .. } => {
+            for (idx, rule) in input.rules.iter().enumerate() {
+                push(
+                    format!("rule {idx}"),
+                    at(&format!("rules[{idx}].logic")),
+                    &rule.logic,
+                );
+            }
+        }
+        FunctionConfig::Filter { input, .. } => {
+            push(
+                "filter condition".to_string(),
+                at("condition"),
+                &input.condition,
+            );
+        }
+        FunctionConfig::Reject { input, .. } => {
+            push("reject code".to_string(), at("code"), &input.code);
+            push("reject reason".to_string(), at("reason"), &input.reason);
+        }
+        FunctionConfig::Score { input, .. } => {
+            for (idx, rule) in input.rules.iter().enumerate() {
+                push(
+                    format!("score rule {}", rule.id),
+                    at(&format!("rules[{idx}].logic")),
+                    &rule.logic,
+                );
+            }
+        }
+        FunctionConfig::Join { input, .. } => {
+            push("join key".to_string(), at("key"), &input.key);
+        }
+        FunctionConfig::StateGet { input, .. } => {
+            push("state_get key".to_string(), at("key"), &input.key);
+        }
+        FunctionConfig::StateSet { input, .. } => {
+            push("state_set key".to_string(), at("key"), &input.key);
+            push("state_set value".to_string(), at("value"), &input.value);
+        }
+        FunctionConfig::Switch { input, .. } => {
+            for (idx, case) in input.cases.iter().enumerate() {
+                push(
+                    format!("switch case {idx}"),
+                    at(&format!("cases[{idx}].when")),
+                    &case.when,
+                );
+            }
+        }
+        FunctionConfig::Alert { input, .. } => {
+            push("alert message".to_string(), at("message"), &input.message);
+            for (key, logic) in &input.fields {
+                push(
+                    format!("alert field '{key}'"),
+                    at(&format!("fields.{key}")),
+                    logic,
+                );
+            }
+        }
+        FunctionConfig::Log { input, .. } => {
+            push("log message".to_string(), at("message"), &input.message);
+            for (key, logic) in &input.fields {
+                push(
+                    format!("log field '{key}'"),
+                    at(&format!("fields.{key}")),
+                    logic,
+                );
+            }
+        }
+        FunctionConfig::HttpCall { input, .. } => {
+            if let Some(logic) = &input.path_logic {
+                push("path_logic".to_string(), at("path_logic"), logic);
+            }
+            if let Some(logic) = &input.body_logic {
+                push("body_logic".to_string(), at("body_logic"), logic);
+            }
+        }
+        FunctionConfig::Enrich { input, .. } => {
+            if let Some(logic) = &input.path_logic {
+                push("path_logic".to_string(), at("path_logic"), logic);
+            }
+        }
+        FunctionConfig::PublishKafka { input, .. } => {
+            if let Some(logic) = &input.key_logic {
+                push("key_logic".to_string(), at("key_logic"), logic);
+            }
+            if let Some(logic) = &input.value_logic {
+                push("value_logic".to_string(), at("value_logic"), logic);
+            }
+        }
+        _ => {}
+    }
+    if let FunctionConfig::Foreach { input, .. } = &task.function {
+        for (idx, nested) in input.tasks.iter().enumerate() {
+            let prefix = format!("{prefix}foreach task {}: ", nested.id);
+            let path = format!("{path}{}", at(&format!("tasks[{idx}].")));
+            sites_at(task_id, &prefix, &path, nested, sites);
+        }
+    }
+}
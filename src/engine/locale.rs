@@ -0,0 +1,423 @@
+//! # Locales
+//!
+//! A workflow may declare the locale and time zone its outputs are meant
+//! for, so one engine can produce correctly formatted values for several
+//! regions:
+//!
+//! ```json
+//! {"id": "invoice_de", "name": "German invoices",
+//!  "locale": {"tag": "de-DE", "timezone": "+01:00"},
+//!  "tasks": [...]}
+//! ```
+//!
+//! While the workflow runs, its locale is exposed at `metadata.locale`
+//! (`{"tag": "de-DE", "timezone": "+01:00"}`), where conditions and logic
+//! can read it, and the formatting operators use it:
+//!
+//! ```json
+//! {"format_number": [{"var": "data.total"}, 2]}
+//! {"format_date": [{"var": "data.issued_at"}]}
+//! ```
+//!
+//! - `format_number: [value, decimals?, tag?]` writes a number with the
+//!   locale's grouping and decimal separators, e.g. `1.234,50` for
+//!   `de-DE`. Without `decimals` the number is written as short as it can
+//!   be.
+//! - `format_date: [value, pattern?, timezone?]` writes an RFC 3339 string
+//!   or epoch milliseconds in the locale's time zone, with a
+//!   [`chrono` strftime](https://docs.rs/chrono/latest/chrono/format/strftime/)
+//!   `pattern` or the locale's short date pattern, e.g. `31.01.2025` for
+//!   `de-DE`.
+//!
+//! Explicit arguments override the workflow's locale; without either,
+//! `en-US` and `UTC` apply. Time zones are `UTC` or fixed offsets such as
+//! `+05:30`: there is no time zone database, so a region observing
+//! daylight saving time needs its current offset. Month and weekday names
+//! (`%B`, `%A`) are English. Separators and date patterns follow CLDR for
+//! the common languages; other tags format like `en`.
+//!
+//! The operators are always registered. A custom operator of the same
+//! name replaces them.
+
+use crate::engine::error::{DataflowError, Result};
+use chrono::{DateTime, FixedOffset, Utc};
+use datalogic_rs::bumpalo::Bump;
+use datalogic_rs::operator::EvalContext;
+use datalogic_rs::{CustomOperator, DataValue, Error as LogicError};
+use datavalue::OwnedDataValue;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Name of the number formatting operator.
+pub const FORMAT_NUMBER_OPERATOR: &str = "format_number";
+/// Name of the date formatting operator.
+pub const FORMAT_DATE_OPERATOR: &str = "format_date";
+
+const DEFAULT_TAG: &str = "en-US";
+const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// The locale and time zone a workflow formats values for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocaleContext {
+    /// BCP 47 language tag, e.g. `de-DE`. Default: `en-US`.
+    #[serde(default = "default_tag")]
+    pub tag: String,
+    /// `UTC` or a fixed UTC offset such as `+01:00`. Default: `UTC`.
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_tag() -> String {
+    DEFAULT_TAG.to_string()
+}
+
+fn default_timezone() -> String {
+    DEFAULT_TIMEZONE.to_string()
+}
+
+impl Default for LocaleContext {
+    fn default() -> Self {
+        Self {
+            tag: default_tag(),
+            timezone: default_timezone(),
+        }
+    }
+}
+
+impl LocaleContext {
+    /// Fail when the tag is malformed or the time zone isn't one
+    /// `format_date` understands.
+    pub(crate) fn validate(&self, workflow_id: &str) -> Result<()> {
+        let well_formed = self
+            .tag
+            .split(['-', '_'])
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()));
+        if !well_formed {
+            return Err(DataflowError::Workflow(format!(
+                "Workflow {workflow_id}: invalid locale tag '{}'",
+                self.tag
+            )));
+        }
+        if parse_timezone(&self.timezone).is_none() {
+            return Err(DataflowError::Workflow(format!(
+                "Workflow {workflow_id}: unknown time zone '{}', expected UTC or an offset such as +01:00",
+                self.timezone
+            )));
+        }
+        Ok(())
+    }
+
+    /// The `metadata.locale` object.
+    pub(crate) fn to_metadata(&self) -> OwnedDataValue {
+        OwnedDataValue::Object(vec![
+            ("tag".to_string(), OwnedDataValue::String(self.tag.clone())),
+            (
+                "timezone".to_string(),
+                OwnedDataValue::String(self.timezone.clone()),
+            ),
+        ])
+    }
+}
+
+/// `UTC`, `Z`, `GMT`, or an offset `+HH:MM`, `+HHMM` or `+HH`.
+fn parse_timezone(timezone: &str) -> Option<FixedOffset> {
+    if matches!(timezone, "UTC" | "Z" | "GMT") {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, rest) = match timezone.as_bytes().first()? {
+        b'+' => (1, &timezone[1..]),
+        b'-' => (-1, &timezone[1..]),
+        _ => return None,
+    };
+    let digits: String = rest.chars().filter(|&c| c != ':').collect();
+    if digits.len() != rest.len() - usize::from(rest.contains(':'))
+        || !matches!(digits.len(), 2 | 4)
+        || !digits.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = match digits.len() {
+        4 => digits[2..].parse().ok()?,
+        _ => 0,
+    };
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Language and region subtags of `tag`, e.g. `("de", "CH")`.
+fn subtags(tag: &str) -> (String, String) {
+    let mut parts = tag.split(['-', '_']);
+    let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let region = parts
+        .find(|part| part.len() == 2 || part.chars().all(|c| c.is_ascii_digit()))
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+    (language, region)
+}
+
+/// Grouping and decimal separators of `tag`.
+fn separators(tag: &str) -> (&'static str, char) {
+    match subtags(tag) {
+        (language, region) if region == "CH" && matches!(language.as_str(), "de" | "fr" | "it") => {
+            ("\u{2019}", '.')
+        }
+        (language, region) if language == "es" && region == "MX" => (",", '.'),
+        (language, _) => match language.as_str() {
+            "de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el" => (".", ','),
+            "fr" => ("\u{202f}", ','),
+            "sv" | "nb" | "no" | "fi" | "pl" | "cs" | "sk" | "ru" | "uk" | "hu" | "bg" => {
+                ("\u{a0}", ',')
+            }
+            _ => (",", '.'),
+        },
+    }
+}
+
+/// Short date pattern of `tag`.
+fn date_pattern(tag: &str) -> &'static str {
+    let (language, region) = subtags(tag);
+    match language.as_str() {
+        "en" => match region.as_str() {
+            "US" | "" => "%m/%d/%Y",
+            "CA" => "%Y-%m-%d",
+            _ => "%d/%m/%Y",
+        },
+        "fr" | "es" | "it" | "pt" | "el" => "%d/%m/%Y",
+        "de" | "ru" | "pl" | "cs" | "fi" | "nb" | "no" | "tr" | "da" | "uk" => "%d.%m.%Y",
+        "nl" => "%d-%m-%Y",
+        "ja" | "zh" | "ko" => "%Y/%m/%d",
+        _ => "%Y-%m-%d",
+    }
+}
+
+/// Write `value` with the separators of `tag`, rounded half away from
+/// zero to `decimals` places when given.
+pub(crate) fn format_number(value: f64, decimals: Option<usize>, tag: &str) -> String {
+    let digits = match decimals {
+        Some(decimals) => {
+            let scale = 10f64.powi(decimals as i32);
+            format!("{:.*}", decimals, (value.abs() * scale).round() / scale)
+        }
+        None => value.abs().to_string(),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((&digits, ""));
+    let (group, decimal) = separators(tag);
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3 * group.len() + 1);
+    if value.is_sign_negative() && digits.chars().any(|c| c.is_ascii_digit() && c != '0') {
+        out.push('-');
+    }
+    for (idx, c) in integer.chars().enumerate() {
+        if idx > 0 && (integer.len() - idx) % 3 == 0 {
+            out.push_str(group);
+        }
+        out.push(c);
+    }
+    if !fraction.is_empty() {
+        out.push(decimal);
+        out.push_str(fraction);
+    }
+    out
+}
+
+/// Write `at` in `timezone` with `pattern`.
+pub(crate) fn format_date(
+    at: DateTime<Utc>,
+    pattern: &str,
+    timezone: &str,
+) -> std::result::Result<String, String> {
+    let offset =
+        parse_timezone(timezone).ok_or_else(|| format!("unknown time zone '{timezone}'"))?;
+    let mut out = String::new();
+    write!(out, "{}", at.with_timezone(&offset).format(pattern))
+        .map_err(|_| format!("invalid date pattern '{pattern}'"))?;
+    Ok(out)
+}
+
+/// `metadata.locale.<field>` of the evaluation's root input.
+fn context_field<'a>(ctx: &EvalContext<'_, 'a>, field: &str) -> Option<&'a str> {
+    let root: &'a DataValue<'a> = ctx.root_input();
+    root.get("metadata")?.get("locale")?.get(field)?.as_str()
+}
+
+/// String argument `idx`, or `None` when it is absent or `null`.
+fn optional_str<'a>(
+    args: &[&'a DataValue<'a>],
+    idx: usize,
+    what: &str,
+) -> datalogic_rs::Result<Option<&'a str>> {
+    match args.get(idx) {
+        None => Ok(None),
+        Some(value) if value.is_null() => Ok(None),
+        Some(value) => value
+            .as_str()
+            .map(Some)
+            .ok_or_else(|| LogicError::invalid_arguments(format!("{what} must be a string"))),
+    }
+}
+
+/// The `format_number` operator.
+pub(crate) struct FormatNumberOperator;
+
+impl CustomOperator for FormatNumberOperator {
+    fn evaluate<'a>(
+        &self,
+        args: &[&'a DataValue<'a>],
+        ctx: &mut EvalContext<'_, 'a>,
+        arena: &'a Bump,
+    ) -> datalogic_rs::Result<&'a DataValue<'a>> {
+        let Some(value) = args.first() else {
+            return Err(LogicError::invalid_arguments(
+                "format_number expects [value, decimals?, tag?]",
+            ));
+        };
+        let number = match value.as_f64() {
+            Some(number) => number,
+            None if value.is_null() => return Ok(&DataValue::Null),
+            None => value
+                .as_str()
+                .and_then(|s| s.trim().parse::<f64>().ok())
+                .ok_or_else(|| {
+                    LogicError::invalid_arguments("format_number value must be a number")
+                })?,
+        };
+        let decimals = match args.get(1) {
+            Some(decimals) if !decimals.is_null() => Some(
+                decimals
+                    .as_i64()
+                    .and_then(|d| usize::try_from(d).ok())
+                    .filter(|d| *d <= 20)
+                    .ok_or_else(|| {
+                        LogicError::invalid_arguments(
+                            "format_number decimals must be an integer from 0 to 20",
+                        )
+                    })?,
+            ),
+            _ => None,
+        };
+        let tag = optional_str(args, 2, "format_number tag")?
+            .or_else(|| context_field(ctx, "tag"))
+            .unwrap_or(DEFAULT_TAG);
+        let formatted = format_number(number, decimals, tag);
+        Ok(arena.alloc(DataValue::from_str_in(&formatted, arena)))
+    }
+}
+
+/// The `format_date` operator.
+pub(crate) struct FormatDateOperator;
+
+impl CustomOperator for FormatDateOperator {
+    fn evaluate<'a>(
+        &self,
+        args: &[&'a DataValue<'a>],
+        ctx: &mut EvalContext<'_, 'a>,
+        arena: &'a Bump,
+    ) -> datalogic_rs::Result<&'a DataValue<'a>> {
+        let Some(value) = args.first() else {
+            return Err(LogicError::invalid_arguments(
+                "format_date expects [value, pattern?, timezone?]",
+            ));
+        };
+        let at = if value.is_null() {
+            return Ok(&DataValue::Null);
+        } else if let Some(millis) = value.as_i64() {
+            DateTime::<Utc>::from_timestamp_millis(millis)
+        } else {
+            value
+                .as_str()
+                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                .map(|at| at.with_timezone(&Utc))
+        }
+        .ok_or_else(|| {
+            LogicError::invalid_arguments(
+                "format_date value must be an RFC 3339 string or epoch milliseconds",
+            )
+        })?;
+        let pattern = match optional_str(args, 1, "format_date pattern")? {
+            Some(pattern) => pattern,
+            None => date_pattern(context_field(ctx, "tag").unwrap_or(DEFAULT_TAG)),
+        };
+        let timezone = optional_str(args, 2, "format_date timezone")?
+            .or_else(|| context_field(ctx, "timezone"))
+            .unwrap_or(DEFAULT_TIMEZONE);
+        let formatted =
+            format_date(at, pattern, timezone).map_err(LogicError::invalid_arguments)?;
+        Ok(arena.alloc(DataValue::from_str_in(&formatted, arena)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datalogic_rs::Engine as DatalogicEngine;
+
+    #[test]
+    fn numbers_use_the_locale_separators() {
+        assert_eq!(format_number(1234567.891, Some(2), "en-US"), "1,234,567.89");
+        assert_eq!(format_number(1234.5, Some(2), "de-DE"), "1.234,50");
+        assert_eq!(format_number(-1234.5, None, "fr-FR"), "-1\u{202f}234,5");
+        assert_eq!(format_number(1234.5, Some(0), "de-CH"), "1\u{2019}235");
+        assert_eq!(format_number(999.0, None, "sv-SE"), "999");
+        assert_eq!(format_number(-0.001, Some(2), "en"), "0.00");
+    }
+
+    #[test]
+    fn timezones_are_utc_or_fixed_offsets() {
+        assert_eq!(parse_timezone("UTC"), FixedOffset::east_opt(0));
+        assert_eq!(parse_timezone("+05:30"), FixedOffset::east_opt(19_800));
+        assert_eq!(parse_timezone("-0800"), FixedOffset::east_opt(-28_800));
+        assert_eq!(parse_timezone("+01"), FixedOffset::east_opt(3_600));
+        for bad in ["Europe/Berlin", "+1", "+01:5", "+25:00", "01:00", "+01:00x"] {
+            assert_eq!(parse_timezone(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn operators_read_the_workflow_locale() {
+        let engine = DatalogicEngine::builder()
+            .add_operator(FORMAT_NUMBER_OPERATOR, FormatNumberOperator)
+            .add_operator(FORMAT_DATE_OPERATOR, FormatDateOperator)
+            .build();
+        let german = r#"{"metadata": {"locale": {"tag": "de-DE", "timezone": "+01:00"}},
+                         "at": "2025-01-31T23:30:00Z", "n": 1234.5}"#;
+        let eval = |logic: &str, data: &str| engine.eval_str(logic, data).unwrap();
+
+        assert_eq!(
+            eval(r#"{"format_number": [{"var": "n"}, 2]}"#, german),
+            r#""1.234,50""#
+        );
+        assert_eq!(
+            eval(r#"{"format_date": [{"var": "at"}]}"#, german),
+            r#""01.02.2025""#
+        );
+        assert_eq!(
+            eval(
+                r#"{"format_date": [{"var": "at"}, "%H:%M", "-05:00"]}"#,
+                german
+            ),
+            r#""18:30""#
+        );
+        assert_eq!(
+            eval(
+                r#"{"format_number": [{"var": "n"}, null, "en-US"]}"#,
+                german
+            ),
+            r#""1,234.5""#
+        );
+        assert_eq!(eval(r#"{"format_date": [0]}"#, "{}"), r#""01/01/1970""#);
+        assert_eq!(eval(r#"{"format_number": [null]}"#, "{}"), "null");
+        assert!(
+            engine
+                .eval_str(r#"{"format_date": ["soon"]}"#, "{}")
+                .is_err()
+        );
+        assert!(
+            engine
+                .eval_str(r#"{"format_date": [0, "%Y", "Mars/Olympus"]}"#, "{}")
+                .is_err()
+        );
+    }
+}
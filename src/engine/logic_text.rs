@@ -0,0 +1,407 @@
+//! # Logic Text
+//!
+//! Textual views of JSONLogic expressions for documentation and review:
+//! [`render_logic`] prints an expression in infix form
+//! (`data.amount > 1000 and metadata.type == "pacs.008"`),
+//! [`render_logic_pretty`] breaks long expressions over indented lines, and
+//! [`var_paths`] lists the context paths an expression reads.
+
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Binding strength of rendered operators; higher binds tighter.
+const PREC_OR: u8 = 1;
+const PREC_AND: u8 = 2;
+const PREC_NOT: u8 = 3;
+const PREC_COMPARE: u8 = 4;
+const PREC_SUM: u8 = 5;
+const PREC_PRODUCT: u8 = 6;
+const PREC_ATOM: u8 = 9;
+
+/// Line width above which [`render_logic_pretty`] breaks an expression.
+const PRETTY_WIDTH: usize = 72;
+const INDENT: usize = 4;
+
+/// Render a JSONLogic expression as a single line of infix text.
+///
+/// Comparisons, arithmetic, `and`/`or`/`!` and `if` are written as
+/// operators; `{"var": "data.x"}` becomes `data.x` (with `?? default` when a
+/// default is given). Any other operator is written as a call,
+/// `op(arg, …)`, so every expression renders.
+pub fn render_logic(logic: &Value) -> String {
+    render(logic).0
+}
+
+/// Render a JSONLogic expression as infix text, breaking `and`/`or` chains
+/// and `if` branches onto indented lines when the one-line form of
+/// [`render_logic`] would exceed 72 characters:
+///
+/// ```text
+/// data.amount > 1000
+/// and (
+///     metadata.type == "pacs.008"
+///     or metadata.type == "pacs.009"
+/// )
+/// ```
+///
+/// Short expressions come out exactly as [`render_logic`] renders them.
+pub fn render_logic_pretty(logic: &Value) -> String {
+    pretty(logic, 0)
+}
+
+/// [`render_logic_pretty`] for a workflow or task condition; `None` for the
+/// default `true` (always runs).
+pub(crate) fn render_condition(condition: &Value) -> Option<String> {
+    (*condition != Value::Bool(true)).then(|| render_logic_pretty(condition))
+}
+
+/// Context paths read by `var` and `missing`/`missing_some` in `logic`,
+/// added to `out`. Paths inside the per-element logic of `map`, `filter`,
+/// `reduce`, `all`, `some` and `none` are relative to the element and are
+/// not collected.
+pub(crate) fn var_paths(logic: &Value, out: &mut BTreeSet<String>) {
+    match logic {
+        Value::Array(items) => items.iter().for_each(|item| var_paths(item, out)),
+        Value::Object(map) if map.len() == 1 => {
+            let (op, args) = map.iter().next().expect("one entry");
+            match (op.as_str(), args) {
+                ("var", Value::String(path)) => insert_path(path, out),
+                ("var", Value::Array(items)) => {
+                    if let Some(Value::String(path)) = items.first() {
+                        insert_path(path, out);
+                    }
+                    items.iter().skip(1).for_each(|item| var_paths(item, out));
+                }
+                ("missing", args) => string_args(args, out),
+                ("missing_some", Value::Array(items)) => {
+                    items.iter().skip(1).for_each(|item| string_args(item, out));
+                }
+                ("map" | "filter" | "reduce" | "all" | "some" | "none", Value::Array(items)) => {
+                    if let Some(first) = items.first() {
+                        var_paths(first, out);
+                    }
+                    items.iter().skip(2).for_each(|item| var_paths(item, out));
+                }
+                (_, args) => var_paths(args, out),
+            }
+        }
+        Value::Object(map) => map.values().for_each(|value| var_paths(value, out)),
+        _ => {}
+    }
+}
+
+fn insert_path(path: &str, out: &mut BTreeSet<String>) {
+    if !path.is_empty() {
+        out.insert(path.to_string());
+    }
+}
+
+fn string_args(args: &Value, out: &mut BTreeSet<String>) {
+    match args {
+        Value::String(path) => insert_path(path, out),
+        Value::Array(items) => items.iter().for_each(|item| string_args(item, out)),
+        other => var_paths(other, out),
+    }
+}
+
+/// Rendered text and its precedence.
+fn render(logic: &Value) -> (String, u8) {
+    let Value::Object(map) = logic else {
+        return match logic {
+            Value::Array(items) => (format!("[{}]", render_list(items)), PREC_ATOM),
+            other => (other.to_string(), PREC_ATOM),
+        };
+    };
+    if map.len() != 1 {
+        return (logic.to_string(), PREC_ATOM);
+    }
+    let (op, args) = map.iter().next().expect("one entry");
+    let args: Vec<&Value> = match args {
+        Value::Array(items) => items.iter().collect(),
+        single => vec![single],
+    };
+
+    match (op.as_str(), args.as_slice()) {
+        ("var", [Value::String(path), rest @ ..]) => {
+            let path = if path.is_empty() { "$" } else { path.as_str() };
+            match rest.first() {
+                Some(default) => (
+                    format!("{path} ?? {}", operand(default, PREC_COMPARE + 1)),
+                    PREC_COMPARE,
+                ),
+                None => (path.to_string(), PREC_ATOM),
+            }
+        }
+        ("var", []) => ("$".to_string(), PREC_ATOM),
+        ("and" | "or", [_, _, ..]) => {
+            let (word, prec) = if op == "and" {
+                ("and", PREC_AND)
+            } else {
+                ("or", PREC_OR)
+            };
+            let parts: Vec<String> = args.iter().map(|arg| operand(arg, prec + 1)).collect();
+            (parts.join(&format!(" {word} ")), prec)
+        }
+        ("!", [arg]) => (format!("not {}", operand(arg, PREC_NOT)), PREC_NOT),
+        ("!!", [arg]) => (format!("bool({})", render(arg).0), PREC_ATOM),
+        ("-", [arg]) => (format!("-{}", operand(arg, PREC_ATOM)), PREC_ATOM),
+        ("==" | "===" | "!=" | "!==" | ">" | ">=" | "<" | "<=", [_, _, ..]) => {
+            // `{"<": [a, b, c]}` is JSONLogic's between: `a < b < c`.
+            let parts: Vec<String> = args
+                .iter()
+                .map(|arg| operand(arg, PREC_COMPARE + 1))
+                .collect();
+            (parts.join(&format!(" {op} ")), PREC_COMPARE)
+        }
+        ("in", [needle, haystack]) => (
+            format!(
+                "{} in {}",
+                operand(needle, PREC_COMPARE + 1),
+                operand(haystack, PREC_COMPARE + 1)
+            ),
+            PREC_COMPARE,
+        ),
+        ("+" | "-" | "*" | "/" | "%", [_, _, ..]) => {
+            let prec = if matches!(op.as_str(), "+" | "-") {
+                PREC_SUM
+            } else {
+                PREC_PRODUCT
+            };
+            // Left-associative: later operands need parentheses at equal
+            // precedence (`a - (b - c)`).
+            let parts: Vec<String> = args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| operand(arg, if i == 0 { prec } else { prec + 1 }))
+                .collect();
+            (parts.join(&format!(" {op} ")), prec)
+        }
+        ("if" | "?:", [_, _, ..]) => {
+            let mut text = String::new();
+            let mut rest = args.as_slice();
+            while let [condition, then, tail @ ..] = rest {
+                let keyword = if text.is_empty() { "if" } else { " elif" };
+                text.push_str(&format!(
+                    "{keyword} {} then {}",
+                    render(condition).0,
+                    render(then).0
+                ));
+                rest = tail;
+            }
+            if let [otherwise] = rest {
+                text.push_str(&format!(" else {}", render(otherwise).0));
+            }
+            (text, PREC_OR - 1)
+        }
+        _ => (format!("{op}({})", render_list(args)), PREC_ATOM),
+    }
+}
+
+/// Multi-line rendering of `logic` whose continuation lines start at
+/// column `indent`.
+fn pretty(logic: &Value, indent: usize) -> String {
+    let flat = render_logic(logic);
+    if indent + flat.len() <= PRETTY_WIDTH {
+        return flat;
+    }
+    let Value::Object(map) = logic else {
+        return flat;
+    };
+    let Some((op, Value::Array(args))) = map.iter().next().filter(|_| map.len() == 1) else {
+        return flat;
+    };
+    let pad = " ".repeat(indent);
+
+    match (op.as_str(), args.as_slice()) {
+        ("and" | "or", [_, _, ..]) => {
+            let prec = if op == "and" { PREC_AND } else { PREC_OR };
+            let mut text = String::new();
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    text.push_str(&format!("\n{pad}{op} "));
+                }
+                text.push_str(&pretty_operand(arg, prec + 1, indent));
+            }
+            text
+        }
+        ("if" | "?:", [_, _, ..]) => {
+            let inner = indent + INDENT;
+            let mut text = String::new();
+            let mut rest = args.as_slice();
+            while let [condition, then, tail @ ..] = rest {
+                let keyword = if text.is_empty() { "if" } else { "elif" };
+                if !text.is_empty() {
+                    text.push_str(&format!("\n{pad}"));
+                }
+                text.push_str(&format!(
+                    "{keyword} {}\n{pad}then {}",
+                    pretty(condition, inner),
+                    pretty(then, inner)
+                ));
+                rest = tail;
+            }
+            if let [otherwise] = rest {
+                text.push_str(&format!("\n{pad}else {}", pretty(otherwise, inner)));
+            }
+            text
+        }
+        _ => flat,
+    }
+}
+
+/// [`pretty`] for an operand of a broken chain: parenthesized when its
+/// precedence is below `min_prec`, with the body on its own indented lines
+/// when it does not fit on one.
+fn pretty_operand(logic: &Value, min_prec: u8, indent: usize) -> String {
+    let (flat, prec) = render(logic);
+    if prec >= min_prec {
+        return pretty(logic, indent + INDENT);
+    }
+    if indent + flat.len() + 2 <= PRETTY_WIDTH {
+        return format!("({flat})");
+    }
+    let inner = indent + INDENT;
+    format!(
+        "(\n{}{}\n{})",
+        " ".repeat(inner),
+        pretty(logic, inner),
+        " ".repeat(indent)
+    )
+}
+
+/// Render `logic` as an operand that needs at least `min_prec`,
+/// parenthesizing it otherwise.
+fn operand(logic: &Value, min_prec: u8) -> String {
+    let (text, prec) = render(logic);
+    if prec < min_prec {
+        format!("({text})")
+    } else {
+        text
+    }
+}
+
+fn render_list<'a>(items: impl IntoIterator<Item = &'a Value>) -> String {
+    items
+        .into_iter()
+        .map(|item| render(item).0)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn renders_operators_infix_with_minimal_parentheses() {
+        let logic = json!({"and": [
+            {">": [{"var": "data.amount"}, 1000]},
+            {"or": [
+                {"==": [{"var": "metadata.type"}, "pacs.008"]},
+                {"!": {"var": "data.flagged"}}
+            ]}
+        ]});
+        assert_eq!(
+            render_logic(&logic),
+            r#"data.amount > 1000 and (metadata.type == "pacs.008" or not data.flagged)"#
+        );
+        assert_eq!(
+            render_logic(&json!({"-": [{"var": "a"}, {"-": [{"var": "b"}, 1]}]})),
+            "a - (b - 1)"
+        );
+        assert_eq!(
+            render_logic(&json!({"*": [{"+": [1, 2]}, {"var": ["x", 0]}]})),
+            "(1 + 2) * (x ?? 0)"
+        );
+        assert_eq!(
+            render_logic(&json!({"<=": [0, {"var": "n"}, 10]})),
+            "0 <= n <= 10"
+        );
+    }
+
+    #[test]
+    fn renders_if_chains_and_unknown_operators_as_calls() {
+        let logic = json!({"if": [
+            {">": [{"var": "n"}, 10]}, "big",
+            {">": [{"var": "n"}, 5]}, "medium",
+            "small"
+        ]});
+        assert_eq!(
+            render_logic(&logic),
+            r#"if n > 10 then "big" elif n > 5 then "medium" else "small""#
+        );
+        assert_eq!(
+            render_logic(&json!({"cat": ["id-", {"var": "data.id"}]})),
+            r#"cat("id-", data.id)"#
+        );
+        assert_eq!(render_logic(&json!(true)), "true");
+    }
+
+    #[test]
+    fn pretty_breaks_long_chains_over_indented_lines() {
+        let short = json!({">": [{"var": "data.amount"}, 1000]});
+        assert_eq!(render_logic_pretty(&short), render_logic(&short));
+
+        let logic = json!({"and": [
+            {">": [{"var": "data.payment.amount"}, 1000]},
+            {"or": [
+                {"==": [{"var": "metadata.message_type"}, "pacs.008"]},
+                {"==": [{"var": "metadata.message_type"}, "pacs.009"]}
+            ]},
+            {"!": {"var": "data.payment.flagged"}}
+        ]});
+        assert_eq!(
+            render_logic_pretty(&logic),
+            [
+                "data.payment.amount > 1000",
+                "and (",
+                r#"    metadata.message_type == "pacs.008""#,
+                r#"    or metadata.message_type == "pacs.009""#,
+                ")",
+                "and not data.payment.flagged",
+            ]
+            .join("\n")
+        );
+
+        let branches = json!({"if": [
+            {"==": [{"var": "data.customer.segment"}, "enterprise"]}, "priority-support-queue",
+            {"==": [{"var": "data.customer.segment"}, "smb"]}, "standard-queue",
+            "self-service"
+        ]});
+        assert_eq!(
+            render_logic_pretty(&branches),
+            [
+                r#"if data.customer.segment == "enterprise""#,
+                r#"then "priority-support-queue""#,
+                r#"elif data.customer.segment == "smb""#,
+                r#"then "standard-queue""#,
+                r#"else "self-service""#,
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn collects_context_paths_but_not_element_relative_ones() {
+        let logic = json!({"and": [
+            {"var": "data.a"},
+            {"var": ["data.b", {"var": "data.fallback"}]},
+            {"missing": ["data.c", "data.d"]},
+            {"all": [{"var": "data.items"}, {">": [{"var": "qty"}, 0]}]}
+        ]});
+        let mut paths = BTreeSet::new();
+        var_paths(&logic, &mut paths);
+        assert_eq!(
+            paths.into_iter().collect::<Vec<_>>(),
+            [
+                "data.a",
+                "data.b",
+                "data.c",
+                "data.d",
+                "data.fallback",
+                "data.items"
+            ]
+        );
+    }
+}
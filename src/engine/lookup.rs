@@ -0,0 +1,237 @@
+//! # Lookup Tables
+//!
+//! Named reference data (country codes, fee schedules, feature toggles)
+//! registered on the engine with
+//! [`EngineBuilder::with_lookup_table`](crate::EngineBuilder::with_lookup_table)
+//! and read from any condition or mapping with the `lookup` operator:
+//!
+//! ```json
+//! {"lookup": ["countries", {"var": "data.country"}]}
+//! {"lookup": ["fees", {"var": "data.tier"}, 0]}
+//! ```
+//!
+//! The key indexes an object table by name (numbers are converted to their
+//! text) or an array table by position. A missing key returns the optional
+//! third argument, or `null`. An unknown table name is an evaluation error.
+//!
+//! Only the matched entry is copied into the evaluation, so large tables
+//! cost nothing per lookup beyond the key search. A table can hold any JSON,
+//! including templates that mappings pick from.
+//!
+//! ## Hot Reload
+//!
+//! [`Engine::update_lookup_table`](crate::Engine::update_lookup_table) and
+//! [`Engine::remove_lookup_table`](crate::Engine::remove_lookup_table)
+//! publish a new *generation* of the table set without rebuilding the
+//! engine. Each message pins the generation current when its processing
+//! starts and sees that generation until it finishes, so a refresh never
+//! gives one message a mix of old and new tables. Messages that start
+//! after the update see the new generation. Engines derived with
+//! `with_new_workflows` share the tables.
+//!
+//! The `lookup` operator is always registered. A custom operator of the
+//! same name replaces it.
+
+use datalogic_rs::bumpalo::Bump;
+use datalogic_rs::operator::EvalContext;
+use datalogic_rs::{CustomOperator, DataValue, Error as LogicError};
+use datavalue::OwnedDataValue;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+
+/// Name of the JSONLogic operator that reads lookup tables.
+pub const LOOKUP_OPERATOR: &str = "lookup";
+
+/// One immutable version of the table set.
+#[derive(Debug, Default)]
+pub(crate) struct LookupGeneration {
+    pub(crate) number: u64,
+    tables: HashMap<String, Arc<OwnedDataValue>>,
+}
+
+/// The current table generation, swapped atomically on update. Shared by the
+/// engines produced from one builder (including hot reloads).
+#[derive(Debug, Default)]
+pub(crate) struct LookupStore {
+    current: RwLock<Arc<LookupGeneration>>,
+}
+
+impl LookupStore {
+    pub(crate) fn new(tables: HashMap<String, OwnedDataValue>) -> Self {
+        let tables = tables
+            .into_iter()
+            .map(|(name, table)| (name, Arc::new(table)))
+            .collect();
+        Self {
+            current: RwLock::new(Arc::new(LookupGeneration { number: 0, tables })),
+        }
+    }
+
+    /// The generation new messages see.
+    pub(crate) fn current(&self) -> Arc<LookupGeneration> {
+        Arc::clone(&self.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Publish a generation with `name` set to `table` (or removed, for
+    /// `None`). Returns the new generation number.
+    pub(crate) fn publish(&self, name: &str, table: Option<OwnedDataValue>) -> u64 {
+        let mut current = self.current.write().unwrap_or_else(|e| e.into_inner());
+        // Tables are `Arc`s, so the copy only bumps refcounts.
+        let mut tables = current.tables.clone();
+        match table {
+            Some(table) => tables.insert(name.to_string(), Arc::new(table)),
+            None => tables.remove(name),
+        };
+        let number = current.number + 1;
+        *current = Arc::new(LookupGeneration { number, tables });
+        number
+    }
+}
+
+tokio::task_local! {
+    /// The generation pinned by the message being processed.
+    static PINNED: Arc<LookupGeneration>;
+}
+
+/// Run `future` with `generation` pinned for every `lookup` it evaluates.
+pub(crate) async fn pinned<F: Future>(
+    generation: Option<Arc<LookupGeneration>>,
+    future: F,
+) -> F::Output {
+    match generation {
+        Some(generation) => PINNED.scope(generation, future).await,
+        None => future.await,
+    }
+}
+
+/// Synchronous form of [`pinned`].
+pub(crate) fn pinned_sync<T>(
+    generation: Option<Arc<LookupGeneration>>,
+    f: impl FnOnce() -> T,
+) -> T {
+    match generation {
+        Some(generation) => PINNED.sync_scope(generation, f),
+        None => f(),
+    }
+}
+
+/// The `lookup` operator. Reads the pinned generation, or the current one
+/// when evaluated outside message processing.
+pub(crate) struct LookupOperator(pub(crate) Arc<LookupStore>);
+
+impl CustomOperator for LookupOperator {
+    fn evaluate<'a>(
+        &self,
+        args: &[&'a DataValue<'a>],
+        _ctx: &mut EvalContext<'_, 'a>,
+        arena: &'a Bump,
+    ) -> datalogic_rs::Result<&'a DataValue<'a>> {
+        let (name, key) = match args {
+            [name, key] | [name, key, _] => (name, key),
+            _ => {
+                return Err(LogicError::invalid_arguments(
+                    "lookup expects [table, key] or [table, key, default]",
+                ));
+            }
+        };
+        let name = name
+            .as_str()
+            .ok_or_else(|| LogicError::invalid_arguments("lookup table name must be a string"))?;
+        let generation = PINNED
+            .try_with(Arc::clone)
+            .unwrap_or_else(|_| self.0.current());
+        let table = generation.tables.get(name).ok_or_else(|| {
+            LogicError::invalid_arguments(format!("unknown lookup table '{name}'"))
+        })?;
+
+        match find(table, key) {
+            Some(entry) => Ok(arena.alloc(entry.to_arena(arena))),
+            None => Ok(args.get(2).copied().unwrap_or(&DataValue::Null)),
+        }
+    }
+}
+
+fn find<'t>(table: &'t OwnedDataValue, key: &DataValue<'_>) -> Option<&'t OwnedDataValue> {
+    match table {
+        OwnedDataValue::Array(_) => key
+            .as_i64()
+            .and_then(|index| usize::try_from(index).ok())
+            .and_then(|index| table.get(index)),
+        _ => match key.as_str() {
+            Some(key) => table.get(key),
+            None if key.is_number() => table.get(key.to_string()),
+            None => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datalogic_rs::Engine as DatalogicEngine;
+    use serde_json::json;
+
+    #[test]
+    fn looks_up_object_and_array_entries_with_defaults() {
+        let tables = HashMap::from([
+            (
+                "countries".to_string(),
+                OwnedDataValue::from(json!({"US": {"region": "NA"}, "1": "one"})),
+            ),
+            ("tiers".to_string(), OwnedDataValue::from(json!(["gold"]))),
+        ]);
+        let engine = DatalogicEngine::builder()
+            .add_operator(
+                LOOKUP_OPERATOR,
+                LookupOperator(Arc::new(LookupStore::new(tables))),
+            )
+            .build();
+        let eval = |logic: &str| engine.eval_str(logic, r#"{"c": "US"}"#).unwrap();
+
+        assert_eq!(
+            eval(r#"{"lookup": ["countries", {"var": "c"}]}"#),
+            r#"{"region":"NA"}"#
+        );
+        assert_eq!(eval(r#"{"lookup": ["countries", 1]}"#), r#""one""#);
+        assert_eq!(eval(r#"{"lookup": ["tiers", 0]}"#), r#""gold""#);
+        assert_eq!(eval(r#"{"lookup": ["countries", "FR", "??"]}"#), r#""??""#);
+        assert_eq!(eval(r#"{"lookup": ["tiers", 5]}"#), "null");
+        assert!(
+            engine
+                .eval_str(r#"{"lookup": ["nope", "x"]}"#, "null")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn pinned_generation_survives_updates() {
+        let store = Arc::new(LookupStore::new(HashMap::from([(
+            "t".to_string(),
+            OwnedDataValue::from(json!({"k": "old"})),
+        )])));
+        let engine = DatalogicEngine::builder()
+            .add_operator(LOOKUP_OPERATOR, LookupOperator(Arc::clone(&store)))
+            .build();
+        let eval = || {
+            engine
+                .eval_str(r#"{"lookup": ["t", "k"]}"#, "null")
+                .unwrap()
+        };
+
+        let pinned = store.current();
+        assert_eq!(
+            store.publish("t", Some(OwnedDataValue::from(json!({"k": "new"})))),
+            1
+        );
+        assert_eq!(pinned_sync(Some(pinned), eval), r#""old""#);
+        assert_eq!(eval(), r#""new""#);
+
+        assert_eq!(store.publish("t", None), 2);
+        assert!(
+            engine
+                .eval_str(r#"{"lookup": ["t", "k"]}"#, "null")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! # Engine Manifests
+//!
+//! One JSON document describing a whole processing node: engine options,
+//! the workflow, rule pack and task fragment files to load, lookup tables
+//! and feature toggles. The manifest and the files it references can be
+//! versioned and deployed as one artifact.
+//!
+//! ```json
+//! {
+//!     "name": "payments-node",
+//!     "version": "2024.10.1",
+//!     "options": {"retry": {"max_retries": 2}, "strict_functions": true},
+//!     "parse_mode": "strict",
+//!     "workflows": ["workflows/"],
+//!     "rule_packs": ["packs/sepa_core.json"],
+//!     "fragments": ["fragments/"],
+//!     "lookup_tables": {"countries": "tables/countries.json"},
+//!     "features": {"fraud_checks": true}
+//! }
+//! ```
+//!
+//! - `options` is an [`EngineOptions`] document.
+//! - `parse_mode` is the [`ParseMode`] workflow files are read in;
+//!   `"strict"` rejects unknown keys. Default: `"lenient"`.
+//! - `workflows`, `rule_packs` and `fragments` list files or directories;
+//!   a directory contributes its `*.json` files, sorted by file name.
+//! - `lookup_tables` maps a table name to a JSON file, registered as with
+//!   [`EngineBuilder::with_lookup_table`].
+//! - `features` are named on/off toggles, exposed to logic as the
+//!   `features` lookup table: `{"lookup": ["features", "fraud_checks", false]}`.
+//!
+//! Relative paths are resolved against the manifest's directory. Every key
+//! is optional; unknown keys are rejected.
+//!
+//! [`Engine::from_manifest`](crate::Engine::from_manifest) builds an engine
+//! in one call. When workflows use custom functions, turn the manifest into
+//! a builder and register the handlers first:
+//!
+//! ```no_run
+//! # use dataflow_rs::EngineManifest;
+//! let engine = EngineManifest::from_file("node.json")?
+//!     .into_builder()?
+//!     // .register("notify", NotifyHandler)
+//!     .build()?;
+//! # Ok::<(), dataflow_rs::DataflowError>(())
+//! ```
+
+use crate::engine::error::{DataflowError, Result, locate, parse_failure};
+use crate::engine::fragment::TaskFragment;
+use crate::engine::options::EngineOptions;
+use crate::engine::parse_mode::ParseMode;
+use crate::engine::rule_pack::RulePack;
+use crate::engine::workflow::Workflow;
+use crate::engine::{Engine, EngineBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the lookup table holding the manifest's feature toggles.
+pub const FEATURES_TABLE: &str = "features";
+
+/// A declarative description of an engine. See the [module docs](self).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineManifest {
+    /// Free-form node name, for diagnostics.
+    pub name: Option<String>,
+    /// Free-form version label, for diagnostics.
+    pub version: Option<String>,
+    pub options: EngineOptions,
+    /// How workflow files treat unknown keys.
+    pub parse_mode: ParseMode,
+    /// Workflow files or directories.
+    pub workflows: Vec<PathBuf>,
+    /// Rule pack files or directories.
+    pub rule_packs: Vec<PathBuf>,
+    /// Task fragment files or directories.
+    pub fragments: Vec<PathBuf>,
+    /// Lookup table name to JSON file.
+    pub lookup_tables: BTreeMap<String, PathBuf>,
+    /// Feature toggles.
+    pub features: BTreeMap<String, bool>,
+    /// Directory relative paths are resolved against. Set by
+    /// [`EngineManifest::from_file`]; defaults to the working directory.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+}
+
+impl EngineManifest {
+    /// Parse a manifest from a JSON string. Relative paths resolve against
+    /// the working directory.
+    pub fn from_json(json_str: &str) -> Result<Self> {
+        serde_json::from_str(json_str).map_err(|err| parse_failure(err).into())
+    }
+
+    /// Load a manifest from a JSON file. Relative paths resolve against the
+    /// file's directory.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut manifest = Self::from_json(&read(path)?).map_err(|e| in_file(path, e))?;
+        manifest.base_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(manifest)
+    }
+
+    /// Whether feature `name` is switched on. Unlisted features are off.
+    pub fn feature_enabled(&self, name: &str) -> bool {
+        self.features.get(name).copied().unwrap_or(false)
+    }
+
+    /// Load every referenced file into a builder. Errors name the file
+    /// that failed.
+    pub fn into_builder(self) -> Result<EngineBuilder> {
+        let mut builder = EngineBuilder::new().with_options(self.options);
+
+        for path in json_files(&self.base_dir, &self.workflows)? {
+            let mut workflow = Workflow::from_json_with_mode(&read(&path)?, self.parse_mode)
+                .map_err(|e| in_file(&path, e))?;
+            workflow.anchor_includes(&path);
+            builder = builder.with_workflow(workflow);
+        }
+        for path in json_files(&self.base_dir, &self.rule_packs)? {
+            let pack = RulePack::from_json(&read(&path)?).map_err(|e| in_file(&path, e))?;
+            builder = builder.with_rule_pack(pack);
+        }
+        for path in json_files(&self.base_dir, &self.fragments)? {
+            builder = builder.with_fragment(TaskFragment::from_file(&path)?);
+        }
+        for (name, path) in self.lookup_tables {
+            let path = self.base_dir.join(path);
+            let table: Value = serde_json::from_str(&read(&path)?)
+                .map_err(|e| in_file(&path, DataflowError::from_serde(e)))?;
+            builder = builder.with_lookup_table(name, table);
+        }
+        if !self.features.is_empty() {
+            let features =
+                serde_json::to_value(&self.features).map_err(DataflowError::from_serde)?;
+            builder = builder.with_lookup_table(FEATURES_TABLE, features);
+        }
+        Ok(builder)
+    }
+}
+
+impl Engine {
+    /// Build an engine from a manifest file. See [`EngineManifest`]; use
+    /// [`EngineManifest::into_builder`] to register custom functions first.
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> Result<Self> {
+        EngineManifest::from_file(path)?.into_builder()?.build()
+    }
+}
+
+/// Expand `entries` (files or directories, relative to `base_dir`) into a
+/// list of files. Directories contribute their `*.json` files, sorted.
+fn json_files(base_dir: &Path, entries: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = base_dir.join(entry);
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        let mut dir_files = Vec::new();
+        for item in fs::read_dir(&path).map_err(|e| in_file(&path, DataflowError::from_io(e)))? {
+            let item = item.map_err(DataflowError::from_io)?.path();
+            if item.extension().is_some_and(|ext| ext == "json") {
+                dir_files.push(item);
+            }
+        }
+        dir_files.sort();
+        files.extend(dir_files);
+    }
+    Ok(files)
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| in_file(path, DataflowError::from_io(e)))
+}
+
+/// A load error as a [`DataflowError::Config`] naming the file it came
+/// from.
+pub(crate) fn in_file(path: &Path, err: DataflowError) -> DataflowError {
+    locate(err, |at| at.in_file(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_section_and_rejects_unknown_keys() {
+        let manifest = EngineManifest::from_json(
+            r#"{"name": "node", "options": {"strict_functions": true},
+                "workflows": ["a.json", "dir"],
+                "lookup_tables": {"countries": "countries.json"},
+                "features": {"beta": true, "legacy": false}}"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.name.as_deref(), Some("node"));
+        assert!(manifest.options.strict_functions);
+        assert_eq!(manifest.workflows.len(), 2);
+        assert!(manifest.rule_packs.is_empty());
+        assert!(manifest.feature_enabled("beta"));
+        assert!(!manifest.feature_enabled("legacy"));
+        assert!(!manifest.feature_enabled("unlisted"));
+
+        assert!(EngineManifest::from_json(r#"{"workflow": []}"#).is_err());
+    }
+
+    #[test]
+    fn load_errors_name_the_file() {
+        let manifest = EngineManifest {
+            workflows: vec![PathBuf::from("/nonexistent/dataflow/wf.json")],
+            ..Default::default()
+        };
+        let err = manifest.into_builder().err().expect("missing file");
+        assert!(err.to_string().contains("/nonexistent/dataflow/wf.json"));
+    }
+}
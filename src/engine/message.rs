@@ -1,11 +1,28 @@
+use crate::engine::audit::AuditMismatch;
+use crate::engine::dry_run::HeldBackTask;
 use crate::engine::error::{DataflowError, ErrorInfo};
+use crate::engine::extensions::Extensions;
+use crate::engine::lookup::LookupGeneration;
+use crate::engine::utils::set_nested_value;
 use chrono::{DateTime, Utc};
 use datavalue::OwnedDataValue;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+/// `metadata` key holding the id (or ids) a message was derived from.
+pub(crate) const PARENT_ID: &str = "parent_id";
+/// `metadata` key holding the id of the original input a message derives
+/// from.
+pub(crate) const ROOT_ID: &str = "root_id";
+/// `metadata` key holding a message's processing priority.
+pub(crate) const PRIORITY: &str = "priority";
+/// `metadata` key set to `true` by the `reject` function.
+pub(crate) const REJECTED: &str = "rejected";
+
 /// A message flowing through the dataflow engine.
 ///
 /// Construct via [`Message::builder`] for the full API, or use the shortcuts
@@ -23,7 +40,8 @@ use uuid::Uuid;
 /// Every other field is encapsulated — read via `id()`, `payload()`,
 /// `audit_trail()`, `errors()`, `capture_changes()`; mutate `errors` via
 /// [`Message::add_error`]; mutate `context` via [`crate::TaskContext::set`].
-/// Direct mutation of `audit_trail` is engine-internal.
+/// Direct mutation of `audit_trail` is engine-internal. Typed, non-JSON
+/// per-message state lives in [`Message::extensions`].
 #[derive(Debug, Clone)]
 pub struct Message {
     pub(crate) id: String,
@@ -45,28 +63,56 @@ pub struct Message {
     /// the bulk-pipeline fast path. UI debug consumers should leave this at
     /// `true`. Wire shape is unchanged either way.
     pub(crate) capture_changes: bool,
+    /// Typed scratch store for custom handlers. In-memory only — never
+    /// serialized and never visible to JSONLogic.
+    pub(crate) extensions: Extensions,
+    /// Processing deadline. When set, the engine writes the time left to
+    /// `metadata.deadline_remaining_ms` before each workflow and task.
+    /// In-memory only — never serialized.
+    pub(crate) deadline: Option<DateTime<Utc>>,
+    /// Cancellation signal. When cancelled, the engine stops before the next
+    /// workflow or task. In-memory only — never serialized.
+    pub(crate) cancellation: Option<CancellationToken>,
+    /// Lookup-table generation pinned when processing started, so the whole
+    /// run sees one version of the tables. In-memory only — never serialized.
+    pub(crate) lookups: Option<Arc<LookupGeneration>>,
+    /// HMAC of the audit chain head, set when the engine signs audit
+    /// trails. Serialized only when present.
+    pub(crate) audit_signature: Option<String>,
+    /// `Some` during a [dry run](crate::engine::dry_run), collecting the
+    /// tasks it held back. In-memory only — never serialized.
+    pub(crate) held_back: Option<Vec<HeldBackTask>>,
+    /// Channel the message is being processed through, so its checkpoints
+    /// resume on that channel. In-memory only — never serialized.
+    pub(crate) channel: Option<String>,
 }
 
-// Custom Serialize: stable wire format ({id, payload, context, audit_trail, errors}).
-// `capture_changes` is an in-memory hint only — never serialized.
+// Custom Serialize: stable wire format ({id, payload, context, audit_trail, errors},
+// plus `audit_signature` when signed). `capture_changes`, `extensions`,
+// `deadline` and `cancellation` are in-memory only — never serialized.
 impl Serialize for Message {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
         use serde::ser::SerializeStruct;
-        let mut state = serializer.serialize_struct("Message", 5)?;
+        let fields = 5 + usize::from(self.audit_signature.is_some());
+        let mut state = serializer.serialize_struct("Message", fields)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("payload", &self.payload)?;
         state.serialize_field("context", &self.context)?;
         state.serialize_field("audit_trail", &self.audit_trail)?;
         state.serialize_field("errors", &self.errors)?;
+        if let Some(signature) = &self.audit_signature {
+            state.serialize_field("audit_signature", signature)?;
+        }
         state.end()
     }
 }
 
 // Custom Deserialize: mirrors the Serialize shape; no cache field to seed.
-// `capture_changes` defaults to `true` for back-compat.
+// `capture_changes` defaults to `true` for back-compat; `extensions` starts
+// empty; there is no deadline or cancellation token.
 impl<'de> Deserialize<'de> for Message {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -79,6 +125,8 @@ impl<'de> Deserialize<'de> for Message {
             context: OwnedDataValue,
             audit_trail: Vec<AuditTrail>,
             errors: Vec<ErrorInfo>,
+            #[serde(default)]
+            audit_signature: Option<String>,
         }
 
         let data = MessageData::deserialize(deserializer)?;
@@ -89,6 +137,13 @@ impl<'de> Deserialize<'de> for Message {
             audit_trail: data.audit_trail,
             errors: data.errors,
             capture_changes: true,
+            extensions: Extensions::new(),
+            deadline: None,
+            cancellation: None,
+            lookups: None,
+            audit_signature: data.audit_signature,
+            held_back: None,
+            channel: None,
         })
     }
 }
@@ -117,6 +172,13 @@ impl Message {
             audit_trail: vec![],
             errors: vec![],
             capture_changes: true,
+            extensions: Extensions::new(),
+            deadline: None,
+            cancellation: None,
+            lookups: None,
+            audit_signature: None,
+            held_back: None,
+            channel: None,
         }
     }
 
@@ -175,6 +237,47 @@ impl Message {
         &self.audit_trail
     }
 
+    /// Id of the original input this message derives from
+    /// (`metadata.root_id`), or its own id when it is an original input.
+    pub fn root_id(&self) -> &str {
+        self.metadata()
+            .get(ROOT_ID)
+            .and_then(|v| v.as_str())
+            .unwrap_or(&self.id)
+    }
+
+    /// Ids of the messages this one was derived or aggregated from
+    /// (`metadata.parent_id`). Empty for an original input.
+    pub fn parent_ids(&self) -> Vec<&str> {
+        match self.metadata().get(PARENT_ID) {
+            Some(OwnedDataValue::String(id)) => vec![id.as_str()],
+            Some(OwnedDataValue::Array(ids)) => ids.iter().filter_map(|id| id.as_str()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Processing priority (`metadata.priority`), for queues that order
+    /// messages by it. The engine only carries it over to child messages;
+    /// see [`urgency`](crate::engine::urgency).
+    pub fn priority(&self) -> Option<i64> {
+        self.metadata().get(PRIORITY).and_then(|v| v.as_i64())
+    }
+
+    /// Whether a `reject` task rejected this message (`metadata.rejected`).
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self.metadata().get(REJECTED),
+            Some(OwnedDataValue::Bool(true))
+        )
+    }
+
+    /// Signature over the audit trail, when the engine signs audit trails.
+    /// Check it with [`crate::Engine::verify_audit`].
+    #[inline]
+    pub fn audit_signature(&self) -> Option<&str> {
+        self.audit_signature.as_deref()
+    }
+
     /// Errors collected while processing — both validation failures and
     /// task errors that the workflow swallowed via `continue_on_error`.
     #[inline]
@@ -190,6 +293,107 @@ impl Message {
         self.capture_changes
     }
 
+    /// Typed scratch store shared by every task that processes this message.
+    /// Lets custom handlers pass parsed structs or other non-JSON state
+    /// between tasks without round-tripping through `temp_data`.
+    #[inline]
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Mutable access to the typed scratch store. Writes here are not
+    /// recorded in the audit trail.
+    #[inline]
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Processing deadline, if one was set.
+    #[inline]
+    pub fn deadline(&self) -> Option<DateTime<Utc>> {
+        self.deadline
+    }
+
+    /// Set or clear the processing deadline. While set, the engine writes
+    /// the milliseconds left (floored at 0) to
+    /// `metadata.deadline_remaining_ms` before each workflow condition and
+    /// each task, so conditions can skip optional work when time is short.
+    /// The engine does not abort on its own when the deadline passes.
+    pub fn set_deadline(&mut self, deadline: Option<DateTime<Utc>>) {
+        self.deadline = deadline;
+    }
+
+    /// Cancellation token, if one was set.
+    #[inline]
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.cancellation.as_ref()
+    }
+
+    /// Set or clear the cancellation token. Once it is cancelled the engine
+    /// stops before the next workflow or task, records a `CANCELLED` error,
+    /// and returns [`DataflowError::Cancelled`]. Async handlers see the
+    /// token through [`crate::TaskContext::cancellation`] and may stop
+    /// early themselves.
+    pub fn set_cancellation(&mut self, token: Option<CancellationToken>) {
+        self.cancellation = token;
+    }
+
+    /// `Err(Cancelled)` once the cancellation token has been cancelled.
+    pub(crate) fn check_cancelled(&self) -> crate::engine::error::Result<()> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => Err(DataflowError::Cancelled(format!(
+                "processing of message {} was cancelled",
+                self.id
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Take the serialized state of `snapshot` — payload, context, audit
+    /// trail, errors and signature — keeping this message's in-memory
+    /// settings (extensions, deadline, cancellation, change capture).
+    pub(crate) fn restore(&mut self, snapshot: &Message) {
+        self.payload = Arc::clone(&snapshot.payload);
+        self.context = snapshot.context.clone();
+        self.audit_trail = snapshot.audit_trail.clone();
+        self.errors = snapshot.errors.clone();
+        self.audit_signature = snapshot.audit_signature.clone();
+    }
+
+    /// Write `metadata.deadline_remaining_ms` as of `now`. Returns `false`
+    /// (and writes nothing) when there is no deadline.
+    pub(crate) fn stamp_deadline_remaining(&mut self, now: DateTime<Utc>) -> bool {
+        let Some(deadline) = self.deadline else {
+            return false;
+        };
+        let remaining = (deadline - now).num_milliseconds().max(0) as u64;
+        set_nested_value(
+            &mut self.context,
+            "metadata.deadline_remaining_ms",
+            OwnedDataValue::from(remaining),
+        );
+        true
+    }
+
+    /// Undo `changes` against `context`, newest first, writing each
+    /// `old_value` back to its path. Paths that did not exist before the
+    /// change are restored as `null` — `Change` does not distinguish
+    /// "absent" from "null"; [`TaskContext::rollback`](crate::TaskContext::rollback)
+    /// removes them. Does not touch the audit trail.
+    pub fn rollback_changes(&mut self, changes: &[Change]) {
+        for change in changes.iter().rev() {
+            set_nested_value(&mut self.context, &change.path, change.old_value.clone());
+        }
+    }
+
+    /// Check that the audit trail accounts for this message's `data`,
+    /// replaying its recorded changes over `original`, the message as it
+    /// arrived. Persistence layers can run this before archiving. See
+    /// [Replay](crate::engine::audit#replay).
+    pub fn replay_audit(&self, original: &Message) -> std::result::Result<(), AuditMismatch> {
+        crate::engine::audit::replay(original, self)
+    }
+
     /// Get a reference to the `data` field in context. Returns
     /// `&OwnedDataValue::Null` if missing (matches `serde_json::Value`'s
     /// `Index` fallback semantics).
@@ -222,6 +426,14 @@ impl Message {
 ///     .build();
 /// assert!(m.id().len() > 0);
 /// assert!(m.capture_changes());
+///
+/// // Pre-populated context sections, validated.
+/// let m = Message::builder()
+///     .data_json(&json!({"order": {"total": 1500}}))
+///     .metadata_json(&json!({"source": "api"}))
+///     .try_build()
+///     .unwrap();
+/// assert_eq!(m.metadata()["source"].as_str(), Some("api"));
 /// ```
 #[must_use = "MessageBuilder must be `.build()` to produce a Message"]
 #[derive(Default)]
@@ -229,6 +441,16 @@ pub struct MessageBuilder {
     id: Option<String>,
     payload: Option<Arc<OwnedDataValue>>,
     capture_changes: Option<bool>,
+    deadline: Option<DateTime<Utc>>,
+    cancellation: Option<CancellationToken>,
+    data: Option<OwnedDataValue>,
+    metadata: Option<OwnedDataValue>,
+    temp_data: Option<OwnedDataValue>,
+    /// `metadata.parent_id` and `metadata.root_id`, written over the
+    /// metadata section at build.
+    lineage: Option<(OwnedDataValue, String)>,
+    /// `metadata.priority`, written over the metadata section at build.
+    priority: Option<i64>,
 }
 
 impl MessageBuilder {
@@ -267,22 +489,176 @@ impl MessageBuilder {
         self
     }
 
-    /// Finalize. Defaults: id = UUID v7, payload = `OwnedDataValue::Null`,
-    /// capture_changes = `true`.
-    pub fn build(self) -> Message {
+    /// Processing deadline; see [`Message::set_deadline`]. Defaults to none.
+    pub fn deadline(mut self, deadline: DateTime<Utc>) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Cancellation token; see [`Message::set_cancellation`]. Defaults to
+    /// none.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Initial `data` section. Must be an object: see [`Self::try_build`].
+    pub fn data(mut self, data: OwnedDataValue) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    /// [`Self::data`] from a `serde_json::Value`.
+    pub fn data_json(self, data: &JsonValue) -> Self {
+        self.data(OwnedDataValue::from(data))
+    }
+
+    /// Initial `metadata` section. Must be an object: see [`Self::try_build`].
+    pub fn metadata(mut self, metadata: OwnedDataValue) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// [`Self::metadata`] from a `serde_json::Value`.
+    pub fn metadata_json(self, metadata: &JsonValue) -> Self {
+        self.metadata(OwnedDataValue::from(metadata))
+    }
+
+    /// Initial `temp_data` section. Must be an object: see [`Self::try_build`].
+    pub fn temp_data(mut self, temp_data: OwnedDataValue) -> Self {
+        self.temp_data = Some(temp_data);
+        self
+    }
+
+    /// [`Self::temp_data`] from a `serde_json::Value`.
+    pub fn temp_data_json(self, temp_data: &JsonValue) -> Self {
+        self.temp_data(OwnedDataValue::from(temp_data))
+    }
+
+    /// Record `parent` as the message this one was split or emitted from:
+    /// sets `metadata.parent_id` to its id and `metadata.root_id` to its
+    /// root (its own id when it has no parent).
+    pub fn derived_from(mut self, parent: &Message) -> Self {
+        self.lineage = Some((
+            OwnedDataValue::String(parent.id.clone()),
+            parent.root_id().to_string(),
+        ));
+        self
+    }
+
+    /// Processing priority; see [`Message::priority`]. Written to
+    /// `metadata.priority` at build, over the metadata section.
+    pub fn priority(mut self, priority: i64) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Record `parents` as the messages this one aggregates:
+    /// `metadata.parent_id` lists their ids and `metadata.root_id` is the
+    /// first parent's root. No-op for an empty list.
+    pub fn aggregated_from<'m>(mut self, parents: impl IntoIterator<Item = &'m Message>) -> Self {
+        let parents: Vec<&Message> = parents.into_iter().collect();
+        if let Some(first) = parents.first() {
+            let ids = parents
+                .iter()
+                .map(|p| OwnedDataValue::String(p.id.clone()))
+                .collect();
+            self.lineage = Some((OwnedDataValue::Array(ids), first.root_id().to_string()));
+        }
+        self
+    }
+
+    /// Finalize, checking that every context section set on the builder is
+    /// an object. Defaults: id = UUID v7, payload = `OwnedDataValue::Null`,
+    /// empty sections, capture_changes = `true`. Use this when the sections
+    /// come from external input.
+    pub fn try_build(self) -> crate::engine::error::Result<Message> {
+        for (name, section) in [
+            ("data", &self.data),
+            ("metadata", &self.metadata),
+            ("temp_data", &self.temp_data),
+        ] {
+            if let Some(other) = section.as_ref().filter(|v| !is_object(v)) {
+                return Err(DataflowError::Validation(format!(
+                    "Message {} must be an object, got {}",
+                    name,
+                    type_name(other)
+                )));
+            }
+        }
+        Ok(self.build())
+    }
+
+    /// Finalize. Same defaults as [`Self::try_build`], but a context
+    /// section that is not an object is logged at `warn` level and left
+    /// empty rather than rejected.
+    pub fn build(mut self) -> Message {
+        for (name, section) in [
+            ("data", &mut self.data),
+            ("metadata", &mut self.metadata),
+            ("temp_data", &mut self.temp_data),
+        ] {
+            if let Some(other) = section.take_if(|v| !is_object(v)) {
+                warn!(
+                    "MessageBuilder: ignoring the {} section, which must be an object, got {}",
+                    name,
+                    type_name(&other)
+                );
+            }
+        }
+        let empty = || OwnedDataValue::Object(Vec::new());
+        let mut sections = vec![
+            ("data".to_string(), self.data.unwrap_or_else(empty)),
+            ("metadata".to_string(), self.metadata.unwrap_or_else(empty)),
+            (
+                "temp_data".to_string(),
+                self.temp_data.unwrap_or_else(empty),
+            ),
+        ];
+        if let Some((parent_id, root_id)) = self.lineage {
+            let metadata = &mut sections[1].1;
+            set_nested_value(metadata, PARENT_ID, parent_id);
+            set_nested_value(metadata, ROOT_ID, OwnedDataValue::String(root_id));
+        }
+        if let Some(priority) = self.priority {
+            set_nested_value(&mut sections[1].1, PRIORITY, OwnedDataValue::from(priority));
+        }
         Message {
             id: self.id.unwrap_or_else(|| Uuid::now_v7().to_string()),
             payload: self
                 .payload
                 .unwrap_or_else(|| Arc::new(OwnedDataValue::Null)),
-            context: empty_context(),
+            context: OwnedDataValue::Object(sections),
             audit_trail: vec![],
             errors: vec![],
             capture_changes: self.capture_changes.unwrap_or(true),
+            extensions: Extensions::new(),
+            deadline: self.deadline,
+            cancellation: self.cancellation,
+            lookups: None,
+            audit_signature: None,
+            held_back: None,
+            channel: None,
         }
     }
 }
 
+fn is_object(value: &OwnedDataValue) -> bool {
+    matches!(value, OwnedDataValue::Object(_))
+}
+
+/// JSON type name of `value`, for error messages.
+fn type_name(value: &OwnedDataValue) -> &'static str {
+    match value {
+        OwnedDataValue::Null => "null",
+        OwnedDataValue::Bool(_) => "boolean",
+        OwnedDataValue::String(_) => "string",
+        OwnedDataValue::Array(_) => "array",
+        OwnedDataValue::Object(_) => "object",
+        _ => "number",
+    }
+}
+
 /// Build the canonical empty context shape used by `Message::new` and
 /// `MessageBuilder::build`.
 fn empty_context() -> OwnedDataValue {
@@ -300,6 +676,24 @@ pub struct AuditTrail {
     pub timestamp: DateTime<Utc>,
     pub changes: Vec<Change>,
     pub status: usize,
+    /// Annotations of the task and its workflow, when any are declared.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<Arc<OwnedDataValue>>,
+    /// SHA-256 of the task's function config, with audit input hashing on.
+    /// See [`audit`](crate::engine::audit).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_hash: Option<Arc<str>>,
+    /// SHA-256 of the context slice the task read before it ran, with
+    /// audit input hashing on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_hash: Option<String>,
+    /// Retries the task took before this outcome, when it was retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_count: Option<u32>,
+    /// SHA-256 linking this entry to the previous one, with audit chaining
+    /// on. See [`audit`](crate::engine::audit).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
 }
 
 /// A single recorded mutation in the audit trail.
@@ -333,4 +727,115 @@ mod tests {
         let err = Message::from_json_str("{ not json").expect_err("malformed input should fail");
         assert!(matches!(err, DataflowError::Deserialization(_)));
     }
+
+    #[test]
+    fn rollback_changes_restores_old_values_newest_first() {
+        let mut msg = Message::from_value(&serde_json::json!({}));
+        set_nested_value(&mut msg.context, "data.x", OwnedDataValue::from(1u64));
+        let changes = vec![
+            Change {
+                path: Arc::from("data.x"),
+                old_value: OwnedDataValue::from(1u64),
+                new_value: OwnedDataValue::from(2u64),
+            },
+            Change {
+                path: Arc::from("data.x"),
+                old_value: OwnedDataValue::from(2u64),
+                new_value: OwnedDataValue::from(3u64),
+            },
+        ];
+        set_nested_value(&mut msg.context, "data.x", OwnedDataValue::from(3u64));
+
+        msg.rollback_changes(&changes);
+        assert_eq!(msg.context["data"]["x"], OwnedDataValue::from(1u64));
+    }
+
+    #[test]
+    fn extensions_are_not_serialized() {
+        let mut msg = Message::from_value(&serde_json::json!({"a": 1}));
+        msg.extensions_mut().insert(42u64);
+        let wire = serde_json::to_value(&msg).unwrap();
+        assert!(wire.get("extensions").is_none());
+
+        let back: Message = serde_json::from_value(wire).unwrap();
+        assert!(back.extensions().is_empty());
+        assert_eq!(msg.clone().extensions().get::<u64>(), Some(&42));
+    }
+
+    #[test]
+    fn lineage_links_children_and_aggregates_to_the_root() {
+        let root = Message::builder().id("root").build();
+        assert_eq!(root.root_id(), "root");
+        assert!(root.parent_ids().is_empty());
+
+        let child = Message::builder()
+            .id("child")
+            .metadata_json(&serde_json::json!({"source": "split"}))
+            .derived_from(&root)
+            .build();
+        assert_eq!(child.parent_ids(), ["root"]);
+        assert_eq!(child.root_id(), "root");
+        assert_eq!(child.metadata()["source"].as_str(), Some("split"));
+
+        let grandchild = Message::builder().derived_from(&child).build();
+        assert_eq!(grandchild.parent_ids(), ["child"]);
+        assert_eq!(grandchild.root_id(), "root");
+
+        let sibling = Message::builder().id("sibling").derived_from(&root).build();
+        let joined = Message::builder()
+            .aggregated_from([&child, &sibling])
+            .build();
+        assert_eq!(joined.parent_ids(), ["child", "sibling"]);
+        assert_eq!(joined.root_id(), "root");
+    }
+
+    #[test]
+    fn deadline_remaining_is_floored_at_zero() {
+        let now = Utc::now();
+        let mut msg = Message::builder()
+            .deadline(now + chrono::Duration::milliseconds(1500))
+            .build();
+        assert!(msg.stamp_deadline_remaining(now));
+        assert_eq!(
+            msg.metadata()["deadline_remaining_ms"],
+            OwnedDataValue::from(1500u64)
+        );
+
+        assert!(msg.stamp_deadline_remaining(now + chrono::Duration::seconds(5)));
+        assert_eq!(
+            msg.metadata()["deadline_remaining_ms"],
+            OwnedDataValue::from(0u64)
+        );
+
+        msg.set_deadline(None);
+        assert!(!msg.stamp_deadline_remaining(now));
+    }
+
+    #[test]
+    fn builder_sets_and_validates_context_sections() {
+        let msg = Message::builder()
+            .data_json(&serde_json::json!({"order": {"total": 5}}))
+            .temp_data_json(&serde_json::json!({"scratch": true}))
+            .try_build()
+            .unwrap();
+        assert_eq!(msg.data()["order"]["total"], OwnedDataValue::from(5u64));
+        assert_eq!(msg.temp_data()["scratch"], OwnedDataValue::Bool(true));
+        assert!(msg.metadata().is_object());
+
+        let err = Message::builder()
+            .metadata_json(&serde_json::json!("not an object"))
+            .try_build()
+            .expect_err("string metadata must be rejected");
+        assert!(matches!(err, DataflowError::Validation(ref m) if m.contains("metadata")));
+
+        // `build` leaves a bad section empty instead of panicking.
+        let msg = Message::builder()
+            .data_json(&serde_json::json!([1, 2]))
+            .metadata_json(&serde_json::json!({"source": "api"}))
+            .priority(3)
+            .build();
+        assert_eq!(msg.data(), &OwnedDataValue::Object(Vec::new()));
+        assert_eq!(msg.metadata()["source"], OwnedDataValue::from("api"));
+        assert_eq!(msg.priority(), Some(3));
+    }
 }
@@ -0,0 +1,365 @@
+//! # Metrics
+//!
+//! Optional instrumentation. When a [`MetricsSink`] is configured
+//! ([`EngineBuilder::with_metrics`](crate::EngineBuilder::with_metrics)),
+//! the engine reports counters, histograms and gauges as it processes
+//! messages:
+//!
+//! | Metric | Kind | Labels |
+//! |--------|------|--------|
+//! | [`MESSAGES`] / [`MESSAGE_ERRORS`] | counter | — |
+//! | [`MESSAGE_DURATION_MS`] | histogram | — |
+//! | [`IN_FLIGHT`] | gauge | — |
+//! | [`WORKFLOW_EXECUTIONS`] / [`WORKFLOW_ERRORS`] | counter | `workflow` |
+//! | [`WORKFLOW_DURATION_MS`] | histogram | `workflow` |
+//! | [`TASK_EXECUTIONS`] / [`TASK_ERRORS`] | counter | `workflow`, `task` |
+//! | [`TASK_DURATION_MS`] | histogram | `workflow`, `task` |
+//!
+//! Message metrics cover `process_message` and
+//! `process_message_for_channel` (and so batches and streams); workflow and
+//! task metrics cover every entry point. A task error is an `Err` or a 5xx
+//! status. Skipped workflows and tasks are not counted.
+//!
+//! Without a sink nothing is measured. [`InMemoryMetrics`] keeps everything
+//! in process and summarizes it as [`EngineStats`], returned by
+//! [`Engine::stats`](crate::Engine::stats).
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Messages processed.
+pub const MESSAGES: &str = "dataflow_messages_total";
+/// Messages whose processing returned an error.
+pub const MESSAGE_ERRORS: &str = "dataflow_message_errors_total";
+/// Wall time of one message, in milliseconds.
+pub const MESSAGE_DURATION_MS: &str = "dataflow_message_duration_ms";
+/// Messages currently being processed.
+pub const IN_FLIGHT: &str = "dataflow_in_flight";
+/// Workflows whose condition matched and which ran.
+pub const WORKFLOW_EXECUTIONS: &str = "dataflow_workflow_executions_total";
+/// Workflows that ended with an error.
+pub const WORKFLOW_ERRORS: &str = "dataflow_workflow_errors_total";
+/// Wall time of one workflow, in milliseconds.
+pub const WORKFLOW_DURATION_MS: &str = "dataflow_workflow_duration_ms";
+/// Tasks whose condition matched and which ran.
+pub const TASK_EXECUTIONS: &str = "dataflow_task_executions_total";
+/// Tasks that returned an error or a 5xx status.
+pub const TASK_ERRORS: &str = "dataflow_task_errors_total";
+/// Wall time of one task, in milliseconds, retries included.
+pub const TASK_DURATION_MS: &str = "dataflow_task_duration_ms";
+
+/// Label pairs attached to one measurement.
+pub type Labels<'a> = &'a [(&'a str, &'a str)];
+
+/// Destination for engine metrics. Calls happen on the processing path, so
+/// implementations should be cheap and must not block: buffer, or update
+/// atomics, and export elsewhere.
+pub trait MetricsSink: Send + Sync {
+    fn increment_counter(&self, name: &str, labels: Labels<'_>, value: u64);
+    fn record_histogram(&self, name: &str, labels: Labels<'_>, value: f64);
+    fn set_gauge(&self, name: &str, labels: Labels<'_>, value: f64);
+
+    /// Summary of what was recorded, for sinks that keep it. Backs
+    /// [`Engine::stats`](crate::Engine::stats).
+    fn stats(&self) -> Option<EngineStats> {
+        None
+    }
+}
+
+impl<T: MetricsSink + ?Sized> MetricsSink for Arc<T> {
+    fn increment_counter(&self, name: &str, labels: Labels<'_>, value: u64) {
+        (**self).increment_counter(name, labels, value)
+    }
+
+    fn record_histogram(&self, name: &str, labels: Labels<'_>, value: f64) {
+        (**self).record_histogram(name, labels, value)
+    }
+
+    fn set_gauge(&self, name: &str, labels: Labels<'_>, value: f64) {
+        (**self).set_gauge(name, labels, value)
+    }
+
+    fn stats(&self) -> Option<EngineStats> {
+        (**self).stats()
+    }
+}
+
+/// `MetricsSink` that discards everything.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl MetricsSink for NoopMetrics {
+    fn increment_counter(&self, _name: &str, _labels: Labels<'_>, _value: u64) {}
+    fn record_histogram(&self, _name: &str, _labels: Labels<'_>, _value: f64) {}
+    fn set_gauge(&self, _name: &str, _labels: Labels<'_>, _value: f64) {}
+}
+
+/// Metric name plus its labels, in the order they were given.
+type Series = (String, Vec<(String, String)>);
+
+fn series(name: &str, labels: Labels<'_>) -> Series {
+    (
+        name.to_string(),
+        labels
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    )
+}
+
+/// Samples of one histogram series. Percentiles come from the most recent
+/// `HISTOGRAM_WINDOW` samples; count and sum cover all of them.
+#[derive(Debug, Default)]
+struct Histogram {
+    count: u64,
+    sum: f64,
+    recent: VecDeque<f64>,
+}
+
+/// Samples kept per histogram series for percentiles.
+const HISTOGRAM_WINDOW: usize = 1024;
+
+impl Histogram {
+    fn record(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        if self.recent.len() == HISTOGRAM_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(value);
+    }
+
+    fn summary(&self) -> LatencySummary {
+        let mut sorted: Vec<f64> = self.recent.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            let rank = ((p * sorted.len() as f64).ceil() as usize).clamp(1, sorted.len());
+            sorted[rank - 1]
+        };
+        LatencySummary {
+            mean_ms: if self.count == 0 {
+                0.0
+            } else {
+                self.sum / self.count as f64
+            },
+            p50_ms: percentile(0.50),
+            p99_ms: percentile(0.99),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    counters: BTreeMap<Series, u64>,
+    gauges: BTreeMap<Series, f64>,
+    histograms: BTreeMap<Series, Histogram>,
+}
+
+/// `MetricsSink` that keeps every series in memory and summarizes them as
+/// [`EngineStats`]. Histograms keep their most recent 1024 samples for
+/// percentiles, so memory stays bounded.
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    state: Mutex<State>,
+}
+
+impl InMemoryMetrics {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current value of a counter; 0 if never incremented.
+    pub fn counter(&self, name: &str, labels: Labels<'_>) -> u64 {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .counters
+            .get(&series(name, labels))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Current value of a gauge, if ever set.
+    pub fn gauge(&self, name: &str, labels: Labels<'_>) -> Option<f64> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.gauges.get(&series(name, labels)).copied()
+    }
+
+    /// Summarize the engine metrics recorded so far.
+    pub fn snapshot(&self) -> EngineStats {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let counter = |name: &str, labels: &[(String, String)]| {
+            state
+                .counters
+                .get(&(name.to_string(), labels.to_vec()))
+                .copied()
+                .unwrap_or(0)
+        };
+        let label = |labels: &[(String, String)], key: &str| {
+            labels
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+
+        let mut stats = EngineStats {
+            messages: counter(MESSAGES, &[]),
+            message_errors: counter(MESSAGE_ERRORS, &[]),
+            in_flight: state
+                .gauges
+                .get(&(IN_FLIGHT.to_string(), Vec::new()))
+                .map_or(0, |v| *v as usize),
+            message_latency: state
+                .histograms
+                .get(&(MESSAGE_DURATION_MS.to_string(), Vec::new()))
+                .map(Histogram::summary)
+                .unwrap_or_default(),
+            workflows: Vec::new(),
+            tasks: Vec::new(),
+        };
+        for ((name, labels), histogram) in &state.histograms {
+            if name == WORKFLOW_DURATION_MS {
+                stats.workflows.push(WorkflowStats {
+                    workflow_id: label(labels, "workflow"),
+                    executions: counter(WORKFLOW_EXECUTIONS, labels),
+                    errors: counter(WORKFLOW_ERRORS, labels),
+                    latency: histogram.summary(),
+                });
+            } else if name == TASK_DURATION_MS {
+                stats.tasks.push(TaskStats {
+                    workflow_id: label(labels, "workflow"),
+                    task_id: label(labels, "task"),
+                    executions: counter(TASK_EXECUTIONS, labels),
+                    errors: counter(TASK_ERRORS, labels),
+                    latency: histogram.summary(),
+                });
+            }
+        }
+        stats
+    }
+}
+
+impl MetricsSink for InMemoryMetrics {
+    fn increment_counter(&self, name: &str, labels: Labels<'_>, value: u64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        *state.counters.entry(series(name, labels)).or_default() += value;
+    }
+
+    fn record_histogram(&self, name: &str, labels: Labels<'_>, value: f64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state
+            .histograms
+            .entry(series(name, labels))
+            .or_default()
+            .record(value);
+    }
+
+    fn set_gauge(&self, name: &str, labels: Labels<'_>, value: f64) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.gauges.insert(series(name, labels), value);
+    }
+
+    fn stats(&self) -> Option<EngineStats> {
+        Some(self.snapshot())
+    }
+}
+
+/// Latency summary of one histogram, in milliseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LatencySummary {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+/// Per-workflow figures in [`EngineStats`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkflowStats {
+    pub workflow_id: String,
+    pub executions: u64,
+    pub errors: u64,
+    pub latency: LatencySummary,
+}
+
+/// Per-task figures in [`EngineStats`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskStats {
+    pub workflow_id: String,
+    pub task_id: String,
+    pub executions: u64,
+    pub errors: u64,
+    pub latency: LatencySummary,
+}
+
+/// Summary of an engine's metrics, from [`InMemoryMetrics`]. Workflows and
+/// tasks are sorted by id.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EngineStats {
+    pub messages: u64,
+    pub message_errors: u64,
+    /// Messages being processed when the gauge was last updated.
+    pub in_flight: usize,
+    pub message_latency: LatencySummary,
+    pub workflows: Vec<WorkflowStats>,
+    pub tasks: Vec<TaskStats>,
+}
+
+/// Milliseconds elapsed since `start`.
+pub(crate) fn elapsed_ms(start: DateTime<Utc>) -> f64 {
+    (Utc::now() - start)
+        .num_microseconds()
+        .map_or(f64::MAX, |us| us as f64 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_workflow_and_task_series() {
+        let metrics = InMemoryMetrics::new();
+        let task = [("workflow", "wf"), ("task", "t")];
+        for ms in 1..=100 {
+            metrics.increment_counter(TASK_EXECUTIONS, &task, 1);
+            metrics.record_histogram(TASK_DURATION_MS, &task, ms as f64);
+        }
+        metrics.increment_counter(TASK_ERRORS, &task, 1);
+        metrics.increment_counter(MESSAGES, &[], 3);
+        metrics.set_gauge(IN_FLIGHT, &[], 2.0);
+
+        let stats = metrics.stats().unwrap();
+        assert_eq!(stats.messages, 3);
+        assert_eq!(stats.in_flight, 2);
+        assert_eq!(stats.tasks.len(), 1);
+        let task_stats = &stats.tasks[0];
+        assert_eq!(
+            (task_stats.workflow_id.as_str(), task_stats.task_id.as_str()),
+            ("wf", "t")
+        );
+        assert_eq!((task_stats.executions, task_stats.errors), (100, 1));
+        assert_eq!(task_stats.latency.p50_ms, 50.0);
+        assert_eq!(task_stats.latency.p99_ms, 99.0);
+        assert_eq!(task_stats.latency.mean_ms, 50.5);
+        assert!(NoopMetrics.stats().is_none());
+    }
+
+    #[test]
+    fn histogram_percentiles_use_a_bounded_window() {
+        let mut histogram = Histogram::default();
+        for _ in 0..HISTOGRAM_WINDOW {
+            histogram.record(1000.0);
+        }
+        for _ in 0..HISTOGRAM_WINDOW {
+            histogram.record(1.0);
+        }
+        assert_eq!(histogram.recent.len(), HISTOGRAM_WINDOW);
+        assert_eq!(histogram.count, 2 * HISTOGRAM_WINDOW as u64);
+        assert_eq!(histogram.summary().p99_ms, 1.0);
+    }
+}
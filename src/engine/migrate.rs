@@ -0,0 +1,266 @@
+//! # Migrating v1 Workflows
+//!
+//! [`Workflow::migrate_from_v1`] upgrades a workflow document written for
+//! v1 of the crate to the current schema and reports every change, so a
+//! repository of rule files can be upgraded by a script instead of by hand
+//! (see `examples/migrate_v1.rs`):
+//!
+//! ```
+//! # use dataflow_rs::Workflow;
+//! let (workflow, report) = Workflow::migrate_from_v1(r#"{
+//!     "id": "orders", "condition": null,
+//!     "tasks": [{"id": "check", "name": "Check", "function": "validate",
+//!                "input": {"rules": []}}]
+//! }"#)?;
+//! assert_eq!(workflow.name, "orders");
+//! for change in &report.changes {
+//!     println!("{} {}", change.json_pointer, change.message);
+//! }
+//! # Ok::<(), dataflow_rs::DataflowError>(())
+//! ```
+//!
+//! The v1 shapes rewritten, in the workflow and each task (`foreach`
+//! bodies included):
+//!
+//! - `"function": "<name>"` with the task's `input` beside it becomes
+//!   `"function": {"name": "<name>", "input": ...}`.
+//! - A `function` object without `input` gets `"input": {}`.
+//! - The function name `validate` becomes `validation`.
+//! - `"condition": null` is removed, so the condition defaults to `true`.
+//! - A missing or empty `name` is set to the `id`.
+//!
+//! Functions v1 had without a direct equivalent — `parse` without
+//! `source` and `target`, and `http` — are left as written and reported
+//! with `needs_review`, to be replaced by `parse_json` and `http_call`.
+//! Everything else in the document, unknown keys included, is kept; the
+//! keys of [`MigrationReport::document`] come out sorted.
+
+use crate::engine::error::{DataflowError, Result, parse_failure};
+use crate::engine::workflow::Workflow;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+
+/// What [`Workflow::migrate_from_v1`] did to a document.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MigrationReport {
+    /// The upgraded document, to write back in place of the v1 one.
+    pub document: Value,
+    /// Changes in document order, each with what still needs a hand.
+    pub changes: Vec<MigrationChange>,
+}
+
+/// One change made, or left to be made, by a migration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationChange {
+    /// JSON pointer to the changed value, e.g. `/tasks/2/function`.
+    pub json_pointer: String,
+    pub message: String,
+    /// The construct was left as written and must be migrated by hand.
+    pub needs_review: bool,
+}
+
+impl MigrationReport {
+    /// Whether the document was already in the current schema.
+    pub fn is_unchanged(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Changes that must be finished by hand.
+    pub fn needs_review(&self) -> impl Iterator<Item = &MigrationChange> {
+        self.changes.iter().filter(|change| change.needs_review)
+    }
+
+    fn note(&mut self, pointer: &str, message: String) {
+        self.changes.push(MigrationChange {
+            json_pointer: pointer.to_string(),
+            message,
+            needs_review: false,
+        });
+    }
+
+    fn review(&mut self, pointer: &str, message: String) {
+        self.changes.push(MigrationChange {
+            json_pointer: pointer.to_string(),
+            message,
+            needs_review: true,
+        });
+    }
+}
+
+impl Workflow {
+    /// Upgrade the v1 workflow document `json` and load it. Returns the
+    /// workflow and the [`MigrationReport`]; fails like
+    /// [`from_json`](Self::from_json) when the upgraded document still
+    /// doesn't parse. See the [module docs](crate::engine::migrate).
+    pub fn migrate_from_v1(json: &str) -> Result<(Self, MigrationReport)> {
+        let mut document: Value =
+            serde_json::from_str(json).map_err(|err| DataflowError::from(parse_failure(err)))?;
+        let mut report = MigrationReport::default();
+        if let Value::Object(fields) = &mut document {
+            migrate_unit(fields, "", &mut report);
+        }
+        let workflow = Self::from_json(&document.to_string())?;
+        report.document = document;
+        Ok((workflow, report))
+    }
+}
+
+/// Migrate the keys shared by workflows and tasks, then the workflow's
+/// tasks or the task's function.
+fn migrate_unit(fields: &mut Map<String, Value>, pointer: &str, report: &mut MigrationReport) {
+    if fields.get("condition") == Some(&Value::Null) {
+        fields.remove("condition");
+        report.note(
+            &format!("{pointer}/condition"),
+            "removed null condition; it defaults to true".to_string(),
+        );
+    }
+    let missing_name = fields
+        .get("name")
+        .is_none_or(|name| name.as_str().is_some_and(str::is_empty));
+    if missing_name && let Some(id) = fields.get("id").and_then(Value::as_str) {
+        let id = id.to_string();
+        report.note(
+            &format!("{pointer}/name"),
+            format!("set missing name to id '{id}'"),
+        );
+        fields.insert("name".to_string(), Value::String(id));
+    }
+    if fields.contains_key("function") {
+        migrate_function(fields, pointer, report);
+    }
+    migrate_tasks(fields.get_mut("tasks"), &format!("{pointer}/tasks"), report);
+}
+
+fn migrate_tasks(tasks: Option<&mut Value>, pointer: &str, report: &mut MigrationReport) {
+    let Some(Value::Array(tasks)) = tasks else {
+        return;
+    };
+    for (idx, task) in tasks.iter_mut().enumerate() {
+        if let Value::Object(fields) = task
+            && !fields.contains_key("include")
+        {
+            migrate_unit(fields, &format!("{pointer}/{idx}"), report);
+        }
+    }
+}
+
+fn migrate_function(task: &mut Map<String, Value>, pointer: &str, report: &mut MigrationReport) {
+    let pointer = format!("{pointer}/function");
+    if let Some(Value::String(name)) = task.get("function") {
+        let name = name.clone();
+        let input = task.remove("input").unwrap_or_else(|| json!({}));
+        task.insert(
+            "function".to_string(),
+            json!({"name": name, "input": input}),
+        );
+        report.note(
+            &pointer,
+            format!("moved function '{name}' and its input into a function object"),
+        );
+    }
+    let Some(Value::Object(function)) = task.get_mut("function") else {
+        return;
+    };
+    if !function.contains_key("input") {
+        function.insert("input".to_string(), json!({}));
+        report.note(&format!("{pointer}/input"), "added empty input".to_string());
+    }
+    let name = function
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    match name.as_str() {
+        "validate" => {
+            function.insert("name".to_string(), json!("validation"));
+            report.note(
+                &format!("{pointer}/name"),
+                "renamed function 'validate' to 'validation'".to_string(),
+            );
+        }
+        "parse"
+            if !(function["input"].get("source").is_some()
+                && function["input"].get("target").is_some()) =>
+        {
+            report.review(
+                &pointer,
+                "v1 'parse' has no direct equivalent; use parse_json with a source and target"
+                    .to_string(),
+            );
+        }
+        "http" => report.review(
+            &pointer,
+            "v1 'http' has no direct equivalent; use http_call with a registered connector"
+                .to_string(),
+        ),
+        "foreach" => {
+            let body = format!("{pointer}/input/tasks");
+            migrate_tasks(function["input"].get_mut("tasks"), &body, report);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::functions::FunctionConfig;
+
+    #[test]
+    fn upgrades_v1_shapes_and_reports_each() {
+        let (workflow, report) = Workflow::migrate_from_v1(
+            &json!({"id": "orders", "condition": null, "tasks": [
+                {"id": "check", "function": "validate", "input": {"rules": []}},
+                {"id": "each", "name": "Each", "function": {"name": "foreach", "input": {
+                    "path": "data.items", "tasks": [
+                        {"id": "tag", "name": "Tag", "condition": null, "function": {"name": "stamp"}}
+                    ]
+                }}},
+                {"id": "fetch", "name": "Fetch", "function": {"name": "http", "input": {"url": "x"}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+
+        assert_eq!(workflow.name, "orders");
+        assert!(matches!(
+            workflow.tasks[0].function,
+            FunctionConfig::Validation { .. }
+        ));
+        let pointers: Vec<(&str, bool)> = report
+            .changes
+            .iter()
+            .map(|c| (c.json_pointer.as_str(), c.needs_review))
+            .collect();
+        assert_eq!(
+            pointers,
+            [
+                ("/condition", false),
+                ("/name", false),
+                ("/tasks/0/name", false),
+                ("/tasks/0/function", false),
+                ("/tasks/0/function/name", false),
+                ("/tasks/1/function/input/tasks/0/condition", false),
+                ("/tasks/1/function/input/tasks/0/function/input", false),
+                ("/tasks/2/function", true),
+            ]
+        );
+        assert_eq!(report.needs_review().count(), 1);
+        assert_eq!(
+            report.document["tasks"][0]["function"],
+            json!({"name": "validation", "input": {"rules": []}})
+        );
+        assert!(report.document["tasks"][0].get("input").is_none());
+    }
+
+    #[test]
+    fn current_documents_are_unchanged() {
+        let json = json!({"id": "w", "name": "W", "tasks": [
+            {"id": "m", "name": "M", "function": {"name": "map", "input": {"mappings": []}}}
+        ]});
+        let (_, report) = Workflow::migrate_from_v1(&json.to_string()).unwrap();
+        assert!(report.is_unchanged());
+        assert_eq!(report.document, json);
+    }
+}
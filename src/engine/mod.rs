@@ -54,46 +54,202 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 ```
 */
 
+pub mod alert;
+pub mod audit;
+pub mod bulkhead;
+pub mod capability;
+pub mod change_tracker;
+pub mod checkpoint;
+pub mod circuit_breaker;
 pub mod compiler;
+pub mod complexity;
+pub mod construction;
+pub mod context_roots;
+pub mod coverage;
+pub mod dead_letter;
+pub mod dedup;
+pub mod describe;
+pub mod diagnostics;
+pub mod dry_run;
+pub mod embedded_tests;
 pub mod error;
 pub mod executor;
+pub mod explain;
+pub mod expression_cache;
+pub mod extensions;
+pub mod fragment;
 pub mod functions;
+pub mod hooks;
+pub mod id;
+pub mod instances;
+pub mod interpolation;
+pub mod journal;
+mod lifecycle;
+pub mod lint;
+pub mod locale;
+pub mod logic_text;
+pub mod lookup;
+pub mod manifest;
 pub mod message;
+pub mod metrics;
+pub mod migrate;
+pub mod options;
+pub mod outbox;
+pub mod parse_mode;
+pub mod pipeline;
+pub mod quarantine;
+pub mod rate_limit;
+pub mod retry;
+pub mod routing;
+pub mod rule_pack;
+pub mod secrets;
+pub mod shadow;
+pub mod shared;
+pub mod sink;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod source;
+pub mod state;
+pub mod structure;
 pub mod task;
 pub mod task_context;
 pub mod task_executor;
 pub mod task_outcome;
+mod telemetry;
 pub mod trace;
+pub mod unknown_vars;
+pub mod urgency;
 pub mod utils;
+pub mod variant;
+#[cfg(feature = "watch")]
+pub mod watch;
 pub mod workflow;
 pub mod workflow_executor;
+pub mod workflow_validation;
 
 // Re-export key types for easier access
-pub use error::{DataflowError, ErrorInfo, Result};
+pub use alert::{Alert, AlertSeverity, AlertSink, InMemoryAlertSink};
+pub use audit::AuditMismatch;
+pub use capability::Capability;
+pub use change_tracker::ChangeTracker;
+pub use checkpoint::{Checkpoint, CheckpointStore, InMemoryCheckpointStore};
+pub use circuit_breaker::{CircuitBreakerConfig, CircuitState};
+pub use complexity::{ComplexityThresholds, ExpressionComplexity, LogicComplexity};
+pub use construction::{ConstructionReport, EngineBuildError, SkippedItem, WorkflowReport};
+pub use coverage::CoverageReport;
+pub use dead_letter::{
+    DeadLetter, DeadLetterHandler, DeadLetterPolicy, DeadLetterReason, FileDeadLetterQueue,
+    InMemoryDeadLetterQueue,
+};
+pub use dedup::{DeduplicationStore, InMemoryDeduplicationStore};
+pub use describe::{TaskDescription, WorkflowDescription};
+pub use diagnostics::{RoutingDiagnostics, SkipReason, WorkflowDecision, WorkflowDiagnostic};
+pub use dry_run::{DryRunReport, HeldBackTask};
+pub use embedded_tests::{
+    WorkflowTestCase, WorkflowTestExpectations, WorkflowTestReport, WorkflowTestResult,
+};
+pub use error::{ConfigError, ConfigErrorKind, DataflowError, ErrorInfo, Result};
+pub use explain::{ExecutionPlan, TaskPlan, WorkflowPlan};
+pub use expression_cache::ExpressionCacheStats;
+pub use extensions::Extensions;
+pub use fragment::{IncludeConfig, TaskFragment};
 pub use functions::{
     AsyncFunctionHandler, BoxedFunctionHandler, CompiledCustomInput, DynAsyncFunctionHandler,
     FunctionConfig,
 };
-pub use message::Message;
+pub use hooks::{MessageHook, ProcessingHook};
+pub use id::{IdGenerator, Snowflake, UuidV7};
+pub use instances::{PendingInstance, WaitReason};
+pub use interpolation::InterpolationConfig;
+pub use journal::{InMemoryJournal, Journal, JournalEntry, JournalStatus};
+pub use lint::LintWarning;
+pub use locale::LocaleContext;
+pub use logic_text::{render_logic, render_logic_pretty};
+pub use lookup::LOOKUP_OPERATOR;
+pub use manifest::EngineManifest;
+pub use message::{Message, MessageBuilder};
+pub use metrics::{
+    EngineStats, InMemoryMetrics, LatencySummary, MetricsSink, NoopMetrics, TaskStats,
+    WorkflowStats,
+};
+pub use migrate::{MigrationChange, MigrationReport};
+pub use options::EngineOptions;
+pub use outbox::{
+    DispatchStats, InMemoryOutbox, OutboxDispatcher, OutboxIntent, OutboxRetryPolicy, OutboxSink,
+    OutboxStatus,
+};
+pub use parse_mode::ParseMode;
+pub use pipeline::{Pipeline, PipelineTrace, Stage, StageErrorPolicy, StageTrace};
+pub use quarantine::{InMemoryQuarantineSink, QuarantinePolicy, QuarantineRecord, QuarantineSink};
+pub use rate_limit::{RateLimit, RateLimits};
+pub use retry::RetryConfig;
+pub use rule_pack::RulePack;
+pub use secrets::{EnvSecretsResolver, SecretsResolver};
+pub use shadow::{ShadowDiff, ValueDiff};
+pub use shared::SharedEngine;
+pub use sink::{InMemorySink, NdjsonFileSink, SinkHandler, SinkRecord, StdoutSink, WorkflowOutput};
+#[cfg(not(target_arch = "wasm32"))]
+pub use source::{
+    Acknowledge, AfterProcessing, CronSchedule, CronSource, DirectorySource, FileFormat,
+    FileTailSource, NackReason, RestartPolicy, SourceEmitter, SourceEvent, SourceFunctionHandler,
+    SourceManager, SourceOptions, SourceRuntime, SourceStatus,
+};
+#[cfg(all(feature = "kafka", not(target_arch = "wasm32")))]
+pub use source::{
+    FailedRecordPolicy, KafkaConnector, KafkaConsumer, KafkaRecord, KafkaSource, KafkaSourceConfig,
+    OffsetReset,
+};
+#[cfg(all(feature = "http-source", not(target_arch = "wasm32")))]
+pub use source::{HttpRoute, HttpSource};
+pub use state::{InMemoryStateStore, RetryState, StateStore};
 pub use task::Task;
 pub use task_context::TaskContext;
 pub use task_outcome::TaskOutcome;
 pub use trace::{ExecutionStep, ExecutionTrace, StepResult};
-pub use workflow::{Workflow, WorkflowStatus};
+pub use urgency::ChildUrgency;
+pub use variant::WorkflowVariant;
+#[cfg(feature = "watch")]
+pub use watch::WorkflowWatcher;
+pub use workflow::{
+    Annotations, NORMALIZED_FORMAT_VERSION, PriorityTieBreak, Workflow, WorkflowStatus,
+};
+pub use workflow_validation::ValidationDiagnostic;
 
 // `EngineBuilder` is defined further down in this file but exposed here so
 // downstream paths can import it via `dataflow_rs::engine::EngineBuilder`.
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use datalogic_rs::Engine as DatalogicEngine;
 use datavalue::OwnedDataValue;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
+use bulkhead::Bulkheads;
+use capability::check_capabilities;
+use circuit_breaker::CircuitBreakers;
 use compiler::LogicCompiler;
+use dead_letter::DeadLetters;
+use dedup::validate_idempotency;
+use error::{invalid, locate};
+use executor::eval_to_owned;
+use expression_cache::ExpressionCache;
+use fragment::Fragments;
+use functions::join::{JoinConfig, JoinState};
+use hooks::ProcessingHooks;
+use lifecycle::Lifecycle;
+use lookup::LookupStore;
+use quarantine::Quarantine;
+use rate_limit::RateLimiters;
+use rule_pack::RulePacks;
+use secrets::Redactor;
+use sink::{Sinks, validate_outputs};
 use task_executor::TaskExecutor;
+use telemetry::Span;
 use utils::set_nested_value;
+use variant::VariantRouter;
 use workflow_executor::WorkflowExecutor;
+use workflow_validation::{Registry, check_workflows};
 
 /// High-performance async workflow engine for message processing.
 ///
@@ -120,13 +276,94 @@ pub struct Engine {
     channel_index: Arc<HashMap<String, Vec<usize>>>,
     /// Workflow executor for orchestrating workflow execution
     workflow_executor: Arc<WorkflowExecutor>,
-    /// Shared datalogic v5 engine for JSONLogic evaluation (Send + Sync)
+    /// Shared datalogic v5 engine for JSONLogic evaluation (Send + Sync),
+    /// built from [`EngineOptions`]. Reused by `with_new_workflows`, so the
+    /// options and custom operators carry over.
     datalogic: Arc<DatalogicEngine>,
+    /// Compiled ad-hoc expressions, for [`Engine::evaluate`] and
+    /// [`TaskContext::evaluate`]. Shared with engines produced by
+    /// `with_new_workflows`.
+    expressions: Arc<ExpressionCache>,
     /// Pre-built `Arc<OwnedDataValue::String>` of the engine version. Built
     /// once at construction; stamped into `metadata.engine_version` per
     /// message via an `Arc` refcount bump (the underlying `String` is never
     /// re-allocated for this stamp).
     engine_version: Arc<OwnedDataValue>,
+    /// Options the engine was built with. Kept so `with_new_workflows`
+    /// sorts, checks and lints a reloaded set the same way.
+    options: EngineOptions,
+    /// Poison-message detection, when configured via
+    /// [`EngineBuilder::with_quarantine`]. Shared with engines produced by
+    /// `with_new_workflows` so failure streaks survive a hot reload.
+    quarantine: Option<Arc<Quarantine>>,
+    /// Dead-letter handling, when configured via
+    /// [`EngineBuilder::with_dead_letters`]. Shared with engines produced by
+    /// `with_new_workflows`.
+    dead_letters: Option<Arc<DeadLetters>>,
+    /// Validation rule packs available to `rules_ref`. Kept so
+    /// `with_new_workflows` resolves references in a reloaded set.
+    rule_packs: RulePacks,
+    /// Task fragments available to `include`. Kept so `with_new_workflows`
+    /// resolves includes in a reloaded set.
+    fragments: Fragments,
+    /// Variant groups over `workflows`, used to pick one member of each
+    /// group per message.
+    variants: Arc<VariantRouter>,
+    /// Journal for side-effecting tasks, when configured via
+    /// [`EngineBuilder::with_journal`]. Carried over by `with_new_workflows`.
+    journal: Option<Arc<dyn Journal>>,
+    /// Outbox for `outbox: true` tasks, when configured via
+    /// [`EngineBuilder::with_outbox`]. Carried over by `with_new_workflows`.
+    outbox: Option<Arc<dyn OutboxSink>>,
+    /// Hooks run around each `process_message` call, in registration order.
+    /// Carried over by `with_new_workflows`.
+    hooks: Arc<Vec<Arc<dyn MessageHook>>>,
+    /// Hooks run around each executed workflow and task. Carried over by
+    /// `with_new_workflows`.
+    processing_hooks: ProcessingHooks,
+    /// In-flight counter and draining flag behind [`Engine::drain`]. Not
+    /// carried over by `with_new_workflows`: the new engine starts open.
+    lifecycle: Lifecycle,
+    /// Lint warnings found while compiling `workflows`.
+    lint_warnings: Arc<Vec<LintWarning>>,
+    /// Metrics sink, when configured via [`EngineBuilder::with_metrics`].
+    /// Carried over by `with_new_workflows`.
+    metrics: Option<Arc<dyn MetricsSink>>,
+    /// Lookup tables, updated in place by [`Engine::update_lookup_table`].
+    /// Shared with engines derived by `with_new_workflows`.
+    lookups: Arc<LookupStore>,
+    /// Secrets source, when configured via
+    /// [`EngineBuilder::with_secrets_resolver`]. Carried over by
+    /// `with_new_workflows`.
+    secrets: Option<Arc<dyn SecretsResolver>>,
+    /// Id source for [`Engine::message`]. Carried over by
+    /// `with_new_workflows`.
+    id_generator: Arc<dyn IdGenerator>,
+    /// Shared state, when configured via
+    /// [`EngineBuilder::with_state_store`]. Carried over by
+    /// `with_new_workflows`.
+    state_store: Option<Arc<dyn StateStore>>,
+    /// Idempotency key store, when configured via
+    /// [`EngineBuilder::with_deduplication_store`]. Carried over by
+    /// `with_new_workflows`.
+    deduplication: Option<Arc<dyn DeduplicationStore>>,
+    /// Checkpoint store, when configured via
+    /// [`EngineBuilder::with_checkpoint_store`]. Carried over by
+    /// `with_new_workflows`.
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    /// Destination of `alert` tasks, when configured via
+    /// [`EngineBuilder::with_alert_sink`]. Carried over by
+    /// `with_new_workflows`.
+    alerts: Option<Arc<dyn AlertSink>>,
+    /// Destinations of workflow `output`s, registered via
+    /// [`EngineBuilder::with_sink`]. Carried over by `with_new_workflows`.
+    sinks: Sinks,
+}
+
+/// Whether workflow `idx` lost its variant group's draw for this message.
+#[inline]
+fn is_excluded(excluded: &Option<Vec<bool>>, idx: usize) -> bool {
+    excluded.as_ref().is_some_and(|mask| mask[idx])
 }
 
 /// Build a channel index from pre-sorted workflows.
@@ -170,11 +407,103 @@ impl Engine {
         workflows: Vec<Workflow>,
         custom_functions: HashMap<String, BoxedFunctionHandler>,
     ) -> Result<Self> {
+        Self::construct(EngineBuilder {
+            workflows,
+            handlers: custom_functions,
+            ..Default::default()
+        })
+    }
+
+    /// [`Engine::new`], also returning a [`ConstructionReport`]: expression
+    /// counts and compile times per workflow, lint warnings, and workflows
+    /// and tasks that can never run. On failure, the [`EngineBuildError`]
+    /// carries the report of what compiled before the error.
+    pub fn try_new(
+        workflows: Vec<Workflow>,
+        custom_functions: HashMap<String, BoxedFunctionHandler>,
+    ) -> std::result::Result<(Self, ConstructionReport), EngineBuildError> {
+        Self::construct_with_report(EngineBuilder {
+            workflows,
+            handlers: custom_functions,
+            ..Default::default()
+        })
+    }
+
+    /// Shared body of [`Engine::new`] and [`EngineBuilder::build`].
+    fn construct(builder: EngineBuilder) -> Result<Self> {
+        Ok(Self::construct_with_report(builder)?.0)
+    }
+
+    /// Shared body of [`Engine::try_new`] and
+    /// [`EngineBuilder::build_with_report`].
+    fn construct_with_report(
+        builder: EngineBuilder,
+    ) -> std::result::Result<(Self, ConstructionReport), EngineBuildError> {
+        let mut report = ConstructionReport::default();
+        match Self::construct_into(builder, &mut report) {
+            Ok(engine) => Ok((engine, report)),
+            Err(error) => Err(EngineBuildError { error, report }),
+        }
+    }
+
+    /// Build the engine, filling in `report` as construction proceeds.
+    fn construct_into(builder: EngineBuilder, report: &mut ConstructionReport) -> Result<Self> {
+        let EngineBuilder {
+            workflows,
+            handlers: custom_functions,
+            quarantine,
+            dead_letters,
+            rule_packs,
+            duplicate_rule_packs,
+            fragments,
+            lookup_tables,
+            journal,
+            outbox,
+            hooks,
+            processing_hooks,
+            options,
+            metrics,
+            secrets,
+            id_generator,
+            state_store,
+            deduplication,
+            checkpoints,
+            alerts,
+            sinks,
+        } = builder;
+        if options.audit_signing_key.is_some() && secrets.is_none() {
+            return Err(
+                invalid("audit_signing_key is set but no secrets resolver is registered").into(),
+            );
+        }
+        if let Some(name) = duplicate_rule_packs.first() {
+            return Err(invalid(format!("rule pack '{name}' is registered more than once")).into());
+        }
+        let processing_hooks: ProcessingHooks = Arc::new(processing_hooks);
+        let rule_packs: RulePacks = Arc::new(rule_packs);
+        let fragments: Fragments = Arc::new(fragments);
+        let lookups = Arc::new(LookupStore::new(lookup_tables));
+        let sinks: Sinks = Arc::new(sinks);
+
         // Compile workflows (sorted by priority at compile time). Each
         // workflow/task/config owns its own `Arc<Logic>` slots — no central
         // cache to return. Any compile failure bubbles up immediately.
-        let compiler = LogicCompiler::new();
-        let mut sorted_workflows = compiler.compile_workflows(workflows)?;
+        let compiler = LogicCompiler::from_datalogic(Arc::new(options.build_datalogic(&lookups)))
+            .with_priority_tie_break(options.priority_tie_break)
+            .with_rule_packs(Arc::clone(&rule_packs))
+            .with_fragments(Arc::clone(&fragments))
+            .with_interpolation(options.interpolation.clone())
+            .with_secrets(secrets.clone())
+            .with_complexity_thresholds(options.complexity)
+            .with_audit_input_hashes(options.audit_input_hashes)
+            .with_readable_roots(options.readable_roots.clone());
+        let mut compiled = Vec::new();
+        let sorted_workflows = compiler.compile_workflows_reported(workflows, &mut compiled);
+        *report = ConstructionReport::compiled(compiled);
+        let mut sorted_workflows = sorted_workflows?;
+        let lint_warnings = compiler.lint(&sorted_workflows);
+        report.finish(&sorted_workflows, &lint_warnings);
+        let redactor = Arc::new(Redactor::new(compiler.take_resolved_secrets()));
         let datalogic = compiler.into_engine();
 
         let task_functions = custom_functions;
@@ -186,26 +515,81 @@ impl Engine {
         // logic. Built-in async configs (HttpCall/Enrich/PublishKafka) are
         // already typed by serde and need no second pass.
         precompile_custom_inputs(&mut sorted_workflows, &task_functions)?;
+        if options.strict_functions {
+            check_builtin_handlers(&sorted_workflows, &task_functions)?;
+        }
+        if let Some(allowed) = &options.allowed_capabilities {
+            check_capabilities(&sorted_workflows, &task_functions, allowed)?;
+        }
+        outbox::validate_outbox_tasks(&sorted_workflows, outbox.is_some())?;
+        validate_idempotency(&sorted_workflows, deduplication.is_some())?;
+        validate_outputs(&sorted_workflows, &sinks)?;
+
+        let circuit_breakers = CircuitBreakers::new(&options.circuit_breakers)?;
+        let function_limits = RateLimiters::new(&options.rate_limits.functions)?;
+        let workflow_limits = RateLimiters::new(&options.rate_limits.workflows)?;
+        let bulkheads = Bulkheads::new(&options.bulkheads)?;
+        let expressions = Arc::new(ExpressionCache::new(options.expression_cache_size));
+        let task_executor = Arc::new(
+            TaskExecutor::new(Arc::new(task_functions), Arc::clone(&datalogic))
+                .with_expressions(Arc::clone(&expressions))
+                .with_joins(Arc::new(JoinState::new(state_store.clone())))
+                .with_alerts(alerts.clone())
+                .with_circuit_breakers(Arc::new(circuit_breakers))
+                .with_rate_limits(Arc::new(function_limits))
+                .with_bulkheads(Arc::new(bulkheads)),
+        );
 
-        let task_executor = Arc::new(TaskExecutor::new(
-            Arc::new(task_functions),
-            Arc::clone(&datalogic),
-        ));
-
-        let workflow_executor =
-            Arc::new(WorkflowExecutor::new(task_executor, Arc::clone(&datalogic)));
+        let workflow_executor = Arc::new(
+            WorkflowExecutor::new(task_executor, Arc::clone(&datalogic))
+                .with_rate_limits(Arc::new(workflow_limits))
+                .with_journal(journal.clone())
+                .with_outbox(outbox.clone())
+                .with_retry(options.retry)
+                .with_state_store(state_store.clone())
+                .with_deduplication(deduplication.clone())
+                .with_checkpoints(checkpoints.clone())
+                .with_metrics(metrics.clone())
+                .with_processing_hooks(Arc::clone(&processing_hooks))
+                .with_audit_chain(options.chains_audit())
+                .with_skip_audited_tasks(options.skip_audited_tasks)
+                .with_redactor(redactor),
+        );
 
         // Build channel index for O(1) channel-based routing
         let channel_index = build_channel_index(&sorted_workflows);
+        let variants = VariantRouter::build(&sorted_workflows)?;
 
         Ok(Self {
             workflows: Arc::new(sorted_workflows),
             channel_index: Arc::new(channel_index),
             workflow_executor,
             datalogic,
+            expressions,
             engine_version: Arc::new(OwnedDataValue::String(
                 env!("CARGO_PKG_VERSION").to_string(),
             )),
+            quarantine,
+            dead_letters,
+            rule_packs,
+            fragments,
+            variants: Arc::new(variants),
+            journal,
+            outbox,
+            hooks: Arc::new(hooks),
+            processing_hooks,
+            options,
+            lifecycle: Lifecycle::default(),
+            lint_warnings: Arc::new(lint_warnings),
+            metrics,
+            lookups,
+            secrets,
+            id_generator: id_generator.unwrap_or_else(|| Arc::new(UuidV7)),
+            state_store,
+            deduplication,
+            checkpoints,
+            alerts,
+            sinks,
         })
     }
 
@@ -226,6 +610,29 @@ impl Engine {
         EngineBuilder::new()
     }
 
+    /// Start a message with an id from the engine's [`IdGenerator`]
+    /// (UUID v7 unless configured). Calling `.id(…)` on the builder still
+    /// overrides it. See the [`id`] module.
+    pub fn message(&self) -> MessageBuilder {
+        id::message_builder(self.id_generator.as_ref())
+    }
+
+    /// Start a message split or emitted from `parent`: derived from it
+    /// (see [`MessageBuilder::derived_from`]), with the priority and
+    /// deadline given by [`EngineOptions::child_urgency`] — the parent's by
+    /// default. See the [`urgency`] module.
+    pub fn child_message(&self, parent: &Message) -> Result<MessageBuilder> {
+        let (priority, deadline) = self.options.child_urgency.inherit(self, parent)?;
+        let mut builder = self.message().derived_from(parent);
+        if let Some(priority) = priority {
+            builder = builder.priority(priority);
+        }
+        if let Some(deadline) = deadline {
+            builder = builder.deadline(deadline);
+        }
+        Ok(builder)
+    }
+
     /// Cached `OwnedDataValue::String` of the engine version.
     pub fn engine_version_value(&self) -> &OwnedDataValue {
         &self.engine_version
@@ -244,34 +651,172 @@ impl Engine {
         // Extract the shared function registry from the existing executor
         let task_functions = self.workflow_executor.task_functions();
 
-        // Compile new workflows with a fresh datalogic engine instance.
-        let compiler = LogicCompiler::new();
+        // Compile new workflows with the existing datalogic engine, so
+        // evaluator options and custom operators carry over.
+        let compiler = LogicCompiler::from_datalogic(Arc::clone(&self.datalogic))
+            .with_priority_tie_break(self.options.priority_tie_break)
+            .with_rule_packs(Arc::clone(&self.rule_packs))
+            .with_fragments(Arc::clone(&self.fragments))
+            .with_interpolation(self.options.interpolation.clone())
+            .with_secrets(self.secrets.clone())
+            .with_complexity_thresholds(self.options.complexity)
+            .with_audit_input_hashes(self.options.audit_input_hashes)
+            .with_readable_roots(self.options.readable_roots.clone());
         let mut sorted_workflows = compiler.compile_workflows(workflows)?;
+        let lint_warnings = compiler.lint(&sorted_workflows);
+        // Workflows carried over keep the secret values already resolved.
+        let redactor = Arc::new(Redactor::new(
+            self.workflow_executor
+                .redactor()
+                .values()
+                .iter()
+                .cloned()
+                .chain(compiler.take_resolved_secrets()),
+        ));
         let datalogic = compiler.into_engine();
 
         // Pre-parse Custom inputs against the existing handler registry —
         // hot-reload still validates the new workflow set against the
         // already-registered handlers.
         precompile_custom_inputs(&mut sorted_workflows, &task_functions)?;
+        if self.options.strict_functions {
+            check_builtin_handlers(&sorted_workflows, &task_functions)?;
+        }
+        if let Some(allowed) = &self.options.allowed_capabilities {
+            check_capabilities(&sorted_workflows, &task_functions, allowed)?;
+        }
+        outbox::validate_outbox_tasks(&sorted_workflows, self.outbox.is_some())?;
+        validate_idempotency(&sorted_workflows, self.deduplication.is_some())?;
+        validate_outputs(&sorted_workflows, &self.sinks)?;
 
         // Rebuild the executor stack, reusing the existing function registry
-        let task_executor = Arc::new(TaskExecutor::new(task_functions, Arc::clone(&datalogic)));
+        let task_executor = Arc::new(
+            TaskExecutor::new(task_functions, Arc::clone(&datalogic))
+                .with_expressions(Arc::clone(&self.expressions))
+                .with_joins(self.workflow_executor.joins())
+                .with_alerts(self.alerts.clone())
+                .with_circuit_breakers(self.workflow_executor.circuit_breakers())
+                .with_rate_limits(self.workflow_executor.function_rate_limits())
+                .with_bulkheads(self.workflow_executor.bulkheads()),
+        );
 
-        let workflow_executor =
-            Arc::new(WorkflowExecutor::new(task_executor, Arc::clone(&datalogic)));
+        let workflow_executor = Arc::new(
+            WorkflowExecutor::new(task_executor, Arc::clone(&datalogic))
+                .with_rate_limits(self.workflow_executor.rate_limits())
+                .with_journal(self.journal.clone())
+                .with_outbox(self.outbox.clone())
+                .with_retry(self.options.retry)
+                .with_state_store(self.state_store.clone())
+                .with_deduplication(self.deduplication.clone())
+                .with_checkpoints(self.checkpoints.clone())
+                .with_metrics(self.metrics.clone())
+                .with_processing_hooks(Arc::clone(&self.processing_hooks))
+                .with_audit_chain(self.options.chains_audit())
+                .with_skip_audited_tasks(self.options.skip_audited_tasks)
+                .with_redactor(redactor),
+        );
 
         // Build channel index for O(1) channel-based routing
         let channel_index = build_channel_index(&sorted_workflows);
+        let variants = VariantRouter::build(&sorted_workflows)?;
 
         Ok(Self {
             workflows: Arc::new(sorted_workflows),
             channel_index: Arc::new(channel_index),
             workflow_executor,
             datalogic,
+            expressions: Arc::clone(&self.expressions),
             engine_version: Arc::clone(&self.engine_version),
+            quarantine: self.quarantine.clone(),
+            dead_letters: self.dead_letters.clone(),
+            rule_packs: Arc::clone(&self.rule_packs),
+            fragments: Arc::clone(&self.fragments),
+            variants: Arc::new(variants),
+            journal: self.journal.clone(),
+            outbox: self.outbox.clone(),
+            hooks: Arc::clone(&self.hooks),
+            processing_hooks: Arc::clone(&self.processing_hooks),
+            options: self.options.clone(),
+            lifecycle: Lifecycle::default(),
+            lint_warnings: Arc::new(lint_warnings),
+            metrics: self.metrics.clone(),
+            lookups: Arc::clone(&self.lookups),
+            secrets: self.secrets.clone(),
+            id_generator: Arc::clone(&self.id_generator),
+            state_store: self.state_store.clone(),
+            deduplication: self.deduplication.clone(),
+            checkpoints: self.checkpoints.clone(),
+            alerts: self.alerts.clone(),
+            sinks: Arc::clone(&self.sinks),
         })
     }
 
+    /// Stop accepting messages and wait for the ones in flight to finish.
+    ///
+    /// Once called, every `process_message*` entry point (and so
+    /// `process_batch` and `process_stream`) returns
+    /// [`DataflowError::Draining`] without touching the message. The
+    /// returned future resolves when no call is in flight; it is safe to
+    /// call from several places and resolves immediately on an idle engine.
+    /// Draining cannot be undone.
+    ///
+    /// Use it for rolling restarts: stop pulling from the source, `drain`,
+    /// then exit. After a hot reload, draining the old engine waits out
+    /// the calls still using it.
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::Engine;
+    /// # async fn demo(engine: Engine) {
+    /// engine.drain().await;
+    /// assert_eq!(engine.in_flight(), 0);
+    /// # }
+    /// ```
+    pub async fn drain(&self) {
+        self.lifecycle.drain().await;
+    }
+
+    /// Summary of the metrics recorded so far: per-workflow and per-task
+    /// counts, errors and p50/p99 latencies. `None` unless the engine was
+    /// built with a sink that keeps them, such as [`InMemoryMetrics`].
+    pub fn stats(&self) -> Option<EngineStats> {
+        self.metrics.as_ref().and_then(|sink| sink.stats())
+    }
+
+    /// Publish the in-flight gauge, less `finishing` calls about to end.
+    fn report_in_flight(&self, finishing: usize) {
+        if let Some(sink) = &self.metrics {
+            let in_flight = self.lifecycle.in_flight().saturating_sub(finishing);
+            sink.set_gauge(metrics::IN_FLIGHT, &[], in_flight as f64);
+        }
+    }
+
+    /// Report a finished message started at `started`.
+    fn record_message(&self, started: DateTime<Utc>, result: &Result<()>) {
+        let Some(sink) = &self.metrics else {
+            return;
+        };
+        sink.increment_counter(metrics::MESSAGES, &[], 1);
+        if result.is_err() {
+            sink.increment_counter(metrics::MESSAGE_ERRORS, &[], 1);
+        }
+        sink.record_histogram(
+            metrics::MESSAGE_DURATION_MS,
+            &[],
+            metrics::elapsed_ms(started),
+        );
+        self.report_in_flight(1);
+    }
+
+    /// Whether [`drain`](Self::drain) has been called.
+    pub fn is_draining(&self) -> bool {
+        self.lifecycle.is_draining()
+    }
+
+    /// Number of `process_message*` calls currently in progress.
+    pub fn in_flight(&self) -> usize {
+        self.lifecycle.in_flight()
+    }
+
     /// Processes a message through workflows that match their conditions.
     ///
     /// This async method:
@@ -301,25 +846,453 @@ impl Engine {
     /// # Returns
     /// * `Result<()>` — `Ok(())` if every workflow completed (each may have
     ///   pushed errors to `message.errors`); `Err(e)` if the engine
-    ///   stopped early on a hard failure.
+    ///   stopped early on a hard failure. With quarantine configured, the
+    ///   `Err` becomes [`DataflowError::Quarantined`] once the message has
+    ///   been diverted to the sink; with dead letters configured,
+    ///   [`DataflowError::DeadLettered`] once it was handed to the
+    ///   handler.
     pub async fn process_message(&self, message: &mut Message) -> Result<()> {
+        self.process(message, None).await
+    }
+
+    /// Body of [`process_message`](Self::process_message), recording a
+    /// routing decision per workflow in `diagnostics` when given.
+    async fn process(
+        &self,
+        message: &mut Message,
+        diagnostics: Option<&mut RoutingDiagnostics>,
+    ) -> Result<()> {
+        let _in_flight = self.lifecycle.enter()?;
+        self.report_in_flight(0);
         // Capture a single timestamp for the entire process_message call. The
         // workflow executor reads it back via Message metadata if it needs to
         // emit AuditTrail entries; this caps the number of `Utc::now()` syscalls
         // at 1 per message (down from 3+ — one stamp here, one per AuditTrail).
         let now = Utc::now();
         set_processing_metadata(&mut message.context, &self.engine_version, now, None);
+        message.lookups = Some(self.lookups.current());
+        let span = Span::message(message, None);
+        if let Err(veto) = hooks::run_before(&self.hooks, message).await {
+            if let Some(diagnostics) = diagnostics {
+                let order = self.boosted_order(message);
+                diagnostics.workflows.extend(
+                    routing::Order::new(order.as_deref(), self.workflows.len())
+                        .map(|idx| WorkflowDiagnostic::not_reached(&self.workflows[idx])),
+                );
+            }
+            let result = hooks::run_after(&self.hooks, message, Err(veto)).await;
+            let result = result.and(self.seal_audit(message));
+            self.record_message(now, &result);
+            span.end_message(message, &result);
+            return result;
+        }
+        let excluded = self.assign_variants(message);
+        let order = self.boosted_order(message);
+        let mut ran = Vec::new();
 
         // Process each workflow in priority order (pre-sorted at construction)
-        for workflow in self.workflows.iter() {
-            self.workflow_executor
-                .execute(workflow, message, now)
-                .await?;
+        let result = span
+            .in_scope(self.run_checkpointed(
+                message,
+                now,
+                &excluded,
+                order.as_deref(),
+                0,
+                0,
+                &mut ran,
+                diagnostics,
+            ))
+            .await;
+
+        let result = hooks::run_after(&self.hooks, message, result).await;
+        let result = result.and(self.seal_audit(message));
+        let result = self.send_outputs(message, &ran, result).await;
+        let result = self.observe_failure(message, result).await;
+        self.forget_checkpoint(message, &result).await;
+        self.record_message(now, &result);
+        span.end_message(message, &result);
+        result
+    }
+
+    /// Continue processing a message interrupted after `checkpoint` was
+    /// saved, e.g. by a crash. `message` takes the checkpoint's snapshot
+    /// (its in-memory settings such as the deadline and cancellation token
+    /// are kept), then runs from the task after the checkpointed one, and
+    /// on through the remaining workflows, as
+    /// [`process_message`](Self::process_message) would have. Completed
+    /// tasks are not run again. `before_message` hooks are skipped; the
+    /// rest behaves as `process_message`, including removing the
+    /// checkpoint on success. A checkpoint saved by
+    /// [`process_message_for_channel`](Self::process_message_for_channel)
+    /// continues through that channel's workflows only. See the
+    /// [`checkpoint`] module.
+    ///
+    /// Fails without touching `message` when the checkpoint belongs to
+    /// another message, or names a workflow or task the engine no longer
+    /// has.
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::{CheckpointStore, Engine, Message};
+    /// # async fn demo(engine: Engine, store: impl CheckpointStore, mut message: Message) {
+    /// if let Ok(Some(checkpoint)) = store.load(message.id()).await {
+    ///     let _ = engine.resume_message(&checkpoint, &mut message).await;
+    /// }
+    /// # }
+    /// ```
+    pub async fn resume_message(
+        &self,
+        checkpoint: &Checkpoint,
+        message: &mut Message,
+    ) -> Result<()> {
+        let _in_flight = self.lifecycle.enter()?;
+        if checkpoint.message_id() != message.id() {
+            return Err(DataflowError::Validation(format!(
+                "Checkpoint of message {} cannot resume message {}",
+                checkpoint.message_id(),
+                message.id()
+            )));
         }
+        let boosted;
+        let order = match &checkpoint.channel {
+            Some(channel) => {
+                let indices = self.channel_indices(channel);
+                boosted = self.boosted_channel_order(indices, &checkpoint.message);
+                Some(boosted.as_deref().unwrap_or(indices))
+            }
+            None => {
+                boosted = self.boosted_order(&checkpoint.message);
+                boosted.as_deref()
+            }
+        };
+        let (position, idx) = routing::Order::new(order, self.workflows.len())
+            .enumerate()
+            .find(|&(_, idx)| self.workflows[idx].id == checkpoint.workflow_id)
+            .ok_or_else(|| {
+                DataflowError::Workflow(format!(
+                    "Checkpointed workflow {} is not loaded",
+                    checkpoint.workflow_id
+                ))
+            })?;
+        let (first, start) = match &checkpoint.task_id {
+            Some(task_id) => {
+                let task = self.workflows[idx]
+                    .tasks
+                    .iter()
+                    .position(|t| t.id == *task_id)
+                    .ok_or_else(|| {
+                        DataflowError::Workflow(format!(
+                            "Checkpointed task {} is not in workflow {}",
+                            task_id, checkpoint.workflow_id
+                        ))
+                    })?;
+                (position, task + 1)
+            }
+            None => (position + 1, 0),
+        };
+        self.report_in_flight(0);
+        let now = Utc::now();
+        message.restore(&checkpoint.message);
+        message.lookups = Some(self.lookups.current());
+        let span = Span::message(message, checkpoint.channel.as_deref());
+        let excluded = self.assign_variants(message);
+        let mut ran = Vec::new();
+        message.channel = checkpoint.channel.clone();
+
+        let result = span
+            .in_scope(
+                self.run_checkpointed(message, now, &excluded, order, first, start, &mut ran, None),
+            )
+            .await;
+        message.channel = None;
+
+        let result = hooks::run_after(&self.hooks, message, result).await;
+        let result = result.and(self.seal_audit(message));
+        let result = self.send_outputs(message, &ran, result).await;
+        let result = self.observe_failure(message, result).await;
+        self.forget_checkpoint(message, &result).await;
+        self.record_message(now, &result);
+        span.end_message(message, &result);
+        result
+    }
+
+    /// Rebuild a message as it stood just before task `task_id` of
+    /// `workflow_id` ran, from the message as it arrived (`original`) and
+    /// the `audit_trail` of its earlier run, and process it again from that
+    /// task with this engine. The tasks before it are not run again; the
+    /// workflows after it run as in
+    /// [`process_message`](Self::process_message). See
+    /// [Reprocessing](audit#reprocessing).
+    ///
+    /// Fails before running anything when the trail has no entry for the
+    /// task, when its changes don't apply to `original`, or when the engine
+    /// no longer has the task. Otherwise the reprocessed message is
+    /// returned, with any processing errors in [`Message::errors`]. A task
+    /// that is the first of its workflow re-evaluates the workflow
+    /// condition and idempotency key.
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::{Engine, Message};
+    /// # async fn demo(engine: Engine, original: Message, processed: Message) {
+    /// // After fixing the `totals` mapping of the `orders` workflow:
+    /// let fixed = engine
+    ///     .reprocess_from_audit(&original, processed.audit_trail(), "orders", "totals")
+    ///     .await;
+    /// # }
+    /// ```
+    pub async fn reprocess_from_audit(
+        &self,
+        original: &Message,
+        audit_trail: &[message::AuditTrail],
+        workflow_id: &str,
+        task_id: &str,
+    ) -> Result<Message> {
+        let _in_flight = self.lifecycle.enter()?;
+        let entry = audit_trail
+            .iter()
+            .position(|e| &*e.workflow_id == workflow_id && &*e.task_id == task_id)
+            .ok_or_else(|| {
+                DataflowError::Validation(format!(
+                    "Audit trail has no entry for task {task_id} of workflow {workflow_id}"
+                ))
+            })?;
+        let mut message = audit::rewind(original, audit_trail, entry)
+            .map_err(|e| DataflowError::Validation(format!("Cannot rebuild message: {e}")))?;
+        let order = self.boosted_order(&message);
+        let (position, idx) = routing::Order::new(order.as_deref(), self.workflows.len())
+            .enumerate()
+            .find(|&(_, idx)| self.workflows[idx].id == workflow_id)
+            .ok_or_else(|| {
+                DataflowError::Workflow(format!("Workflow {workflow_id} is not loaded"))
+            })?;
+        let start = self.workflows[idx]
+            .tasks
+            .iter()
+            .position(|t| t.id == task_id)
+            .ok_or_else(|| {
+                DataflowError::Workflow(format!("Task {task_id} is not in workflow {workflow_id}"))
+            })?;
+
+        self.report_in_flight(0);
+        let now = Utc::now();
+        set_processing_metadata(&mut message.context, &self.engine_version, now, None);
+        message.lookups = Some(self.lookups.current());
+        let span = Span::message(&message, None);
+        let excluded = self.assign_variants(&mut message);
+        let mut ran = Vec::new();
+
+        let result = span
+            .in_scope(self.run_checkpointed(
+                &mut message,
+                now,
+                &excluded,
+                order.as_deref(),
+                position,
+                start,
+                &mut ran,
+                None,
+            ))
+            .await;
+
+        let result = hooks::run_after(&self.hooks, &mut message, result).await;
+        let result = result.and(self.seal_audit(&mut message));
+        let result = self.send_outputs(&mut message, &ran, result).await;
+        let result = self.observe_failure(&message, result).await;
+        self.forget_checkpoint(&message, &result).await;
+        self.record_message(now, &result);
+        span.end_message(&message, &result);
+        Ok(message)
+    }
 
+    /// Run the workflows from position `first` of the message's `order`
+    /// on, the first of them from task `start`, saving checkpoints when a
+    /// store is configured. The indices of the workflows that ran are
+    /// pushed to `ran`, and a decision per workflow to `diagnostics` when
+    /// given.
+    #[allow(clippy::too_many_arguments)]
+    async fn run_checkpointed(
+        &self,
+        message: &mut Message,
+        now: DateTime<Utc>,
+        excluded: &Option<Vec<bool>>,
+        order: Option<&[usize]>,
+        first: usize,
+        start: usize,
+        ran: &mut Vec<usize>,
+        mut diagnostics: Option<&mut RoutingDiagnostics>,
+    ) -> Result<()> {
+        let mut order = routing::Order::new(order, self.workflows.len())
+            .enumerate()
+            .skip(first);
+        while let Some((position, idx)) = order.next() {
+            let workflow = &self.workflows[idx];
+            if is_excluded(excluded, idx) {
+                if let Some(diagnostics) = diagnostics.as_deref_mut() {
+                    diagnostics
+                        .workflows
+                        .push(self.variant_not_selected(workflow, message));
+                }
+                continue;
+            }
+            let start = if position == first { start } else { 0 };
+            let executed =
+                match diagnostics.as_deref_mut() {
+                    Some(diagnostics) => {
+                        let executed = self
+                            .run_diagnosed(workflow, message, now, start, diagnostics)
+                            .await;
+                        if executed.is_err() {
+                            diagnostics.workflows.extend(order.by_ref().map(|(_, idx)| {
+                                WorkflowDiagnostic::not_reached(&self.workflows[idx])
+                            }));
+                        }
+                        executed
+                    }
+                    None => {
+                        self.workflow_executor
+                            .execute_checkpointed(workflow, message, now, start)
+                            .await
+                    }
+                };
+            if executed? {
+                ran.push(idx);
+            }
+        }
         Ok(())
     }
 
+    /// Run `workflow` as [`run_checkpointed`](Self::run_checkpointed)
+    /// does, recording what its condition evaluated to and what happened.
+    /// The condition is evaluated one extra time to capture its value.
+    async fn run_diagnosed(
+        &self,
+        workflow: &Workflow,
+        message: &mut Message,
+        now: DateTime<Utc>,
+        start: usize,
+        diagnostics: &mut RoutingDiagnostics,
+    ) -> Result<bool> {
+        let mut entry = WorkflowDiagnostic::not_reached(workflow);
+        workflow_executor::stamp_annotations(message, workflow.compiled_annotations.as_ref());
+        workflow_executor::stamp_locale(message, workflow.compiled_locale.as_ref());
+        let condition = workflow
+            .compiled_condition
+            .as_ref()
+            .map(|logic| executor::eval_to_owned(&self.datalogic, logic, &message.context));
+        let condition_error = match condition {
+            Some(Ok(value)) => {
+                entry.condition_value = Some(value);
+                None
+            }
+            Some(Err(e)) => Some(e.to_string()),
+            None => None,
+        };
+
+        let errors_before = message.errors.len();
+        let result = self
+            .workflow_executor
+            .execute_checkpointed(workflow, message, now, start)
+            .await;
+        entry.decision = match &result {
+            Ok(true) => WorkflowDecision::Executed {
+                errors: message.errors.len() - errors_before,
+            },
+            Ok(false) => WorkflowDecision::Skipped(match condition_error {
+                Some(error) => SkipReason::ConditionError { error },
+                None => SkipReason::ConditionFalse,
+            }),
+            Err(e) => WorkflowDecision::Failed {
+                error: e.to_string(),
+            },
+        };
+        diagnostics.workflows.push(entry);
+        result
+    }
+
+    /// Diagnostic entry for a workflow left out because another member of
+    /// its variant group was selected.
+    fn variant_not_selected(&self, workflow: &Workflow, message: &Message) -> WorkflowDiagnostic {
+        let group = workflow
+            .variant
+            .as_ref()
+            .map(|v| v.group.clone())
+            .unwrap_or_default();
+        let selected = message
+            .context
+            .get("metadata")
+            .and_then(|m| m.get("variants"))
+            .and_then(|v| v.get(group.as_str()))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        WorkflowDiagnostic {
+            decision: WorkflowDecision::Skipped(SkipReason::VariantNotSelected { group, selected }),
+            ..WorkflowDiagnostic::not_reached(workflow)
+        }
+    }
+
+    /// The message's workflow order when it carries a priority boost;
+    /// `None` for engine order. See [`routing`].
+    fn boosted_order(&self, message: &Message) -> Option<Vec<usize>> {
+        routing::boosted_order(
+            &self.workflows,
+            0..self.workflows.len(),
+            message,
+            self.options.priority_tie_break,
+        )
+    }
+
+    /// Indices of the workflows registered for `channel`, in engine order.
+    fn channel_indices(&self, channel: &str) -> &[usize] {
+        self.channel_index.get(channel).map_or(&[], Vec::as_slice)
+    }
+
+    /// [`boosted_order`](Self::boosted_order) of a channel's workflows.
+    fn boosted_channel_order(&self, indices: &[usize], message: &Message) -> Option<Vec<usize>> {
+        routing::boosted_order(
+            &self.workflows,
+            indices.iter().copied(),
+            message,
+            self.options.priority_tie_break,
+        )
+    }
+
+    /// Drop the checkpoint of a message processed without error.
+    async fn forget_checkpoint(&self, message: &Message, result: &Result<()>) {
+        if result.is_ok()
+            && let Some(store) = &self.checkpoints
+            && let Err(e) = store.remove(message.id()).await
+        {
+            log::warn!(
+                "Failed to remove checkpoint of message {}: {}",
+                message.id(),
+                e
+            );
+        }
+    }
+
+    /// [`process_message`](Self::process_message) with a cancellation
+    /// token, for aborting in-flight messages on shutdown or client
+    /// disconnect. Once `token` is cancelled the engine stops before the next
+    /// workflow or task, records a `CANCELLED` error, and returns
+    /// [`DataflowError::Cancelled`]; `continue_on_error` does not apply.
+    /// Handlers see the token through
+    /// [`TaskContext::cancellation`](crate::TaskContext::cancellation).
+    ///
+    /// The token is attached for this call only. To cancel messages going
+    /// through [`process_batch`](Self::process_batch) or another entry
+    /// point, set it on the message with
+    /// [`Message::set_cancellation`] instead.
+    pub async fn process_message_cancellable(
+        &self,
+        message: &mut Message,
+        token: CancellationToken,
+    ) -> Result<()> {
+        let previous = message.cancellation.replace(token);
+        let result = self.process_message(message).await;
+        message.cancellation = previous;
+        result
+    }
+
     /// Processes a message through workflows with step-by-step tracing.
     ///
     /// This method is similar to `process_message` but captures an execution trace
@@ -334,27 +1307,76 @@ impl Engine {
         &self,
         message: &mut Message,
     ) -> Result<ExecutionTrace> {
-        use trace::ExecutionTrace;
+        let (trace, result) = self.trace_message(message).await;
+        result.map(|()| trace)
+    }
 
+    /// Body of [`Self::process_message_with_trace`], keeping the steps
+    /// recorded before a failure.
+    pub(crate) async fn trace_message(
+        &self,
+        message: &mut Message,
+    ) -> (ExecutionTrace, Result<()>) {
+        let mut trace = ExecutionTrace::new();
+        let _in_flight = match self.lifecycle.enter() {
+            Ok(guard) => guard,
+            Err(e) => return (trace, Err(e)),
+        };
         let now = Utc::now();
         set_processing_metadata(&mut message.context, &self.engine_version, now, None);
+        message.lookups = Some(self.lookups.current());
 
-        let mut trace = ExecutionTrace::new();
+        let excluded = self.assign_variants(message);
 
         // Process each workflow in priority order (pre-sorted at construction)
-        for workflow in self.workflows.iter() {
-            self.workflow_executor
+        let order = self.boosted_order(message);
+        for idx in routing::Order::new(order.as_deref(), self.workflows.len()) {
+            let workflow = &self.workflows[idx];
+            if is_excluded(&excluded, idx) {
+                trace.add_step(ExecutionStep::workflow_skipped(&workflow.id));
+                continue;
+            }
+            if let Err(e) = self
+                .workflow_executor
                 .execute_with_trace(workflow, message, &mut trace, now)
-                .await?;
+                .await
+            {
+                return (trace, Err(e));
+            }
         }
 
-        Ok(trace)
+        (trace, Ok(()))
+    }
+
+    /// Processes a message through the same pipeline as
+    /// [`Engine::process_message`] — hooks, checkpoints, sinks, quarantine,
+    /// dead letters and metrics included — and records, for every
+    /// workflow, what its condition evaluated to and whether it ran, was
+    /// skipped (and why), or was never reached. See [`RoutingDiagnostics`].
+    ///
+    /// The processing error, if any, is returned in
+    /// [`RoutingDiagnostics::error`] so the diagnostics survive a failed
+    /// run. A message vetoed by a `before_message` hook reaches no
+    /// workflow. Each workflow condition is evaluated one extra time to
+    /// capture its value, so this is meant for investigation rather than
+    /// every message.
+    pub async fn process_message_with_diagnostics(
+        &self,
+        message: &mut Message,
+    ) -> RoutingDiagnostics {
+        let mut diagnostics = RoutingDiagnostics::default();
+        let error = self.process(message, Some(&mut diagnostics)).await.err();
+        diagnostics.error = error;
+        diagnostics
     }
 
     /// Processes a message through only the Active workflows registered for a given channel.
     ///
     /// Workflows are processed in priority order (lowest first), same as process_message().
     /// If the channel does not exist or has no Active workflows, this is a no-op.
+    /// Checkpoints are saved and removed as in `process_message`, and
+    /// [`resume_message`](Self::resume_message) continues them on the same
+    /// channel.
     ///
     /// # Arguments
     /// * `channel` - The channel name to route the message through
@@ -364,6 +1386,8 @@ impl Engine {
         channel: &str,
         message: &mut Message,
     ) -> Result<()> {
+        let _in_flight = self.lifecycle.enter()?;
+        self.report_in_flight(0);
         let now = Utc::now();
         set_processing_metadata(
             &mut message.context,
@@ -371,16 +1395,44 @@ impl Engine {
             now,
             Some(channel),
         );
-
-        if let Some(indices) = self.channel_index.get(channel) {
-            for &idx in indices {
-                self.workflow_executor
-                    .execute(&self.workflows[idx], message, now)
-                    .await?;
-            }
+        message.lookups = Some(self.lookups.current());
+        let span = Span::message(message, Some(channel));
+        if let Err(veto) = hooks::run_before(&self.hooks, message).await {
+            let result = hooks::run_after(&self.hooks, message, Err(veto)).await;
+            let result = result.and(self.seal_audit(message));
+            self.record_message(now, &result);
+            span.end_message(message, &result);
+            return result;
         }
-
-        Ok(())
+        let excluded = self.assign_variants(message);
+        let mut ran = Vec::new();
+
+        let indices = self.channel_indices(channel);
+        let order = self.boosted_channel_order(indices, message);
+        message.channel = Some(channel.to_string());
+
+        let result = span
+            .in_scope(self.run_checkpointed(
+                message,
+                now,
+                &excluded,
+                Some(order.as_deref().unwrap_or(indices)),
+                0,
+                0,
+                &mut ran,
+                None,
+            ))
+            .await;
+        message.channel = None;
+
+        let result = hooks::run_after(&self.hooks, message, result).await;
+        let result = result.and(self.seal_audit(message));
+        let result = self.send_outputs(message, &ran, result).await;
+        let result = self.observe_failure(message, result).await;
+        self.forget_checkpoint(message, &result).await;
+        self.record_message(now, &result);
+        span.end_message(message, &result);
+        result
     }
 
     /// Processes a message through a channel with step-by-step tracing.
@@ -395,6 +1447,7 @@ impl Engine {
     ) -> Result<ExecutionTrace> {
         use trace::ExecutionTrace;
 
+        let _in_flight = self.lifecycle.enter()?;
         let now = Utc::now();
         set_processing_metadata(
             &mut message.context,
@@ -402,11 +1455,18 @@ impl Engine {
             now,
             Some(channel),
         );
+        message.lookups = Some(self.lookups.current());
 
+        let excluded = self.assign_variants(message);
         let mut trace = ExecutionTrace::new();
 
         if let Some(indices) = self.channel_index.get(channel) {
-            for &idx in indices {
+            let order = self.boosted_channel_order(indices, message);
+            for &idx in order.as_deref().unwrap_or(indices) {
+                if is_excluded(&excluded, idx) {
+                    trace.add_step(ExecutionStep::workflow_skipped(&self.workflows[idx].id));
+                    continue;
+                }
                 self.workflow_executor
                     .execute_with_trace(&self.workflows[idx], message, &mut trace, now)
                     .await?;
@@ -416,6 +1476,614 @@ impl Engine {
         Ok(trace)
     }
 
+    /// Process `messages` with up to `concurrency` of them in flight at
+    /// once, returning one result per message in input order.
+    ///
+    /// Messages run concurrently on the calling task (no spawning), so the
+    /// batch shares the engine by reference and works on any runtime. A
+    /// `concurrency` of 0 is treated as 1. Each message goes through the
+    /// full [`process_message`](Self::process_message) path; a failed
+    /// message yields its error without affecting the rest of the batch.
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::{Engine, Message};
+    /// # async fn demo(engine: Engine, inputs: Vec<Message>) {
+    /// for result in engine.process_batch(inputs, 16).await {
+    ///     match result {
+    ///         Ok(message) => println!("processed {}", message.id()),
+    ///         Err(e) => eprintln!("failed: {e}"),
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub async fn process_batch(
+        &self,
+        messages: Vec<Message>,
+        concurrency: usize,
+    ) -> Vec<Result<Message>> {
+        use futures_util::StreamExt;
+
+        self.process_stream(futures_util::stream::iter(messages), concurrency, true)
+            .collect()
+            .await
+    }
+
+    /// Pipe a stream of messages through the engine, yielding each result
+    /// as it completes. At most `concurrency` messages are in flight (0 is
+    /// treated as 1), and the input is pulled only as capacity frees up, so
+    /// an unbounded source (a Kafka consumer, an HTTP body) is never
+    /// buffered in full.
+    ///
+    /// With `ordered`, results come out in input order; a slow message then
+    /// holds back the ones behind it. Without it, results come out as soon
+    /// as they finish. Like [`process_batch`](Self::process_batch),
+    /// messages run concurrently on the polling task.
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::{Engine, Message};
+    /// # use futures_util::{Stream, StreamExt};
+    /// # async fn demo(engine: Engine, incoming: impl Stream<Item = Message>) {
+    /// let results = engine.process_stream(incoming, 32, false);
+    /// futures_util::pin_mut!(results);
+    /// while let Some(result) = results.next().await {
+    ///     // ack / publish / dead-letter
+    /// #   let _ = result;
+    /// }
+    /// # }
+    /// ```
+    pub fn process_stream<'a, S>(
+        &'a self,
+        messages: S,
+        concurrency: usize,
+        ordered: bool,
+    ) -> impl futures_util::Stream<Item = Result<Message>> + 'a
+    where
+        S: futures_util::Stream<Item = Message> + 'a,
+    {
+        use futures_util::StreamExt;
+        use futures_util::future::Either;
+
+        let concurrency = concurrency.max(1);
+        let results = messages.map(move |mut message| async move {
+            self.process_message(&mut message).await.map(|()| message)
+        });
+        if ordered {
+            Either::Left(results.buffered(concurrency))
+        } else {
+            Either::Right(results.buffer_unordered(concurrency))
+        }
+    }
+
+    /// Process a copy of `message` through this engine and dry-run another
+    /// through `shadow` concurrently, and return both results with a
+    /// structural diff of their `data` and recorded errors.
+    ///
+    /// `message` itself is left untouched; act on [`ShadowDiff::primary`].
+    /// This engine runs its full `process_message` path. The shadow runs
+    /// as [`dry_run`](Self::dry_run), so it has no side effects: tasks that
+    /// may have any are held back and listed in
+    /// [`ShadowDiff::shadow_held_back`]. See [`shadow`].
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::{Engine, Message};
+    /// # async fn demo(live: Engine, candidate: Engine, input: Message) {
+    /// let diff = live.process_shadow(&input, &candidate).await;
+    /// if !diff.is_identical() {
+    ///     log::warn!("candidate diverged on {}: {:?}", input.id(), diff.data);
+    /// }
+    /// let output = diff.primary;
+    /// # }
+    /// ```
+    pub async fn process_shadow(&self, message: &Message, shadow: &Engine) -> ShadowDiff {
+        let mut primary_msg = message.clone();
+        let (primary_result, shadow_report) = tokio::join!(
+            self.process_message(&mut primary_msg),
+            shadow.dry_run(message)
+        );
+        ShadowDiff::new(primary_msg, primary_result.err(), shadow_report)
+    }
+
+    /// Preview what processing `message` would do without changing it or
+    /// anything outside the engine: the changes each task would make, the
+    /// errors it would record, and the tasks held back because they may
+    /// have side effects. See [`dry_run`].
+    ///
+    /// ```
+    /// # use dataflow_rs::{Engine, Message, Workflow};
+    /// # use serde_json::json;
+    /// # async fn demo() -> dataflow_rs::Result<()> {
+    /// let workflow = Workflow::from_json(r#"{"id": "w", "name": "W", "tasks": [
+    ///     {"id": "m", "name": "M", "function": {"name": "map", "input": {
+    ///         "mappings": [{"path": "data.total", "logic": 42}]
+    ///     }}}
+    /// ]}"#)?;
+    /// let engine = Engine::builder().with_workflow(workflow).build()?;
+    /// let message = Message::from_value(&json!({}));
+    /// let report = engine.dry_run(&message).await;
+    /// assert_eq!(report.changes[0].changes[0].path.as_ref(), "data.total");
+    /// assert!(message.audit_trail().is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn dry_run(&self, message: &Message) -> DryRunReport {
+        let mut message = message.clone();
+        message.capture_changes = true;
+        message.held_back = Some(Vec::new());
+        let audit_start = message.audit_trail.len();
+        let now = Utc::now();
+        set_processing_metadata(&mut message.context, &self.engine_version, now, None);
+        message.lookups = Some(self.lookups.current());
+        let excluded = self.assign_variants(&mut message);
+
+        let mut error = None;
+        let order = self.boosted_order(&message);
+        for idx in routing::Order::new(order.as_deref(), self.workflows.len()) {
+            let workflow = &self.workflows[idx];
+            if is_excluded(&excluded, idx) {
+                continue;
+            }
+            if let Err(e) = self
+                .workflow_executor
+                .execute(workflow, &mut message, now)
+                .await
+            {
+                error = Some(e);
+                break;
+            }
+        }
+        DryRunReport::new(message, audit_start, error)
+    }
+
+    /// Report which workflows and tasks would run for `message`, with the
+    /// value of each condition, without running anything or changing the
+    /// message. Conditions see the message as given, not the writes of
+    /// earlier tasks. See [`explain`].
+    ///
+    /// ```
+    /// # use dataflow_rs::{Engine, Message, Workflow};
+    /// # use serde_json::json;
+    /// # fn demo() -> dataflow_rs::Result<()> {
+    /// let workflow = Workflow::from_json(r#"{"id": "eu", "name": "EU",
+    ///     "condition": {"==": [{"var": "data.region"}, "eu"]},
+    ///     "tasks": [{"id": "m", "name": "M", "function": {"name": "map", "input": {
+    ///         "mappings": [{"path": "data.vat", "logic": true}]
+    ///     }}}]}"#)?;
+    /// let engine = Engine::builder().with_workflow(workflow).build()?;
+    /// let plan = engine.explain(&Message::builder().data_json(&json!({"region": "us"})).build());
+    /// assert!(!plan.workflow("eu").unwrap().runs());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn explain(&self, message: &Message) -> ExecutionPlan {
+        let mut message = message.clone();
+        set_processing_metadata(&mut message.context, &self.engine_version, Utc::now(), None);
+        message.lookups = Some(self.lookups.current());
+        let excluded = self.assign_variants(&mut message);
+
+        let mut plan = ExecutionPlan::default();
+        let order = self.boosted_order(&message);
+        for idx in routing::Order::new(order.as_deref(), self.workflows.len()) {
+            let workflow = &self.workflows[idx];
+            if is_excluded(&excluded, idx) {
+                let selected = workflow
+                    .variant
+                    .as_ref()
+                    .and_then(|v| {
+                        message
+                            .context
+                            .get("metadata")
+                            .and_then(|m| m.get("variants"))
+                            .and_then(|variants| variants.get(v.group.as_str()))
+                    })
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string();
+                plan.workflows
+                    .push(explain::variant_not_selected(workflow, selected));
+                continue;
+            }
+            workflow_executor::stamp_annotations(
+                &mut message,
+                workflow.compiled_annotations.as_ref(),
+            );
+            workflow_executor::stamp_locale(&mut message, workflow.compiled_locale.as_ref());
+            let entry = lookup::pinned_sync(message.lookups.clone(), || {
+                explain::plan_workflow(&self.datalogic, workflow, &message)
+            });
+            plan.workflows.push(entry);
+        }
+        plan
+    }
+
+    /// Run every sample message through the workflows and report which
+    /// validation rules failed and which map mappings wrote a value, with
+    /// per-rule and per-mapping hit counts. See [`CoverageReport`].
+    ///
+    /// Samples are processed on the tracing path and are not fed to the
+    /// quarantine tracker. They run as in [`dry_run`](Self::dry_run): tasks
+    /// that may have side effects are held back, counted in
+    /// `held_back` and reported as not executed. A sample whose run errors
+    /// is counted in [`CoverageReport::failed_messages`]; the steps it
+    /// reached still count.
+    pub async fn coverage<I>(&self, samples: I) -> CoverageReport
+    where
+        I: IntoIterator<Item = Message>,
+    {
+        let mut report = CoverageReport::new(&self.workflows);
+        for mut message in samples {
+            let now = Utc::now();
+            message.held_back = Some(Vec::new());
+            set_processing_metadata(&mut message.context, &self.engine_version, now, None);
+            message.lookups = Some(self.lookups.current());
+            let excluded = self.assign_variants(&mut message);
+
+            let mut trace = trace::ExecutionTrace::new();
+            let mut failed = false;
+            let order = self.boosted_order(&message);
+            for idx in routing::Order::new(order.as_deref(), self.workflows.len()) {
+                let workflow = &self.workflows[idx];
+                if is_excluded(&excluded, idx) {
+                    trace.add_step(ExecutionStep::workflow_skipped(&workflow.id));
+                    continue;
+                }
+                if self
+                    .workflow_executor
+                    .execute_with_trace(workflow, &mut message, &mut trace, now)
+                    .await
+                    .is_err()
+                {
+                    failed = true;
+                    break;
+                }
+            }
+            let held_back = message.held_back.take().unwrap_or_default();
+            report.record(&self.workflows, &trace, &held_back, failed, &self.datalogic);
+        }
+        report
+    }
+
+    /// Run the test cases embedded in the workflows (their `tests`), each
+    /// through its own workflow only, and report which passed. See
+    /// [`embedded_tests`].
+    ///
+    /// Cases run as in [`dry_run`](Self::dry_run): tasks that may have
+    /// side effects are held back, and no idempotency key, outbox intent,
+    /// journal entry, rate-limit permit or metric is recorded, so a test
+    /// run leaves production state alone.
+    pub async fn run_workflow_tests(&self) -> WorkflowTestReport {
+        let mut report = WorkflowTestReport::default();
+        for workflow in self.workflows.iter() {
+            for case in &workflow.tests {
+                let failures = match case.message() {
+                    Ok(mut message) => {
+                        let now = Utc::now();
+                        message.held_back = Some(Vec::new());
+                        set_processing_metadata(
+                            &mut message.context,
+                            &self.engine_version,
+                            now,
+                            None,
+                        );
+                        message.lookups = Some(self.lookups.current());
+                        // Errors are recorded on the message and checked there.
+                        let _ = self
+                            .workflow_executor
+                            .execute(workflow, &mut message, now)
+                            .await;
+                        case.check(&message)
+                    }
+                    Err(e) => vec![format!("invalid test input: {}", e)],
+                };
+                report.results.push(WorkflowTestResult {
+                    workflow_id: workflow.id.clone(),
+                    test: case.name.clone(),
+                    failures,
+                });
+            }
+        }
+        report
+    }
+
+    /// Pick the active member of each variant group for `message`. See
+    /// [`variant`].
+    fn assign_variants(&self, message: &mut Message) -> Option<Vec<bool>> {
+        lookup::pinned_sync(message.lookups.clone(), || {
+            self.variants
+                .assign(&self.datalogic, &self.workflows, message)
+        })
+    }
+
+    /// Deliver one outbox intent by running its task against the intent's
+    /// message snapshot. Used by [`OutboxDispatcher`].
+    pub(crate) async fn deliver_outbox_intent(&self, intent: &OutboxIntent) -> Result<()> {
+        outbox::deliver(
+            &self.workflows,
+            self.workflow_executor.task_executor(),
+            intent,
+        )
+        .await
+    }
+
+    /// Send a message processed without error to the `output`s of the
+    /// workflows at `ran`. See [`sink`].
+    async fn send_outputs(
+        &self,
+        message: &mut Message,
+        ran: &[usize],
+        result: Result<()>,
+    ) -> Result<()> {
+        if result.is_err() || self.sinks.is_empty() {
+            return result;
+        }
+        sink::dispatch(&self.sinks, &self.workflows, ran, message).await
+    }
+
+    /// Feed a processing result into the quarantine tracker, then the
+    /// dead-letter handler, if configured. Trace runs are debugging aids and
+    /// deliberately bypass this.
+    async fn observe_failure(&self, message: &Message, result: Result<()>) -> Result<()> {
+        let result = match &self.quarantine {
+            Some(quarantine) => quarantine.observe(message, result).await,
+            None => result,
+        };
+        match &self.dead_letters {
+            Some(dead_letters) => dead_letters.observe(message, result).await,
+            None => result,
+        }
+    }
+
+    /// Sign the message's audit chain, when a signing key is configured.
+    /// A key that can't be resolved leaves the message unsigned with an
+    /// `AUDIT_SIGNING_ERROR`.
+    fn seal_audit(&self, message: &mut Message) -> Result<()> {
+        let Some(key) = self.audit_signing_key() else {
+            return Ok(());
+        };
+        match key {
+            Ok(key) => {
+                message.audit_signature = Some(audit::sign(key.as_bytes(), message));
+                Ok(())
+            }
+            Err(e) => {
+                message.audit_signature = None;
+                message.errors.push(
+                    ErrorInfo::builder(
+                        "AUDIT_SIGNING_ERROR",
+                        format!("Failed to sign audit trail: {}", e),
+                    )
+                    .build(),
+                );
+                Err(e)
+            }
+        }
+    }
+
+    /// The resolved audit signing key, if one is configured.
+    fn audit_signing_key(&self) -> Option<Result<String>> {
+        let name = self.options.audit_signing_key.as_deref()?;
+        let secrets = self.secrets.as_ref()?;
+        Some(secrets.resolve(name))
+    }
+
+    /// Check `message`'s audit trail for tampering: every entry must link
+    /// to the one before and, with a signing key configured, the signature
+    /// must match. A trail that was never chained or signed does not
+    /// verify. Errors only when the signing key can't be resolved. See
+    /// [`audit`].
+    pub fn verify_audit(&self, message: &Message) -> Result<bool> {
+        match self.audit_signing_key() {
+            Some(key) => Ok(audit::verify_signature(key?.as_bytes(), message)),
+            None => Ok(audit::verify_chain(message)),
+        }
+    }
+
+    /// Open `join` groups and persisted retries the engine is holding,
+    /// with their correlation keys, ages and what they wait for. Fails
+    /// when the state store cannot list its keys. See [`instances`].
+    pub async fn pending_instances(&self) -> Result<Vec<PendingInstance>> {
+        let joins = self.workflow_executor.joins();
+        let mut pending = Vec::new();
+        for workflow in self.workflows.iter() {
+            for task in &workflow.tasks {
+                if let FunctionConfig::Join { input, .. } = &task.function {
+                    pending.extend(input.pending(&joins, &workflow.id, &task.id).await?);
+                }
+            }
+        }
+        if let Some(store) = &self.state_store {
+            pending.extend(instances::retries(store.as_ref(), &self.workflows).await?);
+        }
+        Ok(pending)
+    }
+
+    /// Complete a pending `join` group now, with the parts it holds:
+    /// `message` gets them as the message completing the group would, then
+    /// processing continues with the task after the join, as
+    /// [`resume_message`](Self::resume_message) would. Fails without
+    /// touching `message` for retries, and when the group is no longer
+    /// open or its task is gone.
+    pub async fn force_complete(
+        &self,
+        instance: &PendingInstance,
+        message: &mut Message,
+    ) -> Result<()> {
+        if !matches!(instance.reason, WaitReason::Join { .. }) {
+            return Err(DataflowError::Validation(format!(
+                "Only join groups can be completed; expire the retry of message {} instead",
+                instance.key
+            )));
+        }
+        let config = self.join_config(&instance.workflow_id, &instance.task_id)?;
+        config
+            .force_complete(message, &self.workflow_executor.joins(), &instance.key)
+            .await?;
+        let checkpoint = Checkpoint {
+            workflow_id: instance.workflow_id.clone(),
+            task_id: Some(instance.task_id.clone()),
+            channel: None,
+            message: message.clone(),
+            created_at: Utc::now(),
+        };
+        self.resume_message(&checkpoint, message).await
+    }
+
+    /// Drop a pending instance: a `join` group is discarded with its
+    /// parts, and a retry is forgotten so a redelivered message starts a
+    /// fresh retry budget. Dropping an instance that is gone is not an
+    /// error.
+    pub async fn force_expire(&self, instance: &PendingInstance) -> Result<()> {
+        let key = instance.store_key();
+        match instance.reason {
+            WaitReason::Join { .. } => self.workflow_executor.joins().discard(&key).await,
+            WaitReason::Retry { .. } => match &self.state_store {
+                Some(store) => store.delete(&key).await,
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Complete every `join` group whose `timeout_ms` has passed, instead
+    /// of waiting for a message that may never arrive. Each group becomes a
+    /// new message holding its parts, as the message completing it would,
+    /// which then continues with the task after the join as
+    /// [`force_complete`](Self::force_complete) does. Returns those
+    /// messages; one whose processing failed carries the errors and has
+    /// been through the dead-letter handler. Fails when the state store
+    /// cannot list its keys or a group can't be read.
+    ///
+    /// Call it periodically. Groups of tasks without a timeout are left
+    /// alone.
+    pub async fn expire_joins(&self) -> Result<Vec<Message>> {
+        let joins = self.workflow_executor.joins();
+        let now = Utc::now();
+        let mut combined = Vec::new();
+        for workflow in self.workflows.iter() {
+            for task in &workflow.tasks {
+                let FunctionConfig::Join { input, .. } = &task.function else {
+                    continue;
+                };
+                if input.timeout_ms.is_none() {
+                    continue;
+                }
+                for instance in input.pending(&joins, &workflow.id, &task.id).await? {
+                    let mut message = self.message().build();
+                    if input
+                        .expire(&mut message, &joins, &instance.key, now)
+                        .await?
+                        .is_none()
+                    {
+                        continue;
+                    }
+                    let checkpoint = Checkpoint {
+                        workflow_id: workflow.id.clone(),
+                        task_id: Some(task.id.clone()),
+                        channel: None,
+                        message: message.clone(),
+                        created_at: now,
+                    };
+                    if let Err(e) = self.resume_message(&checkpoint, &mut message).await {
+                        log::debug!(
+                            "Expired join group {} of task {} failed: {}",
+                            instance.key,
+                            task.id,
+                            e
+                        );
+                    }
+                    combined.push(message);
+                }
+            }
+        }
+        Ok(combined)
+    }
+
+    /// The config of join task `task_id` of `workflow_id`.
+    fn join_config(&self, workflow_id: &str, task_id: &str) -> Result<&JoinConfig> {
+        self.workflow_by_id(workflow_id)
+            .and_then(|workflow| workflow.tasks.iter().find(|t| t.id == task_id))
+            .and_then(|task| match &task.function {
+                FunctionConfig::Join { input, .. } => Some(input),
+                _ => None,
+            })
+            .ok_or_else(|| {
+                DataflowError::Workflow(format!(
+                    "Join task {task_id} is not in workflow {workflow_id}"
+                ))
+            })
+    }
+
+    /// Options this engine was built with.
+    pub fn options(&self) -> &EngineOptions {
+        &self.options
+    }
+
+    /// Replace (or add) lookup table `name` without rebuilding the engine.
+    /// Messages already in flight keep the tables they started with;
+    /// messages started afterwards see the new one. Returns the new table
+    /// generation. See [`lookup`] for the operator.
+    pub fn update_lookup_table(&self, name: &str, table: Value) -> u64 {
+        self.lookups
+            .publish(name, Some(OwnedDataValue::from(table)))
+    }
+
+    /// Remove lookup table `name`, with the same in-flight guarantee as
+    /// [`Engine::update_lookup_table`]. Returns the new table generation.
+    pub fn remove_lookup_table(&self, name: &str) -> u64 {
+        self.lookups.publish(name, None)
+    }
+
+    /// Current lookup table generation: 0 at build, incremented by every
+    /// update or removal.
+    pub fn lookup_generation(&self) -> u64 {
+        self.lookups.current().number
+    }
+
+    /// Lint warnings found while building this engine, e.g. overly complex
+    /// expressions. Each was also logged at `warn` level. See
+    /// [`LintWarning`].
+    pub fn lint_warnings(&self) -> &[LintWarning] {
+        &self.lint_warnings
+    }
+
+    /// Check `workflows` against this engine — its functions, rule packs,
+    /// fragments and JSONLogic operators — and report every problem found
+    /// instead of failing on the first. An empty result means an engine
+    /// built from them would not reject them for these reasons. See
+    /// [`workflow_validation`](crate::engine::workflow_validation).
+    pub fn validate_workflows(&self, workflows: &[Workflow]) -> Vec<ValidationDiagnostic> {
+        let handlers = self.workflow_executor.task_functions();
+        let registry = Registry {
+            handlers: &handlers,
+            rule_packs: &self.rule_packs,
+            fragments: &self.fragments,
+        };
+        check_workflows(workflows, &self.datalogic, Some(&registry))
+    }
+
+    /// Lint `workflows` with this engine's complexity thresholds and
+    /// priority tie-break, without building an engine from them. Unlike
+    /// [`Engine::lint_warnings`], this also reports problems that would
+    /// fail construction, such as empty workflows and reused task ids, so
+    /// a CI gate sees every finding at once. See
+    /// [`structure`](crate::engine::structure).
+    pub fn lint_workflows(&self, workflows: &[Workflow]) -> Vec<LintWarning> {
+        LogicCompiler::from_datalogic(Arc::clone(&self.datalogic))
+            .with_priority_tie_break(self.options.priority_tie_break)
+            .with_complexity_thresholds(self.options.complexity)
+            .lint(workflows)
+    }
+
+    /// State of the circuit breaker around the function `name`, or `None`
+    /// when it has none. See [`circuit_breaker`](crate::engine::circuit_breaker).
+    pub fn circuit_state(&self, name: &str) -> Option<CircuitState> {
+        self.workflow_executor.circuit_breakers().state(name)
+    }
+
+    /// Free slots of the bulkhead around the function `name`, or `None`
+    /// when it has none. See [`bulkhead`](crate::engine::bulkhead).
+    pub fn bulkhead_available(&self, name: &str) -> Option<usize> {
+        self.workflow_executor.bulkheads().available(name)
+    }
+
     /// Get a reference to the workflows (pre-sorted by priority)
     pub fn workflows(&self) -> &Arc<Vec<Workflow>> {
         &self.workflows
@@ -430,6 +2098,35 @@ impl Engine {
     pub fn datalogic(&self) -> &Arc<DatalogicEngine> {
         &self.datalogic
     }
+
+    /// Evaluate a JSONLogic expression against `context`, e.g. for an
+    /// editor preview, with the engine's evaluator options, operators and
+    /// lookup tables. The compiled expression is kept in the engine's
+    /// [expression cache](crate::engine::expression_cache), so evaluating
+    /// it again skips compilation.
+    ///
+    /// ```
+    /// # use dataflow_rs::Engine;
+    /// # use serde_json::json;
+    /// let engine = Engine::builder().build()?;
+    /// let total = engine.evaluate(
+    ///     &json!({"*": [{"var": "data.qty"}, {"var": "data.price"}]}),
+    ///     &json!({"data": {"qty": 3, "price": 5}}),
+    /// )?;
+    /// assert_eq!(total, json!(15));
+    /// # Ok::<(), dataflow_rs::DataflowError>(())
+    /// ```
+    pub fn evaluate(&self, logic: &Value, context: &Value) -> Result<Value> {
+        let compiled = self.expressions.get_or_compile(logic, &self.datalogic)?;
+        let result = eval_to_owned(&self.datalogic, &compiled, &OwnedDataValue::from(context))
+            .map_err(|e| DataflowError::LogicEvaluation(e.to_string()))?;
+        serde_json::to_value(&result).map_err(DataflowError::from_serde)
+    }
+
+    /// Hit and miss counts of the engine's expression cache.
+    pub fn expression_cache_stats(&self) -> ExpressionCacheStats {
+        self.expressions.stats()
+    }
 }
 
 /// Builder for [`Engine`]. The recommended construction path — chain
@@ -455,6 +2152,26 @@ impl Engine {
 pub struct EngineBuilder {
     workflows: Vec<Workflow>,
     handlers: HashMap<String, BoxedFunctionHandler>,
+    quarantine: Option<Arc<Quarantine>>,
+    dead_letters: Option<Arc<DeadLetters>>,
+    rule_packs: HashMap<String, Arc<RulePack>>,
+    /// Names of rule packs registered more than once; fail `build`.
+    duplicate_rule_packs: Vec<String>,
+    fragments: HashMap<String, Arc<TaskFragment>>,
+    lookup_tables: HashMap<String, OwnedDataValue>,
+    journal: Option<Arc<dyn Journal>>,
+    outbox: Option<Arc<dyn OutboxSink>>,
+    hooks: Vec<Arc<dyn MessageHook>>,
+    processing_hooks: Vec<Arc<dyn ProcessingHook>>,
+    options: EngineOptions,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    secrets: Option<Arc<dyn SecretsResolver>>,
+    id_generator: Option<Arc<dyn IdGenerator>>,
+    state_store: Option<Arc<dyn StateStore>>,
+    deduplication: Option<Arc<dyn DeduplicationStore>>,
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    alerts: Option<Arc<dyn AlertSink>>,
+    sinks: HashMap<String, Arc<dyn SinkHandler>>,
 }
 
 impl EngineBuilder {
@@ -503,11 +2220,371 @@ impl EngineBuilder {
         self
     }
 
+    /// Choose how workflows with the same `priority` are ordered. Defaults
+    /// to [`PriorityTieBreak::DeclarationOrder`] — the order in which they
+    /// were added to the builder.
+    pub fn with_priority_tie_break(mut self, tie_break: PriorityTieBreak) -> Self {
+        self.options.priority_tie_break = tie_break;
+        self
+    }
+
+    /// Enable poison-message detection. A message id that fails the same
+    /// task `policy.max_consecutive_failures` times in a row (across
+    /// `process_message` calls) is handed to `sink`, and `process_message`
+    /// returns the non-retryable [`DataflowError::Quarantined`].
+    pub fn with_quarantine<S>(mut self, policy: QuarantinePolicy, sink: S) -> Self
+    where
+        S: QuarantineSink + 'static,
+    {
+        self.quarantine = Some(Arc::new(Quarantine::new(policy, Box::new(sink))));
+        self
+    }
+
+    /// Hand messages the engine gives up on to `handler`: those failing
+    /// with a non-retryable error, and — with `policy.error_budget` set —
+    /// those completing with more errors than the budget. `process_message`
+    /// then returns the non-retryable [`DataflowError::DeadLettered`]. See
+    /// [`dead_letter`](crate::engine::dead_letter).
+    pub fn with_dead_letters<H>(mut self, policy: DeadLetterPolicy, handler: H) -> Self
+    where
+        H: DeadLetterHandler + 'static,
+    {
+        self.dead_letters = Some(Arc::new(DeadLetters::new(policy, Box::new(handler))));
+        self
+    }
+
+    /// Record task execution events to `journal`. Side-effecting tasks
+    /// (async built-ins and custom handlers) get a `Started` entry before
+    /// they run and a `Completed` or `Failed` entry after. See
+    /// [`journal`](crate::engine::journal).
+    pub fn with_journal<J>(mut self, journal: J) -> Self
+    where
+        J: Journal + 'static,
+    {
+        self.journal = Some(Arc::new(journal));
+        self
+    }
+
+    /// Write the side effects of tasks marked `"outbox": true` to `outbox`
+    /// instead of performing them inline. Deliver them with an
+    /// [`OutboxDispatcher`] sharing the same outbox (pass an `Arc` here and
+    /// to the dispatcher).
+    pub fn with_outbox<S>(mut self, outbox: S) -> Self
+    where
+        S: OutboxSink + 'static,
+    {
+        self.outbox = Some(Arc::new(outbox));
+        self
+    }
+
+    /// Run `hook` around every `process_message` /
+    /// `process_message_for_channel` call. Hooks run in registration order.
+    /// See [`hooks`](crate::engine::hooks).
+    pub fn with_message_hook<H>(mut self, hook: H) -> Self
+    where
+        H: MessageHook + 'static,
+    {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Closure form of [`MessageHook::before_message`]: runs before any
+    /// workflow and vetoes the message by returning `Err`.
+    pub fn before_message<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Message) -> Result<()> + Send + Sync + 'static,
+    {
+        self.with_message_hook(hooks::BeforeFn(hook))
+    }
+
+    /// Closure form of [`MessageHook::after_message`]: runs after
+    /// processing with its result.
+    pub fn after_message<F>(self, hook: F) -> Self
+    where
+        F: Fn(&mut Message, &Result<()>) -> Result<()> + Send + Sync + 'static,
+    {
+        self.with_message_hook(hooks::AfterFn(hook))
+    }
+
+    /// Run `hook` around every executed workflow and task, on every entry
+    /// point. Hooks run in registration order. See
+    /// [`hooks`](crate::engine::hooks).
+    pub fn with_processing_hook<H>(mut self, hook: H) -> Self
+    where
+        H: ProcessingHook + 'static,
+    {
+        self.processing_hooks.push(Arc::new(hook));
+        self
+    }
+
+    /// Retry async tasks that fail with a retryable error. See
+    /// [`retry`](crate::engine::retry).
+    pub fn with_retry_config(mut self, retry: RetryConfig) -> Self {
+        self.options.retry = retry;
+        self
+    }
+
+    /// Only allow functions that need nothing beyond `capabilities`;
+    /// `build()` fails on a task whose function needs another. Pass an empty
+    /// set to rule out every function that does I/O. See
+    /// [`capability`](crate::engine::capability).
+    pub fn with_allowed_capabilities(
+        mut self,
+        capabilities: impl IntoIterator<Item = Capability>,
+    ) -> Self {
+        self.options.allowed_capabilities = Some(capabilities.into_iter().collect());
+        self
+    }
+
+    /// Let workflow `workflow_id` read only the context `roots` (e.g.
+    /// `["data", "metadata"]`); `build()` fails on an expression of it
+    /// that reads another. See [`context_roots`](crate::engine::context_roots).
+    pub fn with_readable_roots<R: Into<String>>(
+        mut self,
+        workflow_id: impl Into<String>,
+        roots: impl IntoIterator<Item = R>,
+    ) -> Self {
+        self.options = self.options.with_readable_roots(workflow_id, roots);
+        self
+    }
+
+    /// Put a circuit breaker around the function handler `name`, so calls
+    /// fail fast while its dependency is down. See
+    /// [`circuit_breaker`](crate::engine::circuit_breaker).
+    pub fn with_circuit_breaker(
+        mut self,
+        name: impl Into<String>,
+        config: CircuitBreakerConfig,
+    ) -> Self {
+        self.options.circuit_breakers.insert(name.into(), config);
+        self
+    }
+
+    /// Run at most `max_concurrent` calls of the function handler `name` at
+    /// once, across every message in flight. See
+    /// [`bulkhead`](crate::engine::bulkhead).
+    pub fn with_bulkhead(mut self, name: impl Into<String>, max_concurrent: usize) -> Self {
+        self.options = self.options.with_bulkhead(name, max_concurrent);
+        self
+    }
+
+    /// Cap how often the function handler `name` is called, across every
+    /// message in flight. See [`rate_limit`](crate::engine::rate_limit).
+    pub fn with_function_rate_limit(mut self, name: impl Into<String>, limit: RateLimit) -> Self {
+        self.options = self.options.with_function_rate_limit(name, limit);
+        self
+    }
+
+    /// Cap how often workflow `workflow_id` runs, across every message in
+    /// flight. See [`rate_limit`](crate::engine::rate_limit).
+    pub fn with_workflow_rate_limit(
+        mut self,
+        workflow_id: impl Into<String>,
+        limit: RateLimit,
+    ) -> Self {
+        self.options = self.options.with_workflow_rate_limit(workflow_id, limit);
+        self
+    }
+
+    /// Require a registered handler for every built-in async function
+    /// (`http_call`, `enrich`, `publish_kafka`) the workflows use, failing
+    /// `build()` with `FunctionNotFound` instead of failing the first
+    /// message that reaches the task. Custom functions are always checked.
+    pub fn with_strict_functions(mut self, strict: bool) -> Self {
+        self.options.strict_functions = strict;
+        self
+    }
+
+    /// Set a variable for `${vars.<name>}` placeholders in task inputs,
+    /// filled in at construction. See
+    /// [`interpolation`](crate::engine::interpolation).
+    pub fn with_var(mut self, name: impl Into<String>, value: Value) -> Self {
+        self.options.interpolation.vars.insert(name.into(), value);
+        self
+    }
+
+    /// Fail `build()` when a task input has a placeholder with no variable
+    /// or environment variable to fill it, instead of leaving it in place.
+    pub fn with_strict_interpolation(mut self, strict: bool) -> Self {
+        self.options.interpolation.strict = strict;
+        self
+    }
+
+    /// Limits above which an expression is reported as a
+    /// `complex_expression` lint warning. Defaults to
+    /// [`ComplexityThresholds::default`]; pass
+    /// [`ComplexityThresholds::unlimited`] to turn the check off.
+    pub fn with_complexity_thresholds(mut self, thresholds: ComplexityThresholds) -> Self {
+        self.options.complexity = thresholds;
+        self
+    }
+
+    /// Report message, workflow and task counts, durations and errors to
+    /// `sink`. With [`InMemoryMetrics`], [`Engine::stats`] summarizes them.
+    /// See the [`metrics`] module for the metric names.
+    pub fn with_metrics<M>(mut self, sink: M) -> Self
+    where
+        M: MetricsSink + 'static,
+    {
+        self.metrics = Some(Arc::new(sink));
+        self
+    }
+
+    /// Fetch named secrets, such as
+    /// [`EngineOptions::audit_signing_key`] and `${secret:NAME}`
+    /// placeholders in task inputs, from `resolver`. See the [`secrets`]
+    /// module.
+    pub fn with_secrets_resolver<R>(mut self, resolver: R) -> Self
+    where
+        R: SecretsResolver + 'static,
+    {
+        self.secrets = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Keep shared state in `store`. The engine persists retry progress
+    /// there, so retries resume after a restart; pass an `Arc` to share the
+    /// store with other code. See the [`state`] module.
+    pub fn with_state_store<S>(mut self, store: S) -> Self
+    where
+        S: StateStore + 'static,
+    {
+        self.state_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Record idempotency keys in `store`, so workflows with an
+    /// `idempotency_key` skip messages already seen. Required by such
+    /// workflows; pass an `Arc` to share the store. See the [`dedup`]
+    /// module.
+    pub fn with_deduplication_store<S>(mut self, store: S) -> Self
+    where
+        S: DeduplicationStore + 'static,
+    {
+        self.deduplication = Some(Arc::new(store));
+        self
+    }
+
+    /// Save checkpoints of `process_message` runs to `store`, so an
+    /// interrupted message can be picked up with
+    /// [`Engine::resume_message`]; pass an `Arc` to load checkpoints from
+    /// the store yourself. See the [`checkpoint`] module.
+    pub fn with_checkpoint_store<S>(mut self, store: S) -> Self
+    where
+        S: CheckpointStore + 'static,
+    {
+        self.checkpoints = Some(Arc::new(store));
+        self
+    }
+
+    /// Send the alerts raised by `alert` tasks to `sink`. Without one they
+    /// are logged. See the [`alert`] module.
+    pub fn with_alert_sink<S>(mut self, sink: S) -> Self
+    where
+        S: AlertSink + 'static,
+    {
+        self.alerts = Some(Arc::new(sink));
+        self
+    }
+
+    /// Register `sink` under `name`, for workflows to list in their
+    /// `output`. Replaces an earlier sink of the same name. See the
+    /// [`sink`] module.
+    pub fn with_sink<S>(mut self, name: impl Into<String>, sink: S) -> Self
+    where
+        S: SinkHandler + 'static,
+    {
+        self.sinks.insert(name.into(), Arc::new(sink));
+        self
+    }
+
+    /// Generate the ids of messages started with [`Engine::message`] with
+    /// `generator`, e.g. [`Snowflake`] or a closure. Defaults to
+    /// [`UuidV7`].
+    pub fn with_id_generator<G>(mut self, generator: G) -> Self
+    where
+        G: IdGenerator + 'static,
+    {
+        self.id_generator = Some(Arc::new(generator));
+        self
+    }
+
+    /// Replace every option at once, e.g. with options loaded from a config
+    /// document. Overrides earlier calls to the individual setters
+    /// (`with_retry_config`, `with_priority_tie_break`, …); later calls
+    /// still adjust single values. See [`EngineOptions`].
+    pub fn with_options(mut self, options: EngineOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Register a validation rule pack, making it available to
+    /// `"rules_ref"` in `validation` tasks. Registering two packs with the
+    /// same name makes [`build`](Self::build) fail.
+    pub fn with_rule_pack(mut self, pack: RulePack) -> Self {
+        let name = pack.name.clone();
+        if self
+            .rule_packs
+            .insert(name.clone(), Arc::new(pack))
+            .is_some()
+        {
+            self.duplicate_rule_packs.push(name);
+        }
+        self
+    }
+
+    /// Register every pack in `packs` (e.g. from [`RulePack::load_dir`]),
+    /// as [`with_rule_pack`](Self::with_rule_pack) does.
+    pub fn with_rule_packs<I>(self, packs: I) -> Self
+    where
+        I: IntoIterator<Item = RulePack>,
+    {
+        packs.into_iter().fold(self, Self::with_rule_pack)
+    }
+
+    /// Register a task fragment, making it available to `"include"` entries
+    /// in task lists. Replaces an earlier fragment with the same id.
+    pub fn with_fragment(mut self, fragment: TaskFragment) -> Self {
+        self.fragments
+            .insert(fragment.id.clone(), Arc::new(fragment));
+        self
+    }
+
+    /// Register every fragment in `fragments` (e.g. from
+    /// [`TaskFragment::load_dir`]).
+    pub fn with_fragments<I>(mut self, fragments: I) -> Self
+    where
+        I: IntoIterator<Item = TaskFragment>,
+    {
+        for fragment in fragments {
+            self.fragments
+                .insert(fragment.id.clone(), Arc::new(fragment));
+        }
+        self
+    }
+
+    /// Register a lookup table under `name`, readable from logic with
+    /// `{"lookup": [name, key]}`. Replaces an earlier table of the same
+    /// name. See the [`lookup`] module.
+    pub fn with_lookup_table(mut self, name: impl Into<String>, table: Value) -> Self {
+        self.lookup_tables
+            .insert(name.into(), OwnedDataValue::from(table));
+        self
+    }
+
     /// Compile the workflows, pre-parse Custom inputs, and produce the
     /// engine. Compile errors and missing handler references surface here —
     /// the engine never deserializes Custom config on the hot path.
     pub fn build(self) -> Result<Engine> {
-        Engine::new(self.workflows, self.handlers)
+        Engine::construct(self)
+    }
+
+    /// [`build`](Self::build), also returning the [`ConstructionReport`];
+    /// see [`Engine::try_new`].
+    pub fn build_with_report(
+        self,
+    ) -> std::result::Result<(Engine, ConstructionReport), EngineBuildError> {
+        Engine::construct_with_report(self)
     }
 }
 
@@ -528,19 +2605,64 @@ fn precompile_custom_inputs(
     handlers: &HashMap<String, BoxedFunctionHandler>,
 ) -> Result<()> {
     for workflow in workflows {
-        for task in &mut workflow.tasks {
-            if let FunctionConfig::Custom {
+        precompile_task_inputs(&mut workflow.tasks, handlers)
+            .map_err(|e| locate(e, |at| at.in_workflow(&workflow.id)))?;
+    }
+    Ok(())
+}
+
+/// [`precompile_custom_inputs`] for one task list, descending into
+/// `foreach` bodies.
+fn precompile_task_inputs(
+    tasks: &mut [Task],
+    handlers: &HashMap<String, BoxedFunctionHandler>,
+) -> Result<()> {
+    for (idx, task) in tasks.iter_mut().enumerate() {
+        let task_id = task.id.clone();
+        let at = |e| {
+            locate(e, |at| {
+                at.in_task(&task_id).within(&format!("/tasks/{idx}"))
+            })
+        };
+        match &mut task.function {
+            FunctionConfig::Custom {
                 name,
                 input,
                 compiled_input,
-            } = &mut task.function
-            {
+            } => {
                 let handler = handlers
                     .get(name)
                     .ok_or_else(|| function_not_found_error(name, handlers))?;
-                let parsed = handler.parse_input_box(input)?;
+                let parsed = handler
+                    .parse_input_box(input)
+                    .map_err(|e| at(locate(e, |at| at.within("/function/input"))))?;
                 *compiled_input = Some(CompiledCustomInput(Arc::from(parsed)));
             }
+            FunctionConfig::Foreach { input, .. } => {
+                precompile_task_inputs(&mut input.tasks, handlers)
+                    .map_err(|e| at(locate(e, |at| at.within("/function/input"))))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Strict-mode check: every `http_call`/`enrich`/`publish_kafka` task needs
+/// a handler registered under that name.
+fn check_builtin_handlers(
+    workflows: &[Workflow],
+    handlers: &HashMap<String, BoxedFunctionHandler>,
+) -> Result<()> {
+    for task in workflows.iter().flat_map(|w| &w.tasks) {
+        let name = match &task.function {
+            FunctionConfig::HttpCall { .. } => "http_call",
+            FunctionConfig::Enrich { .. } => "enrich",
+            FunctionConfig::PublishKafka { .. } => "publish_kafka",
+            _ => continue,
+        };
+        if !handlers.contains_key(name) {
+            return Err(function_not_found_error(name, handlers));
         }
     }
     Ok(())
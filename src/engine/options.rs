@@ -0,0 +1,507 @@
+//! # Engine Options
+//!
+//! Engine-wide settings in one serializable struct, so an engine can be
+//! configured from a single config document:
+//!
+//! - workflow ordering ([`PriorityTieBreak`]), async task retries
+//!   ([`RetryConfig`]), circuit breakers ([`CircuitBreakerConfig`]), rate
+//!   limits ([`RateLimits`]), bulkheads, strict function checking, allowed
+//!   [`Capability`]s, lint limits ([`ComplexityThresholds`]) and
+//!   placeholder variables ([`InterpolationConfig`]) and the size of the
+//!   ad-hoc [expression cache](crate::engine::expression_cache);
+//! - the `datalogic_rs` instance that compiles and evaluates every
+//!   JSONLogic expression: structure-preserving (templating) mode, constant
+//!   folding, evaluation behaviour and limits, and custom operators.
+//!
+//! Passed to [`EngineBuilder::with_options`](crate::EngineBuilder::with_options).
+//! The defaults match what the engine has always used.
+//!
+//! ```json
+//! {
+//!   "priority_tie_break": "id",
+//!   "retry": {"max_retries": 3},
+//!   "strict_functions": true,
+//!   "complexity": {"max_nodes": 200},
+//!   "evaluation": {"preset": "safe_arithmetic", "max_recursion_depth": 64}
+//! }
+//! ```
+//!
+//! Missing keys keep their defaults and unknown keys are rejected.
+//! `evaluation` takes the keys of [`EvaluationConfig::from_json_str`].
+//! Custom operators and a custom truthy evaluator are Rust-only: they are
+//! not serialized and must be added in code.
+
+use crate::engine::capability::Capability;
+use crate::engine::circuit_breaker::CircuitBreakerConfig;
+use crate::engine::complexity::ComplexityThresholds;
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::expression_cache::DEFAULT_EXPRESSION_CACHE_SIZE;
+use crate::engine::interpolation::InterpolationConfig;
+use crate::engine::locale::{
+    FORMAT_DATE_OPERATOR, FORMAT_NUMBER_OPERATOR, FormatDateOperator, FormatNumberOperator,
+};
+use crate::engine::lookup::{LOOKUP_OPERATOR, LookupOperator, LookupStore};
+use crate::engine::rate_limit::{RateLimit, RateLimits};
+use crate::engine::retry::RetryConfig;
+use crate::engine::urgency::ChildUrgency;
+use crate::engine::workflow::PriorityTieBreak;
+use datalogic_rs::bumpalo::Bump;
+use datalogic_rs::operator::EvalContext;
+use datalogic_rs::{CustomOperator, DataValue, Engine as DatalogicEngine, EvaluationConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+/// Engine-wide options. See the [module docs](self) for the config format.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineOptions {
+    /// How workflows of equal priority are ordered. Default:
+    /// [`PriorityTieBreak::DeclarationOrder`].
+    pub priority_tie_break: PriorityTieBreak,
+    /// Retry policy for async tasks. Default: no retries.
+    pub retry: RetryConfig,
+    /// Circuit breakers around async function handlers, keyed by function
+    /// name (see [`circuit_breaker`](crate::engine::circuit_breaker)).
+    /// Default: none.
+    pub circuit_breakers: BTreeMap<String, CircuitBreakerConfig>,
+    /// Rate limits by function name and workflow id (see
+    /// [`rate_limit`](crate::engine::rate_limit)). Default: none.
+    pub rate_limits: RateLimits,
+    /// Maximum concurrent executions of function handlers, keyed by
+    /// function name (see [`bulkhead`](crate::engine::bulkhead)). Default:
+    /// none.
+    pub bulkheads: BTreeMap<String, usize>,
+    /// Context roots each listed workflow may read, keyed by workflow id
+    /// (see [`context_roots`](crate::engine::context_roots)). Default:
+    /// none, every workflow unrestricted.
+    pub readable_roots: BTreeMap<String, BTreeSet<String>>,
+    /// Require a registered handler for every built-in async function used
+    /// by a workflow at construction. Default: `false`.
+    pub strict_functions: bool,
+    /// Capabilities functions may use; construction fails on a task whose
+    /// function needs another (see [`capability`](crate::engine::capability)).
+    /// Default: none set, every capability allowed.
+    pub allowed_capabilities: Option<BTreeSet<Capability>>,
+    /// Limits for `complex_expression` lints. Default:
+    /// [`ComplexityThresholds::default`].
+    pub complexity: ComplexityThresholds,
+    /// Variables for `${vars.…}` placeholders in task inputs and whether
+    /// unresolved placeholders are an error (see
+    /// [`interpolation`](crate::engine::interpolation)). Default: no
+    /// variables, lenient.
+    pub interpolation: InterpolationConfig,
+    /// Compiled ad-hoc expressions kept for reuse (see
+    /// [`expression_cache`](crate::engine::expression_cache)); 0 disables
+    /// the cache. Default: 256.
+    pub expression_cache_size: usize,
+    /// Record config and input-data digests on every audit entry (see
+    /// [`audit`](crate::engine::audit)). Default: `false`.
+    pub audit_input_hashes: bool,
+    /// Chain audit entries by hash so edits to a trail are detectable.
+    /// Default: `false`.
+    pub audit_chain: bool,
+    /// Skip tasks the message's audit trail already records completing, so
+    /// redelivered messages don't repeat side effects (see
+    /// [`audit`](crate::engine::audit)). Default: `false`.
+    pub skip_audited_tasks: bool,
+    /// Name of the secret, fetched from the engine's
+    /// [`SecretsResolver`](crate::SecretsResolver), that signs each
+    /// message's audit chain. Implies [`Self::audit_chain`]. Default: none.
+    pub audit_signing_key: Option<String>,
+    /// Priority and deadline of messages started with
+    /// [`Engine::child_message`](crate::Engine::child_message) (see
+    /// [`urgency`](crate::engine::urgency)). Default: the parent's.
+    pub child_urgency: ChildUrgency,
+    /// Preserve object structure: multi-key objects in logic are output
+    /// templates and unknown operator keys pass through as literal keys.
+    /// Mappings that build objects rely on this. Default: `true`.
+    pub preserve_structure: bool,
+    /// Prefix marking a template key as a literal output key rather than an
+    /// operator, e.g. `'$'` so `{"$type": …}` writes `type`. Only applies
+    /// with `preserve_structure`. Default: none.
+    pub template_key_escape: Option<char>,
+    /// Pre-compute constant sub-expressions at construction. Default: `true`.
+    pub constant_folding: bool,
+    /// Evaluation behaviour (NaN and division-by-zero handling, truthiness,
+    /// numeric coercion) and limits such as `max_recursion_depth`.
+    /// Default: [`EvaluationConfig::default`].
+    #[serde(with = "evaluation_serde")]
+    pub evaluation: EvaluationConfig,
+    /// Custom operators, in registration order.
+    #[serde(skip)]
+    operators: Vec<(String, Arc<dyn CustomOperator>)>,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            priority_tie_break: PriorityTieBreak::default(),
+            retry: RetryConfig::default(),
+            circuit_breakers: BTreeMap::new(),
+            rate_limits: RateLimits::default(),
+            bulkheads: BTreeMap::new(),
+            readable_roots: BTreeMap::new(),
+            strict_functions: false,
+            allowed_capabilities: None,
+            complexity: ComplexityThresholds::default(),
+            interpolation: InterpolationConfig::default(),
+            expression_cache_size: DEFAULT_EXPRESSION_CACHE_SIZE,
+            audit_input_hashes: false,
+            audit_chain: false,
+            skip_audited_tasks: false,
+            audit_signing_key: None,
+            child_urgency: ChildUrgency::default(),
+            preserve_structure: true,
+            template_key_escape: None,
+            constant_folding: true,
+            evaluation: EvaluationConfig::default(),
+            operators: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Debug for EngineOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EngineOptions")
+            .field("priority_tie_break", &self.priority_tie_break)
+            .field("retry", &self.retry)
+            .field("circuit_breakers", &self.circuit_breakers)
+            .field("rate_limits", &self.rate_limits)
+            .field("bulkheads", &self.bulkheads)
+            .field("readable_roots", &self.readable_roots)
+            .field("strict_functions", &self.strict_functions)
+            .field("allowed_capabilities", &self.allowed_capabilities)
+            .field("complexity", &self.complexity)
+            .field("interpolation", &self.interpolation)
+            .field("expression_cache_size", &self.expression_cache_size)
+            .field("audit_input_hashes", &self.audit_input_hashes)
+            .field("audit_chain", &self.audit_chain)
+            .field("skip_audited_tasks", &self.skip_audited_tasks)
+            .field("audit_signing_key", &self.audit_signing_key)
+            .field("child_urgency", &self.child_urgency)
+            .field("preserve_structure", &self.preserve_structure)
+            .field("template_key_escape", &self.template_key_escape)
+            .field("constant_folding", &self.constant_folding)
+            .field("evaluation", &self.evaluation)
+            .field("operators", &self.operator_names().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl EngineOptions {
+    /// Default options. Equivalent to [`EngineOptions::default`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse options from a JSON config document.
+    pub fn from_json(json_str: &str) -> Result<Self> {
+        serde_json::from_str(json_str).map_err(DataflowError::from_serde)
+    }
+
+    /// Set [`Self::priority_tie_break`].
+    pub fn with_priority_tie_break(mut self, tie_break: PriorityTieBreak) -> Self {
+        self.priority_tie_break = tie_break;
+        self
+    }
+
+    /// Set [`Self::retry`].
+    pub fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Put a circuit breaker around the function handler `name`.
+    pub fn with_circuit_breaker(
+        mut self,
+        name: impl Into<String>,
+        config: CircuitBreakerConfig,
+    ) -> Self {
+        self.circuit_breakers.insert(name.into(), config);
+        self
+    }
+
+    /// Limit how often the function handler `name` is called.
+    pub fn with_function_rate_limit(mut self, name: impl Into<String>, limit: RateLimit) -> Self {
+        self.rate_limits.functions.insert(name.into(), limit);
+        self
+    }
+
+    /// Limit how often workflow `workflow_id` runs.
+    pub fn with_workflow_rate_limit(
+        mut self,
+        workflow_id: impl Into<String>,
+        limit: RateLimit,
+    ) -> Self {
+        self.rate_limits.workflows.insert(workflow_id.into(), limit);
+        self
+    }
+
+    /// Run at most `max_concurrent` calls of the function handler `name`
+    /// at once.
+    pub fn with_bulkhead(mut self, name: impl Into<String>, max_concurrent: usize) -> Self {
+        self.bulkheads.insert(name.into(), max_concurrent);
+        self
+    }
+
+    /// Let workflow `workflow_id` read only the context `roots`.
+    pub fn with_readable_roots<R: Into<String>>(
+        mut self,
+        workflow_id: impl Into<String>,
+        roots: impl IntoIterator<Item = R>,
+    ) -> Self {
+        let roots = roots.into_iter().map(Into::into).collect();
+        self.readable_roots.insert(workflow_id.into(), roots);
+        self
+    }
+
+    /// Set [`Self::strict_functions`].
+    pub fn with_strict_functions(mut self, strict: bool) -> Self {
+        self.strict_functions = strict;
+        self
+    }
+
+    /// Set [`Self::allowed_capabilities`].
+    pub fn with_allowed_capabilities(
+        mut self,
+        capabilities: impl IntoIterator<Item = Capability>,
+    ) -> Self {
+        self.allowed_capabilities = Some(capabilities.into_iter().collect());
+        self
+    }
+
+    /// Set [`Self::complexity`].
+    pub fn with_complexity(mut self, thresholds: ComplexityThresholds) -> Self {
+        self.complexity = thresholds;
+        self
+    }
+
+    /// Set the `${vars.<name>}` variable `name`.
+    pub fn with_var(mut self, name: impl Into<String>, value: serde_json::Value) -> Self {
+        self.interpolation.vars.insert(name.into(), value);
+        self
+    }
+
+    /// Set [`InterpolationConfig::strict`].
+    pub fn with_strict_interpolation(mut self, strict: bool) -> Self {
+        self.interpolation.strict = strict;
+        self
+    }
+
+    /// Set [`Self::expression_cache_size`].
+    pub fn with_expression_cache_size(mut self, size: usize) -> Self {
+        self.expression_cache_size = size;
+        self
+    }
+
+    /// Set [`Self::audit_input_hashes`].
+    pub fn with_audit_input_hashes(mut self, on: bool) -> Self {
+        self.audit_input_hashes = on;
+        self
+    }
+
+    /// Set [`Self::audit_chain`].
+    pub fn with_audit_chain(mut self, on: bool) -> Self {
+        self.audit_chain = on;
+        self
+    }
+
+    /// Set [`Self::skip_audited_tasks`].
+    pub fn with_skip_audited_tasks(mut self, on: bool) -> Self {
+        self.skip_audited_tasks = on;
+        self
+    }
+
+    /// Set [`Self::audit_signing_key`] to the secret called `name`.
+    pub fn with_audit_signing_key(mut self, name: impl Into<String>) -> Self {
+        self.audit_signing_key = Some(name.into());
+        self
+    }
+
+    /// Set [`Self::child_urgency`].
+    pub fn with_child_urgency(mut self, urgency: ChildUrgency) -> Self {
+        self.child_urgency = urgency;
+        self
+    }
+
+    /// Whether audit entries are chained, explicitly or for signing.
+    pub(crate) fn chains_audit(&self) -> bool {
+        self.audit_chain || self.audit_signing_key.is_some()
+    }
+
+    /// Set [`Self::preserve_structure`].
+    pub fn with_preserve_structure(mut self, on: bool) -> Self {
+        self.preserve_structure = on;
+        self
+    }
+
+    /// Set [`Self::template_key_escape`].
+    pub fn with_template_key_escape(mut self, prefix: char) -> Self {
+        self.template_key_escape = Some(prefix);
+        self
+    }
+
+    /// Set [`Self::constant_folding`].
+    pub fn with_constant_folding(mut self, on: bool) -> Self {
+        self.constant_folding = on;
+        self
+    }
+
+    /// Set [`Self::evaluation`].
+    pub fn with_evaluation(mut self, config: EvaluationConfig) -> Self {
+        self.evaluation = config;
+        self
+    }
+
+    /// Register a custom JSONLogic operator under `name`, usable in every
+    /// condition and mapping. Built-in operators win on a name clash.
+    pub fn with_operator<T>(mut self, name: impl Into<String>, operator: T) -> Self
+    where
+        T: CustomOperator + 'static,
+    {
+        self.operators.push((name.into(), Arc::new(operator)));
+        self
+    }
+
+    /// Names of the registered custom operators.
+    pub fn operator_names(&self) -> impl Iterator<Item = &str> {
+        self.operators.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Build the evaluator these options describe, with the `lookup`
+    /// operator reading `lookups`.
+    pub(crate) fn build_datalogic(&self, lookups: &Arc<LookupStore>) -> DatalogicEngine {
+        let mut builder = DatalogicEngine::builder()
+            .with_templating(self.preserve_structure)
+            .with_constant_folding(self.constant_folding)
+            .with_config(self.evaluation.clone())
+            .add_operator(LOOKUP_OPERATOR, LookupOperator(Arc::clone(lookups)))
+            .add_operator(FORMAT_NUMBER_OPERATOR, FormatNumberOperator)
+            .add_operator(FORMAT_DATE_OPERATOR, FormatDateOperator);
+        if let Some(prefix) = self.template_key_escape {
+            builder = builder.with_template_key_escape(prefix);
+        }
+        for (name, operator) in &self.operators {
+            builder = builder.add_operator(name.clone(), SharedOperator(Arc::clone(operator)));
+        }
+        builder.build()
+    }
+}
+
+/// Lets one registered operator back several evaluators (the options are
+/// `Clone`; `datalogic_rs` takes operators by value).
+struct SharedOperator(Arc<dyn CustomOperator>);
+
+impl CustomOperator for SharedOperator {
+    fn evaluate<'a>(
+        &self,
+        args: &[&'a DataValue<'a>],
+        ctx: &mut EvalContext<'_, 'a>,
+        arena: &'a Bump,
+    ) -> datalogic_rs::Result<&'a DataValue<'a>> {
+        self.0.evaluate(args, ctx, arena)
+    }
+}
+
+/// `EvaluationConfig` in the key format of
+/// [`EvaluationConfig::from_json_str`]; `datalogic_rs` has no serde support.
+mod evaluation_serde {
+    use datalogic_rs::{DivisionByZeroHandling, EvaluationConfig, NanHandling, TruthyEvaluator};
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_json::{Value, json};
+
+    pub(super) fn serialize<S: Serializer>(
+        config: &EvaluationConfig,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let nan = match config.arithmetic_nan_handling {
+            NanHandling::ThrowError => "throw_error",
+            NanHandling::IgnoreValue => "ignore_value",
+            NanHandling::CoerceToZero => "coerce_to_zero",
+            NanHandling::ReturnNull => "return_null",
+        };
+        let division = match config.division_by_zero {
+            DivisionByZeroHandling::ReturnSaturated => "return_saturated",
+            DivisionByZeroHandling::ThrowError => "throw_error",
+            DivisionByZeroHandling::ReturnNull => "return_null",
+            DivisionByZeroHandling::ReturnInfinity => "return_infinity",
+        };
+        let truthy = match config.truthy_evaluator {
+            TruthyEvaluator::JavaScript => "javascript",
+            TruthyEvaluator::Python => "python",
+            TruthyEvaluator::StrictBoolean => "strict_boolean",
+            TruthyEvaluator::Custom(_) => {
+                return Err(S::Error::custom(
+                    "a custom truthy evaluator cannot be serialized",
+                ));
+            }
+        };
+        let coercion = &config.numeric_coercion;
+        json!({
+            "arithmetic_nan_handling": nan,
+            "division_by_zero": division,
+            "loose_equality_errors": config.loose_equality_errors,
+            "truthy_evaluator": truthy,
+            "numeric_coercion": {
+                "empty_string_to_zero": coercion.empty_string_to_zero,
+                "null_to_zero": coercion.null_to_zero,
+                "bool_to_number": coercion.bool_to_number,
+                "reject_non_numeric": coercion.reject_non_numeric,
+            },
+            "max_recursion_depth": config.max_recursion_depth,
+        })
+        .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<EvaluationConfig, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        EvaluationConfig::from_json_str(&value.to_string()).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datalogic_rs::{DivisionByZeroHandling, TruthyEvaluator};
+
+    #[test]
+    fn parses_partial_config_and_round_trips() {
+        let options = EngineOptions::from_json(
+            r#"{"priority_tie_break": "id",
+                "retry": {"max_retries": 3},
+                "complexity": {"max_nodes": 200},
+                "evaluation": {"preset": "safe_arithmetic", "truthy_evaluator": "python"}}"#,
+        )
+        .unwrap();
+        assert_eq!(options.priority_tie_break, PriorityTieBreak::Id);
+        assert_eq!(options.retry, RetryConfig::new(3));
+        assert!(!options.strict_functions);
+        assert_eq!(options.complexity.max_nodes, Some(200));
+        assert_eq!(options.complexity.max_depth, Some(12));
+        assert!(options.preserve_structure);
+        assert!(matches!(
+            options.evaluation.truthy_evaluator,
+            TruthyEvaluator::Python
+        ));
+        assert!(matches!(
+            options.evaluation.division_by_zero,
+            DivisionByZeroHandling::ReturnNull
+        ));
+
+        let json = serde_json::to_string(&options).unwrap();
+        let reparsed = EngineOptions::from_json(&json).unwrap();
+        assert_eq!(serde_json::to_string(&reparsed).unwrap(), json);
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert!(EngineOptions::from_json(r#"{"retries": 3}"#).is_err());
+        assert!(EngineOptions::from_json(r#"{"evaluation": {"nan": "x"}}"#).is_err());
+    }
+}
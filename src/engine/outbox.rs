@@ -0,0 +1,384 @@
+//! # Outbox
+//!
+//! Transactional-outbox support for side-effecting tasks. A task marked
+//! `"outbox": true` does not perform its I/O while the message is being
+//! processed; the engine instead writes an [`OutboxIntent`] — the task's
+//! coordinates plus a snapshot of the message as the task would have seen
+//! it — to the configured [`OutboxSink`], and the task counts as succeeded.
+//!
+//! An [`OutboxDispatcher`] later reads due intents and delivers each one by
+//! running that single task through the same registered handler. Failures
+//! that are retryable ([`DataflowError::retryable`], or a 5xx task status)
+//! are retried with exponential backoff. Delivery is at-least-once, so
+//! handlers should be idempotent on [`OutboxIntent::id`].
+//!
+//! ```json
+//! {"id": "notify", "name": "Notify", "outbox": true,
+//!  "function": {"name": "publish_kafka", "input": {"topic": "orders"}}}
+//! ```
+//!
+//! Only async tasks (`http_call`, `enrich`, `publish_kafka`, custom
+//! handlers) can use the outbox. Because delivery happens later, anything
+//! the handler writes to the message (including a task `output`) is not
+//! visible to the rest of the workflow.
+
+use crate::engine::Engine;
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::Message;
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::workflow::Workflow;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Delivery state of an intent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    /// Waiting for (re)delivery at `next_attempt_at`.
+    Pending,
+    /// Delivered successfully.
+    Delivered,
+    /// Gave up after `max_attempts`.
+    Dead,
+}
+
+/// A side effect waiting to be performed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxIntent {
+    /// `"{message_id}:{workflow_id}:{task_id}"`. Stable across reprocessing
+    /// of the same message, so sinks can deduplicate on it.
+    pub id: String,
+    pub message_id: String,
+    pub workflow_id: String,
+    pub task_id: String,
+    /// The message as it stood when the task would have run.
+    pub message: Message,
+    pub status: OutboxStatus,
+    /// Delivery attempts made so far.
+    pub attempts: u32,
+    /// Error from the most recent failed attempt.
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    /// Earliest time the dispatcher may try (again).
+    pub next_attempt_at: DateTime<Utc>,
+}
+
+impl OutboxIntent {
+    pub(crate) fn new(message: &Message, workflow_id: &str, task_id: &str) -> Self {
+        let now = Utc::now();
+        Self {
+            id: format!("{}:{}:{}", message.id(), workflow_id, task_id),
+            message_id: message.id().to_string(),
+            workflow_id: workflow_id.to_string(),
+            task_id: task_id.to_string(),
+            message: message.clone(),
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            last_error: None,
+            created_at: now,
+            next_attempt_at: now,
+        }
+    }
+}
+
+/// Durable store for outbox intents. `enqueue` is called on the processing
+/// path; the other methods are used by [`OutboxDispatcher`].
+///
+/// If `enqueue` returns `Err`, the task fails like any other task error.
+#[async_trait]
+pub trait OutboxSink: Send + Sync {
+    /// Persist a new intent. Enqueueing an id that already exists should
+    /// leave the stored intent unchanged.
+    async fn enqueue(&self, intent: OutboxIntent) -> Result<()>;
+
+    /// Up to `limit` `Pending` intents with `next_attempt_at <= now`,
+    /// oldest first.
+    async fn due(&self, now: DateTime<Utc>, limit: usize) -> Result<Vec<OutboxIntent>>;
+
+    /// Store the intent's updated `status`, `attempts`, `last_error` and
+    /// `next_attempt_at` after a delivery attempt.
+    async fn update(&self, intent: &OutboxIntent) -> Result<()>;
+}
+
+/// `OutboxSink` that keeps intents in memory. Not durable; handy for tests.
+#[derive(Debug, Default)]
+pub struct InMemoryOutbox {
+    intents: Mutex<BTreeMap<String, OutboxIntent>>,
+}
+
+impl InMemoryOutbox {
+    /// Create an empty outbox.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of every intent, ordered by id.
+    pub fn intents(&self) -> Vec<OutboxIntent> {
+        self.intents
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl OutboxSink for InMemoryOutbox {
+    async fn enqueue(&self, intent: OutboxIntent) -> Result<()> {
+        self.intents
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(intent.id.clone())
+            .or_insert(intent);
+        Ok(())
+    }
+
+    async fn due(&self, now: DateTime<Utc>, limit: usize) -> Result<Vec<OutboxIntent>> {
+        let intents = self.intents.lock().unwrap_or_else(|e| e.into_inner());
+        let mut due: Vec<_> = intents
+            .values()
+            .filter(|i| i.status == OutboxStatus::Pending && i.next_attempt_at <= now)
+            .cloned()
+            .collect();
+        due.sort_by_key(|i| i.created_at);
+        due.truncate(limit);
+        Ok(due)
+    }
+
+    async fn update(&self, intent: &OutboxIntent) -> Result<()> {
+        self.intents
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(intent.id.clone(), intent.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: OutboxSink + ?Sized> OutboxSink for Arc<T> {
+    async fn enqueue(&self, intent: OutboxIntent) -> Result<()> {
+        (**self).enqueue(intent).await
+    }
+
+    async fn due(&self, now: DateTime<Utc>, limit: usize) -> Result<Vec<OutboxIntent>> {
+        (**self).due(now, limit).await
+    }
+
+    async fn update(&self, intent: &OutboxIntent) -> Result<()> {
+        (**self).update(intent).await
+    }
+}
+
+/// Retry schedule for [`OutboxDispatcher`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutboxRetryPolicy {
+    /// Attempts before an intent is marked `Dead`. Defaults to 5.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after each failure. Defaults
+    /// to 1 000 ms.
+    pub initial_backoff_ms: u64,
+    /// Upper bound on the delay. Defaults to 300 000 ms (5 minutes).
+    pub max_backoff_ms: u64,
+    /// Intents fetched per [`OutboxDispatcher::dispatch_once`]. Defaults to
+    /// 100.
+    pub batch_size: usize,
+}
+
+impl Default for OutboxRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff_ms: 1_000,
+            max_backoff_ms: 300_000,
+            batch_size: 100,
+        }
+    }
+}
+
+impl OutboxRetryPolicy {
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 1u64 << attempts.saturating_sub(1).min(32);
+        let ms = self
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms);
+        Duration::milliseconds(ms as i64)
+    }
+}
+
+/// Counts from one [`OutboxDispatcher::dispatch_once`] pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct DispatchStats {
+    pub delivered: usize,
+    /// Failed and rescheduled.
+    pub retried: usize,
+    /// Failed for the last time and marked `Dead`.
+    pub dead: usize,
+}
+
+/// Delivers outbox intents by running their task through the engine's
+/// registered handlers. Callers drive it, typically from a timer loop:
+///
+/// ```no_run
+/// # use dataflow_rs::{Engine, InMemoryOutbox, OutboxDispatcher};
+/// # use std::sync::Arc;
+/// # async fn demo(engine: Arc<Engine>, outbox: Arc<InMemoryOutbox>) {
+/// let dispatcher = OutboxDispatcher::new(engine, outbox);
+/// loop {
+///     let stats = dispatcher.dispatch_once().await.unwrap();
+///     if stats.delivered + stats.retried + stats.dead == 0 {
+///         // sleep before polling again
+///     }
+/// }
+/// # }
+/// ```
+///
+/// Intents are resolved against the dispatcher's engine by workflow and
+/// task id, so after a hot reload pass the new engine to a new dispatcher.
+pub struct OutboxDispatcher {
+    engine: Arc<Engine>,
+    outbox: Arc<dyn OutboxSink>,
+    policy: OutboxRetryPolicy,
+}
+
+impl OutboxDispatcher {
+    /// Dispatcher with the default [`OutboxRetryPolicy`].
+    pub fn new<S>(engine: Arc<Engine>, outbox: S) -> Self
+    where
+        S: OutboxSink + 'static,
+    {
+        Self {
+            engine,
+            outbox: Arc::new(outbox),
+            policy: OutboxRetryPolicy::default(),
+        }
+    }
+
+    /// Replace the retry policy.
+    pub fn with_policy(mut self, policy: OutboxRetryPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Attempt every due intent once (up to `batch_size`). Returns `Err`
+    /// only when the outbox itself fails; delivery failures are recorded on
+    /// the intents.
+    pub async fn dispatch_once(&self) -> Result<DispatchStats> {
+        let mut stats = DispatchStats::default();
+        for mut intent in self.outbox.due(Utc::now(), self.policy.batch_size).await? {
+            intent.attempts += 1;
+            match self.engine.deliver_outbox_intent(&intent).await {
+                Ok(()) => {
+                    debug!("Outbox intent {} delivered", intent.id);
+                    intent.status = OutboxStatus::Delivered;
+                    intent.last_error = None;
+                    stats.delivered += 1;
+                }
+                Err(e) => {
+                    intent.last_error = Some(e.to_string());
+                    if intent.attempts >= self.policy.max_attempts || !e.retryable() {
+                        warn!("Outbox intent {} dead: {}", intent.id, e);
+                        intent.status = OutboxStatus::Dead;
+                        stats.dead += 1;
+                    } else {
+                        intent.next_attempt_at = Utc::now() + self.policy.backoff(intent.attempts);
+                        stats.retried += 1;
+                    }
+                }
+            }
+            self.outbox.update(&intent).await?;
+        }
+        Ok(stats)
+    }
+}
+
+/// Run the intent's task against its message snapshot.
+pub(crate) async fn deliver(
+    workflows: &[Workflow],
+    executor: &crate::engine::task_executor::TaskExecutor,
+    intent: &OutboxIntent,
+) -> Result<()> {
+    let task = workflows
+        .iter()
+        .find(|w| w.id == intent.workflow_id)
+        .and_then(|w| w.tasks.iter().find(|t| t.id == intent.task_id))
+        .ok_or_else(|| {
+            DataflowError::Workflow(format!(
+                "Outbox intent {} refers to unknown task {} in workflow {}",
+                intent.id, intent.task_id, intent.workflow_id
+            ))
+        })?;
+    let mut message = intent.message.clone();
+    let (outcome, _) = executor.execute(task, &mut message).await?;
+    match outcome {
+        TaskOutcome::Status(code) if code >= 500 => Err(DataflowError::http(
+            code,
+            format!("Task {} returned status {}", task.id, code),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Reject `outbox: true` on tasks that can't use it, or when no outbox is
+/// configured.
+pub(crate) fn validate_outbox_tasks(workflows: &[Workflow], configured: bool) -> Result<()> {
+    for workflow in workflows {
+        for task in workflow.tasks.iter().filter(|t| t.outbox) {
+            if task.function.is_sync_builtin() {
+                return Err(DataflowError::Workflow(format!(
+                    "Task {} in workflow {} sets outbox, but '{}' has no side effects to defer",
+                    task.id,
+                    workflow.id,
+                    task.function.function_name()
+                )));
+            }
+            if !configured {
+                return Err(DataflowError::Workflow(format!(
+                    "Task {} in workflow {} sets outbox, but the engine has no outbox configured",
+                    task.id, workflow.id
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = OutboxRetryPolicy {
+            initial_backoff_ms: 100,
+            max_backoff_ms: 500,
+            ..Default::default()
+        };
+        let ms: Vec<_> = (1..=5)
+            .map(|n| policy.backoff(n).num_milliseconds())
+            .collect();
+        assert_eq!(ms, vec![100, 200, 400, 500, 500]);
+    }
+
+    #[tokio::test]
+    async fn in_memory_outbox_keeps_first_enqueue_and_filters_due() {
+        let outbox = InMemoryOutbox::new();
+        let message = Message::from_value(&serde_json::json!({}));
+        let mut intent = OutboxIntent::new(&message, "wf", "t");
+        outbox.enqueue(intent.clone()).await.unwrap();
+
+        intent.attempts = 9;
+        outbox.enqueue(intent.clone()).await.unwrap();
+        assert_eq!(outbox.intents()[0].attempts, 0);
+
+        intent.next_attempt_at = Utc::now() + Duration::hours(1);
+        outbox.update(&intent).await.unwrap();
+        assert!(outbox.due(Utc::now(), 10).await.unwrap().is_empty());
+    }
+}
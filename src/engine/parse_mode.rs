@@ -0,0 +1,366 @@
+//! # Strict Parsing
+//!
+//! Workflow documents are parsed leniently by default: a key the engine
+//! doesn't know is ignored, so a misspelt `"mapings"` silently leaves a
+//! task without mappings. Loading with [`ParseMode::Strict`] rejects such
+//! keys instead, listing every one with the closest known key:
+//!
+//! ```text
+//! Configuration error: unknown key 'mapings' in tasks[0].function.input, did you mean 'mappings'? (workflow 'orders', task 'enrich', at /tasks/0/function/input)
+//! ```
+//!
+//! Select the mode when loading — [`Workflow::from_json_with_mode`],
+//! [`Workflow::from_file_with_mode`], [`Workflow::load_dir_with_mode`] — or
+//! with a manifest's `parse_mode` key.
+//!
+//! Strict parsing checks the workflow and its `output` entries, its tasks
+//! (`foreach` bodies included), their `function` objects, the inputs of
+//! built-in functions, and the mappings and rules inside `map` and
+//! `validation` inputs.
+//! JSONLogic, annotations and custom function inputs are free-form and
+//! not checked; a custom handler's `Input` type can reject unknown fields
+//! itself with `#[serde(deny_unknown_fields)]`.
+//!
+//! [`Workflow::from_json_with_mode`]: crate::Workflow::from_json_with_mode
+//! [`Workflow::from_file_with_mode`]: crate::Workflow::from_file_with_mode
+//! [`Workflow::load_dir_with_mode`]: crate::Workflow::load_dir_with_mode
+
+use crate::engine::error::{ConfigError, ConfigErrorKind, Result};
+use crate::engine::fragment::IncludeConfig;
+use crate::engine::functions::{
+    AggregateRule, AlertConfig, EnrichConfig, ErrorMapping, FilterConfig, FlattenConfig,
+    ForeachConfig, HttpCallConfig, JoinConfig, LogConfig, MapConfig, MapErrorsConfig, MapMapping,
+    ParseConfig, ProjectConfig, PublishConfig, PublishKafkaConfig, RejectConfig, RenameKeysConfig,
+    RequiredRule, SampleConfig, ScoreConfig, ScoreRule, StateGetConfig, StateSetConfig,
+    SwitchConfig, ValidationConfig, ValidationRule,
+};
+use crate::engine::sink::WorkflowOutput;
+use crate::engine::task::Task;
+use crate::engine::unknown_vars::edit_distance;
+use crate::engine::variant::WorkflowVariant;
+use crate::engine::workflow::{Workflow, task_id_at};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::{Serialize, forward_to_deserialize_any};
+use serde_json::{Map, Value};
+
+/// How keys the engine doesn't know are treated when loading workflows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseMode {
+    /// Ignore unknown keys.
+    #[default]
+    Lenient,
+    /// Reject unknown keys, suggesting the closest known one.
+    Strict,
+}
+
+/// Fail with every unknown key in the workflow document `raw`, located
+/// at the object holding the first.
+pub(crate) fn check_workflow(raw: &Value) -> Result<()> {
+    let mut unknown = Vec::new();
+    let mut walk = Walk {
+        unknown: &mut unknown,
+    };
+    walk.workflow(raw);
+    let Some((pointer, _)) = unknown.first() else {
+        return Ok(());
+    };
+    let messages: Vec<&str> = unknown
+        .iter()
+        .map(|(_, message)| message.as_str())
+        .collect();
+    let mut error = ConfigError::new(ConfigErrorKind::UnknownKeys(messages.join("; ")));
+    if let Some(id) = raw.get("id").and_then(Value::as_str) {
+        error = error.in_workflow(id);
+    }
+    if let Some(id) = task_id_at(raw, pointer) {
+        error = error.in_task(id);
+    }
+    Err(error.within(pointer).into())
+}
+
+/// JSON pointer of a path as written in messages: `tasks[0].function`
+/// is `/tasks/0/function`, `the workflow` the empty pointer.
+fn pointer(path: &str) -> String {
+    if path == "the workflow" {
+        return String::new();
+    }
+    path.replace('[', ".")
+        .replace(']', "")
+        .split('.')
+        .map(|segment| format!("/{segment}"))
+        .collect()
+}
+
+struct Walk<'a> {
+    /// JSON pointer of the holding object and message, per unknown key.
+    unknown: &'a mut Vec<(String, String)>,
+}
+
+impl Walk<'_> {
+    fn workflow(&mut self, raw: &Value) {
+        let Some(fields) = raw.as_object() else {
+            return;
+        };
+        self.keys(fields, &[field_names::<Workflow>()], "the workflow");
+        if let Some(tasks) = fields.get("tasks") {
+            self.tasks(tasks, "tasks");
+        }
+        if let Some(Value::Object(variant)) = fields.get("variant") {
+            self.keys(variant, &[field_names::<WorkflowVariant>()], "variant");
+        }
+        self.list(
+            fields.get("output"),
+            &[field_names::<WorkflowOutput>()],
+            "output",
+        );
+    }
+
+    fn tasks(&mut self, raw: &Value, path: &str) {
+        for (idx, task) in raw.as_array().into_iter().flatten().enumerate() {
+            let Some(fields) = task.as_object() else {
+                continue;
+            };
+            if fields.len() == 1 && fields.contains_key("include") {
+                continue;
+            }
+            let path = format!("{path}[{idx}]");
+            self.keys(fields, &[field_names::<Task>()], &path);
+            if let Some(Value::Object(function)) = fields.get("function") {
+                self.function(function, &format!("{path}.function"));
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Map<String, Value>, path: &str) {
+        self.keys(function, &[&["name", "input"]], path);
+        let (Some(Value::String(name)), Some(Value::Object(input))) =
+            (function.get("name"), function.get("input"))
+        else {
+            return;
+        };
+        let Some(fields) = input_field_names(name) else {
+            return;
+        };
+        let path = format!("{path}.input");
+        self.keys(input, &[fields], &path);
+        match name.as_str() {
+            "map" => self.list(
+                input.get("mappings"),
+                &[field_names::<MapMapping>()],
+                &format!("{path}.mappings"),
+            ),
+            "validation" | "validate" => self.list(
+                input.get("rules"),
+                &[
+                    field_names::<ValidationRule>(),
+                    field_names::<RequiredRule>(),
+                    field_names::<AggregateRule>(),
+                ],
+                &format!("{path}.rules"),
+            ),
+            "score" => self.list(
+                input.get("rules"),
+                &[field_names::<ScoreRule>()],
+                &format!("{path}.rules"),
+            ),
+            "map_errors" => self.list(
+                input.get("mappings"),
+                &[field_names::<ErrorMapping>()],
+                &format!("{path}.mappings"),
+            ),
+            "foreach" => {
+                if let Some(tasks) = input.get("tasks") {
+                    self.tasks(tasks, &format!("{path}.tasks"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn list(&mut self, raw: Option<&Value>, known: &[&[&str]], path: &str) {
+        for (idx, item) in raw
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            if let Value::Object(fields) = item {
+                self.keys(fields, known, &format!("{path}[{idx}]"));
+            }
+        }
+    }
+
+    /// Record the keys of `fields` found in none of `known`.
+    fn keys(&mut self, fields: &Map<String, Value>, known: &[&[&str]], path: &str) {
+        let known = || known.iter().flat_map(|names| names.iter().copied());
+        for key in fields.keys() {
+            if known().any(|name| name == key) {
+                continue;
+            }
+            let mut message = format!("unknown key '{key}' in {path}");
+            if let Some(suggestion) = closest(key, known()) {
+                message.push_str(&format!(", did you mean '{suggestion}'?"));
+            }
+            self.unknown.push((pointer(path), message));
+        }
+    }
+}
+
+/// The known key `key` most likely misspells: a short edit apart.
+fn closest<'a>(key: &str, known: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    known
+        .filter_map(|name| {
+            let distance = edit_distance(key, name);
+            let allowed = key.chars().count().max(name.chars().count()) / 4;
+            (distance <= allowed.max(1)).then_some((distance, name))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name)
+}
+
+/// Keys accepted in the input of built-in function `name`; `None` for
+/// custom functions.
+fn input_field_names(name: &str) -> Option<&'static [&'static str]> {
+    Some(match name {
+        "map" => field_names::<MapConfig>(),
+        "validation" | "validate" => field_names::<ValidationConfig>(),
+        "parse_json" | "parse_xml" => field_names::<ParseConfig>(),
+        "publish_json" | "publish_xml" => field_names::<PublishConfig>(),
+        "filter" => field_names::<FilterConfig>(),
+        "log" => field_names::<LogConfig>(),
+        "project" => field_names::<ProjectConfig>(),
+        "flatten" | "unflatten" => field_names::<FlattenConfig>(),
+        "rename_keys" => field_names::<RenameKeysConfig>(),
+        "sample" => field_names::<SampleConfig>(),
+        "switch" => field_names::<SwitchConfig>(),
+        "foreach" => field_names::<ForeachConfig>(),
+        "reject" => field_names::<RejectConfig>(),
+        "score" => field_names::<ScoreConfig>(),
+        "map_errors" => field_names::<MapErrorsConfig>(),
+        "join" => field_names::<JoinConfig>(),
+        "state_get" => field_names::<StateGetConfig>(),
+        "state_set" => field_names::<StateSetConfig>(),
+        "alert" => field_names::<AlertConfig>(),
+        "include" => field_names::<IncludeConfig>(),
+        "http_call" => field_names::<HttpCallConfig>(),
+        "enrich" => field_names::<EnrichConfig>(),
+        "publish_kafka" => field_names::<PublishKafkaConfig>(),
+        _ => return None,
+    })
+}
+
+/// The keys a derived `Deserialize` of struct `T` accepts, captured from
+/// the field list it hands to `deserialize_struct`.
+fn field_names<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    struct Capture<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Capture<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(
+            self,
+            _: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("captured"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Capture(&mut fields));
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn captures_derived_field_names() {
+        assert_eq!(field_names::<MapMapping>(), &["path", "logic"]);
+        assert_eq!(field_names::<ValidationConfig>(), &["rules", "rules_ref"]);
+        assert!(field_names::<Task>().contains(&"continue_on_error"));
+        assert!(!field_names::<Task>().contains(&"compiled_condition"));
+    }
+
+    #[test]
+    fn reports_unknown_keys_with_suggestions() {
+        let raw = json!({
+            "id": "w", "name": "W", "prority": 1,
+            "tasks": [
+                {"include": "common"},
+                {"id": "m", "name": "M", "function": {"name": "map", "input": {
+                    "mapings": [], "mappings": [{"path": "data.x", "logic": 1, "logc": 2}]
+                }}},
+                {"id": "f", "name": "F", "function": {"name": "foreach", "input": {
+                    "path": "data.items", "tasks": [{"id": "t", "name": "T", "conditon": true,
+                        "function": {"name": "custom", "input": {"anything": 1}}}]
+                }}},
+                {"id": "v", "name": "V", "function": {"name": "validation", "input": {"rules": [
+                    {"type": "required", "paths": ["data.x"]},
+                    {"logic": true, "message": "m", "code": "C"},
+                    {"logic": true, "mesage": "m"}
+                ]}}}
+            ]
+        });
+        let err = check_workflow(&raw).unwrap_err().to_string();
+        for expected in [
+            "unknown key 'prority' in the workflow, did you mean 'priority'?",
+            "unknown key 'mapings' in tasks[1].function.input, did you mean 'mappings'?",
+            "unknown key 'logc' in tasks[1].function.input.mappings[0], did you mean 'logic'?",
+            "unknown key 'conditon' in tasks[2].function.input.tasks[0], did you mean 'condition'?",
+            "unknown key 'mesage' in tasks[3].function.input.rules[2], did you mean 'message'?",
+        ] {
+            assert!(err.contains(expected), "{expected} not in {err}");
+        }
+        assert_eq!(err.matches("unknown key").count(), 5, "{err}");
+    }
+
+    #[test]
+    fn unrelated_keys_get_no_suggestion() {
+        let err = check_workflow(&json!({"id": "w", "name": "W", "tasks": [], "owner": "ops"}))
+            .unwrap_err();
+        let config = err.config().unwrap();
+        assert_eq!(
+            config.kind,
+            ConfigErrorKind::UnknownKeys("unknown key 'owner' in the workflow".to_string())
+        );
+        assert_eq!(config.json_pointer.as_deref(), Some(""));
+    }
+
+    #[test]
+    fn locates_the_first_unknown_key() {
+        let raw = json!({"id": "w", "name": "W", "tasks": [
+            {"id": "f", "name": "F", "function": {"name": "foreach", "input": {
+                "path": "data.items", "tasks": [{"id": "t", "name": "T", "conditon": true,
+                    "function": {"name": "map", "input": {"mapings": []}}}]
+            }}}
+        ]});
+        let err = check_workflow(&raw).unwrap_err();
+        let config = err.config().unwrap();
+        assert_eq!(config.workflow_id.as_deref(), Some("w"));
+        assert_eq!(config.task_id.as_deref(), Some("t"));
+        assert_eq!(
+            config.json_pointer.as_deref(),
+            Some("/tasks/0/function/input/tasks/0")
+        );
+        assert_eq!(config.kind.message().matches("unknown key").count(), 2);
+    }
+}
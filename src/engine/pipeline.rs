@@ -0,0 +1,216 @@
+//! # Pipelines
+//!
+//! Chains several engines so a large rule base can be split into modules —
+//! say a parse/normalize engine, a business rules engine and an output
+//! engine — instead of one engine holding every workflow. A [`Pipeline`]
+//! passes the message through each stage's engine in order, as that
+//! engine's `process_message` would:
+//!
+//! ```no_run
+//! # use dataflow_rs::{Engine, Message, Pipeline, StageErrorPolicy};
+//! # async fn demo(normalize: Engine, rules: Engine, output: Engine, mut message: Message) {
+//! let pipeline = Pipeline::new()
+//!     .with_stage("normalize", normalize)
+//!     .with_stage_policy("rules", rules, StageErrorPolicy::Continue)
+//!     .with_stage("output", output);
+//! let result = pipeline.process_message(&mut message).await;
+//! # }
+//! ```
+//!
+//! When a stage's engine returns `Err`, the stage's [`StageErrorPolicy`]
+//! decides what happens next: fail the pipeline with that error (the
+//! default), carry on with the next stage, or end the pipeline without an
+//! error. Errors a stage only records in `message.errors()` (workflows with
+//! `continue_on_error`) never trigger the policy.
+//!
+//! Each stage keeps its own hooks, stores, variants and options;
+//! `metadata.pipeline.stage` names the stage the message last entered.
+//! [`Pipeline::process_message_with_trace`] returns the execution trace of
+//! every stage that ran, in order, as one [`PipelineTrace`].
+
+use crate::engine::Engine;
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::Message;
+use crate::engine::trace::ExecutionTrace;
+use crate::engine::utils::set_nested_value;
+use datavalue::OwnedDataValue;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Where the current stage is recorded on the message.
+const STAGE_PATH: &str = "metadata.pipeline.stage";
+
+/// What a pipeline does when a stage's engine returns `Err`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageErrorPolicy {
+    /// Stop and return the stage's error.
+    #[default]
+    Fail,
+    /// Pass the message on to the next stage. The error stays in
+    /// `message.errors()`.
+    Continue,
+    /// Stop without running later stages and return `Ok`.
+    Halt,
+}
+
+/// One engine of a pipeline.
+pub struct Stage {
+    name: String,
+    engine: Arc<Engine>,
+    on_error: StageErrorPolicy,
+}
+
+impl Stage {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn engine(&self) -> &Arc<Engine> {
+        &self.engine
+    }
+
+    pub fn on_error(&self) -> StageErrorPolicy {
+        self.on_error
+    }
+}
+
+/// Engines run one after another over the same message. See the
+/// [`pipeline`](self) module.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Create a pipeline with no stages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a stage that fails the pipeline when its engine fails.
+    /// Takes an `Engine` or an `Arc<Engine>` shared with other callers.
+    pub fn with_stage(self, name: impl Into<String>, engine: impl Into<Arc<Engine>>) -> Self {
+        self.with_stage_policy(name, engine, StageErrorPolicy::Fail)
+    }
+
+    /// Append a stage handling its engine's errors with `on_error`.
+    pub fn with_stage_policy(
+        mut self,
+        name: impl Into<String>,
+        engine: impl Into<Arc<Engine>>,
+        on_error: StageErrorPolicy,
+    ) -> Self {
+        self.stages.push(Stage {
+            name: name.into(),
+            engine: engine.into(),
+            on_error,
+        });
+        self
+    }
+
+    /// The stages, in order.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Run `message` through every stage with
+    /// [`Engine::process_message`].
+    pub async fn process_message(&self, message: &mut Message) -> Result<()> {
+        for stage in &self.stages {
+            enter(stage, message);
+            if let Err(e) = stage.engine.process_message(message).await {
+                match handle(stage, e) {
+                    Handled::Next => continue,
+                    Handled::Stop(result) => return result,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run `message` through every stage with
+    /// [`Engine::process_message_with_trace`], collecting each stage's
+    /// trace. A stage that fails under [`StageErrorPolicy::Fail`] returns
+    /// its error and the traces are dropped, as with a single engine.
+    pub async fn process_message_with_trace(&self, message: &mut Message) -> Result<PipelineTrace> {
+        let mut trace = PipelineTrace::default();
+        for stage in &self.stages {
+            enter(stage, message);
+            let (steps, result) = stage.engine.trace_message(message).await;
+            let error = result.err();
+            trace.stages.push(StageTrace {
+                stage: stage.name.clone(),
+                trace: steps,
+                error: error.clone(),
+            });
+            if let Some(e) = error {
+                match handle(stage, e) {
+                    Handled::Next => continue,
+                    Handled::Stop(result) => return result.map(|()| trace),
+                }
+            }
+        }
+        Ok(trace)
+    }
+}
+
+/// The execution trace of each stage a message went through.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipelineTrace {
+    pub stages: Vec<StageTrace>,
+}
+
+impl PipelineTrace {
+    /// The trace of stage `name`, if it ran.
+    pub fn stage(&self, name: &str) -> Option<&StageTrace> {
+        self.stages.iter().find(|stage| stage.stage == name)
+    }
+
+    /// Executed steps across every stage.
+    pub fn executed_count(&self) -> usize {
+        self.stages.iter().map(|s| s.trace.executed_count()).sum()
+    }
+}
+
+/// What one stage did.
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTrace {
+    pub stage: String,
+    /// Steps recorded before the stage finished or failed.
+    pub trace: ExecutionTrace,
+    /// Error the stage's engine returned, handled per its policy.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<DataflowError>,
+}
+
+enum Handled {
+    Next,
+    Stop(Result<()>),
+}
+
+fn enter(stage: &Stage, message: &mut Message) {
+    set_nested_value(
+        &mut message.context,
+        STAGE_PATH,
+        OwnedDataValue::String(stage.name.clone()),
+    );
+}
+
+fn handle(stage: &Stage, error: DataflowError) -> Handled {
+    match stage.on_error {
+        StageErrorPolicy::Fail => Handled::Stop(Err(error)),
+        StageErrorPolicy::Continue => {
+            log::warn!(
+                "Pipeline stage {} failed, continuing: {}",
+                stage.name,
+                error
+            );
+            Handled::Next
+        }
+        StageErrorPolicy::Halt => {
+            log::warn!("Pipeline stage {} failed, halting: {}", stage.name, error);
+            Handled::Stop(Ok(()))
+        }
+    }
+}
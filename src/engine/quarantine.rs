@@ -0,0 +1,307 @@
+//! # Quarantine
+//!
+//! Poison-message detection. The engine counts consecutive failures of the
+//! same task per message id across `process_message` calls (i.e. across
+//! caller-driven retries of the same message). Once a message reaches
+//! [`QuarantinePolicy::max_consecutive_failures`], it is handed to the
+//! configured [`QuarantineSink`] together with its audit trail and errors,
+//! and `process_message` returns the non-retryable
+//! [`DataflowError::Quarantined`] so ordered consumers can move past it
+//! instead of retrying forever.
+//!
+//! Any successful run of a message, or a failure at a different task,
+//! resets its counter.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::Message;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Thresholds for poison-message detection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuarantinePolicy {
+    /// Consecutive failures of the same task (for the same message id)
+    /// after which the message is quarantined. Defaults to 3.
+    pub max_consecutive_failures: u32,
+    /// Upper bound on the number of message ids tracked at once. When full,
+    /// an arbitrary entry is evicted to make room. Defaults to 10 000.
+    pub max_tracked_messages: usize,
+}
+
+impl Default for QuarantinePolicy {
+    fn default() -> Self {
+        Self {
+            max_consecutive_failures: 3,
+            max_tracked_messages: 10_000,
+        }
+    }
+}
+
+/// Diagnostic context handed to a [`QuarantineSink`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineRecord {
+    /// The message as it stood after its final failed attempt, including
+    /// its audit trail and error list.
+    pub message: Message,
+    /// Workflow containing the failing task.
+    pub workflow_id: String,
+    /// Task that failed repeatedly.
+    pub task_id: String,
+    /// Number of consecutive failures observed.
+    pub consecutive_failures: u32,
+    /// Error returned by the final attempt.
+    pub error: DataflowError,
+    /// When the message was quarantined.
+    pub quarantined_at: DateTime<Utc>,
+}
+
+/// Destination for poison messages. Implement this to divert messages to a
+/// dead-letter topic, a database table, or a file.
+///
+/// If `quarantine` returns `Err`, the message is not considered diverted:
+/// the engine returns the original processing error and keeps counting.
+#[async_trait]
+pub trait QuarantineSink: Send + Sync {
+    async fn quarantine(&self, record: QuarantineRecord) -> Result<()>;
+}
+
+/// `QuarantineSink` that keeps records in memory. Handy for tests and for
+/// services that expose quarantined messages through their own API.
+#[derive(Debug, Default)]
+pub struct InMemoryQuarantineSink {
+    records: Mutex<Vec<QuarantineRecord>>,
+}
+
+impl InMemoryQuarantineSink {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of every record received so far.
+    pub fn records(&self) -> Vec<QuarantineRecord> {
+        self.records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Remove and return every record received so far.
+    pub fn drain(&self) -> Vec<QuarantineRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+#[async_trait]
+impl QuarantineSink for InMemoryQuarantineSink {
+    async fn quarantine(&self, record: QuarantineRecord) -> Result<()> {
+        self.records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(record);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: QuarantineSink + ?Sized> QuarantineSink for Arc<T> {
+    async fn quarantine(&self, record: QuarantineRecord) -> Result<()> {
+        (**self).quarantine(record).await
+    }
+}
+
+/// Per-message failure streak: which task failed, and how many times in a
+/// row.
+struct FailureStreak {
+    workflow_id: String,
+    task_id: String,
+    count: u32,
+}
+
+/// Engine-internal quarantine state: policy, sink, and the per-message
+/// failure counters. Shared by `Arc` so counters survive
+/// `Engine::with_new_workflows`.
+pub(crate) struct Quarantine {
+    policy: QuarantinePolicy,
+    sink: Box<dyn QuarantineSink>,
+    streaks: Mutex<HashMap<String, FailureStreak>>,
+}
+
+impl Quarantine {
+    pub(crate) fn new(policy: QuarantinePolicy, sink: Box<dyn QuarantineSink>) -> Self {
+        Self {
+            policy,
+            sink,
+            streaks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Fold the result of one processing attempt into the message's failure
+    /// streak. Returns the result the caller should see: unchanged, or
+    /// `Err(Quarantined)` once the message has been diverted.
+    pub(crate) async fn observe(&self, message: &Message, result: Result<()>) -> Result<()> {
+        let err = match result {
+            Ok(()) => {
+                self.lock().remove(message.id());
+                return Ok(());
+            }
+            // A cancelled run says nothing about the message itself.
+            Err(e @ DataflowError::Cancelled(_)) => return Err(e),
+            Err(e) => e,
+        };
+
+        // Attribute the failure to the last task recorded in the errors.
+        // Failures outside any task (e.g. a workflow condition) are not
+        // counted.
+        let Some((workflow_id, task_id)) = message
+            .errors()
+            .iter()
+            .rev()
+            .find_map(|e| Some((e.workflow_id.as_deref()?, e.task_id.as_deref()?)))
+        else {
+            return Err(err);
+        };
+
+        let count = {
+            let mut streaks = self.lock();
+            if !streaks.contains_key(message.id())
+                && streaks.len() >= self.policy.max_tracked_messages
+                && let Some(evict) = streaks.keys().next().cloned()
+            {
+                streaks.remove(&evict);
+            }
+            let streak = streaks
+                .entry(message.id().to_string())
+                .or_insert_with(|| FailureStreak {
+                    workflow_id: workflow_id.to_string(),
+                    task_id: task_id.to_string(),
+                    count: 0,
+                });
+            if streak.workflow_id != workflow_id || streak.task_id != task_id {
+                streak.workflow_id = workflow_id.to_string();
+                streak.task_id = task_id.to_string();
+                streak.count = 0;
+            }
+            streak.count += 1;
+            streak.count
+        };
+
+        if count < self.policy.max_consecutive_failures {
+            return Err(err);
+        }
+
+        warn!(
+            "Quarantining message {} after {} consecutive failures of task {}/{}",
+            message.id(),
+            count,
+            workflow_id,
+            task_id
+        );
+        let record = QuarantineRecord {
+            message: message.clone(),
+            workflow_id: workflow_id.to_string(),
+            task_id: task_id.to_string(),
+            consecutive_failures: count,
+            error: err.clone(),
+            quarantined_at: Utc::now(),
+        };
+        match self.sink.quarantine(record).await {
+            Ok(()) => {
+                self.lock().remove(message.id());
+                Err(DataflowError::Quarantined(format!(
+                    "message {} failed task {}/{} {} times in a row: {}",
+                    message.id(),
+                    workflow_id,
+                    task_id,
+                    count,
+                    err
+                )))
+            }
+            Err(sink_err) => {
+                error!(
+                    "Quarantine sink rejected message {}: {}",
+                    message.id(),
+                    sink_err
+                );
+                Err(err)
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, FailureStreak>> {
+        self.streaks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::error::ErrorInfo;
+
+    fn failed(id: &str, task: &str) -> Message {
+        let mut msg = Message::builder().id(id).build();
+        msg.add_error(
+            ErrorInfo::builder("TASK_ERROR", "boom")
+                .workflow_id("wf")
+                .task_id(task)
+                .build(),
+        );
+        msg
+    }
+
+    fn boom() -> Result<()> {
+        Err(DataflowError::Task("boom".to_string()))
+    }
+
+    #[tokio::test]
+    async fn quarantines_after_consecutive_failures_of_same_task() {
+        let sink = Arc::new(InMemoryQuarantineSink::new());
+        let q = Quarantine::new(
+            QuarantinePolicy {
+                max_consecutive_failures: 2,
+                ..Default::default()
+            },
+            Box::new(Arc::clone(&sink)),
+        );
+
+        let msg = failed("m1", "t1");
+        assert!(matches!(
+            q.observe(&msg, boom()).await,
+            Err(DataflowError::Task(_))
+        ));
+        assert!(matches!(
+            q.observe(&msg, boom()).await,
+            Err(DataflowError::Quarantined(_))
+        ));
+
+        let records = sink.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].task_id, "t1");
+        assert_eq!(records[0].consecutive_failures, 2);
+    }
+
+    #[tokio::test]
+    async fn success_or_different_task_resets_streak() {
+        let sink = Arc::new(InMemoryQuarantineSink::new());
+        let q = Quarantine::new(
+            QuarantinePolicy {
+                max_consecutive_failures: 2,
+                ..Default::default()
+            },
+            Box::new(Arc::clone(&sink)),
+        );
+
+        let _ = q.observe(&failed("m1", "t1"), boom()).await;
+        let _ = q.observe(&failed("m1", "t2"), boom()).await;
+        q.observe(&failed("m1", "t2"), Ok(())).await.unwrap();
+        let result = q.observe(&failed("m1", "t2"), boom()).await;
+
+        assert!(matches!(result, Err(DataflowError::Task(_))));
+        assert!(sink.records().is_empty());
+    }
+}
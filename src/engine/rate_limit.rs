@@ -0,0 +1,183 @@
+//! # Rate Limits
+//!
+//! Caps how often a function handler is called, or a workflow runs, across
+//! every message in flight — e.g. to respect a partner API's quota. Limits
+//! are declared per function name
+//! ([`EngineBuilder::with_function_rate_limit`](crate::EngineBuilder::with_function_rate_limit))
+//! or per workflow id
+//! ([`EngineBuilder::with_workflow_rate_limit`](crate::EngineBuilder::with_workflow_rate_limit)),
+//! or in [`EngineOptions`](crate::EngineOptions):
+//!
+//! ```json
+//! {"rate_limits": {
+//!     "functions": {"partner_lookup": {"max_per_second": 20, "burst": 5}},
+//!     "workflows": {"bulk_export": {"max_per_second": 2}}
+//! }}
+//! ```
+//!
+//! Each limit is a token bucket holding up to `burst` tokens (default 1),
+//! refilled at `max_per_second`. A call or workflow run takes one token;
+//! when none is left it waits, in arrival order, for the next one rather
+//! than failing. A workflow is limited once its condition has matched; the
+//! wait does not count against its `timeout_ms`. Buckets are shared with
+//! engines produced by `with_new_workflows`.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::retry;
+use chrono::{DateTime, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// One rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    /// Sustained rate, in calls per second. Must be positive.
+    pub max_per_second: f64,
+    /// Calls allowed back to back after a quiet period. Default: 1.
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+}
+
+fn default_burst() -> u32 {
+    1
+}
+
+impl RateLimit {
+    /// At most `max_per_second` calls per second, one at a time.
+    pub fn new(max_per_second: f64) -> Self {
+        Self {
+            max_per_second,
+            burst: default_burst(),
+        }
+    }
+
+    /// Allow `burst` calls back to back.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+}
+
+/// Rate limits of an engine, by function name and by workflow id.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub functions: BTreeMap<String, RateLimit>,
+    pub workflows: BTreeMap<String, RateLimit>,
+}
+
+/// A token bucket. `tokens` goes negative while callers wait: each caller
+/// reserves its token up front and sleeps until the refill covers it.
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    state: Mutex<(f64, DateTime<Utc>)>,
+}
+
+impl Bucket {
+    /// Reserve a token, returning how long to wait before using it.
+    fn reserve(&self) -> Duration {
+        let now = Utc::now();
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, refilled_at) = &mut *state;
+        let elapsed = (now - *refilled_at).num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6;
+        *tokens = (*tokens + elapsed * self.limit.max_per_second).min(f64::from(self.limit.burst));
+        *refilled_at = now;
+        *tokens -= 1.0;
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / self.limit.max_per_second)
+        }
+    }
+}
+
+/// Token buckets keyed by function name or workflow id. Keys without a
+/// limit are never delayed.
+#[derive(Debug, Default)]
+pub(crate) struct RateLimiters {
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiters {
+    /// Full buckets for `limits`. Fails on a non-positive rate or a zero
+    /// burst.
+    pub(crate) fn new(limits: &BTreeMap<String, RateLimit>) -> Result<Self> {
+        let now = Utc::now();
+        let mut buckets = HashMap::with_capacity(limits.len());
+        for (key, limit) in limits {
+            if !(limit.max_per_second > 0.0 && limit.max_per_second.is_finite()) || limit.burst == 0
+            {
+                return Err(DataflowError::Workflow(format!(
+                    "Rate limit for '{}' needs a positive max_per_second and a burst of at least 1",
+                    key
+                )));
+            }
+            let state = Mutex::new((f64::from(limit.burst), now));
+            buckets.insert(
+                key.clone(),
+                Bucket {
+                    limit: *limit,
+                    state,
+                },
+            );
+        }
+        Ok(Self { buckets })
+    }
+
+    /// Wait for a token for `key`.
+    pub(crate) async fn acquire(&self, key: &str) {
+        let Some(bucket) = self.buckets.get(key) else {
+            return;
+        };
+        let wait = bucket.reserve();
+        if !wait.is_zero() {
+            debug!("Rate limit for {} delays it {:?}", key, wait);
+            retry::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiters(limit: RateLimit) -> RateLimiters {
+        RateLimiters::new(&BTreeMap::from([("api".to_string(), limit)])).unwrap()
+    }
+
+    #[test]
+    fn burst_is_free_then_calls_are_spaced_out() {
+        let limiters = limiters(RateLimit::new(10.0).with_burst(2));
+        let bucket = &limiters.buckets["api"];
+        assert_eq!(bucket.reserve(), Duration::ZERO);
+        assert_eq!(bucket.reserve(), Duration::ZERO);
+        let third = bucket.reserve().as_millis();
+        let fourth = bucket.reserve().as_millis();
+        assert!((95..=100).contains(&third), "{third}");
+        assert!((195..=200).contains(&fourth), "{fourth}");
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_a_token() {
+        let limiters = limiters(RateLimit::new(20.0));
+        let started = Utc::now();
+        for _ in 0..3 {
+            limiters.acquire("api").await;
+        }
+        limiters.acquire("unlimited").await;
+        assert!((Utc::now() - started).num_milliseconds() >= 90);
+    }
+
+    #[test]
+    fn rejects_invalid_limits() {
+        for limit in [RateLimit::new(0.0), RateLimit::new(5.0).with_burst(0)] {
+            let limits = BTreeMap::from([("api".to_string(), limit)]);
+            assert!(RateLimiters::new(&limits).is_err());
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! # Task Retries
+//!
+//! Engine-wide retry policy for async tasks (`http_call`, `enrich`,
+//! `publish_kafka`, custom handlers). A task whose error is
+//! [`retryable`](crate::DataflowError::retryable) — HTTP 5xx, timeouts, I/O
+//! failures — is re-run up to `max_retries` more times before the error is
+//! recorded. Handler writes are rolled back on error, so every attempt sees
+//! the same message state.
+//!
+//! Retries run back to back unless a backoff is set with
+//! [`RetryConfig::with_backoff`]: the first retry then waits
+//! `initial_backoff_ms`, and each later one twice as long as the last, up
+//! to `max_backoff_ms`. With [`RetryConfig::with_jitter`] each delay is
+//! drawn between half and all of that, so messages that failed together
+//! don't retry in lockstep. The draw is derived from the message, task and
+//! retry number, making it repeatable.
+//!
+//! A task can override the engine policy with its own `max_retries`,
+//! `backoff_ms` (the first delay; later ones still double up to
+//! `max_backoff_ms`) and `jitter`. With a [`StateStore`](crate::StateStore)
+//! configured, retry counts and due times are persisted, so a message
+//! redelivered after a restart continues its retry budget and backoff
+//! rather than starting over (see [`state`](crate::engine::state)). For
+//! redelivery decoupled from message processing, mark the task
+//! `outbox: true` and let the [`OutboxDispatcher`](crate::OutboxDispatcher)
+//! retry it.
+//!
+//! Sync built-ins (`map`, `validation`, …) are never retried: their errors
+//! are data or logic errors and would fail the same way again.
+
+use crate::engine::error::Result;
+use crate::engine::task::Task;
+use serde::{Deserialize, Serialize};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::time::Duration;
+
+/// Retry policy for async tasks. The default performs no retries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryConfig {
+    /// Extra attempts after the first failure.
+    #[serde(default)]
+    pub max_retries: u32,
+    /// Delay before the first retry, in milliseconds. `0` retries at once.
+    #[serde(default)]
+    pub initial_backoff_ms: u64,
+    /// Cap on the delay between retries, in milliseconds. A cap below
+    /// `initial_backoff_ms` keeps every delay at `initial_backoff_ms`.
+    #[serde(default)]
+    pub max_backoff_ms: u64,
+    /// Randomise each delay between half and all of its backoff.
+    #[serde(default)]
+    pub jitter: bool,
+}
+
+impl RetryConfig {
+    /// Retry retryable failures up to `max_retries` times.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Self::default()
+        }
+    }
+
+    /// Wait `initial_ms` before the first retry, doubling for each later
+    /// one up to `max_ms`.
+    pub fn with_backoff(mut self, initial_ms: u64, max_ms: u64) -> Self {
+        self.initial_backoff_ms = initial_ms;
+        self.max_backoff_ms = max_ms;
+        self
+    }
+
+    /// Draw each delay between half and all of its backoff.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// This policy with the overrides `task` declares.
+    pub(crate) fn for_task(&self, task: &Task) -> Self {
+        Self {
+            max_retries: task.max_retries.unwrap_or(self.max_retries),
+            initial_backoff_ms: task.backoff_ms.unwrap_or(self.initial_backoff_ms),
+            max_backoff_ms: self.max_backoff_ms,
+            jitter: task.jitter.unwrap_or(self.jitter),
+        }
+    }
+
+    /// Delay before retry number `retry` (from 1) of the task run keyed by
+    /// `seed`, jittered when enabled.
+    pub(crate) fn delay(&self, retry: u32, seed: &str) -> Duration {
+        let backoff = self.backoff(retry);
+        if !self.jitter || backoff.is_zero() {
+            return backoff;
+        }
+        let mut hasher = DefaultHasher::new();
+        (seed, retry).hash(&mut hasher);
+        let ms = backoff.as_millis() as u64;
+        let half = ms / 2;
+        Duration::from_millis(ms - half + hasher.finish() % (half + 1))
+    }
+
+    /// Delay before retry number `retry` (from 1).
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        let factor = 1u64 << retry.saturating_sub(1).min(32);
+        let delay = self
+            .initial_backoff_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms.max(self.initial_backoff_ms));
+        Duration::from_millis(delay)
+    }
+
+    /// Whether `result`, produced by attempt number `retries + 1`, should be
+    /// retried.
+    pub(crate) fn should_retry<T>(&self, result: &Result<T>, retries: u32) -> bool {
+        retries < self.max_retries && matches!(result, Err(e) if e.retryable())
+    }
+}
+
+/// Wait for `delay`. Timers need a Tokio runtime with time enabled; on
+/// `wasm32` there is none and retries are not delayed.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(delay: Duration) {
+    tokio::time::sleep(delay).await
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(_delay: Duration) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::error::DataflowError;
+
+    #[test]
+    fn retries_only_retryable_errors_up_to_the_limit() {
+        let config = RetryConfig::new(2);
+        let transient: Result<()> = Err(DataflowError::http(503, "unavailable"));
+        let permanent: Result<()> = Err(DataflowError::Validation("bad".into()));
+
+        assert!(config.should_retry(&transient, 0));
+        assert!(config.should_retry(&transient, 1));
+        assert!(!config.should_retry(&transient, 2));
+        assert!(!config.should_retry(&permanent, 0));
+        assert!(!config.should_retry(&Ok(()), 0));
+        assert!(!RetryConfig::default().should_retry(&transient, 0));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let config = RetryConfig::new(5).with_backoff(100, 350);
+        let delays: Vec<u128> = (1..=4).map(|n| config.backoff(n).as_millis()).collect();
+        assert_eq!(delays, [100, 200, 350, 350]);
+        assert_eq!(RetryConfig::new(3).backoff(2), Duration::ZERO);
+    }
+
+    #[test]
+    fn jitter_keeps_delays_between_half_and_full_backoff() {
+        let config = RetryConfig::new(3).with_backoff(1000, 1000);
+        assert_eq!(config.delay(1, "m1"), Duration::from_millis(1000));
+
+        let config = config.with_jitter(true);
+        let delays: Vec<Duration> = (0..20).map(|n| config.delay(1, &format!("m{n}"))).collect();
+        assert!(delays.iter().all(|d| (500..=1000).contains(&d.as_millis())));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+        assert_eq!(config.delay(2, "m1"), config.delay(2, "m1"));
+    }
+
+    #[test]
+    fn task_overrides_the_engine_policy() {
+        use crate::engine::functions::FunctionConfig;
+        use serde_json::json;
+
+        let engine = RetryConfig::new(1).with_backoff(100, 1000);
+        let mut task = Task::action(
+            "fetch",
+            "Fetch",
+            FunctionConfig::Custom {
+                name: "fetch".to_string(),
+                input: json!({}),
+                compiled_input: None,
+            },
+        );
+        assert_eq!(engine.for_task(&task), engine);
+
+        task.max_retries = Some(4);
+        task.backoff_ms = Some(300);
+        task.jitter = Some(true);
+        let policy = engine.for_task(&task);
+        assert_eq!(policy.max_retries, 4);
+        assert_eq!(policy.backoff(2), Duration::from_millis(600));
+        assert_eq!(policy.max_backoff_ms, 1000);
+        assert!(policy.jitter);
+    }
+}
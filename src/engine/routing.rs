@@ -0,0 +1,200 @@
+//! # Per-Message Priority Boosts
+//!
+//! A message can change the order it runs through the workflows in by
+//! carrying `metadata.routing.priority_boost`: an object mapping workflow
+//! ids to a whole number added to that workflow's `priority`, for this
+//! message only.
+//!
+//! ```json
+//! {"metadata": {"routing": {"priority_boost": {"fraud_check": -100}}}}
+//! ```
+//!
+//! Lower priorities run first, so a negative boost moves a workflow
+//! earlier and a positive one later. Workflows whose boosted priorities
+//! tie are ordered by the engine's [`PriorityTieBreak`]: by id under
+//! [`PriorityTieBreak::Id`], otherwise in engine order. A boost never moves
+//! a workflow ahead of a workflow named in its `depends_on`.
+//!
+//! The boost is read once, when processing starts (after `before_message`
+//! hooks), so a task writing it does not reorder the message's own run.
+//! Entries naming unknown workflows or holding anything but a whole number
+//! are ignored. Messages without a boost run in engine order without extra
+//! work.
+
+use crate::engine::message::Message;
+use crate::engine::workflow::{PriorityTieBreak, Workflow};
+use std::collections::HashMap;
+
+/// Order of `candidates` (indices into the priority-sorted `workflows`)
+/// for `message`, or `None` when the message carries no boost and engine
+/// order applies.
+pub(crate) fn boosted_order(
+    workflows: &[Workflow],
+    candidates: impl IntoIterator<Item = usize>,
+    message: &Message,
+    tie_break: PriorityTieBreak,
+) -> Option<Vec<usize>> {
+    let boosts = message
+        .context
+        .get("metadata")?
+        .get("routing")?
+        .get("priority_boost")?
+        .as_object()?;
+    if boosts.is_empty() {
+        return None;
+    }
+    let effective = |idx: usize| {
+        let workflow = &workflows[idx];
+        let boost = boosts
+            .iter()
+            .find(|(id, _)| *id == workflow.id)
+            .and_then(|(_, boost)| boost.as_i64())
+            .unwrap_or(0);
+        i64::from(workflow.priority).saturating_add(boost)
+    };
+    let mut waiting: Vec<(i64, usize)> = candidates
+        .into_iter()
+        .map(|idx| (effective(idx), idx))
+        .collect();
+    waiting.sort_by(|(a, a_idx), (b, b_idx)| {
+        let by_id = || match tie_break {
+            PriorityTieBreak::Id => workflows[*a_idx].id.cmp(&workflows[*b_idx].id),
+            PriorityTieBreak::DeclarationOrder => std::cmp::Ordering::Equal,
+        };
+        a.cmp(b).then_with(by_id).then(a_idx.cmp(b_idx))
+    });
+
+    // Honour `depends_on` among the candidates, as engine order does: the
+    // earliest waiting workflow whose dependencies have all run goes next.
+    let mut unplaced: HashMap<&str, usize> = HashMap::new();
+    for &(_, idx) in &waiting {
+        *unplaced.entry(workflows[idx].id.as_str()).or_default() += 1;
+    }
+    let mut order = Vec::with_capacity(waiting.len());
+    while !waiting.is_empty() {
+        let ready = waiting
+            .iter()
+            .position(|&(_, idx)| {
+                workflows[idx]
+                    .depends_on
+                    .iter()
+                    .all(|dep| unplaced.get(dep.as_str()).is_none_or(|left| *left == 0))
+            })
+            // Engine order already satisfies every dependency, so a ready
+            // workflow always exists; fall back to the first regardless.
+            .unwrap_or(0);
+        let (_, idx) = waiting.remove(ready);
+        if let Some(left) = unplaced.get_mut(workflows[idx].id.as_str()) {
+            *left -= 1;
+        }
+        order.push(idx);
+    }
+    Some(order)
+}
+
+/// Iterator over workflow indices in a message's order: the boosted order
+/// when there is one, engine order otherwise.
+pub(crate) struct Order<'a> {
+    boosted: Option<&'a [usize]>,
+    pos: usize,
+    len: usize,
+}
+
+impl<'a> Order<'a> {
+    pub(crate) fn new(boosted: Option<&'a [usize]>, len: usize) -> Self {
+        Self {
+            boosted,
+            pos: 0,
+            len: boosted.map_or(len, <[usize]>::len),
+        }
+    }
+}
+
+impl Iterator for Order<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.pos == self.len {
+            return None;
+        }
+        let idx = self.boosted.map_or(self.pos, |order| order[self.pos]);
+        self.pos += 1;
+        Some(idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workflows(specs: &[(&str, u32, &[&str])]) -> Vec<Workflow> {
+        specs
+            .iter()
+            .map(|(id, priority, depends_on)| {
+                Workflow::from_json(
+                    &json!({"id": id, "name": id, "priority": priority, "depends_on": depends_on,
+                        "tasks": [{"id": "t", "name": "T", "function": {"name": "log", "input": {"message": "x"}}}]})
+                    .to_string(),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn message(boost: serde_json::Value) -> Message {
+        Message::builder()
+            .metadata_json(&json!({"routing": {"priority_boost": boost}}))
+            .build()
+    }
+
+    #[test]
+    fn boosts_reorder_workflows_for_one_message() {
+        let wfs = workflows(&[
+            ("a", 1, &[]),
+            ("b", 2, &[]),
+            ("c", 3, &["a"]),
+            ("d", 3, &[]),
+        ]);
+        let order = |boost, tie_break| {
+            boosted_order(&wfs, 0..wfs.len(), &message(boost), tie_break).map(|order| {
+                order
+                    .into_iter()
+                    .map(|i| wfs[i].id.as_str())
+                    .collect::<Vec<_>>()
+            })
+        };
+        let declared = PriorityTieBreak::DeclarationOrder;
+
+        assert_eq!(
+            order(json!({"d": -10}), declared).unwrap(),
+            ["d", "a", "b", "c"]
+        );
+        // `c` depends on `a`, so boosting it cannot overtake `a`.
+        assert_eq!(
+            order(json!({"c": -10}), declared).unwrap(),
+            ["a", "c", "b", "d"]
+        );
+        // Ties fall back to engine order, or to ids.
+        assert_eq!(
+            order(json!({"d": -1}), declared).unwrap(),
+            ["a", "b", "d", "c"]
+        );
+        assert_eq!(
+            order(json!({"d": -1, "c": -1}), PriorityTieBreak::Id).unwrap(),
+            ["a", "b", "c", "d"]
+        );
+        assert_eq!(
+            order(json!({"zzz": "soon"}), declared).unwrap(),
+            ["a", "b", "c", "d"]
+        );
+        assert!(order(json!({}), declared).is_none());
+        assert!(boosted_order(&wfs, 0..4, &Message::builder().build(), declared).is_none());
+    }
+
+    #[test]
+    fn order_iterates_boosted_or_engine_order() {
+        assert_eq!(Order::new(None, 3).collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(Order::new(Some(&[2, 0]), 3).collect::<Vec<_>>(), [2, 0]);
+    }
+}
@@ -0,0 +1,189 @@
+//! # Rule Packs
+//!
+//! Named, versioned sets of validation rules shipped independently of
+//! workflow definitions. Packs are registered on the engine at construction
+//! ([`EngineBuilder::with_rule_pack`](crate::EngineBuilder::with_rule_pack))
+//! and referenced from `validation` tasks by name:
+//!
+//! ```json
+//! {"name": "validation", "input": {"rules_ref": "sepa_core_v2"}}
+//! ```
+//!
+//! A pack file uses the same rule syntax as an inline `rules` array
+//! (including `required` and `aggregate` rules):
+//!
+//! ```json
+//! {
+//!     "name": "sepa_core_v2",
+//!     "version": "2.1.0",
+//!     "rules": [
+//!         {"type": "required", "paths": ["data.header.msg_id"]}
+//!     ]
+//! }
+//! ```
+//!
+//! Packs are loaded from JSON strings or files. Packs hosted behind a URL
+//! are fetched by the caller with its own HTTP client and passed to
+//! [`RulePack::from_json`].
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::functions::ValidationConfig;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Registered packs, keyed by name. Shared by the engines produced from one
+/// builder (including hot reloads).
+pub(crate) type RulePacks = Arc<HashMap<String, Arc<RulePack>>>;
+
+/// A named set of validation rules.
+#[derive(Debug, Clone)]
+pub struct RulePack {
+    /// Name used in `rules_ref`.
+    pub name: String,
+    /// Free-form version label, for diagnostics.
+    pub version: Option<String>,
+    /// Human-readable description.
+    pub description: Option<String>,
+    /// Parsed rules. `rules_ref` is always empty: packs cannot reference
+    /// other packs.
+    pub validation: ValidationConfig,
+}
+
+#[derive(Deserialize)]
+struct RawRulePack {
+    name: String,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    rules: Vec<Value>,
+}
+
+impl RulePack {
+    /// Parse a pack from a JSON string.
+    pub fn from_json(json_str: &str) -> Result<Self> {
+        let raw: RawRulePack = serde_json::from_str(json_str).map_err(DataflowError::from_serde)?;
+        if raw.name.is_empty() {
+            return Err(DataflowError::Validation(
+                "Rule pack name cannot be empty".to_string(),
+            ));
+        }
+        let validation = ValidationConfig::from_json(&json!({"rules": raw.rules}))
+            .map_err(|e| DataflowError::Validation(format!("Rule pack '{}': {}", raw.name, e)))?;
+        Ok(Self {
+            name: raw.name,
+            version: raw.version,
+            description: raw.description,
+            validation,
+        })
+    }
+
+    /// Load a pack from a JSON file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let json_str = fs::read_to_string(path).map_err(DataflowError::from_io)?;
+        Self::from_json(&json_str)
+    }
+
+    /// Load every `*.json` file in `dir` as a pack, sorted by file name.
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(DataflowError::from_io)? {
+            let path = entry.map_err(DataflowError::from_io)?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        paths.into_iter().map(Self::from_file).collect()
+    }
+}
+
+/// Merge the packs named in `config.rules_ref` into `config`, pack rules
+/// first and in reference order. Empties `rules_ref`, so resolving twice is
+/// a no-op.
+pub(crate) fn resolve_rule_refs(
+    config: &mut ValidationConfig,
+    packs: &HashMap<String, Arc<RulePack>>,
+    task_id: &str,
+    workflow_id: &str,
+) -> Result<()> {
+    if config.rules_ref.is_empty() {
+        return Ok(());
+    }
+    let mut rules = Vec::new();
+    let mut aggregates = Vec::new();
+    for name in std::mem::take(&mut config.rules_ref) {
+        let pack = packs.get(&name).ok_or_else(|| {
+            DataflowError::Validation(format!(
+                "Unknown rule pack '{}' referenced by task {} in workflow {}",
+                name, task_id, workflow_id
+            ))
+        })?;
+        rules.extend(pack.validation.rules.iter().cloned());
+        aggregates.extend(pack.validation.aggregates.iter().cloned());
+    }
+    rules.append(&mut config.rules);
+    aggregates.append(&mut config.aggregates);
+    config.rules = rules;
+    config.aggregates = aggregates;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(json: Value) -> RulePack {
+        RulePack::from_json(&json.to_string()).unwrap()
+    }
+
+    #[test]
+    fn parses_pack_with_shorthand_rules() {
+        let p = pack(json!({
+            "name": "core",
+            "version": "1.0",
+            "rules": [
+                {"type": "required", "paths": ["data.a", "data.b"]},
+                {"logic": true, "message": "ok"}
+            ]
+        }));
+        assert_eq!(p.name, "core");
+        assert_eq!(p.version.as_deref(), Some("1.0"));
+        assert_eq!(p.validation.rules.len(), 3);
+    }
+
+    #[test]
+    fn rejects_unnamed_or_malformed_packs() {
+        assert!(RulePack::from_json(r#"{"name": "", "rules": []}"#).is_err());
+        assert!(
+            RulePack::from_json(r#"{"name": "x", "rules": [{"message": "no logic"}]}"#).is_err()
+        );
+        assert!(RulePack::from_json(r#"{"name": "x"}"#).is_err());
+    }
+
+    #[test]
+    fn resolve_prepends_pack_rules_in_order() {
+        let mut packs = HashMap::new();
+        for name in ["a", "b"] {
+            let p = pack(json!({"name": name, "rules": [{"logic": true, "message": name}]}));
+            packs.insert(name.to_string(), Arc::new(p));
+        }
+        let mut config = ValidationConfig::from_json(&json!({
+            "rules_ref": ["b", "a"],
+            "rules": [{"logic": true, "message": "inline"}]
+        }))
+        .unwrap();
+
+        resolve_rule_refs(&mut config, &packs, "t", "w").unwrap();
+        let messages: Vec<_> = config.rules.iter().map(|r| r.message.as_str()).collect();
+        assert_eq!(messages, vec!["b", "a", "inline"]);
+        assert!(config.rules_ref.is_empty());
+
+        config.rules_ref = vec!["missing".to_string()];
+        assert!(resolve_rule_refs(&mut config, &packs, "t", "w").is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! # Secrets
+//!
+//! Named secrets the engine needs at run time (such as the audit signing
+//! key) come from a [`SecretsResolver`] registered with
+//! [`EngineBuilder::with_secrets_resolver`](crate::EngineBuilder::with_secrets_resolver),
+//! so they never appear in workflow or option documents. Configuration
+//! refers to a secret by name only.
+//!
+//! Secrets are resolved each time they are used, so a resolver backed by a
+//! vault or a mounted file picks up rotations without restarting.
+//!
+//! Task inputs refer to secrets with `${secret:NAME}` placeholders — an API
+//! key in an `http_call` header, say — filled in with the other
+//! [placeholders](crate::engine::interpolation) when the engine is built:
+//!
+//! ```json
+//! {"name": "http_call", "input": {
+//!     "connector": "geo",
+//!     "headers": {"Authorization": "Bearer ${secret:GEO_API_KEY}"}
+//! }}
+//! ```
+//!
+//! An unresolvable secret always fails engine construction. Resolved
+//! values are replaced by `[REDACTED]` wherever they turn up in audit
+//! trail changes and in execution traces (message snapshots, mapping
+//! contexts and error messages). Placeholders are resolved once per
+//! workflow definition: rebuilding with `with_new_workflows` keeps the
+//! values already resolved, so reload workflows from their source to pick
+//! up a rotated secret. [`EnvSecretsResolver`] reads secrets from
+//! environment variables.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::{AuditTrail, Message};
+use crate::engine::trace::ExecutionStep;
+use datavalue::OwnedDataValue;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Text that replaces a secret value in audit trails and traces.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Source of named secrets.
+pub trait SecretsResolver: Send + Sync {
+    /// The secret called `name`. An unknown name is an error.
+    fn resolve(&self, name: &str) -> Result<String>;
+}
+
+impl<T: SecretsResolver + ?Sized> SecretsResolver for Arc<T> {
+    fn resolve(&self, name: &str) -> Result<String> {
+        (**self).resolve(name)
+    }
+}
+
+/// Resolves each secret from the environment variable of the same name,
+/// optionally prefixed: with prefix `APP_`, secret `DB_PASSWORD` is read
+/// from `APP_DB_PASSWORD`.
+#[derive(Debug, Clone, Default)]
+pub struct EnvSecretsResolver {
+    prefix: String,
+}
+
+impl EnvSecretsResolver {
+    /// Resolve secrets from identically named environment variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve secrets from environment variables named `prefix` + name.
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl SecretsResolver for EnvSecretsResolver {
+    fn resolve(&self, name: &str) -> Result<String> {
+        let variable = format!("{}{}", self.prefix, name);
+        std::env::var(&variable).map_err(|_| {
+            DataflowError::Workflow(format!(
+                "Unknown secret '{name}': environment variable {variable} is not set"
+            ))
+        })
+    }
+}
+
+/// Secret values resolved into task inputs, hidden from audit trails and
+/// traces.
+#[derive(Debug, Default)]
+pub(crate) struct Redactor {
+    /// Longest first, so a secret containing another is replaced whole.
+    values: Vec<String>,
+}
+
+impl Redactor {
+    pub(crate) fn new(values: impl IntoIterator<Item = String>) -> Self {
+        let mut values: Vec<String> = values.into_iter().filter(|v| !v.is_empty()).collect();
+        values.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        values.dedup();
+        Self { values }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub(crate) fn values(&self) -> &[String] {
+        &self.values
+    }
+
+    /// Redact the old and new values of `entry`'s changes.
+    pub(crate) fn redact_audit(&self, entry: &mut AuditTrail) {
+        for change in &mut entry.changes {
+            self.redact_data(&mut change.old_value);
+            self.redact_data(&mut change.new_value);
+        }
+    }
+
+    /// Redact the message snapshot and mapping contexts of `step`.
+    pub(crate) fn redact_step(&self, step: &mut ExecutionStep) {
+        if let Some(message) = &mut step.message {
+            self.redact_message(message);
+        }
+        for context in step.mapping_contexts.iter_mut().flatten() {
+            self.redact_json(context);
+        }
+    }
+
+    fn redact_message(&self, message: &mut Message) {
+        // Its audit trail was redacted as it was recorded.
+        self.redact_data(&mut message.context);
+        for error in &mut message.errors {
+            self.redact_str(&mut error.message);
+        }
+    }
+
+    fn redact_data(&self, value: &mut OwnedDataValue) {
+        match value {
+            OwnedDataValue::String(s) => self.redact_str(s),
+            OwnedDataValue::Array(items) => items.iter_mut().for_each(|v| self.redact_data(v)),
+            OwnedDataValue::Object(fields) => {
+                fields.iter_mut().for_each(|(_, v)| self.redact_data(v))
+            }
+            _ => {}
+        }
+    }
+
+    fn redact_json(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => self.redact_str(s),
+            Value::Array(items) => items.iter_mut().for_each(|v| self.redact_json(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.redact_json(v)),
+            _ => {}
+        }
+    }
+
+    fn redact_str(&self, s: &mut String) {
+        for secret in &self.values {
+            if s.contains(secret.as_str()) {
+                *s = s.replace(secret.as_str(), REDACTED);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn env_resolver_reads_prefixed_variables() {
+        assert_eq!(
+            EnvSecretsResolver::new().resolve("CARGO_PKG_NAME").unwrap(),
+            "dataflow-rs"
+        );
+        assert_eq!(
+            EnvSecretsResolver::with_prefix("CARGO_PKG_")
+                .resolve("NAME")
+                .unwrap(),
+            "dataflow-rs"
+        );
+        let err = EnvSecretsResolver::with_prefix("DATAFLOW_UNSET_")
+            .resolve("KEY")
+            .unwrap_err();
+        assert!(err.to_string().contains("DATAFLOW_UNSET_KEY"), "{err}");
+    }
+
+    #[test]
+    fn redacts_every_occurrence_longest_first() {
+        let redactor = Redactor::new(["abc".to_string(), "abcdef".to_string(), String::new()]);
+        let mut value = json!({"k": ["x abcdef y", "abc-abc"], "n": 1});
+        redactor.redact_json(&mut value);
+        assert_eq!(
+            value,
+            json!({"k": ["x [REDACTED] y", "[REDACTED]-[REDACTED]"], "n": 1})
+        );
+    }
+}
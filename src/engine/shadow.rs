@@ -0,0 +1,202 @@
+//! # Shadow Execution
+//!
+//! Runs the same input through the live engine and a candidate ("shadow")
+//! engine and reports how their outputs differ. Used to validate new
+//! mappings against live traffic before switching over: the caller keeps
+//! using [`ShadowDiff::primary`] and logs or counts the differences.
+//!
+//! The candidate runs as a [dry run](crate::engine::dry_run), so shadow
+//! traffic has no side effects: its tasks that may have any (`http_call`,
+//! `publish_kafka`, custom handlers, …) are held back and listed in
+//! [`ShadowDiff::shadow_held_back`], and no sink, quarantine or dead-letter
+//! handler sees its result. Data those tasks write on the live side shows
+//! up as a difference.
+//!
+//! Produced by [`Engine::process_shadow`](crate::Engine::process_shadow).
+
+use crate::engine::dry_run::{DryRunReport, HeldBackTask};
+use crate::engine::error::{DataflowError, ErrorInfo};
+use crate::engine::message::Message;
+use datavalue::OwnedDataValue;
+use serde::Serialize;
+
+/// One differing location between the primary and shadow results.
+/// `None` means the path is absent on that side.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueDiff {
+    /// Dot-path relative to the compared root (`""` for the root itself).
+    pub path: String,
+    pub primary: Option<OwnedDataValue>,
+    pub shadow: Option<OwnedDataValue>,
+}
+
+/// Result of [`Engine::process_shadow`](crate::Engine::process_shadow).
+#[derive(Debug, Clone, Serialize)]
+pub struct ShadowDiff {
+    /// The message as processed by the primary engine. This is the result
+    /// callers should act on.
+    pub primary: Message,
+    /// The message as the shadow engine's dry run left it.
+    pub shadow: Message,
+    /// Error returned by the primary engine's `process_message`, if any.
+    pub primary_error: Option<DataflowError>,
+    /// Error that stopped the shadow engine's dry run, if any.
+    pub shadow_error: Option<DataflowError>,
+    /// Shadow tasks held back because they may have side effects.
+    pub shadow_held_back: Vec<HeldBackTask>,
+    /// Structural differences in `data`, as paths relative to `data`.
+    pub data: Vec<ValueDiff>,
+    /// Errors recorded by the primary but not the shadow (by code, path and
+    /// message).
+    pub errors_only_in_primary: Vec<ErrorInfo>,
+    /// Errors recorded by the shadow but not the primary.
+    pub errors_only_in_shadow: Vec<ErrorInfo>,
+}
+
+impl ShadowDiff {
+    pub(crate) fn new(
+        primary: Message,
+        primary_error: Option<DataflowError>,
+        shadow: DryRunReport,
+    ) -> Self {
+        let DryRunReport {
+            message: shadow,
+            held_back: shadow_held_back,
+            error: shadow_error,
+            ..
+        } = shadow;
+        let data = diff_values(primary.data(), shadow.data());
+        let errors_only_in_primary = errors_missing_from(primary.errors(), shadow.errors());
+        let errors_only_in_shadow = errors_missing_from(shadow.errors(), primary.errors());
+        Self {
+            primary,
+            shadow,
+            primary_error,
+            shadow_error,
+            shadow_held_back,
+            data,
+            errors_only_in_primary,
+            errors_only_in_shadow,
+        }
+    }
+
+    /// Whether both engines produced the same data, the same errors and the
+    /// same overall outcome.
+    pub fn is_identical(&self) -> bool {
+        self.data.is_empty()
+            && self.errors_only_in_primary.is_empty()
+            && self.errors_only_in_shadow.is_empty()
+            && self.primary_error.is_some() == self.shadow_error.is_some()
+    }
+}
+
+/// Errors in `left` with no counterpart (same code, path and message) in
+/// `right`. Timestamps and workflow/task ids are ignored.
+fn errors_missing_from(left: &[ErrorInfo], right: &[ErrorInfo]) -> Vec<ErrorInfo> {
+    left.iter()
+        .filter(|l| {
+            !right
+                .iter()
+                .any(|r| r.code == l.code && r.path == l.path && r.message == l.message)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Structural diff of two values. Objects are compared key by key
+/// (order-insensitive), arrays element by element; any other mismatch is
+/// reported at the deepest common path.
+pub(crate) fn diff_values(primary: &OwnedDataValue, shadow: &OwnedDataValue) -> Vec<ValueDiff> {
+    let mut out = Vec::new();
+    diff_into(String::new(), primary, shadow, &mut out);
+    out
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn diff_into(path: String, a: &OwnedDataValue, b: &OwnedDataValue, out: &mut Vec<ValueDiff>) {
+    match (a, b) {
+        (OwnedDataValue::Object(left), OwnedDataValue::Object(right)) => {
+            for (key, lv) in left {
+                match right.iter().find(|(k, _)| k == key) {
+                    Some((_, rv)) => diff_into(join(&path, key), lv, rv, out),
+                    None => out.push(ValueDiff {
+                        path: join(&path, key),
+                        primary: Some(lv.clone()),
+                        shadow: None,
+                    }),
+                }
+            }
+            for (key, rv) in right {
+                if !left.iter().any(|(k, _)| k == key) {
+                    out.push(ValueDiff {
+                        path: join(&path, key),
+                        primary: None,
+                        shadow: Some(rv.clone()),
+                    });
+                }
+            }
+        }
+        (OwnedDataValue::Array(left), OwnedDataValue::Array(right)) => {
+            for i in 0..left.len().max(right.len()) {
+                let child = join(&path, &i.to_string());
+                match (left.get(i), right.get(i)) {
+                    (Some(lv), Some(rv)) => diff_into(child, lv, rv, out),
+                    (lv, rv) => out.push(ValueDiff {
+                        path: child,
+                        primary: lv.cloned(),
+                        shadow: rv.cloned(),
+                    }),
+                }
+            }
+        }
+        _ if a == b => {}
+        _ => out.push(ValueDiff {
+            path,
+            primary: Some(a.clone()),
+            shadow: Some(b.clone()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn dv(v: serde_json::Value) -> OwnedDataValue {
+        OwnedDataValue::from(&v)
+    }
+
+    #[test]
+    fn diff_reports_changed_added_and_removed_paths() {
+        let diffs = diff_values(
+            &dv(json!({"a": 1, "b": {"c": [1, 2], "d": "x"}, "gone": true})),
+            &dv(json!({"b": {"d": "x", "c": [1, 3, 4]}, "a": 1, "new": null})),
+        );
+        let paths: Vec<_> = diffs.iter().map(|d| d.path.as_str()).collect();
+        assert_eq!(paths, vec!["b.c.1", "b.c.2", "gone", "new"]);
+        assert_eq!(diffs[1].primary, None);
+        assert_eq!(diffs[1].shadow, Some(dv(json!(4))));
+        assert_eq!(diffs[2].shadow, None);
+    }
+
+    #[test]
+    fn identical_values_have_no_diff() {
+        let v = dv(json!({"a": [{"b": 1}], "c": "d"}));
+        assert!(diff_values(&v, &v.clone()).is_empty());
+    }
+
+    #[test]
+    fn type_change_is_reported_at_the_changed_node() {
+        let diffs = diff_values(&dv(json!({"a": {"b": 1}})), &dv(json!({"a": [1]})));
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(diffs[0].path, "a");
+    }
+}
@@ -0,0 +1,119 @@
+//! # Shared Engine
+//!
+//! A [`SharedEngine`] is the swappable engine a long-running service
+//! processes messages with: callers take the [`current`](SharedEngine::current)
+//! engine per message, while reloads and workflow toggles publish a new one
+//! built with [`Engine::with_new_workflows`]. Messages already in flight
+//! finish on the engine they started with.
+//!
+//! [`disable`](SharedEngine::disable) takes a workflow out of the engine
+//! altogether, unlike `status: paused`, which only removes it from channel
+//! routing. The definition is kept so [`enable`](SharedEngine::enable) can
+//! put it back, and a [`reload`](SharedEngine::reload) keeps disabled ids
+//! out of the new engine, storing their latest definition.
+
+use crate::engine::Engine;
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::workflow::Workflow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Swappable engine handle; clones share the same engine.
+#[derive(Clone)]
+pub struct SharedEngine {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    current: RwLock<Arc<Engine>>,
+    /// Disabled workflows by id. Also serialises rebuilds, so concurrent
+    /// toggles don't publish engines built from stale workflow sets.
+    disabled: Mutex<BTreeMap<String, Workflow>>,
+}
+
+impl SharedEngine {
+    pub fn new(engine: impl Into<Arc<Engine>>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                current: RwLock::new(engine.into()),
+                disabled: Mutex::new(BTreeMap::new()),
+            }),
+        }
+    }
+
+    /// The engine new messages should use.
+    pub fn current(&self) -> Arc<Engine> {
+        Arc::clone(&self.inner.current.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Replace the workflow set, e.g. with a
+    /// [`WorkflowWatcher`](crate::WorkflowWatcher) result. Disabled ids stay
+    /// out. On error the current engine is kept.
+    pub fn reload(&self, workflows: Vec<Workflow>) -> Result<()> {
+        let mut disabled = self.disabled();
+        let (off, on): (Vec<_>, Vec<_>) = workflows
+            .into_iter()
+            .partition(|workflow| disabled.contains_key(&workflow.id));
+        self.publish(on)?;
+        for workflow in off {
+            disabled.insert(workflow.id.clone(), workflow);
+        }
+        Ok(())
+    }
+
+    /// Take workflow `id` out of the engine. Fails when the engine has no
+    /// such workflow.
+    pub fn disable(&self, id: &str) -> Result<()> {
+        let mut disabled = self.disabled();
+        let mut workflows = self.current().workflows().as_ref().clone();
+        let idx = workflows
+            .iter()
+            .position(|workflow| workflow.id == id)
+            .ok_or_else(|| DataflowError::Workflow(format!("No enabled workflow '{id}'")))?;
+        let workflow = workflows.remove(idx);
+        self.publish(workflows)?;
+        disabled.insert(workflow.id.clone(), workflow);
+        Ok(())
+    }
+
+    /// Put disabled workflow `id` back. Fails when it isn't disabled.
+    pub fn enable(&self, id: &str) -> Result<()> {
+        let mut disabled = self.disabled();
+        let workflow = disabled
+            .get(id)
+            .cloned()
+            .ok_or_else(|| DataflowError::Workflow(format!("No disabled workflow '{id}'")))?;
+        let mut workflows = self.current().workflows().as_ref().clone();
+        workflows.push(workflow);
+        self.publish(workflows)?;
+        disabled.remove(id);
+        Ok(())
+    }
+
+    /// Ids of the disabled workflows, sorted.
+    pub fn disabled_ids(&self) -> Vec<String> {
+        self.disabled().keys().cloned().collect()
+    }
+
+    /// The definition of disabled workflow `id`.
+    pub fn disabled_workflow(&self, id: &str) -> Option<Workflow> {
+        self.disabled().get(id).cloned()
+    }
+
+    fn publish(&self, workflows: Vec<Workflow>) -> Result<()> {
+        let engine = self.current().with_new_workflows(workflows)?;
+        *self
+            .inner
+            .current
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = Arc::new(engine);
+        Ok(())
+    }
+
+    fn disabled(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Workflow>> {
+        self.inner
+            .disabled
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,309 @@
+//! # Sinks
+//!
+//! Sends processed messages on to where they go next. A workflow lists its
+//! destinations under `output`, each naming a [`SinkHandler`] registered
+//! with [`EngineBuilder::with_sink`](crate::EngineBuilder::with_sink) and,
+//! optionally, the `path` of the value to send instead of the whole
+//! message:
+//!
+//! ```json
+//! {
+//!     "id": "orders",
+//!     "name": "Orders",
+//!     "tasks": [...],
+//!     "output": [
+//!         {"sink": "archive"},
+//!         {"sink": "billing", "path": "data.invoice"}
+//!     ]
+//! }
+//! ```
+//!
+//! Once a message is processed without error, every workflow that ran
+//! hands the final message — after all workflows and `after_message`
+//! hooks, with its audit trail sealed — to each of its outputs, in the
+//! order the workflows ran. A `path` that resolves to nothing sends
+//! nothing. A failed or cancelled message goes to no sink.
+//!
+//! Outputs are sent by [`Engine::process_message`](crate::Engine::process_message)
+//! and the paths built on it (batches, streams, sources),
+//! [`Engine::process_message_for_channel`](crate::Engine::process_message_for_channel),
+//! [`Engine::resume_message`](crate::Engine::resume_message) and
+//! [`Engine::reprocess_from_audit`](crate::Engine::reprocess_from_audit).
+//! Trace and diagnostic runs and embedded workflow tests send nothing.
+//!
+//! Every output is attempted. When one fails, the message gets a
+//! `SINK_ERROR` and processing returns the first sink error, so a source
+//! can redeliver it and the dead-letter handler sees it as any other
+//! failure. A workflow naming a sink the engine doesn't have fails at
+//! build.
+//!
+//! [`StdoutSink`] and [`NdjsonFileSink`] ship with the engine, and
+//! [`InMemorySink`] for tests. Kafka, HTTP or database sinks belong to the
+//! service: implement [`SinkHandler`] and register it by name.
+
+use crate::engine::error::{DataflowError, ErrorInfo, Result, invalid};
+use crate::engine::message::Message;
+use crate::engine::utils::get_nested_value;
+use crate::engine::workflow::Workflow;
+use async_trait::async_trait;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// One entry of a workflow's `output`: where to send the processed
+/// message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkflowOutput {
+    /// Name the sink was registered under.
+    pub sink: String,
+    /// Dotted path into the message context (e.g. `data.invoice`) of the
+    /// value to send. Defaults to the whole message.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+/// What a [`SinkHandler`] receives for one output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SinkRecord {
+    /// Workflow whose `output` named the sink.
+    pub workflow_id: String,
+    pub message_id: String,
+    /// The serialized message, or the value at the output's `path`.
+    pub payload: Value,
+}
+
+/// Destination for processed messages. Implement this to publish to a
+/// topic, post to an endpoint or insert into a table.
+///
+/// If `send` returns `Err`, the message is considered undelivered: the
+/// engine records a `SINK_ERROR` and returns the error.
+#[async_trait]
+pub trait SinkHandler: Send + Sync {
+    async fn send(&self, record: SinkRecord) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: SinkHandler + ?Sized> SinkHandler for Arc<T> {
+    async fn send(&self, record: SinkRecord) -> Result<()> {
+        (**self).send(record).await
+    }
+}
+
+/// `SinkHandler` that writes each payload to standard output as one line
+/// of JSON.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdoutSink;
+
+#[async_trait]
+impl SinkHandler for StdoutSink {
+    async fn send(&self, record: SinkRecord) -> Result<()> {
+        let line = serde_json::to_string(&record.payload).map_err(DataflowError::from_serde)?;
+        writeln!(std::io::stdout().lock(), "{line}").map_err(DataflowError::from_io)
+    }
+}
+
+/// `SinkHandler` that appends each payload to a file as one line of JSON
+/// (NDJSON). The file is created on first use.
+#[derive(Debug)]
+pub struct NdjsonFileSink {
+    path: PathBuf,
+    write_lock: Mutex<()>,
+}
+
+impl NdjsonFileSink {
+    /// Sink writing to the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Path of the backing file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every payload in the file, oldest first. A missing file reads as
+    /// empty.
+    pub fn read_all(&self) -> Result<Vec<Value>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(DataflowError::from_io(e)),
+        };
+        BufReader::new(file)
+            .lines()
+            .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+            .map(|line| {
+                let line = line.map_err(DataflowError::from_io)?;
+                serde_json::from_str(&line).map_err(DataflowError::from_serde)
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SinkHandler for NdjsonFileSink {
+    async fn send(&self, record: SinkRecord) -> Result<()> {
+        let mut line = serde_json::to_string(&record.payload).map_err(DataflowError::from_serde)?;
+        line.push('\n');
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(DataflowError::from_io)
+    }
+}
+
+/// `SinkHandler` that keeps records in memory. Handy for tests.
+#[derive(Debug, Default)]
+pub struct InMemorySink {
+    records: Mutex<Vec<SinkRecord>>,
+}
+
+impl InMemorySink {
+    /// Create an empty sink.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Snapshot of every record received so far.
+    pub fn records(&self) -> Vec<SinkRecord> {
+        self.records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Remove and return every record received so far.
+    pub fn drain(&self) -> Vec<SinkRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+#[async_trait]
+impl SinkHandler for InMemorySink {
+    async fn send(&self, record: SinkRecord) -> Result<()> {
+        self.records
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(record);
+        Ok(())
+    }
+}
+
+/// Registered sinks, by name. Shared by `Arc` with engines produced by
+/// `Engine::with_new_workflows`.
+pub(crate) type Sinks = Arc<HashMap<String, Arc<dyn SinkHandler>>>;
+
+/// Fail when a workflow's `output` names a sink that isn't registered.
+pub(crate) fn validate_outputs(workflows: &[Workflow], sinks: &Sinks) -> Result<()> {
+    for workflow in workflows {
+        for (idx, output) in workflow.output.iter().enumerate() {
+            if !sinks.contains_key(&output.sink) {
+                return Err(invalid(format!("unknown sink '{}'", output.sink))
+                    .in_workflow(&workflow.id)
+                    .within(&format!("/output/{idx}/sink"))
+                    .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Send `message` to the outputs of the workflows at `ran`, in order.
+/// Every output is attempted; a failure is recorded on the message and
+/// the first one returned.
+pub(crate) async fn dispatch(
+    sinks: &Sinks,
+    workflows: &[Workflow],
+    ran: &[usize],
+    message: &mut Message,
+) -> Result<()> {
+    let mut whole = None;
+    let mut first_error = None;
+    for workflow in ran.iter().map(|&idx| &workflows[idx]) {
+        for output in &workflow.output {
+            let payload = match &output.path {
+                Some(path) => match get_nested_value(&message.context, path) {
+                    Some(value) => Value::from(value),
+                    None => {
+                        debug!(
+                            "Nothing at {} for sink {} of workflow {}",
+                            path, output.sink, workflow.id
+                        );
+                        continue;
+                    }
+                },
+                // Serialized once, however many outputs send the whole message.
+                None => match &whole {
+                    Some(value) => Value::clone(value),
+                    None => {
+                        let value =
+                            serde_json::to_value(&*message).map_err(DataflowError::from_serde)?;
+                        whole = Some(value.clone());
+                        value
+                    }
+                },
+            };
+            let Some(sink) = sinks.get(&output.sink) else {
+                continue;
+            };
+            let record = SinkRecord {
+                workflow_id: workflow.id.clone(),
+                message_id: message.id().to_string(),
+                payload,
+            };
+            if let Err(e) = sink.send(record).await {
+                message.errors.push(
+                    ErrorInfo::builder(
+                        "SINK_ERROR",
+                        format!("Failed to send to sink {}: {}", output.sink, e),
+                    )
+                    .workflow_id(&workflow.id)
+                    .build(),
+                );
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn file_sink_appends_one_payload_per_line() {
+        let path =
+            std::env::temp_dir().join(format!("dataflow-sink-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sink = NdjsonFileSink::new(&path);
+        assert!(sink.read_all().unwrap().is_empty());
+
+        for n in 0..2 {
+            sink.send(SinkRecord {
+                workflow_id: "w".to_string(),
+                message_id: format!("m{n}"),
+                payload: serde_json::json!({"n": n}),
+            })
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(
+            sink.read_all().unwrap(),
+            [serde_json::json!({"n": 0}), serde_json::json!({"n": 1})]
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
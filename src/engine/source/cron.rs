@@ -0,0 +1,268 @@
+//! # Cron Source
+//!
+//! Emits an event on a cron schedule, for periodic jobs — nightly
+//! roll-ups, polling, reminders:
+//!
+//! ```no_run
+//! # use dataflow_rs::CronSource;
+//! # use serde_json::json;
+//! # fn demo() -> dataflow_rs::Result<()> {
+//! // Every 15 minutes during office hours on weekdays.
+//! let source = CronSource::new("*/15 9-17 * * 1-5", json!({"job": "poll"}))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Schedules use the five standard fields, evaluated in UTC: minute
+//! (0-59), hour (0-23), day of month (1-31), month (1-12) and day of week
+//! (0-7, both 0 and 7 being Sunday). Each field is `*`, a value, a range
+//! `a-b`, a step `*/n` or `a-b/n`, or a comma-separated list of those.
+//! When both day fields are restricted, a day matching either runs, as in
+//! cron.
+//!
+//! Each event carries the configured payload and `metadata.scheduled_at`,
+//! the RFC 3339 time it was due. A fire time missed while processing took
+//! longer than the interval is skipped rather than made up.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::source::{SourceEmitter, SourceEvent, SourceFunctionHandler};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+use log::warn;
+use serde_json::{Value, json};
+use tokio_util::sync::CancellationToken;
+
+/// Years searched for the next fire time before a schedule is deemed
+/// never to fire (e.g. `0 0 30 2 *`).
+const SEARCH_YEARS: i32 = 5;
+
+/// A parsed five-field cron expression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day-of-month / day-of-week field is `*`.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    /// Parse `expr`. See the [module docs](self) for the syntax.
+    pub fn parse(expr: &str) -> Result<Self> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(expr, "expected 5 fields"));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7).map_err(|e| invalid(expr, &e))?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59).map_err(|e| invalid(expr, &e))?,
+            hours: parse_field(hour, 0, 23).map_err(|e| invalid(expr, &e))?,
+            days: parse_field(day, 1, 31).map_err(|e| invalid(expr, &e))?,
+            months: parse_field(month, 1, 12).map_err(|e| invalid(expr, &e))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// First fire time strictly after `after`, or `None` when the schedule
+    /// never fires.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = after.year() + SEARCH_YEARS;
+        while t.year() <= limit {
+            if !has(self.months, t.month()) {
+                let (year, month) = match t.month() {
+                    12 => (t.year() + 1, 1),
+                    m => (t.year(), m + 1),
+                };
+                t = midnight(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.day_matches(t) {
+                t = midnight(t.date_naive().succ_opt()?);
+            } else if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + Duration::hours(1);
+            } else if !has(self.minutes, t.minute()) {
+                t += Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: DateTime<Utc>) -> bool {
+        let day = has(self.days, t.day());
+        let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+fn has(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+}
+
+fn invalid(expr: &str, reason: &str) -> DataflowError {
+    DataflowError::Validation(format!("invalid cron expression '{expr}': {reason}"))
+}
+
+/// Bit mask of the values `field` selects within `min..=max`.
+fn parse_field(field: &str, min: u32, max: u32) -> std::result::Result<u64, String> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("bad step in '{part}'"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let value = |text: &str| {
+            text.parse::<u32>()
+                .ok()
+                .filter(|v| (min..=max).contains(v))
+                .ok_or_else(|| format!("'{text}' is not in {min}-{max}"))
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if start > end {
+            return Err(format!("empty range '{range}'"));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+/// Source emitting `payload` on a [`CronSchedule`].
+#[derive(Debug, Clone)]
+pub struct CronSource {
+    schedule: CronSchedule,
+    payload: Value,
+}
+
+impl CronSource {
+    /// Source emitting `payload` whenever `expr` fires.
+    pub fn new(expr: &str, payload: Value) -> Result<Self> {
+        Ok(Self {
+            schedule: CronSchedule::parse(expr)?,
+            payload,
+        })
+    }
+
+    /// The parsed schedule.
+    pub fn schedule(&self) -> &CronSchedule {
+        &self.schedule
+    }
+}
+
+#[async_trait]
+impl SourceFunctionHandler for CronSource {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()> {
+        loop {
+            let Some(due) = self.schedule.next_after(Utc::now()) else {
+                return Err(DataflowError::Validation(format!(
+                    "cron source {} never fires",
+                    emitter.source()
+                )));
+            };
+            let wait = (due - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = shutdown.cancelled() => return Ok(()),
+                _ = tokio::time::sleep(wait) => {}
+            }
+            let event = SourceEvent::new(self.payload.clone())
+                .with_metadata(json!({"scheduled_at": due.to_rfc3339()}));
+            if let Err(e) = emitter.emit(event).await {
+                warn!(
+                    "Cron source {}: run due {} failed: {}",
+                    emitter.source(),
+                    due,
+                    e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().to_utc()
+    }
+
+    #[test]
+    fn finds_the_next_fire_time() {
+        let next = |expr: &str, after: &str| {
+            CronSchedule::parse(expr)
+                .unwrap()
+                .next_after(at(after))
+                .map(|t| t.to_rfc3339())
+        };
+        assert_eq!(
+            next("*/15 * * * *", "2026-03-10T10:07:30Z").unwrap(),
+            "2026-03-10T10:15:00+00:00"
+        );
+        assert_eq!(
+            next("0 2 * * *", "2026-03-10T02:00:00Z").unwrap(),
+            "2026-03-11T02:00:00+00:00"
+        );
+        // 2026-03-14 is a Saturday; weekdays skip to Monday.
+        assert_eq!(
+            next("30 9 * * 1-5", "2026-03-13T10:00:00Z").unwrap(),
+            "2026-03-16T09:30:00+00:00"
+        );
+        // Both day fields restricted: the 1st or any Sunday.
+        assert_eq!(
+            next("0 0 1 * 7", "2026-03-02T00:00:00Z").unwrap(),
+            "2026-03-08T00:00:00+00:00"
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2026-03-01T00:00:00Z").unwrap(),
+            "2028-02-29T00:00:00+00:00"
+        );
+        assert_eq!(next("0 0 30 2 *", "2026-03-01T00:00:00Z"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            let err = CronSchedule::parse(expr).unwrap_err();
+            assert!(
+                err.to_string().contains("invalid cron expression"),
+                "{expr}"
+            );
+        }
+    }
+}
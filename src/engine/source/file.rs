@@ -0,0 +1,509 @@
+//! # File Sources
+//!
+//! "Drop a file, run the pipeline" integrations without external tooling.
+//! [`FileTailSource`] follows a file like `tail -f` and processes each line
+//! appended to it; [`DirectorySource`] watches a directory and processes
+//! each file that lands in it:
+//!
+//! ```no_run
+//! # use dataflow_rs::{AfterProcessing, DirectorySource, FileFormat, FileTailSource, SharedEngine, SourceManager};
+//! # fn demo(shared: SharedEngine) {
+//! let runtime = SourceManager::new(shared)
+//!     .register("events", FileTailSource::new("/var/log/app/events.ndjson"))
+//!     .register(
+//!         "inbox",
+//!         DirectorySource::new("/data/inbox")
+//!             .with_extension("json")
+//!             .with_format(FileFormat::Lines)
+//!             .after_processing(AfterProcessing::MoveTo("/data/inbox/done".into())),
+//!     )
+//!     .start();
+//! # }
+//! ```
+//!
+//! Both poll the file system, every 500 ms by default
+//! ([`with_poll_interval`](FileTailSource::with_poll_interval)). Each line
+//! or file becomes a message whose payload is parsed as JSON when it is
+//! JSON and is the text otherwise; blank lines are skipped.
+//!
+//! ## Tailing
+//!
+//! The tail starts at the end of the file, or at its start with
+//! [`from_start`](FileTailSource::from_start), and processes each complete
+//! line added after that; a last line without its newline waits for it. A
+//! file that doesn't exist yet is waited for and read from its start. A
+//! file that shrinks, e.g. truncated or replaced by a rotation, is read
+//! again from its start. The position survives restarts of the source but
+//! not of the process. `metadata.file` holds the file's `path` and the
+//! byte `offset` of the line.
+//!
+//! ## Watching a Directory
+//!
+//! Every file in the directory (not below it) whose name has the configured
+//! extension is processed once, including files already there when the
+//! source starts, in name order. A file is picked up once its size stayed
+//! the same for a poll interval, so a file still being written is left
+//! alone. With [`FileFormat::Document`] (the default) the whole file is one
+//! message; with [`FileFormat::Lines`] each line is one. `metadata.file`
+//! holds the file's `path` and `name`, and the `line` number for lines.
+//!
+//! After its messages are processed, the file is kept, deleted or moved
+//! per [`AfterProcessing`]. Kept files are remembered so they aren't
+//! processed again while the process runs; delete or move them to be safe
+//! across restarts. A file with a message that returned an error is logged
+//! and left in place, without being processed again.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::source::{SourceEmitter, SourceEvent, SourceFunctionHandler, body_payload};
+use async_trait::async_trait;
+use log::warn;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wait `interval`; `false` when shut down meanwhile.
+async fn pause(shutdown: &CancellationToken, interval: Duration) -> bool {
+    tokio::select! {
+        _ = shutdown.cancelled() => false,
+        _ = tokio::time::sleep(interval) => true,
+    }
+}
+
+/// The non-blank lines of `bytes`, with the byte offset each starts at
+/// and its 1-based line number.
+fn lines(bytes: &[u8]) -> impl Iterator<Item = (usize, usize, &[u8])> {
+    let mut start = 0;
+    bytes
+        .split(|b| *b == b'\n')
+        .enumerate()
+        .filter_map(move |(idx, line)| {
+            let offset = start;
+            start += line.len() + 1;
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            (!line.trim_ascii().is_empty()).then_some((offset, idx + 1, line))
+        })
+}
+
+#[derive(Debug, Default)]
+struct TailState {
+    /// Bytes of the file consumed; `None` until the first poll.
+    offset: Option<u64>,
+    /// Consumed bytes of a line still waiting for its newline.
+    partial: Vec<u8>,
+}
+
+/// Source processing the lines appended to a file. See the
+/// [module docs](self).
+#[derive(Debug)]
+pub struct FileTailSource {
+    path: PathBuf,
+    from_start: bool,
+    poll_interval: Duration,
+    state: Mutex<TailState>,
+}
+
+impl FileTailSource {
+    /// Source tailing the file at `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            from_start: false,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Process the lines already in the file too.
+    pub fn from_start(mut self) -> Self {
+        self.from_start = true;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Complete lines added since the last poll, with their byte offsets.
+    fn read_lines(&self) -> Result<Vec<(u64, Vec<u8>)>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                state.offset.get_or_insert(0);
+                return Ok(Vec::new());
+            }
+            Err(e) => return Err(DataflowError::from_io(e)),
+        };
+        let len = file.metadata().map_err(DataflowError::from_io)?.len();
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let offset = *state
+            .offset
+            .get_or_insert(if self.from_start { 0 } else { len });
+        let offset = if len < offset {
+            state.partial.clear();
+            0
+        } else {
+            offset
+        };
+        let mut buf = std::mem::take(&mut state.partial);
+        let start = offset - buf.len() as u64;
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.read_to_end(&mut buf))
+            .map_err(DataflowError::from_io)?;
+        state.offset = Some(start + buf.len() as u64);
+
+        let complete = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        state.partial = buf.split_off(complete);
+        Ok(lines(&buf)
+            .map(|(at, _, line)| (start + at as u64, line.to_vec()))
+            .collect())
+    }
+}
+
+#[async_trait]
+impl SourceFunctionHandler for FileTailSource {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()> {
+        loop {
+            for (offset, line) in self.read_lines()? {
+                let event = SourceEvent::new(body_payload(&line)).with_metadata(json!({"file": {
+                    "path": self.path.display().to_string(),
+                    "offset": offset,
+                }}));
+                if let Err(e) = emitter.emit(event).await {
+                    warn!(
+                        "File source {}: line at {} of {} failed: {}",
+                        emitter.source(),
+                        offset,
+                        self.path.display(),
+                        e
+                    );
+                }
+            }
+            if !pause(&shutdown, self.poll_interval).await {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// How a [`DirectorySource`] turns a file into messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileFormat {
+    /// The whole file is one message (default).
+    #[default]
+    Document,
+    /// Each non-blank line is one message, as in NDJSON.
+    Lines,
+}
+
+/// What a [`DirectorySource`] does with a file once processed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum AfterProcessing {
+    /// Leave it, remembering not to process it again (default).
+    #[default]
+    Keep,
+    Delete,
+    /// Move it into this directory, created when missing.
+    MoveTo(PathBuf),
+}
+
+#[derive(Debug, Default)]
+struct DirectoryState {
+    /// Size of each candidate file at the last poll.
+    sizes: HashMap<PathBuf, u64>,
+    /// Files processed and left in place.
+    done: HashSet<PathBuf>,
+}
+
+/// Source processing the files dropped into a directory. See the
+/// [module docs](self).
+#[derive(Debug)]
+pub struct DirectorySource {
+    dir: PathBuf,
+    extension: Option<String>,
+    format: FileFormat,
+    after: AfterProcessing,
+    poll_interval: Duration,
+    state: Mutex<DirectoryState>,
+}
+
+impl DirectorySource {
+    /// Source watching the directory `dir`.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            extension: None,
+            format: FileFormat::default(),
+            after: AfterProcessing::default(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Only process files with extension `extension`, e.g. `"json"`.
+    pub fn with_extension(mut self, extension: impl Into<String>) -> Self {
+        self.extension = Some(extension.into());
+        self
+    }
+
+    pub fn with_format(mut self, format: FileFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn after_processing(mut self, after: AfterProcessing) -> Self {
+        self.after = after;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Files whose size hasn't changed since the last poll, in name order.
+    fn settled_files(&self) -> Result<Vec<PathBuf>> {
+        let mut sizes = HashMap::new();
+        for entry in std::fs::read_dir(&self.dir).map_err(DataflowError::from_io)? {
+            let entry = entry.map_err(DataflowError::from_io)?;
+            let path = entry.path();
+            let wanted = self
+                .extension
+                .as_deref()
+                .is_none_or(|ext| path.extension().is_some_and(|e| e == ext));
+            match entry.metadata() {
+                Ok(meta) if meta.is_file() && wanted => {
+                    sizes.insert(path, meta.len());
+                }
+                _ => {}
+            }
+        }
+
+        let mut guard = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let state = &mut *guard;
+        let previous = std::mem::replace(&mut state.sizes, sizes);
+        // Forget files that are gone, so a new file of the same name counts.
+        state.done.retain(|path| state.sizes.contains_key(path));
+        let mut settled: Vec<PathBuf> = state
+            .sizes
+            .iter()
+            .filter(|(path, size)| {
+                previous.get(*path) == Some(*size) && !state.done.contains(*path)
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled.sort();
+        Ok(settled)
+    }
+
+    fn events(&self, path: &Path, bytes: &[u8]) -> Vec<SourceEvent> {
+        let file = |line: Option<usize>| {
+            let mut file = json!({
+                "path": path.display().to_string(),
+                "name": path.file_name().map(|n| n.to_string_lossy().into_owned()),
+            });
+            if let Some(line) = line {
+                file["line"] = json!(line);
+            }
+            json!({"file": file})
+        };
+        match self.format {
+            FileFormat::Document => {
+                vec![SourceEvent::new(body_payload(bytes)).with_metadata(file(None))]
+            }
+            FileFormat::Lines => lines(bytes)
+                .map(|(_, number, line)| {
+                    SourceEvent::new(body_payload(line)).with_metadata(file(Some(number)))
+                })
+                .collect(),
+        }
+    }
+
+    /// Process the file at `path`; whether all of its messages succeeded.
+    async fn process(&self, emitter: &SourceEmitter, path: &Path) -> bool {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!(
+                    "File source {}: cannot read {}: {}",
+                    emitter.source(),
+                    path.display(),
+                    e
+                );
+                return false;
+            }
+        };
+        let mut ok = true;
+        for event in self.events(path, &bytes) {
+            if let Err(e) = emitter.emit(event).await {
+                warn!(
+                    "File source {}: {} failed: {}",
+                    emitter.source(),
+                    path.display(),
+                    e
+                );
+                ok = false;
+            }
+        }
+        ok
+    }
+
+    /// Apply [`AfterProcessing`] to a processed file.
+    fn finish(&self, path: &Path) -> std::io::Result<()> {
+        match &self.after {
+            AfterProcessing::Keep => Ok(()),
+            AfterProcessing::Delete => std::fs::remove_file(path),
+            AfterProcessing::MoveTo(dir) => {
+                std::fs::create_dir_all(dir)?;
+                let name = path.file_name().unwrap_or_default();
+                std::fs::rename(path, dir.join(name))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SourceFunctionHandler for DirectorySource {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()> {
+        loop {
+            for path in self.settled_files()? {
+                if shutdown.is_cancelled() {
+                    return Ok(());
+                }
+                // Files left in place are remembered so they aren't
+                // processed again.
+                let left = if !self.process(&emitter, &path).await {
+                    true
+                } else if let Err(e) = self.finish(&path) {
+                    warn!(
+                        "File source {}: cannot clean up {}: {}",
+                        emitter.source(),
+                        path.display(),
+                        e
+                    );
+                    true
+                } else {
+                    self.after == AfterProcessing::Keep
+                };
+                if left {
+                    let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                    state.done.insert(path);
+                }
+            }
+            if !pause(&shutdown, self.poll_interval).await {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "dataflow-file-source-{name}-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn append(path: &Path, text: &str) {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn texts(lines: Vec<(u64, Vec<u8>)>) -> Vec<(u64, String)> {
+        lines
+            .into_iter()
+            .map(|(at, line)| (at, String::from_utf8(line).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn tail_reads_complete_lines_and_restarts_after_truncation() {
+        let path = dir("tail").join("events.ndjson");
+        append(&path, "{\"old\":1}\n");
+        let tail = FileTailSource::new(&path);
+        assert!(tail.read_lines().unwrap().is_empty());
+
+        append(&path, "{\"a\":1}\n\n{\"b\"");
+        assert_eq!(
+            texts(tail.read_lines().unwrap()),
+            [(10, "{\"a\":1}".into())]
+        );
+        append(&path, ":2}\r\n");
+        assert_eq!(
+            texts(tail.read_lines().unwrap()),
+            [(19, "{\"b\":2}".into())]
+        );
+
+        std::fs::write(&path, "x\n").unwrap();
+        assert_eq!(texts(tail.read_lines().unwrap()), [(0, "x".into())]);
+    }
+
+    #[test]
+    fn tail_reads_a_file_created_later_from_its_start() {
+        let path = dir("tail-later").join("events.ndjson");
+        let tail = FileTailSource::new(&path);
+        assert!(tail.read_lines().unwrap().is_empty());
+        append(&path, "1\n2\n");
+        assert_eq!(
+            texts(tail.read_lines().unwrap()),
+            [(0, "1".into()), (2, "2".into())]
+        );
+    }
+
+    #[test]
+    fn directory_picks_up_files_once_their_size_settles() {
+        let dir = dir("watch");
+        std::fs::write(dir.join("b.json"), "{}").unwrap();
+        std::fs::write(dir.join("a.json"), "{}").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+        let source = DirectorySource::new(&dir).with_extension("json");
+
+        assert!(source.settled_files().unwrap().is_empty());
+        std::fs::write(dir.join("b.json"), "{\"growing\": true}").unwrap();
+        assert_eq!(source.settled_files().unwrap(), [dir.join("a.json")]);
+        assert_eq!(
+            source.settled_files().unwrap(),
+            [dir.join("a.json"), dir.join("b.json")]
+        );
+    }
+
+    #[test]
+    fn lines_format_numbers_each_non_blank_line() {
+        let source = DirectorySource::new("in").with_format(FileFormat::Lines);
+        let events = source.events(Path::new("in/x.ndjson"), b"{\"n\":1}\n\n  \nplain\n");
+        let got: Vec<_> = events
+            .iter()
+            .map(|e| {
+                (
+                    e.payload.clone(),
+                    e.metadata.as_ref().unwrap()["file"]["line"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            got,
+            [(json!({"n": 1}), json!(1)), (json!("plain"), json!(4))]
+        );
+    }
+}
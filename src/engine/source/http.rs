@@ -0,0 +1,195 @@
+//! # HTTP Source
+//!
+//! Serves HTTP routes that run each request through the engine and answer
+//! with the result — the request/reply wrapper services otherwise write by
+//! hand. Requires the `http-source` feature.
+//!
+//! ```no_run
+//! # use dataflow_rs::{HttpRoute, HttpSource, SharedEngine, SourceManager};
+//! # async fn demo(shared: SharedEngine) {
+//! let source = HttpSource::bind(([0, 0, 0, 0], 8080).into())
+//!     .route(HttpRoute::new("/orders").with_channel("orders"))
+//!     .route(HttpRoute::new("/quotes").with_reply("data.quote"));
+//! let runtime = SourceManager::new(shared).register("api", source).start();
+//! # }
+//! ```
+//!
+//! Each route accepts `POST`. The request becomes a message whose payload
+//! is the body — parsed as JSON when it is JSON, the raw text otherwise,
+//! `{}` when empty — and whose `metadata.http` holds the request's `path`,
+//! `query` string and `headers` (lowercase names; the last value of a
+//! repeated header). An `x-request-id` header becomes the message id.
+//!
+//! The response is `200` with the processed message's `data`, or the value
+//! at the route's reply path (`null` when missing). A message that ends
+//! with errors answers `422` with `{"errors": [...]}`; a processing error
+//! answers `500` with `{"error": "..."}`.
+//!
+//! Requests are processed concurrently, each on its own connection's task.
+//! On shutdown the server stops accepting connections and finishes the
+//! requests in flight.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::source::{SourceEmitter, SourceEvent, SourceFunctionHandler, body_payload};
+use crate::engine::utils::get_nested_value;
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde_json::{Map, Value, json};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+type Reply = (StatusCode, Json<Value>);
+
+/// One route served by an [`HttpSource`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRoute {
+    path: String,
+    channel: Option<String>,
+    reply: String,
+}
+
+impl HttpRoute {
+    /// Route serving `path` (axum syntax), replying with the message's
+    /// `data`.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            channel: None,
+            reply: "data".to_string(),
+        }
+    }
+
+    /// Process the route's messages on `channel`, overriding the source's.
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    /// Reply with the value at context path `path`, e.g. `data.result`.
+    pub fn with_reply(mut self, path: impl Into<String>) -> Self {
+        self.reply = path.into();
+        self
+    }
+}
+
+#[derive(Debug)]
+enum Listen {
+    Addr(SocketAddr),
+    Listener(std::net::TcpListener),
+}
+
+/// Source serving [`HttpRoute`]s. See the [module docs](self).
+#[derive(Debug)]
+pub struct HttpSource {
+    listen: Listen,
+    routes: Vec<HttpRoute>,
+}
+
+impl HttpSource {
+    /// Source listening on `addr` once started.
+    pub fn bind(addr: SocketAddr) -> Self {
+        Self {
+            listen: Listen::Addr(addr),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Source accepting connections on an already bound `listener`.
+    pub fn from_listener(listener: std::net::TcpListener) -> Self {
+        Self {
+            listen: Listen::Listener(listener),
+            routes: Vec::new(),
+        }
+    }
+
+    /// Serve `route` as well.
+    pub fn route(mut self, route: HttpRoute) -> Self {
+        self.routes.push(route);
+        self
+    }
+
+    fn router(&self, emitter: &SourceEmitter) -> Router {
+        self.routes.iter().fold(Router::new(), |router, route| {
+            let state = Arc::new(RouteState {
+                emitter: match &route.channel {
+                    Some(channel) => emitter.on_channel(channel),
+                    None => emitter.clone(),
+                },
+                reply: route.reply.clone(),
+            });
+            router.route(&route.path, post(ingest).with_state(state))
+        })
+    }
+
+    async fn listener(&self) -> std::io::Result<tokio::net::TcpListener> {
+        match &self.listen {
+            Listen::Addr(addr) => tokio::net::TcpListener::bind(addr).await,
+            // Cloned so a restarted source can listen again.
+            Listen::Listener(listener) => {
+                let listener = listener.try_clone()?;
+                listener.set_nonblocking(true)?;
+                tokio::net::TcpListener::from_std(listener)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SourceFunctionHandler for HttpSource {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()> {
+        let listener = self.listener().await.map_err(DataflowError::from_io)?;
+        axum::serve(listener, self.router(&emitter))
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+            .map_err(DataflowError::from_io)
+    }
+}
+
+struct RouteState {
+    emitter: SourceEmitter,
+    reply: String,
+}
+
+async fn ingest(
+    State(route): State<Arc<RouteState>>,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Reply {
+    let header_values: Map<String, Value> = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), Value::from(value.to_str().ok()?))))
+        .collect();
+    let mut event = SourceEvent::new(body_payload(&body)).with_metadata(json!({"http": {
+        "path": uri.path(),
+        "query": uri.query(),
+        "headers": header_values,
+    }}));
+    if let Some(id) = headers.get("x-request-id").and_then(|v| v.to_str().ok()) {
+        event = event.with_id(id);
+    }
+
+    match route.emitter.emit(event).await {
+        Ok(message) if message.has_errors() => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(json!({"errors": message.errors()})),
+        ),
+        Ok(message) => (
+            StatusCode::OK,
+            Json(
+                get_nested_value(&message.context, &route.reply)
+                    .map(Value::from)
+                    .unwrap_or(Value::Null),
+            ),
+        ),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": e.to_string()})),
+        ),
+    }
+}
@@ -0,0 +1,242 @@
+//! # Kafka Source
+//!
+//! Consumes a Kafka topic as a consumer group member and processes each
+//! record as a message, committing its offset once processed. Requires the
+//! `kafka` feature.
+//!
+//! Like `publish_kafka`, the source doesn't bundle a Kafka client: the
+//! service supplies a [`KafkaConnector`] that opens a [`KafkaConsumer`] for
+//! a [`KafkaSourceConfig`] — typically a thin wrapper over the client it
+//! already uses:
+//!
+//! ```no_run
+//! # use dataflow_rs::{KafkaConnector, KafkaSource, KafkaSourceConfig, OffsetReset, SharedEngine, SourceManager};
+//! # fn demo(shared: SharedEngine, connector: impl KafkaConnector + 'static) {
+//! let config = KafkaSourceConfig {
+//!     brokers: vec!["kafka-1:9092".into()],
+//!     topic: "orders".into(),
+//!     group: "order-rules".into(),
+//!     offset_reset: OffsetReset::Earliest,
+//! };
+//! let runtime = SourceManager::new(shared)
+//!     .register("orders", KafkaSource::new(config, connector))
+//!     .start();
+//! # }
+//! ```
+//!
+//! Each record becomes a message whose payload is the record value —
+//! parsed as JSON when it is JSON, the text otherwise — with id
+//! `<topic>-<partition>-<offset>` and `metadata.kafka` holding the
+//! record's `topic`, `partition`, `offset`, `key`, `headers` and
+//! `timestamp`.
+//!
+//! Records go through [`SourceEmitter::deliver`]: the offset is committed
+//! when the message is acked, after it has been processed, so a crash
+//! mid-message redelivers it: processing is at-least-once. A message that
+//! ends with errors counts as processed, unless the source is registered
+//! with [`SourceOptions::nack_on_errors`](crate::SourceOptions::nack_on_errors).
+//! A nacked record follows the [`FailedRecordPolicy`]: `Skip` logs it and
+//! commits, `Redeliver` stops the source without committing, so it is
+//! consumed again once the source is restarted per its
+//! [`RestartPolicy`](crate::RestartPolicy). A fresh consumer is opened on
+//! every (re)start.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::source::{
+    Acknowledge, NackReason, SourceEmitter, SourceEvent, SourceFunctionHandler, body_payload,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+
+/// Where a consumer group with no committed offset starts reading.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OffsetReset {
+    /// The oldest record still retained.
+    Earliest,
+    /// Records produced from now on (default).
+    #[default]
+    Latest,
+}
+
+/// What the consumer connects to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KafkaSourceConfig {
+    /// Bootstrap servers, as `host:port`.
+    pub brokers: Vec<String>,
+    pub topic: String,
+    /// Consumer group id.
+    pub group: String,
+    #[serde(default)]
+    pub offset_reset: OffsetReset,
+}
+
+/// One consumed record.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KafkaRecord {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<Vec<u8>>,
+    pub value: Vec<u8>,
+    pub headers: Vec<(String, Vec<u8>)>,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// A consumer opened by a [`KafkaConnector`], used by one run of the
+/// source.
+#[async_trait]
+pub trait KafkaConsumer: Send {
+    /// Wait for the next record.
+    async fn next(&mut self) -> Result<KafkaRecord>;
+
+    /// Commit `record`'s offset for the group.
+    async fn commit(&mut self, record: &KafkaRecord) -> Result<()>;
+}
+
+/// Opens consumers for a [`KafkaSource`], backed by the service's Kafka
+/// client.
+#[async_trait]
+pub trait KafkaConnector: Send + Sync {
+    async fn connect(&self, config: &KafkaSourceConfig) -> Result<Box<dyn KafkaConsumer>>;
+}
+
+/// What a [`KafkaSource`] does with a nacked record: one whose processing
+/// returned an error or, with `nack_on_errors`, ended with errors.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailedRecordPolicy {
+    /// Log the error, commit and move on (default).
+    #[default]
+    Skip,
+    /// Stop the source without committing, so the record is consumed again
+    /// after a restart.
+    Redeliver,
+}
+
+/// Source consuming a Kafka topic. See the [module docs](self).
+pub struct KafkaSource {
+    config: KafkaSourceConfig,
+    connector: Arc<dyn KafkaConnector>,
+    on_failure: FailedRecordPolicy,
+}
+
+impl KafkaSource {
+    /// Source consuming per `config` through `connector`.
+    pub fn new(config: KafkaSourceConfig, connector: impl KafkaConnector + 'static) -> Self {
+        Self {
+            config,
+            connector: Arc::new(connector),
+            on_failure: FailedRecordPolicy::default(),
+        }
+    }
+
+    pub fn with_failure_policy(mut self, policy: FailedRecordPolicy) -> Self {
+        self.on_failure = policy;
+        self
+    }
+
+    pub fn config(&self) -> &KafkaSourceConfig {
+        &self.config
+    }
+}
+
+#[async_trait]
+impl SourceFunctionHandler for KafkaSource {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()> {
+        let mut consumer = self.connector.connect(&self.config).await?;
+        loop {
+            let record = tokio::select! {
+                _ = shutdown.cancelled() => return Ok(()),
+                record = consumer.next() => record?,
+            };
+            let mut offset = Offset {
+                consumer: consumer.as_mut(),
+                record: &record,
+                source: emitter.source(),
+                on_failure: self.on_failure,
+                committed: false,
+            };
+            let result = emitter.deliver(event(&record), &mut offset).await;
+            if !offset.committed {
+                return Err(result.err().unwrap_or_else(|| {
+                    DataflowError::Task(format!(
+                        "Record {}-{}-{} ended with errors",
+                        record.topic, record.partition, record.offset
+                    ))
+                }));
+            }
+        }
+    }
+}
+
+/// Settles a record by committing its offset.
+struct Offset<'a> {
+    consumer: &'a mut dyn KafkaConsumer,
+    record: &'a KafkaRecord,
+    source: &'a str,
+    on_failure: FailedRecordPolicy,
+    /// Whether the offset was committed; the source stops when it wasn't.
+    committed: bool,
+}
+
+#[async_trait]
+impl Acknowledge for Offset<'_> {
+    async fn ack(&mut self) -> Result<()> {
+        self.consumer.commit(self.record).await?;
+        self.committed = true;
+        Ok(())
+    }
+
+    async fn nack(&mut self, reason: NackReason<'_>) -> Result<()> {
+        match self.on_failure {
+            FailedRecordPolicy::Skip => {
+                let failure = match reason {
+                    NackReason::Failed(e) => e.to_string(),
+                    NackReason::Errors(message) => {
+                        format!("ended with {} error(s)", message.errors().len())
+                    }
+                };
+                warn!(
+                    "Kafka source {}: record {}-{}-{} failed: {}",
+                    self.source,
+                    self.record.topic,
+                    self.record.partition,
+                    self.record.offset,
+                    failure
+                );
+                self.ack().await
+            }
+            FailedRecordPolicy::Redeliver => Ok(()),
+        }
+    }
+}
+
+fn text(bytes: &[u8]) -> Value {
+    Value::String(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn event(record: &KafkaRecord) -> SourceEvent {
+    let headers: Map<String, Value> = record
+        .headers
+        .iter()
+        .map(|(name, value)| (name.clone(), text(value)))
+        .collect();
+    SourceEvent::new(body_payload(&record.value))
+        .with_id(format!(
+            "{}-{}-{}",
+            record.topic, record.partition, record.offset
+        ))
+        .with_metadata(json!({"kafka": {
+            "topic": record.topic,
+            "partition": record.partition,
+            "offset": record.offset,
+            "key": record.key.as_deref().map(text),
+            "headers": headers,
+            "timestamp": record.timestamp.map(|t| t.to_rfc3339()),
+        }}))
+}
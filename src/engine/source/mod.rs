@@ -0,0 +1,516 @@
+//! # Sources
+//!
+//! Feed the engine from event producers — timers, queues, sockets —
+//! without writing the glue. A source implements
+//! [`SourceFunctionHandler`]: its `run` produces events and hands each to
+//! a [`SourceEmitter`], which turns it into a message and processes it
+//! through the engine. A [`SourceManager`] runs every registered source on
+//! its own Tokio task and supervises it, restarting it per its
+//! [`RestartPolicy`] when it fails:
+//!
+//! ```no_run
+//! # use dataflow_rs::{CronSource, SharedEngine, SourceManager};
+//! # use serde_json::json;
+//! # async fn demo(shared: SharedEngine) -> dataflow_rs::Result<()> {
+//! let runtime = SourceManager::new(shared)
+//!     .register("nightly", CronSource::new("0 2 * * *", json!({"job": "rollup"}))?)
+//!     .start();
+//! // ...
+//! runtime.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Each event becomes a message with the event's payload, id and metadata,
+//! and `metadata.source` set to the source's name. It is processed with
+//! [`Engine::process_message`](crate::Engine::process_message), or
+//! [`process_message_for_channel`](crate::Engine::process_message_for_channel)
+//! when the source is registered with a channel, on the engine current at
+//! that moment, so [`SharedEngine::reload`] applies to the next event.
+//! Processing happens inline in the source's task: a source emits its
+//! next event once the previous one has been processed.
+//!
+//! Sources consuming from a broker with at-least-once semantics hand
+//! each delivery to [`SourceEmitter::deliver`] along with an
+//! [`Acknowledge`], which is acked only after the message has been
+//! processed and nacked when processing failed.
+//!
+//! Besides [`CronSource`], [`FileTailSource`] and [`DirectorySource`]
+//! process lines appended to a file and files dropped into a directory.
+//! The `http-source` feature adds
+//! [`HttpSource`](http::HttpSource), serving HTTP routes with the
+//! processed message as the response, and the `kafka` feature adds
+//! [`KafkaSource`](kafka::KafkaSource), consuming a Kafka topic.
+//!
+//! Sources are not available on `wasm32`.
+
+pub mod cron;
+pub mod file;
+#[cfg(feature = "http-source")]
+pub mod http;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::Message;
+use crate::engine::shared::SharedEngine;
+use crate::engine::utils::set_nested_value;
+use async_trait::async_trait;
+use datavalue::OwnedDataValue;
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+pub use cron::{CronSchedule, CronSource};
+pub use file::{AfterProcessing, DirectorySource, FileFormat, FileTailSource};
+#[cfg(feature = "http-source")]
+pub use http::{HttpRoute, HttpSource};
+#[cfg(feature = "kafka")]
+pub use kafka::{
+    FailedRecordPolicy, KafkaConnector, KafkaConsumer, KafkaRecord, KafkaSource, KafkaSourceConfig,
+    OffsetReset,
+};
+
+/// One event produced by a source.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceEvent {
+    /// Message id; generated by the engine when `None`.
+    pub id: Option<String>,
+    pub payload: Value,
+    /// Initial `metadata` of the message; `metadata.source` is added.
+    pub metadata: Option<Value>,
+}
+
+impl SourceEvent {
+    /// Event carrying `payload`.
+    pub fn new(payload: Value) -> Self {
+        Self {
+            payload,
+            ..Self::default()
+        }
+    }
+
+    /// Use `id` as the message id, e.g. the upstream record's key.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Start the message's `metadata` from `metadata`.
+    pub fn with_metadata(mut self, metadata: Value) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+}
+
+/// Payload of a message built from a raw body: the body as JSON when it
+/// parses, its text otherwise, `{}` when empty.
+pub(crate) fn body_payload(body: &[u8]) -> Value {
+    if body.is_empty() {
+        return Value::Object(Default::default());
+    }
+    serde_json::from_slice(body)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(body).into_owned()))
+}
+
+/// Why a delivery is nacked by [`SourceEmitter::deliver`].
+#[derive(Debug)]
+pub enum NackReason<'a> {
+    /// Processing returned this error.
+    Failed(&'a DataflowError),
+    /// The message ended with errors and the source is registered with
+    /// [`SourceOptions::nack_on_errors`].
+    Errors(&'a Message),
+}
+
+/// Settles one delivery with the system it came from, for sources with
+/// at-least-once semantics: commit an offset, delete a queue message, ack
+/// an AMQP delivery. Passed to [`SourceEmitter::deliver`], which calls
+/// exactly one of the two once the message has been processed.
+#[async_trait]
+pub trait Acknowledge: Send {
+    /// The message was processed.
+    async fn ack(&mut self) -> Result<()>;
+
+    /// The message was not processed: release it for redelivery,
+    /// dead-letter it, or commit it anyway.
+    async fn nack(&mut self, reason: NackReason<'_>) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: Acknowledge + ?Sized> Acknowledge for &mut T {
+    async fn ack(&mut self) -> Result<()> {
+        (**self).ack().await
+    }
+
+    async fn nack(&mut self, reason: NackReason<'_>) -> Result<()> {
+        (**self).nack(reason).await
+    }
+}
+
+/// Hands a source's events to the engine. Cheap to clone.
+#[derive(Clone)]
+pub struct SourceEmitter {
+    source: Arc<str>,
+    engine: SharedEngine,
+    channel: Option<Arc<str>>,
+    nack_on_errors: bool,
+}
+
+impl SourceEmitter {
+    pub(crate) fn new(source: &str, engine: SharedEngine, channel: Option<&str>) -> Self {
+        Self {
+            source: source.into(),
+            engine,
+            channel: channel.map(Into::into),
+            nack_on_errors: false,
+        }
+    }
+
+    pub(crate) fn with_nack_on_errors(mut self, nack_on_errors: bool) -> Self {
+        self.nack_on_errors = nack_on_errors;
+        self
+    }
+
+    /// Emitter processing on `channel` instead of the source's channel,
+    /// for sources routing some of their events elsewhere.
+    pub fn on_channel(&self, channel: &str) -> Self {
+        Self {
+            channel: Some(channel.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Name the source is registered under.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Build a message from `event` and process it. Returns the processed
+    /// message, or the processing error as
+    /// [`Engine::process_message`](crate::Engine::process_message) does.
+    pub async fn emit(&self, event: SourceEvent) -> Result<Message> {
+        let engine = self.engine.current();
+        let mut builder = engine.message().payload_json(&event.payload);
+        if let Some(id) = event.id {
+            builder = builder.id(id);
+        }
+        if let Some(metadata) = &event.metadata {
+            builder = builder.metadata_json(metadata);
+        }
+        let mut message = builder.try_build()?;
+        self.process(&mut message).await?;
+        Ok(message)
+    }
+
+    /// [`emit`](Self::emit) `event`, then settle it through `acker`: `ack`
+    /// once processing succeeded, `nack` when it returned an error — or,
+    /// with [`SourceOptions::nack_on_errors`], when the message ended with
+    /// errors. Returns what `emit` returns, unless settling fails, in which
+    /// case that error is returned.
+    pub async fn deliver<A: Acknowledge>(
+        &self,
+        event: SourceEvent,
+        mut acker: A,
+    ) -> Result<Message> {
+        let result = self.emit(event).await;
+        match &result {
+            Ok(message) if self.nack_on_errors && message.has_errors() => {
+                acker.nack(NackReason::Errors(message)).await?
+            }
+            Ok(_) => acker.ack().await?,
+            Err(e) => acker.nack(NackReason::Failed(e)).await?,
+        }
+        result
+    }
+
+    /// Process a message the source built itself, tagging it with the
+    /// source's name like [`emit`](Self::emit) does.
+    pub async fn process(&self, message: &mut Message) -> Result<()> {
+        set_nested_value(
+            &mut message.context,
+            "metadata.source",
+            OwnedDataValue::String(self.source.to_string()),
+        );
+        let engine = self.engine.current();
+        match &self.channel {
+            Some(channel) => engine.process_message_for_channel(channel, message).await,
+            None => engine.process_message(message).await,
+        }
+    }
+}
+
+/// A producer of events, run and supervised by a [`SourceManager`].
+///
+/// `run` should emit events until `shutdown` is cancelled and then return
+/// `Ok`. Returning earlier ends the source — for good with `Ok`, or
+/// subject to the [`RestartPolicy`] with `Err`. A failed message is
+/// returned by [`SourceEmitter::emit`] and is the source's to handle;
+/// logging it and carrying on is usually right. Sources that acknowledge
+/// deliveries upstream use [`SourceEmitter::deliver`] with an
+/// [`Acknowledge`], so nothing is acked before it has been processed.
+#[async_trait]
+pub trait SourceFunctionHandler: Send + Sync {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: SourceFunctionHandler + ?Sized> SourceFunctionHandler for Arc<T> {
+    async fn run(&self, emitter: SourceEmitter, shutdown: CancellationToken) -> Result<()> {
+        (**self).run(emitter, shutdown).await
+    }
+}
+
+/// What the manager does when a source's `run` returns before shutdown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave the source stopped.
+    Never,
+    /// Restart after `backoff` when `run` failed, at most `max_restarts`
+    /// times (unbounded when `None`). A source that returns `Ok` stays
+    /// stopped.
+    OnFailure {
+        max_restarts: Option<u32>,
+        backoff: Duration,
+    },
+    /// Restart after `backoff` whenever `run` returns.
+    Always { backoff: Duration },
+}
+
+impl Default for RestartPolicy {
+    /// Restart failed sources after a second, without limit.
+    fn default() -> Self {
+        Self::OnFailure {
+            max_restarts: None,
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Whether a source that returned `result` after `restarts` restarts
+    /// is started again, and after how long.
+    fn restart_after(&self, result: &Result<()>, restarts: u32) -> Option<Duration> {
+        match *self {
+            Self::Never => None,
+            Self::OnFailure {
+                max_restarts,
+                backoff,
+            } => (result.is_err() && max_restarts.is_none_or(|max| restarts < max))
+                .then_some(backoff),
+            Self::Always { backoff } => Some(backoff),
+        }
+    }
+}
+
+/// Per-source settings for [`SourceManager::register_with`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceOptions {
+    pub restart: RestartPolicy,
+    /// Process the source's messages on this channel only.
+    pub channel: Option<String>,
+    /// Nack deliveries whose message ended with errors, not only those
+    /// whose processing failed. See [`SourceEmitter::deliver`].
+    pub nack_on_errors: bool,
+}
+
+impl SourceOptions {
+    pub fn with_restart(mut self, restart: RestartPolicy) -> Self {
+        self.restart = restart;
+        self
+    }
+
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    pub fn with_nack_on_errors(mut self, nack_on_errors: bool) -> Self {
+        self.nack_on_errors = nack_on_errors;
+        self
+    }
+}
+
+/// Where a supervised source stands.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SourceStatus {
+    pub name: String,
+    /// Whether `run` is executing or waiting to be restarted.
+    pub running: bool,
+    /// Times the source was restarted.
+    pub restarts: u32,
+    /// Error of the last failed `run`, if any.
+    pub last_error: Option<String>,
+}
+
+struct Registration {
+    name: String,
+    handler: Arc<dyn SourceFunctionHandler>,
+    options: SourceOptions,
+}
+
+/// Registers sources and starts them against an engine.
+pub struct SourceManager {
+    engine: SharedEngine,
+    sources: Vec<Registration>,
+}
+
+impl SourceManager {
+    /// Manager feeding `engine`. Wrap a plain engine with
+    /// [`SharedEngine::new`].
+    pub fn new(engine: SharedEngine) -> Self {
+        Self {
+            engine,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Register `handler` as source `name` with the default options.
+    pub fn register<H>(self, name: impl Into<String>, handler: H) -> Self
+    where
+        H: SourceFunctionHandler + 'static,
+    {
+        self.register_with(name, handler, SourceOptions::default())
+    }
+
+    /// Register `handler` as source `name` with `options`.
+    pub fn register_with<H>(
+        mut self,
+        name: impl Into<String>,
+        handler: H,
+        options: SourceOptions,
+    ) -> Self
+    where
+        H: SourceFunctionHandler + 'static,
+    {
+        self.sources.push(Registration {
+            name: name.into(),
+            handler: Arc::new(handler),
+            options,
+        });
+        self
+    }
+
+    /// Spawn every source on the current Tokio runtime.
+    pub fn start(self) -> SourceRuntime {
+        let shutdown = CancellationToken::new();
+        let statuses = Arc::new(Mutex::new(BTreeMap::new()));
+        let tasks = self
+            .sources
+            .into_iter()
+            .map(|source| {
+                statuses.lock().unwrap_or_else(|e| e.into_inner()).insert(
+                    source.name.clone(),
+                    SourceStatus {
+                        name: source.name.clone(),
+                        running: true,
+                        ..SourceStatus::default()
+                    },
+                );
+                let emitter = SourceEmitter::new(
+                    &source.name,
+                    self.engine.clone(),
+                    source.options.channel.as_deref(),
+                )
+                .with_nack_on_errors(source.options.nack_on_errors);
+                tokio::spawn(supervise(
+                    source,
+                    emitter,
+                    shutdown.clone(),
+                    Arc::clone(&statuses),
+                ))
+            })
+            .collect();
+        SourceRuntime {
+            shutdown,
+            statuses,
+            tasks,
+        }
+    }
+}
+
+type Statuses = Arc<Mutex<BTreeMap<String, SourceStatus>>>;
+
+/// Run a source until shutdown or until its policy stops restarting it.
+async fn supervise(
+    source: Registration,
+    emitter: SourceEmitter,
+    shutdown: CancellationToken,
+    statuses: Statuses,
+) {
+    let update = |f: &dyn Fn(&mut SourceStatus)| {
+        if let Some(status) = statuses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(&source.name)
+        {
+            f(status);
+        }
+    };
+    let mut restarts = 0;
+    loop {
+        let result = source.handler.run(emitter.clone(), shutdown.clone()).await;
+        if let Err(e) = &result {
+            warn!("Source {} failed: {}", source.name, e);
+            update(&|status| status.last_error = Some(e.to_string()));
+        }
+        if shutdown.is_cancelled() {
+            break;
+        }
+        let Some(backoff) = source.options.restart.restart_after(&result, restarts) else {
+            info!("Source {} stopped", source.name);
+            break;
+        };
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = tokio::time::sleep(backoff) => {}
+        }
+        restarts += 1;
+        update(&|status| status.restarts = restarts);
+    }
+    update(&|status| status.running = false);
+}
+
+/// Handle on started sources.
+pub struct SourceRuntime {
+    shutdown: CancellationToken,
+    statuses: Statuses,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl SourceRuntime {
+    /// Status of every source, by name.
+    pub fn status(&self) -> Vec<SourceStatus> {
+        self.statuses
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Token cancelled on shutdown, for tying other work to the sources.
+    pub fn shutdown_token(&self) -> &CancellationToken {
+        &self.shutdown
+    }
+
+    /// Wait until every source has stopped on its own.
+    pub async fn wait(self) -> Vec<SourceStatus> {
+        for task in self.tasks {
+            let _ = task.await;
+        }
+        let statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        statuses.values().cloned().collect()
+    }
+
+    /// Cancel every source and wait for them to return.
+    pub async fn shutdown(self) -> Vec<SourceStatus> {
+        self.shutdown.cancel();
+        self.wait().await
+    }
+}
@@ -0,0 +1,184 @@
+//! # State Store
+//!
+//! Key-value state that outlives a single message — and, with a durable
+//! implementation, the process. Configure one with
+//! [`EngineBuilder::with_state_store`](crate::EngineBuilder::with_state_store).
+//!
+//! The engine keeps retry progress there: with a store configured, each
+//! retryable failure of an async task records a [`RetryState`] under
+//! `retry:<message_id>:<workflow_id>:<task_id>`, and the entry is deleted
+//! once the task succeeds or gives up. A message redelivered after a
+//! restart resumes the retry budget and waits out the remaining backoff
+//! instead of starting over.
+//!
+//! `join` tasks keep their open groups there too, under
+//! `join:<workflow_id>:<task_id>:<key>`; see the
+//! [`join`](crate::engine::functions::join) module.
+//!
+//! [`Engine::pending_instances`](crate::Engine::pending_instances) lists
+//! both kinds of entry for operators; see the
+//! [`instances`](crate::engine::instances) module.
+//!
+//! Workflows read and write their own entries with `state_get` and
+//! `state_set` (see the [`state`](crate::engine::functions::state)
+//! module), and custom handlers reach the store through
+//! [`TaskContext::state`](crate::TaskContext::state).
+//!
+//! Values are JSON. Entries may carry a time to live; an expired entry
+//! reads as absent.
+
+use crate::engine::error::{DataflowError, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Shared key-value storage. Implementations should persist a `set` or
+/// `delete` before returning `Ok`.
+#[async_trait]
+pub trait StateStore: Send + Sync {
+    /// The value stored under `key`, or `None` when unset or expired.
+    async fn get(&self, key: &str) -> Result<Option<Value>>;
+
+    /// Store `value` under `key`, expiring after `ttl` when given.
+    async fn set(&self, key: &str, value: Value, ttl: Option<Duration>) -> Result<()>;
+
+    /// Remove `key`. Removing an absent key is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Keys starting with `prefix` that have not expired, sorted. Used by
+    /// [`Engine::pending_instances`](crate::Engine::pending_instances);
+    /// stores that cannot list their keys keep the default, which fails.
+    async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        Err(DataflowError::Validation(format!(
+            "state store cannot list keys (prefix '{prefix}')"
+        )))
+    }
+}
+
+/// A stored value and when it expires.
+type Entry = (Value, Option<DateTime<Utc>>);
+
+/// `StateStore` that keeps entries in memory. Not durable; handy for tests
+/// and single-process deployments.
+#[derive(Debug, Default)]
+pub struct InMemoryStateStore {
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl InMemoryStateStore {
+    /// Create an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keys of the entries that have not expired, sorted. Expired entries
+    /// are dropped.
+    pub fn keys(&self) -> Vec<String> {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (_, expires)| expires.is_none_or(|at| at > now));
+        let mut keys: Vec<String> = entries.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+}
+
+#[async_trait]
+impl StateStore for InMemoryStateStore {
+    async fn get(&self, key: &str) -> Result<Option<Value>> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(key) {
+            Some((_, Some(expires))) if *expires <= Utc::now() => {
+                entries.remove(key);
+                Ok(None)
+            }
+            entry => Ok(entry.map(|(value, _)| value.clone())),
+        }
+    }
+
+    async fn set(&self, key: &str, value: Value, ttl: Option<Duration>) -> Result<()> {
+        let expires = ttl.map(|ttl| Utc::now() + ttl);
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), (value, expires));
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+        Ok(())
+    }
+
+    async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        let mut keys = self.keys();
+        keys.retain(|key| key.starts_with(prefix));
+        Ok(keys)
+    }
+}
+
+#[async_trait]
+impl<T: StateStore + ?Sized> StateStore for Arc<T> {
+    async fn get(&self, key: &str) -> Result<Option<Value>> {
+        (**self).get(key).await
+    }
+
+    async fn set(&self, key: &str, value: Value, ttl: Option<Duration>) -> Result<()> {
+        (**self).set(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        (**self).delete(key).await
+    }
+
+    async fn keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>> {
+        (**self).keys_with_prefix(prefix).await
+    }
+}
+
+/// Retry progress of one task for one message, as kept in the state store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryState {
+    /// Retries made so far (attempts minus one).
+    pub retries: u32,
+    /// When the next attempt is due.
+    pub next_attempt_at: DateTime<Utc>,
+}
+
+/// State store key of the [`RetryState`] for a task run on a message.
+pub fn retry_state_key(message_id: &str, workflow_id: &str, task_id: &str) -> String {
+    format!("retry:{message_id}:{workflow_id}:{task_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn entries_expire_after_their_ttl() {
+        let store = InMemoryStateStore::new();
+        store.set("kept", json!(1), None).await.unwrap();
+        store
+            .set("brief", json!(2), Some(Duration::from_millis(20)))
+            .await
+            .unwrap();
+        assert_eq!(store.keys(), ["brief", "kept"]);
+        assert_eq!(store.get("brief").await.unwrap(), Some(json!(2)));
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(store.get("brief").await.unwrap(), None);
+        assert_eq!(store.keys(), ["kept"]);
+
+        store.delete("kept").await.unwrap();
+        store.delete("kept").await.unwrap();
+        assert_eq!(store.get("kept").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,214 @@
+//! # Structural Lints
+//!
+//! Findings about the shape of a workflow set rather than its expressions:
+//!
+//! | Code | Finding |
+//! |------|---------|
+//! | `false_condition` | A workflow or task condition is the literal `false`, so it never runs |
+//! | `empty_workflow` | A workflow has no tasks |
+//! | `duplicate_task_id` | A task id is used twice in the same task list |
+//! | `priority_collision` | Active workflows on one channel share a `priority` and nothing else orders them |
+//!
+//! An engine refuses to build from empty workflows or duplicate task ids,
+//! so those two only show up when linting a workflow set before building,
+//! with [`Engine::lint_workflows`](crate::Engine::lint_workflows).
+//!
+//! Tied priorities are resolved by declaration order unless the engine
+//! uses [`PriorityTieBreak::Id`], so the run order of the tied workflows
+//! can change with the order files are loaded in. A collision is not
+//! reported under [`PriorityTieBreak::Id`], or when one of the workflows
+//! lists the other in `depends_on`.
+
+use crate::engine::functions::FunctionConfig;
+use crate::engine::lint::LintWarning;
+use crate::engine::task::Task;
+use crate::engine::workflow::{PriorityTieBreak, Workflow, WorkflowStatus};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+
+/// Structural lints for `workflow` on its own.
+pub(crate) fn lint_workflow(workflow: &Workflow) -> Vec<LintWarning> {
+    let mut warnings = Vec::new();
+    let mut warn = |code: &str, task_id: Option<&str>, message: String| {
+        warnings.push(LintWarning {
+            code: code.to_string(),
+            workflow_id: workflow.id.clone(),
+            task_id: task_id.map(str::to_string),
+            message,
+        })
+    };
+    if workflow.tasks.is_empty() {
+        warn("empty_workflow", None, "workflow has no tasks".to_string());
+    }
+    if workflow.condition == Value::Bool(false) {
+        warn(
+            "false_condition",
+            None,
+            "condition is always false, so no task of the workflow runs".to_string(),
+        );
+    }
+    check_tasks(&workflow.tasks, None, "", &mut warn);
+    warnings
+}
+
+/// Report literal `false` conditions and reused ids in `tasks`, recursing
+/// into `foreach` bodies, each its own id scope. `owner` is the top-level
+/// task the findings are attributed to and `prefix` locates nested tasks.
+fn check_tasks(
+    tasks: &[Task],
+    owner: Option<&str>,
+    prefix: &str,
+    warn: &mut impl FnMut(&str, Option<&str>, String),
+) {
+    let mut ids = HashSet::new();
+    for task in tasks {
+        let task_id = owner.unwrap_or(&task.id);
+        if !ids.insert(task.id.as_str()) {
+            warn(
+                "duplicate_task_id",
+                Some(task_id),
+                format!("{prefix}task id '{}' is used more than once", task.id),
+            );
+        }
+        if task.condition == Value::Bool(false) {
+            warn(
+                "false_condition",
+                Some(task_id),
+                format!(
+                    "{prefix}task '{}' has a condition that is always false and never runs",
+                    task.id
+                ),
+            );
+        }
+        if let FunctionConfig::Foreach { input, .. } = &task.function {
+            let prefix = format!("{prefix}foreach task {}: ", task.id);
+            check_tasks(&input.tasks, Some(task_id), &prefix, warn);
+        }
+    }
+}
+
+/// `priority_collision` lints for the active workflows of `workflows`.
+pub(crate) fn lint_priorities(
+    workflows: &[Workflow],
+    tie_break: PriorityTieBreak,
+) -> Vec<LintWarning> {
+    if tie_break == PriorityTieBreak::Id {
+        return Vec::new();
+    }
+    let mut groups: BTreeMap<(&str, u32), Vec<&Workflow>> = BTreeMap::new();
+    for workflow in workflows {
+        if workflow.status == WorkflowStatus::Active {
+            groups
+                .entry((workflow.channel.as_str(), workflow.priority))
+                .or_default()
+                .push(workflow);
+        }
+    }
+    let ordered =
+        |a: &Workflow, b: &Workflow| a.depends_on.contains(&b.id) || b.depends_on.contains(&a.id);
+    let mut warnings = Vec::new();
+    for group in groups.values() {
+        for (idx, workflow) in group.iter().enumerate() {
+            for earlier in &group[..idx] {
+                if !ordered(earlier, workflow) {
+                    warnings.push(LintWarning {
+                        code: "priority_collision".to_string(),
+                        workflow_id: workflow.id.clone(),
+                        task_id: None,
+                        message: format!(
+                            "shares priority {} with workflow '{}' on channel '{}'; \
+                             their order depends on declaration order",
+                            workflow.priority, earlier.id, workflow.channel
+                        ),
+                    });
+                }
+            }
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workflow(json: &str) -> Workflow {
+        Workflow::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn reports_false_conditions_empty_workflows_and_reused_ids() {
+        let wf = workflow(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "a", "name": "A", "condition": false, "function": {"name": "log", "input": {"message": "a"}}},
+                {"id": "a", "name": "A again", "function": {"name": "foreach", "input": {"path": "data.items", "tasks": [
+                    {"id": "n", "name": "N", "condition": false, "function": {"name": "log", "input": {"message": "n"}}},
+                    {"id": "n", "name": "N", "function": {"name": "log", "input": {"message": "n"}}}
+                ]}}},
+                {"id": "b", "name": "B", "condition": {"==": [1, 2]}, "function": {"name": "log", "input": {"message": "b"}}}
+            ]}"#,
+        );
+        let found: Vec<_> = lint_workflow(&wf)
+            .into_iter()
+            .map(|w| (w.code, w.task_id.unwrap(), w.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "false_condition".to_string(),
+                    "a".to_string(),
+                    "task 'a' has a condition that is always false and never runs".to_string()
+                ),
+                (
+                    "duplicate_task_id".to_string(),
+                    "a".to_string(),
+                    "task id 'a' is used more than once".to_string()
+                ),
+                (
+                    "false_condition".to_string(),
+                    "a".to_string(),
+                    "foreach task a: task 'n' has a condition that is always false and never runs"
+                        .to_string()
+                ),
+                (
+                    "duplicate_task_id".to_string(),
+                    "a".to_string(),
+                    "foreach task a: task id 'n' is used more than once".to_string()
+                ),
+            ]
+        );
+
+        let empty = workflow(r#"{"id": "e", "name": "E", "condition": false, "tasks": []}"#);
+        let codes: Vec<_> = lint_workflow(&empty).into_iter().map(|w| w.code).collect();
+        assert_eq!(codes, vec!["empty_workflow", "false_condition"]);
+    }
+
+    #[test]
+    fn reports_tied_priorities_nothing_orders() {
+        let wf = |id: &str, extra: &str| {
+            workflow(&format!(
+                r#"{{"id": "{id}", "name": "{id}", "priority": 5{extra}, "tasks": [
+                    {{"id": "t", "name": "T", "function": {{"name": "log", "input": {{"message": "x"}}}}}}
+                ]}}"#
+            ))
+        };
+        let workflows = vec![
+            wf("a", ""),
+            wf("b", ""),
+            wf("c", r#", "depends_on": ["a", "b"]"#),
+            wf("d", r#", "channel": "other""#),
+            wf("e", r#", "status": "paused""#),
+        ];
+        let warnings = lint_priorities(&workflows, PriorityTieBreak::DeclarationOrder);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "priority_collision");
+        assert_eq!(warnings[0].workflow_id, "b");
+        assert_eq!(
+            warnings[0].message,
+            "shares priority 5 with workflow 'a' on channel 'default'; \
+             their order depends on declaration order"
+        );
+        assert!(lint_priorities(&workflows, PriorityTieBreak::Id).is_empty());
+    }
+}
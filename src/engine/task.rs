@@ -4,9 +4,12 @@
 //! processing unit within a workflow. Tasks are the fundamental building
 //! blocks of data processing pipelines.
 
+use crate::engine::audit::AuditInputs;
 use crate::engine::functions::FunctionConfig;
+use crate::engine::workflow::Annotations;
 use datalogic_rs::Logic;
-use serde::Deserialize;
+use datavalue::OwnedDataValue;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::sync::Arc;
 
@@ -28,10 +31,11 @@ use std::sync::Arc;
 ///         "name": "validation",
 ///         "input": { "rules": [...] }
 ///     },
-///     "continue_on_error": false
+///     "continue_on_error": false,
+///     "output": "temp_data.validation_result"
 /// }
 /// ```
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Task {
     /// Unique identifier for the task within the workflow.
     pub id: String,
@@ -72,6 +76,64 @@ pub struct Task {
     /// Defaults to `false`.
     #[serde(default)]
     pub continue_on_error: bool,
+
+    /// Optional dot-path (e.g. `"temp_data.http_result"`) where the value a
+    /// handler hands to [`crate::TaskContext::set_output`] is written. The
+    /// write is recorded as a `Change` like any other `TaskContext::set`.
+    /// When absent, handler outputs are discarded.
+    #[serde(default)]
+    pub output: Option<String>,
+
+    /// When `true`, the task's side effect is written to the engine's
+    /// outbox instead of being performed inline, and delivered later by an
+    /// [`crate::OutboxDispatcher`]. Only valid on async tasks. Defaults to
+    /// `false`.
+    #[serde(default)]
+    pub outbox: bool,
+
+    /// Upper bound, in milliseconds, on one run of an async task's handler.
+    /// A run that exceeds it is abandoned, its writes rolled back, and a
+    /// `TIMEOUT_ERROR` recorded (retryable under the engine's
+    /// [`RetryConfig`](crate::RetryConfig)). Ignored for sync built-ins and
+    /// on `wasm32`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+
+    /// Retries of a retryable failure for this task, overriding the
+    /// engine's [`RetryConfig`](crate::RetryConfig) `max_retries`.
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+
+    /// Delay before this task's first retry, in milliseconds, overriding
+    /// the engine's `initial_backoff_ms`. Later retries double it up to the
+    /// engine's `max_backoff_ms`.
+    #[serde(default)]
+    pub backoff_ms: Option<u64>,
+
+    /// Whether this task's retry delays are jittered, overriding the
+    /// engine's `jitter`.
+    #[serde(default)]
+    pub jitter: Option<bool>,
+
+    /// Free-form labels, layered over the workflow's annotations (task keys
+    /// win) at `metadata.annotations` while the task runs, and copied into
+    /// its audit entry.
+    #[serde(default)]
+    pub annotations: Annotations,
+
+    /// Engine-internal: workflow and task annotations merged into an
+    /// `OwnedDataValue` object, `None` when both are empty. Populated by
+    /// `LogicCompiler`. Not part of the stable API.
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub compiled_annotations: Option<Arc<OwnedDataValue>>,
+
+    /// Engine-internal: config hash and read paths for audit hashing,
+    /// populated by `LogicCompiler` when it is enabled. Not part of the
+    /// stable API.
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub compiled_audit_inputs: Option<Arc<AuditInputs>>,
 }
 
 impl Task {
@@ -94,6 +156,15 @@ impl Task {
             compiled_condition: None,
             function,
             continue_on_error: false,
+            output: None,
+            outbox: false,
+            timeout_ms: None,
+            max_retries: None,
+            backoff_ms: None,
+            jitter: None,
+            annotations: Annotations::new(),
+            compiled_annotations: None,
+            compiled_audit_inputs: None,
         }
     }
 }
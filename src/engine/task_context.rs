@@ -2,7 +2,8 @@
 //!
 //! Wraps the per-call state passed to every `AsyncFunctionHandler::execute`
 //! call: the message under processing, a handle to the shared datalogic
-//! engine, and an audit-trail accumulator. Exposes typed helpers so handlers
+//! engine, the engine's state store and expression cache, and an
+//! audit-trail accumulator. Exposes typed helpers so handlers
 //! don't have to reach into `crate::engine::utils::{get,set}_nested_value`
 //! or hand-build `Change` entries.
 //!
@@ -11,13 +12,18 @@
 //! when `message.capture_changes` is true, keeping the audit trail in sync
 //! with the data without per-handler boilerplate.
 
-use crate::engine::error::ErrorInfo;
+use crate::engine::error::{DataflowError, ErrorInfo, Result};
+use crate::engine::executor::eval_to_owned;
+use crate::engine::expression_cache::ExpressionCache;
+use crate::engine::extensions::Extensions;
 use crate::engine::message::{Change, Message};
+use crate::engine::state::StateStore;
 use crate::engine::utils::{get_nested_value, set_nested_value};
 use datalogic_rs::Engine as DatalogicEngine;
 use datavalue::OwnedDataValue;
 use serde_json::Value as JsonValue;
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 
 /// Per-call execution context handed to `AsyncFunctionHandler::execute`.
 ///
@@ -29,10 +35,21 @@ use std::sync::Arc;
 pub struct TaskContext<'a> {
     message: &'a mut Message,
     datalogic: &'a Arc<DatalogicEngine>,
+    /// The engine's state store, set by the executor.
+    state: Option<&'a dyn StateStore>,
+    /// The engine's expression cache, set by the executor.
+    expressions: Option<&'a ExpressionCache>,
     /// Changes accumulated through the `set*` family. Only populated when
     /// `message.capture_changes` is true; otherwise pushes are no-ops to
     /// keep the bulk-pipeline fast path allocation-free.
     changes: Vec<Change>,
+    /// Each top-level context section (`data`, `metadata`, …) this call
+    /// wrote to, as it was before — `None` when it was absent. Kept whether
+    /// or not changes are captured, for [`Self::rollback`].
+    before: Vec<(String, Option<OwnedDataValue>)>,
+    /// Result value handed over via `set_output`; written to the task's
+    /// declared `output` path by the executor after the handler returns.
+    output: Option<OwnedDataValue>,
 }
 
 impl<'a> TaskContext<'a> {
@@ -44,10 +61,27 @@ impl<'a> TaskContext<'a> {
         Self {
             message,
             datalogic,
+            state: None,
+            expressions: None,
             changes: Vec::new(),
+            before: Vec::new(),
+            output: None,
         }
     }
 
+    /// Attach a state store, returned by [`Self::state`]. The executor
+    /// passes the engine's; tests can pass their own.
+    pub fn with_state_store(mut self, store: &'a dyn StateStore) -> Self {
+        self.state = Some(store);
+        self
+    }
+
+    /// Use the engine's expression cache for [`Self::evaluate`].
+    pub(crate) fn with_expression_cache(mut self, expressions: &'a ExpressionCache) -> Self {
+        self.expressions = Some(expressions);
+        self
+    }
+
     /// Borrow the message under processing. Use this when you need to inspect
     /// the message id, payload, or audit trail; for reading and mutating the
     /// `data` / `metadata` / `temp_data` context, prefer the typed helpers on
@@ -72,6 +106,32 @@ impl<'a> TaskContext<'a> {
         self.datalogic
     }
 
+    /// Evaluate a JSONLogic expression — say one taken from the task's
+    /// input or built at run time — against the message context. Compiled
+    /// expressions are cached by the engine, so a handler evaluating the
+    /// same expression for every message compiles it once. See
+    /// [`expression_cache`](crate::engine::expression_cache).
+    pub fn evaluate(&self, logic: &JsonValue) -> Result<OwnedDataValue> {
+        let compiled = match self.expressions {
+            Some(cache) => cache.get_or_compile(logic, self.datalogic)?,
+            None => self
+                .datalogic
+                .compile_arc(logic)
+                .map_err(|e| DataflowError::LogicEvaluation(e.to_string()))?,
+        };
+        eval_to_owned(self.datalogic, &compiled, &self.message.context)
+            .map_err(|e| DataflowError::LogicEvaluation(e.to_string()))
+    }
+
+    /// The engine's [`StateStore`] — the one `state_get` / `state_set` use —
+    /// for state shared across messages. Always set when the engine runs the
+    /// handler; `None` only for contexts built by hand without
+    /// [`Self::with_state_store`].
+    #[inline]
+    pub fn state(&self) -> Option<&'a dyn StateStore> {
+        self.state
+    }
+
     /// Read-only view of `data`. Returns `&OwnedDataValue::Null` if missing
     /// (mirrors the `Index` fallback semantics of `serde_json::Value`).
     #[inline]
@@ -110,6 +170,7 @@ impl<'a> TaskContext<'a> {
     /// [`crate::engine::utils::set_nested_value`] for the exact semantics
     /// (numeric segments → arrays, `#` prefix → escaped object key, etc.).
     pub fn set(&mut self, path: &str, value: OwnedDataValue) {
+        self.keep_section(path, &[]);
         if self.message.capture_changes {
             let old_value = get_nested_value(&self.message.context, path)
                 .cloned()
@@ -124,6 +185,41 @@ impl<'a> TaskContext<'a> {
         set_nested_value(&mut self.message.context, path, value);
     }
 
+    /// Buffer changes made without [`Self::set`] — say through
+    /// [`Self::message_mut`] — for the audit trail, as `set` would have.
+    /// Build them with [`ChangeTracker`](crate::ChangeTracker). Dropped
+    /// when `message.capture_changes` is false.
+    pub fn record_changes(&mut self, changes: impl IntoIterator<Item = Change>) {
+        let changes: Vec<Change> = changes.into_iter().collect();
+        for change in &changes {
+            self.keep_section(&change.path, &changes);
+        }
+        if self.message.capture_changes {
+            self.changes.extend(changes);
+        }
+    }
+
+    /// Remember the section `path` is in as it was before this call first
+    /// wrote to it. `applied` are changes already made through
+    /// [`Self::message_mut`]; those in the section are undone on the copy.
+    fn keep_section(&mut self, path: &str, applied: &[Change]) {
+        let section = path.split('.').next().unwrap_or_default();
+        if section.is_empty() || self.before.iter().any(|(kept, _)| kept == section) {
+            return;
+        }
+        let mut copy = OwnedDataValue::Object(
+            get_nested_value(&self.message.context, section)
+                .map(|value| vec![(section.to_string(), value.clone())])
+                .unwrap_or_default(),
+        );
+        let in_section = |change: &&Change| change.path.split('.').next() == Some(section);
+        for change in applied.iter().filter(in_section).rev() {
+            set_nested_value(&mut copy, &change.path, change.old_value.clone());
+        }
+        let value = get_nested_value(&copy, section).cloned();
+        self.before.push((section.to_string(), value));
+    }
+
     /// Same as [`Self::set`] but accepts a `serde_json::Value` (bridges
     /// through `OwnedDataValue::from`). Convenience for handlers that
     /// already speak `serde_json::Value`.
@@ -132,6 +228,56 @@ impl<'a> TaskContext<'a> {
         self.set(path, OwnedDataValue::from(value));
     }
 
+    /// The message's cancellation token, if the caller set one. Long-running
+    /// handlers can poll [`Self::is_cancelled`] or select on
+    /// `token.cancelled()` to give up early; the engine itself only checks
+    /// between tasks.
+    #[inline]
+    pub fn cancellation(&self) -> Option<&CancellationToken> {
+        self.message.cancellation()
+    }
+
+    /// Whether the message's cancellation token has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation().is_some_and(|t| t.is_cancelled())
+    }
+
+    /// Typed per-message scratch store. Values inserted by one task are
+    /// visible to every later task of the same message.
+    #[inline]
+    pub fn extensions(&self) -> &Extensions {
+        self.message.extensions()
+    }
+
+    /// Mutable access to the per-message scratch store. Unlike [`Self::set`],
+    /// writes here are not recorded in the audit trail.
+    #[inline]
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        self.message.extensions_mut()
+    }
+
+    /// Hand the task's result value to the executor. When the task declares
+    /// an `output` path, the executor writes the value there (recording a
+    /// `Change`) after the handler returns; otherwise the value is dropped.
+    /// Calling this more than once keeps the last value.
+    #[inline]
+    pub fn set_output(&mut self, value: OwnedDataValue) {
+        self.output = Some(value);
+    }
+
+    /// Same as [`Self::set_output`] but accepts a `serde_json::Value`.
+    #[inline]
+    pub fn set_output_json(&mut self, value: &JsonValue) {
+        self.set_output(OwnedDataValue::from(value));
+    }
+
+    /// Take the value handed over via [`Self::set_output`], leaving `None`.
+    #[inline]
+    pub fn take_output(&mut self) -> Option<OwnedDataValue> {
+        self.output.take()
+    }
+
     /// Append an error to `message.errors`. Convenience for
     /// `ctx.message_mut().add_error(...)`.
     #[inline]
@@ -139,6 +285,27 @@ impl<'a> TaskContext<'a> {
         self.message.add_error(error);
     }
 
+    /// Revert every write made so far in this call through the `set`
+    /// family or [`Self::record_changes`], and clear the buffered changes.
+    /// Each top-level section written to is restored as it was, so paths
+    /// that didn't exist are removed again, whether or not changes are
+    /// captured. The executor calls this when a handler returns `Err`, so
+    /// a retried task starts from the message state it saw on its first
+    /// attempt.
+    pub fn rollback(&mut self) {
+        self.changes.clear();
+        for (section, value) in std::mem::take(&mut self.before) {
+            match value {
+                Some(value) => set_nested_value(&mut self.message.context, &section, value),
+                None => {
+                    if let OwnedDataValue::Object(pairs) = &mut self.message.context {
+                        pairs.retain(|(key, _)| *key != section);
+                    }
+                }
+            }
+        }
+    }
+
     /// Drain the accumulated changes. The workflow executor calls this after
     /// the handler returns to fold them into the audit trail; tests and
     /// benchmarks driving the trait directly can use it to inspect what the
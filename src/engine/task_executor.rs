@@ -6,17 +6,26 @@
 //! the async path — `HttpCall`, `Enrich`, `PublishKafka`, and `Custom` —
 //! routed to the matching registered handler.
 
+use crate::engine::alert::AlertSink;
+use crate::engine::bulkhead::Bulkheads;
+use crate::engine::circuit_breaker::CircuitBreakers;
 use crate::engine::error::{DataflowError, Result};
+use crate::engine::expression_cache::{DEFAULT_EXPRESSION_CACHE_SIZE, ExpressionCache};
+use crate::engine::functions::config::BUILTIN_FUNCTION_NAMES;
+use crate::engine::functions::join::JoinState;
 use crate::engine::functions::{BoxedFunctionHandler, FunctionConfig};
 use crate::engine::message::{Change, Message};
+use crate::engine::rate_limit::RateLimiters;
 use crate::engine::task::Task;
 use crate::engine::task_context::TaskContext;
 use crate::engine::task_outcome::TaskOutcome;
 use datalogic_rs::Engine;
+use futures_util::future::BoxFuture;
 use log::{debug, error};
 use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Handles the execution of tasks with their associated functions.
 ///
@@ -33,6 +42,18 @@ pub struct TaskExecutor {
     task_functions: Arc<HashMap<String, BoxedFunctionHandler>>,
     /// Shared datalogic Engine (Send + Sync; Arc-shared across tasks)
     engine: Arc<Engine>,
+    /// Open groups of `join` tasks
+    joins: Arc<JoinState>,
+    /// Circuit breakers around handlers, by function name
+    circuit_breakers: Arc<CircuitBreakers>,
+    /// Rate limits on handlers, by function name
+    rate_limits: Arc<RateLimiters>,
+    /// Concurrency limits on handlers, by function name
+    bulkheads: Arc<Bulkheads>,
+    /// Compiled ad-hoc expressions, for handlers' `TaskContext::evaluate`
+    expressions: Arc<ExpressionCache>,
+    /// Destination of `alert` tasks, when configured
+    alerts: Option<Arc<dyn AlertSink>>,
 }
 
 impl TaskExecutor {
@@ -44,9 +65,51 @@ impl TaskExecutor {
         Self {
             task_functions,
             engine,
+            joins: Arc::default(),
+            circuit_breakers: Arc::default(),
+            rate_limits: Arc::default(),
+            bulkheads: Arc::default(),
+            expressions: Arc::new(ExpressionCache::new(DEFAULT_EXPRESSION_CACHE_SIZE)),
+            alerts: None,
         }
     }
 
+    /// Share `expressions` with handlers through their `TaskContext`.
+    pub(crate) fn with_expressions(mut self, expressions: Arc<ExpressionCache>) -> Self {
+        self.expressions = expressions;
+        self
+    }
+
+    /// Keep the groups of `join` tasks in `joins`.
+    pub(crate) fn with_joins(mut self, joins: Arc<JoinState>) -> Self {
+        self.joins = joins;
+        self
+    }
+
+    /// Guard handler calls with `breakers`.
+    pub(crate) fn with_circuit_breakers(mut self, breakers: Arc<CircuitBreakers>) -> Self {
+        self.circuit_breakers = breakers;
+        self
+    }
+
+    /// Pace handler calls with `limits`.
+    pub(crate) fn with_rate_limits(mut self, limits: Arc<RateLimiters>) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Send the alerts of `alert` tasks to `sink`.
+    pub(crate) fn with_alerts(mut self, sink: Option<Arc<dyn AlertSink>>) -> Self {
+        self.alerts = sink;
+        self
+    }
+
+    /// Cap concurrent handler calls with `bulkheads`.
+    pub(crate) fn with_bulkheads(mut self, bulkheads: Arc<Bulkheads>) -> Self {
+        self.bulkheads = bulkheads;
+        self
+    }
+
     /// Execute a single task. Sync built-ins reach here only when called from
     /// outside the workflow executor's sync-stretch path — they fall back to
     /// their `execute()` methods (which open a fresh thread-local arena).
@@ -54,6 +117,29 @@ impl TaskExecutor {
         &self,
         task: &Task,
         message: &mut Message,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        let timeout = task.timeout_ms.map(Duration::from_millis);
+        self.execute_within(task, message, timeout).await
+    }
+
+    /// [`execute`](Self::execute) for a task nested in a `foreach`. The
+    /// future is boxed because it recurses through this executor.
+    pub(crate) fn execute_nested<'a>(
+        &'a self,
+        task: &'a Task,
+        message: &'a mut Message,
+    ) -> BoxFuture<'a, Result<(TaskOutcome, Vec<Change>)>> {
+        Box::pin(self.execute(task, message))
+    }
+
+    /// [`execute`](Self::execute) with an explicit bound on the handler run
+    /// time, in place of the task's own `timeout_ms`. Sync built-ins are not
+    /// bounded.
+    pub(crate) async fn execute_within(
+        &self,
+        task: &Task,
+        message: &mut Message,
+        timeout: Option<Duration>,
     ) -> Result<(TaskOutcome, Vec<Change>)> {
         debug!(
             "Executing task: {} with function: {:?}",
@@ -80,15 +166,70 @@ impl TaskExecutor {
             }
             FunctionConfig::Filter { input, .. } => input.execute(message, &self.engine),
             FunctionConfig::Log { input, .. } => input.execute(message, &self.engine),
+            FunctionConfig::Project { input, .. } => {
+                crate::engine::functions::project::execute_project(message, input)
+            }
+            FunctionConfig::Flatten { input, .. } => {
+                crate::engine::functions::flatten::execute_flatten(message, input)
+            }
+            FunctionConfig::Unflatten { input, .. } => {
+                crate::engine::functions::flatten::execute_unflatten(message, input)
+            }
+            FunctionConfig::RenameKeys { input, .. } => {
+                crate::engine::functions::rename_keys::execute_rename_keys(message, input)
+            }
+            FunctionConfig::Sample { input, .. } => {
+                crate::engine::functions::sample::execute_sample(message, input)
+            }
+            FunctionConfig::Switch { input, .. } => input.execute(message, &self.engine),
+            FunctionConfig::Reject { input, .. } => input.execute(message, &self.engine),
+            FunctionConfig::Score { input, .. } => input.execute(message, &self.engine),
+            FunctionConfig::MapErrors { input, .. } => {
+                crate::engine::functions::map_errors::execute_map_errors(message, input)
+            }
+            FunctionConfig::Foreach { input, .. } => {
+                input.execute(message, self, &self.engine).await
+            }
+            FunctionConfig::Join { input, .. } => {
+                input.execute(message, &self.joins, &self.engine).await
+            }
+            FunctionConfig::StateGet { input, .. } => {
+                input
+                    .execute(message, self.joins.store().as_ref(), &self.engine)
+                    .await
+            }
+            FunctionConfig::StateSet { input, .. } => {
+                input
+                    .execute(message, self.joins.store().as_ref(), &self.engine)
+                    .await
+            }
+            FunctionConfig::Alert { input, .. } => {
+                input
+                    .execute(message, self.alerts.as_deref(), &self.engine)
+                    .await
+            }
+            FunctionConfig::Include { input, .. } => Err(DataflowError::Workflow(format!(
+                "Unresolved include of fragment '{}'",
+                input.fragment
+            ))),
             // Async / user-registered handlers
             FunctionConfig::HttpCall { input, .. } => {
-                self.dispatch_handler("http_call", message, input).await
+                self.dispatch_handler("http_call", message, input, task.output.as_deref(), timeout)
+                    .await
             }
             FunctionConfig::Enrich { input, .. } => {
-                self.dispatch_handler("enrich", message, input).await
+                self.dispatch_handler("enrich", message, input, task.output.as_deref(), timeout)
+                    .await
             }
             FunctionConfig::PublishKafka { input, .. } => {
-                self.dispatch_handler("publish_kafka", message, input).await
+                self.dispatch_handler(
+                    "publish_kafka",
+                    message,
+                    input,
+                    task.output.as_deref(),
+                    timeout,
+                )
+                .await
             }
             FunctionConfig::Custom {
                 name,
@@ -102,8 +243,14 @@ impl TaskExecutor {
                         name
                     ))
                 })?;
-                self.dispatch_handler_any(name, message, any_input.as_any())
-                    .await
+                self.dispatch_handler_any(
+                    name,
+                    message,
+                    any_input.as_any(),
+                    task.output.as_deref(),
+                    timeout,
+                )
+                .await
             }
         }
     }
@@ -117,39 +264,81 @@ impl TaskExecutor {
         name: &str,
         message: &mut Message,
         input: &T,
+        output_path: Option<&str>,
+        timeout: Option<Duration>,
     ) -> Result<(TaskOutcome, Vec<Change>)>
     where
         T: Any + Send + Sync,
     {
         let any_input: &(dyn Any + Send + Sync) = input;
-        self.dispatch_handler_any(name, message, any_input).await
+        self.dispatch_handler_any(name, message, any_input, output_path, timeout)
+            .await
     }
 
-    /// Inner dispatch: build a `TaskContext`, invoke the handler, drain the
-    /// accumulated `Change` buffer.
+    /// Inner dispatch: build a `TaskContext`, invoke the handler, write any
+    /// handler output to the task's declared `output` path, drain the
+    /// accumulated `Change` buffer. On handler error — including running
+    /// past `timeout` — the buffered changes are rolled back before the
+    /// error propagates. An open circuit for `name` fails the call before
+    /// the handler runs.
     async fn dispatch_handler_any(
         &self,
         name: &str,
         message: &mut Message,
         any_input: &(dyn Any + Send + Sync),
+        output_path: Option<&str>,
+        timeout: Option<Duration>,
     ) -> Result<(TaskOutcome, Vec<Change>)> {
         let handler = self.task_functions.get(name).ok_or_else(|| {
             error!("Function handler not found: {}", name);
             DataflowError::FunctionNotFound(name.to_string())
         })?;
-        let mut ctx = TaskContext::new(message, &self.engine);
-        let outcome = handler.dyn_execute(&mut ctx, any_input).await?;
+        self.rate_limits.acquire(name).await;
+        let permit = self.circuit_breakers.admit(name)?;
+        let mut ctx = TaskContext::new(message, &self.engine)
+            .with_state_store(self.joins.store().as_ref())
+            .with_expression_cache(&self.expressions);
+        let execution = async {
+            let _slot = self.bulkheads.enter(name).await;
+            handler.dyn_execute(&mut ctx, any_input).await
+        };
+        let result = match timeout {
+            Some(limit) => within(limit, execution).await.unwrap_or_else(|| {
+                Err(DataflowError::Timeout(format!(
+                    "Function {} did not finish within {}ms",
+                    name,
+                    limit.as_millis()
+                )))
+            }),
+            None => execution.await,
+        };
+        permit.record(&result);
+        let outcome = match result {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                // Undo partial writes so a retry of this task is idempotent
+                // with respect to message state.
+                ctx.rollback();
+                return Err(e);
+            }
+        };
+        if let Some(value) = ctx.take_output() {
+            match output_path {
+                Some(path) => ctx.set(path, value),
+                None => debug!(
+                    "Function {} produced an output but the task declares none",
+                    name
+                ),
+            }
+        }
         let changes = ctx.into_changes();
         Ok((outcome, changes))
     }
 
-    /// Check if a function handler exists
+    /// Check if a function handler exists: a built-in (see
+    /// `BUILTIN_FUNCTION_NAMES`) or a registered custom handler
     pub fn has_function(&self, name: &str) -> bool {
-        match name {
-            "map" | "validation" | "validate" | "parse_json" | "parse_xml" | "publish_json"
-            | "publish_xml" | "filter" | "log" | "http_call" | "enrich" | "publish_kafka" => true,
-            custom_name => self.task_functions.contains_key(custom_name),
-        }
+        BUILTIN_FUNCTION_NAMES.contains(&name) || self.task_functions.contains_key(name)
     }
 
     /// Get a clone of the task_functions Arc for reuse in new engines
@@ -157,12 +346,45 @@ impl TaskExecutor {
         Arc::clone(&self.task_functions)
     }
 
+    /// Get a clone of the join groups Arc for reuse in new engines
+    pub(crate) fn joins(&self) -> Arc<JoinState> {
+        Arc::clone(&self.joins)
+    }
+
+    /// Get a clone of the circuit breakers Arc for reuse in new engines
+    pub(crate) fn circuit_breakers(&self) -> Arc<CircuitBreakers> {
+        Arc::clone(&self.circuit_breakers)
+    }
+
+    /// Get a clone of the handler rate limits Arc for reuse in new engines
+    pub(crate) fn rate_limits(&self) -> Arc<RateLimiters> {
+        Arc::clone(&self.rate_limits)
+    }
+
+    /// Get a clone of the bulkheads Arc for reuse in new engines
+    pub(crate) fn bulkheads(&self) -> Arc<Bulkheads> {
+        Arc::clone(&self.bulkheads)
+    }
+
     /// Get the count of registered custom functions
     pub fn custom_function_count(&self) -> usize {
         self.task_functions.len()
     }
 }
 
+/// Run `future` to completion unless `limit` elapses first. Timers need a
+/// Tokio runtime with time enabled; on `wasm32` there is none and the
+/// future always runs to completion.
+#[cfg(not(target_arch = "wasm32"))]
+async fn within<F: std::future::Future>(limit: Duration, future: F) -> Option<F::Output> {
+    tokio::time::timeout(limit, future).await.ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn within<F: std::future::Future>(_limit: Duration, future: F) -> Option<F::Output> {
+    Some(future.await)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,9 +397,11 @@ mod tests {
         let task_executor = TaskExecutor::new(Arc::new(HashMap::new()), engine);
 
         // Built-in functions
-        assert!(task_executor.has_function("map"));
-        assert!(task_executor.has_function("validation"));
-        assert!(task_executor.has_function("validate"));
+        for name in BUILTIN_FUNCTION_NAMES {
+            assert!(task_executor.has_function(name), "{name}");
+        }
+        assert!(task_executor.has_function("switch"));
+        assert!(task_executor.has_function("include"));
 
         // Non-existent function
         assert!(!task_executor.has_function("nonexistent"));
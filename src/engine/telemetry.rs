@@ -0,0 +1,167 @@
+//! # Telemetry
+//!
+//! OpenTelemetry spans, behind the `otel` feature: one `process_message`
+//! span per message, a `workflow` span per executed workflow and a `task`
+//! span per executed task, each nested in the one before.
+//!
+//! Spans go to the tracer registered with
+//! `opentelemetry::global::set_tracer_provider`. The message span is a
+//! child of the caller's current context, so attaching the context
+//! extracted from an upstream HTTP request before calling
+//! `process_message` links the engine's spans into that trace. Task
+//! handlers run inside their task's span and can propagate it downstream.
+//!
+//! | Span | Attributes |
+//! |------|------------|
+//! | `process_message` | `message.id`, `message.channel`, `message.errors` |
+//! | `workflow` | `workflow.id` |
+//! | `task` | `workflow.id`, `task.id`, `task.function`, `task.status_code`, `task.changes` |
+//!
+//! A failed message, workflow or task also gets an error status. Without
+//! the feature every call here is a no-op.
+
+use crate::engine::error::Result;
+use crate::engine::message::{Change, Message};
+use crate::engine::task::Task;
+use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::workflow::Workflow;
+use std::future::Future;
+
+#[cfg(feature = "otel")]
+use opentelemetry::{
+    Context, KeyValue,
+    context::FutureExt,
+    global,
+    trace::{Status, TraceContextExt, Tracer},
+};
+
+/// Instrumentation scope name of the engine's tracer.
+#[cfg(feature = "otel")]
+const TRACER: &str = "dataflow-rs";
+
+/// An open span; ended by one of the `end_*` methods.
+pub(crate) struct Span {
+    #[cfg(feature = "otel")]
+    cx: Context,
+}
+
+impl Span {
+    #[cfg(feature = "otel")]
+    fn start(name: &'static str, attributes: Vec<KeyValue>) -> Self {
+        let tracer = global::tracer(TRACER);
+        let span = tracer
+            .span_builder(name)
+            .with_attributes(attributes)
+            .start_with_context(&tracer, &Context::current());
+        Self {
+            cx: Context::current_with_span(span),
+        }
+    }
+
+    /// Span for one `process_message*` call.
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn message(message: &Message, channel: Option<&str>) -> Self {
+        #[cfg(feature = "otel")]
+        {
+            let mut attributes = vec![KeyValue::new("message.id", message.id().to_string())];
+            if let Some(channel) = channel {
+                attributes.push(KeyValue::new("message.channel", channel.to_string()));
+            }
+            Self::start("process_message", attributes)
+        }
+        #[cfg(not(feature = "otel"))]
+        Self {}
+    }
+
+    /// Span for one executed workflow.
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn workflow(workflow: &Workflow) -> Self {
+        #[cfg(feature = "otel")]
+        {
+            Self::start(
+                "workflow",
+                vec![KeyValue::new("workflow.id", workflow.id.clone())],
+            )
+        }
+        #[cfg(not(feature = "otel"))]
+        Self {}
+    }
+
+    /// Span for one executed task.
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn task(workflow: &Workflow, task: &Task) -> Self {
+        #[cfg(feature = "otel")]
+        {
+            Self::start(
+                "task",
+                vec![
+                    KeyValue::new("workflow.id", workflow.id.clone()),
+                    KeyValue::new("task.id", task.id.clone()),
+                    KeyValue::new("task.function", task.function.function_name().to_string()),
+                ],
+            )
+        }
+        #[cfg(not(feature = "otel"))]
+        Self {}
+    }
+
+    /// Run `future` with this span as the current context, so spans started
+    /// inside it become children.
+    pub(crate) fn in_scope<F: Future>(&self, future: F) -> impl Future<Output = F::Output> {
+        #[cfg(feature = "otel")]
+        {
+            future.with_context(self.cx.clone())
+        }
+        #[cfg(not(feature = "otel"))]
+        future
+    }
+
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn end_message(self, message: &Message, result: &Result<()>) {
+        #[cfg(feature = "otel")]
+        {
+            let span = self.cx.span();
+            span.set_attribute(KeyValue::new("message.errors", message.errors.len() as i64));
+            if let Err(e) = result {
+                span.set_status(Status::error(e.to_string()));
+            }
+            span.end();
+        }
+    }
+
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn end_workflow<T>(self, result: &Result<T>) {
+        #[cfg(feature = "otel")]
+        {
+            let span = self.cx.span();
+            if let Err(e) = result {
+                span.set_status(Status::error(e.to_string()));
+            }
+            span.end();
+        }
+    }
+
+    #[cfg_attr(not(feature = "otel"), allow(unused_variables))]
+    pub(crate) fn end_task(self, result: &Result<(TaskOutcome, Vec<Change>)>) {
+        #[cfg(feature = "otel")]
+        {
+            let span = self.cx.span();
+            match result {
+                Ok((outcome, changes)) => {
+                    span.set_attribute(KeyValue::new("task.changes", changes.len() as i64));
+                    if let Some(code) = outcome.audit_status() {
+                        span.set_attribute(KeyValue::new("task.status_code", i64::from(code)));
+                        if code >= 500 {
+                            span.set_status(Status::error(format!("status {code}")));
+                        }
+                    }
+                }
+                Err(e) => {
+                    span.set_attribute(KeyValue::new("task.status_code", 500));
+                    span.set_status(Status::error(e.to_string()));
+                }
+            }
+            span.end();
+        }
+    }
+}
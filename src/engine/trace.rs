@@ -34,6 +34,11 @@ pub struct ExecutionStep {
     /// mapping_contexts[i] = message.context before mapping[i] executed.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mapping_contexts: Option<Vec<Value>>,
+    /// The workflow or task condition behind this step as infix text (see
+    /// [`render_logic_pretty`](crate::render_logic_pretty)); `None` when it
+    /// always runs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
 }
 
 impl ExecutionStep {
@@ -45,6 +50,7 @@ impl ExecutionStep {
             result: StepResult::Executed,
             message: Some(message.clone()),
             mapping_contexts: None,
+            condition: None,
         }
     }
 
@@ -56,6 +62,7 @@ impl ExecutionStep {
             result: StepResult::Skipped,
             message: None,
             mapping_contexts: None,
+            condition: None,
         }
     }
 
@@ -67,9 +74,16 @@ impl ExecutionStep {
             result: StepResult::Skipped,
             message: None,
             mapping_contexts: None,
+            condition: None,
         }
     }
 
+    /// Attach the rendered condition of the step's workflow or task
+    pub fn with_condition(mut self, condition: Option<String>) -> Self {
+        self.condition = condition;
+        self
+    }
+
     /// Set mapping context snapshots (for map tasks in trace mode)
     pub fn with_mapping_contexts(mut self, contexts: Vec<Value>) -> Self {
         self.mapping_contexts = Some(contexts);
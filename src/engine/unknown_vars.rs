@@ -0,0 +1,332 @@
+//! # Unknown Variable References
+//!
+//! `unknown_var` [lint warnings](crate::LintWarning) for likely typos in
+//! `var` paths. A reference such as `data.amunt` evaluates to `null`
+//! without complaint, so a misspelt field silently changes a rule's result.
+//!
+//! Within each workflow the tasks are walked in order, collecting the paths
+//! written so far: mapping paths, parse and publish targets, the `target`
+//! of `sample`, `reject`, `map_errors`, `join`, `state_get`, `flatten`,
+//! `unflatten` and `foreach`, both outputs of `score`, `enrich` merge paths and task
+//! `output` paths. A path read by
+//! a later expression is reported when nothing written covers it but a
+//! written path differs from it in a single segment by a small edit, e.g.
+//! `amunt` against `amount`. Paths the workflow never writes, such as fields of the
+//! payload or of earlier workflows, are not checked.
+//!
+//! A path is also reported when its first segment names a section the
+//! message context can never have: one other than `data`, `metadata` and
+//! `temp_data` that no task of the workflow writes to, such as
+//! `temp_dat.value` or `payload.amount` — the payload is only readable
+//! once a parse function has copied it into `data`.
+
+use crate::engine::functions::FunctionConfig;
+use crate::engine::lint::{LintWarning, task_sites};
+use crate::engine::logic_text::var_paths;
+use crate::engine::task::Task;
+use crate::engine::workflow::Workflow;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// `unknown_var` lints for the expressions of `workflow`.
+pub(crate) fn lint_workflow(workflow: &Workflow) -> Vec<LintWarning> {
+    let mut roots = BTreeSet::from(CONTEXT_ROOTS.map(str::to_string));
+    written_roots(&workflow.tasks, &mut roots);
+    let mut walk = Walk {
+        workflow_id: &workflow.id,
+        roots,
+        written: Vec::new(),
+        reported: BTreeSet::new(),
+        warnings: Vec::new(),
+    };
+    for task in &workflow.tasks {
+        walk.task(task);
+    }
+    walk.warnings
+}
+
+/// Sections every message context has.
+const CONTEXT_ROOTS: [&str; 3] = ["data", "metadata", "temp_data"];
+
+/// Add the first segment of every path `tasks` write, `foreach` bodies
+/// included, to `roots`.
+fn written_roots(tasks: &[Task], roots: &mut BTreeSet<String>) {
+    for task in tasks {
+        let mut paths = written_paths(task);
+        match &task.function {
+            FunctionConfig::Map { input, .. } => {
+                paths.extend(input.mappings.iter().map(|m| m.path.clone()));
+            }
+            FunctionConfig::Foreach { input, .. } => {
+                paths.push(input.item.clone());
+                paths.extend(input.index.clone());
+                written_roots(&input.tasks, roots);
+            }
+            _ => {}
+        }
+        paths.extend(task.output.clone());
+        for path in paths {
+            roots.insert(path.split('.').next().unwrap_or_default().to_string());
+        }
+    }
+}
+
+struct Walk<'a> {
+    workflow_id: &'a str,
+    /// Sections the message context can have while the workflow runs.
+    roots: BTreeSet<String>,
+    /// Paths written by the tasks walked so far.
+    written: Vec<String>,
+    /// Paths already reported, so each typo is reported once.
+    reported: BTreeSet<String>,
+    warnings: Vec<LintWarning>,
+}
+
+impl Walk<'_> {
+    fn task(&mut self, task: &Task) {
+        if let FunctionConfig::Map { input, .. } = &task.function {
+            // Later mappings of a map task see the earlier ones' writes.
+            self.check(&task.id, "condition", &task.condition);
+            for mapping in &input.mappings {
+                self.check(
+                    &task.id,
+                    &format!("mapping {}", mapping.path),
+                    &mapping.logic,
+                );
+                self.written.push(mapping.path.clone());
+            }
+        } else {
+            let mut sites = Vec::new();
+            task_sites(&task.id, "", task, &mut sites);
+            for site in sites {
+                self.check(&task.id, &site.location, site.logic);
+            }
+            self.written.extend(written_paths(task));
+        }
+        if let Some(output) = &task.output {
+            self.written.push(output.clone());
+        }
+    }
+
+    fn check(&mut self, task_id: &str, location: &str, logic: &Value) {
+        let mut paths = BTreeSet::new();
+        var_paths(logic, &mut paths);
+        for path in paths {
+            if self.reported.contains(&path) || self.written.iter().any(|w| covers(w, &path)) {
+                continue;
+            }
+            let root = path.split('.').next().unwrap_or_default();
+            if !self.roots.contains(root) {
+                let mut message = format!(
+                    "{location} reads '{path}', which can never resolve: messages have no '{root}' section"
+                );
+                if root == "payload" {
+                    message.push_str("; parse the payload into data first");
+                } else if let Some(suggestion) = closest_root(root, &self.roots) {
+                    message.push_str(&format!(
+                        "; did you mean '{suggestion}{}'?",
+                        &path[root.len()..]
+                    ));
+                }
+                self.warn(task_id, message);
+                self.reported.insert(path);
+                continue;
+            }
+            if let Some(suggestion) = closest(&path, &self.written) {
+                self.warn(
+                    task_id,
+                    format!(
+                        "{} reads '{}', which no earlier task writes; did you mean '{}'?",
+                        location, path, suggestion
+                    ),
+                );
+                self.reported.insert(path);
+            }
+        }
+    }
+
+    fn warn(&mut self, task_id: &str, message: String) {
+        self.warnings.push(LintWarning {
+            code: "unknown_var".to_string(),
+            workflow_id: self.workflow_id.to_string(),
+            task_id: Some(task_id.to_string()),
+            message,
+        });
+    }
+}
+
+/// The section `root` most likely misspells, a short edit apart.
+fn closest_root<'a>(root: &str, roots: &'a BTreeSet<String>) -> Option<&'a str> {
+    roots
+        .iter()
+        .filter_map(|candidate| {
+            let distance = edit_distance(root, candidate);
+            let allowed = root.chars().count().max(candidate.chars().count()) / 4;
+            (distance <= allowed.max(1)).then_some((distance, candidate.as_str()))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Paths written by a task other than `map`, besides its `output`.
+fn written_paths(task: &Task) -> Vec<String> {
+    match &task.function {
+        FunctionConfig::ParseJson { input, .. } | FunctionConfig::ParseXml { input, .. } => {
+            vec![format!("data.{}", input.target)]
+        }
+        FunctionConfig::PublishJson { input, .. } | FunctionConfig::PublishXml { input, .. } => {
+            vec![format!("data.{}", input.target)]
+        }
+        FunctionConfig::Sample { input, .. } => vec![input.target.clone()],
+        FunctionConfig::Reject { input, .. } => vec![input.target.clone()],
+        FunctionConfig::MapErrors { input, .. } => vec![input.target.clone()],
+        FunctionConfig::Score { input, .. } => {
+            vec![input.target.clone(), input.triggered_target.clone()]
+        }
+        FunctionConfig::Join { input, .. } => vec![input.target.clone()],
+        FunctionConfig::StateGet { input, .. } => vec![input.target.clone()],
+        FunctionConfig::Flatten { input, .. } | FunctionConfig::Unflatten { input, .. } => {
+            vec![input.target.clone().unwrap_or_else(|| input.path.clone())]
+        }
+        FunctionConfig::Foreach { input, .. } => {
+            vec![input.target.clone().unwrap_or_else(|| input.path.clone())]
+        }
+        FunctionConfig::Enrich { input, .. } => vec![input.merge_path.clone()],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether writing `written` makes `path` known: the same path, a field
+/// under it, or an object containing it.
+fn covers(written: &str, path: &str) -> bool {
+    let nested = |outer: &str, inner: &str| {
+        inner
+            .strip_prefix(outer)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    };
+    nested(written, path) || nested(path, written)
+}
+
+/// The written path `path` most likely misspells: one differing segment, a
+/// short edit apart.
+fn closest<'a>(path: &str, written: &'a [String]) -> Option<&'a str> {
+    let segments: Vec<&str> = path.split('.').collect();
+    written
+        .iter()
+        .filter_map(|candidate| {
+            let other: Vec<&str> = candidate.split('.').collect();
+            if other.len() != segments.len() {
+                return None;
+            }
+            let mut differing = segments.iter().zip(&other).filter(|(a, b)| a != b);
+            let (a, b) = differing.next()?;
+            if differing.next().is_some() {
+                return None;
+            }
+            let distance = edit_distance(a, b);
+            let allowed = a.chars().count().max(b.chars().count()) / 4;
+            (distance <= allowed).then_some((distance, candidate.as_str()))
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edits (insertions, deletions, substitutions and swaps of adjacent
+/// characters) turning `a` into `b`.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_near_misses_of_earlier_writes() {
+        let workflow = Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "totals", "name": "Totals", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.amount", "logic": {"var": "data.input.amount"}},
+                    {"path": "data.customer", "logic": {"var": "data.input.customer"}},
+                    {"path": "data.fee", "logic": {"*": [{"var": "data.amunt"}, 0.01]}}
+                ]}}},
+                {"id": "check", "name": "Check", "function": {"name": "validation", "input": {"rules": [
+                    {"logic": {">": [{"var": "data.amunt"}, 0]}, "message": "amount"},
+                    {"logic": {"!!": {"var": "data.customer.naem"}}, "message": "name"},
+                    {"logic": {"!!": {"var": "data.fe"}}, "message": "fee"},
+                    {"logic": {"!!": {"var": "data.currency"}}, "message": "currency"}
+                ]}}}
+            ]}"#,
+        )
+        .unwrap();
+        let warnings = lint_workflow(&workflow);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].code, "unknown_var");
+        assert_eq!(warnings[0].task_id.as_deref(), Some("totals"));
+        assert_eq!(
+            warnings[0].message,
+            "mapping data.fee reads 'data.amunt', which no earlier task writes; did you mean 'data.amount'?"
+        );
+    }
+
+    #[test]
+    fn reports_paths_into_sections_messages_never_have() {
+        let workflow = Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "stage", "name": "Stage", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "temp_data.value", "logic": {"var": "payload.value"}},
+                    {"path": "scratch.total", "logic": {"var": "temp_dat.value"}}
+                ]}}},
+                {"id": "check", "name": "Check",
+                 "condition": {"and": [{"var": "scratch.total"}, {"var": "metadata.source"}]},
+                 "function": {"name": "foreach", "input": {"path": "data.items", "item": "item", "tasks": [
+                    {"id": "price", "name": "Price", "function": {"name": "map", "input": {"mappings": [
+                        {"path": "item.total", "logic": {"*": [{"var": "item.qty"}, {"var": "itme.price"}]}}
+                    ]}}}
+                ]}}}
+            ]}"#,
+        )
+        .unwrap();
+        let messages: Vec<String> = lint_workflow(&workflow)
+            .into_iter()
+            .map(|warning| warning.message)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "mapping temp_data.value reads 'payload.value', which can never resolve: messages have no 'payload' section; parse the payload into data first",
+                "mapping scratch.total reads 'temp_dat.value', which can never resolve: messages have no 'temp_dat' section; did you mean 'temp_data.value'?",
+                "foreach task price: mapping item.total reads 'itme.price', which can never resolve: messages have no 'itme' section; did you mean 'item.price'?",
+            ]
+        );
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_once() {
+        assert_eq!(edit_distance("amunt", "amount"), 1);
+        assert_eq!(edit_distance("naem", "name"), 1);
+        assert_eq!(edit_distance("", "id"), 2);
+        assert_eq!(
+            closest("data.totl", &["data.total".to_string()]),
+            Some("data.total")
+        );
+        assert_eq!(closest("data.b", &["data.a".to_string()]), None);
+        assert_eq!(closest("data.x.totl", &["data.y.total".to_string()]), None);
+    }
+}
@@ -0,0 +1,99 @@
+//! # Child Message Urgency
+//!
+//! Messages split or emitted from another keep its urgency, so a queue
+//! ordering work by priority and deadline doesn't push an urgent order's
+//! line items behind routine traffic.
+//! [`Engine::child_message`](crate::Engine::child_message) starts a message
+//! derived from a parent (see
+//! [`MessageBuilder::derived_from`](crate::MessageBuilder::derived_from))
+//! with the parent's processing priority (`metadata.priority`, see
+//! [`Message::priority`]) and deadline copied over.
+//!
+//! [`EngineOptions::child_urgency`](crate::EngineOptions::child_urgency)
+//! computes them instead, with JSONLogic evaluated against the parent's
+//! context:
+//!
+//! ```json
+//! {
+//!   "child_urgency": {
+//!     "priority": {"+": [{"var": "metadata.priority"}, 1]},
+//!     "deadline_ms": {"if": [
+//!       {"var": "metadata.deadline_remaining_ms"},
+//!       {"-": [{"var": "metadata.deadline_remaining_ms"}, 500]},
+//!       null
+//!     ]}
+//!   }
+//! }
+//! ```
+//!
+//! - `priority` must give a whole number, or `null` for none.
+//! - `deadline_ms` gives the child's time budget in milliseconds from now
+//!   (negative counts as 0), or `null` for no deadline. The parent's time
+//!   left is readable as `metadata.deadline_remaining_ms`, as of the call.
+//!
+//! Anything else fails the call. Either expression left out copies the
+//! parent's value.
+
+use crate::engine::Engine;
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::message::Message;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How children inherit their parent's urgency. See the
+/// [module docs](self).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChildUrgency {
+    /// JSONLogic giving the child's priority. Default: the parent's.
+    pub priority: Option<Value>,
+    /// JSONLogic giving the child's time budget in milliseconds. Default:
+    /// the parent's deadline.
+    pub deadline_ms: Option<Value>,
+}
+
+impl ChildUrgency {
+    /// The priority and deadline of a child of `parent`.
+    pub(crate) fn inherit(
+        &self,
+        engine: &Engine,
+        parent: &Message,
+    ) -> Result<(Option<i64>, Option<DateTime<Utc>>)> {
+        if self.priority.is_none() && self.deadline_ms.is_none() {
+            return Ok((parent.priority(), parent.deadline()));
+        }
+
+        let now = Utc::now();
+        let mut parent = parent.clone();
+        parent.stamp_deadline_remaining(now);
+        let context = Value::from(&parent.context);
+
+        let priority = match &self.priority {
+            None => parent.priority(),
+            Some(logic) => match engine.evaluate(logic, &context)? {
+                Value::Null => None,
+                value => Some(value.as_i64().ok_or_else(|| {
+                    DataflowError::Validation(format!(
+                        "child priority must be a whole number, got {value}"
+                    ))
+                })?),
+            },
+        };
+        let deadline = match &self.deadline_ms {
+            None => parent.deadline(),
+            Some(logic) => match engine.evaluate(logic, &context)? {
+                Value::Null => None,
+                value => {
+                    let ms = value.as_f64().ok_or_else(|| {
+                        DataflowError::Validation(format!(
+                            "child deadline_ms must be a number, got {value}"
+                        ))
+                    })?;
+                    Some(now + Duration::milliseconds(ms.max(0.0) as i64))
+                }
+            },
+        };
+        Ok((priority, deadline))
+    }
+}
@@ -5,6 +5,7 @@
 //! `serde_json::Value` works here unchanged — including `#`-prefix escapes
 //! for numeric object keys.
 
+use crate::engine::message::{Change, Message};
 use datavalue::OwnedDataValue;
 use std::sync::Arc;
 
@@ -246,6 +247,30 @@ pub fn set_nested_value_parts(
     }
 }
 
+/// Replace the value at `path` on `message.context` and return the matching
+/// `Change` (empty when `message.capture_changes` is off). Shared by the
+/// built-ins that rewrite a whole subtree in one write.
+pub(crate) fn replace_with_change(
+    message: &mut Message,
+    path: &str,
+    new_value: OwnedDataValue,
+) -> Vec<Change> {
+    let changes = if message.capture_changes {
+        let old_value = get_nested_value(&message.context, path)
+            .cloned()
+            .unwrap_or(OwnedDataValue::Null);
+        vec![Change {
+            path: Arc::from(path),
+            old_value,
+            new_value: new_value.clone(),
+        }]
+    } else {
+        Vec::new()
+    };
+    set_nested_value(&mut message.context, path, new_value);
+    changes
+}
+
 /// Strip exactly one leading `#` from an object-key path component.
 /// `"#20"` → `"20"`, `"##"` → `"#"`, `"foo"` → `"foo"`.
 #[inline]
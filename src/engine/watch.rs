@@ -0,0 +1,137 @@
+//! # Workflow Directory Watcher
+//!
+//! Reloads a workflow directory when its files change, for deployments
+//! that ship workflows as files. Requires the `watch` feature.
+//!
+//! A [`WorkflowWatcher`] watches a directory recursively. Each call to
+//! [`next`](WorkflowWatcher::next) waits for a change to a `*.json` file
+//! (or a directory) below it, lets further changes settle for the
+//! debounce interval (200 ms by default), then loads the whole directory
+//! again with [`Workflow::load_dir`]. Hand the result to
+//! [`SharedEngine::reload`](crate::SharedEngine::reload), or to
+//! [`Engine::with_new_workflows`](crate::Engine::with_new_workflows):
+//!
+//! ```no_run
+//! # use dataflow_rs::{SharedEngine, WorkflowWatcher};
+//! # async fn demo(shared: SharedEngine) -> dataflow_rs::Result<()> {
+//! let mut watcher = WorkflowWatcher::new("workflows")?;
+//! while let Some(loaded) = watcher.next().await {
+//!     if let Err(e) = loaded.and_then(|workflows| shared.reload(workflows)) {
+//!         log::warn!("keeping the current workflows: {e}");
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A load that fails, e.g. because a file is half written, is returned as
+//! an `Err`; the next change triggers another attempt.
+
+use crate::engine::error::{DataflowError, Result};
+use crate::engine::workflow::Workflow;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Yields a fresh workflow set each time a watched directory changes.
+pub struct WorkflowWatcher {
+    dir: PathBuf,
+    debounce: Duration,
+    changes: mpsc::UnboundedReceiver<()>,
+    // Dropping the watcher stops the notifications.
+    _watcher: RecommendedWatcher,
+}
+
+impl WorkflowWatcher {
+    /// Start watching `dir` and everything below it.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let (tx, changes) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let relevant = match event {
+                    Ok(event) => {
+                        !matches!(event.kind, EventKind::Access(_))
+                            && (event.paths.is_empty()
+                                || event
+                                    .paths
+                                    .iter()
+                                    .any(|path| path.extension().is_none_or(|ext| ext == "json")))
+                    }
+                    Err(e) => {
+                        log::warn!("Workflow watcher error: {e}");
+                        true
+                    }
+                };
+                if relevant {
+                    let _ = tx.send(());
+                }
+            })
+            .map_err(|e| watch_error(&dir, e))?;
+        watcher
+            .watch(&dir, RecursiveMode::Recursive)
+            .map_err(|e| watch_error(&dir, e))?;
+        Ok(Self {
+            dir,
+            debounce: DEFAULT_DEBOUNCE,
+            changes,
+            _watcher: watcher,
+        })
+    }
+
+    /// How long changes must stop before the directory is reloaded.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// The watched directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Wait for the directory to change and load it again. `None` once the
+    /// watcher can no longer deliver changes.
+    pub async fn next(&mut self) -> Option<Result<Vec<Workflow>>> {
+        self.changes.recv().await?;
+        while let Ok(Some(())) = tokio::time::timeout(self.debounce, self.changes.recv()).await {}
+        Some(Workflow::load_dir(&self.dir))
+    }
+}
+
+fn watch_error(dir: &Path, error: notify::Error) -> DataflowError {
+    DataflowError::Io(format!("{}: cannot watch: {error}", dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[tokio::test]
+    async fn reloads_the_directory_after_a_change() {
+        let dir = std::env::temp_dir().join(format!("dataflow-watch-{}", uuid::Uuid::now_v7()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut watcher = WorkflowWatcher::new(&dir)
+            .unwrap()
+            .with_debounce(Duration::from_millis(50));
+
+        fs::write(
+            dir.join("orders.json"),
+            r#"{"id": "orders", "name": "Orders", "tasks": []}"#,
+        )
+        .unwrap();
+        let workflows = tokio::time::timeout(Duration::from_secs(10), watcher.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(workflows.len(), 1);
+        assert_eq!(workflows[0].id, "orders");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
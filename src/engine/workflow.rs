@@ -1,13 +1,27 @@
-use crate::engine::error::{DataflowError, Result};
+use crate::engine::embedded_tests::WorkflowTestCase;
+use crate::engine::error::{DataflowError, Result, invalid, locate, parse_failure};
+use crate::engine::fragment::{anchor_includes, deserialize_tasks};
+use crate::engine::functions::FunctionConfig;
+use crate::engine::locale::LocaleContext;
+use crate::engine::manifest::in_file;
+use crate::engine::parse_mode::{ParseMode, check_workflow};
+use crate::engine::sink::WorkflowOutput;
 use crate::engine::task::Task;
+use crate::engine::variant::WorkflowVariant;
 use chrono::{DateTime, Utc};
 use datalogic_rs::Logic;
+use datavalue::OwnedDataValue;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+/// Version of the normalized interchange format written by
+/// [`Workflow::to_normalized_json`], stamped as `format_version`.
+pub const NORMALIZED_FORMAT_VERSION: u32 = 1;
+
 /// Workflow lifecycle status
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -18,10 +32,28 @@ pub enum WorkflowStatus {
     Archived,
 }
 
+/// How workflows that share the same `priority` are ordered relative to
+/// each other in the engine's pre-sorted workflow list.
+///
+/// The sort happens once at engine construction, so whichever variant is
+/// chosen the resulting order is fixed for the lifetime of the engine and
+/// identical across runs for the same input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriorityTieBreak {
+    /// Keep workflows in the order they were handed to the engine (default).
+    #[default]
+    DeclarationOrder,
+    /// Order tied workflows lexicographically by `id`. Useful when workflows
+    /// are loaded from a directory or merged from several sources and the
+    /// declaration order itself isn't meaningful.
+    Id,
+}
+
 /// Workflow represents a collection of tasks that execute sequentially (also known as a Rule in rules-engine terminology).
 ///
 /// Conditions are evaluated against the full message context, including `data`, `metadata`, and `temp_data` fields.
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Workflow {
     pub id: String,
     /// Engine-internal: `Arc<str>` mirror of `id`, populated by
@@ -34,6 +66,11 @@ pub struct Workflow {
     pub name: String,
     #[serde(default)]
     pub priority: u32,
+    /// Ids of workflows that must run before this one, whatever their
+    /// priority. Priority still orders workflows with no dependency between
+    /// them.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
     pub description: Option<String>,
     #[serde(default = "default_condition")]
     pub condition: Value,
@@ -43,9 +80,29 @@ pub struct Workflow {
     #[doc(hidden)]
     #[serde(skip)]
     pub compiled_condition: Option<Arc<Logic>>,
+    #[serde(deserialize_with = "deserialize_tasks")]
     pub tasks: Vec<Task>,
     #[serde(default)]
     pub continue_on_error: bool,
+    /// Upper bound, in milliseconds, on the workflow's run. Checked before
+    /// each task and applied to async task handlers; when it elapses the
+    /// workflow stops with a `TIMEOUT_ERROR`.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// JSONLogic producing the message's idempotency key. A key already
+    /// seen within `idempotency_ttl_ms` skips the workflow; see
+    /// [`dedup`](crate::engine::dedup).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<Value>,
+    /// How long an idempotency key is remembered, in milliseconds. Default:
+    /// 24 hours.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_ttl_ms: Option<u64>,
+    /// Engine-internal: pre-compiled `idempotency_key`, populated by
+    /// `LogicCompiler`. Not part of the stable API.
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub compiled_idempotency_key: Option<Arc<Logic>>,
     /// Channel for routing (default: "default")
     #[serde(default = "default_channel")]
     pub channel: String,
@@ -64,8 +121,42 @@ pub struct Workflow {
     /// Last update timestamp
     #[serde(default)]
     pub updated_at: Option<DateTime<Utc>>,
+    /// A/B variant-group membership (see [`WorkflowVariant`])
+    #[serde(default)]
+    pub variant: Option<WorkflowVariant>,
+    /// Free-form labels (e.g. `"domain": "sepa"`). Exposed to conditions
+    /// and logic at `metadata.annotations` while the workflow runs, merged
+    /// with the running task's own annotations, and copied into audit
+    /// entries.
+    #[serde(default)]
+    pub annotations: Annotations,
+    /// Engine-internal: `annotations` as an `OwnedDataValue` object, `None`
+    /// when empty. Populated by `LogicCompiler`. Not part of the stable API.
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub compiled_annotations: Option<Arc<OwnedDataValue>>,
+    /// Locale and time zone the workflow formats values for, exposed at
+    /// `metadata.locale` while it runs; see [`locale`](crate::engine::locale).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<LocaleContext>,
+    /// Engine-internal: `locale` as the `metadata.locale` object. Populated
+    /// by `LogicCompiler`. Not part of the stable API.
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub compiled_locale: Option<Arc<OwnedDataValue>>,
+    /// Test cases run by [`Engine::run_workflow_tests`](crate::Engine::run_workflow_tests);
+    /// see [`embedded_tests`](crate::engine::embedded_tests).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tests: Vec<WorkflowTestCase>,
+    /// Sinks the processed message is sent to; see
+    /// [`sink`](crate::engine::sink).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<WorkflowOutput>,
 }
 
+/// Key/value labels on a workflow or task.
+pub type Annotations = BTreeMap<String, Value>;
+
 fn default_condition() -> Value {
     Value::Bool(true)
 }
@@ -91,17 +182,29 @@ impl Workflow {
             id_arc: Arc::from(""),
             name: String::new(),
             priority: 0,
+            depends_on: Vec::new(),
             description: None,
             condition: Value::Bool(true),
             compiled_condition: None,
             tasks: Vec::new(),
             continue_on_error: false,
+            timeout_ms: None,
+            idempotency_key: None,
+            idempotency_ttl_ms: None,
+            compiled_idempotency_key: None,
             channel: default_channel(),
             version: 1,
             status: WorkflowStatus::Active,
             tags: Vec::new(),
             created_at: None,
             updated_at: None,
+            variant: None,
+            annotations: Annotations::new(),
+            compiled_annotations: None,
+            locale: None,
+            compiled_locale: None,
+            tests: Vec::new(),
+            output: Vec::new(),
         }
     }
 
@@ -121,65 +224,304 @@ impl Workflow {
             id_arc: Arc::from(id),
             name: name.to_string(),
             priority: 0,
+            depends_on: Vec::new(),
             description: None,
             condition,
             compiled_condition: None,
             tasks,
             continue_on_error: false,
+            timeout_ms: None,
+            idempotency_key: None,
+            idempotency_ttl_ms: None,
+            compiled_idempotency_key: None,
             channel: default_channel(),
             version: 1,
             status: WorkflowStatus::Active,
             tags: Vec::new(),
             created_at: None,
             updated_at: None,
+            variant: None,
+            annotations: Annotations::new(),
+            compiled_annotations: None,
+            locale: None,
+            compiled_locale: None,
+            tests: Vec::new(),
+            output: Vec::new(),
         }
     }
 
-    /// Load workflow from JSON string
+    /// Load workflow from JSON string. A document that doesn't parse fails
+    /// with a [`DataflowError::Config`] pointing at the first task that
+    /// doesn't parse, if any.
     pub fn from_json(json_str: &str) -> Result<Self> {
-        serde_json::from_str(json_str).map_err(DataflowError::from_serde)
+        serde_json::from_str(json_str).map_err(|err| parse_error(json_str, err))
     }
 
-    /// Load workflow from JSON file
+    /// Load workflow from JSON string, rejecting unknown keys with
+    /// [`ParseMode::Strict`]. See [`parse_mode`](crate::engine::parse_mode).
+    pub fn from_json_with_mode(json_str: &str, mode: ParseMode) -> Result<Self> {
+        match mode {
+            ParseMode::Lenient => Self::from_json(json_str),
+            ParseMode::Strict => {
+                let raw: Value =
+                    serde_json::from_str(json_str).map_err(|err| parse_error(json_str, err))?;
+                check_workflow(&raw)?;
+                Self::deserialize(&raw).map_err(|err| locate_in_document(&raw, err))
+            }
+        }
+    }
+
+    /// Load workflow from JSON file. Relative fragment paths in `include`
+    /// entries are taken relative to the file's directory.
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let json_str = fs::read_to_string(path).map_err(DataflowError::from_io)?;
+        Self::from_file_with_mode(path, ParseMode::Lenient)
+    }
+
+    /// [`from_file`](Self::from_file) in the given [`ParseMode`].
+    pub fn from_file_with_mode<P: AsRef<Path>>(path: P, mode: ParseMode) -> Result<Self> {
+        let path = path.as_ref();
+        let json_str =
+            fs::read_to_string(path).map_err(|e| in_file(path, DataflowError::from_io(e)))?;
+
+        let mut workflow =
+            Self::from_json_with_mode(&json_str, mode).map_err(|e| in_file(path, e))?;
+        workflow.anchor_includes(path);
+        Ok(workflow)
+    }
+
+    /// Load every `*.json` file under `dir`, subdirectories included, as a
+    /// workflow, in path order. Files and directories whose name starts
+    /// with `.` are skipped, so editor and deploy temp files don't break a
+    /// load. Fails on the first file that can't be read or parsed, naming
+    /// it.
+    ///
+    /// ```no_run
+    /// # use dataflow_rs::{Engine, Workflow};
+    /// let engine = Engine::builder()
+    ///     .with_workflows(Workflow::load_dir("workflows")?)
+    ///     .build()?;
+    /// # Ok::<(), dataflow_rs::DataflowError>(())
+    /// ```
+    pub fn load_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Self>> {
+        Self::load_dir_with_mode(dir, ParseMode::Lenient)
+    }
+
+    /// [`load_dir`](Self::load_dir) in the given [`ParseMode`].
+    pub fn load_dir_with_mode<P: AsRef<Path>>(dir: P, mode: ParseMode) -> Result<Vec<Self>> {
+        let mut files = Vec::new();
+        collect_json_files(dir.as_ref(), &mut files)?;
+        files.sort();
+        files
+            .iter()
+            .map(|path| {
+                let json_str = fs::read_to_string(path)
+                    .map_err(|e| in_file(path, DataflowError::from_io(e)))?;
+                let mut workflow =
+                    Self::from_json_with_mode(&json_str, mode).map_err(|e| in_file(path, e))?;
+                workflow.anchor_includes(path);
+                Ok(workflow)
+            })
+            .collect()
+    }
+
+    /// Make relative fragment paths relative to the directory of `file`,
+    /// the file the workflow was read from.
+    pub(crate) fn anchor_includes(&mut self, file: &Path) {
+        if let Some(dir) = file.parent() {
+            anchor_includes(&mut self.tasks, dir);
+        }
+    }
+
+    /// Canonical JSON for the workflow: every field present with defaults
+    /// spelled out (function inputs included), object keys sorted at every
+    /// level, no whitespace, and a `format_version` stamp. Two workflows that
+    /// parse to the same definition produce identical strings, so the output
+    /// can be diffed, hashed or signed.
+    pub fn to_normalized_json(&self) -> Result<String> {
+        let mut value = serde_json::to_value(self).map_err(DataflowError::from_serde)?;
+        if let Value::Object(fields) = &mut value {
+            fields.insert(
+                "format_version".to_string(),
+                Value::from(NORMALIZED_FORMAT_VERSION),
+            );
+        }
+        serde_json::to_string(&sort_keys(value)).map_err(DataflowError::from_serde)
+    }
 
-        Self::from_json(&json_str)
+    /// Load a workflow written by [`to_normalized_json`](Self::to_normalized_json).
+    /// Fails when the `format_version` stamp is missing or newer than this
+    /// crate understands.
+    pub fn from_normalized_json(json_str: &str) -> Result<Self> {
+        let mut value: Value = serde_json::from_str(json_str).map_err(DataflowError::from_serde)?;
+        let stamp = value
+            .as_object_mut()
+            .and_then(|fields| fields.remove("format_version"));
+        match stamp.as_ref().and_then(Value::as_u64) {
+            Some(version) if (1..=u64::from(NORMALIZED_FORMAT_VERSION)).contains(&version) => {
+                Self::deserialize(&value).map_err(|err| locate_in_document(&value, err))
+            }
+            Some(version) => Err(invalid(format!(
+                "Unsupported normalized workflow format_version {}",
+                version
+            ))
+            .within("/format_version")
+            .into()),
+            None => Err(invalid("Normalized workflow is missing format_version").into()),
+        }
     }
 
-    /// Validate the workflow structure
+    /// Validate the workflow structure. Fails with a
+    /// [`DataflowError::Config`] naming the workflow.
     pub fn validate(&self) -> Result<()> {
+        self.check()
+            .map_err(|e| locate(e, |at| at.in_workflow(&self.id)))
+    }
+
+    fn check(&self) -> Result<()> {
         // Check required fields
         if self.id.is_empty() {
-            return Err(DataflowError::Workflow(
-                "Workflow id cannot be empty".to_string(),
-            ));
+            return Err(invalid("Workflow id cannot be empty").within("/id").into());
         }
 
         if self.name.is_empty() {
-            return Err(DataflowError::Workflow(
-                "Workflow name cannot be empty".to_string(),
-            ));
+            return Err(invalid("Workflow name cannot be empty")
+                .within("/name")
+                .into());
         }
 
         // Check tasks
         if self.tasks.is_empty() {
-            return Err(DataflowError::Workflow(
-                "Workflow must have at least one task".to_string(),
-            ));
-        }
-
-        // Validate that task IDs are unique
-        let mut task_ids = std::collections::HashSet::new();
-        for task in &self.tasks {
-            if !task_ids.insert(&task.id) {
-                return Err(DataflowError::Workflow(format!(
-                    "Duplicate task ID '{}' in workflow",
-                    task.id
-                )));
-            }
+            return Err(invalid("Workflow must have at least one task")
+                .within("/tasks")
+                .into());
+        }
+
+        if let Some(locale) = &self.locale {
+            locale
+                .validate(&self.id)
+                .map_err(|e| locate(e, |at| at.within("/locale")))?;
+        }
+
+        check_unique_task_ids(&self.id, &self.tasks)
+    }
+}
+
+/// `value` with the keys of every object sorted.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => {
+            let mut fields: Vec<(String, Value)> = fields.into_iter().collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            Value::Object(
+                fields
+                    .into_iter()
+                    .map(|(key, value)| (key, sort_keys(value)))
+                    .collect::<Map<_, _>>(),
+            )
         }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+        other => other,
+    }
+}
 
-        Ok(())
+/// Append the `*.json` files under `dir` to `files`, recursing into
+/// subdirectories and skipping names starting with `.`.
+fn collect_json_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir).map_err(|e| in_file(dir, DataflowError::from_io(e)))? {
+        let path = entry.map_err(DataflowError::from_io)?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            collect_json_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reject a task id used twice in `tasks`, recursing into `foreach` bodies
+/// (each body is its own id scope).
+fn check_unique_task_ids(workflow_id: &str, tasks: &[Task]) -> Result<()> {
+    let mut task_ids = std::collections::HashSet::new();
+    for (idx, task) in tasks.iter().enumerate() {
+        let at = |e| {
+            locate(e, |at| {
+                at.in_task(&task.id).within(&format!("/tasks/{idx}"))
+            })
+        };
+        if !task_ids.insert(&task.id) {
+            return Err(at(invalid(format!(
+                "Duplicate task ID '{}' in workflow '{}'",
+                task.id, workflow_id
+            ))
+            .into()));
+        }
+        if let FunctionConfig::Foreach { input, .. } = &task.function {
+            check_unique_task_ids(workflow_id, &input.tasks)
+                .map_err(|e| at(locate(e, |at| at.within("/function/input"))))?;
+        }
+    }
+    Ok(())
+}
+
+/// The error for workflow document `json_str`, which failed to parse
+/// with `err`.
+fn parse_error(json_str: &str, err: serde_json::Error) -> DataflowError {
+    match serde_json::from_str::<Value>(json_str) {
+        Ok(raw) if !err.is_syntax() && !err.is_eof() => locate_in_document(&raw, err),
+        _ => parse_failure(err).into(),
+    }
+}
+
+/// `err`, raised parsing the workflow document `raw`, located at the
+/// first task that doesn't parse — at its `function` when that is the
+/// part that doesn't.
+fn locate_in_document(raw: &Value, err: serde_json::Error) -> DataflowError {
+    let mut error = parse_failure(err);
+    if let Some(id) = raw.get("id").and_then(Value::as_str) {
+        error = error.in_workflow(id);
+    }
+    let tasks = raw.get("tasks").and_then(Value::as_array);
+    for (idx, task) in tasks.into_iter().flatten().enumerate() {
+        if deserialize_tasks(Value::Array(vec![task.clone()])).is_ok() {
+            continue;
+        }
+        let mut pointer = format!("/tasks/{idx}");
+        if task
+            .get("function")
+            .is_some_and(|function| FunctionConfig::deserialize(function).is_err())
+        {
+            pointer.push_str("/function");
+        }
+        if let Some(id) = task_id_at(raw, &pointer) {
+            error = error.in_task(id);
+        }
+        return error.within(&pointer).into();
+    }
+    error.into()
+}
+
+/// Id of the innermost task containing the value at `pointer` in the
+/// workflow document `raw`, `foreach` bodies included.
+pub(crate) fn task_id_at<'a>(raw: &'a Value, pointer: &str) -> Option<&'a str> {
+    let mut value = raw;
+    let mut task_id = None;
+    let mut parent = "";
+    for segment in pointer.split('/').skip(1) {
+        value = match value {
+            Value::Object(fields) => fields.get(segment)?,
+            Value::Array(items) => items.get(segment.parse::<usize>().ok()?)?,
+            _ => return task_id,
+        };
+        if parent == "tasks" && value.is_object() {
+            task_id = value.get("id").and_then(Value::as_str).or(task_id);
+        }
+        parent = segment;
     }
+    task_id
 }
@@ -3,17 +3,38 @@
 //! This module handles the execution of workflows and their associated tasks.
 //! It provides a clean separation between workflow orchestration and task execution.
 
+use crate::engine::audit::{self, AuditHashes};
+use crate::engine::bulkhead::Bulkheads;
+use crate::engine::checkpoint::{Checkpoint, CheckpointStore};
+use crate::engine::circuit_breaker::CircuitBreakers;
+use crate::engine::dedup::{
+    DEDUP_STATUS, DEDUP_TASK_ID, DEFAULT_IDEMPOTENCY_TTL, DeduplicationStore, dedup_key,
+};
+use crate::engine::dry_run::{HeldBackTask, runs_in_dry_run};
 use crate::engine::error::{DataflowError, ErrorInfo, Result};
 use crate::engine::executor::{
-    ArenaContext, evaluate_condition, evaluate_condition_in_arena, with_arena,
+    ArenaContext, eval_to_owned, evaluate_condition, evaluate_condition_in_arena, with_arena,
 };
 use crate::engine::functions::BoxedFunctionHandler;
+use crate::engine::functions::join::JoinState;
+use crate::engine::hooks::ProcessingHooks;
+use crate::engine::journal::{Journal, JournalEntry, JournalStatus};
+use crate::engine::logic_text::render_condition;
+use crate::engine::lookup;
 use crate::engine::message::{AuditTrail, Change, Message};
+use crate::engine::metrics::{self, MetricsSink, elapsed_ms};
+use crate::engine::outbox::{OutboxIntent, OutboxSink};
+use crate::engine::rate_limit::RateLimiters;
+use crate::engine::retry::{self, RetryConfig};
+use crate::engine::secrets::Redactor;
+use crate::engine::state::{RetryState, StateStore, retry_state_key};
 use crate::engine::task::Task;
 use crate::engine::task_executor::TaskExecutor;
 use crate::engine::task_outcome::TaskOutcome;
+use crate::engine::telemetry::Span;
 use crate::engine::trace::{ExecutionStep, ExecutionTrace};
 use crate::engine::utils::set_nested_value;
+use crate::engine::variant::key_string;
 use crate::engine::workflow::Workflow;
 use chrono::{DateTime, Utc};
 use datalogic_rs::Engine;
@@ -22,6 +43,10 @@ use log::{debug, error, info, warn};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+
+/// How long persisted retry state outlives the attempt it schedules.
+const RETRY_STATE_GRACE: Duration = Duration::from_secs(24 * 60 * 60);
 
 /// Result of handling a task, including possible control flow signals
 enum TaskControlFlow {
@@ -54,6 +79,30 @@ pub struct WorkflowExecutor {
     task_executor: Arc<TaskExecutor>,
     /// Shared datalogic engine for condition evaluation
     engine: Arc<Engine>,
+    /// Journal for side-effecting tasks, when configured
+    journal: Option<Arc<dyn Journal>>,
+    /// Outbox for tasks marked `outbox: true`, when configured
+    outbox: Option<Arc<dyn OutboxSink>>,
+    /// Retry policy for async tasks
+    retry: RetryConfig,
+    /// Where retry progress is persisted, when configured
+    state_store: Option<Arc<dyn StateStore>>,
+    /// Metrics sink, when configured
+    metrics: Option<Arc<dyn MetricsSink>>,
+    /// Callbacks around each executed workflow and task
+    processing_hooks: ProcessingHooks,
+    /// Link each audit entry to the previous one by hash
+    audit_chain: bool,
+    /// Skip tasks the audit trail records completing
+    skip_audited_tasks: bool,
+    /// Rate limits on workflow runs, by workflow id
+    rate_limits: Arc<RateLimiters>,
+    /// Where idempotency keys are recorded, when configured
+    deduplication: Option<Arc<dyn DeduplicationStore>>,
+    /// Where checkpoints are saved, when configured
+    checkpoints: Option<Arc<dyn CheckpointStore>>,
+    /// Secret values hidden from audit entries and trace steps
+    redactor: Arc<Redactor>,
 }
 
 impl WorkflowExecutor {
@@ -62,14 +111,225 @@ impl WorkflowExecutor {
         Self {
             task_executor,
             engine,
+            journal: None,
+            outbox: None,
+            retry: RetryConfig::default(),
+            state_store: None,
+            metrics: None,
+            processing_hooks: ProcessingHooks::default(),
+            audit_chain: false,
+            skip_audited_tasks: false,
+            rate_limits: Arc::default(),
+            deduplication: None,
+            checkpoints: None,
+            redactor: Arc::default(),
         }
     }
 
+    /// Record `Started`/`Completed`/`Failed` entries for async tasks.
+    pub(crate) fn with_journal(mut self, journal: Option<Arc<dyn Journal>>) -> Self {
+        self.journal = journal;
+        self
+    }
+
     /// Get a clone of the task_functions Arc for reuse in new engines
     pub fn task_functions(&self) -> Arc<HashMap<String, BoxedFunctionHandler>> {
         self.task_executor.task_functions()
     }
 
+    /// Get a clone of the join groups Arc for reuse in new engines
+    pub(crate) fn joins(&self) -> Arc<JoinState> {
+        self.task_executor.joins()
+    }
+
+    /// Get a clone of the circuit breakers Arc for reuse in new engines
+    pub(crate) fn circuit_breakers(&self) -> Arc<CircuitBreakers> {
+        self.task_executor.circuit_breakers()
+    }
+
+    /// Get a clone of the handler rate limits Arc for reuse in new engines
+    pub(crate) fn function_rate_limits(&self) -> Arc<RateLimiters> {
+        self.task_executor.rate_limits()
+    }
+
+    /// Get a clone of the bulkheads Arc for reuse in new engines
+    pub(crate) fn bulkheads(&self) -> Arc<Bulkheads> {
+        self.task_executor.bulkheads()
+    }
+
+    /// Pace runs of rate-limited workflows with `limits`.
+    pub(crate) fn with_rate_limits(mut self, limits: Arc<RateLimiters>) -> Self {
+        self.rate_limits = limits;
+        self
+    }
+
+    /// Get a clone of the workflow rate limits Arc for reuse in new engines
+    pub(crate) fn rate_limits(&self) -> Arc<RateLimiters> {
+        Arc::clone(&self.rate_limits)
+    }
+
+    /// Defer `outbox: true` tasks to `outbox` instead of running them.
+    pub(crate) fn with_outbox(mut self, outbox: Option<Arc<dyn OutboxSink>>) -> Self {
+        self.outbox = outbox;
+        self
+    }
+
+    /// Re-run async tasks that fail with a retryable error.
+    pub(crate) fn with_retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Skip workflows whose idempotency key `store` has already seen.
+    pub(crate) fn with_deduplication(mut self, store: Option<Arc<dyn DeduplicationStore>>) -> Self {
+        self.deduplication = store;
+        self
+    }
+
+    /// Save checkpoints of checkpointed runs to `store`.
+    pub(crate) fn with_checkpoints(mut self, store: Option<Arc<dyn CheckpointStore>>) -> Self {
+        self.checkpoints = store;
+        self
+    }
+
+    /// Persist retry progress to `store` so it survives a restart.
+    pub(crate) fn with_state_store(mut self, store: Option<Arc<dyn StateStore>>) -> Self {
+        self.state_store = store;
+        self
+    }
+
+    /// Report workflow and task counts and durations to `metrics`.
+    pub(crate) fn with_metrics(mut self, metrics: Option<Arc<dyn MetricsSink>>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Run `hooks` around each executed workflow and task.
+    pub(crate) fn with_processing_hooks(mut self, hooks: ProcessingHooks) -> Self {
+        self.processing_hooks = hooks;
+        self
+    }
+
+    /// Hide the values known to `redactor` from audit entries and trace
+    /// steps.
+    pub(crate) fn with_redactor(mut self, redactor: Arc<Redactor>) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Get a clone of the redactor Arc for reuse in new engines
+    pub(crate) fn redactor(&self) -> Arc<Redactor> {
+        Arc::clone(&self.redactor)
+    }
+
+    /// Record a chained `hash` on every audit entry.
+    pub(crate) fn with_audit_chain(mut self, on: bool) -> Self {
+        self.audit_chain = on;
+        self
+    }
+
+    /// Skip tasks the message's audit trail records completing.
+    pub(crate) fn with_skip_audited_tasks(mut self, on: bool) -> Self {
+        self.skip_audited_tasks = on;
+        self
+    }
+
+    /// Whether `task` is skipped because the message's audit trail records
+    /// it completing on an earlier delivery.
+    fn already_completed(&self, workflow: &Workflow, task: &Task, message: &Message) -> bool {
+        self.skip_audited_tasks && audit::completed(&message.audit_trail, &workflow.id, &task.id)
+    }
+
+    /// Append `entry` to the message's audit trail, redacted, and chained
+    /// when enabled.
+    fn record(&self, message: &mut Message, mut entry: AuditTrail) {
+        if !self.redactor.is_empty() {
+            self.redactor.redact_audit(&mut entry);
+        }
+        if self.audit_chain {
+            audit::push_chained(message, entry);
+        } else {
+            message.audit_trail.push(entry);
+        }
+    }
+
+    /// Run every `before_task` hook; stop at the first veto.
+    fn before_task(&self, workflow: &Workflow, task: &Task, message: &Message) -> Result<()> {
+        for hook in self.processing_hooks.iter() {
+            hook.before_task(workflow, task, message)?;
+        }
+        Ok(())
+    }
+
+    fn after_task(
+        &self,
+        workflow: &Workflow,
+        task: &Task,
+        message: &Message,
+        result: &Result<(TaskOutcome, Vec<Change>)>,
+    ) {
+        for hook in self.processing_hooks.iter() {
+            hook.after_task(workflow, task, message, result);
+        }
+        if let Err(e) = result {
+            for hook in self.processing_hooks.iter() {
+                hook.on_error(workflow, Some(task), message, e);
+            }
+        }
+    }
+
+    /// Start time for a measurement; `None` when no sink is configured so
+    /// the unmeasured path skips the clock read, and for dry runs, which
+    /// are not measured.
+    fn metrics_start(&self, message: &Message) -> Option<DateTime<Utc>> {
+        self.metrics
+            .as_ref()
+            .filter(|_| message.held_back.is_none())
+            .map(|_| Utc::now())
+    }
+
+    fn record_workflow(&self, workflow: &Workflow, started: Option<DateTime<Utc>>, failed: bool) {
+        let (Some(sink), Some(started)) = (&self.metrics, started) else {
+            return;
+        };
+        let labels = [("workflow", workflow.id.as_str())];
+        sink.increment_counter(metrics::WORKFLOW_EXECUTIONS, &labels, 1);
+        if failed {
+            sink.increment_counter(metrics::WORKFLOW_ERRORS, &labels, 1);
+        }
+        sink.record_histogram(metrics::WORKFLOW_DURATION_MS, &labels, elapsed_ms(started));
+    }
+
+    fn record_task(
+        &self,
+        workflow: &Workflow,
+        task: &Task,
+        started: Option<DateTime<Utc>>,
+        result: &Result<(TaskOutcome, Vec<Change>)>,
+    ) {
+        let (Some(sink), Some(started)) = (&self.metrics, started) else {
+            return;
+        };
+        let labels = [
+            ("workflow", workflow.id.as_str()),
+            ("task", task.id.as_str()),
+        ];
+        sink.increment_counter(metrics::TASK_EXECUTIONS, &labels, 1);
+        let failed = match result {
+            Ok((outcome, _)) => outcome.audit_status().is_some_and(|code| code >= 500),
+            Err(_) => true,
+        };
+        if failed {
+            sink.increment_counter(metrics::TASK_ERRORS, &labels, 1);
+        }
+        sink.record_histogram(metrics::TASK_DURATION_MS, &labels, elapsed_ms(started));
+    }
+
+    /// The task executor, for running single tasks outside a workflow.
+    pub(crate) fn task_executor(&self) -> &TaskExecutor {
+        &self.task_executor
+    }
+
     /// Execute a workflow if its condition is met
     ///
     /// This method:
@@ -90,7 +350,29 @@ impl WorkflowExecutor {
         message: &mut Message,
         now: DateTime<Utc>,
     ) -> Result<bool> {
-        self.execute_inner(workflow, message, None, now).await
+        lookup::pinned(
+            message.lookups.clone(),
+            self.execute_inner(workflow, message, None, now, None),
+        )
+        .await
+    }
+
+    /// [`execute`](Self::execute), saving a checkpoint after each completed
+    /// async task and after the workflow. A `start` past 0 resumes a
+    /// checkpointed run at that task, skipping the workflow's condition and
+    /// idempotency key.
+    pub(crate) async fn execute_checkpointed(
+        &self,
+        workflow: &Workflow,
+        message: &mut Message,
+        now: DateTime<Utc>,
+        start: usize,
+    ) -> Result<bool> {
+        lookup::pinned(
+            message.lookups.clone(),
+            self.execute_inner(workflow, message, None, now, Some(start)),
+        )
+        .await
     }
 
     /// Execute a workflow with step-by-step tracing
@@ -103,41 +385,148 @@ impl WorkflowExecutor {
         trace: &mut ExecutionTrace,
         now: DateTime<Utc>,
     ) -> Result<bool> {
-        self.execute_inner(workflow, message, Some(trace), now)
-            .await
+        let first = trace.steps.len();
+        let result = lookup::pinned(
+            message.lookups.clone(),
+            self.execute_inner(workflow, message, Some(&mut *trace), now, None),
+        )
+        .await;
+        if !self.redactor.is_empty() {
+            for step in &mut trace.steps[first..] {
+                self.redactor.redact_step(step);
+            }
+        }
+        result
     }
 
     /// Unified workflow-condition + task-loop driver. `trace` is `None` for
     /// the production path and `Some(&mut trace)` for the debug path —
     /// stepping is the only behavioural difference between them.
+    /// `checkpointed` is the task to start at for checkpointed runs.
     async fn execute_inner(
         &self,
         workflow: &Workflow,
         message: &mut Message,
         mut trace: Option<&mut ExecutionTrace>,
         now: DateTime<Utc>,
+        checkpointed: Option<usize>,
     ) -> Result<bool> {
+        check_cancelled(workflow, None, message)?;
+        message.stamp_deadline_remaining(Utc::now());
+        stamp_annotations(message, workflow.compiled_annotations.as_ref());
+        stamp_locale(message, workflow.compiled_locale.as_ref());
+        // A resumed run already passed the condition and claimed its key.
+        let resumed = checkpointed.is_some_and(|start| start > 0);
+
         // Evaluate workflow condition directly against the OwnedDataValue context
-        let should_execute = evaluate_condition(
-            &self.engine,
-            workflow.compiled_condition.as_ref(),
-            &message.context,
-        )?;
+        let should_execute = resumed
+            || evaluate_condition(
+                &self.engine,
+                workflow.compiled_condition.as_ref(),
+                &message.context,
+            )?;
 
         if !should_execute {
             debug!("Skipping workflow {} - condition not met", workflow.id);
             if let Some(t) = trace.as_deref_mut() {
-                t.add_step(ExecutionStep::workflow_skipped(&workflow.id));
+                t.add_step(
+                    ExecutionStep::workflow_skipped(&workflow.id)
+                        .with_condition(render_condition(&workflow.condition)),
+                );
             }
             return Ok(false);
         }
 
+        let claimed = match (&self.deduplication, self.idempotency_key(workflow, message)) {
+            (Some(store), Some(key)) if !resumed && message.held_back.is_none() => {
+                let ttl = workflow
+                    .idempotency_ttl_ms
+                    .map_or(DEFAULT_IDEMPOTENCY_TTL, Duration::from_millis);
+                if store.check_and_record(&key, ttl).await? {
+                    info!(
+                        "Skipping workflow {} - duplicate idempotency key",
+                        workflow.id
+                    );
+                    self.record(
+                        message,
+                        AuditTrail {
+                            timestamp: now,
+                            workflow_id: Arc::clone(&workflow.id_arc),
+                            task_id: Arc::from(DEDUP_TASK_ID),
+                            status: DEDUP_STATUS,
+                            changes: Vec::new(),
+                            annotations: workflow.compiled_annotations.clone(),
+                            input_hash: None,
+                            data_hash: None,
+                            retry_count: None,
+                            hash: None,
+                        },
+                    );
+                    if let Some(t) = trace.as_deref_mut() {
+                        t.add_step(ExecutionStep::workflow_skipped(&workflow.id));
+                    }
+                    return Ok(false);
+                }
+                Some((store, key))
+            }
+            _ => None,
+        };
+
+        if message.held_back.is_none() {
+            self.rate_limits.acquire(&workflow.id).await;
+        }
+        let deadline = workflow
+            .timeout_ms
+            .map(|ms| Utc::now() + chrono::Duration::milliseconds(ms as i64));
+
         // Execute workflow tasks (trace recording happens inside the loop)
-        match self.execute_tasks(workflow, message, trace, now).await {
+        let started = self.metrics_start(message);
+        let span = Span::workflow(workflow);
+        let vetoed = self
+            .processing_hooks
+            .iter()
+            .try_for_each(|hook| hook.before_workflow(workflow, message));
+        let result = match vetoed {
+            Ok(()) => {
+                span.in_scope(self.execute_tasks(
+                    workflow,
+                    message,
+                    trace,
+                    now,
+                    deadline,
+                    checkpointed,
+                ))
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        for hook in self.processing_hooks.iter() {
+            hook.after_workflow(workflow, message, &result);
+        }
+        if let Err(e) = &result {
+            for hook in self.processing_hooks.iter() {
+                hook.on_error(workflow, None, message, e);
+            }
+        }
+        span.end_workflow(&result);
+        self.record_workflow(workflow, started, result.is_err());
+        // A failed run releases its key so a redelivery is not skipped.
+        if result.is_err()
+            && let Some((store, key)) = claimed
+            && let Err(e) = store.release(&key).await
+        {
+            warn!("Failed to release idempotency key {}: {}", key, e);
+        }
+        match result {
             Ok(_) => {
                 info!("Successfully completed workflow: {}", workflow.id);
+                if checkpointed.is_some() {
+                    self.save_checkpoint(workflow, None, message).await;
+                }
                 Ok(true)
             }
+            // Cancellation ends processing regardless of `continue_on_error`.
+            Err(e @ DataflowError::Cancelled(_)) => Err(e),
             Err(e) => {
                 // Single-channel contract: every error appears in
                 // `message.errors`. The `Result::Err` return only signals to
@@ -158,6 +547,9 @@ impl WorkflowExecutor {
                         "Workflow {} encountered error but continuing: {:?}",
                         workflow.id, e
                     );
+                    if checkpointed.is_some() {
+                        self.save_checkpoint(workflow, None, message).await;
+                    }
                     Ok(true)
                 } else {
                     error!("Workflow {} failed: {:?}", workflow.id, e);
@@ -167,6 +559,22 @@ impl WorkflowExecutor {
         }
     }
 
+    /// Store key of the message's idempotency key for `workflow`; `None`
+    /// when it declares none, or the key is `null` or fails to evaluate.
+    fn idempotency_key(&self, workflow: &Workflow, message: &Message) -> Option<String> {
+        let logic = workflow.compiled_idempotency_key.as_ref()?;
+        match eval_to_owned(&self.engine, logic, &message.context) {
+            Ok(value) => key_string(value).map(|key| dedup_key(&workflow.id, &key)),
+            Err(e) => {
+                warn!(
+                    "Failed to evaluate idempotency key of workflow {}: {:?}",
+                    workflow.id, e
+                );
+                None
+            }
+        }
+    }
+
     /// Execute all tasks in a workflow.
     ///
     /// Groups consecutive synchronous built-in tasks into a single
@@ -181,15 +589,23 @@ impl WorkflowExecutor {
     /// When `trace` is `Some`, the loop also records `ExecutionStep` entries
     /// after each task (skipped/executed) including per-mapping snapshots
     /// for `Map` tasks.
+    ///
+    /// `deadline` is the end of the workflow's `timeout_ms`; it is checked
+    /// before every task and bounds async task handlers.
+    ///
+    /// `checkpointed` runs start at the given task and save a checkpoint
+    /// after every async task that completes without halting the workflow.
     async fn execute_tasks(
         &self,
         workflow: &Workflow,
         message: &mut Message,
         mut trace: Option<&mut ExecutionTrace>,
         now: DateTime<Utc>,
+        deadline: Option<DateTime<Utc>>,
+        checkpointed: Option<usize>,
     ) -> Result<()> {
         let tasks = &workflow.tasks;
-        let mut idx = 0;
+        let mut idx = checkpointed.unwrap_or(0);
         while idx < tasks.len() {
             let stretch_end = next_async_boundary(tasks, idx);
 
@@ -201,6 +617,7 @@ impl WorkflowExecutor {
                     message,
                     trace.as_deref_mut(),
                     now,
+                    deadline,
                 )?;
                 if halt {
                     return Ok(());
@@ -211,6 +628,18 @@ impl WorkflowExecutor {
             if idx < tasks.len() {
                 // Single async task (or non-sync-builtin) at `idx`.
                 let task = &tasks[idx];
+                check_cancelled(workflow, Some(task), message)?;
+                check_workflow_deadline(workflow, deadline, message)?;
+                message.stamp_deadline_remaining(Utc::now());
+                stamp_annotations(message, task.compiled_annotations.as_ref());
+                if self.already_completed(workflow, task, message) {
+                    debug!("Skipping task {} - already completed", task.id);
+                    if let Some(t) = trace.as_deref_mut() {
+                        t.add_step(ExecutionStep::task_skipped(&workflow.id, &task.id));
+                    }
+                    idx += 1;
+                    continue;
+                }
                 let should_execute = evaluate_condition(
                     &self.engine,
                     task.compiled_condition.as_ref(),
@@ -220,31 +649,95 @@ impl WorkflowExecutor {
                 if !should_execute {
                     debug!("Skipping task {} - condition not met", task.id);
                     if let Some(t) = trace.as_deref_mut() {
-                        t.add_step(ExecutionStep::task_skipped(&workflow.id, &task.id));
+                        t.add_step(
+                            ExecutionStep::task_skipped(&workflow.id, &task.id)
+                                .with_condition(render_condition(&task.condition)),
+                        );
                     }
                     idx += 1;
                     continue;
                 }
 
-                let result = self.task_executor.execute(task, message).await;
-                let control_flow = self.handle_task_result(
-                    result,
-                    &workflow.id_arc,
-                    &task.id_arc,
-                    task.continue_on_error,
-                    message,
-                    now,
-                )?;
+                if let Some(held_back) = &mut message.held_back
+                    && !runs_in_dry_run(&task.function)
+                {
+                    debug!("Dry run: holding back task {}", task.id);
+                    held_back.push(HeldBackTask {
+                        workflow_id: workflow.id.clone(),
+                        task_id: task.id.clone(),
+                        function: task.function.function_name().to_string(),
+                    });
+                    if let Some(t) = trace.as_deref_mut() {
+                        t.add_step(
+                            ExecutionStep::task_skipped(&workflow.id, &task.id)
+                                .with_condition(render_condition(&task.condition)),
+                        );
+                    }
+                    idx += 1;
+                    continue;
+                }
+
+                let hashes = audit_hashes(task, message);
+                let started = self.metrics_start(message);
+                let span = Span::task(workflow, task);
+                let (result, retries) = match self.before_task(workflow, task, message) {
+                    Ok(()) => match self
+                        .journal_event(message, workflow, task, JournalStatus::Started)
+                        .await
+                    {
+                        Ok(()) => {
+                            span.in_scope(self.run_with_retry(workflow, task, message, deadline))
+                                .await
+                        }
+                        Err(e) => (Err(e), 0),
+                    },
+                    Err(e) => (Err(e), 0),
+                };
+                self.after_task(workflow, task, message, &result);
+                span.end_task(&result);
+                if self.journal.is_some() {
+                    let status = match &result {
+                        Ok((outcome, _))
+                            if outcome.audit_status().is_none_or(|code| code < 500) =>
+                        {
+                            JournalStatus::Completed
+                        }
+                        _ => JournalStatus::Failed,
+                    };
+                    if let Err(e) = self.journal_event(message, workflow, task, status).await {
+                        error!(
+                            "Failed to journal {:?} for task {}: {:?}",
+                            status, task.id, e
+                        );
+                    }
+                }
+                self.record_task(workflow, task, started, &result);
+                let errors_before = message.errors.len();
+                let audit = TaskAudit { hashes, retries };
+                let handled = self.handle_task_result(result, workflow, task, message, now, audit);
+                if retries > 0 {
+                    for error in &mut message.errors[errors_before..] {
+                        error.retry_attempted = Some(true);
+                        error.retry_count = Some(retries);
+                    }
+                }
+                let control_flow = handled?;
 
                 // Async tasks at the boundary have no per-mapping snapshots —
                 // they're either HTTP/Kafka/Enrich or a custom handler.
                 if let Some(t) = trace.as_deref_mut() {
-                    t.add_step(ExecutionStep::executed(&workflow.id, &task.id, message));
+                    t.add_step(
+                        ExecutionStep::executed(&workflow.id, &task.id, message)
+                            .with_condition(render_condition(&task.condition)),
+                    );
                 }
 
                 if matches!(control_flow, TaskControlFlow::HaltWorkflow) {
                     return Ok(());
                 }
+                if checkpointed.is_some() {
+                    self.save_checkpoint(workflow, Some(task), message).await;
+                }
                 idx += 1;
             }
         }
@@ -252,6 +745,178 @@ impl WorkflowExecutor {
         Ok(())
     }
 
+    /// [`run_or_defer`](Self::run_or_defer) under the retry policy. Returns
+    /// the final result and the number of retries it took. Each attempt is
+    /// bounded by the task's `timeout_ms` and what is left of the workflow's.
+    async fn run_with_retry(
+        &self,
+        workflow: &Workflow,
+        task: &Task,
+        message: &mut Message,
+        deadline: Option<DateTime<Utc>>,
+    ) -> (Result<(TaskOutcome, Vec<Change>)>, u32) {
+        let key = retry_state_key(message.id(), &workflow.id, &task.id);
+        let policy = self.retry.for_task(task);
+        let mut retries = 0;
+        if let Some(state) = self.load_retry_state(&key).await {
+            debug!(
+                "Resuming task {} after {} retries, next due {}",
+                task.id, state.retries, state.next_attempt_at
+            );
+            retries = state.retries;
+            self.wait_until(state.next_attempt_at, deadline).await;
+        }
+        loop {
+            let remaining = deadline.map(|d| (d - Utc::now()).to_std().unwrap_or(Duration::ZERO));
+            let timeout = match (task.timeout_ms.map(Duration::from_millis), remaining) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            };
+            let result = self.run_or_defer(workflow, task, message, timeout).await;
+            if !policy.should_retry(&result, retries) {
+                if retries > 0 {
+                    self.clear_retry_state(&key).await;
+                }
+                return (result, retries);
+            }
+            retries += 1;
+            if let Err(e) = &result {
+                warn!(
+                    "Retrying task {} (attempt {}) after: {}",
+                    task.id,
+                    retries + 1,
+                    e
+                );
+            }
+            let delay = policy.delay(retries, &key);
+            let next_attempt_at =
+                Utc::now() + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX);
+            self.save_retry_state(
+                &key,
+                RetryState {
+                    retries,
+                    next_attempt_at,
+                },
+                delay,
+            )
+            .await;
+            self.wait_until(next_attempt_at, deadline).await;
+        }
+    }
+
+    /// Sleep until `at`, but no later than the workflow deadline.
+    async fn wait_until(&self, at: DateTime<Utc>, deadline: Option<DateTime<Utc>>) {
+        let until = deadline.map_or(at, |d| at.min(d));
+        if let Ok(delay) = (until - Utc::now()).to_std() {
+            retry::sleep(delay).await;
+        }
+    }
+
+    /// Persisted retry progress under `key`. Store failures are logged and
+    /// read as no progress.
+    async fn load_retry_state(&self, key: &str) -> Option<RetryState> {
+        let store = self.state_store.as_ref()?;
+        match store.get(key).await {
+            Ok(value) => value.and_then(|v| serde_json::from_value(v).ok()),
+            Err(e) => {
+                error!("Failed to load retry state {}: {:?}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Persist retry progress. Kept for a day past the next attempt, so
+    /// entries of messages that are never redelivered expire.
+    async fn save_retry_state(&self, key: &str, state: RetryState, delay: Duration) {
+        let Some(store) = &self.state_store else {
+            return;
+        };
+        let value = serde_json::to_value(state).unwrap_or(Value::Null);
+        if let Err(e) = store.set(key, value, Some(delay + RETRY_STATE_GRACE)).await {
+            error!("Failed to save retry state {}: {:?}", key, e);
+        }
+    }
+
+    /// Drop persisted retry progress once the task is done retrying.
+    async fn clear_retry_state(&self, key: &str) {
+        if let Some(store) = &self.state_store
+            && let Err(e) = store.delete(key).await
+        {
+            error!("Failed to clear retry state {}: {:?}", key, e);
+        }
+    }
+
+    /// Run an async task, or enqueue it when it is marked `outbox` (the
+    /// engine rejects `outbox` tasks at construction when no outbox is
+    /// configured).
+    async fn run_or_defer(
+        &self,
+        workflow: &Workflow,
+        task: &Task,
+        message: &mut Message,
+        timeout: Option<Duration>,
+    ) -> Result<(TaskOutcome, Vec<Change>)> {
+        match &self.outbox {
+            Some(outbox) if task.outbox => {
+                debug!("Deferring task {} to the outbox", task.id);
+                outbox
+                    .enqueue(OutboxIntent::new(message, &workflow.id, &task.id))
+                    .await?;
+                Ok((TaskOutcome::Success, Vec::new()))
+            }
+            _ => {
+                self.task_executor
+                    .execute_within(task, message, timeout)
+                    .await
+            }
+        }
+    }
+
+    /// Save how far `message` got, if a checkpoint store is configured. A
+    /// failed save is logged; resuming then repeats some work.
+    async fn save_checkpoint(&self, workflow: &Workflow, task: Option<&Task>, message: &Message) {
+        let Some(store) = &self.checkpoints else {
+            return;
+        };
+        let checkpoint = Checkpoint {
+            workflow_id: workflow.id.clone(),
+            task_id: task.map(|task| task.id.clone()),
+            channel: message.channel.clone(),
+            message: message.clone(),
+            created_at: Utc::now(),
+        };
+        if let Err(e) = store.save(checkpoint).await {
+            error!(
+                "Failed to save checkpoint of message {} in workflow {}: {:?}",
+                message.id(),
+                workflow.id,
+                e
+            );
+        }
+    }
+
+    /// Append one entry to the journal, if configured.
+    async fn journal_event(
+        &self,
+        message: &Message,
+        workflow: &Workflow,
+        task: &Task,
+        status: JournalStatus,
+    ) -> Result<()> {
+        let Some(journal) = &self.journal else {
+            return Ok(());
+        };
+        journal
+            .record(JournalEntry {
+                message_id: message.id().to_string(),
+                workflow_id: workflow.id.clone(),
+                task_id: task.id.clone(),
+                status,
+                timestamp: Utc::now(),
+            })
+            .await
+    }
+
     /// Execute a contiguous run of sync-builtin tasks inside one
     /// `with_arena` scope. The arena context is built once at the start and
     /// refreshed in place after each mutating task. Returns `Ok(true)` if a
@@ -263,11 +928,28 @@ impl WorkflowExecutor {
         message: &mut Message,
         mut trace: Option<&mut ExecutionTrace>,
         now: DateTime<Utc>,
+        deadline: Option<DateTime<Utc>>,
     ) -> Result<bool> {
         let outcome = with_arena(|arena| -> Result<bool> {
             let mut arena_ctx = ArenaContext::from_owned(&message.context, arena);
 
             for task in tasks {
+                check_cancelled(workflow, Some(task), message)?;
+                check_workflow_deadline(workflow, deadline, message)?;
+                let deadline = message.stamp_deadline_remaining(Utc::now());
+                let annotations = stamp_annotations(message, task.compiled_annotations.as_ref());
+                if deadline || annotations {
+                    arena_ctx.refresh_for_path(&message.context, "metadata");
+                }
+
+                if self.already_completed(workflow, task, message) {
+                    debug!("Skipping task {} - already completed", task.id);
+                    if let Some(t) = trace.as_deref_mut() {
+                        t.add_step(ExecutionStep::task_skipped(&workflow.id, &task.id));
+                    }
+                    continue;
+                }
+
                 // Task condition — evaluate against the arena form so we don't
                 // re-borrow the thread-local `RefCell`.
                 let ctx_av = arena_ctx.as_data_value();
@@ -281,7 +963,10 @@ impl WorkflowExecutor {
                 if !should_execute {
                     debug!("Skipping task {} - condition not met", task.id);
                     if let Some(t) = trace.as_deref_mut() {
-                        t.add_step(ExecutionStep::task_skipped(&workflow.id, &task.id));
+                        t.add_step(
+                            ExecutionStep::task_skipped(&workflow.id, &task.id)
+                                .with_condition(render_condition(&task.condition)),
+                        );
                     }
                     continue;
                 }
@@ -295,17 +980,19 @@ impl WorkflowExecutor {
                 } else {
                     None
                 };
-                let result =
-                    self.execute_sync_task_in_arena(task, message, &mut arena_ctx, snapshot_buf);
-
-                let control_flow = self.handle_task_result(
-                    result,
-                    &workflow.id_arc,
-                    &task.id_arc,
-                    task.continue_on_error,
-                    message,
-                    now,
-                )?;
+                let hashes = audit_hashes(task, message);
+                let started = self.metrics_start(message);
+                let span = Span::task(workflow, task);
+                let result = self.before_task(workflow, task, message).and_then(|()| {
+                    self.execute_sync_task_in_arena(task, message, &mut arena_ctx, snapshot_buf)
+                });
+                self.after_task(workflow, task, message, &result);
+                span.end_task(&result);
+                self.record_task(workflow, task, started, &result);
+
+                let audit = TaskAudit { hashes, retries: 0 };
+                let control_flow =
+                    self.handle_task_result(result, workflow, task, message, now, audit)?;
 
                 // The audit-trail / progress-metadata writes performed by
                 // `handle_task_result` mutate `message.context`. Refresh the
@@ -313,7 +1000,8 @@ impl WorkflowExecutor {
                 arena_ctx.refresh_for_path(&message.context, "metadata");
 
                 if let Some(t) = trace.as_deref_mut() {
-                    let mut step = ExecutionStep::executed(&workflow.id, &task.id, message);
+                    let mut step = ExecutionStep::executed(&workflow.id, &task.id, message)
+                        .with_condition(render_condition(&task.condition));
                     if !mapping_snapshots.is_empty() {
                         step = step.with_mapping_contexts(mapping_snapshots);
                     }
@@ -369,18 +1057,28 @@ impl WorkflowExecutor {
 
     /// Handle the result of a task execution.
     ///
-    /// `workflow_id_arc` and `task_id_arc` are the compile-time cached
+    /// `workflow.id_arc` and `task.id_arc` are the compile-time cached
     /// `Arc<str>` mirrors of `workflow.id` / `task.id`; we Arc-clone them into
     /// each `AuditTrail` rather than reallocating from the `&str` form.
+    /// `audit` carries the digests taken before the task ran and the
+    /// retries the result took.
     fn handle_task_result(
         &self,
         result: Result<(TaskOutcome, Vec<Change>)>,
-        workflow_id_arc: &Arc<str>,
-        task_id_arc: &Arc<str>,
-        continue_on_error: bool,
+        workflow: &Workflow,
+        task: &Task,
         message: &mut Message,
         now: DateTime<Utc>,
+        audit: TaskAudit,
     ) -> Result<TaskControlFlow> {
+        let (input_hash, data_hash) = match audit.hashes {
+            Some(hashes) => (Some(hashes.input), Some(hashes.data)),
+            None => (None, None),
+        };
+        let workflow_id_arc = &workflow.id_arc;
+        let task_id_arc = &task.id_arc;
+        let continue_on_error = task.continue_on_error;
+        let retry_count = (audit.retries > 0).then_some(audit.retries);
         let workflow_id: &str = workflow_id_arc;
         let task_id: &str = task_id_arc;
         match result {
@@ -403,13 +1101,21 @@ impl WorkflowExecutor {
                 // by LogicCompiler at engine construction; cloning them is a
                 // refcount bump, not a string copy. `now` is shared with all
                 // other AuditTrails in this process_message call.
-                message.audit_trail.push(AuditTrail {
-                    timestamp: now,
-                    workflow_id: Arc::clone(workflow_id_arc),
-                    task_id: Arc::clone(task_id_arc),
-                    status: status as usize,
-                    changes,
-                });
+                self.record(
+                    message,
+                    AuditTrail {
+                        timestamp: now,
+                        workflow_id: Arc::clone(workflow_id_arc),
+                        task_id: Arc::clone(task_id_arc),
+                        status: status as usize,
+                        changes,
+                        annotations: task.compiled_annotations.clone(),
+                        input_hash,
+                        data_hash,
+                        retry_count,
+                        hash: None,
+                    },
+                );
 
                 // Update progress metadata for workflow chaining. Always
                 // emitted: when multiple workflows are registered in the same
@@ -474,17 +1180,31 @@ impl WorkflowExecutor {
                 error!("Task {} failed: {:?}", task_id, e);
 
                 // Record error in audit trail (Arc clones are refcount bumps).
-                message.audit_trail.push(AuditTrail {
-                    timestamp: now,
-                    workflow_id: Arc::clone(workflow_id_arc),
-                    task_id: Arc::clone(task_id_arc),
-                    status: 500,
-                    changes: vec![],
-                });
+                self.record(
+                    message,
+                    AuditTrail {
+                        timestamp: now,
+                        workflow_id: Arc::clone(workflow_id_arc),
+                        task_id: Arc::clone(task_id_arc),
+                        status: 500,
+                        changes: vec![],
+                        annotations: task.compiled_annotations.clone(),
+                        input_hash,
+                        data_hash,
+                        retry_count,
+                        hash: None,
+                    },
+                );
 
                 // Add error to message
+                let code = match e {
+                    DataflowError::Timeout(_) => "TIMEOUT_ERROR",
+                    DataflowError::Cancelled(_) => "CANCELLED",
+                    DataflowError::CircuitOpen(_) => "CIRCUIT_OPEN",
+                    _ => "TASK_ERROR",
+                };
                 message.errors.push(
-                    ErrorInfo::builder("TASK_ERROR", format!("Task {} error: {}", task_id, e))
+                    ErrorInfo::builder(code, format!("Task {} error: {}", task_id, e))
                         .workflow_id(workflow_id)
                         .task_id(task_id)
                         .build(),
@@ -500,6 +1220,100 @@ impl WorkflowExecutor {
     }
 }
 
+/// What a task run adds to its audit entry besides the outcome.
+struct TaskAudit {
+    /// Audit digests taken before the task ran, when enabled.
+    hashes: Option<AuditHashes>,
+    /// Retries the result took.
+    retries: u32,
+}
+
+/// Audit digests of `task` against the current context, when the engine
+/// computes them.
+fn audit_hashes(task: &Task, message: &Message) -> Option<AuditHashes> {
+    task.compiled_audit_inputs
+        .as_ref()
+        .map(|inputs| inputs.hashes(&message.context))
+}
+
+/// Fail with a `CANCELLED` error, recorded against the workflow and the
+/// task about to run, once the message's cancellation token has fired.
+fn check_cancelled(workflow: &Workflow, task: Option<&Task>, message: &mut Message) -> Result<()> {
+    message.check_cancelled().inspect_err(|e| {
+        let mut info = ErrorInfo::builder("CANCELLED", e.to_string()).workflow_id(&workflow.id);
+        if let Some(task) = task {
+            info = info.task_id(&task.id);
+        }
+        message.errors.push(info.build());
+    })
+}
+
+/// Fail with a `TIMEOUT_ERROR` once the workflow's `timeout_ms` has elapsed.
+fn check_workflow_deadline(
+    workflow: &Workflow,
+    deadline: Option<DateTime<Utc>>,
+    message: &mut Message,
+) -> Result<()> {
+    match deadline {
+        Some(deadline) if Utc::now() >= deadline => {
+            let error = DataflowError::Timeout(format!(
+                "Workflow {} did not finish within {}ms",
+                workflow.id,
+                workflow.timeout_ms.unwrap_or_default()
+            ));
+            message.errors.push(
+                ErrorInfo::builder("TIMEOUT_ERROR", error.to_string())
+                    .workflow_id(&workflow.id)
+                    .build(),
+            );
+            Err(error)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Make `annotations` the value of `metadata.annotations`, removing the key
+/// when `None`. Returns whether the context changed.
+pub(crate) fn stamp_annotations(
+    message: &mut Message,
+    annotations: Option<&Arc<OwnedDataValue>>,
+) -> bool {
+    stamp_metadata(message, "annotations", annotations)
+}
+
+/// Expose a workflow's locale at `metadata.locale`, or remove the previous
+/// workflow's. Returns whether the context changed.
+pub(crate) fn stamp_locale(message: &mut Message, locale: Option<&Arc<OwnedDataValue>>) -> bool {
+    stamp_metadata(message, "locale", locale)
+}
+
+/// Set `metadata.<key>` to `value`, or remove it when `value` is `None`.
+/// Returns whether the context changed.
+fn stamp_metadata(message: &mut Message, key: &str, value: Option<&Arc<OwnedDataValue>>) -> bool {
+    let current = message.context.get("metadata").and_then(|m| m.get(key));
+    match (value, current) {
+        (None, None) => false,
+        (Some(wanted), Some(current)) if **wanted == *current => false,
+        (Some(wanted), _) => {
+            set_nested_value(
+                &mut message.context,
+                &format!("metadata.{key}"),
+                (**wanted).clone(),
+            );
+            true
+        }
+        (None, Some(_)) => {
+            if let OwnedDataValue::Object(root) = &mut message.context
+                && let Some((_, OwnedDataValue::Object(pairs))) =
+                    root.iter_mut().find(|(k, _)| k == "metadata")
+            {
+                pairs.retain(|(k, _)| k != key);
+            }
+            true
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,330 @@
+//! # Workflow Validation
+//!
+//! Checks workflows the way engine construction does, but reports every
+//! problem found instead of failing on the first, each with the workflow,
+//! the task and the JSON path of the offending value. Editors, CI gates
+//! and admin tools can show all of them at once before a deploy.
+//!
+//! [`Engine::validate_workflows`](crate::Engine::validate_workflows) checks
+//! a workflow set against an engine: its registered functions, rule packs,
+//! fragments and JSONLogic operators. [`Workflow::diagnose`] checks one
+//! workflow on its own, so references to functions, rule packs and
+//! registered fragments are not checked.
+//!
+//! | Code | Finding |
+//! |------|---------|
+//! | `missing_field` | Empty `id` or `name` |
+//! | `no_tasks` | No tasks |
+//! | `duplicate_workflow_id` | An earlier workflow in the set has the same id |
+//! | `duplicate_task_id` | An earlier task in the same list has the same id |
+//! | `unknown_dependency` | `depends_on` names a workflow not in the set |
+//! | `unknown_function` | No handler is registered for a custom function |
+//! | `invalid_input` | A custom function's handler rejects its input |
+//! | `unknown_rule_pack` | `rules_ref` names an unregistered rule pack |
+//! | `unknown_fragment` | `include` names an unregistered fragment or a missing file |
+//! | `invalid_logic` | A JSONLogic expression doesn't compile |
+//!
+//! With [`EngineOptions::preserve_structure`] (the default) multi-key
+//! objects and unknown operators are literal templates and compile;
+//! without it they are `invalid_logic`.
+//!
+//! Paths point into the workflow as parsed: a `validation` task's rules
+//! are numbered after `required` shorthands are expanded, as in
+//! [`Workflow::to_normalized_json`].
+
+use crate::engine::fragment::TaskFragment;
+use crate::engine::function_not_found_error;
+use crate::engine::functions::{BoxedFunctionHandler, FunctionConfig};
+use crate::engine::lint::logic_sites;
+use crate::engine::lookup::LookupStore;
+use crate::engine::options::EngineOptions;
+use crate::engine::rule_pack::RulePack;
+use crate::engine::task::Task;
+use crate::engine::workflow::Workflow;
+use datalogic_rs::Engine as DatalogicEngine;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+/// One problem found in a workflow.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationDiagnostic {
+    /// Stable identifier of the check, e.g. `unknown_function`.
+    pub code: String,
+    pub workflow_id: String,
+    /// The top-level task the problem is in; `None` for workflow-level
+    /// problems.
+    pub task_id: Option<String>,
+    /// JSON path of the offending value within the workflow, e.g.
+    /// `tasks[1].function.input.mappings[0].logic`.
+    pub path: String,
+    pub message: String,
+}
+
+/// What the workflows are checked against.
+pub(crate) struct Registry<'a> {
+    pub handlers: &'a HashMap<String, BoxedFunctionHandler>,
+    pub rule_packs: &'a HashMap<String, Arc<RulePack>>,
+    pub fragments: &'a HashMap<String, Arc<TaskFragment>>,
+}
+
+impl Workflow {
+    /// Every problem [`Engine::validate_workflows`](crate::Engine::validate_workflows)
+    /// would find in this workflow alone, except unknown functions, rule
+    /// packs and registered fragments. JSONLogic is compiled with the
+    /// default engine options. See [`workflow_validation`](crate::engine::workflow_validation).
+    pub fn diagnose(&self) -> Vec<ValidationDiagnostic> {
+        let datalogic =
+            EngineOptions::default().build_datalogic(&Arc::new(LookupStore::new(HashMap::new())));
+        check_workflows(std::slice::from_ref(self), &datalogic, None)
+    }
+}
+
+/// Check `workflows` as a set. Without a `registry`, references to
+/// functions, rule packs and dependencies outside the set are not checked.
+pub(crate) fn check_workflows(
+    workflows: &[Workflow],
+    datalogic: &DatalogicEngine,
+    registry: Option<&Registry<'_>>,
+) -> Vec<ValidationDiagnostic> {
+    let ids: HashSet<&str> = workflows.iter().map(|w| w.id.as_str()).collect();
+    let mut seen = HashSet::new();
+    let mut diagnostics = Vec::new();
+    for workflow in workflows {
+        let mut check = Check {
+            workflow,
+            registry,
+            diagnostics: &mut diagnostics,
+        };
+        if workflow.id.is_empty() {
+            check.push("missing_field", None, "id", "Workflow id cannot be empty");
+        } else if !seen.insert(workflow.id.as_str()) {
+            check.push(
+                "duplicate_workflow_id",
+                None,
+                "id",
+                format!("Duplicate workflow ID '{}'", workflow.id),
+            );
+        }
+        if workflow.name.is_empty() {
+            check.push(
+                "missing_field",
+                None,
+                "name",
+                "Workflow name cannot be empty",
+            );
+        }
+        if workflow.tasks.is_empty() {
+            check.push(
+                "no_tasks",
+                None,
+                "tasks",
+                "Workflow must have at least one task",
+            );
+        }
+        if registry.is_some() {
+            for (idx, dep) in workflow.depends_on.iter().enumerate() {
+                if !ids.contains(dep.as_str()) {
+                    check.push(
+                        "unknown_dependency",
+                        None,
+                        &format!("depends_on[{idx}]"),
+                        format!("Depends on unknown workflow '{dep}'"),
+                    );
+                }
+            }
+        }
+        check.tasks(&workflow.tasks, None, "tasks");
+        for site in logic_sites(workflow) {
+            if let Err(e) = datalogic.compile_arc(site.logic) {
+                check.push(
+                    "invalid_logic",
+                    site.task_id,
+                    &site.path,
+                    format!("{}: {}", site.location, e),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+struct Check<'a, 'r> {
+    workflow: &'a Workflow,
+    registry: Option<&'a Registry<'r>>,
+    diagnostics: &'a mut Vec<ValidationDiagnostic>,
+}
+
+impl Check<'_, '_> {
+    fn push(&mut self, code: &str, task_id: Option<&str>, path: &str, message: impl Into<String>) {
+        self.diagnostics.push(ValidationDiagnostic {
+            code: code.to_string(),
+            workflow_id: self.workflow.id.clone(),
+            task_id: task_id.map(str::to_string),
+            path: path.to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Check a task list at `path`; `top` is the top-level task it is
+    /// nested in, if any.
+    fn tasks(&mut self, tasks: &[Task], top: Option<&str>, path: &str) {
+        let mut seen = HashSet::new();
+        for (idx, task) in tasks.iter().enumerate() {
+            let task_id = top.unwrap_or(&task.id);
+            let path = format!("{path}[{idx}]");
+            if !seen.insert(task.id.as_str()) {
+                self.push(
+                    "duplicate_task_id",
+                    Some(task_id),
+                    &format!("{path}.id"),
+                    format!("Duplicate task ID '{}'", task.id),
+                );
+            }
+            self.function(task, task_id, &format!("{path}.function"));
+        }
+    }
+
+    fn function(&mut self, task: &Task, task_id: &str, path: &str) {
+        match &task.function {
+            FunctionConfig::Foreach { input, .. } => {
+                self.tasks(&input.tasks, Some(task_id), &format!("{path}.input.tasks"));
+            }
+            FunctionConfig::Include { input, .. } => {
+                let known = self
+                    .registry
+                    .is_some_and(|r| r.fragments.contains_key(&input.fragment));
+                let is_file = input.fragment.ends_with(".json");
+                if (is_file && !Path::new(&input.fragment).is_file())
+                    || (!is_file && self.registry.is_some() && !known)
+                {
+                    self.push(
+                        "unknown_fragment",
+                        Some(task_id),
+                        &format!("{path}.input.fragment"),
+                        format!("Unknown fragment '{}'", input.fragment),
+                    );
+                }
+            }
+            FunctionConfig::Validation { input, .. } => {
+                let Some(registry) = self.registry else {
+                    return;
+                };
+                for (idx, pack) in input.rules_ref.iter().enumerate() {
+                    if !registry.rule_packs.contains_key(pack) {
+                        self.push(
+                            "unknown_rule_pack",
+                            Some(task_id),
+                            &format!("{path}.input.rules_ref[{idx}]"),
+                            format!("Unknown rule pack '{pack}'"),
+                        );
+                    }
+                }
+            }
+            FunctionConfig::Custom { name, input, .. } => {
+                let Some(registry) = self.registry else {
+                    return;
+                };
+                match registry.handlers.get(name) {
+                    None => self.push(
+                        "unknown_function",
+                        Some(task_id),
+                        &format!("{path}.name"),
+                        function_not_found_error(name, registry.handlers).to_string(),
+                    ),
+                    Some(handler) => {
+                        if let Err(e) = handler.parse_input_box(input) {
+                            self.push(
+                                "invalid_input",
+                                Some(task_id),
+                                &format!("{path}.input"),
+                                e.to_string(),
+                            );
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn workflow(value: serde_json::Value) -> Workflow {
+        Workflow::from_json(&value.to_string()).unwrap()
+    }
+
+    fn codes(diagnostics: &[ValidationDiagnostic]) -> Vec<(&str, &str)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.code.as_str(), d.path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn reports_every_structural_and_logic_problem() {
+        let diagnostics = workflow(json!({"id": "w", "name": "", "tasks": [
+            {"id": "a", "name": "A", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.x", "logic": 1},
+                {"path": "data.y", "logic": 2}
+            ]}}},
+            {"id": "a", "name": "A", "function": {"name": "foreach", "input": {
+                "path": "data.items", "tasks": [
+                    {"id": "n", "name": "N", "function": {"name": "custom", "input": {}}},
+                    {"id": "n", "name": "N", "function": {"name": "custom", "input": {}}}
+                ]
+            }}}
+        ]}))
+        .diagnose();
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                ("missing_field", "name"),
+                ("duplicate_task_id", "tasks[1].id"),
+                ("duplicate_task_id", "tasks[1].function.input.tasks[1].id"),
+            ]
+        );
+        assert_eq!(diagnostics[2].task_id.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn reports_logic_that_does_not_compile() {
+        let workflow = workflow(json!({"id": "w", "name": "W", "tasks": [
+            {"id": "a", "name": "A", "condition": {"var": "x", "==": [1, 1]},
+             "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.x", "logic": 1},
+                {"path": "data.y", "logic": {"+": [1, 2], "-": [3]}}
+            ]}}}
+        ]}));
+        let datalogic = EngineOptions::default()
+            .with_preserve_structure(false)
+            .build_datalogic(&Arc::new(LookupStore::new(HashMap::new())));
+        let diagnostics = check_workflows(std::slice::from_ref(&workflow), &datalogic, None);
+        assert_eq!(
+            codes(&diagnostics),
+            vec![
+                ("invalid_logic", "tasks[0].condition"),
+                ("invalid_logic", "tasks[0].function.input.mappings[1].logic"),
+            ]
+        );
+        assert!(diagnostics[1].message.starts_with("mapping data.y: "));
+        // Structure-preserving engines take both as templates.
+        assert!(workflow.diagnose().is_empty());
+    }
+
+    #[test]
+    fn a_valid_workflow_has_no_diagnostics() {
+        let diagnostics = workflow(json!({"id": "w", "name": "W", "tasks": [
+            {"id": "a", "name": "A", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.x", "logic": {"lookup": ["countries", "US"]}}
+            ]}}}
+        ]}))
+        .diagnose();
+        assert!(diagnostics.is_empty(), "{diagnostics:?}");
+    }
+}
@@ -36,6 +36,10 @@ any workflow without further setup:
 | **Parse** | `parse_json` | Deserialize a JSON payload string into `data` |
 | **Parse** | `parse_xml` | Deserialize an XML payload string into `data` |
 | **Transform** | `map` | Assign JSONLogic-derived values to dot-paths within the message |
+| **Transform** | `project` | Keep only allow-listed fields of a subtree |
+| **Transform** | `flatten` / `unflatten` | Convert between nested objects and dotted-key flat maps |
+| **Transform** | `rename_keys` | Convert object keys between camelCase, snake_case and PascalCase |
+| **Transform** | `sample` | Copy a field subset or a fraction of array elements into a compact summary |
 | **Validate** | `validation` | Apply JSONLogic rules with custom error messages |
 | **Routing** | `filter` | Skip or halt processing based on a JSONLogic predicate |
 | **Routing** | `log` | Emit a log entry at a configurable level |
@@ -216,21 +220,113 @@ and JSONLogic shape:
 Source for all four lives under <https://github.com/GoPlasmatic>.
 */
 
+#[cfg(feature = "admin-api")]
+pub mod admin;
+pub mod dsl;
 pub mod engine;
 pub mod prelude;
+pub mod testing;
 
 // Re-export all public APIs for easier access
-pub use engine::error::{DataflowError, ErrorInfo, Result};
+/// The JSONLogic evaluator, for implementing custom operators and
+/// configuring [`EngineOptions::evaluation`].
+pub use datalogic_rs;
+pub use engine::alert::{Alert, AlertSeverity, AlertSink, InMemoryAlertSink};
+pub use engine::audit::AuditMismatch;
+pub use engine::capability::Capability;
+pub use engine::change_tracker::ChangeTracker;
+pub use engine::checkpoint::{Checkpoint, CheckpointStore, InMemoryCheckpointStore};
+pub use engine::circuit_breaker::{CircuitBreakerConfig, CircuitState};
+pub use engine::complexity::{ComplexityThresholds, ExpressionComplexity, LogicComplexity};
+pub use engine::construction::{ConstructionReport, EngineBuildError, SkippedItem, WorkflowReport};
+pub use engine::coverage::CoverageReport;
+pub use engine::dead_letter::{
+    DeadLetter, DeadLetterHandler, DeadLetterPolicy, DeadLetterReason, FileDeadLetterQueue,
+    InMemoryDeadLetterQueue,
+};
+pub use engine::dedup::{DeduplicationStore, InMemoryDeduplicationStore};
+pub use engine::describe::{TaskDescription, WorkflowDescription};
+pub use engine::diagnostics::{
+    RoutingDiagnostics, SkipReason, WorkflowDecision, WorkflowDiagnostic,
+};
+pub use engine::dry_run::{DryRunReport, HeldBackTask};
+pub use engine::embedded_tests::{
+    WorkflowTestCase, WorkflowTestExpectations, WorkflowTestReport, WorkflowTestResult,
+};
+pub use engine::error::{ConfigError, ConfigErrorKind, DataflowError, ErrorInfo, Result};
+pub use engine::explain::{ExecutionPlan, TaskPlan, WorkflowPlan};
+pub use engine::expression_cache::ExpressionCacheStats;
+pub use engine::extensions::Extensions;
+pub use engine::fragment::{IncludeConfig, TaskFragment};
 pub use engine::functions::{
     AsyncFunctionHandler, BoxedFunctionHandler, EnrichConfig, FilterConfig, FunctionConfig,
-    HttpCallConfig, LogConfig, MapConfig, MapMapping, PublishKafkaConfig, ValidationConfig,
-    ValidationRule,
+    HttpCallConfig, LogConfig, MapConfig, MapMapping, MessageTransformer, PublishKafkaConfig,
+    Transformer, ValidationConfig, ValidationRule,
 };
+#[cfg(feature = "geoip")]
+pub use engine::functions::{GeoIpConfig, GeoIpFunction, GeoIpRecord};
+pub use engine::hooks::{MessageHook, ProcessingHook};
+pub use engine::id::{IdGenerator, Snowflake, UuidV7};
+pub use engine::instances::{PendingInstance, WaitReason};
+pub use engine::interpolation::InterpolationConfig;
+pub use engine::journal::{InMemoryJournal, Journal, JournalEntry, JournalStatus};
+pub use engine::lint::LintWarning;
+pub use engine::locale::LocaleContext;
+pub use engine::logic_text::{render_logic, render_logic_pretty};
+pub use engine::lookup::LOOKUP_OPERATOR;
+pub use engine::manifest::EngineManifest;
 pub use engine::message::{AuditTrail, Change, Message, MessageBuilder};
+pub use engine::metrics::{
+    EngineStats, InMemoryMetrics, LatencySummary, MetricsSink, NoopMetrics, TaskStats,
+    WorkflowStats,
+};
+pub use engine::migrate::{MigrationChange, MigrationReport};
+pub use engine::options::EngineOptions;
+pub use engine::outbox::{
+    DispatchStats, InMemoryOutbox, OutboxDispatcher, OutboxIntent, OutboxRetryPolicy, OutboxSink,
+    OutboxStatus,
+};
+pub use engine::parse_mode::ParseMode;
+pub use engine::pipeline::{Pipeline, PipelineTrace, Stage, StageErrorPolicy, StageTrace};
+pub use engine::quarantine::{
+    InMemoryQuarantineSink, QuarantinePolicy, QuarantineRecord, QuarantineSink,
+};
+pub use engine::rate_limit::{RateLimit, RateLimits};
+pub use engine::retry::RetryConfig;
+pub use engine::rule_pack::RulePack;
+pub use engine::secrets::{EnvSecretsResolver, SecretsResolver};
+pub use engine::shadow::{ShadowDiff, ValueDiff};
+pub use engine::shared::SharedEngine;
+pub use engine::sink::{
+    InMemorySink, NdjsonFileSink, SinkHandler, SinkRecord, StdoutSink, WorkflowOutput,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use engine::source::{
+    Acknowledge, AfterProcessing, CronSchedule, CronSource, DirectorySource, FileFormat,
+    FileTailSource, NackReason, RestartPolicy, SourceEmitter, SourceEvent, SourceFunctionHandler,
+    SourceManager, SourceOptions, SourceRuntime, SourceStatus,
+};
+#[cfg(all(feature = "kafka", not(target_arch = "wasm32")))]
+pub use engine::source::{
+    FailedRecordPolicy, KafkaConnector, KafkaConsumer, KafkaRecord, KafkaSource, KafkaSourceConfig,
+    OffsetReset,
+};
+#[cfg(all(feature = "http-source", not(target_arch = "wasm32")))]
+pub use engine::source::{HttpRoute, HttpSource};
+pub use engine::state::{InMemoryStateStore, RetryState, StateStore};
 pub use engine::task_context::TaskContext;
 pub use engine::task_outcome::TaskOutcome;
 pub use engine::trace::{ExecutionStep, ExecutionTrace, StepResult};
-pub use engine::{Engine, EngineBuilder, Task, Workflow, WorkflowStatus};
+pub use engine::urgency::ChildUrgency;
+pub use engine::variant::WorkflowVariant;
+#[cfg(feature = "watch")]
+pub use engine::watch::WorkflowWatcher;
+pub use engine::workflow_validation::ValidationDiagnostic;
+pub use engine::{
+    Annotations, Engine, EngineBuilder, NORMALIZED_FORMAT_VERSION, PriorityTieBreak, Task,
+    Workflow, WorkflowStatus,
+};
+pub use tokio_util::sync::CancellationToken;
 
 /// Type alias for `Workflow` — a Rule represents an IF-THEN unit: IF condition THEN execute actions.
 pub type Rule = Workflow;
@@ -28,6 +28,7 @@
 //! `ExecutionStep`, `StepResult`), and the rules-engine aliases
 //! (`Rule`, `Action`, `RulesEngine`). Reach into the crate root for those.
 
+pub use crate::engine::change_tracker::ChangeTracker;
 pub use crate::engine::error::{DataflowError, ErrorInfo, Result};
 pub use crate::engine::functions::AsyncFunctionHandler;
 pub use crate::engine::message::{AuditTrail, Change, Message, MessageBuilder};
@@ -0,0 +1,463 @@
+//! # Testing Helpers
+//!
+//! [`fuzz_message`] throws structurally mutated payloads at an engine to
+//! check that its workflows cope with malformed input: processing must
+//! never panic, and a run that stops with `Err` must have recorded what
+//! went wrong in `message.errors()`. Cases start from a sample payload, or
+//! one generated from a JSON Schema, and apply one mutation each:
+//!
+//! - remove a field
+//! - give a value the wrong type
+//! - replace a value with a huge array
+//! - replace a string with one decoded from invalid UTF-8 (replacement
+//!   characters, NULs, control characters)
+//! - bury a value under deep nesting
+//!
+//! Cases are drawn from a seeded generator, so a seed reproduces the same
+//! run. Meant for CI, against a workflow corpus:
+//!
+//! ```no_run
+//! # use dataflow_rs::Engine;
+//! use dataflow_rs::testing::{FuzzConfig, fuzz_message};
+//! use serde_json::json;
+//!
+//! # async fn demo(engine: Engine) {
+//! let config = FuzzConfig::from_sample(json!({"order": {"id": "A1", "total": 150}}))
+//!     .with_iterations(500)
+//!     .with_seed(7);
+//! fuzz_message(&engine, config).await.assert_passed();
+//! # }
+//! ```
+
+use crate::engine::Engine;
+use crate::engine::message::Message;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// How a case mutates the sample payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mutation {
+    /// Remove a field, or an array element. A sample without any becomes
+    /// `null`.
+    MissingField,
+    /// Replace a value with one of another type.
+    WrongType,
+    /// Replace a value with an array of `max_array_len` elements.
+    HugeArray,
+    /// Replace a value with a string decoded from invalid UTF-8.
+    BadUtf8,
+    /// Wrap a value in `max_depth` levels of arrays and objects.
+    DeepNesting,
+}
+
+impl Mutation {
+    const ALL: [Mutation; 5] = [
+        Mutation::MissingField,
+        Mutation::WrongType,
+        Mutation::HugeArray,
+        Mutation::BadUtf8,
+        Mutation::DeepNesting,
+    ];
+}
+
+/// What to fuzz and how hard.
+#[derive(Debug, Clone)]
+pub struct FuzzConfig {
+    /// Payload every case mutates.
+    pub sample: Value,
+    /// Cases to run. Default: 200.
+    pub iterations: usize,
+    /// Seed of the case generator. Default: 0.
+    pub seed: u64,
+    /// Length of the arrays of [`Mutation::HugeArray`]. Default: 10 000.
+    pub max_array_len: usize,
+    /// Levels of [`Mutation::DeepNesting`]. Default: 64.
+    pub max_depth: usize,
+}
+
+impl FuzzConfig {
+    /// Fuzz mutations of `sample`.
+    pub fn from_sample(sample: Value) -> Self {
+        Self {
+            sample,
+            iterations: 200,
+            seed: 0,
+            max_array_len: 10_000,
+            max_depth: 64,
+        }
+    }
+
+    /// Fuzz mutations of a payload generated from a JSON Schema. Supports
+    /// `const`, `enum`, `examples`, `default`, `type`, `properties` and
+    /// `items`; anything else becomes `null`.
+    pub fn from_schema(schema: &Value) -> Self {
+        Self::from_sample(sample_of(schema))
+    }
+
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_max_array_len(mut self, len: usize) -> Self {
+        self.max_array_len = len;
+        self
+    }
+
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+}
+
+impl From<Value> for FuzzConfig {
+    fn from(sample: Value) -> Self {
+        Self::from_sample(sample)
+    }
+}
+
+/// A case the engine mishandled.
+#[derive(Debug, Clone, Serialize)]
+pub struct FuzzFinding {
+    /// Index of the case, for reproducing it with the same seed.
+    pub case: usize,
+    pub mutation: Mutation,
+    /// Pointer (RFC 6901) to the mutated value; empty for the root.
+    pub path: String,
+    pub payload: Value,
+    /// What went wrong: the panic message, or the unrecorded error.
+    pub problem: String,
+}
+
+/// Outcome of a [`fuzz_message`] run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FuzzReport {
+    /// Cases run.
+    pub cases: usize,
+    /// Cases the engine mishandled.
+    pub findings: Vec<FuzzFinding>,
+}
+
+impl FuzzReport {
+    /// Whether every case was handled.
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    /// Panic listing the findings, if any. For use in tests.
+    #[track_caller]
+    pub fn assert_passed(&self) {
+        if self.passed() {
+            return;
+        }
+        let findings: Vec<String> = self
+            .findings
+            .iter()
+            .map(|f| {
+                format!(
+                    "case {} ({:?} at '{}'): {}",
+                    f.case, f.mutation, f.path, f.problem
+                )
+            })
+            .collect();
+        panic!(
+            "{} of {} fuzz cases failed:\n{}",
+            self.findings.len(),
+            self.cases,
+            findings.join("\n")
+        );
+    }
+}
+
+/// Run `config.iterations` mutated payloads through
+/// [`Engine::process_message`], reporting each that panicked or returned
+/// `Err` without recording an error on the message. Pass a sample payload
+/// or a [`FuzzConfig`]. See the [module docs](self).
+pub async fn fuzz_message(engine: &Engine, config: impl Into<FuzzConfig>) -> FuzzReport {
+    let config = config.into();
+    let mut rng = Rng(config.seed);
+    let mut report = FuzzReport {
+        cases: config.iterations,
+        findings: Vec::new(),
+    };
+    for case in 0..config.iterations {
+        let mutation = Mutation::ALL[rng.below(Mutation::ALL.len())];
+        let mut payload = config.sample.clone();
+        let path = mutate(&mut payload, mutation, &config, &mut rng);
+        let mut message = Message::builder().payload_json(&payload).build();
+        let outcome = CatchUnwind(Box::pin(engine.process_message(&mut message))).await;
+        let problem = match outcome {
+            Err(panic) => Some(format!("panicked: {}", panic_text(panic.as_ref()))),
+            Ok(Err(e)) if message.errors().is_empty() => Some(format!(
+                "returned an error without recording it on the message: {}",
+                e
+            )),
+            Ok(_) => None,
+        };
+        if let Some(problem) = problem {
+            report.findings.push(FuzzFinding {
+                case,
+                mutation,
+                path,
+                payload,
+                problem,
+            });
+        }
+    }
+    report
+}
+
+/// Polls a future, turning a panic into `Err`.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = std::thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let inner = self.0.as_mut();
+        match catch_unwind(AssertUnwindSafe(|| inner.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    }
+}
+
+fn panic_text(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(text) = panic.downcast_ref::<&str>() {
+        text.to_string()
+    } else if let Some(text) = panic.downcast_ref::<String>() {
+        text.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// SplitMix64: small, seedable and good enough to pick mutations.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n` (which must be positive).
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+/// Apply `mutation` to a value of `payload` picked at random, returning its
+/// pointer.
+fn mutate(payload: &mut Value, mutation: Mutation, config: &FuzzConfig, rng: &mut Rng) -> String {
+    let mut pointers = Vec::new();
+    collect_pointers(payload, String::new(), &mut pointers);
+    if mutation == Mutation::MissingField {
+        // The root can't go missing, only its fields.
+        pointers.remove(0);
+        if pointers.is_empty() {
+            *payload = Value::Null;
+            return String::new();
+        }
+    }
+    let pointer = pointers.swap_remove(rng.below(pointers.len()));
+    if mutation == Mutation::MissingField {
+        let (parent, key) = pointer.rsplit_once('/').unwrap_or_default();
+        match payload.pointer_mut(parent) {
+            Some(Value::Object(map)) => {
+                map.remove(&key.replace("~1", "/").replace("~0", "~"));
+            }
+            Some(Value::Array(items)) => {
+                if let Ok(idx) = key.parse::<usize>() {
+                    items.remove(idx);
+                }
+            }
+            _ => {}
+        }
+        return pointer;
+    }
+    if let Some(target) = payload.pointer_mut(&pointer) {
+        *target = match mutation {
+            Mutation::WrongType => wrong_type(target, rng),
+            Mutation::HugeArray => {
+                let item = if target.is_array() || target.is_object() {
+                    json!(rng.next() as u32)
+                } else {
+                    target.take()
+                };
+                Value::Array(vec![item; config.max_array_len])
+            }
+            Mutation::BadUtf8 => Value::String(bad_utf8(rng)),
+            Mutation::DeepNesting => {
+                let mut nested = target.take();
+                for level in 0..config.max_depth {
+                    nested = if level % 2 == 0 {
+                        json!([nested])
+                    } else {
+                        json!({ "nested": nested })
+                    };
+                }
+                nested
+            }
+            Mutation::MissingField => unreachable!("handled above"),
+        };
+    }
+    pointer
+}
+
+/// Pointers to `value` and everything inside it, parents first.
+fn collect_pointers(value: &Value, pointer: String, out: &mut Vec<String>) {
+    out.push(pointer.clone());
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                let key = key.replace('~', "~0").replace('/', "~1");
+                collect_pointers(child, format!("{pointer}/{key}"), out);
+            }
+        }
+        Value::Array(items) => {
+            for (idx, child) in items.iter().enumerate() {
+                collect_pointers(child, format!("{pointer}/{idx}"), out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A value of a different type than `value`.
+fn wrong_type(value: &Value, rng: &mut Rng) -> Value {
+    let candidates = [
+        Value::Null,
+        json!(true),
+        json!(-1.5e300),
+        json!(""),
+        json!([]),
+        json!({}),
+    ];
+    let same =
+        |candidate: &Value| std::mem::discriminant(candidate) == std::mem::discriminant(value);
+    let others: Vec<&Value> = candidates.iter().filter(|c| !same(c)).collect();
+    others[rng.below(others.len())].clone()
+}
+
+/// Lossy decoding of a byte string that is not valid UTF-8.
+fn bad_utf8(rng: &mut Rng) -> String {
+    const FRAGMENTS: [&[u8]; 6] = [
+        b"\xff\xfe",
+        b"\xc3\x28",
+        b"\xed\xa0\x80",
+        b"\xf0\x28\x8c\x28",
+        b"\x00",
+        b"\x1b[31m",
+    ];
+    let mut bytes = b"text".to_vec();
+    for _ in 0..4 {
+        bytes.extend_from_slice(FRAGMENTS[rng.below(FRAGMENTS.len())]);
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A payload conforming to `schema`, as far as it is understood.
+fn sample_of(schema: &Value) -> Value {
+    if let Some(value) = schema.get("const") {
+        return value.clone();
+    }
+    for key in ["enum", "examples"] {
+        if let Some(first) = schema
+            .get(key)
+            .and_then(Value::as_array)
+            .and_then(|v| v.first())
+        {
+            return first.clone();
+        }
+    }
+    if let Some(value) = schema.get("default") {
+        return value.clone();
+    }
+    let kind = match schema.get("type") {
+        Some(Value::Array(kinds)) => kinds.first().and_then(Value::as_str),
+        Some(kind) => kind.as_str(),
+        None if schema.get("properties").is_some() => Some("object"),
+        None => None,
+    };
+    match kind {
+        Some("object") => {
+            let mut map = Map::new();
+            if let Some(Value::Object(properties)) = schema.get("properties") {
+                for (key, property) in properties {
+                    map.insert(key.clone(), sample_of(property));
+                }
+            }
+            Value::Object(map)
+        }
+        Some("array") => match schema.get("items") {
+            Some(items) => json!([sample_of(items)]),
+            None => json!([]),
+        },
+        Some("string") => json!("text"),
+        Some("integer") => json!(1),
+        Some("number") => json!(1.5),
+        Some("boolean") => json!(true),
+        _ => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_produce_conforming_samples() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "id": {"type": "string"},
+                "total": {"type": "number"},
+                "status": {"enum": ["new", "paid"]},
+                "lines": {"type": "array", "items": {"type": "integer"}}
+            }
+        });
+        assert_eq!(
+            FuzzConfig::from_schema(&schema).sample,
+            json!({"id": "text", "total": 1.5, "status": "new", "lines": [1]})
+        );
+    }
+
+    #[test]
+    fn mutations_are_reproducible_from_the_seed() {
+        let config = FuzzConfig::from_sample(json!({"a": {"b": [1, 2]}, "c": "x"}))
+            .with_max_array_len(3)
+            .with_max_depth(2);
+        let run = |seed| {
+            let mut rng = Rng(seed);
+            Mutation::ALL
+                .iter()
+                .map(|&mutation| {
+                    let mut payload = config.sample.clone();
+                    let path = mutate(&mut payload, mutation, &config, &mut rng);
+                    (path, payload)
+                })
+                .collect::<Vec<_>>()
+        };
+        let cases = run(42);
+        assert_eq!(cases, run(42));
+        for (path, payload) in &cases {
+            assert_ne!(payload, &config.sample, "mutating {path} changed nothing");
+        }
+        assert!(bad_utf8(&mut Rng(1)).contains('\u{FFFD}'));
+    }
+}
@@ -2,7 +2,9 @@ use async_trait::async_trait;
 use dataflow_rs::engine::functions::{AsyncFunctionHandler, FunctionConfig};
 use dataflow_rs::engine::message::Message;
 use dataflow_rs::engine::utils::set_nested_value;
-use dataflow_rs::{Engine, Result, Task, TaskContext, TaskOutcome, Workflow};
+use dataflow_rs::{
+    Engine, Result, SkipReason, Task, TaskContext, TaskOutcome, Workflow, WorkflowDecision,
+};
 use datavalue::OwnedDataValue;
 use serde_json::{Value, json};
 use std::sync::Arc;
@@ -102,6 +104,15 @@ async fn test_workflow_execution() {
             description: Some("A test task".to_string()),
             condition: json!(true),
             compiled_condition: None,
+            output: None,
+            outbox: false,
+            timeout_ms: None,
+            max_retries: None,
+            backoff_ms: None,
+            jitter: None,
+            annotations: Default::default(),
+            compiled_annotations: None,
+            compiled_audit_inputs: None,
             continue_on_error: false,
             function: FunctionConfig::Custom {
                 name: "log".to_string(),
@@ -163,6 +174,15 @@ async fn test_async_workflow_execution() {
             description: Some("An async test task".to_string()),
             condition: json!(true),
             compiled_condition: None,
+            output: None,
+            outbox: false,
+            timeout_ms: None,
+            max_retries: None,
+            backoff_ms: None,
+            jitter: None,
+            annotations: Default::default(),
+            compiled_annotations: None,
+            compiled_audit_inputs: None,
             continue_on_error: false,
             function: FunctionConfig::Custom {
                 name: "async_log".to_string(),
@@ -1625,6 +1645,15 @@ async fn task_err_with_continue_on_error_false_pushes_wrapper_to_errors() {
             description: None,
             condition: json!(true),
             compiled_condition: None,
+            output: None,
+            outbox: false,
+            timeout_ms: None,
+            max_retries: None,
+            backoff_ms: None,
+            jitter: None,
+            annotations: Default::default(),
+            compiled_annotations: None,
+            compiled_audit_inputs: None,
             continue_on_error: false,
             function: FunctionConfig::Custom {
                 name: "fail".to_string(),
@@ -1679,6 +1708,15 @@ async fn task_status_500_pushes_status_error_to_message() {
             description: None,
             condition: json!(true),
             compiled_condition: None,
+            output: None,
+            outbox: false,
+            timeout_ms: None,
+            max_retries: None,
+            backoff_ms: None,
+            jitter: None,
+            annotations: Default::default(),
+            compiled_annotations: None,
+            compiled_audit_inputs: None,
             // Continue past the 500 so we can assert on the *push*
             // independently of the `Result::Err` path.
             continue_on_error: true,
@@ -1712,3 +1750,5401 @@ async fn task_status_500_pushes_status_error_to_message() {
     assert_eq!(message.audit_trail().len(), 1);
     assert_eq!(message.audit_trail()[0].status, 500);
 }
+
+// =============================================================================
+// Task output capture
+// =============================================================================
+
+// Handler that hands a result value to the executor instead of writing it
+// to the context itself.
+struct OutputTask;
+
+#[async_trait]
+impl AsyncFunctionHandler for OutputTask {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, input: &Value) -> Result<TaskOutcome> {
+        ctx.set_output_json(&json!({"echo": input}));
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test]
+async fn task_output_is_written_to_declared_path_and_audited() {
+    let workflow = Workflow::from_json(
+        r#"{
+            "id": "output_wf",
+            "name": "Output",
+            "tasks": [
+                {"id": "with_output", "name": "With Output",
+                 "function": {"name": "echo", "input": {"v": 1}},
+                 "output": "temp_data.echo_result"},
+                {"id": "without_output", "name": "Without Output",
+                 "function": {"name": "echo", "input": {"v": 2}}}
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("echo", OutputTask)
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+
+    let temp = serde_json::to_value(message.temp_data()).unwrap();
+    assert_eq!(temp, json!({"echo_result": {"echo": {"v": 1}}}));
+
+    let trail = message.audit_trail();
+    assert_eq!(trail.len(), 2);
+    assert_eq!(trail[0].changes.len(), 1);
+    assert_eq!(&*trail[0].changes[0].path, "temp_data.echo_result");
+    assert!(trail[1].changes.is_empty());
+}
+
+// =============================================================================
+// Rollback of partial changes on handler error
+// =============================================================================
+
+// Handler that writes to the context and then fails.
+struct PartialWriteTask;
+
+#[async_trait]
+impl AsyncFunctionHandler for PartialWriteTask {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        ctx.set("data.amount", dv(json!(999)));
+        ctx.set("data.status", dv(json!("half-done")));
+        ctx.set("data.extra.note", dv(json!("new key")));
+        ctx.set("temp_data.scratch", dv(json!(1)));
+        Err(dataflow_rs::DataflowError::Task(
+            "failed mid-write".to_string(),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn failed_handler_changes_are_rolled_back() {
+    let workflow = Workflow::from_json(
+        r#"{
+            "id": "rollback_wf",
+            "name": "Rollback",
+            "continue_on_error": true,
+            "tasks": [
+                {"id": "seed", "name": "Seed",
+                 "function": {"name": "map", "input": {"mappings": [
+                     {"path": "data.amount", "logic": 10},
+                     {"path": "data.status", "logic": "new"}
+                 ]}}},
+                {"id": "partial", "name": "Partial", "continue_on_error": true,
+                 "function": {"name": "partial_write", "input": {}}}
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("partial_write", PartialWriteTask)
+        .build()
+        .unwrap();
+
+    // Keys that didn't exist are removed again, with or without capture.
+    for capture in [true, false] {
+        let mut message = Message::builder()
+            .payload_json(&json!({}))
+            .capture_changes(capture)
+            .build();
+        engine.process_message(&mut message).await.unwrap();
+
+        let data = serde_json::to_value(message.data()).unwrap();
+        assert_eq!(data, json!({"amount": 10, "status": "new"}), "{capture}");
+        let temp_data = serde_json::to_value(message.temp_data()).unwrap();
+        assert!(temp_data.get("scratch").is_none(), "{capture}");
+        assert!(message.errors().iter().any(|e| e.code == "TASK_ERROR"));
+    }
+}
+
+// =============================================================================
+// Quarantine of poison messages
+// =============================================================================
+
+#[tokio::test]
+async fn repeatedly_failing_message_is_quarantined() {
+    use dataflow_rs::{DataflowError, InMemoryQuarantineSink, QuarantinePolicy};
+
+    let workflow = Workflow::from_json(
+        r#"{
+            "id": "poison_wf",
+            "name": "Poison",
+            "tasks": [
+                {"id": "boom", "name": "Boom",
+                 "function": {"name": "fail", "input": {}}}
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let sink = Arc::new(InMemoryQuarantineSink::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("fail", FailingTask)
+        .with_quarantine(
+            QuarantinePolicy {
+                max_consecutive_failures: 3,
+                ..Default::default()
+            },
+            Arc::clone(&sink),
+        )
+        .build()
+        .unwrap();
+
+    for attempt in 1..=3 {
+        let mut message = Message::builder().id("poison-1").build();
+        let err = engine.process_message(&mut message).await.unwrap_err();
+        if attempt < 3 {
+            assert!(matches!(err, DataflowError::Task(_)), "attempt {attempt}");
+        } else {
+            assert!(matches!(err, DataflowError::Quarantined(_)));
+            assert!(!err.retryable());
+        }
+    }
+
+    let records = sink.records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].message.id(), "poison-1");
+    assert_eq!(records[0].workflow_id, "poison_wf");
+    assert_eq!(records[0].task_id, "boom");
+    assert!(!records[0].message.errors().is_empty());
+}
+
+#[tokio::test]
+async fn project_result_is_visible_to_later_sync_tasks() {
+    let workflow = Workflow::from_json(
+        r#"{
+            "id": "project_wf",
+            "name": "Project",
+            "tasks": [
+                {"id": "seed", "name": "Seed",
+                 "function": {"name": "map", "input": {"mappings": [
+                     {"path": "data.out.keep", "logic": 1},
+                     {"path": "data.out.drop", "logic": 2}
+                 ]}}},
+                {"id": "project", "name": "Project",
+                 "function": {"name": "project", "input": {
+                     "path": "data.out", "fields": ["keep"]}}},
+                {"id": "check", "name": "Check",
+                 "function": {"name": "map", "input": {"mappings": [
+                     {"path": "data.saw_drop", "logic": {"!!": {"var": "data.out.drop"}}}
+                 ]}}}
+            ]
+        }"#,
+    )
+    .unwrap();
+
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+
+    let data = serde_json::to_value(message.data()).unwrap();
+    assert_eq!(data, json!({"out": {"keep": 1}, "saw_drop": false}));
+}
+
+#[tokio::test]
+async fn validation_rules_ref_resolves_registered_pack() {
+    use dataflow_rs::RulePack;
+
+    let pack = RulePack::from_json(
+        r#"{
+            "name": "core_v1",
+            "version": "1.0.0",
+            "rules": [{"type": "required", "paths": ["data.id"]}]
+        }"#,
+    )
+    .unwrap();
+    let workflow_json = r#"{
+        "id": "packed_wf",
+        "name": "Packed",
+        "continue_on_error": true,
+        "tasks": [
+            {"id": "validate", "name": "Validate",
+             "function": {"name": "validation", "input": {"rules_ref": "core_v1"}}}
+        ]
+    }"#;
+
+    let engine = Engine::builder()
+        .with_workflow(Workflow::from_json(workflow_json).unwrap())
+        .with_rule_pack(pack.clone())
+        .build()
+        .unwrap();
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.errors().len(), 1);
+    assert_eq!(message.errors()[0].code, "REQUIRED_FIELD_MISSING");
+    assert_eq!(message.errors()[0].path.as_deref(), Some("data.id"));
+
+    // Hot reload keeps the registered packs.
+    let reloaded = engine
+        .with_new_workflows(vec![Workflow::from_json(workflow_json).unwrap()])
+        .unwrap();
+    assert_eq!(reloaded.workflows().len(), 1);
+
+    // Referencing an unregistered pack fails construction.
+    let missing = Engine::builder()
+        .with_workflow(Workflow::from_json(workflow_json).unwrap())
+        .build();
+    assert!(missing.is_err());
+
+    // So does registering two packs with the same name.
+    let v2 =
+        RulePack::from_json(r#"{"name": "core_v1", "version": "2.0.0", "rules": []}"#).unwrap();
+    for builder in [
+        Engine::builder()
+            .with_rule_pack(pack.clone())
+            .with_rule_pack(v2.clone()),
+        Engine::builder().with_rule_packs([pack.clone(), v2.clone()]),
+    ] {
+        let err = builder
+            .with_workflow(Workflow::from_json(workflow_json).unwrap())
+            .build()
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .contains("rule pack 'core_v1' is registered more than once")
+        );
+    }
+}
+
+#[tokio::test]
+async fn coverage_reports_rule_and_mapping_hits() {
+    let workflow = Workflow::from_json(
+        r#"{
+            "id": "cov_wf",
+            "name": "Coverage",
+            "continue_on_error": true,
+            "tasks": [
+                {"id": "validate", "name": "Validate",
+                 "function": {"name": "validation", "input": {"rules": [
+                     {"logic": {"!!": {"var": "data.id"}}, "message": "id required"},
+                     {"logic": true, "message": "never fails"}
+                 ]}}},
+                {"id": "map", "name": "Map",
+                 "function": {"name": "map", "input": {"mappings": [
+                     {"path": "data.copy", "logic": {"var": "data.id"}},
+                     {"path": "data.never", "logic": {"var": "data.missing"}}
+                 ]}}},
+                {"id": "gated", "name": "Gated",
+                 "condition": false,
+                 "function": {"name": "map", "input": {"mappings": []}}}
+            ]
+        }"#,
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut samples = vec![
+        Message::from_value(&json!({})),
+        Message::from_value(&json!({})),
+        Message::from_value(&json!({})),
+    ];
+    for (i, m) in samples.iter_mut().enumerate().skip(1) {
+        set_nested_value(&mut m.context, "data.id", dv(json!(i)));
+    }
+
+    let report = engine.coverage(samples).await;
+    assert_eq!(report.messages, 3);
+    let wf = &report.workflows[0];
+    assert_eq!(wf.matched, 3);
+
+    let rules = &wf.tasks[0].rules;
+    assert_eq!((rules[0].evaluated, rules[0].failed), (3, 1));
+    assert_eq!((rules[1].evaluated, rules[1].failed), (3, 0));
+
+    let mappings = &wf.tasks[1].mappings;
+    assert_eq!((mappings[0].executed, mappings[0].written), (3, 2));
+    assert_eq!((mappings[1].executed, mappings[1].written), (3, 0));
+    assert_eq!(wf.tasks[2].skipped, 3);
+
+    let dead: Vec<_> = report
+        .dead_rules()
+        .map(|(_, _, r)| r.message.as_str())
+        .collect();
+    assert_eq!(dead, vec!["never fails"]);
+    let unexercised: Vec<_> = report
+        .unexercised_mappings()
+        .map(|(_, _, m)| m.path.as_str())
+        .collect();
+    assert_eq!(unexercised, vec!["data.never"]);
+    let unexecuted: Vec<_> = report
+        .unexecuted_tasks()
+        .map(|(_, t)| t.task_id.as_str())
+        .collect();
+    assert_eq!(unexecuted, vec!["gated"]);
+
+    // Side-effecting tasks are held back rather than run.
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_workflow(
+            Workflow::from_json(
+                r#"{"id": "send_wf", "name": "Send", "tasks": [
+                    {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}}
+                ]}"#,
+            )
+            .unwrap(),
+        )
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .build()
+        .unwrap();
+    let report = engine.coverage([Message::from_value(&json!({}))]).await;
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    let send = &report.workflows[0].tasks[0];
+    assert_eq!((send.executed, send.skipped, send.held_back), (0, 0, 1));
+    assert_eq!(report.unexecuted_tasks().count(), 1);
+}
+
+#[tokio::test]
+async fn process_shadow_diffs_candidate_against_live() {
+    let live = Workflow::from_json(
+        r#"{"id": "wf", "name": "Live", "tasks": [
+            {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.total", "logic": {"+": [{"var": "data.a"}, 1]}},
+                {"path": "data.label", "logic": "x"}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let candidate = Workflow::from_json(
+        r#"{"id": "wf", "name": "Candidate", "tasks": [
+            {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.total", "logic": {"+": [{"var": "data.a"}, 2]}},
+                {"path": "data.label", "logic": "x"},
+                {"path": "data.extra", "logic": true}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let live = Engine::builder().with_workflow(live).build().unwrap();
+    let candidate = Engine::builder().with_workflow(candidate).build().unwrap();
+
+    let mut input = Message::from_value(&json!({}));
+    set_nested_value(&mut input.context, "data.a", dv(json!(1)));
+
+    let diff = live.process_shadow(&input, &candidate).await;
+    assert!(!diff.is_identical());
+    let paths: Vec<_> = diff.data.iter().map(|d| d.path.as_str()).collect();
+    assert_eq!(paths, vec!["total", "extra"]);
+    assert_eq!(diff.data[0].primary, Some(dv(json!(2))));
+    assert_eq!(diff.data[0].shadow, Some(dv(json!(3))));
+    assert_eq!(diff.primary.data()["total"], dv(json!(2)));
+    // The caller's input is untouched.
+    assert!(input.data().get("total").is_none());
+
+    let same = live.process_shadow(&input, &live).await;
+    assert!(same.is_identical());
+
+    // The candidate's side-effecting tasks are held back, not run.
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let noisy = Engine::builder()
+        .with_workflow(
+            Workflow::from_json(
+                r#"{"id": "wf", "name": "Noisy", "tasks": [
+                    {"id": "call", "name": "Call", "function": {"name": "send", "input": {}}}
+                ]}"#,
+            )
+            .unwrap(),
+        )
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .build()
+        .unwrap();
+    let diff = live.process_shadow(&input, &noisy).await;
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    assert_eq!(diff.shadow_held_back.len(), 1);
+    assert_eq!(diff.shadow_held_back[0].task_id, "call");
+}
+
+#[tokio::test]
+async fn pipeline_runs_stages_in_order_with_per_stage_error_policies() {
+    use dataflow_rs::{Pipeline, StageErrorPolicy};
+
+    let map_engine = |id: &str, path: &str, logic: Value| {
+        let workflow = Workflow::from_json(
+            &json!({"id": id, "name": id, "tasks": [
+                {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                    {"path": path, "logic": logic}
+                ]}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+        Engine::builder().with_workflow(workflow).build().unwrap()
+    };
+    let failing_engine = || {
+        let workflow = Workflow::from_json(
+            r#"{"id": "rules", "name": "Rules", "tasks": [
+                {"id": "boom", "name": "Boom", "function": {"name": "fail", "input": {}}}
+            ]}"#,
+        )
+        .unwrap();
+        Engine::builder()
+            .register("fail", FailingTask)
+            .with_workflow(workflow)
+            .build()
+            .unwrap()
+    };
+    let pipeline = |on_error| {
+        Pipeline::new()
+            .with_stage(
+                "normalize",
+                map_engine(
+                    "normalize",
+                    "data.amount",
+                    json!({"*": [{"var": "data.raw"}, 100]}),
+                ),
+            )
+            .with_stage_policy("rules", failing_engine(), on_error)
+            .with_stage(
+                "output",
+                map_engine("output", "data.cents", json!({"var": "data.amount"})),
+            )
+    };
+    let message = || Message::builder().data_json(&json!({"raw": 2})).build();
+
+    let continuing = pipeline(StageErrorPolicy::Continue);
+    let names: Vec<_> = continuing.stages().iter().map(|s| s.name()).collect();
+    assert_eq!(names, ["normalize", "rules", "output"]);
+    let mut continued = message();
+    continuing.process_message(&mut continued).await.unwrap();
+    assert_eq!(continued.data()["cents"], dv(json!(200)));
+    assert!(continued.has_errors());
+    assert_eq!(
+        continued.metadata()["pipeline"]["stage"],
+        dv(json!("output"))
+    );
+
+    let mut traced = message();
+    let trace = continuing
+        .process_message_with_trace(&mut traced)
+        .await
+        .unwrap();
+    let stages: Vec<_> = trace
+        .stages
+        .iter()
+        .map(|s| (s.stage.as_str(), s.error.is_some()))
+        .collect();
+    assert_eq!(
+        stages,
+        [("normalize", false), ("rules", true), ("output", false)]
+    );
+    assert_eq!(trace.executed_count(), 2);
+    assert_eq!(trace.stage("output").unwrap().trace.executed_count(), 1);
+
+    let mut failed = message();
+    assert!(
+        pipeline(StageErrorPolicy::Fail)
+            .process_message(&mut failed)
+            .await
+            .is_err()
+    );
+    assert!(failed.data().get("cents").is_none());
+    assert_eq!(failed.metadata()["pipeline"]["stage"], dv(json!("rules")));
+
+    let mut halted = message();
+    pipeline(StageErrorPolicy::Halt)
+        .process_message(&mut halted)
+        .await
+        .unwrap();
+    assert!(halted.data().get("cents").is_none());
+    assert_eq!(halted.data()["amount"], dv(json!(200)));
+}
+
+#[tokio::test]
+async fn variant_groups_route_each_key_to_one_workflow() {
+    let variant = |id: &str, weight: u32| {
+        Workflow::from_json(&format!(
+            r#"{{"id": "{id}", "name": "{id}",
+                "variant": {{"group": "pricing", "weight": {weight}, "key": {{"var": "data.customer"}}}},
+                "tasks": [{{"id": "m", "name": "Map", "function": {{"name": "map", "input": {{"mappings": [
+                    {{"path": "data.ran", "logic": "{id}"}}
+                ]}}}}}}]}}"#
+        ))
+        .unwrap()
+    };
+    let engine = Engine::builder()
+        .with_workflow(variant("pricing_v1", 1))
+        .with_workflow(variant("pricing_v2", 1))
+        .build()
+        .unwrap();
+
+    let mut seen = std::collections::HashSet::new();
+    for customer in 0..50 {
+        let mut ran = Vec::new();
+        for _ in 0..2 {
+            let mut message = Message::from_value(&json!({}));
+            set_nested_value(&mut message.context, "data.customer", dv(json!(customer)));
+            engine.process_message(&mut message).await.unwrap();
+            let chosen = message.context["metadata"]["variants"]["pricing"].clone();
+            assert_eq!(message.data()["ran"], chosen);
+            ran.push(chosen);
+        }
+        // Same key, same variant — regardless of the message id.
+        assert_eq!(ran[0], ran[1]);
+        seen.insert(format!("{:?}", ran[0]));
+    }
+    assert_eq!(seen.len(), 2);
+
+    let mut message = Message::from_value(&json!({}));
+    set_nested_value(&mut message.context, "data.customer", dv(json!(7)));
+    let trace = engine
+        .process_message_with_trace(&mut message)
+        .await
+        .unwrap();
+    assert_eq!(trace.executed_count(), 1);
+    assert_eq!(trace.skipped_count(), 1);
+}
+
+#[test]
+fn variant_group_with_zero_weight_is_rejected() {
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "variant": {"group": "g", "weight": 0}, "tasks": []}"#,
+    )
+    .unwrap();
+    assert!(Engine::builder().with_workflow(workflow).build().is_err());
+}
+
+#[tokio::test]
+async fn diagnostics_explain_routing_decisions() {
+    let workflow = |id: &str, priority: u32, condition: Value, function: &str| {
+        Workflow::from_json(
+            &json!({
+                "id": id, "name": id, "priority": priority, "condition": condition,
+                "tasks": [{"id": "t", "name": "T", "function": {"name": function, "input": {}}}]
+            })
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let engine = Engine::builder()
+        .register("noop", LoggingTask)
+        .register("fail", FailingTask)
+        .with_workflow(workflow(
+            "runs",
+            1,
+            json!({"==": [{"var": "data.kind"}, "a"]}),
+            "noop",
+        ))
+        .with_workflow(workflow(
+            "skipped",
+            2,
+            json!({"var": "data.missing"}),
+            "noop",
+        ))
+        .with_workflow(workflow("fails", 3, json!(true), "fail"))
+        .with_workflow(workflow("unreached", 4, json!(true), "noop"))
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    set_nested_value(&mut message.context, "data.kind", dv(json!("a")));
+    let diagnostics = engine.process_message_with_diagnostics(&mut message).await;
+
+    assert!(diagnostics.error.is_some());
+    let runs = diagnostics.workflow("runs").unwrap();
+    assert_eq!(runs.condition.as_deref(), Some(r#"data.kind == "a""#));
+    assert_eq!(runs.condition_value, Some(dv(json!(true))));
+    assert!(diagnostics.workflow("fails").unwrap().condition.is_none());
+    assert_eq!(runs.decision, WorkflowDecision::Executed { errors: 0 });
+    let skipped = diagnostics.workflow("skipped").unwrap();
+    assert_eq!(skipped.condition_value, Some(dv(json!(null))));
+    assert_eq!(
+        skipped.decision,
+        WorkflowDecision::Skipped(SkipReason::ConditionFalse)
+    );
+    assert!(matches!(
+        diagnostics.workflow("fails").unwrap().decision,
+        WorkflowDecision::Failed { .. }
+    ));
+    assert_eq!(
+        diagnostics.workflow("unreached").unwrap().decision,
+        WorkflowDecision::NotReached
+    );
+    assert_eq!(
+        diagnostics.executed().collect::<Vec<_>>(),
+        vec!["runs", "fails"]
+    );
+
+    // A successful diagnostic run sends its outputs as process_message does.
+    let sink = Arc::new(dataflow_rs::InMemorySink::new());
+    let engine = Engine::builder()
+        .with_workflow(
+            Workflow::from_json(
+                r#"{"id": "out", "name": "Out", "output": [{"sink": "memory"}], "tasks": [
+                    {"id": "m", "name": "M", "function": {"name": "map", "input": {"mappings": [
+                        {"path": "data.total", "logic": 1}
+                    ]}}}
+                ]}"#,
+            )
+            .unwrap(),
+        )
+        .with_sink("memory", Arc::clone(&sink))
+        .build()
+        .unwrap();
+    let mut message = Message::builder().id("m1").build();
+    let diagnostics = engine.process_message_with_diagnostics(&mut message).await;
+    assert!(diagnostics.error.is_none());
+    assert_eq!(diagnostics.executed().collect::<Vec<_>>(), vec!["out"]);
+    let records = sink.records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].message_id, "m1");
+}
+
+#[test]
+fn explain_plans_workflows_and_tasks_without_running_them() {
+    let engine = Engine::builder()
+        .register("fail", FailingTask)
+        .with_workflow(
+            Workflow::from_json(
+                &json!({"id": "eu", "name": "EU", "priority": 1,
+                    "condition": {"==": [{"var": "data.region"}, "eu"]},
+                    "tasks": [
+                        {"id": "vat", "name": "VAT", "function": {"name": "map", "input": {"mappings": [
+                            {"path": "data.vat", "logic": true}
+                        ]}}},
+                        {"id": "large", "name": "Large", "condition": {">": [{"var": "data.amount"}, 1000]},
+                         "function": {"name": "fail", "input": {}}}
+                    ]})
+                .to_string(),
+            )
+            .unwrap(),
+        )
+        .with_workflow(
+            Workflow::from_json(
+                &json!({"id": "us", "name": "US", "priority": 2,
+                    "condition": {"==": [{"var": "data.region"}, "us"]},
+                    "tasks": [{"id": "t", "name": "T", "function": {"name": "fail", "input": {}}}]})
+                .to_string(),
+            )
+            .unwrap(),
+        )
+        .build()
+        .unwrap();
+
+    let message = Message::builder()
+        .data_json(&json!({"region": "eu", "amount": 50}))
+        .build();
+    let plan = engine.explain(&message);
+
+    let eu = plan.workflow("eu").unwrap();
+    assert!(eu.runs());
+    assert_eq!(eu.condition.as_deref(), Some(r#"data.region == "eu""#));
+    assert_eq!(eu.condition_value, Some(dv(json!(true))));
+    assert_eq!(eu.tasks[0].function, "map");
+    assert!(eu.tasks[0].condition.is_none());
+    assert_eq!(eu.tasks[1].condition_value, Some(dv(json!(false))));
+    assert_eq!(eu.tasks[1].skipped, Some(SkipReason::ConditionFalse));
+
+    let us = plan.workflow("us").unwrap();
+    assert_eq!(us.skipped, Some(SkipReason::ConditionFalse));
+    assert!(us.tasks.is_empty());
+
+    assert_eq!(plan.tasks_to_run().collect::<Vec<_>>(), vec![("eu", "vat")]);
+    assert!(message.audit_trail().is_empty());
+    assert!(message.data().get("vat").is_none());
+}
+
+#[tokio::test]
+async fn journal_records_side_effecting_tasks() {
+    use dataflow_rs::{InMemoryJournal, JournalStatus};
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "continue_on_error": true, "tasks": [
+            {"id": "map", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.x", "logic": 1}
+            ]}}},
+            {"id": "send", "name": "Send", "function": {"name": "noop", "input": {}}},
+            {"id": "boom", "name": "Boom", "function": {"name": "fail", "input": {}}}
+        ]}"#,
+    )
+    .unwrap();
+    let journal = Arc::new(InMemoryJournal::new());
+    let engine = Engine::builder()
+        .register("noop", LoggingTask)
+        .register("fail", FailingTask)
+        .with_journal(Arc::clone(&journal))
+        .with_workflow(workflow)
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+
+    let events: Vec<_> = journal
+        .entries()
+        .into_iter()
+        .map(|e| {
+            assert_eq!(e.message_id, message.id());
+            (e.task_id, e.status)
+        })
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            ("send".to_string(), JournalStatus::Started),
+            ("send".to_string(), JournalStatus::Completed),
+            ("boom".to_string(), JournalStatus::Started),
+            ("boom".to_string(), JournalStatus::Failed),
+        ]
+    );
+    assert!(dataflow_rs::engine::journal::unfinished(&journal.entries()).is_empty());
+}
+
+#[tokio::test]
+async fn dry_run_previews_changes_without_side_effects() {
+    use dataflow_rs::{HeldBackTask, InMemoryDeduplicationStore};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "charge", "name": "Charge", "continue_on_error": true,
+            "idempotency_key": {"var": "data.payment_id"},
+            "tasks": [
+                {"id": "total", "name": "Total", "function": {"name": "map", "input": {
+                    "mappings": [{"path": "data.total", "logic": {"*": [{"var": "data.qty"}, 5]}}]
+                }}},
+                {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}},
+                {"id": "check", "name": "Check", "function": {"name": "validation", "input": {
+                    "rules": [{"logic": {"<": [{"var": "data.total"}, 10]}, "message": "Too much"}]
+                }}}
+            ]}"#,
+    )
+    .unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(InMemoryDeduplicationStore::new(100));
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .with_deduplication_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    let message = Message::builder()
+        .data_json(&json!({"payment_id": "p1", "qty": 3}))
+        .capture_changes(false)
+        .build();
+    let report = engine.dry_run(&message).await;
+
+    assert_eq!(report.changes.len(), 2);
+    let change = &report.changes[0].changes[0];
+    assert_eq!(change.path.as_ref(), "data.total");
+    assert_eq!(change.new_value, OwnedDataValue::from(&json!(15)));
+    assert_eq!(
+        report.held_back,
+        vec![HeldBackTask {
+            workflow_id: "charge".to_string(),
+            task_id: "send".to_string(),
+            function: "send".to_string(),
+        }]
+    );
+    assert!(!report.is_clean());
+    assert!(
+        report
+            .errors()
+            .iter()
+            .any(|e| e.message.contains("Too much"))
+    );
+
+    // Neither the message nor anything outside the engine was touched.
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert!(store.is_empty());
+    assert!(message.audit_trail().is_empty());
+    assert!(message.data().get("total").is_none());
+}
+
+#[tokio::test]
+async fn workflow_locales_drive_formatting() {
+    let invoice = |id: &str, locale: Value| {
+        let mut workflow = json!({"id": id, "name": id, "tasks": [
+            {"id": "format", "name": "Format", "function": {"name": "map", "input": {"mappings": [
+                {"path": format!("data.{id}.total"),
+                 "logic": {"format_number": [{"var": "data.total"}, 2]}},
+                {"path": format!("data.{id}.issued"),
+                 "logic": {"format_date": [{"var": "data.issued_at"}]}},
+                {"path": format!("data.{id}.tag"), "logic": {"var": "metadata.locale.tag"}}
+            ]}}}
+        ]});
+        if !locale.is_null() {
+            workflow["locale"] = locale;
+        }
+        Workflow::from_json(&workflow.to_string()).unwrap()
+    };
+    let engine = Engine::builder()
+        .with_workflow(invoice("de", json!({"tag": "de-DE", "timezone": "+01:00"})))
+        .with_workflow(invoice("us", Value::Null))
+        .with_workflow(invoice("jp", json!({"tag": "ja-JP", "timezone": "+09:00"})))
+        .build()
+        .unwrap();
+
+    let mut message = Message::builder()
+        .data_json(&json!({"total": 1234567.891, "issued_at": "2025-01-31T20:00:00Z"}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+    let data = message.data();
+    assert_eq!(
+        data["de"],
+        dv(json!({"total": "1.234.567,89", "issued": "31.01.2025", "tag": "de-DE"}))
+    );
+    assert_eq!(
+        data["us"],
+        dv(json!({"total": "1,234,567.89", "issued": "01/31/2025"}))
+    );
+    assert_eq!(
+        data["jp"],
+        dv(json!({"total": "1,234,567.89", "issued": "2025/02/01", "tag": "ja-JP"}))
+    );
+
+    let err = Workflow::from_json(
+        &json!({"id": "w", "name": "W", "locale": {"tag": "de-DE", "timezone": "Europe/Berlin"},
+                "tasks": [{"id": "t", "name": "T", "function": {"name": "map", "input": {"mappings": []}}}]})
+        .to_string(),
+    )
+    .unwrap()
+    .validate()
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("unknown time zone 'Europe/Berlin'"),
+        "{err}"
+    );
+}
+
+// Fails on its first call with a retryable error, then succeeds.
+struct FlakySend {
+    calls: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[async_trait]
+impl AsyncFunctionHandler for FlakySend {
+    type Input = Value;
+
+    async fn execute(&self, _ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        match self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+            0 => Err(dataflow_rs::DataflowError::http(503, "unavailable")),
+            _ => Ok(TaskOutcome::Success),
+        }
+    }
+}
+
+#[tokio::test]
+async fn idempotency_key_skips_duplicates_until_a_run_fails() {
+    use dataflow_rs::InMemoryDeduplicationStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflow = || {
+        Workflow::from_json(
+            r#"{"id": "charge", "name": "Charge",
+                "idempotency_key": {"var": "metadata.payment_id"},
+                "tasks": [
+                    {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}}
+                ]}"#,
+        )
+        .unwrap()
+    };
+    let payment = |id: &str| {
+        Message::builder()
+            .metadata_json(&json!({"payment_id": id}))
+            .build()
+    };
+
+    let err = Engine::builder()
+        .with_workflow(workflow())
+        .register("send", LoggingTask)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("no deduplication store"));
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(InMemoryDeduplicationStore::new(100));
+    let engine = Engine::builder()
+        .with_workflow(workflow())
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .with_deduplication_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    // The first delivery fails, releasing its key for the redelivery.
+    assert!(engine.process_message(&mut payment("p1")).await.is_err());
+    assert!(store.is_empty());
+    engine.process_message(&mut payment("p1")).await.unwrap();
+
+    let mut duplicate = payment("p1");
+    engine.process_message(&mut duplicate).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    let entry = &duplicate.audit_trail()[0];
+    assert_eq!(&*entry.task_id, dataflow_rs::engine::dedup::DEDUP_TASK_ID);
+    assert_eq!(entry.status, 208);
+
+    // Other keys, and a reloaded engine sharing the store, behave the same.
+    let engine = engine.with_new_workflows(vec![workflow()]).unwrap();
+    engine.process_message(&mut payment("p2")).await.unwrap();
+    engine.process_message(&mut payment("p2")).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    assert_eq!(store.len(), 2);
+}
+
+#[tokio::test]
+async fn embedded_workflow_tests_report_each_case() {
+    let workflow = Workflow::from_json(
+        r#"{"id": "totals", "name": "Totals", "tasks": [
+            {"id": "check", "name": "Check", "function": {"name": "validation", "input": {"rules": [
+                {"logic": {">=": [{"var": "data.amount"}, 0]}, "message": "negative amount"}
+            ]}}},
+            {"id": "total", "name": "Total", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.total", "logic": {"+": [{"var": "data.amount"}, 10]}}
+            ]}}}
+        ], "tests": [
+            {"name": "adds tax", "data": {"amount": 100}, "expect": {"data": {"total": 110}}},
+            {"name": "rejects negative amounts", "data": {"amount": -1},
+             "expect": {"errors": ["VALIDATION_ERROR"]}},
+            {"name": "wrong expectation", "data": {"amount": 10}, "expect": {"data": {"total": 12}}}
+        ]}"#,
+    )
+    .unwrap();
+    assert_eq!(workflow.tests.len(), 3);
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let report = engine.run_workflow_tests().await;
+    assert_eq!(report.results.len(), 3);
+    assert!(!report.all_passed());
+    let failed: Vec<_> = report.failures().collect();
+    assert_eq!(failed.len(), 1, "{failed:?}");
+    assert_eq!(failed[0].workflow_id, "totals");
+    assert_eq!(failed[0].test, "wrong expectation");
+    assert_eq!(failed[0].failures, vec!["data.total: expected 12, got 20"]);
+}
+
+#[tokio::test]
+async fn embedded_workflow_tests_leave_idempotency_keys_alone() {
+    use dataflow_rs::InMemoryDeduplicationStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "charge", "name": "Charge",
+            "idempotency_key": {"var": "metadata.payment_id"},
+            "tasks": [
+                {"id": "total", "name": "Total", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.total", "logic": 10}
+                ]}}},
+                {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}}
+            ],
+            "tests": [
+                {"name": "totals", "metadata": {"payment_id": "p1"}, "expect": {"data": {"total": 10}}}
+            ]}"#,
+    )
+    .unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(InMemoryDeduplicationStore::new(100));
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .with_deduplication_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    assert!(engine.run_workflow_tests().await.all_passed());
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+    assert!(store.is_empty());
+
+    // A real message with the same key still runs.
+    let mut message = Message::builder()
+        .metadata_json(&json!({"payment_id": "p1"}))
+        .build();
+    let _ = engine.process_message(&mut message).await;
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert!(
+        message
+            .audit_trail()
+            .iter()
+            .all(|e| &*e.task_id != dataflow_rs::engine::dedup::DEDUP_TASK_ID)
+    );
+}
+
+#[tokio::test]
+async fn outbox_defers_side_effects_to_the_dispatcher() {
+    use dataflow_rs::{InMemoryOutbox, OutboxDispatcher, OutboxRetryPolicy, OutboxStatus};
+
+    let workflow = || {
+        Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "send", "name": "Send", "outbox": true,
+                 "function": {"name": "send", "input": {}}}
+            ]}"#,
+        )
+        .unwrap()
+    };
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let outbox = Arc::new(InMemoryOutbox::new());
+    let engine = Arc::new(
+        Engine::builder()
+            .register(
+                "send",
+                FlakySend {
+                    calls: Arc::clone(&calls),
+                },
+            )
+            .with_outbox(Arc::clone(&outbox))
+            .with_workflow(workflow())
+            .build()
+            .unwrap(),
+    );
+
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    assert_eq!(outbox.intents().len(), 1);
+
+    let dispatcher = OutboxDispatcher::new(Arc::clone(&engine), Arc::clone(&outbox)).with_policy(
+        OutboxRetryPolicy {
+            initial_backoff_ms: 0,
+            ..Default::default()
+        },
+    );
+    assert_eq!(dispatcher.dispatch_once().await.unwrap().retried, 1);
+    assert_eq!(dispatcher.dispatch_once().await.unwrap().delivered, 1);
+    let intent = &outbox.intents()[0];
+    assert_eq!(intent.status, OutboxStatus::Delivered);
+    assert_eq!(intent.attempts, 2);
+    assert_eq!(intent.message_id, message.id());
+
+    // `outbox: true` without a configured outbox fails at build time.
+    assert!(
+        Engine::builder()
+            .register("send", LoggingTask)
+            .with_workflow(workflow())
+            .build()
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn message_hooks_run_around_processing_and_can_veto() {
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.user", "logic": {"var": "metadata.auth.user"}}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .before_message(|message| {
+            if message.data().get("blocked").is_some() {
+                return Err(dataflow_rs::DataflowError::Validation(
+                    "blocked sender".to_string(),
+                ));
+            }
+            set_nested_value(
+                &mut message.context,
+                "metadata.auth.user",
+                dv(json!("alice")),
+            );
+            Ok(())
+        })
+        .after_message(|message, _result| {
+            set_nested_value(&mut message.context, "data.secret", dv(json!("***")));
+            Ok(())
+        })
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    set_nested_value(&mut message.context, "data.secret", dv(json!("hunter2")));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["user"], dv(json!("alice")));
+    assert_eq!(message.data()["secret"], dv(json!("***")));
+
+    let mut blocked = Message::from_value(&json!({}));
+    set_nested_value(&mut blocked.context, "data.blocked", dv(json!(true)));
+    set_nested_value(&mut blocked.context, "data.secret", dv(json!("hunter2")));
+    assert!(engine.process_message(&mut blocked).await.is_err());
+    assert!(blocked.data().get("user").is_none());
+    assert_eq!(blocked.data()["secret"], dv(json!("***")));
+    assert_eq!(blocked.errors()[0].code, "HOOK_ERROR");
+}
+
+#[tokio::test]
+async fn deadline_remaining_lets_tasks_skip_optional_work() {
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "required", "name": "Required", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.required", "logic": true}
+            ]}}},
+            {"id": "optional", "name": "Optional enrichment",
+             "condition": {">": [{"var": "metadata.deadline_remaining_ms"}, 1000]},
+             "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.enriched", "logic": true}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut plenty = Message::builder()
+        .deadline(chrono::Utc::now() + chrono::Duration::seconds(60))
+        .build();
+    engine.process_message(&mut plenty).await.unwrap();
+    assert_eq!(plenty.data()["enriched"], dv(json!(true)));
+
+    let mut late = Message::builder()
+        .deadline(chrono::Utc::now() - chrono::Duration::seconds(1))
+        .build();
+    engine.process_message(&mut late).await.unwrap();
+    assert_eq!(late.data()["required"], dv(json!(true)));
+    assert!(late.data().get("enriched").is_none());
+    assert_eq!(late.metadata()["deadline_remaining_ms"], dv(json!(0)));
+
+    let mut none = Message::from_value(&json!({}));
+    engine.process_message(&mut none).await.unwrap();
+    assert!(none.metadata().get("deadline_remaining_ms").is_none());
+}
+
+#[tokio::test]
+async fn retry_config_reruns_retryable_task_failures() {
+    use dataflow_rs::RetryConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflow = || {
+        Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}}
+            ]}"#,
+        )
+        .unwrap()
+    };
+
+    // Without a retry policy the transient failure stops the message.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_workflow(workflow())
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .build()
+        .unwrap();
+    let mut message = Message::from_value(&json!({}));
+    assert!(engine.process_message(&mut message).await.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // One retry is enough for the second attempt to succeed.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_workflow(workflow())
+        .register(
+            "send",
+            FlakySend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .with_retry_config(RetryConfig::new(1))
+        .build()
+        .unwrap();
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(message.errors().is_empty());
+    assert_eq!(message.audit_trail().len(), 1);
+    assert_eq!(message.audit_trail()[0].status, 200);
+}
+
+#[tokio::test]
+async fn task_retry_settings_override_the_engine_policy() {
+    use dataflow_rs::RetryConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let build = |task_retry: &str, engine_retry: RetryConfig, calls: &Arc<AtomicUsize>| {
+        let workflow = Workflow::from_json(&format!(
+            r#"{{"id": "wf", "name": "WF", "tasks": [
+                {{"id": "send", "name": "Send", {task_retry},
+                  "function": {{"name": "send", "input": {{}}}}}}
+            ]}}"#
+        ))
+        .unwrap();
+        Engine::builder()
+            .with_workflow(workflow)
+            .register(
+                "send",
+                FlakySend {
+                    calls: Arc::clone(calls),
+                },
+            )
+            .with_retry_config(engine_retry)
+            .build()
+            .unwrap()
+    };
+
+    // The task retries although the engine policy does not.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let engine = build(
+        r#""max_retries": 2, "backoff_ms": 5, "jitter": true"#,
+        RetryConfig::default(),
+        &calls,
+    );
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(message.audit_trail()[0].retry_count, Some(1));
+
+    // The task opts out of the engine's retries.
+    let calls = Arc::new(AtomicUsize::new(0));
+    let engine = build(r#""max_retries": 0"#, RetryConfig::new(3), &calls);
+    let mut message = Message::from_value(&json!({}));
+    assert!(engine.process_message(&mut message).await.is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(message.audit_trail()[0].retry_count, None);
+    assert_eq!(message.errors()[0].retry_count, None);
+}
+
+struct DownSend {
+    calls: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[async_trait]
+impl AsyncFunctionHandler for DownSend {
+    type Input = Value;
+
+    async fn execute(&self, _ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Err(dataflow_rs::DataflowError::http(503, "unavailable"))
+    }
+}
+
+#[tokio::test]
+async fn circuit_breaker_fails_fast_once_open() {
+    use dataflow_rs::{CircuitBreakerConfig, CircuitState};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflow = || {
+        Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}}
+            ]}"#,
+        )
+        .unwrap()
+    };
+    let calls = Arc::new(AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_workflow(workflow())
+        .register(
+            "send",
+            DownSend {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .with_circuit_breaker("send", CircuitBreakerConfig::new(2, 60_000))
+        .build()
+        .unwrap();
+    assert_eq!(engine.circuit_state("send"), Some(CircuitState::Closed));
+    assert_eq!(engine.circuit_state("other"), None);
+
+    for _ in 0..2 {
+        let mut message = Message::from_value(&json!({}));
+        assert!(engine.process_message(&mut message).await.is_err());
+        assert_eq!(message.errors()[0].code, "TASK_ERROR");
+    }
+    assert_eq!(engine.circuit_state("send"), Some(CircuitState::Open));
+
+    // Open: the handler is no longer called, and a reload keeps the state.
+    let engine = engine.with_new_workflows(vec![workflow()]).unwrap();
+    let mut message = Message::from_value(&json!({}));
+    let err = engine.process_message(&mut message).await.unwrap_err();
+    assert!(matches!(err, dataflow_rs::DataflowError::CircuitOpen(_)));
+    assert_eq!(message.errors()[0].code, "CIRCUIT_OPEN");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn rate_limits_pace_functions_and_workflows() {
+    use dataflow_rs::RateLimit;
+    use std::time::Instant;
+
+    let workflow = |id: &str| {
+        Workflow::from_json(&format!(
+            r#"{{"id": "{id}", "name": "WF", "tasks": [
+                {{"id": "call", "name": "Call", "function": {{"name": "partner", "input": {{}}}}}}
+            ]}}"#
+        ))
+        .unwrap()
+    };
+    let run = |engine: Arc<Engine>| async move {
+        let started = Instant::now();
+        let runs = (0..3).map(|_| {
+            let engine = Arc::clone(&engine);
+            async move {
+                let mut message = Message::from_value(&json!({}));
+                engine.process_message(&mut message).await.unwrap();
+            }
+        });
+        futures::future::join_all(runs).await;
+        started.elapsed()
+    };
+
+    // Three concurrent messages at 20/s with no burst: two of them wait.
+    let by_function = Engine::builder()
+        .with_workflow(workflow("wf"))
+        .register("partner", LoggingTask)
+        .with_function_rate_limit("partner", RateLimit::new(20.0))
+        .build()
+        .unwrap();
+    assert!(run(Arc::new(by_function)).await.as_millis() >= 90);
+
+    let by_workflow = Engine::builder()
+        .with_workflow(workflow("wf"))
+        .register("partner", LoggingTask)
+        .with_workflow_rate_limit("wf", RateLimit::new(20.0))
+        .build()
+        .unwrap();
+    assert!(run(Arc::new(by_workflow)).await.as_millis() >= 90);
+
+    // A burst covering every message lets them all through at once.
+    let bursting = Engine::builder()
+        .with_workflow(workflow("wf"))
+        .register("partner", LoggingTask)
+        .with_function_rate_limit("partner", RateLimit::new(1.0).with_burst(3))
+        .build()
+        .unwrap();
+    assert!(run(Arc::new(bursting)).await.as_millis() < 500);
+
+    let err = Engine::builder()
+        .with_workflow(workflow("wf"))
+        .register("partner", LoggingTask)
+        .with_function_rate_limit("partner", RateLimit::new(0.0))
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("positive max_per_second"));
+}
+
+#[tokio::test]
+async fn strict_functions_requires_builtin_async_handlers() {
+    let workflow = || {
+        Workflow::from_json(
+            r#"{"id": "wf", "name": "WF", "tasks": [
+                {"id": "call", "name": "Call", "function": {
+                    "name": "http_call", "input": {"connector": "api"}
+                }}
+            ]}"#,
+        )
+        .unwrap()
+    };
+
+    assert!(Engine::builder().with_workflow(workflow()).build().is_ok());
+
+    let err = Engine::builder()
+        .with_workflow(workflow())
+        .with_strict_functions(true)
+        .build()
+        .err()
+        .expect("strict build must reject the missing http_call handler");
+    assert!(
+        matches!(err, dataflow_rs::DataflowError::FunctionNotFound(ref name) if name.starts_with("http_call"))
+    );
+
+    let engine = Engine::builder()
+        .with_workflow(workflow())
+        .register("http_call", LoggingTask)
+        .with_strict_functions(true)
+        .build()
+        .unwrap();
+    // Hot reload re-applies the check.
+    assert!(engine.with_new_workflows(vec![workflow()]).is_ok());
+}
+
+// Tracks how many executions overlap; fails messages whose payload asks to.
+struct InFlightTask {
+    current: Arc<std::sync::atomic::AtomicUsize>,
+    peak: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[async_trait]
+impl AsyncFunctionHandler for InFlightTask {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        use std::sync::atomic::Ordering;
+        let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::task::yield_now().await;
+        self.current.fetch_sub(1, Ordering::SeqCst);
+        if ctx.message().payload()["fail"] == dv(json!(true)) {
+            return Err(dataflow_rs::DataflowError::Validation(
+                "asked to fail".into(),
+            ));
+        }
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test]
+async fn process_batch_bounds_concurrency_and_keeps_order() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let peak = Arc::new(AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_workflow(
+            Workflow::from_json(
+                r#"{"id": "wf", "name": "WF", "tasks": [
+                    {"id": "work", "name": "Work", "function": {"name": "work", "input": {}}}
+                ]}"#,
+            )
+            .unwrap(),
+        )
+        .register(
+            "work",
+            InFlightTask {
+                current: Arc::new(AtomicUsize::new(0)),
+                peak: Arc::clone(&peak),
+            },
+        )
+        .build()
+        .unwrap();
+
+    let messages: Vec<Message> = (0..6)
+        .map(|i| {
+            Message::builder()
+                .id(format!("m{i}"))
+                .payload_json(&json!({"fail": i == 2}))
+                .build()
+        })
+        .collect();
+
+    let results = engine.process_batch(messages, 2).await;
+
+    assert_eq!(results.len(), 6);
+    for (i, result) in results.iter().enumerate() {
+        match result {
+            Ok(message) => assert_eq!(message.id(), format!("m{i}")),
+            Err(_) => assert_eq!(i, 2),
+        }
+    }
+    assert!(results[2].is_err());
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn bulkhead_caps_concurrent_handler_calls() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let peak = Arc::new(AtomicUsize::new(0));
+    let builder = || {
+        Engine::builder()
+            .with_workflow(
+                Workflow::from_json(
+                    r#"{"id": "wf", "name": "WF", "tasks": [
+                        {"id": "work", "name": "Work", "function": {"name": "work", "input": {}}}
+                    ]}"#,
+                )
+                .unwrap(),
+            )
+            .register(
+                "work",
+                InFlightTask {
+                    current: Arc::new(AtomicUsize::new(0)),
+                    peak: Arc::clone(&peak),
+                },
+            )
+    };
+    let engine = builder().with_bulkhead("work", 2).build().unwrap();
+    assert_eq!(engine.bulkhead_available("work"), Some(2));
+    assert_eq!(engine.bulkhead_available("other"), None);
+
+    let messages: Vec<Message> = (0..8).map(|_| Message::from_value(&json!({}))).collect();
+    let results = engine.process_batch(messages, 8).await;
+
+    assert!(results.iter().all(|result| result.is_ok()));
+    assert!(peak.load(Ordering::SeqCst) <= 2);
+    assert_eq!(engine.bulkhead_available("work"), Some(2));
+
+    let err = builder().with_bulkhead("work", 0).build().err().unwrap();
+    assert!(err.to_string().contains("at least 1 concurrent execution"));
+}
+
+// Yields a few times before finishing when the payload says `slow`.
+struct SlowWhenAsked;
+
+#[async_trait]
+impl AsyncFunctionHandler for SlowWhenAsked {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        if ctx.message().payload()["slow"] == dv(json!(true)) {
+            for _ in 0..5 {
+                tokio::task::yield_now().await;
+            }
+        }
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test]
+async fn process_stream_yields_in_completion_or_input_order() {
+    use futures::StreamExt;
+
+    let engine = Engine::builder()
+        .with_workflow(
+            Workflow::from_json(
+                r#"{"id": "wf", "name": "WF", "tasks": [
+                    {"id": "work", "name": "Work", "function": {"name": "work", "input": {}}}
+                ]}"#,
+            )
+            .unwrap(),
+        )
+        .register("work", SlowWhenAsked)
+        .build()
+        .unwrap();
+    let inputs = || {
+        futures::stream::iter([("slow", true), ("fast", false)].map(|(id, slow)| {
+            Message::builder()
+                .id(id)
+                .payload_json(&json!({"slow": slow}))
+                .build()
+        }))
+    };
+    let ids = |results: Vec<Result<Message>>| -> Vec<String> {
+        results
+            .into_iter()
+            .map(|r| r.unwrap().id().to_string())
+            .collect()
+    };
+
+    let unordered: Vec<_> = engine.process_stream(inputs(), 2, false).collect().await;
+    assert_eq!(ids(unordered), ["fast", "slow"]);
+
+    let ordered: Vec<_> = engine.process_stream(inputs(), 2, true).collect().await;
+    assert_eq!(ids(ordered), ["slow", "fast"]);
+}
+
+#[tokio::test]
+async fn annotations_are_visible_to_conditions_and_recorded_in_audit() {
+    let annotated = Workflow::from_json(
+        r#"{"id": "payments", "name": "Payments",
+            "annotations": {"domain": "payments", "owner": "team-a"},
+            "tasks": [
+                {"id": "route", "name": "Route",
+                 "condition": {"==": [{"var": "metadata.annotations.domain"}, "payments"]},
+                 "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.routed", "logic": true}
+                 ]}}},
+                {"id": "own", "name": "Own", "annotations": {"owner": "team-b"},
+                 "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.owner", "logic": {"var": "metadata.annotations.owner"}},
+                    {"path": "data.domain", "logic": {"var": "metadata.annotations.domain"}}
+                 ]}}}
+            ]}"#,
+    )
+    .unwrap();
+    let plain = Workflow::from_json(
+        r#"{"id": "plain", "name": "Plain", "priority": 1, "tasks": [
+            {"id": "check", "name": "Check", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.leftover", "logic": {"var": "metadata.annotations"}}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflows(vec![annotated, plain])
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["routed"], dv(json!(true)));
+    assert_eq!(message.data()["owner"], dv(json!("team-b")));
+    assert_eq!(message.data()["domain"], dv(json!("payments")));
+    assert_eq!(message.data()["leftover"], dv(json!(null)));
+    assert!(message.metadata().get("annotations").is_none());
+
+    let audit = message.audit_trail();
+    assert_eq!(
+        audit[0].annotations.as_deref(),
+        Some(&dv(json!({"domain": "payments", "owner": "team-a"})))
+    );
+    assert_eq!(
+        audit[1].annotations.as_deref(),
+        Some(&dv(json!({"domain": "payments", "owner": "team-b"})))
+    );
+    assert!(audit[2].annotations.is_none());
+}
+
+// Writes to the message, then never finishes in any reasonable time.
+struct Hangs;
+
+#[async_trait]
+impl AsyncFunctionHandler for Hangs {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        ctx.set("data.partial", dv(json!(true)));
+        tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn task_and_workflow_timeouts_record_timeout_errors() {
+    let task_timeout = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "hang", "name": "Hang", "timeout_ms": 50, "continue_on_error": true,
+             "function": {"name": "hang", "input": {}}},
+            {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.after", "logic": true}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(task_timeout)
+        .register("hang", Hangs)
+        .build()
+        .unwrap();
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.errors()[0].code, "TIMEOUT_ERROR");
+    assert_eq!(message.errors()[0].task_id.as_deref(), Some("hang"));
+    // Rolled back: the new key is gone.
+    assert!(
+        serde_json::to_value(message.data())
+            .unwrap()
+            .get("partial")
+            .is_none()
+    );
+    assert_eq!(message.data()["after"], dv(json!(true)));
+
+    let workflow_timeout = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "timeout_ms": 50, "tasks": [
+            {"id": "hang", "name": "Hang", "function": {"name": "hang", "input": {}}},
+            {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.after", "logic": true}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow_timeout)
+        .register("hang", Hangs)
+        .build()
+        .unwrap();
+    let mut message = Message::from_value(&json!({}));
+    assert!(engine.process_message(&mut message).await.is_err());
+    let codes: Vec<&str> = message.errors().iter().map(|e| e.code.as_str()).collect();
+    assert_eq!(codes, ["TIMEOUT_ERROR", "WORKFLOW_ERROR"]);
+    assert!(message.data().get("after").is_none());
+}
+
+// Cancels the message's token, as a shutdown signal arriving mid-task would.
+struct CancelsToken;
+
+#[async_trait]
+impl AsyncFunctionHandler for CancelsToken {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        assert!(!ctx.is_cancelled());
+        ctx.cancellation().expect("token attached").cancel();
+        assert!(ctx.is_cancelled());
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test]
+async fn cancellation_stops_between_tasks_despite_continue_on_error() {
+    use dataflow_rs::{CancellationToken, DataflowError};
+
+    let first = Workflow::from_json(
+        r#"{"id": "first", "name": "First", "continue_on_error": true, "tasks": [
+            {"id": "cancel", "name": "Cancel", "function": {"name": "cancel", "input": {}}},
+            {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.after", "logic": true}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let second = Workflow::from_json(
+        r#"{"id": "second", "name": "Second", "priority": 1, "tasks": [
+            {"id": "mark", "name": "Mark", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.second", "logic": true}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflows(vec![first, second])
+        .register("cancel", CancelsToken)
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    let result = engine
+        .process_message_cancellable(&mut message, CancellationToken::new())
+        .await;
+    assert!(matches!(result, Err(DataflowError::Cancelled(_))));
+    assert!(message.data().get("after").is_none());
+    assert!(message.data().get("second").is_none());
+    let error = message.errors().last().unwrap();
+    assert_eq!(error.code, "CANCELLED");
+    assert_eq!(error.task_id.as_deref(), Some("after"));
+    assert!(message.cancellation().is_none());
+
+    // Set on the message, the token also covers batch processing.
+    let token = CancellationToken::new();
+    token.cancel();
+    let cancelled = Message::builder().cancellation(token).build();
+    let results = engine.process_batch(vec![cancelled], 1).await;
+    assert!(matches!(results[0], Err(DataflowError::Cancelled(_))));
+}
+
+#[tokio::test]
+async fn trace_steps_carry_rendered_conditions() {
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "big", "name": "Big", "condition": {">": [{"var": "data.n"}, 10]},
+             "function": {"name": "map", "input": {"mappings": [{"path": "data.big", "logic": true}]}}},
+            {"id": "always", "name": "Always",
+             "function": {"name": "map", "input": {"mappings": [{"path": "data.seen", "logic": true}]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    set_nested_value(&mut message.context, "data.n", dv(json!(3)));
+    let trace = engine
+        .process_message_with_trace(&mut message)
+        .await
+        .unwrap();
+    assert_eq!(trace.steps[0].task_id.as_deref(), Some("big"));
+    assert_eq!(trace.steps[0].condition.as_deref(), Some("data.n > 10"));
+    assert!(trace.steps[1].condition.is_none());
+    assert!(
+        serde_json::to_value(&trace.steps[1])
+            .unwrap()
+            .get("condition")
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn drain_refuses_new_messages_and_waits_for_in_flight_ones() {
+    use dataflow_rs::DataflowError;
+
+    let engine = Engine::builder()
+        .with_workflow(
+            Workflow::from_json(
+                r#"{"id": "wf", "name": "WF", "tasks": [
+                    {"id": "work", "name": "Work", "function": {"name": "work", "input": {}}}
+                ]}"#,
+            )
+            .unwrap(),
+        )
+        .register("work", SlowWhenAsked)
+        .build()
+        .unwrap();
+
+    let mut slow = Message::builder()
+        .payload_json(&json!({"slow": true}))
+        .build();
+    {
+        let processing = engine.process_message(&mut slow);
+        tokio::pin!(processing);
+        assert!(futures::poll!(processing.as_mut()).is_pending());
+        assert_eq!(engine.in_flight(), 1);
+
+        let drain = engine.drain();
+        tokio::pin!(drain);
+        assert!(futures::poll!(drain.as_mut()).is_pending());
+        assert!(engine.is_draining());
+
+        let mut late = Message::from_value(&json!({}));
+        let refused = engine.process_message(&mut late).await;
+        assert!(matches!(refused, Err(DataflowError::Draining(_))));
+        assert!(late.audit_trail().is_empty());
+        assert!(late.errors().is_empty());
+
+        let (result, ()) = tokio::join!(processing, drain);
+        result.unwrap();
+    }
+    assert_eq!(engine.in_flight(), 0);
+    assert_eq!(slow.audit_trail().len(), 1);
+
+    // An idle engine drains immediately.
+    engine.drain().await;
+}
+
+#[tokio::test]
+async fn engine_options_configure_the_evaluator() {
+    use dataflow_rs::EngineOptions;
+    use dataflow_rs::datalogic_rs::bumpalo::Bump;
+    use dataflow_rs::datalogic_rs::operator::EvalContext;
+    use dataflow_rs::datalogic_rs::{CustomOperator, DataValue, EvaluationConfig};
+
+    struct Double;
+
+    impl CustomOperator for Double {
+        fn evaluate<'a>(
+            &self,
+            args: &[&'a DataValue<'a>],
+            _ctx: &mut EvalContext<'_, 'a>,
+            arena: &'a Bump,
+        ) -> dataflow_rs::datalogic_rs::Result<&'a DataValue<'a>> {
+            let n = args.first().and_then(|v| v.as_f64()).unwrap_or(0.0);
+            Ok(arena.alloc(DataValue::from_f64(n * 2.0)))
+        }
+    }
+
+    let workflow = |id: &str| {
+        Workflow::from_json(&format!(
+            r#"{{"id": "{id}", "name": "WF", "tasks": [
+                {{"id": "calc", "name": "Calc", "function": {{"name": "map", "input": {{"mappings": [
+                    {{"path": "data.doubled", "logic": {{"double": 21}}}},
+                    {{"path": "data.ratio", "logic": {{"/": [1.5, 0]}}}}
+                ]}}}}}}
+            ]}}"#
+        ))
+        .unwrap()
+    };
+    let options = EngineOptions::new()
+        .with_operator("double", Double)
+        .with_evaluation(EvaluationConfig::safe_arithmetic());
+    let engine = Engine::builder()
+        .with_workflow(workflow("a"))
+        .with_options(options)
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["doubled"], dv(json!(42)));
+    assert_eq!(message.data()["ratio"], dv(json!(null)));
+
+    // Hot reload keeps the evaluator, custom operators included.
+    let reloaded = engine.with_new_workflows(vec![workflow("b")]).unwrap();
+    let mut message = Message::from_value(&json!({}));
+    reloaded.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["doubled"], dv(json!(42)));
+}
+
+#[tokio::test]
+async fn metrics_sink_records_counts_errors_and_latencies() {
+    use dataflow_rs::InMemoryMetrics;
+    use dataflow_rs::engine::metrics::{TASK_EXECUTIONS, WORKFLOW_EXECUTIONS};
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "calc", "name": "Calc", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.n", "logic": 1}
+            ]}}},
+            {"id": "fail", "name": "Fail", "continue_on_error": true,
+             "condition": {"var": "data.fail"},
+             "function": {"name": "failing", "input": {}}}
+        ]}"#,
+    )
+    .unwrap();
+    let metrics = Arc::new(InMemoryMetrics::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("failing", FailingTask)
+        .with_metrics(Arc::clone(&metrics))
+        .build()
+        .unwrap();
+
+    for fail in [false, true, false] {
+        let mut message = Message::from_value(&json!({}));
+        set_nested_value(&mut message.context, "data.fail", dv(json!(fail)));
+        engine.process_message(&mut message).await.unwrap();
+    }
+
+    let stats = engine.stats().unwrap();
+    assert_eq!(
+        (stats.messages, stats.message_errors, stats.in_flight),
+        (3, 0, 0)
+    );
+    assert_eq!(stats.workflows.len(), 1);
+    assert_eq!(stats.workflows[0].executions, 3);
+    let tasks: Vec<(&str, u64, u64)> = stats
+        .tasks
+        .iter()
+        .map(|t| (t.task_id.as_str(), t.executions, t.errors))
+        .collect();
+    assert_eq!(tasks, [("calc", 3, 0), ("fail", 1, 1)]);
+    assert!(stats.tasks[0].latency.p99_ms >= stats.tasks[0].latency.p50_ms);
+
+    // The sink itself is still reachable through the shared handle.
+    assert_eq!(
+        metrics.counter(WORKFLOW_EXECUTIONS, &[("workflow", "wf")]),
+        3
+    );
+    assert_eq!(
+        metrics.counter(TASK_EXECUTIONS, &[("workflow", "wf"), ("task", "fail")]),
+        1
+    );
+
+    let unmeasured = Engine::builder().build().unwrap();
+    assert!(unmeasured.stats().is_none());
+}
+
+#[tokio::test]
+async fn engine_from_manifest_loads_referenced_files() {
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("dataflow-manifest-{}", std::process::id()));
+    fs::create_dir_all(dir.join("workflows")).unwrap();
+    fs::write(
+        dir.join("node.json"),
+        r#"{"name": "node", "options": {"retry": {"max_retries": 2}},
+            "workflows": ["workflows/"],
+            "lookup_tables": {"countries": "countries.json"},
+            "features": {"fraud_checks": true}}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("countries.json"), r#"{"US": "North America"}"#).unwrap();
+    fs::write(
+        dir.join("workflows/region.json"),
+        r#"{"id": "region", "name": "Region", "tasks": [
+            {"id": "map", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.region", "logic": {"lookup": ["countries", {"var": "data.country"}, "unknown"]}},
+                {"path": "data.fraud", "logic": {"lookup": ["features", "fraud_checks", false]}}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    fs::write(dir.join("workflows/notes.txt"), "ignored").unwrap();
+
+    let engine = Engine::from_manifest(dir.join("node.json")).unwrap();
+    assert_eq!(engine.options().retry.max_retries, 2);
+    assert_eq!(engine.workflows().len(), 1);
+
+    let mut message = Message::from_value(&json!({}));
+    set_nested_value(&mut message.context, "data", dv(json!({"country": "US"})));
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["region"], dv(json!("North America")));
+    assert_eq!(message.data()["fraud"], dv(json!(true)));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn strict_manifests_reject_misspelt_workflow_keys() {
+    use dataflow_rs::ParseMode;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("dataflow-strict-{}", std::process::id()));
+    fs::create_dir_all(dir.join("workflows")).unwrap();
+    let workflow = r#"{"id": "w", "name": "W", "tasks": [
+        {"id": "m", "name": "M", "function": {"name": "map", "input": {
+            "mappings": [], "mapings": [{"path": "data.x", "logic": 1}]
+        }}}
+    ]}"#;
+    fs::write(dir.join("workflows/w.json"), workflow).unwrap();
+    fs::write(dir.join("node.json"), r#"{"workflows": ["workflows/"]}"#).unwrap();
+    fs::write(
+        dir.join("strict.json"),
+        r#"{"workflows": ["workflows/"], "parse_mode": "strict"}"#,
+    )
+    .unwrap();
+
+    // Lenient by default: the misspelt key is dropped.
+    assert!(Engine::from_manifest(dir.join("node.json")).is_ok());
+    let err = Engine::from_manifest(dir.join("strict.json"))
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("w.json"), "{err}");
+    assert!(
+        err.contains("unknown key 'mapings' in tasks[0].function.input, did you mean 'mappings'?"),
+        "{err}"
+    );
+    assert!(Workflow::load_dir_with_mode(dir.join("workflows"), ParseMode::Strict).is_err());
+    assert!(Workflow::from_json_with_mode(workflow, ParseMode::Lenient).is_ok());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn validate_workflows_reports_every_problem_with_its_location() {
+    struct Notify;
+
+    #[derive(serde::Deserialize)]
+    struct NotifyInput {
+        #[allow(dead_code)]
+        channel: String,
+    }
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Notify {
+        type Input = NotifyInput;
+
+        async fn execute(
+            &self,
+            _ctx: &mut TaskContext<'_>,
+            _input: &NotifyInput,
+        ) -> Result<TaskOutcome> {
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let engine = Engine::builder()
+        .register("notify", Notify)
+        .build()
+        .unwrap();
+    let workflows: Vec<Workflow> = [
+        json!({"id": "a", "name": "A", "depends_on": ["missing"], "tasks": [
+            {"id": "n", "name": "N", "function": {"name": "notify", "input": {"chanel": "ops"}}},
+            {"id": "s", "name": "S", "function": {"name": "notfy", "input": {}}}
+        ]}),
+        json!({"id": "a", "name": "Again", "tasks": [
+            {"id": "v", "name": "V", "function": {"name": "validation", "input": {
+                "rules_ref": "core_v1"
+            }}},
+            {"include": "prelude"}
+        ]}),
+    ]
+    .iter()
+    .map(|w| Workflow::from_json(&w.to_string()).unwrap())
+    .collect();
+
+    let diagnostics = engine.validate_workflows(&workflows);
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|d| {
+            (
+                d.code.as_str(),
+                d.workflow_id.as_str(),
+                d.task_id.as_deref(),
+                d.path.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("unknown_dependency", "a", None, "depends_on[0]"),
+            ("invalid_input", "a", Some("n"), "tasks[0].function.input"),
+            ("unknown_function", "a", Some("s"), "tasks[1].function.name"),
+            ("duplicate_workflow_id", "a", None, "id"),
+            (
+                "unknown_rule_pack",
+                "a",
+                Some("v"),
+                "tasks[0].function.input.rules_ref[0]"
+            ),
+            (
+                "unknown_fragment",
+                "a",
+                Some("prelude"),
+                "tasks[1].function.input.fragment"
+            ),
+        ]
+    );
+    assert!(diagnostics[2].message.contains("notify"), "{diagnostics:?}");
+    assert!(workflows[0].diagnose().is_empty());
+}
+
+#[test]
+fn lint_workflows_flags_dead_tasks_and_ambiguous_priorities() {
+    let workflows: Vec<Workflow> = [
+        json!({"id": "screen", "name": "Screen", "priority": 10, "tasks": [
+            {"id": "old", "name": "Old", "condition": false, "function": {"name": "log", "input": {"message": "x"}}},
+            {"id": "flag", "name": "Flag", "function": {"name": "log", "input": {"message": "x"}}}
+        ]}),
+        json!({"id": "route", "name": "Route", "priority": 10, "tasks": [
+            {"id": "flag", "name": "Flag", "function": {"name": "log", "input": {"message": "x"}}}
+        ]}),
+        json!({"id": "stub", "name": "Stub", "priority": 20, "tasks": []}),
+    ]
+    .iter()
+    .map(|w| Workflow::from_json(&w.to_string()).unwrap())
+    .collect();
+
+    let engine = Engine::builder().build().unwrap();
+    let found: Vec<_> = engine
+        .lint_workflows(&workflows)
+        .into_iter()
+        .map(|w| (w.code, w.workflow_id, w.task_id))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            (
+                "false_condition".to_string(),
+                "screen".to_string(),
+                Some("old".to_string())
+            ),
+            ("empty_workflow".to_string(), "stub".to_string(), None),
+            ("priority_collision".to_string(), "route".to_string(), None),
+        ]
+    );
+
+    let engine = Engine::builder()
+        .with_workflows(workflows.into_iter().take(2))
+        .build()
+        .unwrap();
+    let codes: Vec<_> = engine.lint_warnings().iter().map(|w| &w.code).collect();
+    assert_eq!(codes, ["false_condition", "priority_collision"]);
+}
+
+#[test]
+fn load_dir_reads_workflow_files_recursively() {
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("dataflow-load-dir-{}", std::process::id()));
+    fs::create_dir_all(dir.join("payments/sepa")).unwrap();
+    fs::create_dir_all(dir.join(".staging")).unwrap();
+    let workflow = |id: &str| {
+        json!({"id": id, "name": id, "tasks": [
+            {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.seen", "logic": true}
+            ]}}}
+        ]})
+        .to_string()
+    };
+    fs::write(dir.join("orders.json"), workflow("orders")).unwrap();
+    fs::write(dir.join("payments/sepa/credit.json"), workflow("credit")).unwrap();
+    fs::write(dir.join("payments/cards.json"), workflow("cards")).unwrap();
+    fs::write(dir.join("payments/README.md"), "ignored").unwrap();
+    fs::write(dir.join(".staging/draft.json"), "{not json").unwrap();
+    fs::write(dir.join(".orders.json.tmp"), "{not json").unwrap();
+
+    let workflows = Workflow::load_dir(&dir).unwrap();
+    let ids: Vec<_> = workflows.iter().map(|w| w.id.as_str()).collect();
+    assert_eq!(ids, ["orders", "cards", "credit"]);
+    assert_eq!(
+        Engine::builder()
+            .with_workflows(workflows)
+            .build()
+            .unwrap()
+            .workflows()
+            .len(),
+        3
+    );
+
+    fs::write(dir.join("payments/broken.json"), "{not json").unwrap();
+    let err = Workflow::load_dir(&dir).unwrap_err();
+    assert!(err.to_string().contains("broken.json"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn workflows_include_registered_and_file_fragments() {
+    use dataflow_rs::TaskFragment;
+    use std::fs;
+
+    let set = |id: &str, path: &str| {
+        json!({"id": id, "name": id, "function": {"name": "map", "input": {"mappings": [
+            {"path": path, "logic": {"cat": [{"var": path}, id]}}
+        ]}}})
+    };
+    let dir = std::env::temp_dir().join(format!("dataflow-fragments-{}", std::process::id()));
+    fs::create_dir_all(dir.join("workflows/fragments")).unwrap();
+    fs::write(
+        dir.join("workflows/fragments/tail.json"),
+        json!({"tasks": [set("tail", "data.trail")]}).to_string(),
+    )
+    .unwrap();
+    fs::write(
+        dir.join("workflows/orders.json"),
+        json!({"id": "orders", "name": "Orders", "channel": "orders", "tasks": [
+            {"include": "prelude"},
+            set("own", "data.trail"),
+            {"include": "fragments/tail.json"},
+            {"id": "each", "name": "Each", "function": {"name": "foreach", "input": {
+                "path": "data.items",
+                "tasks": [{"include": "prelude_item"}]
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+
+    let prelude = TaskFragment::from_json(
+        &json!({"id": "prelude", "tasks": [set("a", "data.trail"), set("b", "data.trail")]})
+            .to_string(),
+    )
+    .unwrap();
+    let prelude_item = TaskFragment::from_json(
+        &json!({"id": "prelude_item", "tasks": [set("i", "temp_data.item.seen")]}).to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_fragments([prelude, prelude_item])
+        .with_workflow(Workflow::from_file(dir.join("workflows/orders.json")).unwrap())
+        .build()
+        .unwrap();
+    let ids: Vec<_> = engine.workflows()[0]
+        .tasks
+        .iter()
+        .map(|t| t.id.as_str())
+        .collect();
+    assert_eq!(ids, ["a", "b", "own", "tail", "each"]);
+
+    let mut message = engine
+        .message()
+        .data_json(&json!({"trail": "", "items": [{"seen": ""}]}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["trail"], dv(json!("abowntail")));
+    assert_eq!(message.data()["items"], dv(json!([{"seen": "i"}])));
+
+    // Reloads resolve includes against the same fragments; unknown ones fail.
+    let reloaded = engine
+        .with_new_workflows(vec![
+            Workflow::from_file(dir.join("workflows/orders.json")).unwrap(),
+        ])
+        .unwrap();
+    assert_eq!(reloaded.workflows()[0].tasks.len(), 5);
+    let unknown = Workflow::from_json(
+        &json!({"id": "w", "name": "w", "tasks": [{"include": "missing"}]}).to_string(),
+    )
+    .unwrap();
+    let err = engine.with_new_workflows(vec![unknown]).err().unwrap();
+    assert!(err.to_string().contains("Unknown fragment 'missing'"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn placeholders_in_task_inputs_are_interpolated_at_build() {
+    let workflow = || {
+        Workflow::from_json(
+            &json!({"id": "w", "name": "w", "tasks": [
+                {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.large", "logic": {">": [{"var": "data.amount"}, "${vars.limits.large}"]}},
+                    {"path": "data.url", "logic": "${vars.base}/orders?src=${CARGO_PKG_NAME}"},
+                    {"path": "data.literal", "logic": "$${vars.base}"}
+                ]}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let engine = Engine::builder()
+        .with_var("limits", json!({"large": 1000}))
+        .with_var("base", json!("https://api.test"))
+        .with_strict_interpolation(true)
+        .with_workflow(workflow())
+        .build()
+        .unwrap();
+
+    let mut message = engine.message().data_json(&json!({"amount": 1500})).build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["large"], dv(json!(true)));
+    assert_eq!(
+        message.data()["url"],
+        dv(json!("https://api.test/orders?src=dataflow-rs"))
+    );
+    assert_eq!(message.data()["literal"], dv(json!("${vars.base}")));
+
+    // Rebuilding from the engine's own workflows keeps escapes literal.
+    let rebuilt = engine
+        .with_new_workflows(engine.workflows().as_ref().clone())
+        .unwrap();
+    let mut message = rebuilt.message().build();
+    rebuilt.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["literal"], dv(json!("${vars.base}")));
+
+    // Strict mode rejects a placeholder nothing resolves.
+    let err = Engine::builder()
+        .with_strict_interpolation(true)
+        .with_workflow(workflow())
+        .build()
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("Task m in workflow w: unresolved placeholder '${vars.limits.large}'")
+    );
+}
+
+#[tokio::test]
+async fn ad_hoc_expressions_are_compiled_once() {
+    // Evaluates the expression in its input against the message, as a
+    // scripting function would.
+    struct Script;
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Script {
+        type Input = Value;
+
+        async fn execute(&self, ctx: &mut TaskContext<'_>, input: &Value) -> Result<TaskOutcome> {
+            let result = ctx.evaluate(&input["expr"])?;
+            ctx.set("data.result", result);
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let engine = Engine::builder()
+        .register("script", Script)
+        .with_workflow(
+            Workflow::from_json(
+                &json!({"id": "w", "name": "w", "tasks": [
+                    {"id": "s", "name": "Script", "function": {"name": "script", "input": {
+                        "expr": {"+": [{"var": "data.n"}, 1]}
+                    }}}
+                ]})
+                .to_string(),
+            )
+            .unwrap(),
+        )
+        .build()
+        .unwrap();
+
+    for n in 0..3 {
+        let mut message = engine.message().data_json(&json!({"n": n})).build();
+        engine.process_message(&mut message).await.unwrap();
+        assert_eq!(message.data()["result"], dv(json!(n + 1)));
+    }
+    let stats = engine.expression_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.size), (2, 1, 1));
+
+    // The cache carries over to reloaded engines.
+    let reloaded = engine.with_new_workflows(Vec::new()).unwrap();
+    let context = json!({"data": {"a": 2, "b": 3}});
+    let first = json!({"if": [true, {"var": "data.a"}, {"var": "data.b"}]});
+    assert_eq!(reloaded.evaluate(&first, &context).unwrap(), json!(2));
+    let stats = reloaded.expression_cache_stats();
+    assert_eq!((stats.hits, stats.misses, stats.size), (2, 2, 2));
+    assert_eq!(reloaded.evaluate(&first, &context).unwrap(), json!(2));
+    assert_eq!(reloaded.expression_cache_stats().hits, 3);
+}
+
+#[tokio::test]
+async fn secrets_fill_task_inputs_and_are_redacted() {
+    use dataflow_rs::EnvSecretsResolver;
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "w", "name": "w", "tasks": [
+            {"id": "auth", "name": "Auth", "function": {"name": "map", "input": {
+                "mappings": [{"path": "data.header", "logic": "Bearer ${secret:NAME}"}]
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+
+    let err = Engine::builder()
+        .with_workflow(workflow.clone())
+        .build()
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string().contains("needs a secrets resolver"),
+        "{err}"
+    );
+
+    let engine = Engine::builder()
+        .with_secrets_resolver(EnvSecretsResolver::with_prefix("CARGO_PKG_"))
+        .with_workflow(workflow)
+        .build()
+        .unwrap();
+    let mut message = engine.message().build();
+    let trace = engine
+        .process_message_with_trace(&mut message)
+        .await
+        .unwrap();
+
+    // The task sees the secret; audit trails and traces don't.
+    assert_eq!(message.data()["header"], dv(json!("Bearer dataflow-rs")));
+    let change = &message.audit_trail()[0].changes[0];
+    assert_eq!(change.new_value, dv(json!("Bearer [REDACTED]")));
+    let snapshot = trace.final_message().unwrap();
+    assert_eq!(snapshot.data()["header"], dv(json!("Bearer [REDACTED]")));
+
+    // Rebuilt engines keep redacting values resolved by their predecessor.
+    let reloaded = engine
+        .with_new_workflows(engine.workflows().to_vec())
+        .unwrap();
+    let mut message = reloaded.message().build();
+    reloaded.process_message(&mut message).await.unwrap();
+    let change = &message.audit_trail()[0].changes[0];
+    assert_eq!(change.new_value, dv(json!("Bearer [REDACTED]")));
+}
+
+#[tokio::test]
+async fn dsl_workflows_run_like_json_ones() {
+    use dataflow_rs::dsl::{map, validate};
+
+    let workflow = Workflow::builder("orders", "Orders")
+        .condition(json!({"==": [{"var": "metadata.type"}, "order"]}))
+        .task(map("total", "Total").set(
+            "data.total",
+            json!({"*": [{"var": "data.qty"}, {"var": "data.price"}]}),
+        ))
+        .task(validate("check", "Check").required(["data.total"]).rule(
+            json!({">": [{"var": "data.total"}, 0]}),
+            "Total must be positive",
+        ))
+        .build()
+        .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut message = engine
+        .message()
+        .data_json(&json!({"qty": 3, "price": 4}))
+        .metadata_json(&json!({"type": "order"}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["total"], dv(json!(12)));
+    assert!(message.errors().is_empty());
+
+    let mut message = engine
+        .message()
+        .data_json(&json!({"qty": 0, "price": 4}))
+        .metadata_json(&json!({"type": "order"}))
+        .build();
+    let _ = engine.process_message(&mut message).await;
+    assert!(
+        message
+            .errors()
+            .iter()
+            .any(|e| e.message.contains("Total must be positive"))
+    );
+}
+
+#[cfg(feature = "otel")]
+#[tokio::test]
+async fn otel_spans_nest_tasks_in_workflows_in_messages() {
+    use opentelemetry::{KeyValue, Value as OtelValue, global, trace::Status};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    global::set_tracer_provider(provider);
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "otel_wf", "name": "WF", "tasks": [
+            {"id": "calc", "name": "Calc", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.a", "logic": 1},
+                {"path": "data.b", "logic": 2}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+
+    let attribute = |span: &SpanData, key: &str| {
+        span.attributes
+            .iter()
+            .find(|kv: &&KeyValue| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    };
+    let spans = exporter.get_finished_spans().unwrap();
+    let find = |name: &str, key: &str, value: OtelValue| {
+        spans
+            .iter()
+            .find(|span| span.name == name && attribute(span, key) == Some(value.clone()))
+            .unwrap_or_else(|| panic!("no {name} span"))
+    };
+    let message_span = find(
+        "process_message",
+        "message.id",
+        OtelValue::from(message.id().to_string()),
+    );
+    let workflow_span = find("workflow", "workflow.id", OtelValue::from("otel_wf"));
+    let task_span = find("task", "workflow.id", OtelValue::from("otel_wf"));
+
+    assert_eq!(
+        workflow_span.parent_span_id,
+        message_span.span_context.span_id()
+    );
+    assert_eq!(
+        task_span.parent_span_id,
+        workflow_span.span_context.span_id()
+    );
+    assert_eq!(
+        attribute(task_span, "task.id"),
+        Some(OtelValue::from("calc"))
+    );
+    assert_eq!(
+        attribute(task_span, "task.status_code"),
+        Some(OtelValue::I64(200))
+    );
+    assert_eq!(
+        attribute(task_span, "task.changes"),
+        Some(OtelValue::I64(2))
+    );
+    assert_eq!(task_span.status, Status::Unset);
+}
+
+fn flag_workflow(id: &str, priority: u32) -> Workflow {
+    Workflow::from_json(
+        &json!({"id": id, "name": id, "priority": priority, "tasks": [
+            {"id": "m", "name": "Map", "function": {"name": "map", "input": {"mappings": [
+                {"path": format!("data.{id}"), "logic": true}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn shared_engine_disables_and_reloads_workflows() {
+    use dataflow_rs::SharedEngine;
+
+    let engine = Engine::builder()
+        .with_workflows([flag_workflow("a", 1), flag_workflow("b", 2)])
+        .build()
+        .unwrap();
+    let shared = SharedEngine::new(engine);
+    let before = shared.current();
+
+    shared.disable("b").unwrap();
+    assert!(shared.disable("b").is_err());
+    assert_eq!(shared.disabled_ids(), ["b"]);
+    let mut message = Message::builder().build();
+    shared
+        .current()
+        .process_message(&mut message)
+        .await
+        .unwrap();
+    assert_eq!(message.data()["a"], dv(json!(true)));
+    assert!(message.data().get("b").is_none());
+    // An engine taken earlier keeps its workflows.
+    assert_eq!(before.workflows().len(), 2);
+
+    // A reload keeps disabled ids out but stores their new definition.
+    shared
+        .reload(vec![
+            flag_workflow("a", 1),
+            flag_workflow("b", 5),
+            flag_workflow("c", 3),
+        ])
+        .unwrap();
+    let ids: Vec<_> = shared
+        .current()
+        .workflows()
+        .iter()
+        .map(|w| w.id.clone())
+        .collect();
+    assert_eq!(ids, ["a", "c"]);
+    assert_eq!(shared.disabled_workflow("b").unwrap().priority, 5);
+
+    shared.enable("b").unwrap();
+    assert!(shared.enable("b").is_err());
+    assert!(shared.disabled_ids().is_empty());
+    let mut message = Message::builder().build();
+    shared
+        .current()
+        .process_message(&mut message)
+        .await
+        .unwrap();
+    assert_eq!(message.data()["b"], dv(json!(true)));
+}
+
+#[cfg(feature = "admin-api")]
+#[tokio::test]
+async fn admin_router_serves_introspection_toggles_and_dry_runs() {
+    use dataflow_rs::{InMemoryMetrics, SharedEngine};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let engine = Engine::builder()
+        .with_workflows([flag_workflow("a", 1), flag_workflow("b", 2)])
+        .with_metrics(InMemoryMetrics::new())
+        .build()
+        .unwrap();
+    let shared = SharedEngine::new(engine);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = dataflow_rs::admin::router(shared.clone());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let call = |method: &'static str, path: &'static str, body: Option<Value>| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: admin\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status: u16 = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str::<Value>(body).unwrap())
+    };
+
+    let (status, health) = call("GET", "/health", None).await;
+    assert_eq!((status, health["status"].clone()), (200, json!("ok")));
+
+    let (status, _) = call("POST", "/workflows/b/disable", None).await;
+    assert_eq!(status, 200);
+    assert_eq!(call("POST", "/workflows/b/disable", None).await.0, 404);
+    let (_, workflows) = call("GET", "/workflows", None).await;
+    let listed: Vec<_> = workflows
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|w| (w["id"].clone(), w["enabled"].clone()))
+        .collect();
+    assert_eq!(
+        listed,
+        [(json!("a"), json!(true)), (json!("b"), json!(false))]
+    );
+    let (status, described) = call("GET", "/workflows/b", None).await;
+    assert_eq!((status, described["enabled"].clone()), (200, json!(false)));
+    assert_eq!(call("GET", "/workflows/zzz", None).await.0, 404);
+
+    let (status, run) = call("POST", "/dry-run", Some(json!({"data": {"input": 1}}))).await;
+    assert_eq!(status, 200);
+    assert_eq!(run["message"]["context"]["data"]["a"], json!(true));
+    assert!(run["message"]["context"]["data"].get("b").is_none());
+    assert_eq!(run["error"], Value::Null);
+    assert_eq!(run["held_back"], json!([]));
+    assert!(!run["changes"].as_array().unwrap().is_empty());
+    assert_eq!(
+        call("POST", "/dry-run", Some(json!({"data": 1}))).await.0,
+        422
+    );
+
+    assert_eq!(call("POST", "/workflows/b/enable", None).await.0, 200);
+    assert_eq!(shared.current().workflows().len(), 2);
+
+    let mut message = Message::builder().build();
+    shared
+        .current()
+        .process_message(&mut message)
+        .await
+        .unwrap();
+    let (status, stats) = call("GET", "/metrics", None).await;
+    assert_eq!((status, stats["messages"].clone()), (200, json!(1)));
+    assert_eq!(call("GET", "/lint", None).await, (200, json!([])));
+}
+
+#[tokio::test]
+async fn processing_hooks_wrap_workflows_and_tasks_and_can_veto() {
+    use dataflow_rs::engine::message::Change;
+    use dataflow_rs::{DataflowError, ProcessingHook};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn push(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl ProcessingHook for Recorder {
+        fn before_workflow(&self, workflow: &Workflow, _message: &Message) -> Result<()> {
+            self.push(format!("before_workflow {}", workflow.id));
+            Ok(())
+        }
+
+        fn after_workflow(&self, workflow: &Workflow, _message: &Message, result: &Result<()>) {
+            self.push(format!(
+                "after_workflow {} ok={}",
+                workflow.id,
+                result.is_ok()
+            ));
+        }
+
+        fn before_task(&self, _workflow: &Workflow, task: &Task, _message: &Message) -> Result<()> {
+            self.push(format!("before_task {}", task.id));
+            if task.id == "guarded" {
+                return Err(DataflowError::Validation("not authorized".to_string()));
+            }
+            Ok(())
+        }
+
+        fn after_task(
+            &self,
+            _workflow: &Workflow,
+            task: &Task,
+            _message: &Message,
+            result: &Result<(TaskOutcome, Vec<Change>)>,
+        ) {
+            let changes = result.as_ref().map_or(0, |(_, changes)| changes.len());
+            self.push(format!("after_task {} changes={changes}", task.id));
+        }
+
+        fn on_error(
+            &self,
+            workflow: &Workflow,
+            task: Option<&Task>,
+            _message: &Message,
+            _error: &DataflowError,
+        ) {
+            let at = task.map_or(workflow.id.as_str(), |task| task.id.as_str());
+            self.push(format!("on_error {at}"));
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "calc", "name": "Calc", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.n", "logic": 1}
+            ]}}},
+            {"id": "skipped", "name": "Skipped", "condition": false,
+             "function": {"name": "map", "input": {"mappings": [{"path": "data.s", "logic": 1}]}}},
+            {"id": "guarded", "name": "Guarded", "function": {"name": "fail", "input": {}}}
+        ]}"#,
+    )
+    .unwrap();
+    let recorder = Arc::new(Recorder::default());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("fail", FailingTask)
+        .with_processing_hook(Arc::clone(&recorder))
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    assert!(engine.process_message(&mut message).await.is_err());
+    assert_eq!(
+        *recorder.events.lock().unwrap(),
+        [
+            "before_workflow wf",
+            "before_task calc",
+            "after_task calc changes=1",
+            "before_task guarded",
+            "after_task guarded changes=0",
+            "on_error guarded",
+            "after_workflow wf ok=false",
+            "on_error wf",
+        ]
+    );
+    // The veto fails the task like any task error; the handler never ran.
+    assert!(
+        message
+            .errors()
+            .iter()
+            .any(|e| e.message.contains("not authorized"))
+    );
+    assert!(!message.errors().iter().any(|e| e.message.contains("boom")));
+}
+
+// Signals when it starts, then waits to be released.
+struct Gate {
+    reached: Arc<tokio::sync::Notify>,
+    release: Arc<tokio::sync::Notify>,
+}
+
+#[async_trait]
+impl AsyncFunctionHandler for Gate {
+    type Input = Value;
+
+    async fn execute(&self, _ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        self.reached.notify_one();
+        self.release.notified().await;
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test]
+async fn lookup_table_updates_spare_in_flight_messages() {
+    use tokio::sync::Notify;
+
+    let reached = Arc::new(Notify::new());
+    let release = Arc::new(Notify::new());
+    let map = |id: &str, path: &str| {
+        json!({"id": id, "name": id, "function": {"name": "map", "input": {"mappings": [
+            {"path": path, "logic": {"lookup": ["fees", "gold"]}}
+        ]}}})
+    };
+    let workflow = json!({"id": "fees", "name": "Fees", "tasks": [
+        map("before", "data.before"),
+        {"id": "wait", "name": "Wait", "function": {"name": "gate", "input": {}}},
+        map("after", "data.after")
+    ]});
+    let engine = Engine::builder()
+        .with_workflow(Workflow::from_json(&workflow.to_string()).unwrap())
+        .with_lookup_table("fees", json!({"gold": 1}))
+        .register(
+            "gate",
+            Gate {
+                reached: Arc::clone(&reached),
+                release: Arc::clone(&release),
+            },
+        )
+        .build()
+        .unwrap();
+    assert_eq!(engine.lookup_generation(), 0);
+
+    let mut in_flight = Message::from_value(&json!({}));
+    let (result, generation) = tokio::join!(engine.process_message(&mut in_flight), async {
+        reached.notified().await;
+        let generation = engine.update_lookup_table("fees", json!({"gold": 2}));
+        release.notify_one();
+        generation
+    });
+    result.unwrap();
+    assert_eq!(generation, 1);
+    assert_eq!(in_flight.data()["before"], dv(json!(1)));
+    assert_eq!(in_flight.data()["after"], dv(json!(1)));
+
+    let mut next = Message::from_value(&json!({}));
+    release.notify_one();
+    engine.process_message(&mut next).await.unwrap();
+    assert_eq!(next.data()["before"], dv(json!(2)));
+    assert_eq!(next.data()["after"], dv(json!(2)));
+
+    assert_eq!(engine.remove_lookup_table("fees"), 2);
+    let mut missing = Message::from_value(&json!({}));
+    assert!(engine.process_message(&mut missing).await.is_err());
+}
+
+#[tokio::test]
+async fn audit_input_hashes_track_config_and_read_data() {
+    use dataflow_rs::EngineOptions;
+    use dataflow_rs::engine::audit::{canonical_json, sha256_hex};
+
+    let workflow = |factor: i64| {
+        Workflow::from_json(
+            &json!({"id": "wf", "name": "WF", "tasks": [
+                {"id": "price", "name": "Price", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.total", "logic": {"*": [{"var": "data.amount"}, factor]}}
+                ]}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let run = |engine: Engine, amount: i64, note: &'static str| async move {
+        let mut message = Message::from_value(&json!({}));
+        set_nested_value(
+            &mut message.context,
+            "data",
+            dv(json!({"amount": amount, "note": note})),
+        );
+        engine.process_message(&mut message).await.unwrap();
+        message.audit_trail()[0].clone()
+    };
+    let build = |factor: i64, on: bool| {
+        Engine::builder()
+            .with_options(EngineOptions::new().with_audit_input_hashes(on))
+            .with_workflow(workflow(factor))
+            .build()
+            .unwrap()
+    };
+
+    let entry = run(build(2, true), 10, "a").await;
+    let data_hash = entry.data_hash.clone().expect("data hash recorded");
+    assert_eq!(
+        data_hash,
+        sha256_hex(canonical_json(&json!({"data.amount": 10})).as_bytes())
+    );
+    // Data the task doesn't read leaves the hash alone.
+    let unrelated = run(build(2, true), 10, "b").await;
+    assert_eq!(unrelated.data_hash.as_deref(), Some(data_hash.as_str()));
+    assert_eq!(unrelated.input_hash, entry.input_hash);
+    // Changing the input or the config changes the matching hash.
+    let other_input = run(build(2, true), 11, "a").await;
+    assert_ne!(other_input.data_hash.as_deref(), Some(data_hash.as_str()));
+    let other_config = run(build(3, true), 10, "a").await;
+    assert_ne!(other_config.input_hash, entry.input_hash);
+
+    let off = run(build(2, false), 10, "a").await;
+    assert!(off.input_hash.is_none() && off.data_hash.is_none());
+}
+
+#[tokio::test]
+async fn signed_audit_trails_detect_tampering() {
+    use dataflow_rs::engine::audit;
+    use dataflow_rs::{DataflowError, EngineOptions, SecretsResolver};
+
+    struct Keys(&'static str);
+    impl SecretsResolver for Keys {
+        fn resolve(&self, name: &str) -> dataflow_rs::Result<String> {
+            match name {
+                "audit-key" => Ok(self.0.to_string()),
+                _ => Err(DataflowError::Unknown(format!("no secret {name}"))),
+            }
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "wf", "name": "WF", "tasks": [
+            {"id": "a", "name": "A", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.a", "logic": 1}
+            ]}}},
+            {"id": "b", "name": "B", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.b", "logic": 2}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let build = |key: &'static str, secret: &str| {
+        Engine::builder()
+            .with_options(EngineOptions::new().with_audit_signing_key(secret))
+            .with_secrets_resolver(Keys(key))
+            .with_workflow(workflow.clone())
+            .build()
+            .unwrap()
+    };
+    let engine = build("s3cret", "audit-key");
+
+    let mut message = Message::from_value(&json!({}));
+    engine.process_message(&mut message).await.unwrap();
+    let trail = message.audit_trail();
+    assert_eq!(trail.len(), 2);
+    assert_eq!(
+        trail[0].hash.as_deref(),
+        Some(audit::entry_hash(&audit::chain_seed(&message), &trail[0]).as_str())
+    );
+    assert_eq!(
+        trail[1].hash.as_deref(),
+        Some(audit::entry_hash(trail[0].hash.as_deref().unwrap(), &trail[1]).as_str())
+    );
+    assert!(message.audit_signature().is_some());
+    assert!(engine.verify_audit(&message).unwrap());
+    assert!(!build("other", "audit-key").verify_audit(&message).unwrap());
+
+    // The signature survives a serialization round trip; edits don't.
+    let stored = serde_json::to_value(&message).unwrap();
+    let restored: Message = serde_json::from_value(stored.clone()).unwrap();
+    assert!(engine.verify_audit(&restored).unwrap());
+
+    let mut edited = stored.clone();
+    edited["audit_trail"][0]["status"] = json!(500);
+    let edited: Message = serde_json::from_value(edited).unwrap();
+    assert!(!audit::verify_chain(&edited));
+    assert!(!engine.verify_audit(&edited).unwrap());
+
+    // The chain is bound to the message's id and payload.
+    for (field, value) in [("id", json!("other-id")), ("payload", json!({"x": 1}))] {
+        let mut moved = stored.clone();
+        moved[field] = value;
+        let moved: Message = serde_json::from_value(moved).unwrap();
+        assert!(!audit::verify_chain(&moved), "{field}");
+        assert!(!engine.verify_audit(&moved).unwrap(), "{field}");
+    }
+
+    // Dropping the last entry keeps a valid chain but breaks the signature.
+    let mut truncated = stored;
+    truncated["audit_trail"].as_array_mut().unwrap().pop();
+    let truncated: Message = serde_json::from_value(truncated).unwrap();
+    assert!(audit::verify_chain(&truncated));
+    assert!(!engine.verify_audit(&truncated).unwrap());
+
+    // A key that can't be resolved fails the message, unsigned.
+    let mut unsigned = Message::from_value(&json!({}));
+    let missing = build("s3cret", "nope");
+    assert!(missing.process_message(&mut unsigned).await.is_err());
+    assert!(unsigned.audit_signature().is_none());
+    assert!(
+        unsigned
+            .errors()
+            .iter()
+            .any(|e| e.code == "AUDIT_SIGNING_ERROR")
+    );
+
+    // Signing needs a resolver; chaining alone doesn't.
+    let no_resolver = Engine::builder()
+        .with_options(EngineOptions::new().with_audit_signing_key("audit-key"))
+        .with_workflow(workflow.clone())
+        .build();
+    assert!(no_resolver.is_err());
+    let chained = Engine::builder()
+        .with_options(EngineOptions::new().with_audit_chain(true))
+        .with_workflow(workflow)
+        .build()
+        .unwrap();
+    let mut message = Message::from_value(&json!({}));
+    chained.process_message(&mut message).await.unwrap();
+    assert!(message.audit_signature().is_none());
+    assert!(chained.verify_audit(&message).unwrap());
+}
+
+#[tokio::test]
+async fn skip_audited_tasks_resumes_redelivered_messages() {
+    use dataflow_rs::EngineOptions;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Counts calls; fails the first `fail_first` of them.
+    struct SideEffect {
+        calls: Arc<AtomicUsize>,
+        fail_first: usize,
+    }
+
+    #[async_trait]
+    impl AsyncFunctionHandler for SideEffect {
+        type Input = Value;
+
+        async fn execute(&self, _ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.fail_first {
+                return Err(dataflow_rs::DataflowError::Task("unavailable".to_string()));
+            }
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "payout", "name": "Payout", "tasks": [
+            {"id": "stamp", "name": "Stamp", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.stamped", "logic": true}
+            ]}}},
+            {"id": "charge", "name": "Charge", "function": {"name": "charge", "input": {}}},
+            {"id": "notify", "name": "Notify", "function": {"name": "notify", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let charges = Arc::new(AtomicUsize::new(0));
+    let notifications = Arc::new(AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_options(EngineOptions::new().with_skip_audited_tasks(true))
+        .register(
+            "charge",
+            SideEffect {
+                calls: Arc::clone(&charges),
+                fail_first: 0,
+            },
+        )
+        .register(
+            "notify",
+            SideEffect {
+                calls: Arc::clone(&notifications),
+                fail_first: 1,
+            },
+        )
+        .with_workflow(workflow)
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    assert!(engine.process_message(&mut message).await.is_err());
+    assert_eq!(charges.load(Ordering::SeqCst), 1);
+
+    // Redelivered with the trail of the first attempt: only the failed
+    // task runs again.
+    let mut redelivered = message.clone();
+    engine.process_message(&mut redelivered).await.unwrap();
+    assert_eq!(charges.load(Ordering::SeqCst), 1);
+    assert_eq!(notifications.load(Ordering::SeqCst), 2);
+    let tasks: Vec<(&str, usize)> = redelivered
+        .audit_trail()
+        .iter()
+        .map(|entry| (&*entry.task_id, entry.status))
+        .collect();
+    assert_eq!(
+        tasks,
+        [
+            ("stamp", 200),
+            ("charge", 200),
+            ("notify", 500),
+            ("notify", 200)
+        ]
+    );
+
+    // Off by default: every task runs again.
+    let engine = Engine::builder()
+        .register(
+            "charge",
+            SideEffect {
+                calls: Arc::clone(&charges),
+                fail_first: 0,
+            },
+        )
+        .register(
+            "notify",
+            SideEffect {
+                calls: Arc::clone(&notifications),
+                fail_first: 0,
+            },
+        )
+        .with_workflow(
+            Workflow::from_json(
+                &json!({"id": "payout", "name": "Payout", "tasks": [
+                    {"id": "charge", "name": "Charge", "function": {"name": "charge", "input": {}}}
+                ]})
+                .to_string(),
+            )
+            .unwrap(),
+        )
+        .build()
+        .unwrap();
+    engine.process_message(&mut redelivered).await.unwrap();
+    assert_eq!(charges.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn engine_messages_take_ids_from_the_configured_generator() {
+    use dataflow_rs::Snowflake;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "wf", "name": "WF", "tasks": [
+            {"id": "t", "name": "T", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.copied", "logic": 1}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+
+    let snowflake = Engine::builder()
+        .with_id_generator(Snowflake::new(3))
+        .with_workflow(workflow.clone())
+        .build()
+        .unwrap();
+    let first = snowflake.message().build();
+    let second = snowflake.message().build();
+    assert_eq!(first.id().len(), 19);
+    assert!(first.id() < second.id());
+
+    let counter = AtomicU64::new(0);
+    let external = Engine::builder()
+        .with_id_generator(move || format!("ext-{}", counter.fetch_add(1, Ordering::Relaxed)))
+        .with_workflow(workflow.clone())
+        .build()
+        .unwrap();
+    assert_eq!(external.message().build().id(), "ext-0");
+    let reloaded = external.with_new_workflows(vec![workflow]).unwrap();
+    assert_eq!(reloaded.message().build().id(), "ext-1");
+    // An upstream id still wins.
+    assert_eq!(
+        reloaded.message().id("upstream-7").build().id(),
+        "upstream-7"
+    );
+
+    let mut message = Engine::builder().build().unwrap().message().build();
+    assert_eq!(message.id().len(), 36);
+    snowflake.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["copied"], dv(json!(1)));
+}
+
+#[tokio::test]
+async fn switch_runs_the_selected_branch_and_jumps_with_goto() {
+    let mark = |id: &str| {
+        json!({"id": id, "name": id, "function": {"name": "map", "input": {"mappings": [
+            {"path": format!("data.ran.{id}"), "logic": true}
+        ]}}})
+    };
+    let workflow = Workflow::from_json(
+        &json!({"id": "payments", "name": "Payments", "tasks": [
+            {"id": "route", "name": "Route", "function": {"name": "switch", "input": {
+                "cases": [
+                    {"name": "domestic", "when": {"==": [{"var": "data.country"}, "US"]},
+                     "tasks": ["ach"]},
+                    {"name": "blocked", "when": {"var": "data.sanctioned"}, "goto": "reject"}
+                ],
+                "default": {"tasks": ["swift"]}
+            }}},
+            mark("ach"),
+            mark("swift"),
+            mark("settle"),
+            mark("reject")
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let run = |data: serde_json::Value| {
+        let engine = &engine;
+        async move {
+            let mut message = Message::from_value(&json!({}));
+            set_nested_value(&mut message.context, "data", dv(data));
+            engine.process_message(&mut message).await.unwrap();
+            let mut ran: Vec<String> = match message.data()["ran"].as_object() {
+                Some(ran) => ran.iter().map(|(key, _)| key.to_string()).collect(),
+                None => Vec::new(),
+            };
+            ran.sort();
+            (
+                ran,
+                message.metadata()["branches"]["payments"]["route"].clone(),
+            )
+        }
+    };
+
+    assert_eq!(
+        run(json!({"country": "US"})).await,
+        (
+            vec!["ach".into(), "reject".into(), "settle".into()],
+            dv(json!("domestic"))
+        )
+    );
+    assert_eq!(
+        run(json!({"country": "DE"})).await,
+        (
+            vec!["reject".into(), "settle".into(), "swift".into()],
+            dv(json!("default"))
+        )
+    );
+    // The first matching case wins.
+    assert_eq!(
+        run(json!({"country": "US", "sanctioned": true})).await,
+        (
+            vec!["ach".into(), "reject".into(), "settle".into()],
+            dv(json!("domestic"))
+        )
+    );
+    assert_eq!(
+        run(json!({"country": "DE", "sanctioned": true})).await,
+        (vec!["reject".into()], dv(json!("blocked")))
+    );
+
+    let unknown = Workflow::from_json(
+        &json!({"id": "wf", "name": "WF", "tasks": [
+            {"id": "route", "name": "Route", "function": {"name": "switch", "input": {
+                "cases": [{"when": true, "goto": "missing"}]
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    assert!(Engine::builder().with_workflow(unknown).build().is_err());
+}
+
+#[tokio::test]
+async fn foreach_runs_nested_tasks_per_element() {
+    struct Tag;
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Tag {
+        type Input = Value;
+
+        async fn execute(&self, ctx: &mut TaskContext<'_>, input: &Value) -> Result<TaskOutcome> {
+            ctx.set("temp_data.order.tag", dv(input["tag"].clone()));
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "orders", "name": "Orders", "channel": "orders", "tasks": [
+            {"id": "each", "name": "Each Order", "function": {"name": "foreach", "input": {
+                "path": "data.orders",
+                "item": "temp_data.order",
+                "index": "temp_data.position",
+                "target": "data.priced",
+                "tasks": [
+                    {"id": "total", "name": "Total", "function": {"name": "map", "input": {"mappings": [
+                        {"path": "temp_data.order.total",
+                         "logic": {"*": [{"var": "temp_data.order.qty"}, {"var": "temp_data.order.price"}]}},
+                        {"path": "temp_data.order.position", "logic": {"var": "temp_data.position"}}
+                    ]}}},
+                    {"id": "tag", "name": "Tag Bulk",
+                     "condition": {">": [{"var": "temp_data.order.qty"}, 5]},
+                     "function": {"name": "tag", "input": {"tag": "bulk"}}}
+                ]
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("tag", Tag)
+        .build()
+        .unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    let orders = json!([{"qty": 2, "price": 5}, {"qty": 10, "price": 1}]);
+    set_nested_value(&mut message.context, "data.orders", dv(orders.clone()));
+    engine.process_message(&mut message).await.unwrap();
+
+    assert!(!message.has_errors());
+    assert_eq!(message.data()["orders"], dv(orders));
+    assert_eq!(
+        message.data()["priced"],
+        dv(json!([
+            {"qty": 2, "price": 5, "total": 10, "position": 0},
+            {"qty": 10, "price": 1, "total": 10, "position": 1, "tag": "bulk"}
+        ]))
+    );
+    // The bindings don't outlive the loop.
+    assert!(message.context["temp_data"].get("order").is_none());
+    assert!(message.context["temp_data"].get("position").is_none());
+}
+
+#[tokio::test]
+async fn retry_progress_persists_in_the_state_store() {
+    use dataflow_rs::{InMemoryStateStore, RetryConfig, RetryState, StateStore};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Unavailable {
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Unavailable {
+        type Input = Value;
+
+        async fn execute(&self, _ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Err(dataflow_rs::DataflowError::http(503, "unavailable"))
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "send", "name": "Send", "function": {"name": "send", "input": {}}}
+        ]}"#,
+    )
+    .unwrap();
+    let store = Arc::new(InMemoryStateStore::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register(
+            "send",
+            Unavailable {
+                calls: Arc::clone(&calls),
+            },
+        )
+        .with_retry_config(RetryConfig::new(3).with_backoff(10, 1_000))
+        .with_state_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    // A previous process made two retries and scheduled the third before
+    // going down; the redelivered message picks up from there.
+    let mut message = Message::from_value(&json!({}));
+    let key = format!("retry:{}:wf:send", message.id());
+    let due = chrono::Utc::now() + chrono::Duration::milliseconds(60);
+    let state = RetryState {
+        retries: 2,
+        next_attempt_at: due,
+    };
+    store
+        .set(&key, serde_json::to_value(state).unwrap(), None)
+        .await
+        .unwrap();
+
+    assert!(engine.process_message(&mut message).await.is_err());
+    assert!(chrono::Utc::now() >= due);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(message.errors()[0].retry_count, Some(3));
+    assert!(store.keys().is_empty());
+}
+
+#[tokio::test]
+async fn reject_builds_a_rejection_and_flags_the_message() {
+    use dataflow_rs::ErrorInfo;
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "pacs", "name": "PACS", "tasks": [
+            {"id": "nack", "name": "NACK", "function": {"name": "reject", "input": {
+                "code": "AC01",
+                "reason": {"cat": ["Unknown account ", {"var": "data.account"}]},
+                "references": {"message_id": "data.msg_id", "end_to_end_id": "data.e2e_id"},
+                "target": "data.nack",
+                "include_errors": true,
+                "halt": true
+            }}},
+            {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.after", "logic": true}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut message = Message::from_value(&json!({}));
+    set_nested_value(
+        &mut message.context,
+        "data",
+        dv(json!({"account": "123", "msg_id": "MSG-1"})),
+    );
+    message.add_error(
+        ErrorInfo::builder("VALIDATION_ERROR", "IBAN checksum failed")
+            .path("data.account")
+            .build(),
+    );
+    assert!(!message.is_rejected());
+    engine.process_message(&mut message).await.unwrap();
+
+    assert!(message.is_rejected());
+    let nack = &message.data()["nack"];
+    assert_eq!(nack["code"], dv(json!("AC01")));
+    assert_eq!(nack["reason"], dv(json!("Unknown account 123")));
+    assert_eq!(
+        nack["references"],
+        dv(json!({"end_to_end_id": null, "message_id": "MSG-1"}))
+    );
+    assert_eq!(
+        nack["errors"],
+        dv(
+            json!([{"code": "VALIDATION_ERROR", "message": "IBAN checksum failed", "path": "data.account"}])
+        )
+    );
+    assert!(nack.get("rejected_at").is_some());
+    // `halt` stops the rest of the workflow.
+    assert!(message.data().get("after").is_none());
+}
+
+#[tokio::test]
+async fn score_sums_triggered_weights_and_routes_on_the_result() {
+    let workflow = Workflow::from_json(
+        &json!({"id": "screen", "name": "Screen", "tasks": [
+            {"id": "risk", "name": "Risk", "function": {"name": "score", "input": {
+                "rules": [
+                    {"id": "high_amount", "weight": 40, "logic": {">": [{"var": "data.amount"}, 10000]}},
+                    {"id": "new_account", "weight": 25, "logic": {"<": [{"var": "data.age_days"}, 30]}},
+                    {"id": "trusted_device", "weight": -20, "logic": {"var": "data.trusted"}}
+                ],
+                "target": "data.risk.score",
+                "triggered_target": "data.risk.triggered"
+            }}},
+            {"id": "review", "name": "Review", "condition": {">=": [{"var": "data.risk.score"}, 50]},
+             "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.review", "logic": true}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut message = Message::builder()
+        .data_json(&json!({"amount": 25000, "age_days": 3, "trusted": false}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(
+        message.data()["risk"],
+        dv(json!({"score": 65, "triggered": ["high_amount", "new_account"]}))
+    );
+    assert_eq!(message.data()["review"], dv(json!(true)));
+
+    let mut message = Message::builder()
+        .data_json(&json!({"amount": 25000, "age_days": 3, "trusted": true}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.data()["risk"]["score"], dv(json!(45)));
+    assert!(message.data().get("review").is_none());
+
+    let duplicate = Workflow::from_json(
+        &json!({"id": "dup", "name": "Dup", "tasks": [
+            {"id": "risk", "name": "Risk", "function": {"name": "score", "input": {"rules": [
+                {"id": "r", "weight": 1, "logic": true},
+                {"id": "r", "weight": 2, "logic": false}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let err = Engine::builder()
+        .with_workflow(duplicate)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("duplicate rule id 'r'"), "{err}");
+}
+
+#[tokio::test]
+async fn map_errors_rewrites_errors_into_partner_codes() {
+    let workflow = Workflow::from_json(
+        &json!({"id": "check", "name": "Check", "tasks": [
+            {"id": "rules", "name": "Rules", "function": {"name": "validation", "input": {"rules": [
+                {"logic": {"!!": {"var": "data.iban"}}, "message": "IBAN missing", "code": "IBAN_MISSING", "path": "data.iban"},
+                {"logic": {">": [{"var": "data.amount"}, 0]}, "message": "Amount must be positive"}
+            ]}}},
+            {"id": "summary", "name": "Summary", "continue_on_error": true,
+             "function": {"name": "sample", "input": {"source": "data.items", "target": "data.summary", "max_items": 1}}},
+            {"id": "errors", "name": "Errors", "function": {"name": "map_errors", "input": {
+                "mappings": [
+                    {"from": "IBAN_MISSING", "code": "AC01", "message": "Incorrect account number"},
+                    {"from": "VALIDATION_ERROR", "code": "AM12", "severity": "warning"},
+                    {"from": "TASK_ERROR", "task_id": "lookup", "code": "MS03"}
+                ],
+                "default": {"code": "NARR"},
+                "target": "data.response.errors"
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder().with_workflow(workflow).build().unwrap();
+
+    let mut message = Message::builder().data_json(&json!({"amount": -5})).build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(
+        message.data()["response"]["errors"],
+        dv(json!([
+            {"code": "AC01", "severity": "error", "message": "Incorrect account number", "path": "data.iban"},
+            {"code": "AM12", "severity": "warning", "message": "Amount must be positive"},
+            // The `TASK_ERROR` mapping is for another task.
+            {"code": "NARR", "severity": "error",
+             "message": "Task summary error: Validation error: Sample: path 'data.items' not found"}
+        ]))
+    );
+    // The internal codes stay on the message.
+    assert_eq!(message.errors()[0].code, "IBAN_MISSING");
+}
+
+#[tokio::test]
+async fn join_buffers_by_key_until_the_group_completes() {
+    use dataflow_rs::InMemoryStateStore;
+    use std::sync::Arc;
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "pair", "name": "Pair", "tasks": [
+            {"id": "join", "name": "Join", "function": {"name": "join", "input": {
+                "key": {"var": "data.corr"},
+                "count": 3,
+                "timeout_ms": 100,
+                "source": "data.part"
+            }}},
+            {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.joined", "logic": true}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let store = Arc::new(InMemoryStateStore::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .with_state_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+    let message = |id: &str, corr: &str, part: &str| {
+        engine
+            .message()
+            .id(id)
+            .data_json(&json!({"corr": corr, "part": part}))
+            .build()
+    };
+
+    let mut first = message("m1", "A", "request");
+    engine.process_message(&mut first).await.unwrap();
+    assert!(first.data().get("joined").is_none());
+    assert_eq!(
+        first.metadata()["joins"]["pair"]["join"],
+        dv(json!({"key": "A", "received": 1, "complete": false}))
+    );
+    assert_eq!(store.keys(), ["join:pair:join:A"]);
+
+    // A redelivery replaces its part; another key opens its own group.
+    let mut again = message("m1", "A", "request");
+    engine.process_message(&mut again).await.unwrap();
+    let mut other = message("m2", "B", "other");
+    engine.process_message(&mut other).await.unwrap();
+    let mut second = message("m3", "A", "response");
+    engine.process_message(&mut second).await.unwrap();
+    assert!(second.data().get("joined").is_none());
+
+    let mut third = message("m4", "A", "ack");
+    engine.process_message(&mut third).await.unwrap();
+    assert_eq!(third.data()["joined"], dv(json!(true)));
+    assert_eq!(
+        third.data()["parts"],
+        dv(json!(["request", "response", "ack"]))
+    );
+    assert_eq!(third.parent_ids(), ["m1", "m3"]);
+    assert_eq!(third.root_id(), "m1");
+    assert_eq!(store.keys(), ["join:pair:join:B"]);
+
+    // Past the timeout, the next message completes a short group.
+    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+    let mut late = message("m5", "B", "late");
+    engine.process_message(&mut late).await.unwrap();
+    assert_eq!(late.data()["parts"], dv(json!(["other", "late"])));
+    assert!(store.keys().is_empty());
+
+    let missing = Workflow::from_json(
+        &json!({"id": "bad", "name": "Bad", "tasks": [
+            {"id": "join", "name": "Join", "function": {"name": "join", "input": {
+                "key": {"var": "data.corr"}
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let err = Engine::builder()
+        .with_workflow(missing)
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("'count', 'timeout_ms' or both"));
+}
+
+#[tokio::test]
+async fn state_functions_and_handlers_share_the_state_store() {
+    use dataflow_rs::{InMemoryStateStore, StateStore};
+
+    struct LastCustomer;
+
+    #[async_trait]
+    impl AsyncFunctionHandler for LastCustomer {
+        type Input = Value;
+
+        async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            let store = ctx.state().expect("engine passes its state store");
+            let previous = store.get("last_customer").await?.unwrap_or(Value::Null);
+            ctx.set_json("data.previous_customer", &previous);
+            let customer = ctx.data()["customer"].clone();
+            store
+                .set(
+                    "last_customer",
+                    serde_json::to_value(&customer).unwrap(),
+                    None,
+                )
+                .await?;
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "orders", "name": "Orders", "channel": "orders", "tasks": [
+            {"id": "read", "name": "Read", "function": {"name": "state_get", "input": {
+                "key": {"var": "data.customer"},
+                "target": "data.order_count",
+                "default": 0
+            }}},
+            {"id": "write", "name": "Write", "function": {"name": "state_set", "input": {
+                "key": {"var": "data.customer"},
+                "value": {"+": [{"var": "data.order_count"}, 1]},
+                "ttl_ms": 60000
+            }}},
+            {"id": "last", "name": "Last", "function": {"name": "last_customer", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let store = Arc::new(InMemoryStateStore::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("last_customer", LastCustomer)
+        .with_state_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    let mut counts = Vec::new();
+    for customer in ["ada", "bob", "ada"] {
+        let mut message = engine
+            .message()
+            .data_json(&json!({"customer": customer}))
+            .build();
+        engine.process_message(&mut message).await.unwrap();
+        assert!(message.errors().is_empty());
+        counts.push((
+            message.data()["order_count"].clone(),
+            message.data()["previous_customer"].clone(),
+        ));
+    }
+    assert_eq!(
+        counts,
+        [
+            (dv(json!(0)), dv(json!(null))),
+            (dv(json!(0)), dv(json!("ada"))),
+            (dv(json!(1)), dv(json!("bob"))),
+        ]
+    );
+    assert_eq!(store.get("ada").await.unwrap(), Some(json!(2)));
+
+    // A null key fails the task rather than sharing one entry.
+    let mut anonymous = engine.message().data_json(&json!({})).build();
+    engine.process_message(&mut anonymous).await.ok();
+    assert!(
+        anonymous
+            .errors()
+            .iter()
+            .any(|e| e.message.contains("state_get: key is null"))
+    );
+}
+
+#[tokio::test]
+async fn alert_sends_to_the_sink_when_the_threshold_is_crossed() {
+    use dataflow_rs::{AlertSeverity, InMemoryAlertSink};
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "screen", "name": "Screen", "tasks": [
+            {"id": "high_risk", "name": "High risk",
+             "condition": {">=": [{"var": "data.score"}, 80]},
+             "function": {"name": "alert", "input": {
+                "severity": "critical",
+                "code": "HIGH_RISK",
+                "message": {"cat": ["Risk score ", {"var": "data.score"}]},
+                "fields": {"customer": {"var": "data.customer"}}
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let sink = Arc::new(InMemoryAlertSink::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .with_alert_sink(Arc::clone(&sink))
+        .build()
+        .unwrap();
+
+    for (customer, score) in [("ada", 95), ("bob", 40)] {
+        let mut message = Message::builder()
+            .data_json(&json!({"customer": customer, "score": score}))
+            .build();
+        engine.process_message(&mut message).await.unwrap();
+        assert!(message.errors().is_empty());
+    }
+
+    let alerts = sink.alerts();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!(alerts[0].severity, AlertSeverity::Critical);
+    assert_eq!(alerts[0].code, "HIGH_RISK");
+    assert_eq!(alerts[0].message, "Risk score 95");
+    assert_eq!(alerts[0].fields["customer"], dv(json!("ada")));
+    assert_eq!(
+        (alerts[0].workflow_id.as_str(), alerts[0].task_id.as_str()),
+        ("screen", "high_risk")
+    );
+
+    // A reloaded engine keeps the sink.
+    let engine = engine
+        .with_new_workflows(engine.workflows().to_vec())
+        .unwrap();
+    let mut message = Message::builder()
+        .data_json(&json!({"customer": "cy", "score": 80}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(sink.alerts().len(), 2);
+}
+
+#[tokio::test]
+async fn failed_and_over_budget_messages_are_dead_lettered() {
+    use dataflow_rs::{DataflowError, DeadLetterPolicy, DeadLetterReason, InMemoryDeadLetterQueue};
+
+    let workflow = |continue_on_error: bool| {
+        Workflow::from_json(
+            &json!({"id": "dlq_wf", "name": "DLQ", "continue_on_error": continue_on_error, "tasks": [
+                {"id": "first", "name": "First", "function": {"name": "fail", "input": {}}},
+                {"id": "second", "name": "Second", "function": {"name": "fail", "input": {}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let queue = Arc::new(InMemoryDeadLetterQueue::new());
+    let engine = |continue_on_error: bool| {
+        Engine::builder()
+            .with_workflow(workflow(continue_on_error))
+            .register("fail", FailingTask)
+            .with_dead_letters(
+                DeadLetterPolicy {
+                    error_budget: Some(1),
+                },
+                Arc::clone(&queue),
+            )
+            .build()
+            .unwrap()
+    };
+
+    let mut failed = Message::builder().id("failed").build();
+    let err = engine(false)
+        .process_message(&mut failed)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, DataflowError::DeadLettered(_)));
+    assert!(!err.retryable());
+
+    // Both failures are tolerated, but two errors exceed the budget of one.
+    let mut noisy = Message::builder().id("noisy").build();
+    let err = engine(true).process_message(&mut noisy).await.unwrap_err();
+    assert!(err.to_string().contains("2 errors exceed the budget of 1"));
+
+    let letters = queue.drain();
+    assert_eq!(letters.len(), 2);
+    assert_eq!(letters[0].message.id(), "failed");
+    assert_eq!(letters[0].reason, DeadLetterReason::Failed);
+    assert!(matches!(letters[0].error, Some(DataflowError::Task(_))));
+    assert_eq!(letters[1].message.id(), "noisy");
+    assert_eq!(letters[1].reason, DeadLetterReason::ErrorBudget);
+    assert_eq!(letters[1].errors.len(), 2);
+}
+
+#[tokio::test]
+async fn try_new_reports_compilation_and_skipped_items() {
+    use std::collections::HashMap;
+
+    let task = |id: &str, condition: Value| {
+        json!({"id": id, "name": id, "condition": condition, "function": {"name": "map", "input": {"mappings": [
+            {"path": "data.x", "logic": {"var": "data.y"}}
+        ]}}})
+    };
+    let workflows = vec![
+        Workflow::from_json(
+            &json!({"id": "late", "name": "Late", "priority": 2, "tasks": [
+                task("a", json!(true)),
+                task("never", json!(false))
+            ]})
+            .to_string(),
+        )
+        .unwrap(),
+        Workflow::from_json(
+            &json!({"id": "early", "name": "Early", "priority": 1, "status": "paused",
+                "tasks": [task("b", json!(true))]})
+            .to_string(),
+        )
+        .unwrap(),
+    ];
+
+    let (engine, report) = Engine::try_new(workflows.clone(), HashMap::new()).unwrap();
+    assert_eq!(engine.workflows().len(), 2);
+    let ids: Vec<&str> = report
+        .workflows
+        .iter()
+        .map(|w| w.workflow_id.as_str())
+        .collect();
+    assert_eq!(ids, ["early", "late"]);
+    // Workflow condition, plus a condition and a mapping per task.
+    assert_eq!(report.workflows[1].tasks, 2);
+    assert_eq!(report.workflows[1].expressions, 5);
+    assert_eq!(report.expression_count(), 8);
+    assert!(report.compile_ms >= 0.0);
+    let skipped: Vec<(&str, Option<&str>, &str)> = report
+        .skipped
+        .iter()
+        .map(|s| {
+            (
+                s.workflow_id.as_str(),
+                s.task_id.as_deref(),
+                s.reason.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        skipped,
+        [
+            ("early", None, "workflow status is paused"),
+            ("late", Some("never"), "condition is always false")
+        ]
+    );
+
+    // A failure keeps the report of the workflows compiled before it.
+    let mut broken = workflows;
+    broken.push(
+        Workflow::from_json(
+            &json!({"id": "broken", "name": "Broken", "tasks": [
+                task("c", json!(true)),
+                task("c", json!(true))
+            ]})
+            .to_string(),
+        )
+        .unwrap(),
+    );
+    let err = Engine::try_new(broken, HashMap::new()).err().unwrap();
+    assert!(err.to_string().contains("broken"));
+    assert_eq!(err.report.workflows.len(), 2);
+    let err = dataflow_rs::DataflowError::from(err);
+    let config = err.config().expect("a configuration error");
+    assert_eq!(config.workflow_id.as_deref(), Some("broken"));
+    assert_eq!(config.task_id.as_deref(), Some("c"));
+    assert_eq!(config.json_pointer.as_deref(), Some("/tasks/1"));
+}
+
+#[test]
+fn configuration_errors_carry_their_location() {
+    use dataflow_rs::ConfigErrorKind;
+
+    let dir = std::env::temp_dir().join(format!("dataflow-config-error-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("orders.json");
+    std::fs::write(
+        &file,
+        json!({"id": "orders", "name": "Orders", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {
+                "source": "payload", "target": "order"
+            }}},
+            {"id": "copy", "name": "Copy", "function": {"name": "map", "input": {"mappings": "oops"}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let err = Workflow::from_file(&file).unwrap_err();
+    let config = err.config().expect("a configuration error");
+    assert!(matches!(config.kind, ConfigErrorKind::Parse(_)));
+    assert_eq!(config.file.as_deref(), Some(file.as_path()));
+    assert_eq!(config.workflow_id.as_deref(), Some("orders"));
+    assert_eq!(config.task_id.as_deref(), Some("copy"));
+    assert_eq!(config.json_pointer.as_deref(), Some("/tasks/1/function"));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let err = Workflow::from_json("{\"id\": ").unwrap_err();
+    assert!(matches!(
+        err.config().unwrap().kind,
+        ConfigErrorKind::Syntax(_)
+    ));
+
+    // Compilation errors point into foreach bodies.
+    let workflow = Workflow::from_json(
+        &json!({"id": "scoring", "name": "Scoring", "tasks": [
+            {"id": "each", "name": "Each", "function": {"name": "foreach", "input": {
+                "path": "data.items", "tasks": [
+                    {"id": "ok", "name": "Ok", "function": {"name": "map", "input": {"mappings": []}}},
+                    {"id": "rate", "name": "Rate", "function": {"name": "score", "input": {
+                        "target": "data.score", "rules": [
+                            {"id": "r", "logic": true, "weight": 1},
+                            {"id": "r", "logic": true, "weight": 2}
+                        ]
+                    }}}
+                ]
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let err = Engine::builder()
+        .with_workflow(workflow)
+        .build()
+        .err()
+        .unwrap();
+    let config = err.config().expect("a configuration error");
+    assert!(matches!(config.kind, ConfigErrorKind::Invalid(_)));
+    assert_eq!(config.workflow_id.as_deref(), Some("scoring"));
+    assert_eq!(config.task_id.as_deref(), Some("rate"));
+    assert_eq!(
+        config.json_pointer.as_deref(),
+        Some("/tasks/0/function/input/tasks/1")
+    );
+}
+
+#[test]
+fn normalized_json_is_canonical_and_round_trips() {
+    let terse = Workflow::from_json(
+        r#"{"id": "wf", "name": "WF", "tasks": [
+            {"id": "m", "name": "M", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.x", "logic": 1}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let verbose = Workflow::from_json(
+        r#"{"tasks": [
+            {"function": {"input": {"mappings": [{"logic": 1, "path": "data.x"}]}, "name": "map"},
+             "name": "M", "id": "m", "condition": true, "continue_on_error": false}
+        ], "priority": 0, "channel": "default", "version": 1, "status": "active",
+        "name": "WF", "id": "wf"}"#,
+    )
+    .unwrap();
+
+    let normalized = terse.to_normalized_json().unwrap();
+    assert_eq!(normalized, verbose.to_normalized_json().unwrap());
+
+    let value: Value = serde_json::from_str(&normalized).unwrap();
+    assert_eq!(
+        value["format_version"],
+        json!(dataflow_rs::NORMALIZED_FORMAT_VERSION)
+    );
+    assert_eq!(value["channel"], json!("default"));
+    assert_eq!(value["tasks"][0]["timeout_ms"], Value::Null);
+    let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let restored = Workflow::from_normalized_json(&normalized).unwrap();
+    assert_eq!(restored.to_normalized_json().unwrap(), normalized);
+    assert!(Engine::builder().with_workflow(restored).build().is_ok());
+
+    let unstamped = normalized.replace("\"format_version\":1,", "");
+    assert!(Workflow::from_normalized_json(&unstamped).is_err());
+    let future = normalized.replace("\"format_version\":1", "\"format_version\":99");
+    assert!(Workflow::from_normalized_json(&future).is_err());
+}
+
+#[test]
+fn allowed_capabilities_reject_functions_that_need_more() {
+    use dataflow_rs::Capability;
+
+    struct Archive;
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Archive {
+        type Input = Value;
+
+        async fn execute(&self, _ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            Ok(TaskOutcome::Success)
+        }
+
+        fn capabilities(&self) -> &'static [Capability] {
+            &[Capability::Filesystem]
+        }
+    }
+
+    let build = |allowed: Option<Vec<Capability>>| {
+        let workflow = Workflow::from_json(
+            &json!({"id": "wf", "name": "WF", "tasks": [
+                {"id": "call", "name": "Call", "function": {
+                    "name": "http_call", "input": {"connector": "api"}
+                }},
+                {"id": "each", "name": "Each", "function": {"name": "foreach", "input": {
+                    "path": "data.items",
+                    "item": "temp_data.item",
+                    "tasks": [
+                        {"id": "archive", "name": "Archive",
+                         "function": {"name": "archive", "input": {}}}
+                    ]
+                }}}
+            ]})
+            .to_string(),
+        )
+        .unwrap();
+        let mut builder = Engine::builder()
+            .with_workflow(workflow)
+            .register("http_call", LoggingTask)
+            .register("archive", Archive);
+        if let Some(allowed) = allowed {
+            builder = builder.with_allowed_capabilities(allowed);
+        }
+        builder.build()
+    };
+
+    assert!(build(None).is_ok());
+    assert!(build(Some(vec![Capability::Network, Capability::Filesystem])).is_ok());
+
+    let err = build(Some(vec![])).err().unwrap();
+    assert!(err.to_string().contains("Task call"));
+    assert!(
+        err.to_string()
+            .contains("network capability (allowed: none)")
+    );
+
+    let err = build(Some(vec![Capability::Network])).err().unwrap();
+    assert!(err.to_string().contains("Task archive"));
+    assert!(err.to_string().contains("filesystem capability"));
+}
+
+#[test]
+fn readable_roots_limit_what_a_workflow_reads() {
+    let workflows = || {
+        vec![
+            Workflow::from_json(
+                r#"{"id": "payments", "name": "Payments",
+                    "condition": {"==": [{"var": "metadata.type"}, "pacs.008"]},
+                    "tasks": [
+                        {"id": "total", "name": "Total", "function": {"name": "map", "input": {"mappings": [
+                            {"path": "data.total", "logic": {"+": [{"var": "data.amount"}, {"var": "temp_data.fee"}]}}
+                        ]}}}
+                    ]}"#,
+            )
+            .unwrap(),
+            Workflow::from_json(
+                r#"{"id": "audit", "name": "Audit", "tasks": [
+                    {"id": "copy", "name": "Copy", "function": {"name": "map", "input": {"mappings": [
+                        {"path": "data.copy", "logic": {"var": "temp_data.secret"}}
+                    ]}}}
+                ]}"#,
+            )
+            .unwrap(),
+        ]
+    };
+
+    let engine = Engine::builder()
+        .with_workflows(workflows())
+        .with_readable_roots("payments", ["data", "metadata", "temp_data"])
+        .build();
+    assert!(engine.is_ok());
+
+    let err = Engine::builder()
+        .with_workflows(workflows())
+        .with_readable_roots("payments", ["data", "metadata"])
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.to_string(),
+        "Configuration error: Workflow payments reads 'temp_data.fee' in task total mapping \
+         data.total, but may only read: data, metadata (workflow 'payments')"
+    );
+
+    let err = Engine::builder()
+        .with_workflows(workflows())
+        .with_readable_roots("payments", ["data"])
+        .build()
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("reads 'metadata.type' in condition")
+    );
+
+    let whole_context = Workflow::from_json(
+        r#"{"id": "peek", "name": "Peek", "tasks": [
+            {"id": "copy", "name": "Copy", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.copy", "logic": {"var": ""}}
+            ]}}}
+        ]}"#,
+    )
+    .unwrap();
+    let err = Engine::builder()
+        .with_workflow(whole_context)
+        .with_readable_roots("peek", ["data"])
+        .build()
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("reads the whole context"));
+
+    let err = Engine::builder()
+        .with_workflows(workflows())
+        .with_readable_roots("paymnets", ["data"])
+        .build()
+        .err()
+        .unwrap();
+    assert!(
+        err.to_string()
+            .contains("readable_roots names workflow 'paymnets'")
+    );
+}
+
+#[tokio::test]
+async fn resume_message_continues_after_the_last_checkpoint() {
+    use dataflow_rs::{CheckpointStore, InMemoryCheckpointStore};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflows = vec![
+        Workflow::from_json(
+            r#"{"id": "orders", "name": "Orders", "priority": 0, "tasks": [
+                {"id": "charge", "name": "Charge", "function": {"name": "charge", "input": {}}},
+                {"id": "notify", "name": "Notify", "function": {"name": "notify", "input": {}}}
+            ]}"#,
+        )
+        .unwrap(),
+        Workflow::from_json(
+            r#"{"id": "archive", "name": "Archive", "priority": 1, "tasks": [
+                {"id": "flag", "name": "Flag", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.archived", "logic": true}
+                ]}}}
+            ]}"#,
+        )
+        .unwrap(),
+    ];
+    // Past its first call, FlakySend always succeeds: a plain counter.
+    let charges = Arc::new(AtomicUsize::new(1));
+    let notifies = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(InMemoryCheckpointStore::new());
+    let engine = Engine::builder()
+        .with_workflows(workflows)
+        .register(
+            "charge",
+            FlakySend {
+                calls: Arc::clone(&charges),
+            },
+        )
+        .register(
+            "notify",
+            FlakySend {
+                calls: Arc::clone(&notifies),
+            },
+        )
+        .with_checkpoint_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    // Notifying fails after the charge went through.
+    let mut message = Message::builder().id("order-1").build();
+    assert!(engine.process_message(&mut message).await.is_err());
+    let checkpoint = store.load("order-1").await.unwrap().unwrap();
+    assert_eq!(checkpoint.workflow_id, "orders");
+    assert_eq!(checkpoint.task_id.as_deref(), Some("charge"));
+
+    let mut other = Message::builder().id("order-2").build();
+    assert!(
+        engine
+            .resume_message(&checkpoint, &mut other)
+            .await
+            .is_err()
+    );
+
+    // After a restart the message carries on with `notify`.
+    let mut restarted = Message::builder().id("order-1").build();
+    engine
+        .resume_message(&checkpoint, &mut restarted)
+        .await
+        .unwrap();
+    assert_eq!(charges.load(Ordering::SeqCst), 2);
+    assert_eq!(notifies.load(Ordering::SeqCst), 2);
+    assert_eq!(restarted.data()["archived"], dv(json!(true)));
+    let tasks: Vec<&str> = restarted
+        .audit_trail()
+        .iter()
+        .map(|entry| &*entry.task_id)
+        .collect();
+    assert_eq!(tasks, ["charge", "notify", "flag"]);
+    assert!(store.is_empty());
+}
+
+#[tokio::test]
+async fn resume_message_keeps_a_channel_routed_message_on_its_channel() {
+    use dataflow_rs::{CheckpointStore, InMemoryCheckpointStore};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let workflows = vec![
+        Workflow::from_json(
+            r#"{"id": "orders", "name": "Orders", "channel": "orders", "priority": 0, "tasks": [
+                {"id": "charge", "name": "Charge", "function": {"name": "charge", "input": {}}},
+                {"id": "notify", "name": "Notify", "function": {"name": "notify", "input": {}}}
+            ]}"#,
+        )
+        .unwrap(),
+        Workflow::from_json(
+            r#"{"id": "archive", "name": "Archive", "channel": "orders", "priority": 1, "tasks": [
+                {"id": "flag", "name": "Flag", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.archived", "logic": true}
+                ]}}}
+            ]}"#,
+        )
+        .unwrap(),
+        Workflow::from_json(
+            r#"{"id": "refunds", "name": "Refunds", "channel": "refunds", "priority": 2, "tasks": [
+                {"id": "refund", "name": "Refund", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.refunded", "logic": true}
+                ]}}}
+            ]}"#,
+        )
+        .unwrap(),
+    ];
+    let charges = Arc::new(AtomicUsize::new(1));
+    let notifies = Arc::new(AtomicUsize::new(0));
+    let store = Arc::new(InMemoryCheckpointStore::new());
+    let engine = Engine::builder()
+        .with_workflows(workflows)
+        .register(
+            "charge",
+            FlakySend {
+                calls: Arc::clone(&charges),
+            },
+        )
+        .register(
+            "notify",
+            FlakySend {
+                calls: Arc::clone(&notifies),
+            },
+        )
+        .with_checkpoint_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+
+    let mut message = Message::builder().id("order-1").build();
+    assert!(
+        engine
+            .process_message_for_channel("orders", &mut message)
+            .await
+            .is_err()
+    );
+    let checkpoint = store.load("order-1").await.unwrap().unwrap();
+    assert_eq!(checkpoint.task_id.as_deref(), Some("charge"));
+    assert_eq!(checkpoint.channel.as_deref(), Some("orders"));
+
+    let mut restarted = Message::builder().id("order-1").build();
+    engine
+        .resume_message(&checkpoint, &mut restarted)
+        .await
+        .unwrap();
+    let tasks: Vec<&str> = restarted
+        .audit_trail()
+        .iter()
+        .map(|entry| &*entry.task_id)
+        .collect();
+    assert_eq!(tasks, ["charge", "notify", "flag"]);
+    assert!(store.is_empty());
+
+    // A channel run that succeeds leaves no checkpoint behind.
+    let mut next = Message::builder().id("order-2").build();
+    engine
+        .process_message_for_channel("orders", &mut next)
+        .await
+        .unwrap();
+    assert_eq!(notifies.load(Ordering::SeqCst), 3);
+    assert!(store.is_empty());
+}
+
+struct StrictTotal;
+
+#[async_trait]
+impl AsyncFunctionHandler for StrictTotal {
+    type Input = Value;
+
+    async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+        let total = ctx.data().get("order").and_then(|o| o.get("total"));
+        assert!(
+            total.is_some_and(|t| t.as_f64().is_some()),
+            "total is not a number"
+        );
+        Ok(TaskOutcome::Success)
+    }
+}
+
+#[tokio::test]
+async fn fuzz_message_reports_panics_and_passes_robust_workflows() {
+    use dataflow_rs::testing::{FuzzConfig, fuzz_message};
+
+    let workflow = |check: &str| {
+        Workflow::from_json(&format!(
+            r#"{{"id": "orders", "name": "Orders", "tasks": [
+                {{"id": "parse", "name": "Parse", "function": {{"name": "parse_json", "input": {{"source": "payload", "target": "order"}}}}}},
+                {{"id": "check", "name": "Check", "function": {check}}}
+            ]}}"#
+        ))
+        .unwrap()
+    };
+    let config =
+        FuzzConfig::from_sample(json!({"id": "A1", "total": 150, "lines": [{"sku": "X"}]}))
+            .with_iterations(100)
+            .with_seed(3)
+            .with_max_array_len(100);
+
+    let robust = Engine::builder()
+        .with_workflow(workflow(
+            r#"{"name": "validation", "input": {"rules": [
+                {"logic": {">": [{"var": "data.order.total"}, 0]}, "message": "total must be positive"}
+            ]}}"#,
+        ))
+        .build()
+        .unwrap();
+    let report = fuzz_message(&robust, config.clone()).await;
+    assert_eq!(report.cases, 100);
+    report.assert_passed();
+
+    let fragile = Engine::builder()
+        .with_workflow(workflow(r#"{"name": "strict_total", "input": {}}"#))
+        .register("strict_total", StrictTotal)
+        .build()
+        .unwrap();
+    let report = fuzz_message(&fragile, config).await;
+    assert!(!report.passed());
+    let finding = &report.findings[0];
+    assert!(
+        finding.problem.contains("total is not a number"),
+        "{}",
+        finding.problem
+    );
+    assert!(finding.path.is_empty() || finding.path.starts_with("/total"));
+}
+
+#[tokio::test]
+async fn replay_audit_accounts_for_every_change_to_data() {
+    let workflow = Workflow::from_json(
+        r#"{"id": "orders", "name": "Orders", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {"source": "payload", "target": "order"}}},
+            {"id": "totals", "name": "Totals", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.order.tax", "logic": {"*": [{"var": "data.order.total"}, 0.1]}},
+                {"path": "data", "logic": {"status": "priced"}},
+                {"path": "temp_data.scratch", "logic": 1}
+            ]}}},
+            {"id": "log", "name": "Log", "function": {"name": "log_task", "input": {}}}
+        ]}"#,
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("log_task", LoggingTask)
+        .build()
+        .unwrap();
+    let original = Message::builder()
+        .id("order-1")
+        .payload_json(&json!({"total": 100}))
+        .data_json(&json!({"source": "api"}))
+        .build();
+    let mut message = original.clone();
+    engine.process_message(&mut message).await.unwrap();
+    assert_eq!(message.replay_audit(&original), Ok(()));
+
+    // Data edited after processing no longer matches the trail.
+    let mut edited = message.clone();
+    set_nested_value(&mut edited.context, "data.order.tax", dv(json!(0)));
+    let mismatch = edited.replay_audit(&original).unwrap_err();
+    assert_eq!(mismatch.entry, None);
+    assert_eq!(mismatch.path, "data.order.tax");
+    assert_eq!(mismatch.actual, dv(json!(0)));
+
+    // Replaying over the wrong starting point fails at the first change.
+    let other = Message::builder()
+        .id("order-1")
+        .payload_json(&json!({"total": 100}))
+        .data_json(&json!({"source": "api", "order": {"total": 1}}))
+        .build();
+    let mismatch = message.replay_audit(&other).unwrap_err();
+    assert_eq!(mismatch.entry, Some(0));
+    assert_eq!(mismatch.path, "data.order");
+    assert!(
+        mismatch
+            .to_string()
+            .starts_with("audit entry 0 expects null at data.order")
+    );
+}
+
+#[tokio::test]
+async fn pending_instances_list_and_force_stuck_joins_and_retries() {
+    use dataflow_rs::{InMemoryStateStore, RetryState, StateStore, WaitReason};
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "batch", "name": "Batch", "tasks": [
+            {"id": "join", "name": "Join", "function": {"name": "join", "input": {
+                "key": {"var": "data.batch"},
+                "count": 3,
+                "source": "data.item"
+            }}},
+            {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.done", "logic": true}
+            ]}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let store = Arc::new(InMemoryStateStore::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .with_state_store(Arc::clone(&store))
+        .build()
+        .unwrap();
+    for (id, batch, item) in [("m1", "A", 1), ("m2", "A", 2), ("m3", "B", 3)] {
+        let mut message = engine
+            .message()
+            .id(id)
+            .data_json(&json!({"batch": batch, "item": item}))
+            .build();
+        engine.process_message(&mut message).await.unwrap();
+    }
+    let retry = RetryState {
+        retries: 1,
+        next_attempt_at: chrono::Utc::now(),
+    };
+    store
+        .set(
+            "retry:msg:9:batch:after",
+            serde_json::to_value(retry).unwrap(),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let pending = engine.pending_instances().await.unwrap();
+    let keys: Vec<_> = pending.iter().map(|p| p.key.as_str()).collect();
+    assert_eq!(keys, ["A", "B", "msg:9"]);
+    assert!(pending[0].age().unwrap() >= chrono::TimeDelta::zero());
+    assert_eq!(
+        pending[0].reason,
+        WaitReason::Join {
+            message_ids: vec!["m1".to_string(), "m2".to_string()],
+            count: Some(3),
+            timeout_at: None,
+        }
+    );
+    assert_eq!(pending[2].task_id, "after");
+    assert!(pending[2].age().is_none());
+
+    // The last part of batch A is lost: finish it with what it has.
+    let mut message = engine.message().id("operator").build();
+    engine
+        .force_complete(&pending[0], &mut message)
+        .await
+        .unwrap();
+    assert_eq!(message.data()["parts"], dv(json!([1, 2])));
+    assert_eq!(message.data()["done"], dv(json!(true)));
+    assert_eq!(message.parent_ids(), ["m1", "m2"]);
+    assert!(
+        engine
+            .force_complete(&pending[0], &mut message)
+            .await
+            .is_err()
+    );
+    assert!(
+        engine
+            .force_complete(&pending[2], &mut message)
+            .await
+            .is_err()
+    );
+
+    engine.force_expire(&pending[1]).await.unwrap();
+    engine.force_expire(&pending[2]).await.unwrap();
+    assert!(store.keys().is_empty());
+    assert!(engine.pending_instances().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn expire_joins_completes_groups_past_their_timeout() {
+    let join = |id: &str, limit: Value| {
+        let mut input = json!({"key": {"var": "data.batch"}, "source": "data.item"});
+        input
+            .as_object_mut()
+            .unwrap()
+            .extend(limit.as_object().unwrap().clone());
+        Workflow::from_json(
+            &json!({"id": id, "name": id, "tasks": [
+                {"id": "join", "name": "Join", "function": {"name": "join", "input": input}},
+                {"id": "after", "name": "After", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.done", "logic": true}
+                ]}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let timed = Engine::builder()
+        .with_workflow(join("timed", json!({"count": 3, "timeout_ms": 1})))
+        .build()
+        .unwrap();
+    let counted = Engine::builder()
+        .with_workflow(join("counted", json!({"count": 3})))
+        .build()
+        .unwrap();
+    for engine in [&timed, &counted] {
+        for (id, item) in [("m1", 1), ("m2", 2)] {
+            let mut message = engine
+                .message()
+                .id(id)
+                .data_json(&json!({"batch": "A", "item": item}))
+                .build();
+            engine.process_message(&mut message).await.unwrap();
+        }
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+    // The timed group completes without another message arriving.
+    let expired = timed.expire_joins().await.unwrap();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].data()["parts"], dv(json!([1, 2])));
+    assert_eq!(expired[0].data()["done"], dv(json!(true)));
+    assert_eq!(expired[0].parent_ids(), ["m1", "m2"]);
+    assert!(expired[0].errors().is_empty());
+    assert!(timed.expire_joins().await.unwrap().is_empty());
+    assert!(timed.pending_instances().await.unwrap().is_empty());
+
+    // A group without a timeout is left for its last message.
+    assert!(counted.expire_joins().await.unwrap().is_empty());
+    assert_eq!(counted.pending_instances().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn priority_boost_reorders_workflows_for_one_message() {
+    let workflow = |id: &str, priority: u32| {
+        Workflow::from_json(
+            &json!({"id": id, "name": id, "priority": priority, "tasks": [
+                {"id": "mark", "name": "Mark", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.order", "logic": {"merge": [{"var": "data.order"}, [id]]}}
+                ]}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let engine = Engine::builder()
+        .with_workflows([workflow("a", 1), workflow("b", 2), workflow("c", 2)])
+        .build()
+        .unwrap();
+
+    let mut plain = Message::builder().data_json(&json!({"order": []})).build();
+    engine.process_message(&mut plain).await.unwrap();
+    assert_eq!(plain.data()["order"], dv(json!(["a", "b", "c"])));
+
+    let mut boosted = Message::builder()
+        .data_json(&json!({"order": []}))
+        .metadata_json(&json!({"routing": {"priority_boost": {"c": -2}}}))
+        .build();
+    engine.process_message(&mut boosted).await.unwrap();
+    assert_eq!(boosted.data()["order"], dv(json!(["c", "a", "b"])));
+
+    let plan = engine.explain(&boosted);
+    let ids: Vec<_> = plan
+        .workflows
+        .iter()
+        .map(|w| w.workflow_id.as_str())
+        .collect();
+    assert_eq!(ids, ["c", "a", "b"]);
+}
+
+#[tokio::test]
+async fn reprocess_from_audit_reruns_from_the_fixed_task() {
+    let workflow = |tax_rate: f64| {
+        Workflow::from_json(
+            &json!({"id": "orders", "name": "Orders", "channel": "orders", "tasks": [
+                {"id": "stamp", "name": "Stamp", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.visits", "logic": {"merge": [{"var": "data.visits"}, ["stamp"]]}}
+                ]}}},
+                {"id": "totals", "name": "Totals", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.tax", "logic": {"*": [{"var": "data.total"}, tax_rate]}}
+                ]}}},
+                {"id": "label", "name": "Label", "function": {"name": "map", "input": {"mappings": [
+                    {"path": "data.label", "logic": {"cat": ["tax ", {"var": "data.tax"}]}}
+                ]}}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let original = Message::builder()
+        .id("order-7")
+        .data_json(&json!({"total": 100, "visits": []}))
+        .build();
+    let faulty = Engine::builder()
+        .with_workflow(workflow(1.0))
+        .build()
+        .unwrap();
+    let mut processed = original.clone();
+    faulty.process_message(&mut processed).await.unwrap();
+    assert_eq!(processed.data()["label"], dv(json!("tax 100")));
+
+    let fixed = Engine::builder()
+        .with_workflow(workflow(0.2))
+        .build()
+        .unwrap();
+    let message = fixed
+        .reprocess_from_audit(&original, processed.audit_trail(), "orders", "totals")
+        .await
+        .unwrap();
+    assert_eq!(message.id(), "order-7");
+    assert_eq!(message.data()["visits"], dv(json!(["stamp"])));
+    assert_eq!(message.data()["tax"], dv(json!(20.0)));
+    assert_eq!(message.data()["label"], dv(json!("tax 20")));
+    let tasks: Vec<_> = message.audit_trail().iter().map(|e| &*e.task_id).collect();
+    assert_eq!(tasks, ["stamp", "totals", "label"]);
+    assert!(message.replay_audit(&original).is_ok());
+
+    let missing = fixed
+        .reprocess_from_audit(&original, processed.audit_trail(), "orders", "nope")
+        .await
+        .unwrap_err();
+    assert!(
+        missing.to_string().contains("no entry for task nope"),
+        "{missing}"
+    );
+    let other = Message::builder()
+        .data_json(&json!({"total": 100, "visits": ["x"]}))
+        .build();
+    assert!(
+        fixed
+            .reprocess_from_audit(&other, processed.audit_trail(), "orders", "totals")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn source_manager_processes_events_and_restarts_failed_sources() {
+    use dataflow_rs::{
+        CancellationToken, DataflowError, RestartPolicy, SharedEngine, SourceEmitter, SourceEvent,
+        SourceFunctionHandler, SourceManager, SourceOptions,
+    };
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    struct Batches {
+        seen: Arc<Mutex<Vec<Message>>>,
+    }
+
+    #[async_trait]
+    impl SourceFunctionHandler for Batches {
+        async fn run(&self, emitter: SourceEmitter, _shutdown: CancellationToken) -> Result<()> {
+            for n in 0..2 {
+                let message = emitter
+                    .emit(SourceEvent::new(json!({"n": n})).with_metadata(json!({"batch": true})))
+                    .await?;
+                self.seen.lock().unwrap().push(message);
+            }
+            Err(DataflowError::Io("connection reset".to_string()))
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "ingest", "name": "Ingest", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {
+                "source": "payload", "target": "event"
+            }}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let shared = SharedEngine::new(Engine::builder().with_workflow(workflow).build().unwrap());
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let runtime = SourceManager::new(shared)
+        .register_with(
+            "feed",
+            Batches {
+                seen: Arc::clone(&seen),
+            },
+            SourceOptions::default().with_restart(RestartPolicy::OnFailure {
+                max_restarts: Some(1),
+                backoff: Duration::from_millis(10),
+            }),
+        )
+        .start();
+    let statuses = tokio::time::timeout(Duration::from_secs(5), runtime.wait())
+        .await
+        .unwrap();
+
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 4);
+    assert_eq!(seen[1].data()["event"], dv(json!({"n": 1})));
+    assert_eq!(seen[0].metadata()["source"], dv(json!("feed")));
+    assert_eq!(seen[0].metadata()["batch"], dv(json!(true)));
+    assert_eq!(statuses.len(), 1);
+    assert!(!statuses[0].running);
+    assert_eq!(statuses[0].restarts, 1);
+    assert_eq!(
+        statuses[0].last_error.as_deref(),
+        Some("IO error: connection reset")
+    );
+}
+
+#[tokio::test]
+async fn delivered_events_are_acked_only_after_processing() {
+    use dataflow_rs::{
+        Acknowledge, CancellationToken, NackReason, SharedEngine, SourceEmitter, SourceEvent,
+        SourceFunctionHandler, SourceManager, SourceOptions,
+    };
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    struct Settle {
+        id: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl Acknowledge for Settle {
+        async fn ack(&mut self) -> Result<()> {
+            self.log.lock().unwrap().push(format!("ack {}", self.id));
+            Ok(())
+        }
+
+        async fn nack(&mut self, reason: NackReason<'_>) -> Result<()> {
+            let reason = match reason {
+                NackReason::Failed(e) => e.to_string(),
+                NackReason::Errors(message) => message.errors()[0].message.clone(),
+            };
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("nack {}: {}", self.id, reason));
+            Ok(())
+        }
+    }
+
+    struct Queue {
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl SourceFunctionHandler for Queue {
+        async fn run(&self, emitter: SourceEmitter, _shutdown: CancellationToken) -> Result<()> {
+            for (id, kind) in [("m1", "good"), ("m2", "bad"), ("m3", "fail")] {
+                let acker = Settle {
+                    id,
+                    log: Arc::clone(&self.log),
+                };
+                let result = emitter
+                    .deliver(SourceEvent::new(json!({"kind": kind})).with_id(id), acker)
+                    .await;
+                assert_eq!(result.is_err(), kind == "fail");
+            }
+            Ok(())
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "ingest", "name": "Ingest", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {
+                "source": "payload", "target": "order"
+            }}},
+            {"id": "check", "name": "Check", "function": {"name": "validation", "input": {"rules": [
+                {"logic": {"!=": [{"var": "data.order.kind"}, "bad"]}, "message": "bad order"}
+            ]}}},
+            {"id": "boom", "name": "Boom", "condition": {"==": [{"var": "data.order.kind"}, "fail"]},
+             "function": {"name": "fail", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("fail", FailingTask)
+        .build()
+        .unwrap();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let runtime = SourceManager::new(SharedEngine::new(engine))
+        .register_with(
+            "queue",
+            Queue {
+                log: Arc::clone(&log),
+            },
+            SourceOptions::default().with_nack_on_errors(true),
+        )
+        .start();
+    tokio::time::timeout(Duration::from_secs(5), runtime.wait())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            "ack m1".to_string(),
+            "nack m2: bad order".to_string(),
+            "nack m3: Task error: boom".to_string(),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn directory_source_processes_dropped_files() {
+    use dataflow_rs::{AfterProcessing, DirectorySource, FileFormat, SharedEngine, SourceManager};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    struct Record {
+        seen: Arc<Mutex<Vec<(Value, Value)>>>,
+    }
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Record {
+        type Input = Value;
+
+        async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            let message = ctx.message();
+            self.seen.lock().unwrap().push((
+                Value::from(&message.data()["event"]),
+                Value::from(&message.metadata()["file"]["line"]),
+            ));
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let dir = std::env::temp_dir().join(format!("dataflow-inbox-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("orders.ndjson"), "{\"id\": 1}\n\n{\"id\": 2}\n").unwrap();
+    std::fs::write(dir.join("readme.txt"), "not an order").unwrap();
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "ingest", "name": "Ingest", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {
+                "source": "payload", "target": "event"
+            }}},
+            {"id": "record", "name": "Record", "function": {"name": "record", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register(
+            "record",
+            Record {
+                seen: Arc::clone(&seen),
+            },
+        )
+        .build()
+        .unwrap();
+    let source = DirectorySource::new(&dir)
+        .with_extension("ndjson")
+        .with_format(FileFormat::Lines)
+        .with_poll_interval(Duration::from_millis(10))
+        .after_processing(AfterProcessing::MoveTo(dir.join("done")));
+    let runtime = SourceManager::new(SharedEngine::new(engine))
+        .register("inbox", source)
+        .start();
+
+    let moved = dir.join("done").join("orders.ndjson");
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !moved.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    runtime.shutdown().await;
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [(json!({"id": 1}), json!(1)), (json!({"id": 2}), json!(3))]
+    );
+    assert!(!dir.join("orders.ndjson").exists());
+    assert!(dir.join("readme.txt").exists());
+}
+
+#[test]
+fn child_messages_inherit_priority_and_deadline() {
+    use chrono::{Duration, Utc};
+    use dataflow_rs::EngineOptions;
+
+    let deadline = Utc::now() + Duration::seconds(10);
+    let parent = Message::builder()
+        .id("order-1")
+        .priority(2)
+        .deadline(deadline)
+        .build();
+
+    let engine = Engine::builder().build().unwrap();
+    let child = engine.child_message(&parent).unwrap().build();
+    assert_eq!(child.priority(), Some(2));
+    assert_eq!(child.deadline(), Some(deadline));
+    assert_eq!(child.parent_ids(), ["order-1"]);
+
+    let options = EngineOptions::from_json(
+        &json!({"child_urgency": {
+            "priority": {"+": [{"var": "metadata.priority"}, 1]},
+            "deadline_ms": {"if": [
+                {"var": "metadata.deadline_remaining_ms"},
+                {"-": [{"var": "metadata.deadline_remaining_ms"}, 4000]},
+                null
+            ]}
+        }})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder().with_options(options).build().unwrap();
+    let child = engine.child_message(&parent).unwrap().build();
+    assert_eq!(child.priority(), Some(3));
+    let budget = (child.deadline().unwrap() - Utc::now()).num_milliseconds();
+    assert!((5000..=6000).contains(&budget), "{budget}");
+
+    // `null` leaves the child without a deadline.
+    let child = engine
+        .child_message(&Message::builder().build())
+        .unwrap()
+        .build();
+    assert_eq!(child.priority(), Some(1));
+    assert_eq!(child.deadline(), None);
+}
+
+#[tokio::test]
+async fn transformer_mounts_plain_rust_code_as_a_function() {
+    use dataflow_rs::Transformer;
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "legacy", "name": "Legacy", "tasks": [
+            {"id": "normalize", "name": "Normalize", "function": {"name": "normalize", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register(
+            "normalize",
+            Transformer::new(|data: &mut Value| {
+                let email = data["email"].as_str().unwrap_or_default().to_lowercase();
+                data["email"] = json!(email);
+                Ok(())
+            }),
+        )
+        .build()
+        .unwrap();
+
+    let mut message = Message::builder()
+        .data_json(&json!({"email": "Ann@Example.COM", "name": "Ann"}))
+        .build();
+    engine.process_message(&mut message).await.unwrap();
+
+    assert_eq!(message.data()["email"], dv(json!("ann@example.com")));
+    assert_eq!(message.data()["name"], dv(json!("Ann")));
+    let changes = &message.audit_trail()[0].changes;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(&*changes[0].path, "data.email");
+    assert_eq!(changes[0].old_value, dv(json!("Ann@Example.COM")));
+}
+
+#[tokio::test]
+async fn handlers_record_in_place_edits_with_change_tracker() {
+    use dataflow_rs::ChangeTracker;
+
+    struct Uppercase;
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Uppercase {
+        type Input = Value;
+
+        async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            let before = ctx.message().context.clone();
+            let name = ctx.data()["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_uppercase();
+            set_nested_value(&mut ctx.message_mut().context, "data.name", dv(json!(name)));
+            let changes = ChangeTracker::diff_at("", &before, &ctx.message().context);
+            ctx.record_changes(changes);
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let workflow = Workflow::from_json(
+        &json!({"id": "wf", "name": "WF", "tasks": [
+            {"id": "upper", "name": "Upper", "function": {"name": "upper", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let engine = Engine::builder()
+        .with_workflow(workflow)
+        .register("upper", Uppercase)
+        .build()
+        .unwrap();
+    let mut message = Message::builder()
+        .data_json(&json!({"name": "ann", "id": 7}))
+        .build();
+    let original = message.clone();
+    engine.process_message(&mut message).await.unwrap();
+
+    let changes = &message.audit_trail()[0].changes;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(&*changes[0].path, "data.name");
+    assert_eq!(changes[0].new_value, dv(json!("ANN")));
+    assert!(message.replay_audit(&original).is_ok());
+}
+
+#[cfg(feature = "http-source")]
+#[tokio::test]
+async fn http_source_replies_with_the_processed_message() {
+    use dataflow_rs::{HttpRoute, HttpSource, SharedEngine, SourceManager};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let workflows = [
+        json!({"id": "orders", "name": "Orders", "channel": "orders", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {
+                "source": "payload", "target": "order"
+            }}},
+            {"id": "tag", "name": "Tag", "function": {"name": "map", "input": {"mappings": [
+                {"path": "data.request_id", "logic": {"var": "metadata.http.headers.x-request-id"}}
+            ]}}}
+        ]}),
+        json!({"id": "strict", "name": "Strict", "channel": "strict", "tasks": [
+            {"id": "parse", "name": "Parse", "function": {"name": "parse_json", "input": {
+                "source": "payload", "target": "body"
+            }}},
+            {"id": "check", "name": "Check", "function": {"name": "validation", "input": {"rules": [
+                {"logic": {"!!": {"var": "data.body.id"}}, "message": "id required"}
+            ]}}}
+        ]}),
+    ]
+    .map(|w| Workflow::from_json(&w.to_string()).unwrap());
+    let shared = SharedEngine::new(Engine::builder().with_workflows(workflows).build().unwrap());
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let source = HttpSource::from_listener(listener)
+        .route(HttpRoute::new("/orders").with_channel("orders"))
+        .route(
+            HttpRoute::new("/orders/total")
+                .with_channel("orders")
+                .with_reply("data.order.total"),
+        )
+        .route(HttpRoute::new("/strict").with_channel("strict"));
+    let runtime = SourceManager::new(shared).register("api", source).start();
+
+    let call = |path: &'static str, body: Value| async move {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let body = body.to_string();
+        let request = format!(
+            "POST {path} HTTP/1.1\r\nHost: api\r\nConnection: close\r\nX-Request-Id: req-1\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let status: u16 = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str::<Value>(body).unwrap())
+    };
+
+    let (status, data) = call("/orders", json!({"total": 12})).await;
+    assert_eq!(status, 200);
+    assert_eq!(data, json!({"order": {"total": 12}, "request_id": "req-1"}));
+    assert_eq!(
+        call("/orders/total", json!({"total": 7})).await,
+        (200, json!(7))
+    );
+    let (status, body) = call("/strict", json!({})).await;
+    assert_eq!(status, 422);
+    assert_eq!(body["errors"][0]["message"], json!("id required"));
+    assert_eq!(call("/strict", json!({"id": 1})).await.0, 200);
+
+    let statuses = runtime.shutdown().await;
+    assert!(statuses[0].last_error.is_none());
+}
+
+#[cfg(feature = "kafka")]
+#[tokio::test]
+async fn kafka_source_commits_records_after_processing() {
+    use dataflow_rs::{
+        DataflowError, FailedRecordPolicy, KafkaConnector, KafkaConsumer, KafkaRecord, KafkaSource,
+        KafkaSourceConfig, OffsetReset, RestartPolicy, SharedEngine, SourceManager, SourceOptions,
+    };
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Topic shared by the consumers: records from the last commit on.
+    #[derive(Default)]
+    struct Topic {
+        records: Vec<KafkaRecord>,
+        committed: Mutex<Vec<i64>>,
+    }
+
+    struct Consumer {
+        topic: Arc<Topic>,
+        pending: VecDeque<KafkaRecord>,
+    }
+
+    #[async_trait]
+    impl KafkaConsumer for Consumer {
+        async fn next(&mut self) -> Result<KafkaRecord> {
+            match self.pending.pop_front() {
+                Some(record) => Ok(record),
+                None => std::future::pending().await,
+            }
+        }
+
+        async fn commit(&mut self, record: &KafkaRecord) -> Result<()> {
+            self.topic.committed.lock().unwrap().push(record.offset);
+            Ok(())
+        }
+    }
+
+    struct Connector(Arc<Topic>);
+
+    #[async_trait]
+    impl KafkaConnector for Connector {
+        async fn connect(&self, config: &KafkaSourceConfig) -> Result<Box<dyn KafkaConsumer>> {
+            assert_eq!(config.offset_reset, OffsetReset::Earliest);
+            let next = self.0.committed.lock().unwrap().last().map_or(0, |o| o + 1);
+            Ok(Box::new(Consumer {
+                topic: Arc::clone(&self.0),
+                pending: self
+                    .0
+                    .records
+                    .iter()
+                    .filter(|r| r.offset >= next)
+                    .cloned()
+                    .collect(),
+            }))
+        }
+    }
+
+    /// Fails the first attempt at each offset in `flaky`; records the id,
+    /// payload and Kafka metadata of the rest.
+    struct Flaky {
+        flaky: Mutex<Vec<i64>>,
+        seen: Arc<Mutex<Vec<(String, Value, Value)>>>,
+    }
+
+    #[async_trait]
+    impl AsyncFunctionHandler for Flaky {
+        type Input = Value;
+
+        async fn execute(&self, ctx: &mut TaskContext<'_>, _input: &Value) -> Result<TaskOutcome> {
+            let offset = ctx.metadata()["kafka"]["offset"].as_i64().unwrap();
+            let mut flaky = self.flaky.lock().unwrap();
+            if let Some(pos) = flaky.iter().position(|o| *o == offset) {
+                flaky.remove(pos);
+                return Err(DataflowError::Io("broker hiccup".to_string()));
+            }
+            let message = ctx.message();
+            self.seen.lock().unwrap().push((
+                message.id().to_string(),
+                serde_json::to_value(message.payload()).unwrap(),
+                serde_json::to_value(&message.metadata()["kafka"]).unwrap(),
+            ));
+            Ok(TaskOutcome::Success)
+        }
+    }
+
+    let record = |offset: i64, value: &str| KafkaRecord {
+        topic: "orders".to_string(),
+        partition: 0,
+        offset,
+        key: Some(b"k".to_vec()),
+        value: value.as_bytes().to_vec(),
+        ..KafkaRecord::default()
+    };
+    let topic = Arc::new(Topic {
+        records: vec![
+            record(0, r#"{"n": 0}"#),
+            record(1, "plain"),
+            record(2, "{}"),
+        ],
+        ..Topic::default()
+    });
+    let workflow = Workflow::from_json(
+        &json!({"id": "ingest", "name": "Ingest", "tasks": [
+            {"id": "flaky", "name": "Flaky", "function": {"name": "flaky", "input": {}}}
+        ]})
+        .to_string(),
+    )
+    .unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let engine_with_flaky = |flaky: Vec<i64>| {
+        Engine::builder()
+            .with_workflow(workflow.clone())
+            .register(
+                "flaky",
+                Flaky {
+                    flaky: Mutex::new(flaky),
+                    seen: Arc::clone(&seen),
+                },
+            )
+            .build()
+            .unwrap()
+    };
+    let engine = engine_with_flaky(vec![1]);
+    let config = KafkaSourceConfig {
+        brokers: vec!["localhost:9092".to_string()],
+        topic: "orders".to_string(),
+        group: "rules".to_string(),
+        offset_reset: OffsetReset::Earliest,
+    };
+    let source = KafkaSource::new(config.clone(), Connector(Arc::clone(&topic)))
+        .with_failure_policy(FailedRecordPolicy::Redeliver);
+    let runtime = SourceManager::new(SharedEngine::new(engine))
+        .register_with(
+            "orders",
+            source,
+            SourceOptions::default().with_restart(RestartPolicy::OnFailure {
+                max_restarts: None,
+                backoff: Duration::from_millis(10),
+            }),
+        )
+        .start();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while topic.committed.lock().unwrap().len() < 3 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let statuses = runtime.shutdown().await;
+
+    // Offset 1 failed once; it was redelivered after a restart rather than
+    // committed.
+    assert_eq!(*topic.committed.lock().unwrap(), [0, 1, 2]);
+    assert_eq!(statuses[0].restarts, 1);
+    {
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen[0].0, "orders-0-0");
+        assert_eq!(seen[0].1, json!({"n": 0}));
+        assert_eq!(seen[1].1, json!("plain"));
+        assert_eq!(seen[0].2["key"], json!("k"));
+        assert_eq!(seen[0].2["topic"], json!("orders"));
+    }
+
+    // With `Skip`, a failed record is committed and the source carries on.
+    let topic = Arc::new(Topic {
+        records: vec![record(0, "{}"), record(1, "{}")],
+        ..Topic::default()
+    });
+    let engine = engine_with_flaky(vec![0]);
+    let runtime = SourceManager::new(SharedEngine::new(engine))
+        .register(
+            "orders",
+            KafkaSource::new(config, Connector(Arc::clone(&topic))),
+        )
+        .start();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while topic.committed.lock().unwrap().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let statuses = runtime.shutdown().await;
+    assert_eq!(*topic.committed.lock().unwrap(), [0, 1]);
+    assert_eq!(statuses[0].restarts, 0);
+}
+
+#[tokio::test]
+async fn workflow_outputs_send_processed_messages_to_sinks() {
+    use dataflow_rs::{DataflowError, InMemorySink, NdjsonFileSink, SinkHandler, SinkRecord};
+
+    struct DownSink;
+
+    #[async_trait]
+    impl SinkHandler for DownSink {
+        async fn send(&self, _record: SinkRecord) -> dataflow_rs::Result<()> {
+            Err(DataflowError::Io("broker unavailable".to_string()))
+        }
+    }
+
+    let workflow = |output: Value| {
+        Workflow::from_json(
+            &json!({"id": "orders", "name": "Orders", "output": output, "tasks": [
+                {"id": "total", "name": "Total", "function": {"name": "map", "input": {
+                    "mappings": [{"path": "data.total", "logic": 42}]
+                }}}
+            ]})
+            .to_string(),
+        )
+        .unwrap()
+    };
+    let path = std::env::temp_dir().join(format!("dataflow-outputs-{}.ndjson", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let memory = Arc::new(InMemorySink::new());
+    let engine = Engine::builder()
+        .with_workflow(workflow(json!([
+            {"sink": "memory"},
+            {"sink": "file", "path": "data.total"},
+            {"sink": "memory", "path": "data.missing"}
+        ])))
+        .with_sink("memory", Arc::clone(&memory))
+        .with_sink("file", NdjsonFileSink::new(&path))
+        .build()
+        .unwrap();
+
+    let mut message = Message::builder().id("m1").build();
+    engine.process_message(&mut message).await.unwrap();
+
+    let records = memory.drain();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].workflow_id, "orders");
+    assert_eq!(records[0].message_id, "m1");
+    assert_eq!(records[0].payload["context"]["data"]["total"], json!(42));
+    assert_eq!(NdjsonFileSink::new(&path).read_all().unwrap(), [json!(42)]);
+    std::fs::remove_file(&path).unwrap();
+
+    let err = Engine::builder()
+        .with_workflow(workflow(json!([{"sink": "kafka"}])))
+        .build()
+        .err()
+        .unwrap();
+    let config = err.config().unwrap();
+    assert_eq!(config.workflow_id.as_deref(), Some("orders"));
+    assert_eq!(config.json_pointer.as_deref(), Some("/output/0/sink"));
+
+    let engine = Engine::builder()
+        .with_workflow(workflow(json!([{"sink": "down"}])))
+        .with_sink("down", DownSink)
+        .build()
+        .unwrap();
+    let mut message = Message::builder().id("m2").build();
+    let err = engine.process_message(&mut message).await.unwrap_err();
+    assert!(matches!(err, DataflowError::Io(_)));
+    assert_eq!(message.errors().last().unwrap().code, "SINK_ERROR");
+}
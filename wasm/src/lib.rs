@@ -45,8 +45,9 @@
 //! console.log(JSON.parse(result));
 //! ```
 
-use dataflow_rs::{Engine, Message, Workflow};
+use dataflow_rs::{ConfigError, ConfigErrorKind, DataflowError, Engine, Message, Workflow};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen_futures::future_to_promise;
@@ -76,6 +77,12 @@ impl WasmEngine {
     /// # Arguments
     /// * `workflows_json` - JSON string containing an array of workflow definitions
     ///
+    /// # Errors
+    /// Throws an `Error` whose message describes the problem. For
+    /// configuration problems it also carries `kind` and, when known,
+    /// `workflowId`, `taskId` and `jsonPointer` — a JSON pointer into the
+    /// array, e.g. `/3/tasks/1/function`.
+    ///
     /// # Example
     /// ```javascript
     /// const workflows = JSON.stringify([{
@@ -84,29 +91,53 @@ impl WasmEngine {
     ///     priority: 1,
     ///     tasks: [...]
     /// }]);
-    /// const engine = new WasmEngine(workflows);
+    /// try {
+    ///     const engine = new WasmEngine(workflows);
+    /// } catch (e) {
+    ///     console.error(e.message, e.workflowId, e.taskId, e.jsonPointer);
+    /// }
     /// ```
     #[wasm_bindgen(constructor)]
-    pub fn new(workflows_json: &str) -> Result<WasmEngine, String> {
-        let workflows_value: Value = serde_json::from_str(workflows_json)
-            .map_err(|e| format!("Invalid workflows JSON: {}", e))?;
+    pub fn new(workflows_json: &str) -> Result<WasmEngine, JsValue> {
+        Self::build(workflows_json).map_err(|e| js_error(&e))
+    }
+
+    fn build(workflows_json: &str) -> dataflow_rs::Result<WasmEngine> {
+        let workflows_value: Value = serde_json::from_str(workflows_json).map_err(|e| {
+            ConfigError::new(ConfigErrorKind::Syntax(format!(
+                "Invalid workflows JSON: {e}"
+            )))
+        })?;
 
-        let workflows_array = workflows_value
-            .as_array()
-            .ok_or_else(|| "Workflows must be a JSON array".to_string())?;
+        let workflows_array = workflows_value.as_array().ok_or_else(|| {
+            ConfigError::new(ConfigErrorKind::Parse(
+                "Workflows must be a JSON array".to_string(),
+            ))
+        })?;
 
         let mut workflows = Vec::with_capacity(workflows_array.len());
+        let mut indexes = HashMap::new();
         for (i, workflow_value) in workflows_array.iter().enumerate() {
-            let workflow_str = serde_json::to_string(workflow_value).map_err(|e| e.to_string())?;
-            let workflow = Workflow::from_json(&workflow_str)
-                .map_err(|e| format!("Invalid workflow at index {}: {}", i, e))?;
+            let workflow_str =
+                serde_json::to_string(workflow_value).map_err(DataflowError::from_serde)?;
+            let workflow = Workflow::from_json(&workflow_str).map_err(|e| at_index(e, i))?;
+            indexes.entry(workflow.id.clone()).or_insert(i);
             workflows.push(workflow);
         }
 
         let engine = Engine::builder()
             .with_workflows(workflows)
             .build()
-            .map_err(|e| format!("Engine construction failed: {}", e))?;
+            .map_err(|e| {
+                let index = e
+                    .config()
+                    .and_then(|config| config.workflow_id.as_ref())
+                    .and_then(|id| indexes.get(id));
+                match index {
+                    Some(&i) => at_index(e, i),
+                    None => e,
+                }
+            })?;
         Ok(WasmEngine {
             inner: Arc::new(engine),
         })
@@ -233,6 +264,39 @@ pub fn process_message(workflows_json: &str, payload: &str) -> js_sys::Promise {
     let engine_result = WasmEngine::new(workflows_json);
     match engine_result {
         Ok(engine) => engine.process(payload),
-        Err(e) => future_to_promise(async move { Err(JsValue::from_str(&e)) }),
+        Err(e) => future_to_promise(async move { Err(e) }),
+    }
+}
+
+/// `err`, raised for the workflow at `index` of the array, with its JSON
+/// pointer made relative to the array.
+fn at_index(err: DataflowError, index: usize) -> DataflowError {
+    match err {
+        DataflowError::Config(config) => config.within(&format!("/{index}")).into(),
+        other => other,
+    }
+}
+
+/// A JavaScript `Error` for `err`, carrying the location of a
+/// configuration error as properties.
+fn js_error(err: &DataflowError) -> JsValue {
+    let error = js_sys::Error::new(&err.to_string());
+    if let Some(config) = err.config() {
+        let kind = serde_json::to_value(&config.kind)
+            .ok()
+            .and_then(|kind| kind["type"].as_str().map(str::to_string));
+        let file = config.file.as_ref().map(|f| f.display().to_string());
+        for (name, value) in [
+            ("kind", kind.as_deref()),
+            ("file", file.as_deref()),
+            ("workflowId", config.workflow_id.as_deref()),
+            ("taskId", config.task_id.as_deref()),
+            ("jsonPointer", config.json_pointer.as_deref()),
+        ] {
+            if let Some(value) = value {
+                let _ = js_sys::Reflect::set(&error, &name.into(), &value.into());
+            }
+        }
     }
+    error.into()
 }